@@ -0,0 +1,403 @@
+// files of an extraction written on a pool of threads, while the archive is read on the calling
+// one. How many threads write, and the size of the chunks they are handed, follow the throughput
+// measured on both sides: no fixed setting suits both a local NVMe drive, where one thread keeps
+// up with the decompression, and a network share, where the latency of every write calls for
+// many of them in flight.
+
+use std::{
+    collections::{HashSet, VecDeque},
+    fs::File,
+    io::{Error, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{sync_channel, Receiver},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime},
+};
+
+use super::{retry::IoRetryPolicy, ArchiveError};
+
+/// Smallest and largest chunk of a file handed to its writer.
+const CHUNK_SIZES: (usize, usize) = (64 * 1024, 4 * 1024 * 1024);
+/// Chunks of a file waiting for its writer before reading it blocks.
+const CHUNKS_IN_FLIGHT: usize = 4;
+/// Time between two revisions of the settings.
+const TUNING_INTERVAL: Duration = Duration::from_millis(200);
+/// Share of the time the reader waits for the writers past which one more is tried.
+const WRITE_BOUND: f64 = 0.5;
+/// Share of the time the reader waits for the writers under which one less suffices.
+const READ_BOUND: f64 = 0.05;
+/// Gain of throughput the last writer added must bring to be kept.
+const MIN_GAIN: f64 = 1.1;
+
+/// Called with the path of a file once it is written.
+pub(crate) type StoreHook = Box<dyn FnOnce(&Path) -> Result<(), Error> + Send>;
+
+/// A file to write, its content coming in chunks.
+struct Job {
+    path: PathBuf,
+    chunks: Receiver<Vec<u8>>,
+    modified: Option<SystemTime>,
+    mode: Option<u32>,
+    then: Option<StoreHook>,
+}
+
+#[derive(Default)]
+struct State {
+    queue: VecDeque<Job>,
+    /// Number of writers taking jobs, the others wait until it grows again.
+    active: usize,
+    /// Files being written.
+    busy: usize,
+    closed: bool,
+    /// First failure of a writer, ending the extraction.
+    error: Option<Error>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    changed: Condvar,
+    written: AtomicU64,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        // a writer panicking while holding the lock leaves a state that is still consistent
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn wait<'s>(&self, state: MutexGuard<'s, State>) -> MutexGuard<'s, State> {
+        self.changed.wait(state).unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Settings the writes ended with, reported once the extraction is done.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct AdaptiveSettings {
+    pub(crate) writers: usize,
+    pub(crate) chunk_size: usize,
+}
+
+/// Measures taken since the last revision of the settings.
+struct Window {
+    started: Instant,
+    written: u64,
+    /// Time the reader waited for the writers.
+    blocked: Duration,
+}
+
+pub(crate) struct AdaptiveWrites {
+    shared: Arc<Shared>,
+    workers: Vec<JoinHandle<()>>,
+    max_workers: usize,
+    retry: IoRetryPolicy,
+    chunk_size: usize,
+    window: Window,
+    /// Write rate before the last writer was added, to tell whether it helped.
+    rate_before_growing: Option<f64>,
+    /// Number of writers past which adding one did not help.
+    ceiling: usize,
+    /// Files handed to the writers since they were last idle.
+    pending: HashSet<PathBuf>,
+}
+
+impl AdaptiveWrites {
+    pub(crate) fn new(retry: IoRetryPolicy) -> Result<Self, ArchiveError> {
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+        // writes mostly wait on the storage, there may be more of them than cores
+        let max_workers = (cores * 4).min(64);
+        let mut writes = Self {
+            shared: Arc::new(Shared {
+                state: Mutex::default(),
+                changed: Condvar::new(),
+                written: AtomicU64::new(0),
+            }),
+            workers: Vec::new(),
+            max_workers,
+            retry,
+            chunk_size: 4 * CHUNK_SIZES.0,
+            window: Window {
+                started: Instant::now(),
+                written: 0,
+                blocked: Duration::ZERO,
+            },
+            rate_before_growing: None,
+            ceiling: max_workers,
+            pending: HashSet::new(),
+        };
+        writes.set_active(1)?;
+        Ok(writes)
+    }
+
+    /// Lets `active` writers take jobs, starting the missing ones.
+    fn set_active(&mut self, active: usize) -> Result<(), ArchiveError> {
+        while self.workers.len() < active {
+            let id = self.workers.len();
+            let shared = Arc::clone(&self.shared);
+            let retry = self.retry;
+            let worker = std::thread::Builder::new()
+                .name(format!("hezi-writer-{}", id))
+                .spawn(move || work(id, &shared, retry))?;
+            self.workers.push(worker);
+        }
+        self.shared.lock().active = active;
+        self.shared.changed.notify_all();
+        Ok(())
+    }
+
+    /// The failure of a writer, if any.
+    fn check(&self) -> Result<(), ArchiveError> {
+        match self.shared.lock().error.take() {
+            Some(e) => Err(e.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether `path` is being written, or waits for it.
+    pub(crate) fn is_pending(&self, path: &Path) -> bool {
+        self.pending.contains(path)
+    }
+
+    /// Waits until every file handed to the writers is written.
+    pub(crate) fn wait_idle(&mut self) -> Result<(), ArchiveError> {
+        let mut state = self.shared.lock();
+        while (!state.queue.is_empty() || state.busy > 0) && state.error.is_none() {
+            state = self.shared.wait(state);
+        }
+        drop(state);
+        self.pending.clear();
+        self.check()
+    }
+
+    /// Hands `path` to the writers, reading its content from `reader` as they write it, and
+    /// returns its size. The file gets the `modified` time and the `mode` once written, then is
+    /// handed to `then`.
+    pub(crate) fn write<R: Read + ?Sized>(
+        &mut self,
+        path: &Path,
+        reader: &mut R,
+        modified: Option<SystemTime>,
+        mode: Option<u32>,
+        then: Option<StoreHook>,
+    ) -> Result<u64, ArchiveError> {
+        self.check()?;
+        // two files waiting for each writer keep them busy without holding too much in memory
+        let waiting = Instant::now();
+        let mut state = self.shared.lock();
+        while state.queue.len() >= state.active * 2 && state.error.is_none() {
+            state = self.shared.wait(state);
+        }
+        let (sender, chunks) = sync_channel(CHUNKS_IN_FLIGHT);
+        state.queue.push_back(Job {
+            path: path.to_path_buf(),
+            chunks,
+            modified,
+            mode,
+            then,
+        });
+        drop(state);
+        self.shared.changed.notify_all();
+        self.window.blocked += waiting.elapsed();
+        self.pending.insert(path.to_path_buf());
+
+        let mut size = 0;
+        loop {
+            let mut chunk = Vec::with_capacity(self.chunk_size);
+            let read = (&mut *reader)
+                .take(self.chunk_size as u64)
+                .read_to_end(&mut chunk)?;
+            if read == 0 {
+                break;
+            }
+            size += read as u64;
+            let sending = Instant::now();
+            // the writer of the file failed, which the next check reports
+            if sender.send(chunk).is_err() {
+                break;
+            }
+            self.window.blocked += sending.elapsed();
+            self.tune()?;
+        }
+        drop(sender);
+        self.tune()?;
+        self.check()?;
+        Ok(size)
+    }
+
+    /// Revises the number of writers and the size of the chunks from the last measures: writers
+    /// are added while the reader waits for them and each one raises the throughput, and removed
+    /// once the reader is the bottleneck.
+    fn tune(&mut self) -> Result<(), ArchiveError> {
+        let elapsed = self.window.started.elapsed();
+        if elapsed < TUNING_INTERVAL {
+            return Ok(());
+        }
+        let written = self.shared.written.load(Ordering::Relaxed);
+        let rate = (written - self.window.written) as f64 / elapsed.as_secs_f64();
+        let blocked = self.window.blocked.as_secs_f64() / elapsed.as_secs_f64();
+        self.window = Window {
+            started: Instant::now(),
+            written,
+            blocked: Duration::ZERO,
+        };
+
+        let active = self.shared.lock().active;
+        if let Some(before) = self.rate_before_growing.take() {
+            if rate < before * MIN_GAIN {
+                self.ceiling = active - 1;
+                self.set_active(active - 1)?;
+            }
+        } else if blocked > WRITE_BOUND && active < self.ceiling.min(self.max_workers) {
+            self.rate_before_growing = Some(rate);
+            self.set_active(active + 1)?;
+        } else if blocked < READ_BOUND && active > 1 {
+            self.set_active(active - 1)?;
+        }
+
+        // each write then takes about 10ms, amortizing its latency without starving the others
+        let per_writer = rate / self.shared.lock().active as f64;
+        self.chunk_size = ((per_writer / 100.0) as usize)
+            .next_power_of_two()
+            .clamp(CHUNK_SIZES.0, CHUNK_SIZES.1);
+        Ok(())
+    }
+
+    /// Waits for the writers to be done, returning the settings they ended with.
+    pub(crate) fn finish(mut self) -> Result<AdaptiveSettings, ArchiveError> {
+        let settings = AdaptiveSettings {
+            writers: self.shared.lock().active,
+            chunk_size: self.chunk_size,
+        };
+        self.shared.lock().closed = true;
+        self.shared.changed.notify_all();
+        for worker in self.workers.drain(..) {
+            worker
+                .join()
+                .map_err(|_| Error::other("a writer thread panicked"))?;
+        }
+        self.check()?;
+        Ok(settings)
+    }
+}
+
+impl Drop for AdaptiveWrites {
+    fn drop(&mut self) {
+        // the writers of an extraction that failed stop once the queued files are written
+        self.shared.lock().closed = true;
+        self.shared.changed.notify_all();
+    }
+}
+
+/// Writes the jobs of the queue while the writer `id` is active, until the queue is closed.
+fn work(id: usize, shared: &Shared, retry: IoRetryPolicy) {
+    loop {
+        let mut state = shared.lock();
+        let job = loop {
+            if state.closed && state.queue.is_empty() {
+                return;
+            }
+            if id < state.active {
+                if let Some(job) = state.queue.pop_front() {
+                    state.busy += 1;
+                    break job;
+                }
+            }
+            state = shared.wait(state);
+        };
+        drop(state);
+        shared.changed.notify_all();
+
+        let result = write_job(job, shared, retry);
+        let mut state = shared.lock();
+        state.busy -= 1;
+        if let Err(e) = result {
+            state.error.get_or_insert(e);
+        }
+        drop(state);
+        shared.changed.notify_all();
+    }
+}
+
+fn write_job(job: Job, shared: &Shared, retry: IoRetryPolicy) -> Result<(), Error> {
+    let file = File::create(&job.path)?;
+    let mut out = retry.wrap(file, job.path.display(), None);
+    for chunk in job.chunks {
+        out.write_all(&chunk)?;
+        shared
+            .written
+            .fetch_add(chunk.len() as u64, Ordering::Relaxed);
+    }
+    out.flush()?;
+    if let Some(modified) = job.modified {
+        out.get_ref().set_modified(modified)?;
+    }
+    if let Some(mode) = job.mode {
+        super::extract_sink::set_mode(&job.path, mode).map_err(Error::other)?;
+    }
+    if let Some(then) = job.then {
+        then(&job.path)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_files_on_threads() {
+        let dir = std::env::temp_dir().join(format!("hezi-adaptive-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut writes = AdaptiveWrites::new(IoRetryPolicy::default()).unwrap();
+        let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        let contents = (0..50)
+            .map(|i| vec![i as u8; i * 20_000])
+            .collect::<Vec<_>>();
+        for (i, content) in contents.iter().enumerate() {
+            let path = dir.join(format!("{}.bin", i));
+            let size = writes
+                .write(&path, &mut content.as_slice(), Some(modified), None, None)
+                .unwrap();
+            assert_eq!(size, content.len() as u64);
+            assert!(writes.is_pending(&path));
+        }
+        writes.wait_idle().unwrap();
+        assert!(!writes.is_pending(&dir.join("0.bin")));
+        let settings = writes.finish().unwrap();
+        assert!(settings.writers >= 1);
+        assert!((CHUNK_SIZES.0..=CHUNK_SIZES.1).contains(&settings.chunk_size));
+
+        for (i, content) in contents.iter().enumerate() {
+            let path = dir.join(format!("{}.bin", i));
+            assert_eq!(&std::fs::read(&path).unwrap(), content);
+            assert_eq!(
+                std::fs::metadata(&path).unwrap().modified().unwrap(),
+                modified
+            );
+        }
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn reports_failed_writes() {
+        let dir = std::env::temp_dir().join(format!("hezi-adaptive-fail-{}", std::process::id()));
+        let mut writes = AdaptiveWrites::new(IoRetryPolicy::default()).unwrap();
+        // the parent directory does not exist, the failure shows at the latest once idle
+        let result = writes
+            .write(
+                &dir.join("missing/file"),
+                &mut &b"content"[..],
+                None,
+                None,
+                None,
+            )
+            .and_then(|_| writes.wait_idle());
+        assert!(matches!(result, Err(ArchiveError::Io(_))));
+    }
+}
@@ -0,0 +1,483 @@
+// Unix ar archives, the container of static libraries and Debian packages. Members are flat
+// files, names longer than 15 bytes are read from the GNU name table or following a BSD header,
+// and written the BSD way. Symbol tables are not listed.
+
+use std::{
+    cell::OnceCell,
+    fs::File,
+    io::{BufReader, ErrorKind, Read, Write},
+    ops::ControlFlow,
+    time::UNIX_EPOCH,
+};
+
+use byte_unit::{Byte, UnitType};
+
+use crate::archive::{
+    datetime_from_timestamp,
+    entry_visitor::{
+        extract_entries, find_first_entry, list_entries, open_entry, scan_entries, EntryVisitor,
+        VisitOptions, VisitedEntry,
+    },
+    ArchiveError, ArchiveEvent, ArchiveFileEntity, ArchiveFileEntityType, ArchiveMetadata,
+    ArchiveScan, ArchiveType, Archived, CreateOptions, CreateResult, DataSource, EventHandler,
+    ExtractOptions, ExtractReport, ListOptions, OpenOptions, DEFAULT_BUF_SIZE,
+};
+
+use super::edit::EntryEdit;
+
+pub(crate) const MAGIC: &[u8; 8] = b"!<arch>\n";
+const HEADER_LEN: usize = 60;
+/// Prefix of the BSD names stored after the header, followed by their length.
+const BSD_NAME: &str = "#1/";
+
+#[derive(Debug)]
+struct Header {
+    name: String,
+    mtime: i64,
+    mode: u32,
+    /// Size of the data following the header, the BSD names included.
+    size: u64,
+}
+
+impl Header {
+    /// Reads the next header, or `None` at the end of the archive.
+    fn read<R: Read>(reader: &mut R) -> Result<Option<Self>, ArchiveError> {
+        let mut header = [0; HEADER_LEN];
+        let mut read = 0;
+        while read < HEADER_LEN {
+            match reader.read(&mut header[read..])? {
+                0 if read == 0 => return Ok(None),
+                0 => return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into()),
+                n => read += n,
+            }
+        }
+        if &header[58..] != b"`\n" {
+            return Err(invalid_data("invalid ar member header".to_string()));
+        }
+
+        let field = |range: std::ops::Range<usize>| {
+            String::from_utf8_lossy(&header[range])
+                .trim_end()
+                .to_string()
+        };
+        let number = |range: std::ops::Range<usize>, radix: u32| {
+            let value = field(range);
+            match value.is_empty() {
+                true => Ok(0),
+                false => u64::from_str_radix(&value, radix)
+                    .map_err(|_| invalid_data(format!("invalid ar header field: {}", value))),
+            }
+        };
+
+        Ok(Some(Self {
+            name: field(0..16),
+            mtime: number(16..28, 10)? as i64,
+            mode: number(40..48, 8)? as u32,
+            size: number(48..58, 10)?,
+        }))
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), ArchiveError> {
+        writeln!(
+            writer,
+            "{:<16}{:<12}{:<6}{:<6}{:<8o}{:<10}`",
+            self.name, self.mtime, 0, 0, self.mode, self.size
+        )?;
+        Ok(())
+    }
+}
+
+fn invalid_data(message: String) -> ArchiveError {
+    ArchiveError::Io(std::io::Error::new(ErrorKind::InvalidData, message))
+}
+
+/// Name of the member at `offset` of the GNU name table, where names end with `/\n`.
+fn table_name(table: &[u8], offset: &str) -> Result<String, ArchiveError> {
+    let name = offset
+        .parse::<usize>()
+        .ok()
+        .and_then(|offset| table.get(offset..))
+        .map(|rest| rest.split(|b| *b == b'\n').next().unwrap_or_default())
+        .ok_or_else(|| invalid_data(format!("invalid ar name table offset: {}", offset)))?;
+    Ok(String::from_utf8_lossy(name)
+        .trim_end_matches('/')
+        .to_string())
+}
+
+pub struct ArArchive {
+    pub(crate) source: DataSource,
+    scan: OnceCell<ArchiveScan>,
+}
+
+impl ArArchive {
+    fn scan(&self) -> Result<&ArchiveScan, ArchiveError> {
+        ArchiveScan::cached(&self.scan, || scan_entries(self, None, None))
+    }
+}
+
+impl EntryVisitor for ArArchive {
+    fn for_each_entry<F>(
+        &self,
+        options: &VisitOptions<'_>,
+        mut visit: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        let source = options
+            .retry
+            .wrap(self.source.clone(), &self.source, options.events);
+        let mut reader = BufReader::with_capacity(DEFAULT_BUF_SIZE, source);
+
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not an ar archive".to_string()));
+        }
+
+        let mut name_table = Vec::new();
+        while let Some(header) = Header::read(&mut reader)? {
+            // members start on even offsets
+            let padding = header.size % 2;
+            let mut data = (&mut reader).take(header.size);
+
+            let name = match header.name.as_str() {
+                "//" => {
+                    name_table.clear();
+                    data.read_to_end(&mut name_table)?;
+                    None
+                }
+                // GNU and BSD symbol tables
+                "/" | "/SYM64/" | "__.SYMDEF" | "__.SYMDEF SORTED" => None,
+                name => match name.strip_prefix(BSD_NAME) {
+                    Some(len) => {
+                        let len = len.parse::<u64>().map_err(|_| {
+                            invalid_data(format!("invalid ar member name: {}", name))
+                        })?;
+                        let mut name = Vec::new();
+                        (&mut data).take(len).read_to_end(&mut name)?;
+                        let name = String::from_utf8_lossy(&name)
+                            .trim_end_matches('\0')
+                            .to_string();
+                        (!name.starts_with("__.SYMDEF")).then_some(name)
+                    }
+                    None => match name.strip_prefix('/') {
+                        Some(offset) => Some(table_name(&name_table, offset)?),
+                        None => Some(name.trim_end_matches('/').to_string()),
+                    },
+                },
+            };
+
+            let flow = match name {
+                Some(name) => {
+                    let size = data.limit();
+                    let entity = ArchiveFileEntity {
+                        name,
+                        size: Some(size),
+                        compressed_size: Some(size),
+                        last_modified: datetime_from_timestamp(header.mtime).ok(),
+                        compression: None,
+                        fstype: ArchiveFileEntityType::File,
+                        raw: None,
+                        unix: None,
+                    };
+                    let visited = VisitedEntry {
+                        mode: Some(header.mode & 0o7777),
+                        ..VisitedEntry::new(entity)
+                    };
+                    if !options.wants(&visited.entity) {
+                        ControlFlow::Continue(())
+                    } else if options.contents {
+                        visit(visited, Some(&mut data))?
+                    } else {
+                        visit(visited, None)?
+                    }
+                }
+                None => ControlFlow::Continue(()),
+            };
+            // the visitor may leave the data unread
+            std::io::copy(&mut data, &mut std::io::sink())?;
+            if flow.is_break() {
+                break;
+            }
+            // the padding of the last member may be missing
+            let mut pad = [0; 1];
+            if padding == 1 && reader.read(&mut pad)? == 0 {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Archived for ArArchive {
+    fn of(source: DataSource) -> Result<Self, ArchiveError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            source,
+            scan: OnceCell::new(),
+        })
+    }
+
+    fn extract(&self, options: ExtractOptions) -> Result<ExtractReport, ArchiveError> {
+        extract_entries(self, &options, self.source.as_ref())
+    }
+
+    fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
+        if options.is_whole() {
+            return Ok(self.scan()?.entries.clone());
+        }
+        list_entries(self, self.scan.get(), &options)
+    }
+
+    fn create(options: CreateOptions) -> Result<CreateResult, ArchiveError> {
+        let file = File::create(&options.destination).map_err(|e| {
+            ArchiveError::Io(std::io::Error::new(
+                e.kind(),
+                format!("could not create destination file: {}", e),
+            ))
+        })?;
+        let mut writer = std::io::BufWriter::with_capacity(DEFAULT_BUF_SIZE, &file);
+        writer.write_all(MAGIC)?;
+
+        let mut total_size = 0;
+        for path in &options.files {
+            let metadata = std::fs::metadata(path)?;
+            let name = options
+                .entry_name(path)
+                .to_string_lossy()
+                .replace('\\', "/");
+            if metadata.is_dir() {
+                // the source directory itself has no member
+                if name.is_empty() {
+                    continue;
+                }
+                return Err(ArchiveError::Io(std::io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("ar archives cannot hold directories: {}", name),
+                )));
+            }
+
+            let size = metadata.len();
+            let mtime = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs() as i64);
+            #[cfg(unix)]
+            let mode = std::os::unix::fs::PermissionsExt::mode(&metadata.permissions());
+            #[cfg(not(unix))]
+            let mode = 0o100644;
+
+            // names that do not fit the header, or would be cut at a space, follow it
+            let inline = name.len() < 16 && !name.contains(' ') && !name.starts_with(BSD_NAME);
+            let header = Header {
+                name: match inline {
+                    true => format!("{}/", name),
+                    false => format!("{}{}", BSD_NAME, name.len()),
+                },
+                mtime,
+                mode,
+                size: size + if inline { 0 } else { name.len() as u64 },
+            };
+            header.write(&mut writer)?;
+            if !inline {
+                writer.write_all(name.as_bytes())?;
+            }
+
+            let reader = BufReader::with_capacity(DEFAULT_BUF_SIZE, File::open(path)?);
+            let copied = std::io::copy(&mut reader.take(size), &mut writer)?;
+            if copied < size {
+                return Err(ArchiveError::Io(std::io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    format!("{} got shorter while it was read", path.display()),
+                )));
+            }
+            if header.size % 2 == 1 {
+                writer.write_all(b"\n")?;
+            }
+            total_size += size;
+            options.handle(ArchiveEvent::Added(name, Some(size)));
+        }
+        writer.flush()?;
+        drop(writer);
+
+        let size = file.metadata()?.len();
+        eprintln!(
+            "Done creating ar archive: {} ({})",
+            options.destination.display(),
+            Byte::from(size).get_appropriate_unit(UnitType::Both)
+        );
+        options.handle(ArchiveEvent::DoneCreating(
+            options.destination.to_string_lossy().to_string(),
+        ));
+
+        Ok(CreateResult {
+            path: options.destination,
+            total_size,
+            compressed_size: size,
+            unreadable: Vec::new(),
+        })
+    }
+
+    fn metadata(&self) -> Result<ArchiveMetadata, ArchiveError> {
+        let scan = self.scan()?;
+
+        Ok(ArchiveMetadata {
+            archive_type: ArchiveType::Ar,
+            entry_count: scan.entries.len(),
+            entries: scan.entries.clone(),
+            total_size: scan.total_size,
+            compressed_size: scan.compressed_size,
+            compression: None,
+            created: self.source.created(),
+            modified: self.source.modified(),
+            additional: None,
+        })
+    }
+
+    fn open(&self, options: OpenOptions) -> Result<(), ArchiveError> {
+        open_entry(self, options)
+    }
+
+    fn find_first<P>(
+        &self,
+        predicate: P,
+    ) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+    where
+        P: Fn(&ArchiveFileEntity) -> bool,
+    {
+        find_first_entry(self, predicate)
+    }
+
+    fn check_password(&self, _password: &str) -> Result<bool, ArchiveError> {
+        // ar has no encryption
+        Ok(true)
+    }
+
+    fn edit(&self, _entry: &str, _edit: &EntryEdit) -> Result<(), ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "edit".to_string(),
+            ArchiveType::Ar,
+        ))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::archive::{Archive, ArchiveCompression, CompressionSpec, SimpleLogger};
+
+    fn names(archive: &Archive) -> Vec<(String, Option<u64>)> {
+        archive
+            .list(ListOptions::default())
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.name, e.size))
+            .collect()
+    }
+
+    #[test]
+    fn lists_debian_packages() {
+        assert_eq!(
+            ArchiveType::guess_from_filename("test1.deb").unwrap().0,
+            ArchiveType::Ar
+        );
+        let archive = Archive::of(DataSource::file("tests/fixtures/test1.deb").unwrap()).unwrap();
+        assert_eq!(
+            names(&archive),
+            vec![
+                ("debian-binary".to_string(), Some(4)),
+                ("control.tar.gz".to_string(), Some(224)),
+                ("data.tar.gz".to_string(), Some(210)),
+            ]
+        );
+
+        let (_, version) = archive
+            .find_first(|e| e.name == "debian-binary")
+            .unwrap()
+            .unwrap();
+        assert_eq!(version, b"2.0\n");
+
+        #[cfg(feature = "tar_archive")]
+        {
+            let (_, data) = archive
+                .find_first(|e| e.name == "data.tar.gz")
+                .unwrap()
+                .unwrap();
+            let nested = Archive::of(DataSource::memory(data.as_slice())).unwrap();
+            assert!(
+                names(&nested).contains(&("./usr/share/test1/file1.txt".to_string(), Some(1469)))
+            );
+        }
+    }
+
+    #[test]
+    fn reads_gnu_long_names() {
+        let archive = Archive::of(DataSource::file("tests/fixtures/test1.a").unwrap()).unwrap();
+        assert_eq!(
+            names(&archive),
+            vec![
+                ("a_rather_long_member_name.txt".to_string(), Some(1469)),
+                ("file2.txt".to_string(), Some(444)),
+            ]
+        );
+        let (_, content) = archive
+            .find_first(|e| e.name == "file2.txt")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            content,
+            std::fs::read("tests/fixtures/test1/dir1/file2.txt").unwrap()
+        );
+    }
+
+    #[test]
+    fn creates_ar_archives() {
+        let dir = std::env::temp_dir().join(format!("hezi-ar-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        let source = dir.join("source");
+        std::fs::create_dir_all(&source).unwrap();
+        // an odd size, padded, and a name that follows the header
+        std::fs::write(source.join("odd.txt"), "odd").unwrap();
+        std::fs::write(source.join("a member with spaces.txt"), "spaces").unwrap();
+
+        let destination = dir.join("out.a");
+        Archive::create(CreateOptions {
+            destination: destination.clone(),
+            source: source.clone(),
+            roots: vec![],
+            files: vec![
+                source.clone(),
+                source.join("odd.txt"),
+                source.join("a member with spaces.txt"),
+            ],
+            password: None,
+            archive_type: ArchiveType::Ar,
+            compression: Some(CompressionSpec::new(ArchiveCompression::None)),
+            overwrite: true,
+            include_hidden: true,
+            skip_unreadable: false,
+            verify_after: true,
+            volume_size: None,
+            update: false,
+            sevenz: Default::default(),
+            zip: Default::default(),
+            tar: Default::default(),
+            event_handler: Box::new(SimpleLogger),
+        })
+        .unwrap();
+
+        let archive = Archive::of(DataSource::file(&destination).unwrap()).unwrap();
+        assert_eq!(
+            names(&archive),
+            vec![
+                ("odd.txt".to_string(), Some(3)),
+                ("a member with spaces.txt".to_string(), Some(6)),
+            ]
+        );
+        _ = std::fs::remove_dir_all(&dir);
+    }
+}
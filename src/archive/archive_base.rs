@@ -1,9 +1,12 @@
 use std::{
+    cell::OnceCell,
+    collections::{HashMap, HashSet},
     fmt::Debug,
     fs::File,
-    io::{Cursor, Error, ErrorKind, Read, Seek, SeekFrom, Write},
-    marker::PhantomData,
-    path::{Path, PathBuf},
+    io::{BufReader, Cursor, Error, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
 };
 
 use chrono::TimeZone;
@@ -11,24 +14,64 @@ use serde::{Deserialize, Serialize};
 
 use crate::archive::codecs::ArchiveCodec;
 
-use super::codecs::ArchiveCompression;
+use super::codecs::{ArchiveCompression, CompressionSpec};
+use super::edit::EntryEdit;
+use super::entry_visitor::normalized_name;
+use super::extract_cache::ExtractCache;
+use super::guess::ArchiveTypeExt;
+use super::manifest::{ManifestDiff, SourceFiles};
+use super::owner::Owner;
+use super::password::PasswordProvider;
+use super::retry::IoRetryPolicy;
+use super::suggest::entry_suggestions;
+use super::volumes::{self, Volumes};
 
 #[cfg(feature = "sevenz_archive")]
 use super::sevenz_archive::SevenZArchive;
 
 #[cfg(feature = "tar_archive")]
-use super::tar_archive::TarArchive;
+use super::tar_archive::{is_tar_header, TarArchive};
 
 #[cfg(feature = "zip_archive")]
 use super::zip_archive::ZipArchive;
 
+#[cfg(feature = "ar_archive")]
+use super::ar_archive::ArArchive;
+#[cfg(feature = "arj_archive")]
+use super::arj_archive::ArjArchive;
+#[cfg(feature = "cab_archive")]
+use super::cab_archive::CabArchive;
+#[cfg(feature = "compressed_file")]
+use super::compressed_file::CompressedFile;
+#[cfg(feature = "cpio_archive")]
+use super::cpio_archive::{CpioArchive, CpioFormat};
+#[cfg(feature = "disk_image")]
+use super::disk_image::{is_disk_image, DiskImageArchive};
+#[cfg(feature = "dmg_archive")]
+use super::dmg_archive::DmgArchive;
 #[cfg(feature = "iso_archive")]
 use super::iso_archive::ISOArchive;
+#[cfg(feature = "lha_archive")]
+use super::lha_archive::LhaArchive;
+#[cfg(feature = "msi_archive")]
+use super::msi_archive::{is_installer as is_msi_installer, MsiArchive, COMPOUND_FILE_MAGIC};
+#[cfg(feature = "nsis_archive")]
+use super::nsis_archive::{is_installer as is_nsis_installer, NsisArchive};
+#[cfg(feature = "rar_archive")]
+use super::rar_archive::RarArchive;
+#[cfg(feature = "squashfs_archive")]
+use super::squashfs_archive::SquashfsArchive;
+#[cfg(feature = "warc_archive")]
+use super::warc_archive::{WarcArchive, WARC_MAGIC};
+#[cfg(feature = "wim_archive")]
+use super::wim_archive::WimArchive;
+#[cfg(feature = "zpaq_archive")]
+use super::zpaq_archive::ZpaqArchive;
 
 pub const DEFAULT_BUF_SIZE: usize = 32 * 1024;
 
-pub trait Archived<'a> {
-    fn of(source: DataSource<'a>) -> Result<Self, ArchiveError>
+pub trait Archived {
+    fn of(source: DataSource) -> Result<Self, ArchiveError>
     where
         Self: Sized;
 
@@ -39,14 +82,16 @@ pub trait Archived<'a> {
         Self::of(DataSource::file(path)?)
     }
 
-    fn from_bytes(bytes: &'a Vec<u8>) -> Result<Self, ArchiveError>
+    fn from_bytes<B: Into<Arc<[u8]>>>(bytes: B) -> Result<Self, ArchiveError>
     where
         Self: Sized,
     {
-        Self::of(DataSource::stream(bytes))
+        Self::of(DataSource::memory(bytes))
     }
 
-    fn extract(&self, options: ExtractOptions) -> Result<(), ArchiveError>;
+    /// Extracts the archive to [`ExtractOptions::destination`], reporting what became of each
+    /// entry.
+    fn extract(&self, options: ExtractOptions) -> Result<ExtractReport, ArchiveError>;
 
     fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError>;
 
@@ -54,61 +99,402 @@ pub trait Archived<'a> {
 
     fn metadata(&self) -> Result<ArchiveMetadata, ArchiveError>;
 
-    fn open(&'a self, options: OpenOptions) -> Result<(), ArchiveError>;
+    fn open(&self, options: OpenOptions) -> Result<(), ArchiveError>;
+
+    /// Returns the first file entry matching `predicate` along with its content, reading the
+    /// archive no further than that entry. Meant for previews, e.g. the cover of a comic book.
+    fn find_first<P>(
+        &self,
+        predicate: P,
+    ) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+    where
+        P: Fn(&ArchiveFileEntity) -> bool;
+
+    /// Returns whether `password` decrypts the archive. Archives without encryption accept
+    /// any password.
+    fn check_password(&self, password: &str) -> Result<bool, ArchiveError>;
+
+    /// Applies `edit` to the entry named `entry` of the archive file, rewriting as little of it
+    /// as the format allows.
+    fn edit(&self, entry: &str, edit: &EntryEdit) -> Result<(), ArchiveError>;
+
+    /// Renames `entry`, and the entries under it if it is a directory, to `new_name`.
+    fn rename(&self, entry: &str, new_name: &str) -> Result<(), ArchiveError> {
+        self.edit(entry, &EntryEdit::Rename(new_name.to_string()))
+    }
+
+    /// Sets the modification time of `entry`.
+    fn touch(
+        &self,
+        entry: &str,
+        modified: chrono::DateTime<chrono::FixedOffset>,
+    ) -> Result<(), ArchiveError> {
+        self.edit(entry, &EntryEdit::Touch(modified))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ArchiveMetadata {
+    pub archive_type: ArchiveType,
     pub total_size: u64,
     pub compressed_size: u64,
     pub compression: Option<ArchiveCompression>,
+    pub entry_count: usize,
+    pub created: Option<chrono::DateTime<chrono::FixedOffset>>,
+    pub modified: Option<chrono::DateTime<chrono::FixedOffset>>,
     pub entries: Vec<ArchiveFileEntity>,
     pub additional: Option<serde_json::Value>,
 }
 
+/// Entries and size aggregates gathered in a single pass over an archive.
+#[derive(Debug, Clone)]
+pub(crate) struct ArchiveScan {
+    pub(crate) entries: Vec<ArchiveFileEntity>,
+    pub(crate) total_size: u64,
+    pub(crate) compressed_size: u64,
+}
+
+impl ArchiveScan {
+    pub(crate) fn new(entries: Vec<ArchiveFileEntity>) -> Self {
+        let (total_size, compressed_size) = entries.iter().fold((0, 0), |(s, cs), e| {
+            (s + e.size.unwrap_or(0), cs + e.compressed_size.unwrap_or(0))
+        });
+        Self {
+            entries,
+            total_size,
+            compressed_size,
+        }
+    }
+
+    /// Returns the scan stored in `cell`, running `scan` to fill it on first use so that
+    /// `list()` and `metadata()` on the same handle only walk the archive once.
+    pub(crate) fn cached<F>(
+        cell: &OnceCell<ArchiveScan>,
+        scan: F,
+    ) -> Result<&ArchiveScan, ArchiveError>
+    where
+        F: FnOnce() -> Result<Vec<ArchiveFileEntity>, ArchiveError>,
+    {
+        if let Some(cached) = cell.get() {
+            return Ok(cached);
+        }
+        let entries = scan()?;
+        Ok(cell.get_or_init(|| ArchiveScan::new(entries)))
+    }
+}
+
 pub struct CreateResult {
     pub path: PathBuf,
     pub total_size: u64,
     pub compressed_size: u64,
+    /// Files left out because they could not be read, with the error reading them, see
+    /// [`CreateOptions::skip_unreadable`].
+    pub unreadable: Vec<(PathBuf, String)>,
+}
+
+/// Outcome of an extraction, so that partial failures can be acted upon without parsing the
+/// events.
+#[derive(Debug, Default)]
+pub struct ExtractReport {
+    /// Files, directories and links written to the destination.
+    pub extracted: Vec<PathBuf>,
+    /// Entries that were not written, with the reason why. The entries left out by
+    /// [`ExtractOptions::files`] are not reported.
+    pub skipped: Vec<(String, SkipReason)>,
+    /// Entries that could not be read, with the error reading them.
+    pub failed: Vec<(String, String)>,
+    /// Uncompressed size of the extracted entries, as far as the archive tells it.
+    pub bytes: u64,
+    pub duration: std::time::Duration,
 }
 
-pub enum Archive<'a> {
+impl ExtractReport {
+    /// Whether every entry was extracted.
+    pub fn is_complete(&self) -> bool {
+        self.skipped.is_empty() && self.failed.is_empty()
+    }
+}
+
+pub enum Archive {
     #[cfg(feature = "zip_archive")]
-    Zip(ZipArchive<'a>),
+    Zip(ZipArchive),
     #[cfg(feature = "tar_archive")]
-    Tar(TarArchive<'a>),
+    Tar(TarArchive),
     #[cfg(feature = "sevenz_archive")]
-    SevenZ(SevenZArchive<'a>),
+    SevenZ(SevenZArchive),
     #[cfg(feature = "iso_archive")]
-    Iso(ISOArchive<'a>),
-    _Unreachable(PhantomData<&'a ()>),
+    Iso(ISOArchive),
+    #[cfg(feature = "rar_archive")]
+    Rar(RarArchive),
+    #[cfg(feature = "cpio_archive")]
+    Cpio(CpioArchive),
+    #[cfg(feature = "ar_archive")]
+    Ar(ArArchive),
+    #[cfg(feature = "squashfs_archive")]
+    Squashfs(SquashfsArchive),
+    #[cfg(feature = "cab_archive")]
+    Cab(CabArchive),
+    #[cfg(feature = "wim_archive")]
+    Wim(WimArchive),
+    #[cfg(feature = "lha_archive")]
+    Lha(LhaArchive),
+    #[cfg(feature = "arj_archive")]
+    Arj(ArjArchive),
+    #[cfg(feature = "msi_archive")]
+    Msi(MsiArchive),
+    #[cfg(feature = "nsis_archive")]
+    Nsis(NsisArchive),
+    #[cfg(feature = "zpaq_archive")]
+    Zpaq(ZpaqArchive),
+    #[cfg(feature = "dmg_archive")]
+    Dmg(DmgArchive),
+    #[cfg(feature = "disk_image")]
+    DiskImage(DiskImageArchive),
+    #[cfg(feature = "warc_archive")]
+    Warc(WarcArchive),
+    #[cfg(feature = "compressed_file")]
+    CompressedFile(CompressedFile),
+    _Unreachable,
 }
 
-impl<'a> Archive<'a> {
-    pub fn of(data: DataSource<'a>) -> Result<Self, ArchiveError> {
-        match ArchiveType::try_from_datasource(data.clone())?.0 {
+impl Archive {
+    /// Opens `data` as the archive its content tells, or else as the one its file extension tells,
+    /// with a warning logged.
+    pub fn of(data: DataSource) -> Result<Self, ArchiveError> {
+        Self::of_with_events(data, None)
+    }
+
+    /// Opens `data` like [`Archive::of`], the warning of an archive opened after its extension
+    /// being sent to `events` as an [`ArchiveEvent::Log`] rather than logged.
+    pub fn of_with_events(
+        data: DataSource,
+        events: Option<&dyn EventHandler>,
+    ) -> Result<Self, ArchiveError> {
+        let archive_type = match ArchiveType::try_from_datasource(data.clone()) {
+            Ok((archive_type, _)) => archive_type,
+            Err(e @ ArchiveError::UnknownArchiveType(_)) => {
+                // only files have an extension, streams are known by their content alone
+                let DataSource::File(_, path) = &data else {
+                    return Err(e);
+                };
+                let Ok((archive_type, _)) = ArchiveType::guess_from_filename(path) else {
+                    return Err(e);
+                };
+                let warning = format!(
+                    "{}: content not recognized, opening it as {} after its extension",
+                    path, archive_type
+                );
+                match events {
+                    Some(events) => events.handle(ArchiveEvent::Log(warning)),
+                    None => log::warn!("{}", warning),
+                }
+                return Self::of_type(archive_type, data).map_err(|_| e);
+            }
+            Err(e) => return Err(e),
+        };
+        Self::of_type(archive_type, data)
+    }
+
+    fn of_type(archive_type: ArchiveType, data: DataSource) -> Result<Self, ArchiveError> {
+        match archive_type {
             #[cfg(feature = "zip_archive")]
-            ArchiveType::Zip => Ok(Archive::Zip(ZipArchive { source: data })),
+            ArchiveType::Zip => Ok(Archive::Zip(ZipArchive::of(data)?)),
             #[cfg(feature = "tar_archive")]
-            ArchiveType::Tar => Ok(Archive::Tar(TarArchive { source: data })),
+            ArchiveType::Tar => Ok(Archive::Tar(TarArchive::of(data)?)),
             #[cfg(feature = "sevenz_archive")]
-            ArchiveType::SevenZ => Ok(Archive::SevenZ(SevenZArchive { source: data })),
+            ArchiveType::SevenZ => Ok(Archive::SevenZ(SevenZArchive::of(data)?)),
             #[cfg(feature = "iso_archive")]
-            ArchiveType::Iso => Ok(Archive::Iso(ISOArchive { source: data })),
+            ArchiveType::Iso => Ok(Archive::Iso(ISOArchive::of(data)?)),
+            #[cfg(feature = "rar_archive")]
+            ArchiveType::Rar => Ok(Archive::Rar(RarArchive::of(data)?)),
+            #[cfg(feature = "cpio_archive")]
+            ArchiveType::Cpio => Ok(Archive::Cpio(CpioArchive::of(data)?)),
+            #[cfg(feature = "ar_archive")]
+            ArchiveType::Ar => Ok(Archive::Ar(ArArchive::of(data)?)),
+            #[cfg(feature = "squashfs_archive")]
+            ArchiveType::Squashfs => Ok(Archive::Squashfs(SquashfsArchive::of(data)?)),
+            #[cfg(feature = "cab_archive")]
+            ArchiveType::Cab => Ok(Archive::Cab(CabArchive::of(data)?)),
+            #[cfg(feature = "wim_archive")]
+            ArchiveType::Wim => Ok(Archive::Wim(WimArchive::of(data)?)),
+            #[cfg(feature = "lha_archive")]
+            ArchiveType::Lha => Ok(Archive::Lha(LhaArchive::of(data)?)),
+            #[cfg(feature = "arj_archive")]
+            ArchiveType::Arj => Ok(Archive::Arj(ArjArchive::of(data)?)),
+            #[cfg(feature = "msi_archive")]
+            ArchiveType::Msi => Ok(Archive::Msi(MsiArchive::of(data)?)),
+            #[cfg(feature = "nsis_archive")]
+            ArchiveType::Nsis => Ok(Archive::Nsis(NsisArchive::of(data)?)),
+            #[cfg(feature = "zpaq_archive")]
+            ArchiveType::Zpaq => Ok(Archive::Zpaq(ZpaqArchive::of(data)?)),
+            #[cfg(feature = "dmg_archive")]
+            ArchiveType::Dmg => Ok(Archive::Dmg(DmgArchive::of(data)?)),
+            #[cfg(feature = "disk_image")]
+            ArchiveType::DiskImage => Ok(Archive::DiskImage(DiskImageArchive::of(data)?)),
+            #[cfg(feature = "warc_archive")]
+            ArchiveType::Warc => Ok(Archive::Warc(WarcArchive::of(data)?)),
+            #[cfg(feature = "compressed_file")]
+            ArchiveType::CompressedFile => Ok(Archive::CompressedFile(CompressedFile::of(data)?)),
             ArchiveType::_Unreachable => unreachable!(),
         }
     }
+
+    /// Fills an [`ArchiveError::EntryNotFound`] with the names of the entries closest to the one
+    /// that was asked for.
+    fn suggest_entries(&self, error: ArchiveError) -> ArchiveError {
+        let ArchiveError::EntryNotFound(path, _) = error else {
+            return error;
+        };
+        // without suggestions if the entries cannot be listed, e.g. without a password
+        let target = path.to_string_lossy().replace('\\', "/");
+        let suggestions = self
+            .list(ListOptions::default())
+            .map(|entries| entry_suggestions(&target, entries.iter().map(|e| e.name.as_str())))
+            .unwrap_or_default();
+        ArchiveError::EntryNotFound(path, suggestions)
+    }
+
+    /// Adds [`AppendOptions::files`] to the archive file without recreating it from the source
+    /// files: zip archives are written to over their central directory, uncompressed tar archives
+    /// past their last entry, and compressed tar and 7z archives are compressed again. Files
+    /// already in the archive are refused unless [`AppendOptions::replace`] is set, directories
+    /// already in it are left as they are.
+    pub fn append(&self, mut options: AppendOptions) -> Result<CreateResult, ArchiveError> {
+        let existing = self
+            .list(ListOptions::default())?
+            .into_iter()
+            .map(|e| normalized_name(&e.name).trim_end_matches('/').to_string())
+            .collect::<HashSet<_>>();
+        let mut files = Vec::with_capacity(options.files.len());
+        for path in std::mem::take(&mut options.files) {
+            let name = options
+                .entry_name(&path)
+                .to_string_lossy()
+                .replace('\\', "/");
+            if !existing.contains(name.trim_end_matches('/')) || options.replace && !path.is_dir() {
+                files.push(path);
+            } else if !path.is_dir() {
+                return Err(ArchiveError::Io(Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("{} is already in the archive", name),
+                )));
+            }
+        }
+        options.files = files;
+
+        match self {
+            #[cfg(feature = "zip_archive")]
+            Archive::Zip(a) => a.append(options),
+            #[cfg(feature = "tar_archive")]
+            Archive::Tar(a) => a.append(options),
+            #[cfg(feature = "sevenz_archive")]
+            Archive::SevenZ(a) => a.append(options),
+            _ => Err(ArchiveError::UnsupportedActionForArchiveType(
+                "append".to_string(),
+                self.metadata()?.archive_type,
+            )),
+        }
+    }
+}
+
+/// Adds the files of `options` that changed since they were stored in the archive at its
+/// destination, see [`CreateOptions::update`].
+fn update_archive(options: CreateOptions) -> Result<CreateResult, ArchiveError> {
+    let archive = Archive::of(DataSource::file(&options.destination)?)?;
+    let unsupported = match options.archive_type {
+        _ if options.password.is_some() => Some("update with a password"),
+        #[cfg(feature = "sevenz_archive")]
+        ArchiveType::SevenZ if options.sevenz.solid => Some("update with solid blocks"),
+        _ => None,
+    };
+    if let Some(action) = unsupported {
+        return Err(ArchiveError::UnsupportedActionForArchiveType(
+            action.to_string(),
+            options.archive_type,
+        ));
+    }
+    let entries = archive
+        .list(ListOptions::default())?
+        .into_iter()
+        .map(|e| {
+            (
+                normalized_name(&e.name).trim_end_matches('/').to_string(),
+                e,
+            )
+        })
+        .collect::<HashMap<_, _>>();
+    let changed = |path: &PathBuf| {
+        let name = options
+            .entry_name(path)
+            .to_string_lossy()
+            .replace('\\', "/");
+        let name = normalized_name(&name).trim_end_matches('/');
+        let (Some(entry), Ok(metadata)) = (entries.get(name), std::fs::metadata(path)) else {
+            // the source directory itself is the top level of the archive
+            return !name.is_empty();
+        };
+        if metadata.is_dir() {
+            return false;
+        }
+        let modified = metadata
+            .modified()
+            .ok()
+            .map(|m| chrono::DateTime::<chrono::Utc>::from(m).timestamp());
+        // zip times are to two seconds
+        let same_time = match (modified, entry.last_modified) {
+            (Some(modified), Some(stored)) => (0..2).contains(&(modified - stored.timestamp())),
+            _ => false,
+        };
+        entry.size != Some(metadata.len()) || !same_time
+    };
+    let files = options
+        .files
+        .iter()
+        .filter(|path| changed(path))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    if files.is_empty() {
+        options.event_handler.handle(ArchiveEvent::Log(format!(
+            "{} is up to date",
+            options.destination.display()
+        )));
+        return Ok(CreateResult {
+            compressed_size: std::fs::metadata(&options.destination)?.len(),
+            path: options.destination,
+            total_size: 0,
+            unreadable: Vec::new(),
+        });
+    }
+    options.event_handler.handle(ArchiveEvent::Log(format!(
+        "Updating {} with {} changed files",
+        options.destination.display(),
+        files.len()
+    )));
+    let level = options.compression_level();
+    archive.append(AppendOptions {
+        source: options.source,
+        files,
+        compression: options.compression.map(|c| c.algorithm),
+        level,
+        roots: options.roots,
+        replace: true,
+        sevenz: options.sevenz,
+        zip: options.zip,
+        tar: options.tar,
+        event_handler: options.event_handler,
+    })
 }
 
-impl<'a> Archived<'a> for Archive<'a> {
-    fn of(source: DataSource<'a>) -> Result<Self, ArchiveError>
+impl Archived for Archive {
+    fn of(source: DataSource) -> Result<Self, ArchiveError>
     where
         Self: Sized,
     {
         Self::of(source)
     }
 
-    fn extract(&self, options: ExtractOptions) -> Result<(), ArchiveError> {
+    fn extract(&self, options: ExtractOptions) -> Result<ExtractReport, ArchiveError> {
         match self {
             #[cfg(feature = "zip_archive")]
             Archive::Zip(a) => a.extract(options),
@@ -118,7 +504,37 @@ impl<'a> Archived<'a> for Archive<'a> {
             Archive::SevenZ(a) => a.extract(options),
             #[cfg(feature = "iso_archive")]
             Archive::Iso(a) => a.extract(options),
-            Archive::_Unreachable(_) => unreachable!(),
+            #[cfg(feature = "rar_archive")]
+            Archive::Rar(a) => a.extract(options),
+            #[cfg(feature = "cpio_archive")]
+            Archive::Cpio(a) => a.extract(options),
+            #[cfg(feature = "ar_archive")]
+            Archive::Ar(a) => a.extract(options),
+            #[cfg(feature = "squashfs_archive")]
+            Archive::Squashfs(a) => a.extract(options),
+            #[cfg(feature = "cab_archive")]
+            Archive::Cab(a) => a.extract(options),
+            #[cfg(feature = "wim_archive")]
+            Archive::Wim(a) => a.extract(options),
+            #[cfg(feature = "lha_archive")]
+            Archive::Lha(a) => a.extract(options),
+            #[cfg(feature = "arj_archive")]
+            Archive::Arj(a) => a.extract(options),
+            #[cfg(feature = "msi_archive")]
+            Archive::Msi(a) => a.extract(options),
+            #[cfg(feature = "nsis_archive")]
+            Archive::Nsis(a) => a.extract(options),
+            #[cfg(feature = "zpaq_archive")]
+            Archive::Zpaq(a) => a.extract(options),
+            #[cfg(feature = "dmg_archive")]
+            Archive::Dmg(a) => a.extract(options),
+            #[cfg(feature = "disk_image")]
+            Archive::DiskImage(a) => a.extract(options),
+            #[cfg(feature = "warc_archive")]
+            Archive::Warc(a) => a.extract(options),
+            #[cfg(feature = "compressed_file")]
+            Archive::CompressedFile(a) => a.extract(options),
+            Archive::_Unreachable => unreachable!(),
         }
     }
 
@@ -132,13 +548,52 @@ impl<'a> Archived<'a> for Archive<'a> {
             Archive::SevenZ(a) => a.list(options),
             #[cfg(feature = "iso_archive")]
             Archive::Iso(a) => a.list(options),
-            Archive::_Unreachable(_) => unreachable!(),
+            #[cfg(feature = "rar_archive")]
+            Archive::Rar(a) => a.list(options),
+            #[cfg(feature = "cpio_archive")]
+            Archive::Cpio(a) => a.list(options),
+            #[cfg(feature = "ar_archive")]
+            Archive::Ar(a) => a.list(options),
+            #[cfg(feature = "squashfs_archive")]
+            Archive::Squashfs(a) => a.list(options),
+            #[cfg(feature = "cab_archive")]
+            Archive::Cab(a) => a.list(options),
+            #[cfg(feature = "wim_archive")]
+            Archive::Wim(a) => a.list(options),
+            #[cfg(feature = "lha_archive")]
+            Archive::Lha(a) => a.list(options),
+            #[cfg(feature = "arj_archive")]
+            Archive::Arj(a) => a.list(options),
+            #[cfg(feature = "msi_archive")]
+            Archive::Msi(a) => a.list(options),
+            #[cfg(feature = "nsis_archive")]
+            Archive::Nsis(a) => a.list(options),
+            #[cfg(feature = "zpaq_archive")]
+            Archive::Zpaq(a) => a.list(options),
+            #[cfg(feature = "dmg_archive")]
+            Archive::Dmg(a) => a.list(options),
+            #[cfg(feature = "disk_image")]
+            Archive::DiskImage(a) => a.list(options),
+            #[cfg(feature = "warc_archive")]
+            Archive::Warc(a) => a.list(options),
+            #[cfg(feature = "compressed_file")]
+            Archive::CompressedFile(a) => a.list(options),
+            Archive::_Unreachable => unreachable!(),
         }
     }
 
-    fn create(options: CreateOptions) -> Result<CreateResult, ArchiveError> {
+    fn create(mut options: CreateOptions) -> Result<CreateResult, ArchiveError> {
         let archive_type = ArchiveType::guess_from_filename(&options.destination)?.0;
-        match archive_type {
+        options.remove_destination();
+        let unreadable = options.remove_unreadable();
+        let volume_size = options.volume_size;
+        // hashed before the backend takes the options
+        let sources = match options.verify_after {
+            true => Some((SourceFiles::hash(&options)?, options.password.clone())),
+            false => None,
+        };
+        let result: Result<CreateResult, ArchiveError> = match archive_type {
+            _ if options.update && options.destination.is_file() => update_archive(options),
             #[cfg(feature = "zip_archive")]
             ArchiveType::Zip => ZipArchive::create(options),
             #[cfg(feature = "tar_archive")]
@@ -147,8 +602,49 @@ impl<'a> Archived<'a> for Archive<'a> {
             ArchiveType::SevenZ => SevenZArchive::create(options),
             #[cfg(feature = "iso_archive")]
             ArchiveType::Iso => ISOArchive::create(options),
+            #[cfg(feature = "rar_archive")]
+            ArchiveType::Rar => RarArchive::create(options),
+            #[cfg(feature = "cpio_archive")]
+            ArchiveType::Cpio => CpioArchive::create(options),
+            #[cfg(feature = "ar_archive")]
+            ArchiveType::Ar => ArArchive::create(options),
+            #[cfg(feature = "squashfs_archive")]
+            ArchiveType::Squashfs => SquashfsArchive::create(options),
+            #[cfg(feature = "cab_archive")]
+            ArchiveType::Cab => CabArchive::create(options),
+            #[cfg(feature = "wim_archive")]
+            ArchiveType::Wim => WimArchive::create(options),
+            #[cfg(feature = "lha_archive")]
+            ArchiveType::Lha => LhaArchive::create(options),
+            #[cfg(feature = "arj_archive")]
+            ArchiveType::Arj => ArjArchive::create(options),
+            #[cfg(feature = "msi_archive")]
+            ArchiveType::Msi => MsiArchive::create(options),
+            #[cfg(feature = "nsis_archive")]
+            ArchiveType::Nsis => NsisArchive::create(options),
+            #[cfg(feature = "zpaq_archive")]
+            ArchiveType::Zpaq => ZpaqArchive::create(options),
+            #[cfg(feature = "dmg_archive")]
+            ArchiveType::Dmg => DmgArchive::create(options),
+            #[cfg(feature = "disk_image")]
+            ArchiveType::DiskImage => DiskImageArchive::create(options),
+            #[cfg(feature = "warc_archive")]
+            ArchiveType::Warc => WarcArchive::create(options),
+            #[cfg(feature = "compressed_file")]
+            ArchiveType::CompressedFile => CompressedFile::create(options),
             ArchiveType::_Unreachable => unreachable!(),
+        };
+        let mut result = result?;
+        if let Some((sources, password)) = sources {
+            sources.verify(&result.path, password.as_deref())?;
+        }
+        if let Some(volume_size) = volume_size {
+            result.path = volumes::split(&result.path, volume_size)?.swap_remove(0);
         }
+        Ok(CreateResult {
+            unreadable,
+            ..result
+        })
     }
 
     fn metadata(&self) -> Result<ArchiveMetadata, ArchiveError> {
@@ -161,12 +657,42 @@ impl<'a> Archived<'a> for Archive<'a> {
             Archive::SevenZ(a) => a.metadata(),
             #[cfg(feature = "iso_archive")]
             Archive::Iso(a) => a.metadata(),
-            Archive::_Unreachable(_) => unreachable!(),
+            #[cfg(feature = "rar_archive")]
+            Archive::Rar(a) => a.metadata(),
+            #[cfg(feature = "cpio_archive")]
+            Archive::Cpio(a) => a.metadata(),
+            #[cfg(feature = "ar_archive")]
+            Archive::Ar(a) => a.metadata(),
+            #[cfg(feature = "squashfs_archive")]
+            Archive::Squashfs(a) => a.metadata(),
+            #[cfg(feature = "cab_archive")]
+            Archive::Cab(a) => a.metadata(),
+            #[cfg(feature = "wim_archive")]
+            Archive::Wim(a) => a.metadata(),
+            #[cfg(feature = "lha_archive")]
+            Archive::Lha(a) => a.metadata(),
+            #[cfg(feature = "arj_archive")]
+            Archive::Arj(a) => a.metadata(),
+            #[cfg(feature = "msi_archive")]
+            Archive::Msi(a) => a.metadata(),
+            #[cfg(feature = "nsis_archive")]
+            Archive::Nsis(a) => a.metadata(),
+            #[cfg(feature = "zpaq_archive")]
+            Archive::Zpaq(a) => a.metadata(),
+            #[cfg(feature = "dmg_archive")]
+            Archive::Dmg(a) => a.metadata(),
+            #[cfg(feature = "disk_image")]
+            Archive::DiskImage(a) => a.metadata(),
+            #[cfg(feature = "warc_archive")]
+            Archive::Warc(a) => a.metadata(),
+            #[cfg(feature = "compressed_file")]
+            Archive::CompressedFile(a) => a.metadata(),
+            Archive::_Unreachable => unreachable!(),
         }
     }
 
-    fn open(&'a self, options: OpenOptions) -> Result<(), ArchiveError> {
-        match self {
+    fn open(&self, options: OpenOptions) -> Result<(), ArchiveError> {
+        let result: Result<(), ArchiveError> = match self {
             #[cfg(feature = "zip_archive")]
             Archive::Zip(a) => a.open(options),
             #[cfg(feature = "tar_archive")]
@@ -175,51 +701,804 @@ impl<'a> Archived<'a> for Archive<'a> {
             Archive::SevenZ(a) => a.open(options),
             #[cfg(feature = "iso_archive")]
             Archive::Iso(a) => a.open(options),
-            Archive::_Unreachable(_) => unreachable!(),
+            #[cfg(feature = "rar_archive")]
+            Archive::Rar(a) => a.open(options),
+            #[cfg(feature = "cpio_archive")]
+            Archive::Cpio(a) => a.open(options),
+            #[cfg(feature = "ar_archive")]
+            Archive::Ar(a) => a.open(options),
+            #[cfg(feature = "squashfs_archive")]
+            Archive::Squashfs(a) => a.open(options),
+            #[cfg(feature = "cab_archive")]
+            Archive::Cab(a) => a.open(options),
+            #[cfg(feature = "wim_archive")]
+            Archive::Wim(a) => a.open(options),
+            #[cfg(feature = "lha_archive")]
+            Archive::Lha(a) => a.open(options),
+            #[cfg(feature = "arj_archive")]
+            Archive::Arj(a) => a.open(options),
+            #[cfg(feature = "msi_archive")]
+            Archive::Msi(a) => a.open(options),
+            #[cfg(feature = "nsis_archive")]
+            Archive::Nsis(a) => a.open(options),
+            #[cfg(feature = "zpaq_archive")]
+            Archive::Zpaq(a) => a.open(options),
+            #[cfg(feature = "dmg_archive")]
+            Archive::Dmg(a) => a.open(options),
+            #[cfg(feature = "disk_image")]
+            Archive::DiskImage(a) => a.open(options),
+            #[cfg(feature = "warc_archive")]
+            Archive::Warc(a) => a.open(options),
+            #[cfg(feature = "compressed_file")]
+            Archive::CompressedFile(a) => a.open(options),
+            Archive::_Unreachable => unreachable!(),
+        };
+        result.map_err(|e| self.suggest_entries(e))
+    }
+
+    fn find_first<P>(
+        &self,
+        predicate: P,
+    ) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+    where
+        P: Fn(&ArchiveFileEntity) -> bool,
+    {
+        match self {
+            #[cfg(feature = "zip_archive")]
+            Archive::Zip(a) => a.find_first(predicate),
+            #[cfg(feature = "tar_archive")]
+            Archive::Tar(a) => a.find_first(predicate),
+            #[cfg(feature = "sevenz_archive")]
+            Archive::SevenZ(a) => a.find_first(predicate),
+            #[cfg(feature = "iso_archive")]
+            Archive::Iso(a) => a.find_first(predicate),
+            #[cfg(feature = "rar_archive")]
+            Archive::Rar(a) => a.find_first(predicate),
+            #[cfg(feature = "cpio_archive")]
+            Archive::Cpio(a) => a.find_first(predicate),
+            #[cfg(feature = "ar_archive")]
+            Archive::Ar(a) => a.find_first(predicate),
+            #[cfg(feature = "squashfs_archive")]
+            Archive::Squashfs(a) => a.find_first(predicate),
+            #[cfg(feature = "cab_archive")]
+            Archive::Cab(a) => a.find_first(predicate),
+            #[cfg(feature = "wim_archive")]
+            Archive::Wim(a) => a.find_first(predicate),
+            #[cfg(feature = "lha_archive")]
+            Archive::Lha(a) => a.find_first(predicate),
+            #[cfg(feature = "arj_archive")]
+            Archive::Arj(a) => a.find_first(predicate),
+            #[cfg(feature = "msi_archive")]
+            Archive::Msi(a) => a.find_first(predicate),
+            #[cfg(feature = "nsis_archive")]
+            Archive::Nsis(a) => a.find_first(predicate),
+            #[cfg(feature = "zpaq_archive")]
+            Archive::Zpaq(a) => a.find_first(predicate),
+            #[cfg(feature = "dmg_archive")]
+            Archive::Dmg(a) => a.find_first(predicate),
+            #[cfg(feature = "disk_image")]
+            Archive::DiskImage(a) => a.find_first(predicate),
+            #[cfg(feature = "warc_archive")]
+            Archive::Warc(a) => a.find_first(predicate),
+            #[cfg(feature = "compressed_file")]
+            Archive::CompressedFile(a) => a.find_first(predicate),
+            Archive::_Unreachable => unreachable!(),
         }
     }
+
+    fn check_password(&self, password: &str) -> Result<bool, ArchiveError> {
+        match self {
+            #[cfg(feature = "zip_archive")]
+            Archive::Zip(a) => a.check_password(password),
+            #[cfg(feature = "tar_archive")]
+            Archive::Tar(a) => a.check_password(password),
+            #[cfg(feature = "sevenz_archive")]
+            Archive::SevenZ(a) => a.check_password(password),
+            #[cfg(feature = "iso_archive")]
+            Archive::Iso(a) => a.check_password(password),
+            #[cfg(feature = "rar_archive")]
+            Archive::Rar(a) => a.check_password(password),
+            #[cfg(feature = "cpio_archive")]
+            Archive::Cpio(a) => a.check_password(password),
+            #[cfg(feature = "ar_archive")]
+            Archive::Ar(a) => a.check_password(password),
+            #[cfg(feature = "squashfs_archive")]
+            Archive::Squashfs(a) => a.check_password(password),
+            #[cfg(feature = "cab_archive")]
+            Archive::Cab(a) => a.check_password(password),
+            #[cfg(feature = "wim_archive")]
+            Archive::Wim(a) => a.check_password(password),
+            #[cfg(feature = "lha_archive")]
+            Archive::Lha(a) => a.check_password(password),
+            #[cfg(feature = "arj_archive")]
+            Archive::Arj(a) => a.check_password(password),
+            #[cfg(feature = "msi_archive")]
+            Archive::Msi(a) => a.check_password(password),
+            #[cfg(feature = "nsis_archive")]
+            Archive::Nsis(a) => a.check_password(password),
+            #[cfg(feature = "zpaq_archive")]
+            Archive::Zpaq(a) => a.check_password(password),
+            #[cfg(feature = "dmg_archive")]
+            Archive::Dmg(a) => a.check_password(password),
+            #[cfg(feature = "disk_image")]
+            Archive::DiskImage(a) => a.check_password(password),
+            #[cfg(feature = "warc_archive")]
+            Archive::Warc(a) => a.check_password(password),
+            #[cfg(feature = "compressed_file")]
+            Archive::CompressedFile(a) => a.check_password(password),
+            Archive::_Unreachable => unreachable!(),
+        }
+    }
+
+    fn edit(&self, entry: &str, edit: &EntryEdit) -> Result<(), ArchiveError> {
+        let result: Result<(), ArchiveError> = match self {
+            #[cfg(feature = "zip_archive")]
+            Archive::Zip(a) => a.edit(entry, edit),
+            #[cfg(feature = "tar_archive")]
+            Archive::Tar(a) => a.edit(entry, edit),
+            #[cfg(feature = "sevenz_archive")]
+            Archive::SevenZ(a) => a.edit(entry, edit),
+            #[cfg(feature = "iso_archive")]
+            Archive::Iso(a) => a.edit(entry, edit),
+            #[cfg(feature = "rar_archive")]
+            Archive::Rar(a) => a.edit(entry, edit),
+            #[cfg(feature = "cpio_archive")]
+            Archive::Cpio(a) => a.edit(entry, edit),
+            #[cfg(feature = "ar_archive")]
+            Archive::Ar(a) => a.edit(entry, edit),
+            #[cfg(feature = "squashfs_archive")]
+            Archive::Squashfs(a) => a.edit(entry, edit),
+            #[cfg(feature = "cab_archive")]
+            Archive::Cab(a) => a.edit(entry, edit),
+            #[cfg(feature = "wim_archive")]
+            Archive::Wim(a) => a.edit(entry, edit),
+            #[cfg(feature = "lha_archive")]
+            Archive::Lha(a) => a.edit(entry, edit),
+            #[cfg(feature = "arj_archive")]
+            Archive::Arj(a) => a.edit(entry, edit),
+            #[cfg(feature = "msi_archive")]
+            Archive::Msi(a) => a.edit(entry, edit),
+            #[cfg(feature = "nsis_archive")]
+            Archive::Nsis(a) => a.edit(entry, edit),
+            #[cfg(feature = "zpaq_archive")]
+            Archive::Zpaq(a) => a.edit(entry, edit),
+            #[cfg(feature = "dmg_archive")]
+            Archive::Dmg(a) => a.edit(entry, edit),
+            #[cfg(feature = "disk_image")]
+            Archive::DiskImage(a) => a.edit(entry, edit),
+            #[cfg(feature = "warc_archive")]
+            Archive::Warc(a) => a.edit(entry, edit),
+            #[cfg(feature = "compressed_file")]
+            Archive::CompressedFile(a) => a.edit(entry, edit),
+            Archive::_Unreachable => unreachable!(),
+        };
+        result.map_err(|e| self.suggest_entries(e))
+    }
 }
 #[derive(Debug)]
 pub struct ExtractOptions<'a> {
     pub destination: PathBuf,
-    pub password: Option<String>,
+    pub password: Option<Box<dyn PasswordProvider + 'a>>,
     pub files: Option<Vec<String>>,
     pub overwrite: bool,
+    /// Only replace existing files with entries modified more recently, like rsync's `--update`.
+    pub update: bool,
+    /// Permissions of every extracted file, instead of those of its entry less the umask.
+    pub file_mode: Option<u32>,
+    /// Permissions of every extracted directory, instead of those of its entry less the umask.
+    pub dir_mode: Option<u32>,
+    /// Keep the permissions of the entries as they are, with their setuid, setgid and sticky
+    /// bits and without the umask, like `tar -p`. Otherwise the special bits are dropped, so that
+    /// an archive cannot plant setuid programs in the destination.
+    pub preserve_permissions: bool,
     pub show_hidden: bool,
+    /// Retries of the reads from the archive and writes to the destination that fail with a
+    /// transient error.
+    pub retry: IoRetryPolicy,
+    /// Applied in order to the content of every extracted file.
+    pub transformers: Vec<Box<dyn EntryTransformer + 'a>>,
+    /// Reads the content of every file before it is written, able to reject it.
+    pub inspector: Option<Box<dyn EntryInspector + 'a>>,
+    /// Writes the files on a pool of threads while the archive is read, their number and the size
+    /// of the chunks they write following the measured read and write throughput. The retries of
+    /// those writes are not reported, and inspected files are still written one at a time.
+    pub adaptive: bool,
+    /// Restores the files whose archive records a checksum, as zip and 7z ones do, from this cache
+    /// when it holds their content, which is stored there otherwise. Not used along with
+    /// transformers or an inspector.
+    pub cache: Option<ExtractCache>,
+    /// Sends an [`ArchiveEvent::EntryProgress`] every time this many more bytes of a file are
+    /// copied, so that the progress moves while an archive holding one huge file is extracted.
+    pub progress_interval: Option<u64>,
+    /// Sizes and counts past which the extraction fails with [`ArchiveError::LimitExceeded`],
+    /// so that a hostile archive, such as a zip bomb, cannot fill the disk.
+    pub limits: ExtractLimits,
     pub event_handler: Box<dyn EventHandler + 'a>,
 }
 
-impl<'a> TryFrom<DataSource<'a>> for Archive<'a> {
-    fn try_from(value: DataSource<'a>) -> Result<Self, Self::Error> {
+/// Limits of an extraction, checked against the sizes the archive declares before an entry is
+/// written and against the bytes written while it is, the declared sizes being untrusted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExtractLimits {
+    /// Bytes written for all the files together.
+    pub max_total_uncompressed: Option<u64>,
+    /// Bytes written for a single file.
+    pub max_entry_size: Option<u64>,
+    /// Files, directories and links extracted.
+    pub max_entries: Option<usize>,
+    /// Size of a file over its compressed size in the archive, checked once more than
+    /// [`ExtractLimits::RATIO_CHECKED_FROM`] bytes of it are written, as small files compress
+    /// well without harm. Formats compressing the whole archive, such as tar.gz, do not record
+    /// the compressed size of their entries, whose sizes added up are checked against the size
+    /// of the archive instead, unless it is read from a pipe.
+    pub max_compression_ratio: Option<f64>,
+}
+
+impl ExtractLimits {
+    pub const RATIO_CHECKED_FROM: u64 = 1024 * 1024;
+
+    /// No limit at all.
+    pub const NONE: Self = Self {
+        max_total_uncompressed: None,
+        max_entry_size: None,
+        max_entries: None,
+        max_compression_ratio: None,
+    };
+}
+
+/// Only the compression ratio is limited, to 10000, past the 1032 deflate reaches at most, so
+/// that a file of zeros compressed well is still extracted while the bombs of the formats
+/// compressing further, such as bzip2 or zstd, are not.
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        Self {
+            max_compression_ratio: Some(10000.0),
+            ..Self::NONE
+        }
+    }
+}
+
+/// Limit of [`ExtractLimits`] an extraction went past, with its value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExceededLimit {
+    TotalUncompressed(u64),
+    EntrySize(u64),
+    Entries(usize),
+    CompressionRatio(f64),
+}
+
+impl std::fmt::Display for ExceededLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExceededLimit::TotalUncompressed(max) => write!(f, "{} bytes in total", max),
+            ExceededLimit::EntrySize(max) => write!(f, "{} bytes for a file", max),
+            ExceededLimit::Entries(max) => write!(f, "{} entries", max),
+            ExceededLimit::CompressionRatio(max) => write!(f, "a compression ratio of {}", max),
+        }
+    }
+}
+
+impl ExtractOptions<'_> {
+    /// Runs the content of `entity` through the [`ExtractOptions::transformers`].
+    pub(crate) fn transform<'r>(
+        &self,
+        entity: &ArchiveFileEntity,
+        reader: Box<dyn Read + 'r>,
+    ) -> Box<dyn Read + 'r> {
+        self.transformers
+            .iter()
+            .fold(reader, |reader, t| t.transform(entity, reader))
+    }
+}
+
+impl TryFrom<DataSource> for Archive {
+    fn try_from(value: DataSource) -> Result<Self, Self::Error> {
         Archive::of(value)
     }
 
     type Error = ArchiveError;
 }
 
+impl TryFrom<&Path> for Archive {
+    fn try_from(path: &Path) -> Result<Self, Self::Error> {
+        Archive::of(DataSource::file(path)?)
+    }
+
+    type Error = ArchiveError;
+}
+
+impl TryFrom<&str> for Archive {
+    fn try_from(path: &str) -> Result<Self, Self::Error> {
+        Archive::try_from(Path::new(path))
+    }
+
+    type Error = ArchiveError;
+}
+
+/// The file has no path: its type is only recognized by its content, and it can not be edited nor,
+/// for RAR archives which are read by path, opened.
+impl TryFrom<File> for Archive {
+    fn try_from(file: File) -> Result<Self, Self::Error> {
+        Archive::of(DataSource::from(file))
+    }
+
+    type Error = ArchiveError;
+}
+
 #[derive(Debug)]
 pub struct ListOptions<'a> {
-    pub password: Option<String>,
+    pub password: Option<Box<dyn PasswordProvider + 'a>>,
+    /// Only list the entries under this directory of the archive.
+    pub prefix: Option<String>,
+    /// Number of entries to leave out before the first one listed, e.g. to page through an
+    /// archive along with [`ListOptions::limit`].
+    pub offset: usize,
+    /// Stops reading the archive once this many entries were listed.
+    pub limit: Option<usize>,
     pub event_handler: Box<dyn EventHandler + 'a>,
 }
 
+impl ListOptions<'_> {
+    /// Whether every entry of the archive is listed, which the listing cached by a scan answers.
+    pub(crate) fn is_whole(&self) -> bool {
+        self.prefix.is_none() && self.offset == 0 && self.limit.is_none()
+    }
+}
+
 #[derive(Debug)]
 pub struct CreateOptions<'a> {
     pub destination: PathBuf,
     pub source: PathBuf,
+    /// Additional source roots, each with the prefix its files are stored under.
+    pub roots: Vec<SourceRoot>,
     pub files: Vec<PathBuf>,
     pub password: Option<String>,
     pub archive_type: ArchiveType,
-    pub archive_compression: Option<ArchiveCompression>,
+    /// Compression of the archive and the settings of its codec, the default one of the format
+    /// if `None`, which formats compressing the whole archive lack.
+    pub compression: Option<CompressionSpec>,
     pub overwrite: bool,
     pub include_hidden: bool,
+    /// Leaves out the files that cannot be read, such as broken symlinks or files without read
+    /// permission, instead of failing. They are reported as skipped with
+    /// [`SkipReason::Unreadable`] and listed in [`CreateResult::unreadable`].
+    pub skip_unreadable: bool,
+    /// Reopens the archive once created and checks the size and SHA-256 of its files against
+    /// the source files, failing with [`ArchiveError::VerificationFailed`] if they differ, such
+    /// as when the archive was cut short by a full disk.
+    pub verify_after: bool,
+    /// Splits the archive once created into volumes of this many bytes, named after it with
+    /// `.001`, `.002`, ..., e.g. to fit a file size limit. [`CreateResult::path`] is then the
+    /// first volume, which [`DataSource::file`] reads the whole archive from.
+    pub volume_size: Option<u64>,
+    /// Updates the existing archive at the destination instead of creating it again: only the
+    /// files missing from it, or whose size or modification time differ from their entry, are
+    /// added to it with [`Archive::append`], replacing their entries. The entries of the files
+    /// gone from the source are kept. Archives that cannot be appended to are refused, as are
+    /// passwords and solid 7z blocks, which appending does not support.
+    pub update: bool,
+    /// How the contents of 7z archives are compressed, ignored by the other formats.
+    pub sevenz: SevenZCreateOptions,
+    /// Extra fields and limits of zip archives written to files, ignored by the other formats.
+    pub zip: ZipCreateOptions,
+    /// Header format and owners of tar archives, ignored by the other formats.
+    pub tar: TarCreateOptions,
     pub event_handler: Box<dyn EventHandler + 'a>,
 }
 
+/// Method compressing the contents of the 7z archives created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum SevenZContentMethod {
+    #[default]
+    Lzma2,
+    Lzma,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SevenZCreateOptions {
+    pub method: SevenZContentMethod,
+    /// Dictionary size in bytes, 8 MiB if `None`. Larger dictionaries find matches further
+    /// back, and need as much memory to decompress.
+    pub dictionary_size: Option<u32>,
+    /// Compresses the files together in solid blocks instead of one by one, which compresses
+    /// similar files better but has to decompress a block up to a file to read it.
+    pub solid: bool,
+    /// Size of the files after which a new solid block is started, a single block if `None`.
+    pub solid_block_size: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TarCreateOptions {
+    /// User owning every entry, such as `root`, instead of the owner of the file.
+    pub owner: Option<Owner>,
+    /// Group owning every entry instead of the group of the file.
+    pub group: Option<Owner>,
+    /// Only stores the numeric user and group ids, without their names.
+    pub numeric_owner: bool,
+    pub format: TarFormat,
+    /// Stores the modification times to the nanosecond in pax records, rather than to the second
+    /// in the headers. Only used by the [`TarFormat::Pax`] format.
+    pub precise_times: bool,
+}
+
+/// Headers of the entries of the tar archives created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum TarFormat {
+    /// GNU headers, storing long names in entries of their own, which GNU tar, bsdtar and most
+    /// other tools read.
+    #[default]
+    Gnu,
+    /// POSIX.1-2001 ustar headers, extended by pax records holding the names that do not fit
+    /// them or are not ASCII, as UTF-8.
+    Pax,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ZipCreateOptions {
+    pub zip64: Zip64Mode,
+    /// Adds the extended timestamp field holding the modification time in UTC to the second, on
+    /// top of the local time to two seconds of the MS-DOS fields.
+    pub extended_timestamps: bool,
+    pub unicode_names: ZipUnicodeNames,
+}
+
+/// How zip archives mark the names of their files that are not ASCII as UTF-8.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum ZipUnicodeNames {
+    /// The language encoding flag of their entries, which tools following APPNOTE 6.3 read.
+    #[default]
+    Flag,
+    /// The flag, and the Info-ZIP unicode path field, read by older tools ignoring the flag.
+    PathField,
+}
+
+/// When the entries of zip archives get the Zip64 fields, which older tools cannot read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum Zip64Mode {
+    /// Only the files of 4 GiB or more.
+    #[default]
+    Auto,
+    /// Never, failing on files of 4 GiB or more.
+    Never,
+    /// Every file.
+    Always,
+}
+
+impl Zip64Mode {
+    /// Whether a file of `size` bytes gets the Zip64 fields.
+    pub(crate) fn large_file(self, size: u64) -> bool {
+        match self {
+            Zip64Mode::Auto => size > u32::MAX as u64,
+            Zip64Mode::Never => false,
+            Zip64Mode::Always => true,
+        }
+    }
+}
+
+/// Preset of the [`CreateOptions`] trading between what the archives can be read by, how
+/// faithfully they keep the files and how fast they are created.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum CreateProfile {
+    /// Standard formats keeping the names and times exactly: pax tar headers with times to the
+    /// nanosecond, extended timestamps in zip archives, checked once created.
+    Strict,
+    /// Readable by older tools: GNU tar headers, and zip archives without Zip64 fields, failing
+    /// on files of 4 GiB or more instead.
+    Compat,
+    /// Fastest level of the codec that still compresses.
+    Fast,
+}
+
+impl CreateProfile {
+    /// Applies the preset to `options`, keeping the compression level they set, if any.
+    pub fn apply(self, options: &mut CreateOptions) {
+        match self {
+            CreateProfile::Strict => {
+                options.tar.format = TarFormat::Pax;
+                options.tar.precise_times = true;
+                options.zip.zip64 = Zip64Mode::Auto;
+                options.zip.extended_timestamps = true;
+                options.zip.unicode_names = ZipUnicodeNames::Flag;
+                options.verify_after = true;
+            }
+            CreateProfile::Compat => {
+                options.tar.format = TarFormat::Gnu;
+                options.tar.precise_times = false;
+                options.zip.zip64 = Zip64Mode::Never;
+                options.zip.extended_timestamps = false;
+                options.zip.unicode_names = ZipUnicodeNames::PathField;
+            }
+            CreateProfile::Fast => {
+                if options.compression.is_none() {
+                    options.compression =
+                        default_compression(options.archive_type).map(CompressionSpec::new);
+                }
+                if let Some(compression) = &mut options.compression {
+                    // level 0 of gzip and deflate only stores
+                    let fastest = compression
+                        .algorithm
+                        .valid_level_range()
+                        .map(|range| (*range.start()).max(1));
+                    compression.level = compression.level.or(fastest);
+                }
+            }
+        }
+    }
+}
+
+/// Codec `archive_type` compresses its entries with when [`CreateOptions::compression`] is
+/// `None`, if it compresses them at all.
+fn default_compression(archive_type: ArchiveType) -> Option<ArchiveCompression> {
+    match archive_type {
+        #[cfg(all(feature = "zip_archive", feature = "deflate_codecs"))]
+        ArchiveType::Zip => Some(ArchiveCompression::Deflate),
+        #[cfg(all(feature = "sevenz_archive", feature = "lzma_codecs"))]
+        ArchiveType::SevenZ => Some(ArchiveCompression::Lzma),
+        _ => None,
+    }
+}
+
+/// Files found under `root` are stored in the archive under `prefix`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceRoot {
+    pub root: PathBuf,
+    pub prefix: PathBuf,
+}
+
+impl FromStr for SourceRoot {
+    type Err = String;
+
+    /// Parses `ROOT=:PREFIX`, the prefix may be empty to store files at the top level.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (root, prefix) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected ROOT=:PREFIX but got `{}`", s))?;
+        if root.is_empty() {
+            return Err(format!("missing root in `{}`", s));
+        }
+        let prefix = prefix.strip_prefix(':').unwrap_or(prefix).trim_matches('/');
+
+        Ok(SourceRoot {
+            root: PathBuf::from(root),
+            prefix: PathBuf::from(prefix),
+        })
+    }
+}
+
+impl CreateOptions<'_> {
+    /// Name under which `path` is stored, see [`entry_name`].
+    pub fn entry_name<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        entry_name(&self.source, &self.roots, path)
+    }
+
+    /// Level of the compression, the default one of the codec if `None`.
+    pub(crate) fn compression_level(&self) -> Option<i32> {
+        self.compression.as_ref().and_then(|c| c.level)
+    }
+
+    /// Leaves out of the files the destination and its volumes, which would be archived into
+    /// themselves when the archive is created, or updated, inside the directory archived.
+    pub(crate) fn remove_destination(&mut self) {
+        let (Some(name), Some(directory)) = (
+            self.destination
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string()),
+            canonical_parent(&self.destination),
+        ) else {
+            return;
+        };
+        let is_destination = |path: &Path| {
+            // only the files named after the destination are resolved
+            let named = path.file_name().is_some_and(|file| {
+                let file = file.to_string_lossy();
+                file == name
+                    || file.strip_prefix(&name).is_some_and(|volume| {
+                        volume.len() > 1
+                            && volume.starts_with('.')
+                            && volume[1..].bytes().all(|b| b.is_ascii_digit())
+                    })
+            });
+            named && canonical_parent(path).is_some_and(|p| p == directory)
+        };
+        for path in std::mem::take(&mut self.files) {
+            if path.is_file() && is_destination(&path) {
+                let name = self.entry_name(&path).to_string_lossy().to_string();
+                self.handle(ArchiveEvent::Skipped(name, SkipReason::ArchiveItself));
+            } else {
+                self.files.push(path);
+            }
+        }
+    }
+
+    /// Leaves out of the files the ones that cannot be read if
+    /// [`skip_unreadable`](Self::skip_unreadable) is set, and returns them with the error reading
+    /// them.
+    pub(crate) fn remove_unreadable(&mut self) -> Vec<(PathBuf, String)> {
+        if !self.skip_unreadable {
+            return Vec::new();
+        }
+
+        let mut unreadable = Vec::new();
+        for path in std::mem::take(&mut self.files) {
+            // follows symlinks like the backends do, so broken ones fail here
+            let readable = std::fs::metadata(&path).and_then(|m| {
+                if m.is_file() {
+                    File::open(&path).map(drop)
+                } else {
+                    Ok(())
+                }
+            });
+            match readable {
+                Ok(()) => self.files.push(path),
+                Err(e) => {
+                    let name = self.entry_name(&path).to_string_lossy().to_string();
+                    self.handle(ArchiveEvent::Skipped(name, SkipReason::Unreadable));
+                    unreadable.push((path, e.to_string()));
+                }
+            }
+        }
+        unreadable
+    }
+}
+
+/// The directory holding `path`, with its symlinks resolved, even if `path` does not exist.
+fn canonical_parent(path: &Path) -> Option<PathBuf> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.canonicalize().ok(),
+        _ => std::env::current_dir().ok(),
+    }
+}
+
+/// Files added to an existing archive, see [`Archive::append`].
+#[derive(Debug)]
+pub struct AppendOptions<'a> {
+    /// Directory the names of the entries are relative to, as for [`CreateOptions::source`].
+    pub source: PathBuf,
+    pub files: Vec<PathBuf>,
+    /// Compression of the added entries of zip archives, deflate if `None` and available. The
+    /// other formats keep the compression of the archive.
+    pub compression: Option<ArchiveCompression>,
+    /// Compression level, the default one of the codec if `None`.
+    pub level: Option<i32>,
+    /// Additional source roots, as for [`CreateOptions::roots`].
+    pub roots: Vec<SourceRoot>,
+    /// Replaces the entries of the files already in the archive instead of refusing them. Zip
+    /// and uncompressed tar archives are then written again, without recompressing the entries
+    /// kept.
+    pub replace: bool,
+    /// Options of the added entries, as for [`CreateOptions::sevenz`], without solid blocks.
+    /// The archive is compressed again with them.
+    pub sevenz: SevenZCreateOptions,
+    /// Options of the added entries, as for [`CreateOptions::zip`].
+    pub zip: ZipCreateOptions,
+    /// Options of the added entries, as for [`CreateOptions::tar`].
+    pub tar: TarCreateOptions,
+    pub event_handler: Box<dyn EventHandler + 'a>,
+}
+
+impl AppendOptions<'_> {
+    /// Name under which `path` is stored, see [`entry_name`].
+    pub fn entry_name<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        entry_name(&self.source, &self.roots, path)
+    }
+
+    /// Normalized names of the entries replaced by the files, which the archive may not have.
+    pub(crate) fn replaced(&self) -> HashSet<String> {
+        if !self.replace {
+            return HashSet::new();
+        }
+        self.files
+            .iter()
+            .filter(|path| !path.is_dir())
+            .map(|path| {
+                let name = self.entry_name(path).to_string_lossy().replace('\\', "/");
+                normalized_name(&name).to_string()
+            })
+            .collect()
+    }
+}
+
+impl Default for AppendOptions<'_> {
+    fn default() -> Self {
+        Self {
+            source: PathBuf::from("."),
+            files: Vec::new(),
+            compression: None,
+            level: None,
+            roots: Vec::new(),
+            replace: false,
+            sevenz: SevenZCreateOptions::default(),
+            zip: ZipCreateOptions::default(),
+            tar: TarCreateOptions::default(),
+            event_handler: Box::new(SimpleLogger),
+        }
+    }
+}
+
+/// Whether the file stored as `name` is hidden.
+pub(crate) fn is_hidden(name: &str, metadata: &std::fs::Metadata) -> bool {
+    #[cfg(windows)]
+    {
+        _ = name;
+        use std::os::windows::fs::MetadataExt;
+        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x0000_0002;
+        metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0
+    }
+    #[cfg(not(windows))]
+    {
+        _ = metadata;
+        name.starts_with('.')
+    }
+}
+
+/// Name under which `path` is stored: relative to the deepest matching [`SourceRoot`] and under
+/// its prefix, or relative to `source` when no root matches.
+pub fn entry_name<P: AsRef<Path>>(source: &Path, roots: &[SourceRoot], path: P) -> PathBuf {
+    let path = path.as_ref();
+    let mapped = roots
+        .iter()
+        .filter_map(|r| path.strip_prefix(&r.root).ok().map(|rel| (r, rel)))
+        .max_by_key(|(r, _)| r.root.components().count())
+        .map(|(r, rel)| {
+            if rel.as_os_str().is_empty() {
+                r.prefix.clone()
+            } else {
+                r.prefix.join(rel)
+            }
+        });
+
+    mapped
+        .or_else(|| path.strip_prefix(source).ok().map(Path::to_path_buf))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Every path of `paths` followed, if it is a directory, by the files and directories beneath it
+/// in name order, so that the empty directories of a tree are stored too. Paths found twice are
+/// only kept once.
+pub fn walk_paths<P: AsRef<Path>>(paths: &[P]) -> Vec<PathBuf> {
+    let mut seen = HashSet::new();
+    paths
+        .iter()
+        .flat_map(|path| {
+            walkdir::WalkDir::new(path)
+                .sort_by_file_name()
+                .into_iter()
+                .filter_map(|e| e.ok())
+                .map(|e| e.into_path())
+        })
+        .filter(|path| seen.insert(path.clone()))
+        .collect()
+}
+
 pub struct OpenOptions {
-    pub path: PathBuf,
-    pub password: Option<String>,
+    /// The entries to write, in archive order. A directory stands for the entries under it.
+    pub paths: Vec<PathBuf>,
+    pub password: Option<Box<dyn PasswordProvider>>,
     pub dest: Box<dyn Write>,
+    pub output: OpenOutput,
+}
+
+/// How [`Archived::open`] writes the entries to its destination.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum OpenOutput {
+    /// The contents of the files one after the other.
+    #[default]
+    Concatenated,
+    /// The contents of the files with these bytes between two of them.
+    Separated(Vec<u8>),
+    /// A tar stream of the entries, keeping their names, modes and modification times.
+    #[cfg(feature = "tar_archive")]
+    Tar,
 }
 
 impl Default for ExtractOptions<'_> {
@@ -228,8 +1507,19 @@ impl Default for ExtractOptions<'_> {
             password: None,
             files: None,
             overwrite: false,
+            update: false,
+            file_mode: None,
+            dir_mode: None,
+            preserve_permissions: false,
             show_hidden: true,
             destination: PathBuf::from("."),
+            retry: IoRetryPolicy::default(),
+            transformers: vec![],
+            inspector: None,
+            adaptive: false,
+            cache: None,
+            progress_interval: None,
+            limits: ExtractLimits::default(),
             event_handler: Box::new(SimpleLogger),
         }
     }
@@ -239,6 +1529,9 @@ impl Default for ListOptions<'_> {
     fn default() -> Self {
         Self {
             password: None,
+            prefix: None,
+            offset: 0,
+            limit: None,
             event_handler: Box::new(SimpleLogger),
         }
     }
@@ -262,6 +1555,12 @@ impl<'a> EventHandler for CreateOptions<'a> {
     }
 }
 
+impl<'a> EventHandler for AppendOptions<'a> {
+    fn handle(&self, event: ArchiveEvent) {
+        self.event_handler.handle(event);
+    }
+}
+
 #[derive(Debug)]
 pub struct SimpleLogger;
 
@@ -275,6 +1574,10 @@ impl EventHandler for SimpleLogger {
                     println!("Extracting {}", name);
                 }
             }
+            ArchiveEvent::EntryProgress(name, copied, size) => match size {
+                Some(size) => println!("Extracting {}: {} of {} bytes", name, copied, size),
+                None => println!("Extracting {}: {} bytes", name, copied),
+            },
             ArchiveEvent::DoneExtracting(name, path) => {
                 println!("Done extracting {} to {}", name, path);
             }
@@ -289,7 +1592,28 @@ impl EventHandler for SimpleLogger {
                 SkipReason::NotInFiles => println!("Skipped file {} not in files", name),
                 SkipReason::AlreadyExists => println!("Skipped file {} already exists", name),
                 SkipReason::UnknownType => println!("Skipped file {} with unknown type", name),
+                SkipReason::UnsafePath => {
+                    println!("Skipped file {} outside the destination", name)
+                }
+                SkipReason::IfOlder => {
+                    println!("Skipped file {} at least as recent as the entry", name)
+                }
+                SkipReason::Unreadable => println!("Skipped unreadable file {}", name),
+                SkipReason::ArchiveItself => {
+                    println!("Skipped file {}, the archive itself", name)
+                }
+                SkipReason::NoValidPassword => {
+                    println!("Skipped encrypted file {} without a valid password", name)
+                }
+                SkipReason::Rejected(reason) => {
+                    println!("Skipped rejected file {}: {}", name, reason)
+                }
             },
+            ArchiveEvent::Added(name, _) => println!("Added {}", name),
+            ArchiveEvent::DoneCreating(path) => println!("Done creating {}", path),
+            ArchiveEvent::Retrying(name, retry, e) => {
+                eprintln!("Retrying {} (attempt {}) after error: {}", name, retry, e)
+            }
             ArchiveEvent::Log(msg) => println!("{}", msg),
         }
     }
@@ -298,13 +1622,62 @@ impl EventHandler for SimpleLogger {
 #[derive(Debug, PartialEq, Clone, Copy, serde::Serialize, serde::Deserialize)]
 pub enum ArchiveType {
     #[cfg(feature = "zip_archive")]
+    #[serde(rename = "zip")]
     Zip,
     #[cfg(feature = "tar_archive")]
+    #[serde(rename = "tar")]
     Tar,
     #[cfg(feature = "sevenz_archive")]
+    #[serde(rename = "7z")]
     SevenZ,
     #[cfg(feature = "iso_archive")]
+    #[serde(rename = "iso")]
     Iso,
+    #[cfg(feature = "rar_archive")]
+    #[serde(rename = "rar")]
+    Rar,
+    #[cfg(feature = "cpio_archive")]
+    #[serde(rename = "cpio")]
+    Cpio,
+    #[cfg(feature = "ar_archive")]
+    #[serde(rename = "ar")]
+    Ar,
+    #[cfg(feature = "squashfs_archive")]
+    #[serde(rename = "squashfs")]
+    Squashfs,
+    #[cfg(feature = "cab_archive")]
+    #[serde(rename = "cab")]
+    Cab,
+    #[cfg(feature = "wim_archive")]
+    #[serde(rename = "wim")]
+    Wim,
+    #[cfg(feature = "lha_archive")]
+    #[serde(rename = "lha")]
+    Lha,
+    #[cfg(feature = "arj_archive")]
+    #[serde(rename = "arj")]
+    Arj,
+    #[cfg(feature = "msi_archive")]
+    #[serde(rename = "msi")]
+    Msi,
+    #[cfg(feature = "nsis_archive")]
+    #[serde(rename = "nsis")]
+    Nsis,
+    #[cfg(feature = "zpaq_archive")]
+    #[serde(rename = "zpaq")]
+    Zpaq,
+    #[cfg(feature = "dmg_archive")]
+    #[serde(rename = "dmg")]
+    Dmg,
+    #[cfg(feature = "disk_image")]
+    #[serde(rename = "img")]
+    DiskImage,
+    #[cfg(feature = "warc_archive")]
+    #[serde(rename = "warc")]
+    Warc,
+    #[cfg(feature = "compressed_file")]
+    #[serde(rename = "compressed")]
+    CompressedFile,
     _Unreachable,
 }
 
@@ -327,47 +1700,156 @@ impl ArchiveType {
             | [0x50, 0x4b, 0x07, 0x08, _, _, _, _] => Some(ArchiveType::Zip),
             #[cfg(feature = "sevenz_archive")]
             [0x37, 0x7a, 0xbc, 0xaf, 0x27, 0x1c, _, _] => Some(ArchiveType::SevenZ),
+            // RAR 1.5 to 4.x, then RAR 5
+            #[cfg(feature = "rar_archive")]
+            [0x52, 0x61, 0x72, 0x21, 0x1a, 0x07, 0x00, _]
+            | [0x52, 0x61, 0x72, 0x21, 0x1a, 0x07, 0x01, 0x00] => Some(ArchiveType::Rar),
+            // "070701", "070702" and "070707" in ASCII
+            #[cfg(feature = "cpio_archive")]
+            [0x30, 0x37, 0x30, 0x37, 0x30, 0x31 | 0x32 | 0x37, _, _] => Some(ArchiveType::Cpio),
+            // "!<arch>\n"
+            #[cfg(feature = "ar_archive")]
+            [0x21, 0x3c, 0x61, 0x72, 0x63, 0x68, 0x3e, 0x0a] => Some(ArchiveType::Ar),
+            // "hsqs", the little endian magic of squashfs 4
+            #[cfg(feature = "squashfs_archive")]
+            [0x68, 0x73, 0x71, 0x73, _, _, _, _] => Some(ArchiveType::Squashfs),
+            // "MSCF" followed by a reserved zero field
+            #[cfg(feature = "cab_archive")]
+            [0x4d, 0x53, 0x43, 0x46, 0, 0, 0, 0] => Some(ArchiveType::Cab),
+            // "MSWIM" padded with zeroes
+            #[cfg(feature = "wim_archive")]
+            [0x4d, 0x53, 0x57, 0x49, 0x4d, 0, 0, 0] => Some(ArchiveType::Wim),
+            // header size and checksum, then a method such as "-lh5-"
+            #[cfg(feature = "lha_archive")]
+            [_, _, 0x2d, 0x6c, 0x68 | 0x7a, _, 0x2d, _] => Some(ArchiveType::Lha),
+            // header id, then the size of the main header, at most 2600 bytes
+            #[cfg(feature = "arj_archive")]
+            [0x60, 0xea, low, high, _, _, _, _]
+                if (1..=2600).contains(&u16::from_le_bytes([low, high])) =>
+            {
+                Some(ArchiveType::Arj)
+            }
+            // the locator tag written before blocks, or a block of a streaming archive
+            #[cfg(feature = "zpaq_archive")]
+            [0x37, 0x6b, 0x53, 0x74, 0xa0, 0x31, 0x83, 0xd3]
+            | [0x7a, 0x50, 0x51, 0x01 | 0x02, 0x01, _, _, _] => Some(ArchiveType::Zpaq),
+            // "WARC/" followed by the version
+            #[cfg(feature = "warc_archive")]
+            [0x57, 0x41, 0x52, 0x43, 0x2f, _, _, _] => Some(ArchiveType::Warc),
             _ => None,
         } {
             return Ok((t, ArchiveCompression::None));
         }
 
+        // compound files are installers when they hold a database
+        #[cfg(feature = "msi_archive")]
+        if magic_bytes_0 == COMPOUND_FILE_MAGIC {
+            reader.seek(SeekFrom::Start(0))?;
+            if is_msi_installer(BufReader::new(&mut reader)) {
+                return Ok((ArchiveType::Msi, ArchiveCompression::None));
+            }
+        }
+
+        // the payload of an installer follows its executable
+        #[cfg(feature = "nsis_archive")]
+        if magic_bytes_0[..2] == *b"MZ" {
+            reader.seek(SeekFrom::Start(0))?;
+            if is_nsis_installer(BufReader::new(&mut reader)) {
+                return Ok((ArchiveType::Nsis, ArchiveCompression::None));
+            }
+        }
+
+        // disk images only have a magic in their trailer, and may start with anything
+        #[cfg(feature = "dmg_archive")]
+        {
+            let mut trailer = [0; 4];
+            if reader.seek(SeekFrom::End(-512)).is_ok()
+                && reader.read_exact(&mut trailer).is_ok()
+                && trailer == *b"koly"
+            {
+                return Ok((ArchiveType::Dmg, ArchiveCompression::None));
+            }
+        }
+
+        // checked before tar, whose header lies too far for the smallest archives
+        #[cfg(feature = "cpio_archive")]
+        if let Ok(ref compression) =
+            ArchiveCompression::try_from(MagicBytesAt::<8>(0, magic_bytes_0))
+        {
+            reader.seek(SeekFrom::Start(0))?;
+            let mut magic = [0; 6];
+            if let Ok(ref mut compression_reader) =
+                ArchiveCodec::get_reader(&mut reader, compression)
+            {
+                if compression_reader.read_exact(&mut magic).is_ok()
+                    && CpioFormat::from_magic(&magic).is_some()
+                {
+                    return Ok((ArchiveType::Cpio, compression.clone()));
+                }
+            }
+        }
+
+        // web archives are compressed with a gzip member per record
+        #[cfg(feature = "warc_archive")]
+        if magic_bytes_0[..2] == [0x1f, 0x8b] {
+            reader.seek(SeekFrom::Start(0))?;
+            let mut magic = [0; 5];
+            if let Ok(ref mut compression_reader) =
+                ArchiveCodec::get_reader(&mut reader, &ArchiveCompression::Gzip)
+            {
+                if compression_reader.read_exact(&mut magic).is_ok() && magic == WARC_MAGIC {
+                    return Ok((ArchiveType::Warc, ArchiveCompression::Gzip));
+                }
+            }
+        }
+
         #[cfg(feature = "tar_archive")]
         let mut magic_bytes_257 = [0; 8];
         #[cfg(feature = "tar_archive")]
         {
-            reader.seek(SeekFrom::Start(257))?;
-            reader.read_exact(&mut magic_bytes_257)?;
-            const MAGIC_BYTES_TAR_1: [u8; 8] = [0x75, 0x73, 0x74, 0x61, 0x72, 0x00, 0x30, 0x30];
-            const MAGIC_BYTES_TAR_2: [u8; 8] = [0x75, 0x73, 0x74, 0x61, 0x72, 0x20, 0x20, 0x00];
-
-            if magic_bytes_257 == MAGIC_BYTES_TAR_1 || magic_bytes_257 == MAGIC_BYTES_TAR_2 {
-                return Ok((ArchiveType::Tar, ArchiveCompression::None));
+            // compressed archives, such as the members of Debian packages, may be too small
+            // to hold a tar header at all
+            let mut header = [0; 512];
+            reader.seek(SeekFrom::Start(0))?;
+            if reader.read_exact(&mut header).is_ok() {
+                magic_bytes_257.copy_from_slice(&header[257..265]);
+                if is_tar_header(&header) {
+                    return Ok((ArchiveType::Tar, ArchiveCompression::None));
+                }
             }
             reader.seek(SeekFrom::Start(0))?;
 
             if let Ok(ref compression) =
                 ArchiveCompression::try_from(MagicBytesAt::<8>(0, magic_bytes_0))
             {
-                // eprintln!("compression: {:?}", compression);
                 if let Ok(ref mut compression_reader) =
                     ArchiveCodec::get_reader(&mut reader, compression)
                 {
-                    // skip the first 257 bytes
-                    std::io::copy(&mut compression_reader.take(257), &mut std::io::sink())?;
-                    compression_reader.read_exact(&mut magic_bytes_257)?;
-                    // eprintln!("magic_bytes_257: {:04X?}", magic_bytes_257);
-
-                    if magic_bytes_257 == MAGIC_BYTES_TAR_1 || magic_bytes_257 == MAGIC_BYTES_TAR_2
-                    {
-                        return Ok((ArchiveType::Tar, compression.clone()));
+                    if compression_reader.read_exact(&mut header).is_ok() {
+                        magic_bytes_257.copy_from_slice(&header[257..265]);
+                        if is_tar_header(&header) {
+                            return Ok((ArchiveType::Tar, compression.clone()));
+                        }
                     }
                 }
             }
         }
 
+        // a compressed stream holding no archive is a single compressed file
+        #[cfg(feature = "compressed_file")]
+        if let Ok(compression) = ArchiveCompression::try_from(MagicBytesAt::<8>(0, magic_bytes_0)) {
+            return Ok((ArchiveType::CompressedFile, compression));
+        }
+
         // eprintln!("magic_bytes_257: {:04X?}", magic_bytes_257);
 
+        // filesystems whose boot sector or superblock lies at the start of the image or of one of
+        // its partitions, checked before iso files which need more than the smallest images hold
+        #[cfg(feature = "disk_image")]
+        if is_disk_image(&mut reader) {
+            return Ok((ArchiveType::DiskImage, ArchiveCompression::None));
+        }
+
         // check for iso file
 
         #[cfg(feature = "iso_archive")]
@@ -378,13 +1860,18 @@ impl ArchiveType {
         let mut magic_bytes_cd001_0x9001 = [0; 5];
         #[cfg(feature = "iso_archive")]
         {
-            // check for iso file
-            reader.seek(SeekFrom::Start(0x8001))?;
-            reader.read_exact(&mut magic_bytes_cd001_0x8001)?;
-            reader.seek(SeekFrom::Start(0x8801))?;
-            reader.read_exact(&mut magic_bytes_cd001_0x8801)?;
-            reader.seek(SeekFrom::Start(0x9001))?;
-            reader.read_exact(&mut magic_bytes_cd001_0x9001)?;
+            // check for iso file, files too short to hold its descriptors being something else
+            for (offset, magic) in [
+                (0x8001, &mut magic_bytes_cd001_0x8001),
+                (0x8801, &mut magic_bytes_cd001_0x8801),
+                (0x9001, &mut magic_bytes_cd001_0x9001),
+            ] {
+                if reader.seek(SeekFrom::Start(offset)).is_err()
+                    || reader.read_exact(magic).is_err()
+                {
+                    *magic = [0; 5];
+                }
+            }
             if magic_bytes_cd001_0x8001 == *b"CD001"
                 && magic_bytes_cd001_0x8801 == *b"CD001"
                 && magic_bytes_cd001_0x9001 == *b"CD001"
@@ -407,41 +1894,30 @@ impl ArchiveType {
         }))
     }
 
+    /// Compression of `data`, found from the archive it holds, or else from its first bytes alone
+    /// so that archives opened after their extension are still decompressed.
+    pub(crate) fn compression_of(data: DataSource) -> Result<ArchiveCompression, ArchiveError> {
+        match ArchiveType::try_from_datasource(data.clone()) {
+            Ok((_, compression)) => Ok(compression),
+            Err(ArchiveError::UnknownArchiveType(_)) => {
+                let mut reader = data;
+                let mut magic = [0; 8];
+                reader.seek(SeekFrom::Start(0))?;
+                reader.read_exact(&mut magic)?;
+                Ok(ArchiveCompression::try_from(MagicBytesAt::<8>(0, magic))
+                    .unwrap_or(ArchiveCompression::None))
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Type and compression of the archive named `path`, see [`ArchiveTypeExt::guess_format`].
     pub fn guess_from_filename<R: AsRef<Path>>(
         path: R,
     ) -> Result<(ArchiveType, Option<ArchiveCompression>), ArchiveError> {
-        let binding = path.as_ref().to_string_lossy();
-        let split = binding.split('.').collect::<Vec<_>>();
-
-        match (split.get(split.len() - 2), split[split.len() - 1]) {
-            #[cfg(feature = "tar_archive")]
-            (Some(&"tar"), "gz" | "gzip") | (_, "tgz") => {
-                Ok((ArchiveType::Tar, Some(ArchiveCompression::Gzip)))
-            }
-            #[cfg(all(feature = "tar_archive", feature = "lzma_codecs"))]
-            (Some(&"tar"), "xz") | (_, "txz") => {
-                Ok((ArchiveType::Tar, Some(ArchiveCompression::Lzma)))
-            }
-            #[cfg(all(feature = "tar_archive", feature = "bzip2_codecs"))]
-            (Some(&"tar"), "bz2") | (_, "tbz2") => {
-                Ok((ArchiveType::Tar, Some(ArchiveCompression::Bzip2)))
-            }
-            #[cfg(all(feature = "tar_archive", feature = "zstd_codecs"))]
-            (Some(&"tar"), "zst" | "zstd") | (_, "tzst") => {
-                Ok((ArchiveType::Tar, Some(ArchiveCompression::Zstd)))
-            }
-            #[cfg(feature = "tar_archive")]
-            (_, "tar") => Ok((ArchiveType::Tar, Some(ArchiveCompression::None))),
-            #[cfg(feature = "zip_archive")]
-            (_, "zip") => Ok((ArchiveType::Zip, None)),
-            #[cfg(feature = "sevenz_archive")]
-            (_, "7z" | "7zip") => Ok((ArchiveType::SevenZ, None)),
-            #[cfg(feature = "iso_archive")]
-            (_, "iso") => Ok((ArchiveType::Iso, None)),
-            _ => Err(ArchiveError::UnknownFileExtension(
-                path.as_ref().to_string_lossy().to_string(),
-            )),
-        }
+        path.as_ref()
+            .guess_format()
+            .map(|guessed| (guessed.archive, guessed.compression))
     }
 }
 
@@ -456,6 +1932,36 @@ impl std::fmt::Display for ArchiveType {
             ArchiveType::SevenZ => write!(f, "7z"),
             #[cfg(feature = "iso_archive")]
             ArchiveType::Iso => write!(f, "iso"),
+            #[cfg(feature = "rar_archive")]
+            ArchiveType::Rar => write!(f, "rar"),
+            #[cfg(feature = "cpio_archive")]
+            ArchiveType::Cpio => write!(f, "cpio"),
+            #[cfg(feature = "ar_archive")]
+            ArchiveType::Ar => write!(f, "ar"),
+            #[cfg(feature = "squashfs_archive")]
+            ArchiveType::Squashfs => write!(f, "squashfs"),
+            #[cfg(feature = "cab_archive")]
+            ArchiveType::Cab => write!(f, "cab"),
+            #[cfg(feature = "wim_archive")]
+            ArchiveType::Wim => write!(f, "wim"),
+            #[cfg(feature = "lha_archive")]
+            ArchiveType::Lha => write!(f, "lha"),
+            #[cfg(feature = "arj_archive")]
+            ArchiveType::Arj => write!(f, "arj"),
+            #[cfg(feature = "msi_archive")]
+            ArchiveType::Msi => write!(f, "msi"),
+            #[cfg(feature = "nsis_archive")]
+            ArchiveType::Nsis => write!(f, "nsis"),
+            #[cfg(feature = "zpaq_archive")]
+            ArchiveType::Zpaq => write!(f, "zpaq"),
+            #[cfg(feature = "dmg_archive")]
+            ArchiveType::Dmg => write!(f, "dmg"),
+            #[cfg(feature = "disk_image")]
+            ArchiveType::DiskImage => write!(f, "img"),
+            #[cfg(feature = "warc_archive")]
+            ArchiveType::Warc => write!(f, "warc"),
+            #[cfg(feature = "compressed_file")]
+            ArchiveType::CompressedFile => write!(f, "compressed"),
             ArchiveType::_Unreachable => unreachable!(),
         }
     }
@@ -470,6 +1976,35 @@ pub struct ArchiveFileEntity {
     pub(crate) compression: Option<String>,
     #[serde(rename = "type")]
     pub(crate) fstype: ArchiveFileEntityType,
+    /// Where the entry lies in the archive, for the zip and tar archives.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) raw: Option<RawEntryLocation>,
+    /// Permissions and owner of the entry, for the ISO images with Rock Ridge extensions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) unix: Option<UnixAttributes>,
+}
+
+/// Permissions and owner of an entry, as `stat` gives them for the file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UnixAttributes {
+    /// Permission bits, such as `0o755`, without the type of the file.
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// Location of an entry in its archive, to find its data without going through the archive
+/// reader, e.g. to inspect or repair a damaged archive. The offsets of compressed tar archives
+/// are those of the decompressed stream.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RawEntryLocation {
+    /// Offset of the local header of a zip entry, or of the header of a tar entry, past the
+    /// headers holding its long name and extensions.
+    pub header_offset: u64,
+    /// Offset of the data of the entry.
+    pub data_offset: u64,
+    /// General purpose flags of the local header of a zip entry, or type flag of a tar entry.
+    pub flags: u16,
 }
 
 impl ArchiveFileEntity {
@@ -496,6 +2031,35 @@ impl ArchiveFileEntity {
     pub fn fstype(&self) -> ArchiveFileEntityType {
         self.fstype
     }
+
+    pub fn raw(&self) -> Option<&RawEntryLocation> {
+        self.raw.as_ref()
+    }
+
+    pub fn unix(&self) -> Option<&UnixAttributes> {
+        self.unix.as_ref()
+    }
+
+    /// Compressed size as a percentage of the uncompressed size, if both are known.
+    pub fn ratio(&self) -> Option<f64> {
+        match (self.compressed_size, self.size) {
+            (Some(compressed), Some(size)) if size > 0 => {
+                Some(compressed as f64 / size as f64 * 100.0)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the entry is a file with a common image extension.
+    pub fn is_image(&self) -> bool {
+        const IMAGE_EXTENSIONS: [&str; 7] = ["png", "jpg", "jpeg", "gif", "webp", "bmp", "avif"];
+
+        self.fstype == ArchiveFileEntityType::File
+            && Path::new(&self.name)
+                .extension()
+                .and_then(|e| e.to_str())
+                .is_some_and(|e| IMAGE_EXTENSIONS.iter().any(|i| i.eq_ignore_ascii_case(e)))
+    }
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
@@ -547,6 +2111,118 @@ impl From<tar::EntryType> for ArchiveFileEntityType {
     }
 }
 
+/// Joins an archive entry `path` onto `root`, resolving `.` and `..` lexically. Leading `/` and
+/// drive prefixes are ignored so absolute entry names stay inside `root`, and an entry that would
+/// climb out of it is rejected with [`ArchiveError::UnsafePath`].
+pub fn join_path_with_root<R: AsRef<Path>, P: AsRef<Path>>(
+    root: R,
+    path: P,
+) -> Result<PathBuf, ArchiveError> {
+    let path = path.as_ref();
+    let mut relative = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+            Component::ParentDir => {
+                if !relative.pop() {
+                    return Err(ArchiveError::UnsafePath(path.to_path_buf()));
+                }
+            }
+            Component::Normal(c) => relative.push(c),
+        }
+    }
+    Ok(root.as_ref().join(relative))
+}
+
+/// Path the entry `name` is extracted to under `root`, rejected with [`ArchiveError::UnsafePath`]
+/// if it would escape it. Absolute names and names with `..` components are rejected even where
+/// [`join_path_with_root`] would keep them inside `root`, since they were meant to land
+/// elsewhere, and the symbolic links already in `root`, such as those extracted from earlier entries, are followed:
+/// the directories of the path leading outside of `root` through one are rejected, as is the
+/// path itself when it is such a link, since writing to it would write outside. The returned path
+/// has the links of its directories resolved, so that it is where the entry actually lands.
+pub fn safe_join<R: AsRef<Path>, N: AsRef<Path>>(
+    root: R,
+    name: N,
+) -> Result<PathBuf, ArchiveError> {
+    let (root, name) = (root.as_ref(), name.as_ref());
+    let unsafe_path = || ArchiveError::UnsafePath(name.to_path_buf());
+    let escapes = |c: Component| {
+        matches!(
+            c,
+            Component::Prefix(_) | Component::RootDir | Component::ParentDir
+        )
+    };
+    if name.components().any(escapes) {
+        return Err(unsafe_path());
+    }
+    let joined = join_path_with_root(root, name)?;
+    let relative = joined.strip_prefix(root).map_err(|_| unsafe_path())?;
+
+    let mut path = root.to_path_buf();
+    let mut real_root = None;
+    let mut components = relative.components().peekable();
+    while let Some(component) = components.next() {
+        path.push(component);
+        match path.symlink_metadata() {
+            Ok(metadata) if metadata.file_type().is_symlink() => {}
+            Ok(_) => continue,
+            // nothing exists below a missing directory
+            Err(_) => {
+                path.extend(components);
+                break;
+            }
+        }
+        let real_root = match &mut real_root {
+            Some(real_root) => real_root,
+            None => real_root.insert(root.canonicalize()?),
+        };
+        let within = path
+            .canonicalize()
+            .ok()
+            .and_then(|real| Some(root.join(real.strip_prefix(&*real_root).ok()?)));
+        match (within, components.peek()) {
+            (Some(within), Some(_)) => path = within,
+            // the entry replaces the link itself, as it does a link to a missing file
+            (Some(_), None) => {}
+            (None, None) if !path.exists() => {}
+            (None, _) => return Err(unsafe_path()),
+        }
+    }
+    Ok(path)
+}
+
+/// Resolves the `target` of a symlink extracted to `link` under `root`. Relative targets are kept
+/// relative to the link as long as they resolve inside `root`, absolute targets are rejected with
+/// [`ArchiveError::UnsafePath`] as they point outside of it.
+pub fn symlink_target_within<R: AsRef<Path>, L: AsRef<Path>, T: AsRef<Path>>(
+    root: R,
+    link: L,
+    target: T,
+) -> Result<PathBuf, ArchiveError> {
+    let (root, link, target) = (root.as_ref(), link.as_ref(), target.as_ref());
+    let unsafe_target = || ArchiveError::UnsafePath(target.to_path_buf());
+    if target.has_root() || matches!(target.components().next(), Some(Component::Prefix(_))) {
+        return Err(unsafe_target());
+    }
+    let parent = link
+        .parent()
+        .and_then(|p| p.strip_prefix(root).ok())
+        .ok_or_else(unsafe_target)?;
+    join_path_with_root(root, parent.join(target)).map_err(|_| unsafe_target())?;
+
+    Ok(target.to_path_buf())
+}
+
+/// Modification time stored in MS-DOS format, the date in the high word, read as UTC like zip
+/// archives.
+pub(crate) fn dos_datetime(time: u32) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+    let (date, time) = (time >> 16, time & 0xffff);
+    chrono::NaiveDate::from_ymd_opt(1980 + (date >> 9) as i32, (date >> 5) & 0xf, date & 0x1f)?
+        .and_hms_opt(time >> 11, (time >> 5) & 0x3f, (time & 0x1f) * 2)
+        .map(|t| t.and_utc().fixed_offset())
+}
+
 pub fn datetime_from_timestamp(
     timestamp: i64,
 ) -> Result<chrono::DateTime<chrono::FixedOffset>, std::io::Error> {
@@ -566,15 +2242,56 @@ pub enum SkipReason {
     NotInFiles,
     AlreadyExists,
     UnknownType,
+    UnsafePath,
+    /// The existing file is as recent as the entry, see [`ExtractOptions::update`].
+    IfOlder,
+    /// The file to add could not be read, see [`CreateOptions::skip_unreadable`].
+    Unreadable,
+    /// The file is the archive: the one being created, found among the files to add when it is
+    /// created inside the directory archived, or the one being extracted, which the entry would
+    /// overwrite.
+    ArchiveItself,
+    /// The entry is encrypted and the [`PasswordProvider`] had no password decrypting it.
+    NoValidPassword,
+    /// The [`ExtractOptions::inspector`] rejected the content of the entry, for the given reason.
+    Rejected(String),
+}
+
+impl SkipReason {
+    /// Identifier of the reason, such as `already_exists`, for machine readable output.
+    pub fn name(&self) -> &'static str {
+        match self {
+            SkipReason::Hidden => "hidden",
+            SkipReason::NotInFiles => "not_in_files",
+            SkipReason::AlreadyExists => "already_exists",
+            SkipReason::UnknownType => "unknown_type",
+            SkipReason::UnsafePath => "unsafe_path",
+            SkipReason::IfOlder => "if_older",
+            SkipReason::Unreadable => "unreadable",
+            SkipReason::ArchiveItself => "archive_itself",
+            SkipReason::NoValidPassword => "no_valid_password",
+            SkipReason::Rejected(_) => "rejected",
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum ArchiveEvent {
     Extracting(String, Option<u64>),
+    /// The bytes of the named file copied so far, of its size if known, sent every
+    /// [`ExtractOptions::progress_interval`] bytes while it is extracted.
+    EntryProgress(String, u64, Option<u64>),
     DoneExtracting(String, String),
     FailedToReadEntry(String, ArchiveError),
     Created(String, ArchiveFileEntityType),
     Skipped(String, SkipReason),
+    /// An entry was written to the archive being created, with its size if it is a file.
+    Added(String, Option<u64>),
+    /// The archive at the given path was created.
+    DoneCreating(String),
+    /// A transient IO error on the named source or destination, about to be retried for the
+    /// n-th time.
+    Retrying(String, u32, std::io::Error),
     Log(String),
 }
 
@@ -597,12 +2314,106 @@ where
     }
 }
 
+impl<T> EventHandler for std::rc::Rc<T>
+where
+    T: EventHandler,
+{
+    fn handle(&self, event: ArchiveEvent) {
+        self.as_ref().handle(event);
+    }
+}
+
+/// Rewrites the content of file entries as they are extracted, e.g. to decompress inner files or
+/// convert line endings.
+pub trait EntryTransformer {
+    /// Returns the reader the content of `entity` is extracted from, wrapping `reader` or
+    /// returning it unchanged for entries that should be left alone.
+    fn transform<'r>(
+        &self,
+        entity: &ArchiveFileEntity,
+        reader: Box<dyn Read + 'r>,
+    ) -> Box<dyn Read + 'r>;
+}
+
+impl<F> EntryTransformer for F
+where
+    F: for<'r> Fn(&ArchiveFileEntity, Box<dyn Read + 'r>) -> Box<dyn Read + 'r>,
+{
+    fn transform<'r>(
+        &self,
+        entity: &ArchiveFileEntity,
+        reader: Box<dyn Read + 'r>,
+    ) -> Box<dyn Read + 'r> {
+        self(entity, reader)
+    }
+}
+
+impl<'a> Debug for dyn EntryTransformer + 'a {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "EntryTransformer#{}",
+            self as *const _ as *const u8 as usize
+        )
+    }
+}
+
+/// Boxes a closure as an [`EntryTransformer`], giving it the signature the trait expects.
+pub fn entry_transformer<'a, F>(f: F) -> Box<dyn EntryTransformer + 'a>
+where
+    F: for<'r> Fn(&ArchiveFileEntity, Box<dyn Read + 'r>) -> Box<dyn Read + 'r> + 'a,
+{
+    Box::new(f)
+}
+
+/// Decision of an [`EntryInspector`] on the content of an entry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    Allow,
+    /// The entry is not extracted, for the given reason.
+    Reject(String),
+}
+
+/// Reads the content of file entries before they are extracted, e.g. to scan the uploads of a
+/// service for malware or enforce a policy on them. The entry is only written to its path once
+/// allowed, its content being staged next to it meanwhile.
+pub trait EntryInspector {
+    /// Reads the content of `entity` from `reader` and tells whether it may be extracted. An
+    /// error, such as a scanner that cannot be reached, fails the extraction.
+    fn inspect(
+        &self,
+        entity: &ArchiveFileEntity,
+        reader: &mut dyn Read,
+    ) -> Result<Verdict, ArchiveError>;
+}
+
+impl<F> EntryInspector for F
+where
+    F: Fn(&ArchiveFileEntity, &mut dyn Read) -> Result<Verdict, ArchiveError>,
+{
+    fn inspect(
+        &self,
+        entity: &ArchiveFileEntity,
+        reader: &mut dyn Read,
+    ) -> Result<Verdict, ArchiveError> {
+        self(entity, reader)
+    }
+}
+
+impl<'a> Debug for dyn EntryInspector + 'a {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "EntryInspector#{}",
+            self as *const _ as *const u8 as usize
+        )
+    }
+}
+
 #[derive(Debug)]
 pub enum ArchiveError {
     #[cfg(feature = "zip_archive")]
     Zip(zip::result::ZipError),
-    #[cfg(feature = "zip_archive")]
-    Password(zip::result::InvalidPassword),
     #[cfg(feature = "tar_archive")]
     Tar(std::io::Error),
     #[cfg(feature = "sevenz_archive")]
@@ -610,8 +2421,12 @@ pub enum ArchiveError {
     Io(std::io::Error),
     #[cfg(feature = "iso_archive")]
     Iso(cdfs::ISOError),
+    #[cfg(feature = "rar_archive")]
+    Rar(unrar::error::UnrarError),
+    #[cfg(feature = "squashfs_archive")]
+    Squashfs(backhand::BackhandError),
     #[cfg(feature = "lzma_codecs")]
-    Lzma(lzma::LzmaError),
+    Lzma(liblzma::stream::Error),
     UnknownArchiveType(MagicNumbers),
     UnknownFileExtension(String),
     InvalidDataSource(String),
@@ -620,7 +2435,21 @@ pub enum ArchiveError {
     CompressionMethodRequired,
     UnsupportedActionForArchiveType(String, ArchiveType),
     Json(serde_json::Error),
-    EntryNotFound(PathBuf),
+    /// The entry is not in the archive, with the names of the closest ones.
+    EntryNotFound(PathBuf, Vec<String>),
+    UnsafePath(PathBuf),
+    InvalidNameTemplate(String),
+    /// The archive is encrypted and no password was given.
+    PasswordRequired,
+    /// The password given does not decrypt the archive, whatever its format.
+    WrongPassword,
+    /// The created archive does not match its source files, see
+    /// [`CreateOptions::verify_after`].
+    VerificationFailed(ManifestDiff),
+    /// Extracting the named entry went past a limit of [`ExtractOptions::limits`].
+    LimitExceeded(String, ExceededLimit),
+    #[cfg(feature = "keyring")]
+    Keyring(::keyring::Error),
 }
 
 #[derive(Debug)]
@@ -688,8 +2517,6 @@ impl std::fmt::Display for ArchiveError {
         match self {
             #[cfg(feature = "zip_archive")]
             ArchiveError::Zip(e) => write!(f, "ZipError: {}", e),
-            #[cfg(feature = "zip_archive")]
-            ArchiveError::Password(e) => write!(f, "PasswordError: {}", e),
             ArchiveError::Io(e) => write!(f, "{}", e),
             #[cfg(feature = "tar_archive")]
             ArchiveError::Tar(e) => write!(f, "TarError: {}", e),
@@ -697,6 +2524,10 @@ impl std::fmt::Display for ArchiveError {
             ArchiveError::SevenZ(e) => write!(f, "SevenZError: {}", e),
             #[cfg(feature = "iso_archive")]
             ArchiveError::Iso(e) => write!(f, "ISOError: {}", e),
+            #[cfg(feature = "rar_archive")]
+            ArchiveError::Rar(e) => write!(f, "RarError: {}", e),
+            #[cfg(feature = "squashfs_archive")]
+            ArchiveError::Squashfs(e) => write!(f, "SquashfsError: {}", e),
             #[cfg(feature = "lzma_codecs")]
             ArchiveError::Lzma(e) => write!(f, "LzmaError: {}", e),
             ArchiveError::UnknownArchiveType(n) => {
@@ -723,7 +2554,32 @@ impl std::fmt::Display for ArchiveError {
                 action, archive_type
             ),
             ArchiveError::Json(e) => write!(f, "JsonError: {}", e),
-            ArchiveError::EntryNotFound(p) => write!(f, "Entry not found: {}", p.display()),
+            ArchiveError::EntryNotFound(p, suggestions) => {
+                write!(f, "Entry not found: {}", p.display())?;
+                match suggestions.as_slice() {
+                    [] => Ok(()),
+                    [suggestion] => write!(f, ". Did you mean {}?", suggestion),
+                    [rest @ .., last] => {
+                        write!(f, ". Did you mean {} or {}?", rest.join(", "), last)
+                    }
+                }
+            }
+            ArchiveError::UnsafePath(p) => {
+                write!(f, "Path escapes the destination: {}", p.display())
+            }
+            ArchiveError::InvalidNameTemplate(e) => write!(f, "Invalid name template: {}", e),
+            ArchiveError::PasswordRequired => {
+                write!(f, "A password is required to read this archive.")
+            }
+            ArchiveError::WrongPassword => write!(f, "The password does not decrypt this archive."),
+            ArchiveError::VerificationFailed(diff) => {
+                write!(f, "The archive does not match its source files:\n{}", diff)
+            }
+            ArchiveError::LimitExceeded(name, limit) => {
+                write!(f, "Extracting {} goes past the limit of {}", name, limit)
+            }
+            #[cfg(feature = "keyring")]
+            ArchiveError::Keyring(e) => write!(f, "KeyringError: {}", e),
         }
     }
 }
@@ -737,14 +2593,19 @@ impl From<serde_json::Error> for ArchiveError {
 #[cfg(feature = "zip_archive")]
 impl From<zip::result::ZipError> for ArchiveError {
     fn from(e: zip::result::ZipError) -> Self {
-        ArchiveError::Zip(e)
+        match e {
+            zip::result::ZipError::UnsupportedArchive(zip::result::ZipError::PASSWORD_REQUIRED) => {
+                ArchiveError::PasswordRequired
+            }
+            e => ArchiveError::Zip(e),
+        }
     }
 }
 
 #[cfg(feature = "zip_archive")]
 impl From<zip::result::InvalidPassword> for ArchiveError {
-    fn from(e: zip::result::InvalidPassword) -> Self {
-        ArchiveError::Password(e)
+    fn from(_: zip::result::InvalidPassword) -> Self {
+        ArchiveError::WrongPassword
     }
 }
 
@@ -754,10 +2615,12 @@ impl From<std::io::Error> for ArchiveError {
     }
 }
 
+#[cfg(feature = "tar_archive")]
 pub trait AsTarArchiveResult<T> {
     fn into_tar_archive_result(self) -> Result<T, ArchiveError>;
 }
 
+#[cfg(feature = "tar_archive")]
 impl<T> AsTarArchiveResult<T> for std::io::Result<T> {
     fn into_tar_archive_result(self) -> Result<T, ArchiveError> {
         self.map_err(ArchiveError::Tar)
@@ -767,7 +2630,11 @@ impl<T> AsTarArchiveResult<T> for std::io::Result<T> {
 #[cfg(feature = "sevenz_archive")]
 impl From<sevenz_rust::Error> for ArchiveError {
     fn from(e: sevenz_rust::Error) -> Self {
-        ArchiveError::SevenZ(e)
+        match e {
+            sevenz_rust::Error::PasswordRequired => ArchiveError::PasswordRequired,
+            sevenz_rust::Error::MaybeBadPassword(_) => ArchiveError::WrongPassword,
+            e => ArchiveError::SevenZ(e),
+        }
     }
 }
 
@@ -778,52 +2645,365 @@ impl From<cdfs::ISOError> for ArchiveError {
     }
 }
 
+#[cfg(feature = "rar_archive")]
+impl From<unrar::error::UnrarError> for ArchiveError {
+    fn from(e: unrar::error::UnrarError) -> Self {
+        match e.code {
+            unrar::error::Code::MissingPassword => ArchiveError::PasswordRequired,
+            unrar::error::Code::BadPassword => ArchiveError::WrongPassword,
+            _ => ArchiveError::Rar(e),
+        }
+    }
+}
+
+#[cfg(feature = "squashfs_archive")]
+impl From<backhand::BackhandError> for ArchiveError {
+    fn from(e: backhand::BackhandError) -> Self {
+        ArchiveError::Squashfs(e)
+    }
+}
+
 #[cfg(feature = "lzma_codecs")]
-impl From<lzma::LzmaError> for ArchiveError {
-    fn from(e: lzma::LzmaError) -> Self {
+impl From<liblzma::stream::Error> for ArchiveError {
+    fn from(e: liblzma::stream::Error) -> Self {
         ArchiveError::Lzma(e)
     }
 }
 
+#[cfg(feature = "keyring")]
+impl From<::keyring::Error> for ArchiveError {
+    fn from(e: ::keyring::Error) -> Self {
+        ArchiveError::Keyring(e)
+    }
+}
+
+/// Where the bytes of an archive come from. Sources own their data, so archives opened from them
+/// can outlive the buffers and readers they were made of.
 #[derive(Debug)]
-pub enum DataSource<'a> {
+pub enum DataSource {
+    /// A file and its path, which its clones open again.
     File(Box<File>, String),
-    Stream(Cursor<&'a Vec<u8>>),
+    Memory(Cursor<Arc<[u8]>>),
+    Reader(SharedReader),
+    Pipe(PipedReader),
+    /// An archive split into volumes, read as a single file.
+    Volumes(Volumes),
 }
 
-impl std::fmt::Display for DataSource<'_> {
+impl std::fmt::Display for DataSource {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             DataSource::File(_, path) => write!(f, "{}", path),
             // use the inner value pointer as a unique identifier
-            DataSource::Stream(c) => {
-                write!(f, " stream at {:?}", (c.get_ref() as *const _) as usize)
+            DataSource::Memory(c) => {
+                write!(f, " stream at {:?}", c.get_ref().as_ptr() as usize)
+            }
+            DataSource::Reader(r) => {
+                write!(
+                    f,
+                    " reader at {:?}",
+                    Arc::as_ptr(&r.inner) as *const () as usize
+                )
             }
+            DataSource::Pipe(p) => write!(f, " pipe at {:?}", Arc::as_ptr(&p.inner) as usize),
+            DataSource::Volumes(v) => write!(f, "{}", v.path().display()),
         }
     }
 }
 
-impl<'a> DataSource<'a> {
+/// A source whose path is unknown, read as a [`DataSource::seekable`] reader: its clones share
+/// the file, each keeping its own position.
+impl From<File> for DataSource {
+    fn from(file: File) -> Self {
+        DataSource::seekable(file)
+    }
+}
+
+impl From<Vec<u8>> for DataSource {
+    fn from(data: Vec<u8>) -> Self {
+        DataSource::memory(data)
+    }
+}
+
+impl DataSource {
+    /// A source reading the file at `path`. The first volume of an archive split into volumes
+    /// named `.001`, `.002`, ..., or any volume of a zip archive spanned over `.z01`, `.z02`,
+    /// ..., `.zip` files, reads as the whole archive.
     pub fn file<P: AsRef<Path>>(path: P) -> Result<Self, std::io::Error> {
+        if let Some(volumes) = Volumes::open(path.as_ref())? {
+            return Ok(DataSource::Volumes(volumes));
+        }
         let s = path.as_ref().to_string_lossy().to_string();
         let file = File::open(path)?;
         Ok(DataSource::File(Box::new(file), s))
     }
 
-    pub fn stream(data: &'a Vec<u8>) -> Self {
-        DataSource::Stream(Cursor::new(data))
+    /// A source reading `data` from memory. Clones of the source share the bytes.
+    pub fn memory<D: Into<Arc<[u8]>>>(data: D) -> Self {
+        DataSource::Memory(Cursor::new(data.into()))
+    }
+
+    /// A source reading from any seekable `reader`, e.g. a blob of a database or an object
+    /// downloaded on demand. Clones of the source share the reader, each seeking to its own
+    /// position before reading.
+    pub fn seekable<R: Read + Seek + Send + 'static>(reader: R) -> Self {
+        DataSource::Reader(SharedReader {
+            inner: Arc::new(Mutex::new(Box::new(reader))),
+            position: 0,
+        })
+    }
+
+    /// A source reading from a `reader` which can not seek, e.g. stdin or a pipe. Tar archives,
+    /// compressed or not, cpio archives and single compressed files are read as their data comes,
+    /// only once. Other archives need to seek and are first spooled to a temporary file, and
+    /// inputs shorter than [`PIPE_HEAD_SIZE`] are simply kept in memory.
+    pub fn reader<R: Read + Send + 'static>(mut reader: R) -> Result<Self, std::io::Error> {
+        let mut head = Vec::new();
+        (&mut reader)
+            .take(PIPE_HEAD_SIZE as u64)
+            .read_to_end(&mut head)?;
+        if head.len() < PIPE_HEAD_SIZE {
+            return Ok(DataSource::memory(head));
+        }
+
+        let streamed = match ArchiveType::try_from_datasource(DataSource::memory(head.as_slice())) {
+            #[cfg(feature = "tar_archive")]
+            Ok((ArchiveType::Tar, _)) => true,
+            #[cfg(feature = "cpio_archive")]
+            Ok((ArchiveType::Cpio, _)) => true,
+            #[cfg(feature = "compressed_file")]
+            Ok((ArchiveType::CompressedFile, _)) => true,
+            _ => false,
+        };
+        if streamed {
+            let consumed = head.len() as u64;
+            return Ok(DataSource::Pipe(PipedReader {
+                inner: Arc::new(Mutex::new(Pipe {
+                    head,
+                    rest: Box::new(reader),
+                    consumed,
+                })),
+                position: 0,
+            }));
+        }
+
+        let mut spool = tempfile::tempfile()?;
+        spool.write_all(&head)?;
+        std::io::copy(&mut reader, &mut spool)?;
+        spool.seek(SeekFrom::Start(0))?;
+        Ok(DataSource::from(spool))
+    }
+
+    /// Paths of the files the source reads, none unless it is a file or volumes of an archive.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        match self {
+            DataSource::File(_, path) if !path.is_empty() => vec![PathBuf::from(path)],
+            DataSource::Volumes(v) => v.paths().map(Path::to_path_buf).collect(),
+            DataSource::File(..)
+            | DataSource::Memory(_)
+            | DataSource::Reader(_)
+            | DataSource::Pipe(_) => Vec::new(),
+        }
+    }
+
+    /// Creation time of the underlying file, if the source is a file and the platform reports it.
+    pub fn created(&self) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        match self {
+            DataSource::File(file, _) => file
+                .metadata()
+                .and_then(|m| m.created())
+                .ok()
+                .map(|t| chrono::DateTime::<chrono::Local>::from(t).fixed_offset()),
+            DataSource::Volumes(v) => v
+                .first()
+                .metadata()
+                .and_then(|m| m.created())
+                .ok()
+                .map(|t| chrono::DateTime::<chrono::Local>::from(t).fixed_offset()),
+            DataSource::Memory(_) | DataSource::Reader(_) | DataSource::Pipe(_) => None,
+        }
+    }
+
+    /// Last modification time of the underlying file, if the source is a file.
+    pub fn modified(&self) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+        match self {
+            DataSource::File(file, _) => file
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .map(|t| chrono::DateTime::<chrono::Local>::from(t).fixed_offset()),
+            DataSource::Volumes(v) => v
+                .first()
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .map(|t| chrono::DateTime::<chrono::Local>::from(t).fixed_offset()),
+            DataSource::Memory(_) | DataSource::Reader(_) | DataSource::Pipe(_) => None,
+        }
     }
 
     pub fn try_clone(&self) -> Result<Self, std::io::Error> {
         match self {
+            // a clone of the handle would move the position of the original as it reads
+            DataSource::File(_, path) if path.is_empty() => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "a file source without its path cannot be cloned, see `DataSource::from`",
+            )),
             DataSource::File(_, path) => {
                 Ok(DataSource::File(Box::new(File::open(path)?), path.clone()))
             }
-            DataSource::Stream(val) => Ok(DataSource::Stream(Cursor::new(val.clone().get_ref()))),
+            DataSource::Memory(val) => Ok(DataSource::Memory(Cursor::new(val.get_ref().clone()))),
+            DataSource::Reader(r) => Ok(DataSource::Reader(SharedReader {
+                inner: r.inner.clone(),
+                position: 0,
+            })),
+            DataSource::Pipe(p) => Ok(DataSource::Pipe(PipedReader {
+                inner: p.inner.clone(),
+                position: 0,
+            })),
+            DataSource::Volumes(v) => Ok(DataSource::Volumes(v.try_clone()?)),
         }
     }
 }
 
+/// A reader shared by the clones of a [`DataSource::Reader`], each keeping its own position.
+pub struct SharedReader {
+    inner: Arc<Mutex<Box<dyn ReadSeek + Send>>>,
+    position: u64,
+}
+
+impl SharedReader {
+    fn lock(&self) -> std::io::Result<std::sync::MutexGuard<'_, Box<dyn ReadSeek + Send>>> {
+        self.inner
+            .lock()
+            .map_err(|_| std::io::Error::other("the reader of the source is poisoned"))
+    }
+}
+
+impl std::fmt::Debug for SharedReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedReader")
+            .field("position", &self.position)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Read for SharedReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = {
+            let mut inner = self.lock()?;
+            inner.seek(SeekFrom::Start(self.position))?;
+            inner.read(buf)?
+        };
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for SharedReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::Current(d) => self.position.checked_add_signed(d),
+            SeekFrom::End(d) => self.lock()?.seek(SeekFrom::End(0))?.checked_add_signed(d),
+        };
+        self.position = position.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+/// Bytes kept at the start of a [`DataSource::Pipe`], enough to tell the archive it holds and to
+/// be read again by each of its clones.
+pub const PIPE_HEAD_SIZE: usize = 256 * 1024;
+
+/// A non-seekable reader shared by the clones of a [`DataSource::Pipe`], each keeping its own
+/// position. Its first [`PIPE_HEAD_SIZE`] bytes can be read again, the rest only once and front to
+/// back, seeking forward skipping over the data.
+pub struct PipedReader {
+    inner: Arc<Mutex<Pipe>>,
+    position: u64,
+}
+
+struct Pipe {
+    head: Vec<u8>,
+    rest: Box<dyn Read + Send>,
+    /// Bytes read from the start of the pipe, head included.
+    consumed: u64,
+}
+
+impl std::fmt::Debug for PipedReader {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PipedReader")
+            .field("position", &self.position)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Read for PipedReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut guard = self
+            .inner
+            .lock()
+            .map_err(|_| std::io::Error::other("the pipe of the source is poisoned"))?;
+        let pipe = &mut *guard;
+        let n = match usize::try_from(self.position) {
+            Ok(position) if position < pipe.head.len() => {
+                let head = &pipe.head[position..];
+                let n = head.len().min(buf.len());
+                buf[..n].copy_from_slice(&head[..n]);
+                n
+            }
+            _ if self.position < pipe.consumed => {
+                return Err(std::io::Error::other(format!(
+                    "a piped archive is read only once, its data past {} bytes is gone",
+                    pipe.head.len()
+                )));
+            }
+            _ => {
+                let skip = self.position - pipe.consumed;
+                let skipped =
+                    std::io::copy(&mut (&mut pipe.rest).take(skip), &mut std::io::sink())?;
+                pipe.consumed += skipped;
+                if skipped < skip {
+                    return Ok(0);
+                }
+                let n = pipe.rest.read(buf)?;
+                pipe.consumed += n as u64;
+                n
+            }
+        };
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for PipedReader {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::Current(d) => self.position.checked_add_signed(d),
+            SeekFrom::End(_) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "the end of a piped archive is unknown",
+                ))
+            }
+        };
+        self.position = position.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
 pub trait Lengthed {
     fn len(&self) -> Result<u64, std::io::Error>;
 
@@ -832,11 +3012,17 @@ pub trait Lengthed {
     }
 }
 
-impl Lengthed for DataSource<'_> {
+impl Lengthed for DataSource {
     fn len(&self) -> Result<u64, std::io::Error> {
         match self {
             DataSource::File(f, _) => f.metadata().map(|m| m.len()),
-            DataSource::Stream(val) => Ok(val.get_ref().len() as u64),
+            DataSource::Memory(val) => Ok(val.get_ref().len() as u64),
+            DataSource::Reader(r) => r.lock()?.seek(SeekFrom::End(0)),
+            DataSource::Volumes(v) => Ok(v.len()),
+            DataSource::Pipe(_) => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "the length of a piped archive is unknown",
+            )),
         }
     }
 }
@@ -845,33 +3031,39 @@ pub trait ReadSeek: Read + Seek {}
 
 impl<T: Read + Seek> ReadSeek for T {}
 
-impl<'a> Read for DataSource<'a> {
+impl Read for DataSource {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         match self {
             DataSource::File(file, _) => file.read(buf),
-            DataSource::Stream(val) => val.read(buf),
+            DataSource::Memory(val) => val.read(buf),
+            DataSource::Reader(r) => r.read(buf),
+            DataSource::Pipe(p) => p.read(buf),
+            DataSource::Volumes(v) => v.read(buf),
         }
     }
 }
 
-impl<'a> Seek for DataSource<'a> {
+impl Seek for DataSource {
     fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
         match self {
             DataSource::File(file, _) => file.seek(pos),
-            DataSource::Stream(val) => val.seek(pos),
+            DataSource::Memory(val) => val.seek(pos),
+            DataSource::Reader(r) => r.seek(pos),
+            DataSource::Pipe(p) => p.seek(pos),
+            DataSource::Volumes(v) => v.seek(pos),
         }
     }
 }
 
-impl Clone for DataSource<'_> {
+impl Clone for DataSource {
     fn clone(&self) -> Self {
         self.try_clone()
             .expect("Failed to clone DataSource, this should never happen")
     }
 }
 
-impl<'a> AsRef<DataSource<'a>> for DataSource<'a> {
-    fn as_ref(&self) -> &DataSource<'a> {
+impl AsRef<DataSource> for DataSource {
+    fn as_ref(&self) -> &DataSource {
         self
     }
 }
@@ -902,6 +3094,8 @@ impl<const REPR: char> TryFrom<MagicBytesAt<8, REPR>> for ArchiveCompression {
             }
             #[cfg(feature = "zstd_codecs")]
             MagicBytesAt(0, [0x28, 0xb5, 0x2f, 0xfd, _, _, _, _]) => Ok(ArchiveCompression::Zstd),
+            #[cfg(feature = "lzw_codecs")]
+            MagicBytesAt(0, [0x1f, 0x9d, _, _, _, _, _, _]) => Ok(ArchiveCompression::Compress),
             _ => Err(Error::new(
                 ErrorKind::InvalidData,
                 format!("unknown magic bytes at {}: {:04x?}", magic.0, magic.1),
@@ -932,6 +3126,11 @@ impl TryFrom<ArchiveCompression> for zip::CompressionMethod {
                 ErrorKind::InvalidInput,
                 "Lzma compression is not supported for zip archives.",
             )),
+            #[cfg(feature = "lzw_codecs")]
+            ArchiveCompression::Compress => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Compress compression is not supported for zip archives.",
+            )),
             ArchiveCompression::Unknown(s) => Err(Error::new(
                 ErrorKind::InvalidInput,
                 format!("Unknown compression method: {}", s),
@@ -972,7 +3171,7 @@ mod test {
     #[test]
     fn test_seek() {
         let bfr = vec![1, 2, 3, 4, 5];
-        let mut data = DataSource::stream(&bfr);
+        let mut data = DataSource::memory(bfr);
         let mut buf = [0; 2];
         data.read_exact(&mut buf).unwrap();
         assert_eq!(buf, [1, 2]);
@@ -985,7 +3184,7 @@ mod test {
     #[test]
     fn test_seek_cloned() {
         let bfr = vec![1, 2, 3, 4, 5];
-        let data = DataSource::stream(&bfr);
+        let data = DataSource::memory(bfr);
         let mut reader = data.clone();
 
         let mut buf = [0; 2];
@@ -997,6 +3196,115 @@ mod test {
         assert_eq!(buf, [3, 4]);
     }
 
+    #[test]
+    fn keeps_the_positions_of_clones_of_files() {
+        let mut data = DataSource::from(File::open("tests/fixtures/test1.zip").unwrap());
+        let mut head = [0; 4];
+        data.read_exact(&mut head).unwrap();
+
+        let mut clone = data.clone();
+        let mut buf = [0; 4];
+        clone.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, head);
+        // reading the clone leaves the position of the original alone
+        data.read_exact(&mut buf).unwrap();
+        assert_eq!(data.stream_position().unwrap(), 8);
+        assert_eq!(clone.stream_position().unwrap(), 4);
+
+        let pathless = DataSource::File(
+            Box::new(File::open("tests/fixtures/test1.zip").unwrap()),
+            String::new(),
+        );
+        assert!(pathless.try_clone().is_err());
+    }
+
+    #[test]
+    fn shares_readers_between_clones() {
+        let mut data = DataSource::seekable(std::io::Cursor::new(vec![1, 2, 3, 4, 5]));
+        let mut clone = data.clone();
+        assert_eq!(data.len().unwrap(), 5);
+
+        let mut buf = [0; 2];
+        data.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [1, 2]);
+        // the clone starts over, and reading it leaves the position of the original alone
+        clone.seek(SeekFrom::End(-1)).unwrap();
+        clone.read_exact(&mut buf[..1]).unwrap();
+        assert_eq!(buf[0], 5);
+        data.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [3, 4]);
+        assert!(data.seek(SeekFrom::Current(-5)).is_err());
+    }
+
+    #[cfg(all(feature = "zip_archive", feature = "deflate_codecs"))]
+    #[test]
+    fn opens_archives_from_owned_sources() {
+        let path = "tests/fixtures/test1.zip";
+        let names = |archive: &Archive| {
+            archive
+                .list(ListOptions::default())
+                .unwrap()
+                .into_iter()
+                .map(|e| e.name)
+                .collect::<Vec<_>>()
+        };
+        let expected = names(&Archive::from_path(path).unwrap());
+        let opened = [
+            Archive::of(DataSource::seekable(File::open(path).unwrap())).unwrap(),
+            // the archive outlives the bytes it was read from
+            Archive::from_bytes(std::fs::read(path).unwrap()).unwrap(),
+        ];
+        for archive in opened {
+            assert_eq!(names(&archive), expected);
+        }
+    }
+
+    #[cfg(feature = "tar_archive")]
+    #[test]
+    fn streams_piped_tar_archives() {
+        let path = "tests/fixtures/test1.tar";
+        // the zeroes padding the archive past the head of the pipe end it as its last blocks do
+        let mut bytes = std::fs::read(path).unwrap();
+        bytes.resize(PIPE_HEAD_SIZE * 2, 0);
+        let source = DataSource::reader(Cursor::new(bytes).take(u64::MAX)).unwrap();
+        assert!(matches!(source, DataSource::Pipe(_)));
+
+        let names = |archive: Archive| {
+            let mut names = archive
+                .list(ListOptions::default())
+                .unwrap()
+                .into_iter()
+                .map(|e| e.name)
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            names(Archive::of(source.clone()).unwrap()),
+            names(Archive::from_path(path).unwrap())
+        );
+
+        // its head can be read again, the rest only once
+        let mut head = [0; 512];
+        source.clone().read_exact(&mut head).unwrap();
+        assert!(is_tar_header(&head));
+        let mut all = Vec::new();
+        source.clone().read_to_end(&mut all).unwrap();
+        assert_eq!(all.len(), PIPE_HEAD_SIZE * 2);
+        assert!(source.clone().read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn spools_piped_archives_which_need_to_seek() {
+        let zeroes = vec![0; PIPE_HEAD_SIZE + 1];
+        let source = DataSource::reader(Cursor::new(zeroes.clone()).take(u64::MAX)).unwrap();
+        assert!(matches!(source, DataSource::Reader(_)));
+        assert_eq!(source.len().unwrap(), zeroes.len() as u64);
+
+        let short = DataSource::reader(Cursor::new(vec![1, 2, 3]).take(u64::MAX)).unwrap();
+        assert!(matches!(short, DataSource::Memory(_)));
+    }
+
     #[test]
 
     fn archive_compression_from_magic_bytes() {
@@ -1065,6 +3373,25 @@ mod test {
                 );
             }
 
+            #[cfg(feature = "lzw_codecs")]
+            {
+                let compress = DataSource::file("tests/fixtures/test1.tar.Z")?;
+                assert_eq!(
+                    ArchiveType::try_from_datasource(compress).unwrap(),
+                    (ArchiveType::Tar, ArchiveCompression::Compress)
+                );
+                assert_eq!(
+                    ArchiveType::guess_from_filename("old.tar.Z").unwrap(),
+                    (ArchiveType::Tar, Some(ArchiveCompression::Compress))
+                );
+            }
+
+            let v7 = DataSource::file("tests/fixtures/test1-v7.tar")?;
+            assert_eq!(
+                ArchiveType::try_from_datasource(v7).unwrap(),
+                (ArchiveType::Tar, ArchiveCompression::None)
+            );
+
             let tar = DataSource::file("tests/fixtures/test1.tar")?;
             assert_eq!(
                 ArchiveType::try_from_datasource(tar).unwrap(),
@@ -1092,4 +3419,629 @@ mod test {
 
         Ok(())
     }
+
+    #[cfg(feature = "zip_archive")]
+    #[test]
+    fn guess_zip_based_packages() {
+        for name in [
+            "app.jar", "site.war", "app.apk", "App.ipa", "a.docx", "b.xlsx", "c.epub",
+        ] {
+            assert_eq!(
+                ArchiveType::guess_from_filename(name).unwrap(),
+                (ArchiveType::Zip, None)
+            );
+        }
+    }
+
+    #[test]
+    fn source_root_from_str() {
+        assert_eq!(
+            SourceRoot::from_str("./a=:docs").unwrap(),
+            SourceRoot {
+                root: PathBuf::from("./a"),
+                prefix: PathBuf::from("docs"),
+            }
+        );
+        assert_eq!(
+            SourceRoot::from_str("b=assets/img/").unwrap().prefix,
+            PathBuf::from("assets/img")
+        );
+        assert_eq!(SourceRoot::from_str("c=:").unwrap().prefix, PathBuf::new());
+        assert!(SourceRoot::from_str("nomapping").is_err());
+        assert!(SourceRoot::from_str("=:docs").is_err());
+    }
+
+    #[cfg(feature = "zip_archive")]
+    #[test]
+    fn opens_paths_and_files() {
+        let names = |archive: Archive| {
+            archive
+                .list(ListOptions::default())
+                .unwrap()
+                .into_iter()
+                .map(|e| e.name)
+                .collect::<Vec<_>>()
+        };
+        let expected =
+            names(Archive::of(DataSource::file("tests/fixtures/test1.zip").unwrap()).unwrap());
+        assert_eq!(
+            names(Archive::try_from(Path::new("tests/fixtures/test1.zip")).unwrap()),
+            expected
+        );
+        assert_eq!(
+            names(Archive::try_from("tests/fixtures/test1.zip").unwrap()),
+            expected
+        );
+        assert_eq!(
+            names(Archive::try_from(File::open("tests/fixtures/test1.zip").unwrap()).unwrap()),
+            expected
+        );
+        #[cfg(feature = "sevenz_archive")]
+        assert!(
+            !names(Archive::try_from(File::open("tests/fixtures/test1.7z").unwrap()).unwrap())
+                .is_empty()
+        );
+
+        assert!(matches!(
+            Archive::try_from("tests/fixtures/missing.zip"),
+            Err(ArchiveError::Io(_))
+        ));
+    }
+
+    #[cfg(feature = "tar_archive")]
+    #[test]
+    fn falls_back_to_the_extension() {
+        struct Logs(std::cell::RefCell<Vec<String>>);
+        impl EventHandler for Logs {
+            fn handle(&self, event: ArchiveEvent) {
+                if let ArchiveEvent::Log(message) = event {
+                    self.0.borrow_mut().push(message);
+                }
+            }
+        }
+
+        // an empty archive is only blocks of zeroes, without any header to recognize
+        let path = std::env::temp_dir().join(format!("hezi-fallback-{}.tar", std::process::id()));
+        std::fs::write(&path, [0; 1024]).unwrap();
+        let logs = Logs(Default::default());
+        let archive =
+            Archive::of_with_events(DataSource::file(&path).unwrap(), Some(&logs)).unwrap();
+        assert!(matches!(archive, Archive::Tar(_)));
+        assert!(archive.list(ListOptions::default()).unwrap().is_empty());
+        assert_eq!(logs.0.borrow().len(), 1);
+
+        // streams have no name to fall back to
+        let zeroes = vec![0; 1024];
+        assert!(matches!(
+            Archive::of(DataSource::memory(zeroes.as_slice())),
+            Err(ArchiveError::UnknownArchiveType(_))
+        ));
+        _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn create_options_entry_name() {
+        let options = CreateOptions {
+            destination: PathBuf::from("/out.zip"),
+            source: PathBuf::from("/work"),
+            roots: vec![
+                SourceRoot::from_str("/work/a=:docs").unwrap(),
+                SourceRoot::from_str("/work/a/deep=:deep").unwrap(),
+                SourceRoot::from_str("/elsewhere/b=:assets").unwrap(),
+            ],
+            files: vec![],
+            password: None,
+            archive_type: ArchiveType::_Unreachable,
+            compression: None,
+            overwrite: false,
+            include_hidden: true,
+            skip_unreadable: false,
+            verify_after: false,
+            volume_size: None,
+            update: false,
+            sevenz: Default::default(),
+            zip: Default::default(),
+            tar: Default::default(),
+            event_handler: Box::new(SimpleLogger),
+        };
+
+        assert_eq!(
+            options.entry_name("/work/a/readme.md"),
+            PathBuf::from("docs/readme.md")
+        );
+        assert_eq!(
+            options.entry_name("/work/a/deep/x.txt"),
+            PathBuf::from("deep/x.txt")
+        );
+        assert_eq!(options.entry_name("/elsewhere/b"), PathBuf::from("assets"));
+        assert_eq!(
+            options.entry_name("/work/c/y.txt"),
+            PathBuf::from("c/y.txt")
+        );
+        assert_eq!(options.entry_name("/tmp/z"), PathBuf::from("/tmp/z"));
+    }
+
+    #[test]
+    fn create_options_leave_out_the_destination() {
+        struct Skipped(std::rc::Rc<std::cell::RefCell<Vec<String>>>);
+        impl EventHandler for Skipped {
+            fn handle(&self, event: ArchiveEvent) {
+                if let ArchiveEvent::Skipped(name, SkipReason::ArchiveItself) = event {
+                    self.0.borrow_mut().push(name);
+                }
+            }
+        }
+
+        let dir = std::env::temp_dir().join(format!("hezi-itself-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        let files = [
+            "a.txt",
+            "out.tar",
+            "out.tar.001",
+            "out.tar.bak",
+            "sub/out.tar",
+        ]
+        .map(|name| dir.join(name));
+        for file in &files {
+            std::fs::write(file, "").unwrap();
+        }
+
+        let skipped = std::rc::Rc::default();
+        let mut options = CreateOptions {
+            // named another way than the files
+            destination: dir.join("sub/../out.tar"),
+            source: dir.clone(),
+            roots: vec![],
+            files: files.to_vec(),
+            password: None,
+            archive_type: ArchiveType::_Unreachable,
+            compression: None,
+            overwrite: true,
+            include_hidden: true,
+            skip_unreadable: false,
+            verify_after: false,
+            volume_size: None,
+            update: false,
+            sevenz: Default::default(),
+            zip: Default::default(),
+            tar: Default::default(),
+            event_handler: Box::new(Skipped(std::rc::Rc::clone(&skipped))),
+        };
+        options.remove_destination();
+        assert_eq!(
+            options.files,
+            [&files[0], &files[3], &files[4]].map(PathBuf::to_owned)
+        );
+        assert_eq!(*skipped.borrow(), ["out.tar", "out.tar.001"]);
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(feature = "tar_archive")]
+    #[test]
+    fn create_profiles_preset_options() {
+        let options = |level| CreateOptions {
+            destination: PathBuf::from("/out.tar.gz"),
+            source: PathBuf::from("/work"),
+            roots: vec![],
+            files: vec![],
+            password: None,
+            archive_type: ArchiveType::Tar,
+            compression: Some(CompressionSpec {
+                level,
+                ..CompressionSpec::new(ArchiveCompression::Gzip)
+            }),
+            overwrite: false,
+            include_hidden: true,
+            skip_unreadable: false,
+            verify_after: false,
+            volume_size: None,
+            update: false,
+            sevenz: Default::default(),
+            zip: Default::default(),
+            tar: Default::default(),
+            event_handler: Box::new(SimpleLogger),
+        };
+
+        let mut strict = options(None);
+        CreateProfile::Strict.apply(&mut strict);
+        assert_eq!(strict.tar.format, TarFormat::Pax);
+        assert!(strict.tar.precise_times && strict.zip.extended_timestamps);
+        assert!(strict.verify_after);
+
+        let mut compat = options(None);
+        CreateProfile::Strict.apply(&mut compat);
+        CreateProfile::Compat.apply(&mut compat);
+        assert_eq!(compat.tar, TarCreateOptions::default());
+        assert_eq!(compat.zip.zip64, Zip64Mode::Never);
+        assert_eq!(compat.zip.unicode_names, ZipUnicodeNames::PathField);
+
+        let mut fast = options(None);
+        CreateProfile::Fast.apply(&mut fast);
+        assert_eq!(fast.compression.unwrap().level, Some(1));
+        let mut given = options(Some(6));
+        CreateProfile::Fast.apply(&mut given);
+        assert_eq!(given.compression.unwrap().level, Some(6));
+
+        // without a codec, the one the format compresses with by default
+        #[cfg(all(feature = "zip_archive", feature = "deflate_codecs"))]
+        {
+            let mut zip = CreateOptions {
+                archive_type: ArchiveType::Zip,
+                compression: None,
+                ..options(None)
+            };
+            CreateProfile::Fast.apply(&mut zip);
+            assert_eq!(
+                zip.compression.map(|c| (c.algorithm, c.level)),
+                Some((ArchiveCompression::Deflate, Some(1)))
+            );
+        }
+    }
+
+    #[test]
+    fn join_path_with_root_stays_inside() {
+        let root = PathBuf::from("./Desktop");
+        assert_eq!(
+            join_path_with_root(&root, "/test").unwrap(),
+            PathBuf::from("./Desktop/test")
+        );
+        assert_eq!(
+            join_path_with_root(&root, "a/./b/../c").unwrap(),
+            PathBuf::from("./Desktop/a/c")
+        );
+        assert!(matches!(
+            join_path_with_root(&root, "a/../../etc/passwd"),
+            Err(ArchiveError::UnsafePath(_))
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn safe_join_stays_inside_through_links() {
+        use std::{fs, os::unix::fs::symlink};
+
+        let dir = std::env::temp_dir().join(format!("hezi-safe-join-{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        let (root, outside) = (dir.join("root"), dir.join("outside"));
+        fs::create_dir_all(root.join("real")).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        symlink("real", root.join("inside")).unwrap();
+        symlink(&outside, root.join("escape")).unwrap();
+        symlink("missing", root.join("dangling")).unwrap();
+
+        assert_eq!(safe_join(&root, "./a/./b").unwrap(), root.join("a/b"));
+        assert_eq!(safe_join(&root, "inside/f").unwrap(), root.join("real/f"));
+        assert_eq!(safe_join(&root, "inside").unwrap(), root.join("inside"));
+        assert_eq!(safe_join(&root, "dangling").unwrap(), root.join("dangling"));
+        for name in [
+            "../f",
+            "a/../b",
+            "/a/b",
+            "escape",
+            "escape/f",
+            "inside/../escape/f",
+            "dangling/f",
+        ] {
+            assert!(
+                matches!(safe_join(&root, name), Err(ArchiveError::UnsafePath(_))),
+                "{}",
+                name
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn symlink_target_within_root() {
+        let root = PathBuf::from("/out");
+        let link = PathBuf::from("/out/a/link");
+        assert_eq!(
+            symlink_target_within(&root, &link, "../b/file").unwrap(),
+            PathBuf::from("../b/file")
+        );
+        for target in ["/b/file", "/etc/passwd", "../../etc/passwd"] {
+            assert!(
+                matches!(
+                    symlink_target_within(&root, &link, target),
+                    Err(ArchiveError::UnsafePath(_))
+                ),
+                "{}",
+                target
+            );
+        }
+    }
+
+    #[cfg(all(unix, feature = "tar_archive"))]
+    #[test]
+    fn create_skips_unreadable_files() {
+        let dir = std::env::temp_dir().join(format!("hezi-unreadable-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("ok.txt"), "ok").unwrap();
+        std::os::unix::fs::symlink(dir.join("missing"), dir.join("broken")).unwrap();
+
+        let options = |skip_unreadable| CreateOptions {
+            destination: dir.join("out.tar"),
+            source: dir.clone(),
+            roots: vec![],
+            files: vec![dir.join("ok.txt"), dir.join("broken")],
+            password: None,
+            archive_type: ArchiveType::Tar,
+            compression: Some(CompressionSpec::new(ArchiveCompression::None)),
+            overwrite: true,
+            include_hidden: true,
+            skip_unreadable,
+            verify_after: false,
+            volume_size: None,
+            update: false,
+            sevenz: Default::default(),
+            zip: Default::default(),
+            tar: Default::default(),
+            event_handler: Box::new(SimpleLogger),
+        };
+        assert!(Archive::create(options(false)).is_err());
+
+        let result = Archive::create(options(true)).unwrap();
+        assert_eq!(result.total_size, 2);
+        assert_eq!(result.unreadable.len(), 1);
+        assert_eq!(result.unreadable[0].0, dir.join("broken"));
+        let entries = Archive::of(DataSource::file(dir.join("out.tar")).unwrap())
+            .unwrap()
+            .list(ListOptions::default())
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(all(
+        feature = "zip_archive",
+        feature = "tar_archive",
+        feature = "sevenz_archive",
+        feature = "lzma_codecs"
+    ))]
+    #[test]
+    fn create_keeps_empty_directories() {
+        let dir = std::env::temp_dir().join(format!("hezi-empty-dirs-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        let source = dir.join("source");
+        for empty in ["a/empty", "b/c/deep_empty", "top_empty"] {
+            std::fs::create_dir_all(source.join(empty)).unwrap();
+        }
+        std::fs::write(source.join("a/f.txt"), "f").unwrap();
+
+        for (name, archive_type, compression) in [
+            ("out.zip", ArchiveType::Zip, ArchiveCompression::None),
+            ("out.tar", ArchiveType::Tar, ArchiveCompression::None),
+            ("out.7z", ArchiveType::SevenZ, ArchiveCompression::Lzma),
+        ] {
+            Archive::create(CreateOptions {
+                destination: dir.join(name),
+                source: source.clone(),
+                roots: vec![],
+                files: walk_paths(&[&source]),
+                password: None,
+                archive_type,
+                compression: Some(CompressionSpec::new(compression)),
+                overwrite: true,
+                include_hidden: true,
+                skip_unreadable: false,
+                verify_after: false,
+                volume_size: None,
+                update: false,
+                sevenz: Default::default(),
+                zip: Default::default(),
+                tar: Default::default(),
+                event_handler: Box::new(SimpleLogger),
+            })
+            .unwrap();
+
+            let archive = Archive::of(DataSource::file(dir.join(name)).unwrap()).unwrap();
+            let names = archive
+                .list(ListOptions::default())
+                .unwrap()
+                .into_iter()
+                .filter(|e| e.fstype == ArchiveFileEntityType::Directory)
+                .map(|e| e.name.trim_end_matches('/').to_string())
+                .filter(|name| !name.is_empty() && name != ".")
+                .collect::<Vec<_>>();
+            assert_eq!(
+                names,
+                vec!["a", "a/empty", "b", "b/c", "b/c/deep_empty", "top_empty"],
+                "{}",
+                name
+            );
+
+            let out = dir.join(format!("{}-extracted", name));
+            archive
+                .extract(ExtractOptions {
+                    destination: out.clone(),
+                    ..Default::default()
+                })
+                .unwrap();
+            for empty in ["a/empty", "b/c/deep_empty", "top_empty"] {
+                assert!(out.join(empty).is_dir(), "{} of {}", empty, name);
+            }
+        }
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(feature = "tar_archive")]
+    #[test]
+    fn create_updates_with_the_options_given() {
+        struct Events(std::rc::Rc<std::cell::RefCell<Vec<String>>>);
+        impl EventHandler for Events {
+            fn handle(&self, event: ArchiveEvent) {
+                match event {
+                    ArchiveEvent::Added(name, _) => self.0.borrow_mut().push(name),
+                    ArchiveEvent::Log(message) => self.0.borrow_mut().push(message),
+                    _ => {}
+                }
+            }
+        }
+
+        let dir = std::env::temp_dir().join(format!("hezi-update-options-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        let source = dir.join("source");
+        std::fs::create_dir_all(&source).unwrap();
+        std::fs::write(source.join("kept.txt"), "kept").unwrap();
+        std::fs::write(source.join("changed.txt"), "old").unwrap();
+        let destination = dir.join("out.tar");
+
+        let events = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let options = || {
+            let mut options = CreateOptions {
+                destination: destination.clone(),
+                source: source.clone(),
+                roots: vec![],
+                files: walk_paths(&[&source]),
+                password: None,
+                archive_type: ArchiveType::Tar,
+                compression: Some(CompressionSpec::new(ArchiveCompression::None)),
+                overwrite: true,
+                include_hidden: true,
+                skip_unreadable: false,
+                verify_after: false,
+                volume_size: None,
+                update: true,
+                sevenz: Default::default(),
+                zip: Default::default(),
+                tar: TarCreateOptions {
+                    owner: Some(Owner::user("4242").unwrap()),
+                    ..Default::default()
+                },
+                event_handler: Box::new(Events(std::rc::Rc::clone(&events))),
+            };
+            CreateProfile::Strict.apply(&mut options);
+            options
+        };
+        Archive::create(options()).unwrap();
+        std::fs::write(source.join("changed.txt"), "newer").unwrap();
+        events.borrow_mut().clear();
+        Archive::create(options()).unwrap();
+        assert_eq!(
+            *events.borrow(),
+            [
+                format!("Updating {} with 1 changed files", destination.display()),
+                "changed.txt".to_string()
+            ]
+        );
+
+        let mut tar = tar::Archive::new(File::open(&destination).unwrap());
+        let headers = tar
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().header().clone())
+            .filter(|header| header.entry_type() == tar::EntryType::Regular)
+            .collect::<Vec<_>>();
+        assert_eq!(headers.len(), 2);
+        for header in headers {
+            assert!(header.as_ustar().is_some());
+            assert_eq!(header.uid().unwrap(), 4242);
+        }
+
+        let encrypted = CreateOptions {
+            password: Some("secret".to_string()),
+            ..options()
+        };
+        assert!(matches!(
+            Archive::create(encrypted),
+            Err(ArchiveError::UnsupportedActionForArchiveType(
+                _,
+                ArchiveType::Tar
+            ))
+        ));
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(all(
+        feature = "zip_archive",
+        feature = "tar_archive",
+        feature = "sevenz_archive",
+        feature = "lzma_codecs"
+    ))]
+    #[test]
+    fn create_updates_changed_files() {
+        let dir = std::env::temp_dir().join(format!("hezi-update-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        let source = dir.join("source");
+        let write = |name: &str, content: &str, secs: u64| {
+            let path = source.join(name);
+            std::fs::write(&path, content).unwrap();
+            let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(modified)
+                .unwrap();
+        };
+
+        for (name, compression) in [
+            ("out.zip", ArchiveCompression::None),
+            ("out.tar", ArchiveCompression::None),
+            ("out.tar.gz", ArchiveCompression::Gzip),
+            ("out.7z", ArchiveCompression::Lzma),
+        ] {
+            _ = std::fs::remove_dir_all(&source);
+            std::fs::create_dir_all(source.join("sub")).unwrap();
+            write("kept.txt", "kept", 1_000_000_000);
+            write("sub/changed.txt", "old", 1_000_000_000);
+            write("touched.txt", "same", 1_000_000_000);
+
+            let create = || {
+                Archive::create(CreateOptions {
+                    destination: dir.join(name),
+                    source: source.clone(),
+                    roots: vec![],
+                    files: walk_paths(&[&source]),
+                    password: None,
+                    archive_type: ArchiveType::guess_from_filename(name).unwrap().0,
+                    compression: Some(CompressionSpec::new(compression.clone())),
+                    overwrite: true,
+                    include_hidden: true,
+                    skip_unreadable: false,
+                    verify_after: false,
+                    volume_size: None,
+                    update: true,
+                    sevenz: Default::default(),
+                    zip: Default::default(),
+                    tar: Default::default(),
+                    event_handler: Box::new(SimpleLogger),
+                })
+                .unwrap()
+            };
+            // created from scratch the first time
+            create();
+            assert_eq!(create().total_size, 0, "{}", name);
+
+            write("sub/changed.txt", "newer", 1_000_000_000);
+            write("touched.txt", "same", 1_000_000_100);
+            write("added.txt", "added", 1_000_000_000);
+            assert_eq!(create().total_size, 14, "{}", name);
+
+            let archive = Archive::of(DataSource::file(dir.join(name)).unwrap()).unwrap();
+            let mut files = archive
+                .list(ListOptions::default())
+                .unwrap()
+                .into_iter()
+                .filter(|e| e.fstype == ArchiveFileEntityType::File)
+                .map(|e| e.name)
+                .collect::<Vec<_>>();
+            files.sort();
+            assert_eq!(
+                files,
+                ["added.txt", "kept.txt", "sub/changed.txt", "touched.txt"],
+                "{}",
+                name
+            );
+            let (_, content) = archive
+                .find_first(|e| e.name == "sub/changed.txt")
+                .unwrap()
+                .unwrap();
+            assert_eq!(content, b"newer", "{}", name);
+            assert_eq!(create().total_size, 0, "{}", name);
+        }
+        _ = std::fs::remove_dir_all(&dir);
+    }
 }
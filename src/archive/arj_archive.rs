@@ -0,0 +1,478 @@
+// read-only support for ARJ archives (`.arj`), common in DOS era software distributions. A main
+// header is followed by a header for each member, every header being checked by its CRC-32, and
+// members are stored (method 0), compressed with the static Huffman methods of LHA (methods 1 to
+// 3, which only differ by how hard the archiver looked for matches) or with the simpler method 4.
+
+use std::{
+    cell::OnceCell,
+    io::{BufReader, ErrorKind, Read},
+    ops::ControlFlow,
+};
+
+use crate::archive::{
+    dos_datetime,
+    entry_visitor::{
+        extract_entries, find_first_entry, list_entries, open_entry, scan_entries, EntryVisitor,
+        VisitOptions, VisitedEntry,
+    },
+    ArchiveError, ArchiveFileEntity, ArchiveFileEntityType, ArchiveMetadata, ArchiveScan,
+    ArchiveType, Archived, CreateOptions, CreateResult, DataSource, ExtractOptions, ExtractReport,
+    ListOptions, OpenOptions, DEFAULT_BUF_SIZE,
+};
+
+use super::{
+    edit::EntryEdit,
+    lzh::{BitReader, Decoder},
+};
+
+const HEADER_ID: [u8; 2] = [0x60, 0xea];
+const MAX_HEADER_SIZE: usize = 2600;
+/// Size of the fixed part of the header of a member.
+const FILE_HEADER_SIZE: usize = 30;
+const FLAG_GARBLED: u8 = 0x01;
+const HOST_UNIX: u8 = 2;
+const FILE_BINARY: u8 = 0;
+const FILE_TEXT: u8 = 1;
+const FILE_DIRECTORY: u8 = 3;
+/// Window of method 4, whose matches lie at most 15871 bytes back.
+const FAST_WINDOW_BITS: u32 = 15;
+
+fn invalid_data(message: String) -> ArchiveError {
+    ArchiveError::Io(std::io::Error::new(ErrorKind::InvalidData, message))
+}
+
+fn u16_at(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([buf[at], buf[at + 1]])
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+}
+
+/// Reads the next basic header, checking its CRC and skipping its extended headers, or `None` at
+/// the end of the archive.
+fn read_basic_header<R: Read>(reader: &mut R) -> Result<Option<Vec<u8>>, ArchiveError> {
+    let mut start = [0; 4];
+    match reader.read(&mut start[..1])? {
+        0 => return Ok(None),
+        _ => reader.read_exact(&mut start[1..])?,
+    }
+    if start[..2] != HEADER_ID {
+        return Err(invalid_data("invalid ARJ header".to_string()));
+    }
+    let size = u16_at(&start, 2) as usize;
+    if size == 0 {
+        return Ok(None);
+    }
+    if size > MAX_HEADER_SIZE {
+        return Err(invalid_data(format!("ARJ header of {} bytes", size)));
+    }
+
+    let mut basic = vec![0; size];
+    reader.read_exact(&mut basic)?;
+    let mut crc = [0; 4];
+    reader.read_exact(&mut crc)?;
+    let mut hasher = flate2::Crc::new();
+    hasher.update(&basic);
+    if hasher.sum() != u32::from_le_bytes(crc) {
+        return Err(invalid_data("CRC mismatch for an ARJ header".to_string()));
+    }
+
+    // extended headers, each followed by its CRC, hold nothing read here
+    loop {
+        let mut len = [0; 2];
+        reader.read_exact(&mut len)?;
+        match u16::from_le_bytes(len) {
+            0 => break,
+            len => {
+                std::io::copy(
+                    &mut reader.by_ref().take(len as u64 + 4),
+                    &mut std::io::sink(),
+                )?;
+            }
+        }
+    }
+    Ok(Some(basic))
+}
+
+#[derive(Debug)]
+struct Header {
+    name: String,
+    method: u8,
+    file_type: u8,
+    flags: u8,
+    /// Size of the data following the header.
+    compressed_size: u64,
+    size: u64,
+    last_modified: Option<chrono::DateTime<chrono::FixedOffset>>,
+    mode: Option<u32>,
+    crc: u32,
+}
+
+impl Header {
+    fn parse(basic: &[u8]) -> Result<Self, ArchiveError> {
+        let first_size = *basic.first().unwrap_or(&0) as usize;
+        if first_size < FILE_HEADER_SIZE || basic.len() < first_size {
+            return Err(invalid_data("invalid ARJ file header".to_string()));
+        }
+        let name = basic[first_size..].split(|b| *b == 0).next().unwrap_or(&[]);
+
+        Ok(Self {
+            name: String::from_utf8_lossy(name).replace('\\', "/"),
+            method: basic[5],
+            file_type: basic[6],
+            flags: basic[4],
+            compressed_size: u32_at(basic, 12) as u64,
+            size: u32_at(basic, 16) as u64,
+            last_modified: dos_datetime(u32_at(basic, 8)),
+            // other hosts store their attributes
+            mode: (basic[3] == HOST_UNIX).then(|| u16_at(basic, 26) as u32),
+            crc: u32_at(basic, 20),
+        })
+    }
+
+    /// Members other than files and directories, such as volume labels, are no entries.
+    fn visited_entry(&self) -> Option<VisitedEntry> {
+        let (name, fstype) = match self.file_type {
+            FILE_BINARY | FILE_TEXT => (self.name.clone(), ArchiveFileEntityType::File),
+            FILE_DIRECTORY => (
+                format!("{}/", self.name.trim_end_matches('/')),
+                ArchiveFileEntityType::Directory,
+            ),
+            _ => return None,
+        };
+        let is_file = fstype == ArchiveFileEntityType::File;
+
+        Some(VisitedEntry {
+            entity: ArchiveFileEntity {
+                name,
+                size: is_file.then_some(self.size),
+                compressed_size: is_file.then_some(self.compressed_size),
+                last_modified: self.last_modified,
+                compression: is_file.then(|| match self.method {
+                    0 => "stored".to_string(),
+                    method => format!("method {}", method),
+                }),
+                fstype,
+                raw: None,
+                unix: None,
+            },
+            mode: self.mode.map(|mode| mode & 0o7777),
+            link: None,
+            crc32: None,
+        })
+    }
+
+    /// Reader of the content of the member from its compressed `data`.
+    fn reader<'r, R: Read + 'r>(&self, data: R) -> Result<Box<dyn Read + 'r>, ArchiveError> {
+        if self.flags & FLAG_GARBLED != 0 {
+            return Err(ArchiveError::Io(std::io::Error::new(
+                ErrorKind::Unsupported,
+                format!(
+                    "{} is garbled with a password, which is not supported",
+                    self.name
+                ),
+            )));
+        }
+        match self.method {
+            0 => Ok(Box::new(CrcReader::new(data.take(self.size), self))),
+            1..=3 => Ok(Box::new(CrcReader::new(
+                Decoder::new(data, 16, self.size),
+                self,
+            ))),
+            4 => Ok(Box::new(CrcReader::new(
+                FastDecoder::new(data, self.size),
+                self,
+            ))),
+            method => Err(ArchiveError::Io(std::io::Error::new(
+                ErrorKind::Unsupported,
+                format!("unsupported ARJ method {}", method),
+            ))),
+        }
+    }
+}
+
+/// Checks the CRC-32 of the content once it was read entirely.
+struct CrcReader<R> {
+    inner: R,
+    name: String,
+    crc: flate2::Crc,
+    expected: u32,
+}
+
+impl<R: Read> CrcReader<R> {
+    fn new(inner: R, header: &Header) -> Self {
+        Self {
+            inner,
+            name: header.name.clone(),
+            crc: flate2::Crc::new(),
+            expected: header.crc,
+        }
+    }
+}
+
+impl<R: Read> Read for CrcReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.crc.update(&buf[..n]);
+        if n == 0 && !buf.is_empty() && self.crc.sum() != self.expected {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                format!("CRC mismatch for {}", self.name),
+            ));
+        }
+        Ok(n)
+    }
+}
+
+/// Decoder of method 4, whose literals are a `0` bit followed by the byte, and whose matches have
+/// their length and offset stored with as many leading `1` bits as their values need.
+struct FastDecoder<R> {
+    bits: BitReader<R>,
+    window: Vec<u8>,
+    position: usize,
+    remaining: u64,
+    /// Distance and remaining length of the match being copied.
+    copying: (usize, usize),
+}
+
+impl<R: Read> FastDecoder<R> {
+    fn new(inner: R, size: u64) -> Self {
+        Self {
+            bits: BitReader::new(inner),
+            window: vec![0; 1 << FAST_WINDOW_BITS],
+            position: 0,
+            remaining: size,
+            copying: (0, 0),
+        }
+    }
+
+    /// Reads a value stored as up to `max - min` `1` bits, ended by a `0` bit unless there are
+    /// as many, the count of which tells how many bits the value has past `min`.
+    fn variable(&mut self, min: u32, max: u32) -> std::io::Result<u32> {
+        let mut width = min;
+        let mut base = 0;
+        while width < max && self.bits.bits(1)? == 1 {
+            base += 1 << width;
+            width += 1;
+        }
+        Ok(base + self.bits.bits(width)?)
+    }
+
+    fn next_byte(&mut self) -> std::io::Result<u8> {
+        let mask = self.window.len() - 1;
+        if self.copying.1 == 0 {
+            match self.variable(0, 7)? {
+                0 => {
+                    let byte = self.bits.bits(8)? as u8;
+                    self.window[self.position & mask] = byte;
+                    self.position += 1;
+                    return Ok(byte);
+                }
+                length => {
+                    let offset = self.variable(9, 13)?;
+                    self.copying = (offset as usize + 1, length as usize + 2);
+                }
+            }
+        }
+
+        let byte = self.window[self.position.wrapping_sub(self.copying.0) & mask];
+        self.window[self.position & mask] = byte;
+        self.position += 1;
+        self.copying.1 -= 1;
+        Ok(byte)
+    }
+}
+
+impl<R: Read> Read for FastDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = buf
+            .len()
+            .min(self.remaining.min(usize::MAX as u64) as usize);
+        for byte in &mut buf[..n] {
+            *byte = self.next_byte()?;
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+pub struct ArjArchive {
+    pub(crate) source: DataSource,
+    scan: OnceCell<ArchiveScan>,
+}
+
+impl ArjArchive {
+    fn scan(&self) -> Result<&ArchiveScan, ArchiveError> {
+        ArchiveScan::cached(&self.scan, || scan_entries(self, None, None))
+    }
+}
+
+impl EntryVisitor for ArjArchive {
+    fn for_each_entry<F>(
+        &self,
+        options: &VisitOptions<'_>,
+        mut visit: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        let source = options
+            .retry
+            .wrap(self.source.clone(), &self.source, options.events);
+        let mut reader = BufReader::with_capacity(DEFAULT_BUF_SIZE, source);
+
+        // the main header names the archive, and is followed by the members
+        if read_basic_header(&mut reader)?.is_none() {
+            return Ok(());
+        }
+        while let Some(basic) = read_basic_header(&mut reader)? {
+            let header = Header::parse(&basic)?;
+            let mut data = (&mut reader).take(header.compressed_size);
+
+            let flow = match header.visited_entry() {
+                Some(visited) if options.wants(&visited.entity) => {
+                    if options.contents && visited.entity.fstype == ArchiveFileEntityType::File {
+                        let mut content = header.reader(&mut data)?;
+                        visit(visited, Some(&mut content))?
+                    } else {
+                        visit(visited, None)?
+                    }
+                }
+                _ => ControlFlow::Continue(()),
+            };
+            // the visitor may leave the data unread
+            std::io::copy(&mut data, &mut std::io::sink())?;
+            if flow.is_break() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Archived for ArjArchive {
+    fn of(source: DataSource) -> Result<Self, ArchiveError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            source,
+            scan: OnceCell::new(),
+        })
+    }
+
+    fn extract(&self, options: ExtractOptions) -> Result<ExtractReport, ArchiveError> {
+        extract_entries(self, &options, self.source.as_ref())
+    }
+
+    fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
+        if options.is_whole() {
+            return Ok(self.scan()?.entries.clone());
+        }
+        list_entries(self, self.scan.get(), &options)
+    }
+
+    fn create(_options: CreateOptions) -> Result<CreateResult, ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "create".to_string(),
+            ArchiveType::Arj,
+        ))
+    }
+
+    fn metadata(&self) -> Result<ArchiveMetadata, ArchiveError> {
+        let scan = self.scan()?;
+        Ok(ArchiveMetadata {
+            archive_type: ArchiveType::Arj,
+            entry_count: scan.entries.len(),
+            entries: scan.entries.clone(),
+            total_size: scan.total_size,
+            compressed_size: scan.compressed_size,
+            compression: None,
+            created: self.source.created(),
+            modified: self.source.modified(),
+            additional: None,
+        })
+    }
+
+    fn open(&self, options: OpenOptions) -> Result<(), ArchiveError> {
+        open_entry(self, options)
+    }
+
+    fn find_first<P>(
+        &self,
+        predicate: P,
+    ) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+    where
+        P: Fn(&ArchiveFileEntity) -> bool,
+    {
+        find_first_entry(self, predicate)
+    }
+
+    fn check_password(&self, _password: &str) -> Result<bool, ArchiveError> {
+        // garbled members are not supported, so no password is ever needed
+        Ok(true)
+    }
+
+    fn edit(&self, _entry: &str, _edit: &EntryEdit) -> Result<(), ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "edit".to_string(),
+            ArchiveType::Arj,
+        ))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::archive::Archive;
+
+    #[test]
+    fn reads_arj_archives() {
+        let archive = Archive::of(DataSource::file("tests/fixtures/test1.arj").unwrap()).unwrap();
+        assert!(matches!(archive, Archive::Arj(_)));
+
+        let entries = archive.list(ListOptions::default()).unwrap();
+        let names = entries
+            .iter()
+            .map(|e| (e.name.as_str(), e.fstype, e.compression.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                ("test1/", ArchiveFileEntityType::Directory, None),
+                (
+                    "test1/file1.txt",
+                    ArchiveFileEntityType::File,
+                    Some("method 1")
+                ),
+                (
+                    "test1/dir1/file2.txt",
+                    ArchiveFileEntityType::File,
+                    Some("method 4")
+                ),
+                ("readme.txt", ArchiveFileEntityType::File, Some("stored")),
+            ]
+        );
+        assert_eq!(
+            entries[1].last_modified.unwrap().to_rfc3339(),
+            "2024-05-04T09:24:00+00:00"
+        );
+
+        for (name, expected) in [
+            (
+                "test1/file1.txt",
+                std::fs::read("tests/fixtures/test1/file1.txt").unwrap(),
+            ),
+            (
+                "test1/dir1/file2.txt",
+                std::fs::read("tests/fixtures/test1/dir1/file2.txt").unwrap(),
+            ),
+            ("readme.txt", b"stored as is\n".to_vec()),
+        ] {
+            let (_, content) = archive.find_first(|e| e.name == name).unwrap().unwrap();
+            assert_eq!(content, expected, "{}", name);
+        }
+    }
+}
@@ -0,0 +1,241 @@
+// read-only support for Microsoft Cabinet files, the archives of Windows installers and driver
+// packages. Files are compressed together in folders, so their compressed size is not known, and
+// their names hold the whole path with backslashes; directories have no entry of their own.
+
+use std::{
+    cell::OnceCell,
+    io::{BufReader, Read, Seek, SeekFrom},
+    ops::ControlFlow,
+};
+
+use cab::{Cabinet, CompressionType, FileEntry};
+use chrono::NaiveDate;
+use serde_json::json;
+
+use crate::archive::{
+    entry_visitor::{
+        extract_entries, find_first_entry, list_entries, open_entry, scan_entries, EntryVisitor,
+        VisitOptions, VisitedEntry,
+    },
+    ArchiveError, ArchiveFileEntity, ArchiveFileEntityType, ArchiveMetadata, ArchiveScan,
+    ArchiveType, Archived, CreateOptions, CreateResult, DataSource, ExtractOptions, ExtractReport,
+    ListOptions, OpenOptions, DEFAULT_BUF_SIZE,
+};
+
+use super::edit::EntryEdit;
+
+pub struct CabArchive {
+    pub(crate) source: DataSource,
+    scan: OnceCell<ArchiveScan>,
+}
+
+impl CabArchive {
+    fn scan(&self) -> Result<&ArchiveScan, ArchiveError> {
+        ArchiveScan::cached(&self.scan, || scan_entries(self, None, None))
+    }
+
+    pub(crate) fn entity(file: &FileEntry, compression: CompressionType) -> ArchiveFileEntity {
+        ArchiveFileEntity {
+            name: file.name().replace('\\', "/"),
+            size: Some(file.uncompressed_size() as u64),
+            compressed_size: None,
+            last_modified: file.datetime().and_then(|dt| {
+                NaiveDate::from_ymd_opt(dt.year(), u8::from(dt.month()) as u32, dt.day() as u32)?
+                    .and_hms_opt(dt.hour() as u32, dt.minute() as u32, dt.second() as u32)
+                    .map(|t| t.and_utc().fixed_offset())
+            }),
+            compression: Some(compression_name(compression).to_string()),
+            fstype: ArchiveFileEntityType::File,
+            raw: None,
+            unix: None,
+        }
+    }
+}
+
+impl EntryVisitor for CabArchive {
+    fn for_each_entry<F>(
+        &self,
+        options: &VisitOptions<'_>,
+        mut visit: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        let source = options
+            .retry
+            .wrap(self.source.clone(), &self.source, options.events);
+        let mut cabinet = Cabinet::new(BufReader::with_capacity(DEFAULT_BUF_SIZE, source))?;
+
+        // files are read by name, which needs the cabinet borrowed mutably
+        let files = cabinet
+            .folder_entries()
+            .flat_map(|folder| {
+                folder
+                    .file_entries()
+                    .map(|file| {
+                        (
+                            file.name().to_string(),
+                            Self::entity(file, folder.compression_type()),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        for (name, entity) in files {
+            if !options.wants(&entity) {
+                continue;
+            }
+            let flow = if options.contents {
+                let mut reader = cabinet.read_file(&name)?;
+                visit(VisitedEntry::new(entity), Some(&mut reader))?
+            } else {
+                visit(VisitedEntry::new(entity), None)?
+            };
+            if flow.is_break() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Archived for CabArchive {
+    fn of(source: DataSource) -> Result<Self, ArchiveError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            source,
+            scan: OnceCell::new(),
+        })
+    }
+
+    fn extract(&self, options: ExtractOptions) -> Result<ExtractReport, ArchiveError> {
+        extract_entries(self, &options, self.source.as_ref())
+    }
+
+    fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
+        if options.is_whole() {
+            return Ok(self.scan()?.entries.clone());
+        }
+        list_entries(self, self.scan.get(), &options)
+    }
+
+    fn create(_options: CreateOptions) -> Result<CreateResult, ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "create".to_string(),
+            ArchiveType::Cab,
+        ))
+    }
+
+    fn metadata(&self) -> Result<ArchiveMetadata, ArchiveError> {
+        let mut source = self.source.clone();
+        let size = source.seek(SeekFrom::End(0))?;
+        source.rewind()?;
+        let cabinet = Cabinet::new(BufReader::with_capacity(DEFAULT_BUF_SIZE, source))?;
+        let folders = cabinet
+            .folder_entries()
+            .map(|folder| compression_name(folder.compression_type()))
+            .collect::<Vec<_>>();
+        let additional = json!({
+            "folders": folders,
+            "set_id": cabinet.cabinet_set_id(),
+            "set_index": cabinet.cabinet_set_index(),
+        });
+
+        let scan = self.scan()?;
+
+        Ok(ArchiveMetadata {
+            archive_type: ArchiveType::Cab,
+            entry_count: scan.entries.len(),
+            entries: scan.entries.clone(),
+            total_size: scan.total_size,
+            compressed_size: size,
+            compression: None,
+            created: self.source.created(),
+            modified: self.source.modified(),
+            additional: Some(additional),
+        })
+    }
+
+    fn open(&self, options: OpenOptions) -> Result<(), ArchiveError> {
+        open_entry(self, options)
+    }
+
+    fn find_first<P>(
+        &self,
+        predicate: P,
+    ) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+    where
+        P: Fn(&ArchiveFileEntity) -> bool,
+    {
+        find_first_entry(self, predicate)
+    }
+
+    fn check_password(&self, _password: &str) -> Result<bool, ArchiveError> {
+        // cabinets have no encryption
+        Ok(true)
+    }
+
+    fn edit(&self, _entry: &str, _edit: &EntryEdit) -> Result<(), ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "edit".to_string(),
+            ArchiveType::Cab,
+        ))
+    }
+}
+
+fn compression_name(compression: CompressionType) -> &'static str {
+    match compression {
+        CompressionType::None => "stored",
+        CompressionType::MsZip => "mszip",
+        CompressionType::Quantum(..) => "quantum",
+        CompressionType::Lzx(_) => "lzx",
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::archive::Archive;
+
+    #[test]
+    fn reads_cabinets() {
+        let archive = Archive::of(DataSource::file("tests/fixtures/test1.cab").unwrap()).unwrap();
+        assert!(matches!(archive, Archive::Cab(_)));
+
+        let entries = archive.list(ListOptions::default()).unwrap();
+        let names = entries
+            .iter()
+            .map(|e| (e.name.as_str(), e.size, e.compression.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                ("test1/file1.txt", Some(1469), Some("mszip")),
+                ("test1/dir1/file2.txt", Some(444), Some("mszip")),
+                ("readme.txt", Some(13), Some("stored")),
+            ]
+        );
+        assert_eq!(
+            entries[0].last_modified.unwrap().to_rfc3339(),
+            "2024-05-04T09:24:00+00:00"
+        );
+
+        let (_, content) = archive
+            .find_first(|e| e.name == "test1/dir1/file2.txt")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            content,
+            std::fs::read("tests/fixtures/test1/dir1/file2.txt").unwrap()
+        );
+        let (_, readme) = archive
+            .find_first(|e| e.name == "readme.txt")
+            .unwrap()
+            .unwrap();
+        assert_eq!(readme, b"stored as is\n");
+    }
+}
@@ -2,22 +2,92 @@
 // it detects the compression type and returns the appropriate reader
 // uses flat2 to decompress gzip and xz
 // uses bzip2 to decompress bzip2
-// uses rust-lzma to decompress lzma
+// uses liblzma to decompress xz and lzma, on several threads for xz
+// uses its own decoder to decompress the LZW of `compress`
 // uses zstd to decompress zstd
 
+#[cfg(feature = "lzma_codecs")]
+use std::io::BufRead;
 use std::io::{BufReader, Error, Read, Write};
 
 #[cfg(feature = "lzma_codecs")]
-use lzma::{LzmaReader, LzmaWriter};
+use liblzma::stream::{Check, Stream};
 #[cfg(feature = "sevenz_archive")]
 use sevenz_rust::SevenZMethod;
 use strum::EnumIter;
 
-use crate::archive::{ArchiveError, ReadSeek};
+#[cfg(feature = "lzw_codecs")]
+use crate::archive::lzw::LzwReader;
+#[cfg(feature = "multithreading")]
+use crate::archive::parallel_gzip::ParallelGzEncoder;
+#[cfg(feature = "bzip2_codecs")]
+use crate::archive::tuned_encoders::BzWorkFactorEncoder;
+use crate::archive::{tuned_encoders::StrategyGzEncoder, ArchiveError, ReadSeek};
 
 pub struct ArchiveCodec;
 
+/// Compression of an archive and how its codec is tuned.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CompressionSpec {
+    pub algorithm: ArchiveCompression,
+    /// Compression level, within the [`ArchiveCompression::valid_level_range`] of the algorithm,
+    /// the default one of the codec if `None`.
+    pub level: Option<i32>,
+    /// Number of threads the codecs supporting it may use, as many as the rayon thread pool has
+    /// if `None`, which is all the available cores unless `--threads` is given.
+    pub threads: Option<u32>,
+    pub extra: CodecParams,
+}
+
+impl CompressionSpec {
+    pub fn new(algorithm: ArchiveCompression) -> Self {
+        Self {
+            algorithm,
+            ..Default::default()
+        }
+    }
+
+    #[cfg_attr(not(feature = "multithreading"), allow(dead_code))]
+    pub(crate) fn threads(&self) -> usize {
+        self.threads
+            .map_or_else(rayon::current_num_threads, |t| t as usize)
+            .max(1)
+    }
+}
+
+/// Settings of a single codec, ignored by the others.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CodecParams {
+    /// Window of the long-distance matching of zstd, as a power of two between 10 and 31, which
+    /// finds repetitions far apart in large inputs like `zstd --long`. Windows above 27, 128 MiB,
+    /// take as much memory to decompress.
+    pub zstd_long: Option<u32>,
+    /// How gzip looks for repetitions, the default strategy of zlib if `None`. Gzip only
+    /// compresses on several threads with the default strategy.
+    pub gzip_strategy: Option<GzipStrategy>,
+    /// Effort of bzip2 on highly repetitive data before falling back to a slower sort, between 1
+    /// and 250, 30 by default.
+    pub bzip2_work_factor: Option<u32>,
+}
+
+/// Strategy of deflate, as zlib's `Z_FILTERED`, `Z_HUFFMAN_ONLY`, `Z_RLE` and `Z_FIXED`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "kebab-case")]
+pub enum GzipStrategy {
+    /// Only longer matches, for data such as images made of small random variations
+    Filtered,
+    /// No matches, only the coding of the bytes
+    HuffmanOnly,
+    /// Only runs of the same byte, as fast as huffman only but better on images
+    Rle,
+    /// The fixed codes of deflate, without tables, for small data
+    Fixed,
+}
+
 impl ArchiveCodec {
+    /// Decompressing reader, using as many threads as the rayon thread pool has for the codecs
+    /// that decompress in parallel.
     pub(crate) fn get_reader<'a, R: ReadSeek + 'a>(
         inner: R,
         compression: &ArchiveCompression,
@@ -27,7 +97,8 @@ impl ArchiveCodec {
                 let reader = std::io::BufReader::new(inner);
                 Ok(Box::new(reader))
             }
-            ArchiveCompression::Gzip => Ok(Box::new(flate2::bufread::GzDecoder::new(
+            // a stream may hold several members, such as web archives with one per record
+            ArchiveCompression::Gzip => Ok(Box::new(flate2::bufread::MultiGzDecoder::new(
                 BufReader::new(inner),
             ))),
             #[cfg(feature = "deflate_codecs")]
@@ -39,9 +110,19 @@ impl ArchiveCodec {
                 BufReader::new(inner),
             ))),
             #[cfg(feature = "lzma_codecs")]
-            ArchiveCompression::Lzma => Ok(Box::new(LzmaReader::new_decompressor(inner)?)),
+            ArchiveCompression::Lzma => Ok(Box::new(XzReader::new(
+                BufReader::new(inner),
+                CompressionSpec::default().threads(),
+            ))),
+            #[cfg(feature = "lzw_codecs")]
+            ArchiveCompression::Compress => Ok(Box::new(LzwReader::new(BufReader::new(inner))?)),
             #[cfg(feature = "zstd_codecs")]
-            ArchiveCompression::Zstd => Ok(Box::new(zstd::Decoder::new(inner)?)),
+            ArchiveCompression::Zstd => {
+                let mut decoder = zstd::Decoder::new(inner)?;
+                // the largest windows of long-distance matching
+                decoder.window_log_max(31)?;
+                Ok(Box::new(decoder))
+            }
             #[cfg(feature = "aes_codecs")]
             ArchiveCompression::Aes => Err(ArchiveError::UnsupportedCompression(
                 ArchiveCompression::Aes,
@@ -53,37 +134,103 @@ impl ArchiveCodec {
         }
     }
 
+    /// Writer compressing with the algorithm of `spec` at its level, using up to its number of
+    /// threads when the codec supports it.
     pub(crate) fn get_writer<'w, R: Write + 'w>(
-        tar_compression: &ArchiveCompression,
+        spec: &CompressionSpec,
         writer: R,
     ) -> Result<Box<dyn FinishableWrite + 'w>, ArchiveError> {
-        let writer: Box<dyn FinishableWrite + 'w> = match tar_compression {
+        let level = spec.algorithm.checked_level(spec.level)?;
+
+        let writer: Box<dyn FinishableWrite + 'w> = match &spec.algorithm {
             ArchiveCompression::None => Box::new(NoOpFinishableWrite(writer)),
-            ArchiveCompression::Gzip => Box::new(flate2::write::GzEncoder::new(
-                writer,
-                flate2::Compression::default(),
-            )),
+            ArchiveCompression::Gzip => {
+                let level = level.map_or_else(flate2::Compression::default, |l| {
+                    flate2::Compression::new(l as u32)
+                });
+                if let Some(strategy) = spec.extra.gzip_strategy {
+                    return Ok(Box::new(StrategyGzEncoder::new(
+                        writer,
+                        level.level(),
+                        strategy,
+                    )));
+                }
+
+                #[cfg(feature = "multithreading")]
+                {
+                    let threads = spec.threads();
+                    // a single thread keeps the history across the whole stream
+                    if threads > 1 {
+                        return Ok(Box::new(ParallelGzEncoder::new(writer, level, threads)));
+                    }
+                }
+                Box::new(flate2::write::GzEncoder::new(writer, level))
+            }
             #[cfg(feature = "deflate_codecs")]
             ArchiveCompression::Deflate => Box::new(flate2::write::ZlibEncoder::new(
                 writer,
-                flate2::Compression::default(),
+                level.map_or_else(flate2::Compression::default, |l| {
+                    flate2::Compression::new(l as u32)
+                }),
             )),
             #[cfg(feature = "bzip2_codecs")]
-            ArchiveCompression::Bzip2 => Box::new(bzip2::write::BzEncoder::new(
-                writer,
-                bzip2::Compression::default(),
-            )),
+            ArchiveCompression::Bzip2 => {
+                let level = level.map_or_else(bzip2::Compression::default, |l| {
+                    bzip2::Compression::new(l as u32)
+                });
+                match spec.extra.bzip2_work_factor {
+                    Some(work_factor @ 1..=250) => {
+                        Box::new(BzWorkFactorEncoder::new(writer, level, work_factor))
+                    }
+                    Some(work_factor) => {
+                        return Err(ArchiveError::Io(Error::new(
+                            std::io::ErrorKind::InvalidInput,
+                            format!(
+                                "bzip2 work factor must be between 1 and 250 but was {}",
+                                work_factor
+                            ),
+                        )))
+                    }
+                    None => Box::new(bzip2::write::BzEncoder::new(writer, level)),
+                }
+            }
             #[cfg(feature = "lzma_codecs")]
-            ArchiveCompression::Lzma => Box::new(LzmaWriter::new_compressor(writer, 6)?),
+            ArchiveCompression::Lzma => {
+                let preset = level.unwrap_or(6) as u32;
+                let stream = match spec.threads() {
+                    #[cfg(feature = "multithreading")]
+                    threads if threads > 1 => liblzma::stream::MtStreamBuilder::new()
+                        .threads(threads as u32)
+                        .preset(preset)
+                        .check(Check::Crc64)
+                        .encoder()?,
+                    _ => Stream::new_easy_encoder(preset, Check::Crc64)?,
+                };
+                Box::new(liblzma::write::XzEncoder::new_stream(writer, stream))
+            }
+            // only decompressing is supported
+            #[cfg(feature = "lzw_codecs")]
+            ArchiveCompression::Compress => {
+                return Err(ArchiveError::UnsupportedCompression(
+                    ArchiveCompression::Compress,
+                ))
+            }
             #[cfg(feature = "zstd_codecs")]
             ArchiveCompression::Zstd => {
-                let mut enc = zstd::Encoder::new(writer, 0)?;
+                // 0 is the default level of zstd
+                let mut enc = zstd::Encoder::new(writer, level.unwrap_or(0))?;
+                if let Some(window_log) = spec.extra.zstd_long {
+                    enc.long_distance_matching(true)?;
+                    enc.window_log(window_log)?;
+                }
 
                 #[cfg(feature = "multithreading")]
                 {
-                    _ = enc.multithread(
-                        std::thread::available_parallelism().map_or(1, |n| n.get() as u32),
-                    );
+                    let threads = spec.threads();
+                    // a single thread compresses on the calling one, without workers
+                    if threads > 1 {
+                        _ = enc.multithread(threads as u32);
+                    }
                 }
                 Box::new(enc)
             }
@@ -104,9 +251,8 @@ impl ArchiveCodec {
     }
 }
 
-#[derive(
-    Debug, Clone, PartialEq, EnumIter, serde::Serialize, serde::Deserialize, clap::ValueEnum,
-)]
+#[derive(Debug, Clone, Default, PartialEq, EnumIter, serde::Serialize, serde::Deserialize)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
 #[serde(rename_all = "lowercase")]
 pub enum ArchiveCompression {
     Gzip,
@@ -114,6 +260,10 @@ pub enum ArchiveCompression {
     Bzip2,
     #[cfg(feature = "lzma_codecs")]
     Lzma,
+    /// LZW of the old unix `compress`, which can only be read.
+    #[cfg(feature = "lzw_codecs")]
+    #[cfg_attr(feature = "cli", clap(skip))]
+    Compress,
     #[cfg(feature = "zstd_codecs")]
     Zstd,
     #[cfg(feature = "aes_codecs")]
@@ -121,8 +271,9 @@ pub enum ArchiveCompression {
     #[cfg(feature = "deflate_codecs")]
     Deflate,
     // skip value enum
-    #[clap(skip)]
+    #[cfg_attr(feature = "cli", clap(skip))]
     Unknown(String),
+    #[default]
     None,
 }
 
@@ -130,10 +281,13 @@ impl ArchiveCompression {
     pub fn valid_level_range(&self) -> Option<std::ops::RangeInclusive<i32>> {
         match self {
             ArchiveCompression::Gzip => Some(0..=9),
+            // the size of the blocks, in hundreds of kB
             #[cfg(feature = "bzip2_codecs")]
-            ArchiveCompression::Bzip2 => Some(0..=9),
+            ArchiveCompression::Bzip2 => Some(1..=9),
             #[cfg(feature = "lzma_codecs")]
             ArchiveCompression::Lzma => Some(0..=9),
+            #[cfg(feature = "lzw_codecs")]
+            ArchiveCompression::Compress => None,
             #[cfg(feature = "zstd_codecs")]
             ArchiveCompression::Zstd => Some(1..=19),
             #[cfg(feature = "aes_codecs")]
@@ -144,6 +298,27 @@ impl ArchiveCompression {
             ArchiveCompression::None => None,
         }
     }
+
+    /// `level`, if it is in the [`ArchiveCompression::valid_level_range`]. Codecs without levels
+    /// ignore it.
+    pub(crate) fn checked_level(&self, level: Option<i32>) -> Result<Option<i32>, ArchiveError> {
+        match (level, self.valid_level_range()) {
+            (Some(level), Some(range)) if !range.contains(&level) => {
+                Err(ArchiveError::Io(Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} compression level must be between {} and {} but was {}",
+                        self,
+                        range.start(),
+                        range.end(),
+                        level
+                    ),
+                )))
+            }
+            (level, Some(_)) => Ok(level),
+            (_, None) => Ok(None),
+        }
+    }
 }
 
 #[cfg(feature = "sevenz_archive")]
@@ -173,6 +348,8 @@ impl std::fmt::Display for ArchiveCompression {
             ArchiveCompression::Bzip2 => write!(f, "bzip2"),
             #[cfg(feature = "lzma_codecs")]
             ArchiveCompression::Lzma => write!(f, "lzma"),
+            #[cfg(feature = "lzw_codecs")]
+            ArchiveCompression::Compress => write!(f, "compress"),
             #[cfg(feature = "zstd_codecs")]
             ArchiveCompression::Zstd => write!(f, "zstd"),
             #[cfg(feature = "aes_codecs")]
@@ -229,6 +406,29 @@ impl<W: Write> FinishableWrite for flate2::write::GzEncoder<W> {
     }
 }
 
+impl<W: Write> FinishableWrite for StrategyGzEncoder<W> {
+    fn finish_writer(&mut self) -> Result<(), FinishError<Error>> {
+        self.try_finish()
+            .map_err(|e| FinishError::new("StrategyGzEncoder", e))
+    }
+}
+
+#[cfg(feature = "bzip2_codecs")]
+impl<W: Write> FinishableWrite for BzWorkFactorEncoder<W> {
+    fn finish_writer(&mut self) -> Result<(), FinishError<Error>> {
+        self.try_finish()
+            .map_err(|e| FinishError::new("BzWorkFactorEncoder", e))
+    }
+}
+
+#[cfg(feature = "multithreading")]
+impl<W: Write> FinishableWrite for ParallelGzEncoder<W> {
+    fn finish_writer(&mut self) -> Result<(), FinishError<Error>> {
+        self.try_finish()
+            .map_err(|e| FinishError::new("ParallelGzEncoder", e))
+    }
+}
+
 impl<W: Write> FinishableWrite for flate2::write::ZlibEncoder<W> {
     fn finish_writer(&mut self) -> Result<(), FinishError<Error>> {
         flate2::write::ZlibEncoder::try_finish(self).map_err(|e| FinishError::new("ZlibEncoder", e))
@@ -236,9 +436,89 @@ impl<W: Write> FinishableWrite for flate2::write::ZlibEncoder<W> {
 }
 
 #[cfg(feature = "lzma_codecs")]
-impl<W: Write> FinishableWrite for LzmaWriter<W> {
+impl<W: Write> FinishableWrite for liblzma::write::XzEncoder<W> {
     fn finish_writer(&mut self) -> Result<(), FinishError<Error>> {
-        Ok(())
+        liblzma::write::XzEncoder::try_finish(self).map_err(|e| FinishError::new("XzEncoder", e))
+    }
+}
+
+#[cfg(feature = "lzma_codecs")]
+const XZ_MAGIC: [u8; 6] = [0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+/// Reader of xz streams, decompressed on `threads` threads, or of the legacy lzma format. The
+/// streams of concatenated xz files are decompressed one after the other.
+#[cfg(feature = "lzma_codecs")]
+pub(crate) struct XzReader<R: BufRead> {
+    decoder: Option<liblzma::bufread::XzDecoder<R>>,
+    /// Input not handed to a decoder yet.
+    inner: Option<R>,
+    threads: usize,
+}
+
+#[cfg(feature = "lzma_codecs")]
+impl<R: BufRead> XzReader<R> {
+    pub(crate) fn new(inner: R, threads: usize) -> Self {
+        Self {
+            decoder: None,
+            inner: Some(inner),
+            threads,
+        }
+    }
+
+    /// Starts decoding the next stream, returning `false` once the input is exhausted.
+    fn next_stream(&mut self) -> Result<bool, Error> {
+        let Some(mut inner) = self
+            .inner
+            .take()
+            .or_else(|| self.decoder.take().map(|d| d.into_inner()))
+        else {
+            return Ok(false);
+        };
+        // streams may be followed by null padding
+        let is_xz = loop {
+            let buf = inner.fill_buf()?;
+            let zeros = buf.iter().take_while(|b| **b == 0).count();
+            if zeros == 0 {
+                if buf.is_empty() {
+                    return Ok(false);
+                }
+                break buf.starts_with(&XZ_MAGIC);
+            }
+            inner.consume(zeros);
+        };
+
+        // the legacy lzma format can only be decompressed on a single thread
+        let stream = match is_xz && self.threads > 1 {
+            #[cfg(feature = "multithreading")]
+            true => liblzma::stream::MtStreamBuilder::new()
+                .threads(self.threads as u32)
+                .memlimit_threading(u64::MAX)
+                .memlimit_stop(u64::MAX)
+                .decoder()?,
+            _ => Stream::new_auto_decoder(u64::MAX, 0)?,
+        };
+        self.decoder = Some(liblzma::bufread::XzDecoder::new_stream(inner, stream));
+        Ok(true)
+    }
+}
+
+#[cfg(feature = "lzma_codecs")]
+impl<R: BufRead> Read for XzReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            if let Some(decoder) = self.decoder.as_mut() {
+                let n = decoder.read(buf)?;
+                if n > 0 {
+                    return Ok(n);
+                }
+            }
+            if !self.next_stream()? {
+                return Ok(0);
+            }
+        }
     }
 }
 
@@ -268,6 +548,31 @@ impl<W: Write> FinishableWrite for NoOpFinishableWrite<W> {
     }
 }
 
+/// Counts the bytes written, to know the offsets of the entries or the size of an archive written
+/// to a stream.
+pub(crate) struct CountingWriter<W> {
+    inner: W,
+    pub(crate) written: u64,
+}
+
+impl<W> CountingWriter<W> {
+    pub(crate) fn new(inner: W) -> Self {
+        Self { inner, written: 0 }
+    }
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
 impl AsRef<ArchiveCompression> for ArchiveCompression {
     fn as_ref(&self) -> &ArchiveCompression {
         self
@@ -275,16 +580,24 @@ impl AsRef<ArchiveCompression> for ArchiveCompression {
 }
 
 #[cfg(test)]
+#[allow(clippy::unwrap_used)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_archive_compression_to_string() {
         assert_eq!(ArchiveCompression::Gzip.to_string(), "gzip");
+        #[cfg(feature = "bzip2_codecs")]
         assert_eq!(ArchiveCompression::Bzip2.to_string(), "bzip2");
+        #[cfg(feature = "lzma_codecs")]
         assert_eq!(ArchiveCompression::Lzma.to_string(), "lzma");
+        #[cfg(feature = "lzw_codecs")]
+        assert_eq!(ArchiveCompression::Compress.to_string(), "compress");
+        #[cfg(feature = "zstd_codecs")]
         assert_eq!(ArchiveCompression::Zstd.to_string(), "zstd");
+        #[cfg(feature = "aes_codecs")]
         assert_eq!(ArchiveCompression::Aes.to_string(), "aes");
+        #[cfg(feature = "deflate_codecs")]
         assert_eq!(ArchiveCompression::Deflate.to_string(), "deflate");
         assert_eq!(ArchiveCompression::None.to_string(), "none");
         assert_eq!(
@@ -292,4 +605,175 @@ mod tests {
             "unknown (foo)"
         );
     }
+
+    fn compress(spec: CompressionSpec, data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut writer = ArchiveCodec::get_writer(&spec, &mut out).unwrap();
+        writer.write_all(data).unwrap();
+        writer.finish_writer().unwrap();
+        drop(writer);
+        out
+    }
+
+    fn numbers(count: u64) -> Vec<u8> {
+        (0..count)
+            .flat_map(|i| (i * 7919 % 1000).to_string().into_bytes())
+            .collect()
+    }
+
+    #[cfg(feature = "zstd_codecs")]
+    #[test]
+    fn zstd_writer_uses_threads() {
+        for threads in [Some(1), Some(4), None] {
+            let spec = CompressionSpec {
+                threads,
+                ..CompressionSpec::new(ArchiveCompression::Zstd)
+            };
+            let out = compress(spec, &b"threads".repeat(1000));
+            assert_eq!(
+                zstd::decode_all(out.as_slice()).unwrap(),
+                b"threads".repeat(1000)
+            );
+        }
+    }
+
+    #[cfg(feature = "deflate_codecs")]
+    #[test]
+    fn gzip_writer_uses_level() {
+        let data = numbers(20000);
+        let level = |level| CompressionSpec {
+            level: Some(level),
+            ..CompressionSpec::new(ArchiveCompression::Gzip)
+        };
+        let fast = compress(level(1), &data);
+        let best = compress(level(9), &data);
+        assert!(best.len() < fast.len());
+
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(best.as_slice())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[cfg(feature = "lzma_codecs")]
+    #[test]
+    fn xz_round_trips_on_threads() {
+        // several blocks of 1 MiB at level 1, so that they are spread over the threads
+        let data = numbers(1_000_000);
+        for threads in [1, 4] {
+            let spec = CompressionSpec {
+                level: Some(1),
+                threads: Some(threads),
+                ..CompressionSpec::new(ArchiveCompression::Lzma)
+            };
+            let out = compress(spec, &data);
+            assert!(out.starts_with(&XZ_MAGIC));
+            for threads in [1, 4] {
+                let mut decoded = Vec::new();
+                XzReader::new(out.as_slice(), threads)
+                    .read_to_end(&mut decoded)
+                    .unwrap();
+                assert_eq!(decoded, data);
+            }
+        }
+    }
+
+    #[cfg(feature = "lzma_codecs")]
+    #[test]
+    fn xz_reads_concatenated_and_legacy_streams() {
+        let xz = CompressionSpec::new(ArchiveCompression::Lzma);
+        let mut concatenated = compress(xz.clone(), b"one ");
+        concatenated.extend([0; 4]);
+        concatenated.extend(compress(xz, b"two"));
+        for threads in [1, 4] {
+            let mut decoded = String::new();
+            XzReader::new(concatenated.as_slice(), threads)
+                .read_to_string(&mut decoded)
+                .unwrap();
+            assert_eq!(decoded, "one two");
+        }
+
+        let legacy = std::fs::read("tests/fixtures/test1.tar.lzma").unwrap();
+        let mut decoded = Vec::new();
+        XzReader::new(legacy.as_slice(), 4)
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded.len(), 5632);
+        assert!(decoded.starts_with(b"test1/dir1/"));
+    }
+
+    #[test]
+    fn applies_codec_params() {
+        let data = numbers(20000);
+        let gzip = CompressionSpec {
+            extra: CodecParams {
+                gzip_strategy: Some(GzipStrategy::Rle),
+                ..Default::default()
+            },
+            ..CompressionSpec::new(ArchiveCompression::Gzip)
+        };
+        let mut decoded = Vec::new();
+        flate2::read::GzDecoder::new(compress(gzip, &data).as_slice())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+
+        #[cfg(feature = "zstd_codecs")]
+        {
+            // a window larger than the default limit of decoders
+            let zstd = CompressionSpec {
+                extra: CodecParams {
+                    zstd_long: Some(30),
+                    ..Default::default()
+                },
+                ..CompressionSpec::new(ArchiveCompression::Zstd)
+            };
+            let out = compress(zstd, &data);
+            assert!(zstd::decode_all(out.as_slice()).is_err());
+            let mut decoded = Vec::new();
+            ArchiveCodec::get_reader(std::io::Cursor::new(out), &ArchiveCompression::Zstd)
+                .unwrap()
+                .read_to_end(&mut decoded)
+                .unwrap();
+            assert_eq!(decoded, data);
+        }
+
+        #[cfg(feature = "bzip2_codecs")]
+        {
+            let bzip2 = |work_factor| CompressionSpec {
+                extra: CodecParams {
+                    bzip2_work_factor: Some(work_factor),
+                    ..Default::default()
+                },
+                ..CompressionSpec::new(ArchiveCompression::Bzip2)
+            };
+            let mut decoded = Vec::new();
+            bzip2::read::BzDecoder::new(compress(bzip2(100), &data).as_slice())
+                .read_to_end(&mut decoded)
+                .unwrap();
+            assert_eq!(decoded, data);
+            assert!(ArchiveCodec::get_writer(&bzip2(251), Vec::new()).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_levels_out_of_range() {
+        let level = |algorithm, level| CompressionSpec {
+            level: Some(level),
+            ..CompressionSpec::new(algorithm)
+        };
+        #[cfg(feature = "deflate_codecs")]
+        assert!(
+            ArchiveCodec::get_writer(&level(ArchiveCompression::Gzip, 10), Vec::new()).is_err()
+        );
+        #[cfg(feature = "zstd_codecs")]
+        assert!(
+            ArchiveCodec::get_writer(&level(ArchiveCompression::Zstd, 23), Vec::new()).is_err()
+        );
+        assert_eq!(
+            ArchiveCompression::None.checked_level(Some(42)).unwrap(),
+            None
+        );
+    }
 }
@@ -0,0 +1,335 @@
+// files compressed on their own, such as `notes.txt.gz` or `dump.json.zst`, handled as archives
+// holding that single file. The compressed streams that hold a tar, cpio or web archive are
+// detected as such first.
+
+use std::{
+    cell::OnceCell,
+    fs::File,
+    io::{BufReader, ErrorKind, Read, Write},
+    ops::ControlFlow,
+    path::Path,
+};
+
+use byte_unit::{Byte, UnitType};
+use chrono::{DateTime, FixedOffset};
+
+use crate::archive::{
+    codecs::{ArchiveCodec, ArchiveCompression, CountingWriter},
+    datetime_from_timestamp,
+    entry_visitor::{
+        extract_entries, find_first_entry, list_entries, open_entry, scan_entries, EntryVisitor,
+        VisitOptions, VisitedEntry,
+    },
+    ArchiveError, ArchiveEvent, ArchiveFileEntity, ArchiveFileEntityType, ArchiveMetadata,
+    ArchiveScan, ArchiveType, Archived, CreateOptions, CreateResult, DataSource, EventHandler,
+    ExtractOptions, ExtractReport, Lengthed, ListOptions, OpenOptions, DEFAULT_BUF_SIZE,
+};
+
+use super::edit::EntryEdit;
+
+/// Name of the file when neither the stream nor the source tell it.
+const DEFAULT_NAME: &str = "data";
+
+/// Compresses the single file of `options` to `writer`, front to back, so that it can be a pipe
+/// such as stdout.
+pub fn create_compressed_stream<W: Write>(
+    options: CreateOptions,
+    writer: W,
+) -> Result<CreateResult, ArchiveError> {
+    let compression = options
+        .compression
+        .clone()
+        .ok_or(ArchiveError::CompressionMethodRequired)?;
+    let files = options
+        .files
+        .iter()
+        .filter(|f| f.is_file())
+        .collect::<Vec<_>>();
+    let [path] = files[..] else {
+        return Err(ArchiveError::Io(std::io::Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "a compressed file holds a single file, {} were given",
+                files.len()
+            ),
+        )));
+    };
+
+    let mut counted = CountingWriter::new(writer);
+    let mut writer = ArchiveCodec::get_writer(&compression, &mut counted)?;
+    let mut reader = BufReader::with_capacity(DEFAULT_BUF_SIZE, File::open(path)?);
+    let total_size = std::io::copy(&mut reader, &mut writer)?;
+    writer.finish_writer()?;
+    drop(writer);
+    counted.flush()?;
+    options.handle(ArchiveEvent::Added(
+        options.entry_name(path).to_string_lossy().to_string(),
+        Some(total_size),
+    ));
+
+    let size = counted.written;
+    eprintln!(
+        "Done creating compressed file: {} ({})",
+        options.destination.display(),
+        Byte::from(size).get_appropriate_unit(UnitType::Both)
+    );
+    options.handle(ArchiveEvent::DoneCreating(
+        options.destination.to_string_lossy().to_string(),
+    ));
+
+    Ok(CreateResult {
+        path: options.destination,
+        total_size,
+        compressed_size: size,
+        unreadable: Vec::new(),
+    })
+}
+
+pub struct CompressedFile {
+    pub(crate) source: DataSource,
+    scan: OnceCell<ArchiveScan>,
+}
+
+impl CompressedFile {
+    fn scan(&self) -> Result<&ArchiveScan, ArchiveError> {
+        ArchiveScan::cached(&self.scan, || scan_entries(self, None, None))
+    }
+
+    fn compression(&self) -> Result<ArchiveCompression, ArchiveError> {
+        ArchiveType::compression_of(self.source.clone())
+    }
+
+    /// Name and modification time of the file, from the gzip header if it has them, else from
+    /// the name of the compressed file without its extension.
+    fn origin(&self, compression: &ArchiveCompression) -> (String, Option<DateTime<FixedOffset>>) {
+        let (mut name, mut modified) = (None, None);
+        if *compression == ArchiveCompression::Gzip {
+            let decoder = flate2::read::GzDecoder::new(self.source.clone());
+            if let Some(header) = decoder.header() {
+                name = header
+                    .filename()
+                    .map(|n| String::from_utf8_lossy(n).to_string())
+                    // only the name is meant to be stored, but never trust it to be
+                    .and_then(|n| Some(Path::new(&n).file_name()?.to_string_lossy().to_string()));
+                modified = (header.mtime() != 0)
+                    .then(|| datetime_from_timestamp(header.mtime() as i64).ok())
+                    .flatten();
+            }
+        }
+
+        let name = name.or_else(|| match &self.source {
+            DataSource::File(_, path) => Path::new(path)
+                .file_stem()
+                .map(|n| n.to_string_lossy().to_string()),
+            // `data.gz.001` holds `data`
+            DataSource::Volumes(v) => v
+                .path()
+                .with_extension("")
+                .file_stem()
+                .map(|n| n.to_string_lossy().to_string()),
+            DataSource::Memory(_) | DataSource::Reader(_) | DataSource::Pipe(_) => None,
+        });
+        (
+            name.unwrap_or_else(|| DEFAULT_NAME.to_string()),
+            modified.or_else(|| self.source.modified()),
+        )
+    }
+}
+
+impl EntryVisitor for CompressedFile {
+    fn for_each_entry<F>(
+        &self,
+        options: &VisitOptions<'_>,
+        mut visit: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        let compression = self.compression()?;
+        let (name, last_modified) = self.origin(&compression);
+        let source = options
+            .retry
+            .wrap(self.source.clone(), &self.source, options.events);
+        let mut reader = ArchiveCodec::get_reader(source, &compression)?;
+
+        // the size is only known once the stream is decompressed
+        let size = match options.contents {
+            true => None,
+            false => Some(std::io::copy(&mut reader, &mut std::io::sink())?),
+        };
+        let visited = VisitedEntry::new(ArchiveFileEntity {
+            name,
+            size,
+            compressed_size: self.source.len().ok(),
+            last_modified,
+            compression: Some(compression.to_string()),
+            fstype: ArchiveFileEntityType::File,
+            raw: None,
+            unix: None,
+        });
+        if !options.wants(&visited.entity) {
+            return Ok(());
+        }
+        let contents: Option<&mut dyn Read> = options.contents.then_some(&mut reader);
+        visit(visited, contents).map(|_| ())
+    }
+}
+
+impl Archived for CompressedFile {
+    fn of(source: DataSource) -> Result<Self, ArchiveError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            source,
+            scan: OnceCell::new(),
+        })
+    }
+
+    fn extract(&self, options: ExtractOptions) -> Result<ExtractReport, ArchiveError> {
+        extract_entries(self, &options, self.source.as_ref())
+    }
+
+    fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
+        if options.is_whole() {
+            return Ok(self.scan()?.entries.clone());
+        }
+        list_entries(self, self.scan.get(), &options)
+    }
+
+    fn create(options: CreateOptions) -> Result<CreateResult, ArchiveError> {
+        let file = File::create(&options.destination).map_err(|e| {
+            ArchiveError::Io(std::io::Error::new(
+                e.kind(),
+                format!("could not create destination file: {}", e),
+            ))
+        })?;
+        create_compressed_stream(options, &file)
+    }
+
+    fn metadata(&self) -> Result<ArchiveMetadata, ArchiveError> {
+        let compression = self.compression()?;
+        let scan = self.scan()?;
+
+        Ok(ArchiveMetadata {
+            archive_type: ArchiveType::CompressedFile,
+            entry_count: scan.entries.len(),
+            entries: scan.entries.clone(),
+            total_size: scan.total_size,
+            compressed_size: scan.compressed_size,
+            compression: Some(compression),
+            created: self.source.created(),
+            modified: self.source.modified(),
+            additional: None,
+        })
+    }
+
+    fn open(&self, options: OpenOptions) -> Result<(), ArchiveError> {
+        open_entry(self, options)
+    }
+
+    fn find_first<P>(
+        &self,
+        predicate: P,
+    ) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+    where
+        P: Fn(&ArchiveFileEntity) -> bool,
+    {
+        find_first_entry(self, predicate)
+    }
+
+    fn check_password(&self, _password: &str) -> Result<bool, ArchiveError> {
+        Ok(true)
+    }
+
+    fn edit(&self, _entry: &str, _edit: &EntryEdit) -> Result<(), ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "edit".to_string(),
+            ArchiveType::CompressedFile,
+        ))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::archive::{Archive, CompressionSpec, SimpleLogger};
+
+    #[cfg(all(
+        feature = "lzma_codecs",
+        feature = "bzip2_codecs",
+        feature = "zstd_codecs"
+    ))]
+    #[test]
+    fn creates_lists_and_extracts() {
+        let dir = std::env::temp_dir().join(format!("hezi-compressed-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = Path::new("tests/fixtures/test1/file1.txt");
+        let content = std::fs::read(source).unwrap();
+
+        for ext in ["gz", "xz", "bz2", "zst"] {
+            let destination = dir.join(format!("file1.txt.{}", ext));
+            let (archive_type, archive_compression) =
+                ArchiveType::guess_from_filename(&destination).unwrap();
+            assert_eq!(archive_type, ArchiveType::CompressedFile);
+            Archive::create(CreateOptions {
+                destination: destination.clone(),
+                source: source.parent().unwrap().to_path_buf(),
+                roots: vec![],
+                files: vec![source.to_path_buf()],
+                password: None,
+                archive_type,
+                compression: archive_compression.clone().map(CompressionSpec::new),
+                overwrite: true,
+                include_hidden: true,
+                skip_unreadable: false,
+                verify_after: false,
+                volume_size: None,
+                update: false,
+                sevenz: Default::default(),
+                zip: Default::default(),
+                tar: Default::default(),
+                event_handler: Box::new(SimpleLogger),
+            })
+            .unwrap();
+
+            let archive = Archive::of(DataSource::file(&destination).unwrap()).unwrap();
+            assert!(matches!(archive, Archive::CompressedFile(_)));
+            let entries = archive.list(ListOptions::default()).unwrap();
+            assert_eq!(entries.len(), 1);
+            assert_eq!(entries[0].name, "file1.txt");
+            assert_eq!(entries[0].size, Some(content.len() as u64));
+            assert_eq!(archive.metadata().unwrap().compression, archive_compression);
+
+            let output = dir.join(ext);
+            archive
+                .extract(ExtractOptions {
+                    destination: output.clone(),
+                    ..Default::default()
+                })
+                .unwrap();
+            assert_eq!(std::fs::read(output.join("file1.txt")).unwrap(), content);
+        }
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn names_files_after_gzip_header() {
+        let mut encoder = flate2::GzBuilder::new()
+            .filename("../report.csv")
+            .mtime(1_700_000_000)
+            .write(Vec::new(), flate2::Compression::default());
+        encoder.write_all(b"a,b\n1,2\n").unwrap();
+        let data = encoder.finish().unwrap();
+
+        let archive = Archive::of(DataSource::memory(data.as_slice())).unwrap();
+        let entries = archive.list(ListOptions::default()).unwrap();
+        assert_eq!(entries[0].name, "report.csv");
+        assert_eq!(entries[0].size, Some(8));
+        assert_eq!(entries[0].last_modified.unwrap().timestamp(), 1_700_000_000);
+    }
+}
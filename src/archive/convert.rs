@@ -0,0 +1,263 @@
+// conversion of archives from one format to another: the entries are read in a single traversal
+// and written to the new archive as they come, without going through the disk.
+
+use std::{
+    fs::File,
+    io::{Error, ErrorKind, Read},
+    ops::ControlFlow,
+    path::PathBuf,
+};
+
+use super::{
+    codecs::CompressionSpec,
+    entry_visitor::{EntryVisitor, VisitOptions, VisitedEntry},
+    password::PasswordProvider,
+    Archive, ArchiveError, ArchiveEvent, ArchiveType, CreateResult, EventHandler, SimpleLogger,
+    SkipReason,
+};
+
+/// Writer of the new archive of [`Archive::convert`].
+pub(crate) trait EntryWriter {
+    /// Writes `entry`, with the content of files read from `reader`, returning whether the
+    /// format could store it.
+    fn write(
+        &mut self,
+        entry: &VisitedEntry,
+        reader: Option<&mut dyn Read>,
+    ) -> Result<bool, ArchiveError>;
+
+    /// Writes what follows the entries, such as the central directory of zip archives.
+    fn finish(self: Box<Self>) -> Result<(), ArchiveError>;
+}
+
+#[derive(Debug)]
+pub struct ConvertOptions<'a> {
+    pub destination: PathBuf,
+    /// Compression of the new archive, or of its entries for zip archives, the default one of
+    /// the format if `None`. Only the level is used for 7z archives.
+    pub compression: Option<CompressionSpec>,
+    /// Password of the archive converted, the new one is not encrypted.
+    pub password: Option<Box<dyn PasswordProvider + 'a>>,
+    pub overwrite: bool,
+    pub event_handler: Box<dyn EventHandler + 'a>,
+}
+
+impl Default for ConvertOptions<'_> {
+    fn default() -> Self {
+        Self {
+            destination: PathBuf::new(),
+            compression: None,
+            password: None,
+            overwrite: false,
+            event_handler: Box::new(SimpleLogger),
+        }
+    }
+}
+
+impl EventHandler for ConvertOptions<'_> {
+    fn handle(&self, event: ArchiveEvent) {
+        self.event_handler.handle(event);
+    }
+}
+
+/// Counts the bytes read through it.
+struct Counted<'r> {
+    reader: &'r mut dyn Read,
+    read: u64,
+}
+
+impl Read for Counted<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.read += n as u64;
+        Ok(n)
+    }
+}
+
+/// Writer of `target` archives to `file`, if the format can be written entry by entry.
+#[allow(unused_variables)]
+fn entry_writer(
+    target: ArchiveType,
+    file: File,
+    compression: Option<&CompressionSpec>,
+) -> Result<Box<dyn EntryWriter>, ArchiveError> {
+    match target {
+        #[cfg(feature = "zip_archive")]
+        ArchiveType::Zip => Ok(Box::new(super::zip_archive::ZipEntryWriter::new(
+            file,
+            compression,
+        )?)),
+        #[cfg(feature = "tar_archive")]
+        ArchiveType::Tar => {
+            let compression = compression.cloned().unwrap_or_default();
+            Ok(Box::new(super::tar_archive::TarEntryWriter::new(
+                &compression,
+                file,
+            )?))
+        }
+        #[cfg(all(feature = "sevenz_archive", feature = "lzma_codecs"))]
+        ArchiveType::SevenZ => Ok(Box::new(super::sevenz_archive::SevenZEntryWriter::new(
+            file,
+            compression.and_then(|c| c.level),
+        )?)),
+        _ => Err(ArchiveError::UnsupportedActionForArchiveType(
+            "convert".to_string(),
+            target,
+        )),
+    }
+}
+
+impl Archive {
+    /// Writes the entries of the archive to a new `target` archive at
+    /// [`ConvertOptions::destination`], decoding them as they are written rather than extracting
+    /// them first. Zip, tar and 7z archives can be written. Entries the target format cannot
+    /// store, such as hard links in zip archives, are skipped with [`SkipReason::UnknownType`].
+    pub fn convert(
+        &self,
+        target: ArchiveType,
+        options: ConvertOptions,
+    ) -> Result<CreateResult, ArchiveError> {
+        let destination = &options.destination;
+        if destination.exists() && !options.overwrite {
+            return Err(ArchiveError::Io(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} already exists", destination.display()),
+            )));
+        }
+        let file = File::create(destination)?;
+        let result = entry_writer(target, file, options.compression.as_ref())
+            .and_then(|writer| self.convert_to(writer, &options));
+        let total_size = match result {
+            Ok(total_size) => total_size,
+            Err(e) => {
+                _ = std::fs::remove_file(destination);
+                return Err(e);
+            }
+        };
+
+        options.handle(ArchiveEvent::DoneCreating(
+            destination.to_string_lossy().to_string(),
+        ));
+        Ok(CreateResult {
+            compressed_size: std::fs::metadata(destination)?.len(),
+            path: options.destination,
+            total_size,
+            unreadable: Vec::new(),
+        })
+    }
+
+    /// Writes the entries to `writer`, returning the size of their content.
+    fn convert_to(
+        &self,
+        mut writer: Box<dyn EntryWriter>,
+        options: &ConvertOptions,
+    ) -> Result<u64, ArchiveError> {
+        let visit_options = VisitOptions {
+            password: options.password.as_deref(),
+            contents: true,
+            events: Some(options),
+            ..Default::default()
+        };
+        let mut total_size = 0;
+        self.for_each_entry(&visit_options, |entry, reader| {
+            let name = entry.entity.name.clone();
+            let (written, size) = match reader {
+                Some(reader) => {
+                    let mut counted = Counted { reader, read: 0 };
+                    let written = writer.write(&entry, Some(&mut counted))?;
+                    (written, Some(counted.read))
+                }
+                None => (writer.write(&entry, None)?, None),
+            };
+            if written {
+                total_size += size.unwrap_or(0);
+                options.handle(ArchiveEvent::Added(name, size));
+            } else {
+                options.handle(ArchiveEvent::Skipped(name, SkipReason::UnknownType));
+            }
+            Ok(ControlFlow::Continue(()))
+        })?;
+        writer.finish()?;
+        Ok(total_size)
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "zip_archive",
+    feature = "deflate_codecs",
+    feature = "tar_archive",
+    feature = "sevenz_archive",
+    feature = "lzma_codecs"
+))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::archive::{codecs::ArchiveCompression, DataSource, ListOptions};
+
+    fn files(archive: &Archive) -> Vec<(String, Vec<u8>)> {
+        let mut files = archive
+            .entries(ListOptions::default())
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let mut content = Vec::new();
+                entry.read_to_end(&mut content).unwrap();
+                (entry.name().trim_end_matches('/').to_string(), content)
+            })
+            .collect::<Vec<_>>();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn converts_between_formats() {
+        let dir = std::env::temp_dir().join(format!("hezi-convert-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let zip = Archive::of(DataSource::file("tests/fixtures/test1.zip").unwrap()).unwrap();
+        let expected = files(&zip);
+
+        for (name, target, compression) in [
+            (
+                "out.tar.gz",
+                ArchiveType::Tar,
+                Some(ArchiveCompression::Gzip),
+            ),
+            ("out.7z", ArchiveType::SevenZ, None),
+            ("out.zip", ArchiveType::Zip, None),
+        ] {
+            let destination = dir.join(name);
+            let result = zip
+                .convert(
+                    target,
+                    ConvertOptions {
+                        destination: destination.clone(),
+                        compression: compression.map(CompressionSpec::new),
+                        overwrite: true,
+                        ..Default::default()
+                    },
+                )
+                .unwrap();
+            assert_eq!(result.total_size, 444 + 1510, "{}", name);
+            assert_eq!(
+                result.compressed_size,
+                std::fs::metadata(&destination).unwrap().len()
+            );
+
+            let converted = Archive::of(DataSource::file(&destination).unwrap()).unwrap();
+            assert_eq!(files(&converted), expected, "{}", name);
+        }
+
+        // an existing archive is kept
+        let refused = zip.convert(
+            ArchiveType::Zip,
+            ConvertOptions {
+                destination: dir.join("out.zip"),
+                ..Default::default()
+            },
+        );
+        assert!(
+            matches!(refused, Err(ArchiveError::Io(e)) if e.kind() == ErrorKind::AlreadyExists)
+        );
+        _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,717 @@
+// cpio archives in the "newc" format of the Linux initramfs, with or without checksums, and in
+// the portable "odc" format of POSIX, compressed like tar archives. Both are read, archives are
+// created in the newc format.
+
+use std::{
+    cell::OnceCell,
+    collections::HashMap,
+    fs::{File, Metadata},
+    io::{BufReader, ErrorKind, Read, Write},
+    ops::ControlFlow,
+    time::UNIX_EPOCH,
+};
+
+use byte_unit::{Byte, UnitType};
+use serde_json::json;
+
+use crate::archive::{
+    codecs::{ArchiveCodec, ArchiveCompression},
+    datetime_from_timestamp,
+    entry_visitor::{
+        extract_entries, find_first_entry, list_entries, open_entry, scan_entries, EntryLink,
+        EntryVisitor, VisitOptions, VisitedEntry,
+    },
+    ArchiveError, ArchiveEvent, ArchiveFileEntity, ArchiveFileEntityType, ArchiveMetadata,
+    ArchiveScan, ArchiveType, Archived, CreateOptions, CreateResult, DataSource, EventHandler,
+    ExtractOptions, ExtractReport, ListOptions, OpenOptions, DEFAULT_BUF_SIZE,
+};
+
+use super::edit::EntryEdit;
+
+/// Name of the entry closing the archive.
+const TRAILER: &str = "TRAILER!!!";
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CpioFormat {
+    /// `070701`, the format of the Linux initramfs.
+    Newc,
+    /// `070702`, newc with a checksum of the file contents.
+    Crc,
+    /// `070707`, the portable format of POSIX.
+    Odc,
+}
+
+impl CpioFormat {
+    pub(crate) fn from_magic(magic: &[u8]) -> Option<Self> {
+        match magic.get(..6)? {
+            b"070701" => Some(CpioFormat::Newc),
+            b"070702" => Some(CpioFormat::Crc),
+            b"070707" => Some(CpioFormat::Odc),
+            _ => None,
+        }
+    }
+
+    /// Size of the header, magic included.
+    fn header_len(self) -> usize {
+        match self {
+            CpioFormat::Newc | CpioFormat::Crc => 110,
+            CpioFormat::Odc => 76,
+        }
+    }
+
+    /// Padding after `len` bytes, newc headers and file contents being aligned on 4 bytes.
+    fn padding(self, len: u64) -> u64 {
+        match self {
+            CpioFormat::Newc | CpioFormat::Crc => (4 - len % 4) % 4,
+            CpioFormat::Odc => 0,
+        }
+    }
+}
+
+impl std::fmt::Display for CpioFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CpioFormat::Newc => write!(f, "newc"),
+            CpioFormat::Crc => write!(f, "crc"),
+            CpioFormat::Odc => write!(f, "odc"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Header {
+    format: CpioFormat,
+    /// Device and inode, identifying the hard links to a same file.
+    file_id: (u64, u64),
+    mode: u32,
+    /// User and group ids.
+    owner: (u64, u64),
+    nlink: u64,
+    mtime: u64,
+    size: u64,
+    name: String,
+}
+
+impl Header {
+    /// Reads the next header, or `None` at the trailer.
+    fn read<R: Read>(reader: &mut R) -> Result<Option<Self>, ArchiveError> {
+        let mut magic = [0; 6];
+        reader.read_exact(&mut magic)?;
+        let format = CpioFormat::from_magic(&magic)
+            .ok_or_else(|| invalid_data(format!("invalid cpio header magic: {:02x?}", magic)))?;
+
+        let mut fields = vec![0; format.header_len() - magic.len()];
+        reader.read_exact(&mut fields)?;
+        let (file_id, mode, owner, nlink, mtime, namesize, size) = match format {
+            CpioFormat::Newc | CpioFormat::Crc => {
+                let field = |i: usize| parse_field(&fields[i * 8..(i + 1) * 8], 16);
+                // ino, mode, uid, gid, nlink, mtime, filesize, devmajor, devminor, rdevmajor,
+                // rdevminor, namesize, check
+                let dev = (field(7)? << 32) | field(8)?;
+                (
+                    (dev, field(0)?),
+                    field(1)?,
+                    (field(2)?, field(3)?),
+                    field(4)?,
+                    field(5)?,
+                    field(11)?,
+                    field(6)?,
+                )
+            }
+            CpioFormat::Odc => {
+                let field = |start: usize, len: usize| parse_field(&fields[start..start + len], 8);
+                // dev, ino, mode, uid, gid, nlink, rdev, mtime, namesize, filesize
+                (
+                    (field(0, 6)?, field(6, 6)?),
+                    field(12, 6)?,
+                    (field(18, 6)?, field(24, 6)?),
+                    field(30, 6)?,
+                    field(42, 11)?,
+                    field(53, 6)?,
+                    field(59, 11)?,
+                )
+            }
+        };
+
+        let mut name = vec![0; namesize as usize];
+        reader.read_exact(&mut name)?;
+        skip(
+            reader,
+            format.padding(format.header_len() as u64 + namesize),
+        )?;
+        // the name is terminated by a NUL byte
+        let name =
+            String::from_utf8_lossy(name.split(|b| *b == 0).next().unwrap_or_default()).to_string();
+        if name == TRAILER {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            format,
+            file_id,
+            mode: mode as u32,
+            owner,
+            nlink,
+            mtime,
+            size,
+            name,
+        }))
+    }
+
+    /// Writes the header in the newc format, without checksum.
+    fn write<W: Write>(&self, writer: &mut W) -> Result<(), ArchiveError> {
+        if self.size > u32::MAX as u64 {
+            return Err(ArchiveError::Io(std::io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} is too large for a cpio archive", self.name),
+            )));
+        }
+
+        let namesize = self.name.len() as u64 + 1;
+        write!(
+            writer,
+            "070701{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}{:08x}",
+            self.file_id.1,
+            self.mode,
+            self.owner.0,
+            self.owner.1,
+            self.nlink,
+            self.mtime.min(u32::MAX as u64),
+            self.size,
+            self.file_id.0 >> 32,
+            self.file_id.0 & 0xffff_ffff,
+            0,
+            0,
+            namesize,
+            0
+        )?;
+        writer.write_all(self.name.as_bytes())?;
+        writer.write_all(&[0])?;
+        let padding = self
+            .format
+            .padding(self.format.header_len() as u64 + namesize);
+        writer.write_all(&[0; 3][..padding as usize])?;
+        Ok(())
+    }
+
+    fn fstype(&self) -> ArchiveFileEntityType {
+        match self.mode & S_IFMT {
+            S_IFREG => ArchiveFileEntityType::File,
+            S_IFDIR => ArchiveFileEntityType::Directory,
+            S_IFLNK => ArchiveFileEntityType::SymbolicLink,
+            _ => ArchiveFileEntityType::Unknown,
+        }
+    }
+
+    fn entry(&self, compression: &ArchiveCompression, size: u64) -> VisitedEntry {
+        let fstype = self.fstype();
+        let mut name = self.name.clone();
+        if fstype == ArchiveFileEntityType::Directory && !name.ends_with('/') {
+            name.push('/');
+        }
+        let size = (fstype == ArchiveFileEntityType::File).then_some(size);
+
+        VisitedEntry {
+            mode: Some(self.mode & !S_IFMT),
+            ..VisitedEntry::new(ArchiveFileEntity {
+                name,
+                size,
+                // the compressed size of an entry is unknown once the whole archive is compressed
+                compressed_size: size.filter(|_| *compression == ArchiveCompression::None),
+                last_modified: datetime_from_timestamp(self.mtime as i64).ok(),
+                compression: Some(compression.to_string()),
+                fstype,
+                raw: None,
+                unix: None,
+            })
+        }
+    }
+}
+
+fn invalid_data(message: String) -> ArchiveError {
+    ArchiveError::Io(std::io::Error::new(ErrorKind::InvalidData, message))
+}
+
+fn parse_field(field: &[u8], radix: u32) -> Result<u64, ArchiveError> {
+    std::str::from_utf8(field)
+        .ok()
+        .and_then(|s| u64::from_str_radix(s, radix).ok())
+        .ok_or_else(|| {
+            invalid_data(format!(
+                "invalid cpio header field: {}",
+                String::from_utf8_lossy(field)
+            ))
+        })
+}
+
+fn skip<R: Read>(reader: &mut R, len: u64) -> Result<(), ArchiveError> {
+    let skipped = std::io::copy(&mut reader.take(len), &mut std::io::sink())?;
+    if skipped < len {
+        return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into());
+    }
+    Ok(())
+}
+
+pub struct CpioArchive {
+    pub(crate) source: DataSource,
+    scan: OnceCell<ArchiveScan>,
+}
+
+impl CpioArchive {
+    fn scan(&self) -> Result<&ArchiveScan, ArchiveError> {
+        ArchiveScan::cached(&self.scan, || scan_entries(self, None, None))
+    }
+
+    fn compression(&self) -> Result<ArchiveCompression, ArchiveError> {
+        ArchiveType::compression_of(self.source.clone())
+    }
+}
+
+impl EntryVisitor for CpioArchive {
+    fn for_each_entry<F>(
+        &self,
+        options: &VisitOptions<'_>,
+        mut visit: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        let compression = self.compression()?;
+        let source = options
+            .retry
+            .wrap(self.source.clone(), &self.source, options.events);
+        let mut reader = ArchiveCodec::get_reader(source, &compression)?;
+
+        // newc stores the contents of hard linked files with the last of their links, the
+        // previous ones are visited once it is found, as hard links to it
+        let mut pending_links = HashMap::<(u64, u64), Vec<Header>>::new();
+        while let Some(header) = Header::read(&mut reader)? {
+            let is_file = header.fstype() == ArchiveFileEntityType::File;
+            let linked = is_file && header.nlink > 1 && header.format != CpioFormat::Odc;
+            if linked && header.size == 0 {
+                pending_links
+                    .entry(header.file_id)
+                    .or_default()
+                    .push(header);
+                continue;
+            }
+
+            let visited = header.entry(&compression, header.size);
+            let mut contents = (&mut reader).take(header.size);
+            let flow = if !options.wants(&visited.entity) {
+                ControlFlow::Continue(())
+            } else if visited.entity.fstype == ArchiveFileEntityType::SymbolicLink {
+                let mut target = String::new();
+                contents.read_to_string(&mut target)?;
+                visit(
+                    VisitedEntry {
+                        link: Some(EntryLink::Symbolic(target)),
+                        ..visited
+                    },
+                    None,
+                )?
+            } else if options.contents && is_file {
+                visit(visited, Some(&mut contents))?
+            } else {
+                visit(visited, None)?
+            };
+            // the visitor may leave contents unread
+            std::io::copy(&mut contents, &mut std::io::sink())?;
+            skip(&mut reader, header.format.padding(header.size))?;
+            if flow.is_break() {
+                return Ok(());
+            }
+
+            let links = match linked {
+                true => pending_links.remove(&header.file_id).unwrap_or_default(),
+                false => Vec::new(),
+            };
+            for link in links {
+                let linked = link.entry(&compression, header.size);
+                if !options.wants(&linked.entity) {
+                    continue;
+                }
+                let flow = visit(
+                    VisitedEntry {
+                        link: Some(EntryLink::Hard(header.name.clone())),
+                        ..linked
+                    },
+                    None,
+                )?;
+                if flow.is_break() {
+                    return Ok(());
+                }
+            }
+        }
+        if options.drain {
+            std::io::copy(&mut reader, &mut std::io::sink())?;
+        }
+
+        // links whose contents never came are empty files
+        let mut empty = pending_links.into_values().flatten().collect::<Vec<_>>();
+        empty.sort_by(|a, b| a.name.cmp(&b.name));
+        for header in empty {
+            let visited = header.entry(&compression, 0);
+            if !options.wants(&visited.entity) {
+                continue;
+            }
+            let contents: &mut dyn Read = &mut std::io::empty();
+            let flow = visit(visited, options.contents.then_some(contents))?;
+            if flow.is_break() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Mode and owner stored for a file, with the type bits of its `metadata`.
+fn mode_and_owner(metadata: &Metadata) -> (u32, (u64, u64)) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        (
+            metadata.mode(),
+            (metadata.uid() as u64, metadata.gid() as u64),
+        )
+    }
+    #[cfg(not(unix))]
+    {
+        let mode = if metadata.is_dir() {
+            S_IFDIR | 0o755
+        } else {
+            S_IFREG | 0o644
+        };
+        (mode, (0, 0))
+    }
+}
+
+impl Archived for CpioArchive {
+    fn of(source: DataSource) -> Result<Self, ArchiveError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            source,
+            scan: OnceCell::new(),
+        })
+    }
+
+    fn extract(&self, options: ExtractOptions) -> Result<ExtractReport, ArchiveError> {
+        extract_entries(self, &options, self.source.as_ref())
+    }
+
+    fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
+        if options.is_whole() {
+            return Ok(self.scan()?.entries.clone());
+        }
+        list_entries(self, self.scan.get(), &options)
+    }
+
+    fn create(options: CreateOptions) -> Result<CreateResult, ArchiveError> {
+        let compression = options
+            .compression
+            .clone()
+            .ok_or(ArchiveError::CompressionMethodRequired)?;
+
+        let file = File::create(&options.destination).map_err(|e| {
+            ArchiveError::Io(std::io::Error::new(
+                e.kind(),
+                format!("could not create destination file: {}", e),
+            ))
+        })?;
+        let mut writer = ArchiveCodec::get_writer(&compression, &file)?;
+
+        let mut total_size = 0;
+        for (ino, path) in (1..).zip(&options.files) {
+            let metadata = std::fs::metadata(path).map_err(|e| {
+                ArchiveError::Io(std::io::Error::new(
+                    e.kind(),
+                    format!(
+                        "could not read file metadata for '{}': {}",
+                        path.display(),
+                        e
+                    ),
+                ))
+            })?;
+            let mut name = options
+                .entry_name(path)
+                .to_string_lossy()
+                .replace('\\', "/");
+            if metadata.is_dir() && name.is_empty() {
+                name.push('.');
+            }
+
+            let size = if metadata.is_file() {
+                metadata.len()
+            } else {
+                0
+            };
+            let (mode, owner) = mode_and_owner(&metadata);
+            let mtime = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_secs());
+            let header = Header {
+                format: CpioFormat::Newc,
+                file_id: (0, ino),
+                mode,
+                owner,
+                nlink: if metadata.is_dir() { 2 } else { 1 },
+                mtime,
+                size,
+                name,
+            };
+            header.write(&mut writer)?;
+
+            if metadata.is_file() {
+                let reader = BufReader::with_capacity(DEFAULT_BUF_SIZE, File::open(path)?);
+                let copied = std::io::copy(&mut reader.take(size), &mut writer)?;
+                if copied < size {
+                    return Err(ArchiveError::Io(std::io::Error::new(
+                        ErrorKind::UnexpectedEof,
+                        format!("{} got shorter while it was read", path.display()),
+                    )));
+                }
+                writer.write_all(&[0; 3][..CpioFormat::Newc.padding(size) as usize])?;
+                total_size += size;
+            }
+            options.handle(ArchiveEvent::Added(
+                header.name,
+                metadata.is_file().then_some(size),
+            ));
+        }
+        let trailer = Header {
+            format: CpioFormat::Newc,
+            file_id: (0, 0),
+            mode: 0,
+            owner: (0, 0),
+            nlink: 1,
+            mtime: 0,
+            size: 0,
+            name: TRAILER.to_string(),
+        };
+        trailer.write(&mut writer)?;
+        writer.finish_writer()?;
+
+        let size = file.metadata()?.len();
+        eprintln!(
+            "Done creating cpio archive: {} ({})",
+            options.destination.display(),
+            Byte::from(size).get_appropriate_unit(UnitType::Both)
+        );
+        options.handle(ArchiveEvent::DoneCreating(
+            options.destination.to_string_lossy().to_string(),
+        ));
+
+        Ok(CreateResult {
+            path: options.destination,
+            total_size,
+            compressed_size: size,
+            unreadable: Vec::new(),
+        })
+    }
+
+    fn metadata(&self) -> Result<ArchiveMetadata, ArchiveError> {
+        let compression = self.compression()?;
+        let mut magic = [0; 6];
+        ArchiveCodec::get_reader(self.source.clone(), &compression)?.read_exact(&mut magic)?;
+        let additional = CpioFormat::from_magic(&magic).map(|f| json!({ "format": f.to_string() }));
+
+        let scan = self.scan()?;
+
+        Ok(ArchiveMetadata {
+            archive_type: ArchiveType::Cpio,
+            entry_count: scan.entries.len(),
+            entries: scan.entries.clone(),
+            total_size: scan.total_size,
+            compressed_size: scan.compressed_size,
+            compression: Some(compression),
+            created: self.source.created(),
+            modified: self.source.modified(),
+            additional,
+        })
+    }
+
+    fn open(&self, options: OpenOptions) -> Result<(), ArchiveError> {
+        open_entry(self, options)
+    }
+
+    fn find_first<P>(
+        &self,
+        predicate: P,
+    ) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+    where
+        P: Fn(&ArchiveFileEntity) -> bool,
+    {
+        find_first_entry(self, predicate)
+    }
+
+    fn check_password(&self, _password: &str) -> Result<bool, ArchiveError> {
+        // cpio has no encryption
+        Ok(true)
+    }
+
+    fn edit(&self, _entry: &str, _edit: &EntryEdit) -> Result<(), ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "edit".to_string(),
+            ArchiveType::Cpio,
+        ))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::archive::{Archive, CompressionSpec, SimpleLogger};
+
+    fn names(archive: &Archive) -> Vec<(String, Option<u64>)> {
+        archive
+            .list(ListOptions::default())
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.name, e.size))
+            .collect()
+    }
+
+    #[test]
+    fn reads_newc_and_odc() {
+        let expected = vec![
+            ("test1/".to_string(), None),
+            ("test1/dir1/".to_string(), None),
+            ("test1/file1.txt".to_string(), Some(1469)),
+            ("test1/dir1/file2.txt".to_string(), Some(444)),
+        ];
+        for (path, compression, format) in [
+            (
+                "tests/fixtures/test1.cpio.gz",
+                ArchiveCompression::Gzip,
+                "newc",
+            ),
+            ("tests/fixtures/test1.cpio", ArchiveCompression::None, "odc"),
+        ] {
+            let archive = Archive::of(DataSource::file(path).unwrap()).unwrap();
+            assert!(matches!(archive, Archive::Cpio(_)));
+            assert_eq!(names(&archive), expected);
+
+            let metadata = archive.metadata().unwrap();
+            assert_eq!(metadata.compression, Some(compression));
+            assert_eq!(metadata.additional, Some(json!({ "format": format })));
+
+            let (_, content) = archive
+                .find_first(|e| e.name == "test1/dir1/file2.txt")
+                .unwrap()
+                .unwrap();
+            assert_eq!(
+                content,
+                std::fs::read("tests/fixtures/test1/dir1/file2.txt").unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn creates_newc_archives() {
+        let destination =
+            std::env::temp_dir().join(format!("hezi-cpio-{}.cpio.gz", std::process::id()));
+        let source = std::path::PathBuf::from("tests/fixtures/test1");
+        Archive::create(CreateOptions {
+            destination: destination.clone(),
+            source: source.clone(),
+            roots: vec![],
+            files: walkdir::WalkDir::new(&source)
+                .sort_by_file_name()
+                .into_iter()
+                .map(|e| e.unwrap().into_path())
+                .collect(),
+            password: None,
+            archive_type: ArchiveType::Cpio,
+            compression: Some(CompressionSpec::new(ArchiveCompression::Gzip)),
+            overwrite: true,
+            include_hidden: true,
+            skip_unreadable: false,
+            verify_after: false,
+            volume_size: None,
+            update: false,
+            sevenz: Default::default(),
+            zip: Default::default(),
+            tar: Default::default(),
+            event_handler: Box::new(SimpleLogger),
+        })
+        .unwrap();
+
+        let archive = Archive::of(DataSource::file(&destination).unwrap()).unwrap();
+        assert_eq!(
+            names(&archive),
+            vec![
+                ("./".to_string(), None),
+                ("dir1/".to_string(), None),
+                ("dir1/file2.txt".to_string(), Some(444)),
+                ("file1.txt".to_string(), Some(1469)),
+            ]
+        );
+        let (_, content) = archive
+            .find_first(|e| e.name == "file1.txt")
+            .unwrap()
+            .unwrap();
+        assert_eq!(content, std::fs::read(source.join("file1.txt")).unwrap());
+        _ = std::fs::remove_file(&destination);
+    }
+
+    #[test]
+    fn hard_links_follow_their_contents() {
+        let header = |name: &str, size: u64| Header {
+            format: CpioFormat::Newc,
+            file_id: (0, 7),
+            mode: S_IFREG | 0o644,
+            owner: (0, 0),
+            nlink: 2,
+            mtime: 0,
+            size,
+            name: name.to_string(),
+        };
+        let mut bytes = Vec::new();
+        header("first", 0).write(&mut bytes).unwrap();
+        header("second", 5).write(&mut bytes).unwrap();
+        bytes.extend_from_slice(b"hello\0\0\0");
+        Header {
+            name: TRAILER.to_string(),
+            ..header("", 0)
+        }
+        .write(&mut bytes)
+        .unwrap();
+
+        let archive = CpioArchive::of(DataSource::memory(bytes.as_slice())).unwrap();
+        let mut visited = Vec::new();
+        let options = VisitOptions {
+            contents: true,
+            ..Default::default()
+        };
+        archive
+            .for_each_entry(&options, |entry, reader| {
+                let mut content = String::new();
+                if let Some(reader) = reader {
+                    reader.read_to_string(&mut content)?;
+                }
+                visited.push((entry.entity.name, entry.link, content));
+                Ok(ControlFlow::Continue(()))
+            })
+            .unwrap();
+        assert_eq!(
+            visited,
+            vec![
+                ("second".to_string(), None, "hello".to_string()),
+                (
+                    "first".to_string(),
+                    Some(EntryLink::Hard("second".to_string())),
+                    String::new()
+                ),
+            ]
+        );
+    }
+}
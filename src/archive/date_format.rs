@@ -0,0 +1,107 @@
+use std::str::FromStr;
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, FixedOffset, Utc,
+};
+
+/// How dates are rendered in listings.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum DateFormat {
+    /// RFC 3339, such as `2024-05-04T09:24:00+02:00`.
+    #[default]
+    Iso,
+    /// Relative to now, such as `3 days ago`.
+    Relative,
+    /// A strftime format of [`chrono`], such as `%Y-%m-%d %H:%M`.
+    Custom(String),
+}
+
+impl FromStr for DateFormat {
+    type Err = String;
+
+    /// Parses `iso`, `relative` or `custom:FORMAT`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "iso" => Ok(DateFormat::Iso),
+            "relative" => Ok(DateFormat::Relative),
+            _ => match s.strip_prefix("custom:") {
+                Some(format) if StrftimeItems::new(format).any(|i| matches!(i, Item::Error)) => {
+                    Err(format!("invalid date format `{}`", format))
+                }
+                Some(format) => Ok(DateFormat::Custom(format.to_string())),
+                None => Err(format!(
+                    "expected `iso`, `relative` or `custom:FORMAT` but got `{}`",
+                    s
+                )),
+            },
+        }
+    }
+}
+
+impl DateFormat {
+    pub fn format(&self, date: &DateTime<FixedOffset>) -> String {
+        self.format_at(date, Utc::now().fixed_offset())
+    }
+
+    /// Formats `date`, relative dates being relative to `now`.
+    pub fn format_at(&self, date: &DateTime<FixedOffset>, now: DateTime<FixedOffset>) -> String {
+        match self {
+            DateFormat::Iso => date.to_rfc3339(),
+            DateFormat::Custom(format) => date.format(format).to_string(),
+            DateFormat::Relative => relative(now.signed_duration_since(date).num_seconds()),
+        }
+    }
+}
+
+/// Renders a number of seconds in the past, or in the future when negative, in its largest
+/// whole unit.
+fn relative(seconds: i64) -> String {
+    const UNITS: [(&str, i64); 6] = [
+        ("year", 365 * 24 * 3600),
+        ("month", 30 * 24 * 3600),
+        ("day", 24 * 3600),
+        ("hour", 3600),
+        ("minute", 60),
+        ("second", 1),
+    ];
+
+    let Some((unit, count)) = UNITS
+        .iter()
+        .map(|(unit, length)| (unit, seconds.abs() / length))
+        .find(|(_, count)| *count > 0)
+    else {
+        return "now".to_string();
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    if seconds < 0 {
+        format!("in {} {}{}", count, unit, plural)
+    } else {
+        format!("{} {}{} ago", count, unit, plural)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_dates() {
+        let now = DateTime::parse_from_rfc3339("2024-03-09T14:05:00+01:00").unwrap();
+        let date = DateTime::parse_from_rfc3339("2024-03-06T10:00:00+01:00").unwrap();
+
+        let format = |s: &str, date| DateFormat::from_str(s).unwrap().format_at(&date, now);
+        assert_eq!(format("iso", date), "2024-03-06T10:00:00+01:00");
+        assert_eq!(format("custom:%d/%m/%Y", date), "06/03/2024");
+        assert_eq!(format("relative", date), "3 days ago");
+        assert_eq!(format("relative", now), "now");
+        assert_eq!(
+            format("relative", now + chrono::Duration::minutes(1)),
+            "in 1 minute"
+        );
+
+        assert!(DateFormat::from_str("custom:%Q").is_err());
+        assert!(DateFormat::from_str("unix").is_err());
+    }
+}
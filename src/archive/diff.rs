@@ -0,0 +1,285 @@
+// comparison of two archives entry by entry, by name, type, size and modification time, and by the
+// digest of their content if asked to, e.g. to tell what changed between two releases
+
+use std::{collections::BTreeMap, ops::ControlFlow};
+
+use serde::Serialize;
+
+use super::{
+    entry_visitor::{normalized_name, EntryVisitor, VisitOptions},
+    manifest::ManifestEntry,
+    password::PasswordProvider,
+    Archive, ArchiveError, ArchiveFileEntity, ArchiveFileEntityType,
+};
+
+#[derive(Debug, Default)]
+pub struct DiffOptions<'a> {
+    /// Password of the archive compared.
+    pub password: Option<Box<dyn PasswordProvider + 'a>>,
+    /// Password of the archive it is compared with.
+    pub other_password: Option<Box<dyn PasswordProvider + 'a>>,
+    /// Compares the SHA-256 of the files too, reading both archives whole, to tell files changed
+    /// without a change of size or modification time.
+    pub content: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffKind {
+    /// In the other archive only.
+    Added,
+    /// In the archive compared only.
+    Removed,
+    Changed,
+}
+
+/// What differs between two entries of the same name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffField {
+    Type,
+    Size,
+    Modified,
+    Content,
+}
+
+impl std::fmt::Display for DiffKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiffKind::Added => write!(f, "added"),
+            DiffKind::Removed => write!(f, "removed"),
+            DiffKind::Changed => write!(f, "changed"),
+        }
+    }
+}
+
+impl std::fmt::Display for DiffField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DiffField::Type => write!(f, "type"),
+            DiffField::Size => write!(f, "size"),
+            DiffField::Modified => write!(f, "modified"),
+            DiffField::Content => write!(f, "content"),
+        }
+    }
+}
+
+/// Entry added, removed or changed between two archives.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffEntry {
+    /// Name of the entry, without the leading `./` and trailing `/`.
+    pub name: String,
+    pub kind: DiffKind,
+    /// Fields that differ, empty unless the entry changed.
+    pub fields: Vec<DiffField>,
+    /// The entry in the archive compared, `None` if it was added.
+    pub old: Option<ArchiveFileEntity>,
+    /// The entry in the other archive, `None` if it was removed.
+    pub new: Option<ArchiveFileEntity>,
+}
+
+/// Entity and, when contents are compared, digest of an entry.
+type Entries = BTreeMap<String, (ArchiveFileEntity, Option<ManifestEntry>)>;
+
+impl Archive {
+    /// Entries of `other` that are not in this archive, entries of this archive that are not in
+    /// `other`, and entries of both whose type, size or modification time differ, or whose content
+    /// does with [`DiffOptions::content`], sorted by name. Names are compared without their
+    /// leading `./` and trailing `/`, so that archives of different formats can be compared.
+    pub fn diff(
+        &self,
+        other: &Archive,
+        options: DiffOptions,
+    ) -> Result<Vec<DiffEntry>, ArchiveError> {
+        let old = entries(self, options.password.as_deref(), options.content)?;
+        let mut new = entries(other, options.other_password.as_deref(), options.content)?;
+
+        let mut diff = Vec::new();
+        for (name, (old, old_digest)) in old {
+            let Some((new, new_digest)) = new.remove(&name) else {
+                diff.push(DiffEntry {
+                    name,
+                    kind: DiffKind::Removed,
+                    fields: Vec::new(),
+                    old: Some(old),
+                    new: None,
+                });
+                continue;
+            };
+            let mut fields = Vec::new();
+            if old.fstype != new.fstype {
+                fields.push(DiffField::Type);
+            }
+            if old.size != new.size {
+                fields.push(DiffField::Size);
+            }
+            if old.last_modified != new.last_modified {
+                fields.push(DiffField::Modified);
+            }
+            if old_digest.zip(new_digest).is_some_and(|(o, n)| o != n) {
+                fields.push(DiffField::Content);
+            }
+            if !fields.is_empty() {
+                diff.push(DiffEntry {
+                    name,
+                    kind: DiffKind::Changed,
+                    fields,
+                    old: Some(old),
+                    new: Some(new),
+                });
+            }
+        }
+        diff.extend(new.into_iter().map(|(name, (new, _))| DiffEntry {
+            name,
+            kind: DiffKind::Added,
+            fields: Vec::new(),
+            old: None,
+            new: Some(new),
+        }));
+        diff.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(diff)
+    }
+}
+
+/// Entries of `archive` by normalized name, with the digest of the files if `content` is set.
+fn entries(
+    archive: &Archive,
+    password: Option<&dyn PasswordProvider>,
+    content: bool,
+) -> Result<Entries, ArchiveError> {
+    let options = VisitOptions {
+        password,
+        contents: content,
+        ..Default::default()
+    };
+    let mut entries = Entries::new();
+    archive.for_each_entry(&options, |entry, reader| {
+        let name = normalized_name(&entry.entity.name).trim_end_matches('/');
+        // the root of the archive itself, such as `./`, is not an entry of its own
+        if name.is_empty() {
+            return Ok(ControlFlow::Continue(()));
+        }
+        let digest = match reader {
+            Some(reader) if entry.entity.fstype == ArchiveFileEntityType::File => {
+                Some(ManifestEntry::digest(reader)?)
+            }
+            _ => None,
+        };
+        entries.insert(name.to_string(), (entry.entity, digest));
+        Ok(ControlFlow::Continue(()))
+    })?;
+    Ok(entries)
+}
+
+#[cfg(all(test, feature = "tar_archive"))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::{fs::File, path::Path, time::SystemTime};
+
+    use super::*;
+    use crate::archive::{
+        ArchiveCompression, ArchiveType, Archived, CompressionSpec, CreateOptions, DataSource,
+        SimpleLogger,
+    };
+
+    fn create(source: &Path, destination: &Path) -> Archive {
+        Archive::create(CreateOptions {
+            destination: destination.to_path_buf(),
+            source: source.to_path_buf(),
+            roots: vec![],
+            files: walkdir::WalkDir::new(source)
+                .into_iter()
+                .map(|e| e.unwrap().into_path())
+                .collect(),
+            password: None,
+            archive_type: ArchiveType::Tar,
+            compression: Some(CompressionSpec::new(ArchiveCompression::None)),
+            overwrite: true,
+            include_hidden: true,
+            skip_unreadable: false,
+            verify_after: false,
+            volume_size: None,
+            update: false,
+            sevenz: Default::default(),
+            zip: Default::default(),
+            tar: Default::default(),
+            event_handler: Box::new(SimpleLogger),
+        })
+        .unwrap();
+        Archive::of(DataSource::file(destination).unwrap()).unwrap()
+    }
+
+    fn write(path: &Path, content: &str, modified: SystemTime) {
+        std::fs::write(path, content).unwrap();
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    }
+
+    #[test]
+    fn reports_added_removed_and_changed_entries() {
+        let dir = std::env::temp_dir().join(format!("hezi-diff-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        let source = dir.join("source");
+        std::fs::create_dir_all(source.join("docs")).unwrap();
+        let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_600_000_000);
+        write(&source.join("docs/same.txt"), "same", modified);
+        write(&source.join("docs/edited.txt"), "aaaa", modified);
+        write(&source.join("grown.txt"), "a", modified);
+        write(&source.join("removed.txt"), "gone", modified);
+        let old = create(&source, &dir.join("old.tar"));
+
+        // same size and modification time, only the content tells it changed
+        write(&source.join("docs/edited.txt"), "bbbb", modified);
+        write(&source.join("grown.txt"), "ab", modified);
+        std::fs::remove_file(source.join("removed.txt")).unwrap();
+        write(&source.join("added.txt"), "new", modified);
+        let new = create(&source, &dir.join("new.tar"));
+
+        let summary = |diff: Vec<DiffEntry>| {
+            diff.into_iter()
+                .map(|d| (d.name, d.kind, d.fields))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            summary(old.diff(&new, DiffOptions::default()).unwrap()),
+            [
+                ("added.txt".to_string(), DiffKind::Added, vec![]),
+                (
+                    "grown.txt".to_string(),
+                    DiffKind::Changed,
+                    vec![DiffField::Size]
+                ),
+                ("removed.txt".to_string(), DiffKind::Removed, vec![]),
+            ]
+        );
+        let content = DiffOptions {
+            content: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            summary(old.diff(&new, content).unwrap())[0..2],
+            [
+                ("added.txt".to_string(), DiffKind::Added, vec![]),
+                (
+                    "docs/edited.txt".to_string(),
+                    DiffKind::Changed,
+                    vec![DiffField::Content]
+                ),
+            ]
+        );
+
+        let tar = Archive::of(DataSource::file("tests/fixtures/test1.tar").unwrap()).unwrap();
+        assert!(old.diff(&old, DiffOptions::default()).unwrap().is_empty());
+        // archives without an entry in common
+        assert!(tar
+            .diff(&old, DiffOptions::default())
+            .unwrap()
+            .iter()
+            .all(|d| d.kind != DiffKind::Changed));
+        _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,1365 @@
+// read-only support for raw disk images holding FAT12/16/32 or ext2/3/4 filesystems, either from
+// their first byte or in the partitions of an MBR or GPT partition table. Every filesystem is
+// walked into a list of nodes whose contents are runs of bytes of the image, only read once the
+// entry is visited with its contents.
+
+use std::{
+    cell::OnceCell,
+    collections::HashSet,
+    io::{BufReader, ErrorKind, Read, Seek, SeekFrom},
+    ops::ControlFlow,
+};
+
+use chrono::{DateTime, FixedOffset};
+use serde_json::json;
+
+use crate::archive::{
+    datetime_from_timestamp, dos_datetime,
+    entry_visitor::{
+        extract_entries, find_first_entry, list_entries, open_entry, scan_entries, EntryLink,
+        EntryVisitor, VisitOptions, VisitedEntry,
+    },
+    ArchiveError, ArchiveFileEntity, ArchiveFileEntityType, ArchiveMetadata, ArchiveScan,
+    ArchiveType, Archived, CreateOptions, CreateResult, DataSource, ExtractOptions, ExtractReport,
+    ListOptions, OpenOptions, DEFAULT_BUF_SIZE,
+};
+
+use super::edit::EntryEdit;
+
+const SECTOR_SIZE: u64 = 512;
+/// Directories nested deeper are taken for a loop of a corrupted filesystem.
+const MAX_DEPTH: usize = 256;
+/// Bytes reserved up front to read metadata such as a directory, the rest being allocated as it
+/// is read, since the size comes from the image.
+const MAX_PREALLOCATED: u64 = 1024 * 1024;
+
+const MBR_PROTECTIVE: u8 = 0xee;
+const MBR_EXTENDED: [u8; 3] = [0x05, 0x0f, 0x85];
+
+const FAT_ATTR_VOLUME_ID: u8 = 0x08;
+const FAT_ATTR_DIRECTORY: u8 = 0x10;
+const FAT_ATTR_LONG_NAME: u8 = 0x0f;
+const FAT_DELETED: u8 = 0xe5;
+
+const EXT_MAGIC: u16 = 0xef53;
+const EXT_ROOT_INODE: u32 = 2;
+const EXT_COMPAT_HAS_JOURNAL: u32 = 0x4;
+const EXT_INCOMPAT_COMPRESSION: u32 = 0x1;
+const EXT_INCOMPAT_JOURNAL_DEV: u32 = 0x8;
+const EXT_INCOMPAT_META_BG: u32 = 0x10;
+const EXT_INCOMPAT_EXTENTS: u32 = 0x40;
+const EXT_INCOMPAT_64BIT: u32 = 0x80;
+const EXT_INCOMPAT_FLEX_BG: u32 = 0x200;
+const EXT_INCOMPAT_ENCRYPT: u32 = 0x10000;
+const EXT_EXTENTS_FL: u32 = 0x80000;
+const EXT_INLINE_DATA_FL: u32 = 0x1000_0000;
+const EXT_EXTENT_MAGIC: u16 = 0xf30a;
+
+const S_IFMT: u16 = 0o170000;
+const S_IFDIR: u16 = 0o040000;
+const S_IFREG: u16 = 0o100000;
+const S_IFLNK: u16 = 0o120000;
+
+fn invalid_data(message: String) -> ArchiveError {
+    ArchiveError::Io(std::io::Error::new(ErrorKind::InvalidData, message))
+}
+
+fn unsupported(message: String) -> ArchiveError {
+    ArchiveError::Io(std::io::Error::new(ErrorKind::Unsupported, message))
+}
+
+fn le_u16(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([buf[at], buf[at + 1]])
+}
+
+fn le_u32(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+}
+
+fn le_u64(buf: &[u8], at: usize) -> u64 {
+    (le_u32(buf, at + 4) as u64) << 32 | le_u32(buf, at) as u64
+}
+
+/// Text of a fixed size field, padded with spaces or zeroes.
+fn padded_text(buf: &[u8]) -> String {
+    String::from_utf8_lossy(buf)
+        .trim_end_matches([' ', '\0'])
+        .to_string()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FsKind {
+    Fat12,
+    Fat16,
+    Fat32,
+    Ext2,
+    Ext3,
+    Ext4,
+}
+
+impl FsKind {
+    fn name(self) -> &'static str {
+        match self {
+            FsKind::Fat12 => "FAT12",
+            FsKind::Fat16 => "FAT16",
+            FsKind::Fat32 => "FAT32",
+            FsKind::Ext2 => "ext2",
+            FsKind::Ext3 => "ext3",
+            FsKind::Ext4 => "ext4",
+        }
+    }
+}
+
+/// A filesystem found in the image, starting `offset` bytes into it.
+#[derive(Debug)]
+struct Filesystem {
+    kind: FsKind,
+    offset: u64,
+    /// Number of its partition in the partition table, from 1.
+    partition: Option<usize>,
+}
+
+/// Kind of the filesystem starting at `offset`, if any.
+fn probe<R: Read + Seek>(reader: &mut R, offset: u64) -> Result<Option<FsKind>, ArchiveError> {
+    let mut boot = [0; 512];
+    reader.seek(SeekFrom::Start(offset))?;
+    if reader.read_exact(&mut boot).is_err() {
+        return Ok(None);
+    }
+    if let Some(geometry) = FatGeometry::parse(&boot) {
+        return Ok(Some(geometry.kind));
+    }
+
+    let mut superblock = [0; 1024];
+    reader.seek(SeekFrom::Start(offset + 1024))?;
+    if reader.read_exact(&mut superblock).is_err() {
+        return Ok(None);
+    }
+    Ok(ExtSuperblock::parse(&superblock).map(|s| s.kind))
+}
+
+/// Filesystems of the image: the one it starts with, or those of its partitions.
+fn find_filesystems<R: Read + Seek>(reader: &mut R) -> Result<Vec<Filesystem>, ArchiveError> {
+    if let Some(kind) = probe(reader, 0)? {
+        return Ok(vec![Filesystem {
+            kind,
+            offset: 0,
+            partition: None,
+        }]);
+    }
+
+    let mut filesystems = Vec::new();
+    for (number, start) in partitions(reader)? {
+        if let Some(kind) = probe(reader, start)? {
+            filesystems.push(Filesystem {
+                kind,
+                offset: start,
+                partition: Some(number),
+            });
+        }
+    }
+    Ok(filesystems)
+}
+
+/// Numbers and byte offsets of the primary partitions of an MBR, or of the partitions of a GPT.
+fn partitions<R: Read + Seek>(reader: &mut R) -> Result<Vec<(usize, u64)>, ArchiveError> {
+    let mut mbr = [0; 512];
+    reader.seek(SeekFrom::Start(0))?;
+    if reader.read_exact(&mut mbr).is_err() || mbr[510..] != [0x55, 0xaa] {
+        return Ok(Vec::new());
+    }
+
+    let entries = (0..4).map(|i| &mbr[446 + i * 16..462 + i * 16]);
+    if entries.clone().any(|e| e[4] == MBR_PROTECTIVE) {
+        return gpt_partitions(reader);
+    }
+    Ok(entries
+        .enumerate()
+        .filter(|(_, e)| e[4] != 0 && !MBR_EXTENDED.contains(&e[4]))
+        .map(|(i, e)| (i + 1, le_u32(e, 8) as u64 * SECTOR_SIZE))
+        .collect())
+}
+
+fn gpt_partitions<R: Read + Seek>(reader: &mut R) -> Result<Vec<(usize, u64)>, ArchiveError> {
+    let mut header = [0; 92];
+    reader.seek(SeekFrom::Start(SECTOR_SIZE))?;
+    if reader.read_exact(&mut header).is_err() || &header[..8] != b"EFI PART" {
+        return Ok(Vec::new());
+    }
+    let first = le_u64(&header, 72) * SECTOR_SIZE;
+    let count = le_u32(&header, 80) as usize;
+    let size = le_u32(&header, 84) as usize;
+    if size < 128 || count > 1024 {
+        return Err(invalid_data("invalid GPT partition entries".to_string()));
+    }
+
+    let mut table = vec![0; count * size];
+    reader.seek(SeekFrom::Start(first))?;
+    reader.read_exact(&mut table)?;
+    Ok(table
+        .chunks(size)
+        .enumerate()
+        // entries of unused partitions have a nil type
+        .filter(|(_, e)| e[..16].iter().any(|b| *b != 0))
+        .map(|(i, e)| (i + 1, le_u64(e, 32) * SECTOR_SIZE))
+        .collect())
+}
+
+/// Whether the image holds a filesystem that can be read.
+pub(crate) fn is_disk_image<R: Read + Seek>(reader: &mut R) -> bool {
+    find_filesystems(reader).is_ok_and(|f| !f.is_empty())
+}
+
+/// A run of bytes of a file, lying at `offset` in the image, or zeroes without one.
+#[derive(Debug, Clone)]
+struct Run {
+    offset: Option<u64>,
+    length: u64,
+}
+
+/// Appends a run to `runs`, merged with the last one when it follows it.
+fn push_run(runs: &mut Vec<Run>, offset: Option<u64>, length: u64) {
+    if let Some(last) = runs.last_mut() {
+        let follows = match (last.offset, offset) {
+            (Some(end), Some(start)) => end + last.length == start,
+            (None, None) => true,
+            _ => false,
+        };
+        if follows {
+            last.length += length;
+            return;
+        }
+    }
+    runs.push(Run { offset, length });
+}
+
+#[derive(Debug)]
+enum Data {
+    Runs(Vec<Run>),
+    /// Contents small enough to be stored with the metadata of the file.
+    Inline(Vec<u8>),
+}
+
+/// Reads `size` bytes of `runs` from the image.
+struct RunReader<'r, R> {
+    reader: &'r mut R,
+    runs: &'r [Run],
+    /// Index of the current run, and position in it.
+    run: usize,
+    within: u64,
+    remaining: u64,
+    /// Position of `reader`, to avoid seeking it when reading runs one after the other.
+    position: Option<u64>,
+}
+
+impl<'r, R> RunReader<'r, R> {
+    fn new(reader: &'r mut R, runs: &'r [Run], size: u64) -> Self {
+        Self {
+            reader,
+            runs,
+            run: 0,
+            within: 0,
+            remaining: size,
+            position: None,
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for RunReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.run < self.runs.len() && self.within >= self.runs[self.run].length {
+            self.run += 1;
+            self.within = 0;
+        }
+        if self.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        let Some(run) = self.runs.get(self.run) else {
+            return Err(std::io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "file extends past its blocks",
+            ));
+        };
+
+        let n = (buf.len() as u64)
+            .min(run.length - self.within)
+            .min(self.remaining) as usize;
+        let n = match run.offset {
+            Some(offset) => {
+                let at = offset + self.within;
+                if self.position != Some(at) {
+                    self.reader.seek(SeekFrom::Start(at))?;
+                }
+                let n = self.reader.read(&mut buf[..n])?;
+                if n == 0 {
+                    return Err(ErrorKind::UnexpectedEof.into());
+                }
+                self.position = Some(at + n as u64);
+                n
+            }
+            None => {
+                buf[..n].fill(0);
+                n
+            }
+        };
+        self.within += n as u64;
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+fn read_data<R: Read + Seek>(
+    reader: &mut R,
+    data: &Data,
+    size: u64,
+) -> Result<Vec<u8>, ArchiveError> {
+    match data {
+        Data::Runs(runs) => {
+            let mapped = runs.iter().map(|run| run.length).sum::<u64>();
+            if size > mapped {
+                return Err(invalid_data(format!(
+                    "data of {} bytes past the {} bytes of its blocks",
+                    size, mapped
+                )));
+            }
+            let mut out = Vec::with_capacity(size.min(MAX_PREALLOCATED) as usize);
+            RunReader::new(reader, runs, size).read_to_end(&mut out)?;
+            Ok(out)
+        }
+        Data::Inline(data) => Ok(data[..data.len().min(size as usize)].to_vec()),
+    }
+}
+
+#[derive(Debug)]
+enum NodeKind {
+    Directory,
+    File,
+    Symlink(String),
+}
+
+/// A file, directory or symbolic link of a filesystem.
+#[derive(Debug)]
+struct Node {
+    path: String,
+    kind: NodeKind,
+    mode: Option<u32>,
+    modified: Option<DateTime<FixedOffset>>,
+    size: u64,
+    data: Data,
+}
+
+/// Layout of a FAT filesystem, from its boot sector.
+#[derive(Debug)]
+struct FatGeometry {
+    kind: FsKind,
+    cluster_size: u64,
+    fat_start: u64,
+    fat_size: u64,
+    root_start: u64,
+    root_entries: u64,
+    data_start: u64,
+    clusters: u32,
+    root_cluster: u32,
+    label: String,
+}
+
+impl FatGeometry {
+    fn parse(boot: &[u8; 512]) -> Option<Self> {
+        let sector_size = le_u16(boot, 11) as u64;
+        let sectors_per_cluster = boot[13] as u64;
+        let reserved = le_u16(boot, 14) as u64;
+        let fats = boot[16] as u64;
+        let root_entries = le_u16(boot, 17) as u64;
+        let media = boot[21];
+        let total = match le_u16(boot, 19) {
+            0 => le_u32(boot, 32) as u64,
+            n => n as u64,
+        };
+        let fat_size = match le_u16(boot, 22) {
+            0 => le_u32(boot, 36) as u64,
+            n => n as u64,
+        };
+        if boot[510..] != [0x55, 0xaa]
+            || !matches!(boot[0], 0xeb | 0xe9)
+            || !matches!(sector_size, 512 | 1024 | 2048 | 4096)
+            || !sectors_per_cluster.is_power_of_two()
+            || reserved == 0
+            || !(1..=4).contains(&fats)
+            || (media != 0xf0 && media < 0xf8)
+            || fat_size == 0
+        {
+            return None;
+        }
+
+        let root_sectors = (root_entries * 32).div_ceil(sector_size);
+        let data_sector = reserved + fats * fat_size + root_sectors;
+        let clusters = total.checked_sub(data_sector)? / sectors_per_cluster;
+        let kind = match clusters {
+            0..=4084 => FsKind::Fat12,
+            4085..=65524 => FsKind::Fat16,
+            _ => FsKind::Fat32,
+        };
+        if (kind == FsKind::Fat32) != (root_entries == 0) {
+            return None;
+        }
+        let label = match kind {
+            FsKind::Fat32 => &boot[71..82],
+            _ => &boot[43..54],
+        };
+
+        Some(Self {
+            kind,
+            cluster_size: sectors_per_cluster * sector_size,
+            fat_start: reserved * sector_size,
+            fat_size: fat_size * sector_size,
+            root_start: (reserved + fats * fat_size) * sector_size,
+            root_entries,
+            data_start: data_sector * sector_size,
+            clusters: clusters as u32,
+            root_cluster: le_u32(boot, 44),
+            label: padded_text(label),
+        })
+    }
+}
+
+struct FatVolume {
+    offset: u64,
+    geometry: FatGeometry,
+    /// Next cluster of every cluster, from the first copy of the allocation table.
+    table: Vec<u32>,
+}
+
+/// An entry of a FAT directory.
+struct FatEntry {
+    name: String,
+    attributes: u8,
+    cluster: u32,
+    size: u32,
+    modified: u32,
+}
+
+impl FatVolume {
+    fn open<R: Read + Seek>(reader: &mut R, offset: u64) -> Result<Self, ArchiveError> {
+        let mut boot = [0; 512];
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut boot)?;
+        let geometry =
+            FatGeometry::parse(&boot).ok_or(invalid_data("invalid FAT boot sector".to_string()))?;
+
+        let mut fat = vec![0; geometry.fat_size as usize];
+        reader.seek(SeekFrom::Start(offset + geometry.fat_start))?;
+        reader.read_exact(&mut fat)?;
+        let count = (geometry.clusters as usize + 2).min(match geometry.kind {
+            FsKind::Fat12 => fat.len() * 2 / 3,
+            FsKind::Fat16 => fat.len() / 2,
+            _ => fat.len() / 4,
+        });
+        let table = (0..count)
+            .map(|n| match geometry.kind {
+                FsKind::Fat12 => {
+                    let pair = le_u16(&fat, n * 3 / 2) as u32;
+                    match n % 2 {
+                        0 => pair & 0xfff,
+                        _ => pair >> 4,
+                    }
+                }
+                FsKind::Fat16 => le_u16(&fat, n * 2) as u32,
+                _ => le_u32(&fat, n * 4) & 0x0fff_ffff,
+            })
+            .collect();
+
+        Ok(Self {
+            offset,
+            geometry,
+            table,
+        })
+    }
+
+    fn is_end(&self, cluster: u32) -> bool {
+        let end = match self.geometry.kind {
+            FsKind::Fat12 => 0xff8,
+            FsKind::Fat16 => 0xfff8,
+            _ => 0x0fff_fff8,
+        };
+        cluster < 2 || cluster >= end
+    }
+
+    /// Runs of the clusters chained from `first`.
+    fn chain(&self, first: u32) -> Result<Vec<Run>, ArchiveError> {
+        let mut runs = Vec::new();
+        let mut cluster = first;
+        let mut count = 0;
+        while !self.is_end(cluster) {
+            if cluster as usize >= self.table.len() || count >= self.table.len() {
+                return Err(invalid_data(format!(
+                    "invalid FAT cluster chain at {}",
+                    first
+                )));
+            }
+            let start = self.offset
+                + self.geometry.data_start
+                + (cluster as u64 - 2) * self.geometry.cluster_size;
+            push_run(&mut runs, Some(start), self.geometry.cluster_size);
+            cluster = self.table[cluster as usize];
+            count += 1;
+        }
+        Ok(runs)
+    }
+
+    fn root(&self) -> Result<(Vec<Run>, u64), ArchiveError> {
+        match self.geometry.kind {
+            FsKind::Fat32 => {
+                let runs = self.chain(self.geometry.root_cluster)?;
+                let size = runs.iter().map(|r| r.length).sum();
+                Ok((runs, size))
+            }
+            _ => {
+                let size = self.geometry.root_entries * 32;
+                let start = self.offset + self.geometry.root_start;
+                Ok((
+                    vec![Run {
+                        offset: Some(start),
+                        length: size,
+                    }],
+                    size,
+                ))
+            }
+        }
+    }
+
+    fn walk<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        prefix: &str,
+    ) -> Result<Vec<Node>, ArchiveError> {
+        let mut nodes = Vec::new();
+        let mut visited = HashSet::new();
+        let (runs, size) = self.root()?;
+        let mut pending = vec![(prefix.to_string(), runs, size, 0)];
+        while let Some((path, runs, size, depth)) = pending.pop() {
+            let entries = fat_entries(&read_data(reader, &Data::Runs(runs), size)?);
+            for entry in entries {
+                let name = format!("{}{}", path, entry.name);
+                let modified = dos_datetime(entry.modified);
+                if entry.attributes & FAT_ATTR_DIRECTORY != 0 {
+                    if depth >= MAX_DEPTH || !visited.insert(entry.cluster) {
+                        return Err(invalid_data(format!("FAT directory loop at {}", name)));
+                    }
+                    let runs = self.chain(entry.cluster)?;
+                    let size = runs.iter().map(|r| r.length).sum();
+                    pending.push((format!("{}/", name), runs, size, depth + 1));
+                    nodes.push(Node {
+                        path: name,
+                        kind: NodeKind::Directory,
+                        mode: None,
+                        modified,
+                        size: 0,
+                        data: Data::Runs(Vec::new()),
+                    });
+                } else {
+                    let runs = match entry.size {
+                        0 => Vec::new(),
+                        _ => self.chain(entry.cluster)?,
+                    };
+                    nodes.push(Node {
+                        path: name,
+                        kind: NodeKind::File,
+                        mode: None,
+                        modified,
+                        size: entry.size as u64,
+                        data: Data::Runs(runs),
+                    });
+                }
+            }
+        }
+        Ok(nodes)
+    }
+}
+
+/// Checksum of a short name, that the long name entries preceding it repeat.
+fn short_name_checksum(name: &[u8]) -> u8 {
+    name.iter()
+        .fold(0u8, |sum, c| sum.rotate_right(1).wrapping_add(*c))
+}
+
+/// Short name of an entry, lowercased as Windows NT records it.
+fn short_name(entry: &[u8]) -> String {
+    let decode = |bytes: &[u8], lower: bool| {
+        let text = bytes
+            .iter()
+            .map(|b| *b as char)
+            .collect::<String>()
+            .trim_end()
+            .to_string();
+        match lower {
+            true => text.to_lowercase(),
+            false => text,
+        }
+    };
+    let mut base = entry[..8].to_vec();
+    if base[0] == 0x05 {
+        base[0] = FAT_DELETED;
+    }
+    let base = decode(&base, entry[12] & 0x08 != 0);
+    let extension = decode(&entry[8..11], entry[12] & 0x10 != 0);
+    match extension.is_empty() {
+        true => base,
+        false => format!("{}.{}", base, extension),
+    }
+}
+
+/// Entries of a FAT directory, named after their long name when they have a valid one.
+fn fat_entries(directory: &[u8]) -> Vec<FatEntry> {
+    let mut entries = Vec::new();
+    // parts of the long name of the next entry, by sequence number, and their checksum
+    let mut long_name: Vec<(u8, [u16; 13])> = Vec::new();
+    let mut checksum = 0;
+    for entry in directory.chunks_exact(32) {
+        match entry[0] {
+            0 => break,
+            FAT_DELETED => {
+                long_name.clear();
+                continue;
+            }
+            _ => {}
+        }
+        let attributes = entry[11];
+        if attributes & 0x3f == FAT_ATTR_LONG_NAME {
+            if entry[0] & 0x40 != 0 {
+                long_name.clear();
+                checksum = entry[13];
+            }
+            let mut units = [0; 13];
+            for (i, at) in (1..11)
+                .step_by(2)
+                .chain((14..26).step_by(2))
+                .chain((28..32).step_by(2))
+                .enumerate()
+            {
+                units[i] = le_u16(entry, at);
+            }
+            long_name.push((entry[0] & 0x1f, units));
+            continue;
+        }
+        let parts = std::mem::take(&mut long_name);
+        if attributes & FAT_ATTR_VOLUME_ID != 0 {
+            continue;
+        }
+        let short = short_name(entry);
+        if short == "." || short == ".." {
+            continue;
+        }
+
+        // the parts come last first, and must count down to 1
+        let complete = !parts.is_empty()
+            && checksum == short_name_checksum(&entry[..11])
+            && parts
+                .iter()
+                .rev()
+                .enumerate()
+                .all(|(i, (sequence, _))| *sequence as usize == i + 1);
+        let name = match complete {
+            true => {
+                let units = parts
+                    .iter()
+                    .rev()
+                    .flat_map(|(_, units)| units.iter().copied())
+                    .take_while(|u| *u != 0)
+                    .collect::<Vec<_>>();
+                String::from_utf16_lossy(&units)
+            }
+            false => short,
+        };
+        entries.push(FatEntry {
+            name,
+            attributes,
+            cluster: (le_u16(entry, 20) as u32) << 16 | le_u16(entry, 26) as u32,
+            size: le_u32(entry, 28),
+            modified: (le_u16(entry, 24) as u32) << 16 | le_u16(entry, 22) as u32,
+        });
+    }
+    entries
+}
+
+/// Fields of an ext superblock.
+#[derive(Debug)]
+struct ExtSuperblock {
+    kind: FsKind,
+    inodes: u32,
+    first_data_block: u32,
+    block_size: u64,
+    inodes_per_group: u32,
+    inode_size: u64,
+    incompat: u32,
+    descriptor_size: u64,
+    label: String,
+}
+
+impl ExtSuperblock {
+    fn parse(superblock: &[u8; 1024]) -> Option<Self> {
+        let log_block_size = le_u32(superblock, 24);
+        let inodes_per_group = le_u32(superblock, 40);
+        if le_u16(superblock, 56) != EXT_MAGIC
+            || log_block_size > 6
+            || inodes_per_group == 0
+            || le_u32(superblock, 32) == 0
+        {
+            return None;
+        }
+        let compat = le_u32(superblock, 92);
+        let incompat = le_u32(superblock, 96);
+        let kind =
+            if incompat & (EXT_INCOMPAT_EXTENTS | EXT_INCOMPAT_64BIT | EXT_INCOMPAT_FLEX_BG) != 0 {
+                FsKind::Ext4
+            } else if compat & EXT_COMPAT_HAS_JOURNAL != 0 {
+                FsKind::Ext3
+            } else {
+                FsKind::Ext2
+            };
+        let inode_size = match le_u32(superblock, 76) {
+            0 => 128,
+            _ => le_u16(superblock, 88) as u64,
+        };
+        let descriptor_size = match incompat & EXT_INCOMPAT_64BIT {
+            0 => 32,
+            _ => (le_u16(superblock, 254) as u64).max(32),
+        };
+        if inode_size < 128 {
+            return None;
+        }
+
+        Some(Self {
+            kind,
+            inodes: le_u32(superblock, 0),
+            first_data_block: le_u32(superblock, 20),
+            block_size: 1024 << log_block_size,
+            inodes_per_group,
+            inode_size,
+            incompat,
+            descriptor_size,
+            label: padded_text(&superblock[120..136]),
+        })
+    }
+}
+
+/// Fields of an ext inode.
+struct Inode {
+    mode: u16,
+    size: u64,
+    modified: u32,
+    flags: u32,
+    /// Block map, extent tree root or inline data.
+    block: [u8; 60],
+}
+
+struct ExtVolume {
+    offset: u64,
+    superblock: ExtSuperblock,
+    /// Block of the inode table of every group.
+    inode_tables: Vec<u64>,
+}
+
+impl ExtVolume {
+    fn open<R: Read + Seek>(reader: &mut R, offset: u64) -> Result<Self, ArchiveError> {
+        let mut buf = [0; 1024];
+        reader.seek(SeekFrom::Start(offset + 1024))?;
+        reader.read_exact(&mut buf)?;
+        let superblock =
+            ExtSuperblock::parse(&buf).ok_or(invalid_data("invalid ext superblock".to_string()))?;
+        let unsupported_features = superblock.incompat
+            & (EXT_INCOMPAT_COMPRESSION
+                | EXT_INCOMPAT_JOURNAL_DEV
+                | EXT_INCOMPAT_META_BG
+                | EXT_INCOMPAT_ENCRYPT);
+        if unsupported_features != 0 {
+            return Err(unsupported(format!(
+                "ext filesystem features {:#x} are not supported",
+                unsupported_features
+            )));
+        }
+
+        let groups = superblock.inodes.div_ceil(superblock.inodes_per_group) as usize;
+        let mut descriptors = vec![0; groups * superblock.descriptor_size as usize];
+        let table_block = superblock.first_data_block as u64 + 1;
+        reader.seek(SeekFrom::Start(
+            offset + table_block * superblock.block_size,
+        ))?;
+        reader.read_exact(&mut descriptors)?;
+        let inode_tables = descriptors
+            .chunks_exact(superblock.descriptor_size as usize)
+            .map(|d| match superblock.descriptor_size {
+                32 => le_u32(d, 8) as u64,
+                _ => (le_u32(d, 0x28) as u64) << 32 | le_u32(d, 8) as u64,
+            })
+            .collect();
+
+        Ok(Self {
+            offset,
+            superblock,
+            inode_tables,
+        })
+    }
+
+    fn inode<R: Read + Seek>(&self, reader: &mut R, number: u32) -> Result<Inode, ArchiveError> {
+        let index = number.wrapping_sub(1);
+        let table = self
+            .inode_tables
+            .get((index / self.superblock.inodes_per_group) as usize)
+            .ok_or(invalid_data(format!("invalid ext inode {}", number)))?;
+        let at = table * self.superblock.block_size
+            + (index % self.superblock.inodes_per_group) as u64 * self.superblock.inode_size;
+        let mut buf = [0; 128];
+        reader.seek(SeekFrom::Start(self.offset + at))?;
+        reader.read_exact(&mut buf)?;
+
+        let mut block = [0; 60];
+        block.copy_from_slice(&buf[40..100]);
+        Ok(Inode {
+            mode: le_u16(&buf, 0),
+            size: (le_u32(&buf, 108) as u64) << 32 | le_u32(&buf, 4) as u64,
+            modified: le_u32(&buf, 16),
+            flags: le_u32(&buf, 32),
+            block,
+        })
+    }
+
+    fn block_offset(&self, block: u64) -> u64 {
+        self.offset + block * self.superblock.block_size
+    }
+
+    fn data<R: Read + Seek>(&self, reader: &mut R, inode: &Inode) -> Result<Data, ArchiveError> {
+        if inode.flags & EXT_INLINE_DATA_FL != 0 {
+            // the rest of larger files lies in an extended attribute
+            if inode.size > 60 {
+                return Err(unsupported(
+                    "ext inline data past 60 bytes is not supported".to_string(),
+                ));
+            }
+            return Ok(Data::Inline(inode.block.to_vec()));
+        }
+
+        let blocks = inode.size.div_ceil(self.superblock.block_size);
+        let mut runs = Vec::new();
+        if inode.flags & EXT_EXTENTS_FL != 0 {
+            let mut extents = Vec::new();
+            self.extents(reader, &inode.block, 0, &mut extents)?;
+            extents.sort_by_key(|e| e.0);
+            let mut next = 0;
+            for (logical, physical, length) in extents {
+                if logical >= blocks || logical < next {
+                    continue;
+                }
+                if logical > next {
+                    push_run(
+                        &mut runs,
+                        None,
+                        (logical - next) * self.superblock.block_size,
+                    );
+                }
+                let length = length.min(blocks - logical);
+                let offset = physical.map(|p| self.block_offset(p));
+                push_run(&mut runs, offset, length * self.superblock.block_size);
+                next = logical + length;
+            }
+            if next < blocks {
+                push_run(
+                    &mut runs,
+                    None,
+                    (blocks - next) * self.superblock.block_size,
+                );
+            }
+        } else {
+            let mut remaining = blocks;
+            for (i, depth) in (0..15usize).map(|i| (i, i.saturating_sub(11))) {
+                let pointer = le_u32(&inode.block, i * 4);
+                self.map_blocks(reader, pointer, depth as u32, &mut remaining, &mut runs)?;
+            }
+        }
+        Ok(Data::Runs(runs))
+    }
+
+    /// Collects the logical block, physical block and length of the extents of a tree node,
+    /// without a physical block for the uninitialized ones that read as zeroes.
+    fn extents<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        node: &[u8],
+        level: usize,
+        out: &mut Vec<(u64, Option<u64>, u64)>,
+    ) -> Result<(), ArchiveError> {
+        if node.len() < 12 || le_u16(node, 0) != EXT_EXTENT_MAGIC || level > 5 {
+            return Err(invalid_data("invalid ext extent tree".to_string()));
+        }
+        let entries = (le_u16(node, 2) as usize).min(node.len() / 12 - 1);
+        let depth = le_u16(node, 6);
+        for entry in node[12..].chunks_exact(12).take(entries) {
+            if depth > 0 {
+                let leaf = (le_u16(entry, 8) as u64) << 32 | le_u32(entry, 4) as u64;
+                let mut child = vec![0; self.superblock.block_size as usize];
+                reader.seek(SeekFrom::Start(self.block_offset(leaf)))?;
+                reader.read_exact(&mut child)?;
+                self.extents(reader, &child, level + 1, out)?;
+            } else {
+                let length = le_u16(entry, 4) as u64;
+                let physical = (le_u16(entry, 6) as u64) << 32 | le_u32(entry, 8) as u64;
+                out.push(match length > 32768 {
+                    true => (le_u32(entry, 0) as u64, None, length - 32768),
+                    false => (le_u32(entry, 0) as u64, Some(physical), length),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends the runs of the blocks `pointer` maps through `depth` levels of indirection.
+    fn map_blocks<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        pointer: u32,
+        depth: u32,
+        remaining: &mut u64,
+        runs: &mut Vec<Run>,
+    ) -> Result<(), ArchiveError> {
+        if *remaining == 0 {
+            return Ok(());
+        }
+        let block_size = self.superblock.block_size;
+        if pointer == 0 {
+            // a hole as large as all the blocks the pointer would map
+            let covered = (block_size / 4).saturating_pow(depth).min(*remaining);
+            push_run(runs, None, covered * block_size);
+            *remaining -= covered;
+            return Ok(());
+        }
+        if depth == 0 {
+            push_run(runs, Some(self.block_offset(pointer as u64)), block_size);
+            *remaining -= 1;
+            return Ok(());
+        }
+
+        let mut pointers = vec![0; block_size as usize];
+        reader.seek(SeekFrom::Start(self.block_offset(pointer as u64)))?;
+        reader.read_exact(&mut pointers)?;
+        for at in (0..pointers.len()).step_by(4) {
+            self.map_blocks(reader, le_u32(&pointers, at), depth - 1, remaining, runs)?;
+        }
+        Ok(())
+    }
+
+    fn walk<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        prefix: &str,
+    ) -> Result<Vec<Node>, ArchiveError> {
+        let mut nodes = Vec::new();
+        let mut visited = HashSet::from([EXT_ROOT_INODE]);
+        let mut pending = vec![(prefix.to_string(), EXT_ROOT_INODE, 0)];
+        while let Some((path, number, depth)) = pending.pop() {
+            let inode = self.inode(reader, number)?;
+            let data = self.data(reader, &inode)?;
+            let mut contents = read_data(reader, &data, inode.size)?;
+            if inode.flags & EXT_INLINE_DATA_FL != 0 {
+                // inline directories start with the inode of their parent
+                contents.drain(..contents.len().min(4));
+            }
+
+            for (child, name) in ext_entries(&contents) {
+                let name = format!("{}{}", path, name);
+                let inode = self.inode(reader, child)?;
+                let mode = Some((inode.mode & 0o7777) as u32);
+                let modified = datetime_from_timestamp(inode.modified as i64).ok();
+                let (kind, size, data) = match inode.mode & S_IFMT {
+                    S_IFDIR => {
+                        if depth >= MAX_DEPTH || !visited.insert(child) {
+                            return Err(invalid_data(format!("ext directory loop at {}", name)));
+                        }
+                        pending.push((format!("{}/", name), child, depth + 1));
+                        (NodeKind::Directory, 0, Data::Runs(Vec::new()))
+                    }
+                    S_IFREG => (NodeKind::File, inode.size, self.data(reader, &inode)?),
+                    S_IFLNK => {
+                        // short targets are stored in place of the block map
+                        let fast = inode.flags & (EXT_EXTENTS_FL | EXT_INLINE_DATA_FL) == 0
+                            && inode.size < 60;
+                        let target = match fast {
+                            true => inode.block[..inode.size as usize].to_vec(),
+                            false => {
+                                let data = self.data(reader, &inode)?;
+                                read_data(reader, &data, inode.size)?
+                            }
+                        };
+                        let target = String::from_utf8_lossy(&target).to_string();
+                        (NodeKind::Symlink(target), 0, Data::Runs(Vec::new()))
+                    }
+                    // devices, pipes and sockets
+                    _ => continue,
+                };
+                nodes.push(Node {
+                    path: name,
+                    kind,
+                    mode,
+                    modified,
+                    size,
+                    data,
+                });
+            }
+        }
+        Ok(nodes)
+    }
+}
+
+/// Inodes and names of the entries of an ext directory, whose hash tree blocks, if any, read
+/// as empty entries.
+fn ext_entries(directory: &[u8]) -> Vec<(u32, String)> {
+    let mut entries = Vec::new();
+    let mut position = 0;
+    while position + 8 <= directory.len() {
+        let inode = le_u32(directory, position);
+        let length = le_u16(directory, position + 4) as usize;
+        let name_length = directory[position + 6] as usize;
+        if length < 8 {
+            break;
+        }
+        let name = &directory[(position + 8).min(directory.len())
+            ..(position + 8 + name_length).min(directory.len())];
+        if inode != 0 && name != b"." && name != b".." {
+            entries.push((inode, String::from_utf8_lossy(name).to_string()));
+        }
+        position += length;
+    }
+    entries
+}
+
+enum Volume {
+    Fat(FatVolume),
+    Ext(ExtVolume),
+}
+
+impl Volume {
+    fn open<R: Read + Seek>(reader: &mut R, filesystem: &Filesystem) -> Result<Self, ArchiveError> {
+        match filesystem.kind {
+            FsKind::Fat12 | FsKind::Fat16 | FsKind::Fat32 => {
+                Ok(Volume::Fat(FatVolume::open(reader, filesystem.offset)?))
+            }
+            FsKind::Ext2 | FsKind::Ext3 | FsKind::Ext4 => {
+                Ok(Volume::Ext(ExtVolume::open(reader, filesystem.offset)?))
+            }
+        }
+    }
+
+    fn walk<R: Read + Seek>(
+        &self,
+        reader: &mut R,
+        prefix: &str,
+    ) -> Result<Vec<Node>, ArchiveError> {
+        match self {
+            Volume::Fat(volume) => volume.walk(reader, prefix),
+            Volume::Ext(volume) => volume.walk(reader, prefix),
+        }
+    }
+
+    fn label(&self) -> &str {
+        match self {
+            Volume::Fat(volume) => &volume.geometry.label,
+            Volume::Ext(volume) => &volume.superblock.label,
+        }
+    }
+
+    fn block_size(&self) -> u64 {
+        match self {
+            Volume::Fat(volume) => volume.geometry.cluster_size,
+            Volume::Ext(volume) => volume.superblock.block_size,
+        }
+    }
+}
+
+/// Nodes of every filesystem of the image, each under a directory named after the number of its
+/// partition when there are several.
+fn read_nodes<R: Read + Seek>(reader: &mut R) -> Result<Vec<Node>, ArchiveError> {
+    let filesystems = find_filesystems(reader)?;
+    if filesystems.is_empty() {
+        return Err(invalid_data(
+            "no FAT or ext filesystem in disk image".to_string(),
+        ));
+    }
+
+    let mut nodes = Vec::new();
+    for filesystem in &filesystems {
+        let prefix = match (filesystems.len(), filesystem.partition) {
+            (2.., Some(number)) => {
+                nodes.push(Node {
+                    path: number.to_string(),
+                    kind: NodeKind::Directory,
+                    mode: None,
+                    modified: None,
+                    size: 0,
+                    data: Data::Runs(Vec::new()),
+                });
+                format!("{}/", number)
+            }
+            _ => String::new(),
+        };
+        let volume = Volume::open(reader, filesystem)?;
+        nodes.extend(volume.walk(reader, &prefix)?);
+    }
+    nodes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(nodes)
+}
+
+pub struct DiskImageArchive {
+    pub(crate) source: DataSource,
+    scan: OnceCell<ArchiveScan>,
+}
+
+impl DiskImageArchive {
+    fn scan(&self) -> Result<&ArchiveScan, ArchiveError> {
+        ArchiveScan::cached(&self.scan, || scan_entries(self, None, None))
+    }
+}
+
+impl EntryVisitor for DiskImageArchive {
+    fn for_each_entry<F>(
+        &self,
+        options: &VisitOptions<'_>,
+        mut visit: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        let source = options
+            .retry
+            .wrap(self.source.clone(), &self.source, options.events);
+        let mut reader = BufReader::with_capacity(DEFAULT_BUF_SIZE, source);
+
+        for node in read_nodes(&mut reader)? {
+            let (name, fstype, size, link) = match node.kind {
+                NodeKind::Directory => (
+                    format!("{}/", node.path),
+                    ArchiveFileEntityType::Directory,
+                    None,
+                    None,
+                ),
+                NodeKind::File => (
+                    node.path,
+                    ArchiveFileEntityType::File,
+                    Some(node.size),
+                    None,
+                ),
+                NodeKind::Symlink(target) => (
+                    node.path,
+                    ArchiveFileEntityType::SymbolicLink,
+                    None,
+                    Some(EntryLink::Symbolic(target)),
+                ),
+            };
+            let visited = VisitedEntry {
+                entity: ArchiveFileEntity {
+                    name,
+                    size,
+                    compressed_size: None,
+                    last_modified: node.modified,
+                    compression: None,
+                    fstype,
+                    raw: None,
+                    unix: None,
+                },
+                mode: node.mode,
+                link,
+                crc32: None,
+            };
+            if !options.wants(&visited.entity) {
+                continue;
+            }
+
+            let wants_contents =
+                options.contents && visited.entity.fstype == ArchiveFileEntityType::File;
+            let flow = match (&node.data, wants_contents) {
+                (Data::Runs(runs), true) => {
+                    let mut contents = RunReader::new(&mut reader, runs, node.size);
+                    visit(visited, Some(&mut contents))?
+                }
+                (Data::Inline(data), true) => {
+                    let mut contents = &data[..data.len().min(node.size as usize)];
+                    visit(visited, Some(&mut contents))?
+                }
+                (_, false) => visit(visited, None)?,
+            };
+            if flow.is_break() {
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Archived for DiskImageArchive {
+    fn of(source: DataSource) -> Result<Self, ArchiveError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            source,
+            scan: OnceCell::new(),
+        })
+    }
+
+    fn extract(&self, options: ExtractOptions) -> Result<ExtractReport, ArchiveError> {
+        extract_entries(self, &options, self.source.as_ref())
+    }
+
+    fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
+        if options.is_whole() {
+            return Ok(self.scan()?.entries.clone());
+        }
+        list_entries(self, self.scan.get(), &options)
+    }
+
+    fn create(_options: CreateOptions) -> Result<CreateResult, ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "create".to_string(),
+            ArchiveType::DiskImage,
+        ))
+    }
+
+    fn metadata(&self) -> Result<ArchiveMetadata, ArchiveError> {
+        let mut source = BufReader::with_capacity(DEFAULT_BUF_SIZE, self.source.clone());
+        let size = source.seek(SeekFrom::End(0))?;
+        let mut filesystems = Vec::new();
+        for filesystem in find_filesystems(&mut source)? {
+            let volume = Volume::open(&mut source, &filesystem)?;
+            filesystems.push(json!({
+                "partition": filesystem.partition,
+                "offset": filesystem.offset,
+                "type": filesystem.kind.name(),
+                "label": volume.label(),
+                "block_size": volume.block_size(),
+            }));
+        }
+
+        let scan = self.scan()?;
+
+        Ok(ArchiveMetadata {
+            archive_type: ArchiveType::DiskImage,
+            entry_count: scan.entries.len(),
+            entries: scan.entries.clone(),
+            total_size: scan.total_size,
+            compressed_size: size,
+            compression: None,
+            created: self.source.created(),
+            modified: self.source.modified(),
+            additional: Some(json!({ "filesystems": filesystems })),
+        })
+    }
+
+    fn open(&self, options: OpenOptions) -> Result<(), ArchiveError> {
+        open_entry(self, options)
+    }
+
+    fn find_first<P>(
+        &self,
+        predicate: P,
+    ) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+    where
+        P: Fn(&ArchiveFileEntity) -> bool,
+    {
+        find_first_entry(self, predicate)
+    }
+
+    fn check_password(&self, _password: &str) -> Result<bool, ArchiveError> {
+        Ok(true)
+    }
+
+    fn edit(&self, _entry: &str, _edit: &EntryEdit) -> Result<(), ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "edit".to_string(),
+            ArchiveType::DiskImage,
+        ))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::archive::Archive;
+
+    fn assert_reads_test1(archive: &Archive, prefix: &str) {
+        for (name, path) in [
+            ("test1/file1.txt", "tests/fixtures/test1/file1.txt"),
+            (
+                "test1/dir1/file2.txt",
+                "tests/fixtures/test1/dir1/file2.txt",
+            ),
+        ] {
+            let name = format!("{}{}", prefix, name);
+            let (entity, content) = archive.find_first(|e| e.name == name).unwrap().unwrap();
+            assert_eq!(content, std::fs::read(path).unwrap());
+            assert_eq!(entity.size, Some(content.len() as u64));
+        }
+    }
+
+    #[test]
+    fn reads_partitioned_fat_images() {
+        let archive =
+            Archive::of(DataSource::file("tests/fixtures/test1-fat.img").unwrap()).unwrap();
+        assert!(matches!(archive, Archive::DiskImage(_)));
+
+        let names = archive
+            .list(ListOptions::default())
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "1/",
+                "1/README.TXT",
+                "1/test1/",
+                "1/test1/dir1/",
+                "1/test1/dir1/file2.txt",
+                "1/test1/file1.txt",
+                "2/",
+                "2/A long file name.txt",
+            ]
+        );
+        assert_reads_test1(&archive, "1/");
+    }
+
+    #[test]
+    fn reads_ext4_images() {
+        let archive =
+            Archive::of(DataSource::file("tests/fixtures/test1-ext4.img").unwrap()).unwrap();
+        assert!(matches!(archive, Archive::DiskImage(_)));
+
+        let entries = archive.list(ListOptions::default()).unwrap();
+        let names = entries.iter().map(|e| e.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "lost+found/",
+                "test1/",
+                "test1/dir1/",
+                "test1/dir1/file2.txt",
+                "test1/file1.txt",
+                "test1/link",
+            ]
+        );
+        assert_eq!(entries[5].fstype, ArchiveFileEntityType::SymbolicLink);
+        assert_reads_test1(&archive, "");
+    }
+
+    #[test]
+    fn rejects_sizes_past_the_blocks() {
+        let runs = Data::Runs(vec![Run {
+            offset: Some(0),
+            length: 1024,
+        }]);
+        let mut image = std::io::Cursor::new(vec![7; 1024]);
+        assert_eq!(read_data(&mut image, &runs, 10).unwrap(), vec![7; 10]);
+        assert!(matches!(
+            read_data(&mut image, &runs, u64::MAX),
+            Err(ArchiveError::Io(e)) if e.kind() == ErrorKind::InvalidData
+        ));
+    }
+}
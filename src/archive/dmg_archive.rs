@@ -0,0 +1,970 @@
+// read-only support for Apple disk images (UDIF), whose 512 byte "koly" trailer points to an XML
+// property list. Its "blkx" tables describe each partition of the disk as chunks of sectors,
+// stored as is, filled with zeroes or compressed on their own. The disk is decompressed chunk
+// by chunk while the HFS+ volume it holds is read: its catalog B-tree gives the files and
+// folders, whose contents lie in extents of allocation blocks. APFS volumes are not read.
+
+use std::{
+    cell::OnceCell,
+    collections::{BTreeMap, HashMap},
+    io::{BufReader, ErrorKind, Read, Seek, SeekFrom},
+    ops::ControlFlow,
+};
+
+use serde_json::json;
+
+use crate::archive::{
+    datetime_from_timestamp,
+    entry_visitor::{
+        extract_entries, find_first_entry, list_entries, open_entry, scan_entries, EntryLink,
+        EntryVisitor, VisitOptions, VisitedEntry,
+    },
+    ArchiveError, ArchiveFileEntity, ArchiveFileEntityType, ArchiveMetadata, ArchiveScan,
+    ArchiveType, Archived, CreateOptions, CreateResult, DataSource, ExtractOptions, ExtractReport,
+    ListOptions, OpenOptions, DEFAULT_BUF_SIZE,
+};
+
+#[cfg(feature = "lzma_codecs")]
+use super::codecs::XzReader;
+use super::edit::EntryEdit;
+
+const SECTOR_SIZE: u64 = 512;
+const TRAILER_SIZE: i64 = 512;
+
+const CHUNK_ZERO: u32 = 0x0000_0000;
+const CHUNK_RAW: u32 = 0x0000_0001;
+const CHUNK_IGNORED: u32 = 0x0000_0002;
+const CHUNK_ADC: u32 = 0x8000_0004;
+const CHUNK_ZLIB: u32 = 0x8000_0005;
+const CHUNK_BZIP2: u32 = 0x8000_0006;
+const CHUNK_LZFSE: u32 = 0x8000_0007;
+const CHUNK_LZMA: u32 = 0x8000_0008;
+const CHUNK_COMMENT: u32 = 0x7fff_fffe;
+const CHUNK_END: u32 = 0xffff_ffff;
+
+/// Seconds between the HFS epoch, 1904, and the Unix one.
+const HFS_EPOCH_OFFSET: i64 = 2_082_844_800;
+const ROOT_FOLDER_ID: u32 = 2;
+const S_IFMT: u16 = 0o170000;
+const S_IFLNK: u16 = 0o120000;
+/// Owner flag of files whose contents are compressed in an extended attribute.
+const UF_COMPRESSED: u8 = 0x20;
+/// Longest symbolic link target, `PATH_MAX` of macOS.
+const MAX_LINK_TARGET: u64 = 1024;
+
+fn invalid_data(message: String) -> ArchiveError {
+    ArchiveError::Io(std::io::Error::new(ErrorKind::InvalidData, message))
+}
+
+fn io_error(error: ArchiveError) -> std::io::Error {
+    match error {
+        ArchiveError::Io(e) => e,
+        e => std::io::Error::other(e.to_string()),
+    }
+}
+
+fn be_u16(buf: &[u8], at: usize) -> u16 {
+    u16::from_be_bytes([buf[at], buf[at + 1]])
+}
+
+fn be_u32(buf: &[u8], at: usize) -> u32 {
+    u32::from_be_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+}
+
+fn be_u64(buf: &[u8], at: usize) -> u64 {
+    (be_u32(buf, at) as u64) << 32 | be_u32(buf, at + 4) as u64
+}
+
+fn chunk_name(kind: u32) -> &'static str {
+    match kind {
+        CHUNK_ZERO | CHUNK_IGNORED => "zero",
+        CHUNK_RAW => "raw",
+        CHUNK_ADC => "adc",
+        CHUNK_ZLIB => "zlib",
+        CHUNK_BZIP2 => "bzip2",
+        CHUNK_LZFSE => "lzfse",
+        CHUNK_LZMA => "lzma",
+        _ => "unknown",
+    }
+}
+
+/// Decodes base64, skipping the whitespace property lists wrap it with.
+fn decode_base64(text: &str) -> Result<Vec<u8>, ArchiveError> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in text.bytes() {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' | b' ' | b'\t' | b'\r' | b'\n' => continue,
+            _ => {
+                return Err(invalid_data(
+                    "invalid base64 in DMG property list".to_string(),
+                ))
+            }
+        };
+        acc = acc << 6 | value as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    Ok(out)
+}
+
+/// Text between `open` and `close` in `text`, and what follows it.
+fn between<'t>(text: &'t str, open: &str, close: &str) -> Option<(&'t str, &'t str)> {
+    let start = text.find(open)? + open.len();
+    let len = text[start..].find(close)?;
+    Some((
+        &text[start..start + len],
+        &text[start + len + close.len()..],
+    ))
+}
+
+/// A run of sectors of the disk.
+#[derive(Debug, Clone)]
+struct Chunk {
+    kind: u32,
+    sector: u64,
+    sectors: u64,
+    /// Where its data lies in the image.
+    offset: u64,
+    length: u64,
+}
+
+#[derive(Debug)]
+struct Partition {
+    name: String,
+    sector: u64,
+    sectors: u64,
+}
+
+/// The partitions and chunks of an image, from its trailer and blkx tables.
+struct Image {
+    partitions: Vec<Partition>,
+    chunks: Vec<Chunk>,
+}
+
+impl Image {
+    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, ArchiveError> {
+        let mut trailer = [0; TRAILER_SIZE as usize];
+        reader.seek(SeekFrom::End(-TRAILER_SIZE))?;
+        reader.read_exact(&mut trailer)?;
+        if &trailer[..4] != b"koly" {
+            return Err(invalid_data("missing DMG trailer".to_string()));
+        }
+        let data_offset = be_u64(&trailer, 24);
+        let (xml_offset, xml_length) = (be_u64(&trailer, 216), be_u64(&trailer, 224));
+        if xml_length == 0 {
+            return Err(ArchiveError::Io(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "DMG images without a property list are not supported",
+            )));
+        }
+
+        let mut xml = Vec::new();
+        reader.seek(SeekFrom::Start(xml_offset))?;
+        reader.take(xml_length).read_to_end(&mut xml)?;
+        let xml = String::from_utf8_lossy(&xml);
+        let (blkx, _) = between(&xml, "<key>blkx</key>", "</array>")
+            .ok_or_else(|| invalid_data("missing blkx tables in DMG".to_string()))?;
+
+        let mut partitions = Vec::new();
+        let mut chunks = Vec::new();
+        for dict in blkx.split("<dict>").skip(1) {
+            let Some((data, _)) = between(dict, "<key>Data</key>", "</data>") else {
+                continue;
+            };
+            let table = decode_base64(data.trim_start().trim_start_matches("<data>"))?;
+            if table.len() < 204 || &table[..4] != b"mish" {
+                return Err(invalid_data("invalid DMG blkx table".to_string()));
+            }
+            let name = between(dict, "<key>Name</key>", "</string>")
+                .or_else(|| between(dict, "<key>CFName</key>", "</string>"))
+                .map(|(name, _)| name.trim_start().trim_start_matches("<string>"))
+                .unwrap_or_default();
+            let (first, base) = (be_u64(&table, 8), data_offset + be_u64(&table, 24));
+            partitions.push(Partition {
+                name: name.replace("&amp;", "&"),
+                sector: first,
+                sectors: be_u64(&table, 16),
+            });
+
+            let count = be_u32(&table, 200) as usize;
+            for entry in table[204..].chunks_exact(40).take(count) {
+                let kind = be_u32(entry, 0);
+                match kind {
+                    CHUNK_COMMENT => continue,
+                    CHUNK_END => break,
+                    _ => chunks.push(Chunk {
+                        kind,
+                        sector: first + be_u64(entry, 8),
+                        sectors: be_u64(entry, 16),
+                        offset: base + be_u64(entry, 24),
+                        length: be_u64(entry, 32),
+                    }),
+                }
+            }
+        }
+        chunks.sort_by_key(|c| c.sector);
+        Ok(Self { partitions, chunks })
+    }
+
+    /// Number of chunks of each kind.
+    fn chunk_counts(&self) -> BTreeMap<&'static str, usize> {
+        let mut counts = BTreeMap::new();
+        for chunk in &self.chunks {
+            *counts.entry(chunk_name(chunk.kind)).or_default() += 1;
+        }
+        counts
+    }
+}
+
+/// Apple Data Compression, an LZ77 variant of the oldest compressed images.
+fn decompress_adc(input: &[u8], size: usize) -> Result<Vec<u8>, ArchiveError> {
+    let invalid = || invalid_data("invalid ADC data in DMG".to_string());
+    let mut out = Vec::with_capacity(size);
+    let mut at = 0;
+    while at < input.len() && out.len() < size {
+        let byte = input[at];
+        let (len, distance) = if byte & 0x80 != 0 {
+            let len = (byte & 0x7f) as usize + 1;
+            out.extend_from_slice(input.get(at + 1..at + 1 + len).ok_or_else(invalid)?);
+            at += 1 + len;
+            continue;
+        } else if byte & 0x40 != 0 {
+            let distance = input.get(at + 1..at + 3).ok_or_else(invalid)?;
+            at += 3;
+            ((byte & 0x3f) as usize + 4, be_u16(distance, 0) as usize)
+        } else {
+            let low = *input.get(at + 1).ok_or_else(invalid)?;
+            at += 2;
+            (
+                ((byte >> 2) & 0xf) as usize + 3,
+                ((byte & 3) as usize) << 8 | low as usize,
+            )
+        };
+        let start = out.len().checked_sub(distance + 1).ok_or_else(invalid)?;
+        for i in 0..len {
+            out.push(out[start + i]);
+        }
+    }
+    Ok(out)
+}
+
+/// The disk described by the chunks, read and decompressed on demand.
+struct Disk<'i, R> {
+    reader: R,
+    chunks: &'i [Chunk],
+    position: u64,
+    /// The last decompressed chunk.
+    cache: Option<(usize, Vec<u8>)>,
+}
+
+impl<R: Read + Seek> Disk<'_, R> {
+    fn decompress(&mut self, index: usize) -> Result<Vec<u8>, ArchiveError> {
+        let chunk = &self.chunks[index];
+        let size = (chunk.sectors * SECTOR_SIZE) as usize;
+        self.reader.seek(SeekFrom::Start(chunk.offset))?;
+        let mut compressed = (&mut self.reader).take(chunk.length);
+        let mut out = Vec::with_capacity(size);
+        match chunk.kind {
+            CHUNK_ADC => {
+                let mut input = Vec::new();
+                compressed.read_to_end(&mut input)?;
+                out = decompress_adc(&input, size)?;
+            }
+            CHUNK_ZLIB => {
+                flate2::read::ZlibDecoder::new(compressed).read_to_end(&mut out)?;
+            }
+            #[cfg(feature = "bzip2_codecs")]
+            CHUNK_BZIP2 => {
+                bzip2::read::BzDecoder::new(compressed).read_to_end(&mut out)?;
+            }
+            #[cfg(feature = "lzma_codecs")]
+            CHUNK_LZMA => {
+                XzReader::new(BufReader::new(compressed), 1).read_to_end(&mut out)?;
+            }
+            kind => {
+                return Err(ArchiveError::Io(std::io::Error::new(
+                    ErrorKind::Unsupported,
+                    format!(
+                        "{} compressed DMG chunks are not supported",
+                        chunk_name(kind)
+                    ),
+                )))
+            }
+        }
+        // decompressors may leave the end of the last sector out
+        out.resize(size, 0);
+        Ok(out)
+    }
+}
+
+impl<R: Read + Seek> Read for Disk<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let sector = self.position / SECTOR_SIZE;
+        let index = self
+            .chunks
+            .partition_point(|c| c.sector + c.sectors <= sector);
+        let Some(chunk) = self.chunks.get(index).filter(|c| c.sector <= sector) else {
+            return Ok(0);
+        };
+        let start = self.position - chunk.sector * SECTOR_SIZE;
+        let n = buf
+            .len()
+            .min((chunk.sectors * SECTOR_SIZE - start) as usize);
+        match chunk.kind {
+            CHUNK_ZERO | CHUNK_IGNORED => buf[..n].fill(0),
+            CHUNK_RAW => {
+                self.reader.seek(SeekFrom::Start(chunk.offset + start))?;
+                self.reader.read_exact(&mut buf[..n])?;
+            }
+            _ => {
+                if self.cache.as_ref().map(|(i, _)| *i) != Some(index) {
+                    let data = self.decompress(index).map_err(io_error)?;
+                    self.cache = Some((index, data));
+                }
+                let data = &self
+                    .cache
+                    .as_ref()
+                    .map(|(_, d)| d)
+                    .unwrap_or_else(|| unreachable!());
+                buf[..n].copy_from_slice(&data[start as usize..start as usize + n]);
+            }
+        }
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R> Seek for Disk<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.position = match pos {
+            SeekFrom::Start(offset) => offset,
+            _ => {
+                return Err(std::io::Error::new(
+                    ErrorKind::Unsupported,
+                    "DMG disks are only seeked from their start",
+                ))
+            }
+        };
+        Ok(self.position)
+    }
+}
+
+/// Allocation blocks holding a fork, as start block and block count.
+#[derive(Debug, Clone, Default)]
+struct Fork {
+    size: u64,
+    extents: Vec<(u32, u32)>,
+}
+
+impl Fork {
+    /// Reads a fork data structure, with its first 8 extents.
+    fn parse(buf: &[u8]) -> Self {
+        Self {
+            size: be_u64(buf, 0),
+            extents: extents(&buf[16..80]),
+        }
+    }
+
+    fn blocks(&self) -> u64 {
+        self.extents.iter().map(|(_, count)| *count as u64).sum()
+    }
+}
+
+/// The used extents of an extent record, 8 pairs of start block and block count.
+fn extents(buf: &[u8]) -> Vec<(u32, u32)> {
+    (0..8)
+        .map(|i| (be_u32(buf, 8 * i), be_u32(buf, 8 * i + 4)))
+        .filter(|(_, count)| *count > 0)
+        .collect()
+}
+
+/// An HFS+ or HFSX volume on the disk.
+struct Volume<R> {
+    disk: R,
+    start: u64,
+    block_size: u64,
+    signature: &'static str,
+    /// Extents past the first 8 of forks, by file id and fork type.
+    overflow: HashMap<(u32, u8), Vec<(u32, u32)>>,
+}
+
+impl<R: Read + Seek> Volume<R> {
+    fn read_at(&mut self, offset: u64, buf: &mut [u8]) -> Result<(), ArchiveError> {
+        self.disk.seek(SeekFrom::Start(self.start + offset))?;
+        self.disk.read_exact(buf)?;
+        Ok(())
+    }
+
+    /// Adds the overflowing extents of a fork.
+    fn complete(&self, mut fork: Fork, file_id: u32, fork_type: u8) -> Fork {
+        if let Some(extents) = self.overflow.get(&(file_id, fork_type)) {
+            fork.extents.extend(extents);
+        }
+        fork
+    }
+
+    fn read_fork(&mut self, fork: &Fork, offset: u64, buf: &mut [u8]) -> Result<(), ArchiveError> {
+        let mut done = 0;
+        let mut block_offset = 0;
+        for (start, count) in &fork.extents {
+            let len = *count as u64 * self.block_size;
+            let position = offset + done as u64;
+            if position < block_offset + len {
+                let within = position - block_offset;
+                let n = (buf.len() - done).min((len - within) as usize);
+                let at = *start as u64 * self.block_size + within;
+                self.read_at(at, &mut buf[done..done + n])?;
+                done += n;
+                if done == buf.len() {
+                    return Ok(());
+                }
+            }
+            block_offset += len;
+        }
+        Err(invalid_data("HFS+ fork shorter than expected".to_string()))
+    }
+
+    /// Calls `f` with the key and data of every record of the leaves of a B-tree, in order.
+    fn leaf_records<F>(&mut self, fork: &Fork, mut f: F) -> Result<(), ArchiveError>
+    where
+        F: FnMut(&mut Self, &[u8], &[u8]) -> Result<(), ArchiveError>,
+    {
+        let mut header = [0; 512];
+        self.read_fork(fork, 0, &mut header)?;
+        let first_leaf = be_u32(&header, 24);
+        let node_size = be_u16(&header, 32) as usize;
+        let total_nodes = be_u32(&header, 36);
+        if node_size < 512 {
+            return Err(invalid_data("invalid HFS+ B-tree node size".to_string()));
+        }
+
+        let mut node = vec![0; node_size];
+        let mut next = first_leaf;
+        let mut visited = 0;
+        while next != 0 {
+            visited += 1;
+            if visited > total_nodes {
+                return Err(invalid_data("HFS+ B-tree leaves loop".to_string()));
+            }
+            self.read_fork(fork, next as u64 * node_size as u64, &mut node)?;
+            next = be_u32(&node, 0);
+            let records = be_u16(&node, 10) as usize;
+            for i in 0..records {
+                let offset_at = |i: usize| node_size.checked_sub(2 * (i + 1));
+                let (Some(start), Some(end)) = (offset_at(i), offset_at(i + 1)) else {
+                    break;
+                };
+                let (start, end) = (be_u16(&node, start) as usize, be_u16(&node, end) as usize);
+                let key_len = be_u16(&node, start) as usize;
+                let (key, data) = match node.get(start + 2..end) {
+                    Some(record) if key_len <= record.len() => record.split_at(key_len),
+                    _ => return Err(invalid_data("invalid HFS+ B-tree record".to_string())),
+                };
+                f(self, key, data)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A file or folder of the catalog.
+#[derive(Debug)]
+struct CatalogEntry {
+    parent: u32,
+    name: String,
+    modified: u32,
+    mode: u16,
+    kind: CatalogKind,
+}
+
+#[derive(Debug)]
+enum CatalogKind {
+    Folder,
+    File {
+        fork: Fork,
+        compressed: bool,
+        /// The number of the iNode file in the private folder holding a hard link's contents.
+        link: Option<u32>,
+    },
+}
+
+/// The files and folders of the volume, by catalog node id.
+fn read_catalog<R: Read + Seek>(
+    volume: &mut Volume<R>,
+    catalog: &Fork,
+) -> Result<BTreeMap<u32, CatalogEntry>, ArchiveError> {
+    let mut entries = BTreeMap::new();
+    volume.leaf_records(catalog, |volume, key, data| {
+        if key.len() < 6 || data.len() < 2 {
+            return Err(invalid_data("invalid HFS+ catalog record".to_string()));
+        }
+        let name_len = be_u16(key, 4) as usize;
+        let name = key
+            .get(6..6 + 2 * name_len)
+            .ok_or_else(|| invalid_data("invalid HFS+ catalog key".to_string()))?
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect::<Vec<_>>();
+        // slashes are stored as colons on disk, and the other way round
+        let name = String::from_utf16_lossy(&name).replace('/', ":");
+        let (id, kind) = match be_u16(data, 0) {
+            1 if data.len() >= 88 => (be_u32(data, 8), CatalogKind::Folder),
+            2 if data.len() >= 248 => {
+                let id = be_u32(data, 8);
+                // the type and creator of hard links in their Finder information
+                let hard_link = &data[48..56] == b"hlnkhfs+";
+                let kind = CatalogKind::File {
+                    fork: volume.complete(Fork::parse(&data[88..168]), id, 0),
+                    compressed: data[41] & UF_COMPRESSED != 0,
+                    link: hard_link.then(|| be_u32(data, 44)),
+                };
+                (id, kind)
+            }
+            // thread records, mapping ids back to names
+            _ => return Ok(()),
+        };
+        entries.insert(
+            id,
+            CatalogEntry {
+                parent: be_u32(key, 0),
+                name,
+                modified: be_u32(data, 16),
+                mode: be_u16(data, 42),
+                kind,
+            },
+        );
+        Ok(())
+    })?;
+    Ok(entries)
+}
+
+/// Folders at the root of a volume holding the targets of hard links, and its journal.
+const PRIVATE_NAMES: [&str; 4] = [
+    "\0\0\0\0HFS+ Private Data",
+    ".HFS+ Private Directory Data\r",
+    ".journal",
+    ".journal_info_block",
+];
+
+/// Path of a catalog entry from the root folder, `None` for hidden ones.
+fn catalog_path(entries: &BTreeMap<u32, CatalogEntry>, id: u32) -> Option<String> {
+    let mut parts = Vec::new();
+    let mut current = id;
+    while current != ROOT_FOLDER_ID {
+        let entry = entries.get(&current)?;
+        if entry.parent == ROOT_FOLDER_ID && PRIVATE_NAMES.contains(&entry.name.as_str()) {
+            return None;
+        }
+        parts.push(entry.name.as_str());
+        current = entry.parent;
+        if parts.len() > entries.len() {
+            return None;
+        }
+    }
+    parts.reverse();
+    Some(parts.join("/"))
+}
+
+/// Reads a fork as a stream.
+struct ForkReader<'v, R> {
+    volume: &'v mut Volume<R>,
+    fork: &'v Fork,
+    position: u64,
+}
+
+impl<R: Read + Seek> Read for ForkReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = buf
+            .len()
+            .min(self.fork.size.saturating_sub(self.position) as usize);
+        self.volume
+            .read_fork(self.fork, self.position, &mut buf[..n])
+            .map_err(io_error)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+pub struct DmgArchive {
+    pub(crate) source: DataSource,
+    scan: OnceCell<ArchiveScan>,
+}
+
+impl DmgArchive {
+    fn scan(&self) -> Result<&ArchiveScan, ArchiveError> {
+        ArchiveScan::cached(&self.scan, || scan_entries(self, None, None))
+    }
+}
+
+/// Finds the HFS+ volume among the partitions of the disk.
+fn open_volume<'d, R: Read + Seek>(
+    image: &Image,
+    mut disk: Disk<'d, R>,
+) -> Result<(Volume<Disk<'d, R>>, Fork), ArchiveError> {
+    let mut apfs = false;
+    for partition in &image.partitions {
+        let start = partition.sector * SECTOR_SIZE;
+        let mut header = [0; 512];
+        disk.seek(SeekFrom::Start(start + 1024))?;
+        if disk.read_exact(&mut header).is_err() {
+            continue;
+        }
+        let signature = match &header[..2] {
+            b"H+" => "HFS+",
+            b"HX" => "HFSX",
+            _ => {
+                let mut magic = [0; 4];
+                disk.seek(SeekFrom::Start(start + 32))?;
+                apfs |= disk.read_exact(&mut magic).is_ok() && &magic == b"NXSB";
+                continue;
+            }
+        };
+        let mut volume = Volume {
+            disk,
+            start,
+            block_size: be_u32(&header, 40) as u64,
+            signature,
+            overflow: HashMap::new(),
+        };
+        if volume.block_size < 512 || !volume.block_size.is_power_of_two() {
+            return Err(invalid_data("invalid HFS+ block size".to_string()));
+        }
+
+        let extents = Fork::parse(&header[192..272]);
+        let catalog = Fork::parse(&header[272..352]);
+        if extents.size > 0 {
+            // keyed by fork type, then file id and start block, so in order within a fork
+            let mut overflow: HashMap<(u32, u8), Vec<(u32, u32)>> = HashMap::new();
+            volume.leaf_records(&extents, |_, key, data| {
+                if key.len() >= 10 && data.len() >= 64 {
+                    overflow
+                        .entry((be_u32(key, 2), key[0]))
+                        .or_default()
+                        .extend(self::extents(data));
+                }
+                Ok(())
+            })?;
+            volume.overflow = overflow;
+        }
+        // the catalog may overflow its own fork data too
+        let catalog = match catalog.blocks() * volume.block_size < catalog.size {
+            true => volume.complete(catalog, 4, 0),
+            false => catalog,
+        };
+        return Ok((volume, catalog));
+    }
+
+    Err(match apfs {
+        true => ArchiveError::Io(std::io::Error::new(
+            ErrorKind::Unsupported,
+            "APFS volumes are not supported",
+        )),
+        false => invalid_data("no HFS+ volume in DMG".to_string()),
+    })
+}
+
+impl EntryVisitor for DmgArchive {
+    fn for_each_entry<F>(
+        &self,
+        options: &VisitOptions<'_>,
+        mut visit: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        let source = options
+            .retry
+            .wrap(self.source.clone(), &self.source, options.events);
+        let mut reader = BufReader::with_capacity(DEFAULT_BUF_SIZE, source);
+        let image = Image::read(&mut reader)?;
+        let disk = Disk {
+            reader,
+            chunks: &image.chunks,
+            position: 0,
+            cache: None,
+        };
+        let (mut volume, catalog) = open_volume(&image, disk)?;
+        let entries = read_catalog(&mut volume, &catalog)?;
+
+        // contents of hard links, held by files named after their number in a private folder
+        let inodes = entries
+            .values()
+            .filter_map(|e| Some((e.name.strip_prefix("iNode")?.parse::<u32>().ok()?, e)))
+            .filter(|(_, e)| catalog_path(&entries, e.parent).is_none())
+            .collect::<HashMap<_, _>>();
+
+        let mut paths = entries
+            .iter()
+            .filter(|(id, _)| **id != ROOT_FOLDER_ID)
+            .filter_map(|(id, entry)| Some((catalog_path(&entries, *id)?, entry)))
+            .collect::<Vec<_>>();
+        paths.sort_by(|a, b| a.0.cmp(&b.0));
+
+        for (path, entry) in paths {
+            let last_modified = match entry.modified {
+                0 => None,
+                date => datetime_from_timestamp(date as i64 - HFS_EPOCH_OFFSET).ok(),
+            };
+            let (fork, compressed, mode) = match &entry.kind {
+                CatalogKind::Folder => {
+                    let visited = VisitedEntry {
+                        entity: ArchiveFileEntity {
+                            name: format!("{}/", path),
+                            size: None,
+                            compressed_size: None,
+                            last_modified,
+                            compression: None,
+                            fstype: ArchiveFileEntityType::Directory,
+                            raw: None,
+                            unix: None,
+                        },
+                        mode: Some((entry.mode & 0o7777) as u32).filter(|m| *m != 0),
+                        link: None,
+                        crc32: None,
+                    };
+                    if options.wants(&visited.entity) && visit(visited, None)?.is_break() {
+                        return Ok(());
+                    }
+                    continue;
+                }
+                CatalogKind::File {
+                    link: Some(inode), ..
+                } => match inodes.get(inode).map(|e| &e.kind) {
+                    Some(CatalogKind::File {
+                        fork, compressed, ..
+                    }) => (fork, *compressed, inodes[inode].mode),
+                    _ => {
+                        return Err(invalid_data(format!(
+                            "missing HFS+ hard link target of {}",
+                            path
+                        )))
+                    }
+                },
+                CatalogKind::File {
+                    fork, compressed, ..
+                } => (fork, *compressed, entry.mode),
+            };
+
+            let mut entity = ArchiveFileEntity {
+                name: path.clone(),
+                size: (!compressed).then_some(fork.size),
+                compressed_size: None,
+                last_modified,
+                compression: None,
+                fstype: ArchiveFileEntityType::File,
+                raw: None,
+                unix: None,
+            };
+            let mode = Some((mode & 0o7777) as u32).filter(|m| *m != 0);
+            if mode_is_symlink(entry.mode) {
+                entity.fstype = ArchiveFileEntityType::SymbolicLink;
+                entity.size = None;
+                if !options.wants(&entity) {
+                    continue;
+                }
+                if fork.size > MAX_LINK_TARGET || fork.size > fork.blocks() * volume.block_size {
+                    return Err(invalid_data(format!(
+                        "invalid HFS+ symbolic link target size of {}",
+                        path
+                    )));
+                }
+                let mut target = vec![0; fork.size as usize];
+                volume.read_fork(fork, 0, &mut target)?;
+                let visited = VisitedEntry {
+                    entity,
+                    mode,
+                    link: Some(EntryLink::Symbolic(
+                        String::from_utf8_lossy(&target).to_string(),
+                    )),
+                    crc32: None,
+                };
+                if visit(visited, None)?.is_break() {
+                    return Ok(());
+                }
+                continue;
+            }
+
+            if !options.wants(&entity) {
+                continue;
+            }
+            let visited = VisitedEntry {
+                entity,
+                mode,
+                link: None,
+                crc32: None,
+            };
+            let flow = if options.contents && compressed {
+                return Err(ArchiveError::Io(std::io::Error::new(
+                    ErrorKind::Unsupported,
+                    format!("{} is compressed by HFS+, which is not supported", path),
+                )));
+            } else if options.contents {
+                let mut reader = ForkReader {
+                    volume: &mut volume,
+                    fork,
+                    position: 0,
+                };
+                visit(visited, Some(&mut reader))?
+            } else {
+                visit(visited, None)?
+            };
+            if flow.is_break() {
+                return Ok(());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn mode_is_symlink(mode: u16) -> bool {
+    mode & S_IFMT == S_IFLNK
+}
+
+impl Archived for DmgArchive {
+    fn of(source: DataSource) -> Result<Self, ArchiveError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            source,
+            scan: OnceCell::new(),
+        })
+    }
+
+    fn extract(&self, options: ExtractOptions) -> Result<ExtractReport, ArchiveError> {
+        extract_entries(self, &options, self.source.as_ref())
+    }
+
+    fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
+        if options.is_whole() {
+            return Ok(self.scan()?.entries.clone());
+        }
+        list_entries(self, self.scan.get(), &options)
+    }
+
+    fn create(_options: CreateOptions) -> Result<CreateResult, ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "create".to_string(),
+            ArchiveType::Dmg,
+        ))
+    }
+
+    fn metadata(&self) -> Result<ArchiveMetadata, ArchiveError> {
+        let mut source = BufReader::with_capacity(DEFAULT_BUF_SIZE, self.source.clone());
+        let image = Image::read(&mut source)?;
+        let size = source.seek(SeekFrom::End(0))?;
+        let partitions = image
+            .partitions
+            .iter()
+            .map(|p| json!({ "name": p.name, "first_sector": p.sector, "sectors": p.sectors }))
+            .collect::<Vec<_>>();
+        let chunks = image.chunk_counts();
+        let disk = Disk {
+            reader: source,
+            chunks: &image.chunks,
+            position: 0,
+            cache: None,
+        };
+        let (volume, _) = open_volume(&image, disk)?;
+        let additional = json!({
+            "filesystem": volume.signature,
+            "block_size": volume.block_size,
+            "partitions": partitions,
+            "chunks": chunks,
+        });
+
+        let scan = self.scan()?;
+
+        Ok(ArchiveMetadata {
+            archive_type: ArchiveType::Dmg,
+            entry_count: scan.entries.len(),
+            entries: scan.entries.clone(),
+            total_size: scan.total_size,
+            compressed_size: size,
+            compression: None,
+            created: self.source.created(),
+            modified: self.source.modified(),
+            additional: Some(additional),
+        })
+    }
+
+    fn open(&self, options: OpenOptions) -> Result<(), ArchiveError> {
+        open_entry(self, options)
+    }
+
+    fn find_first<P>(
+        &self,
+        predicate: P,
+    ) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+    where
+        P: Fn(&ArchiveFileEntity) -> bool,
+    {
+        find_first_entry(self, predicate)
+    }
+
+    fn check_password(&self, _password: &str) -> Result<bool, ArchiveError> {
+        // encrypted images have an "encrcdsa" header instead of a readable trailer
+        Ok(true)
+    }
+
+    fn edit(&self, _entry: &str, _edit: &EntryEdit) -> Result<(), ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "edit".to_string(),
+            ArchiveType::Dmg,
+        ))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::archive::Archive;
+
+    #[test]
+    fn reads_hfs_plus_images() {
+        let archive = Archive::of(DataSource::file("tests/fixtures/test1.dmg").unwrap()).unwrap();
+        assert!(matches!(archive, Archive::Dmg(_)));
+
+        let entries = archive.list(ListOptions::default()).unwrap();
+        let names = entries.iter().map(|e| e.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "readme.txt",
+                "test1/",
+                "test1/dir1/",
+                "test1/dir1/file2.txt",
+                "test1/file1.txt",
+                "test1/link",
+            ]
+        );
+        assert_eq!(entries[5].fstype, ArchiveFileEntityType::SymbolicLink);
+
+        for (name, path) in [
+            ("test1/file1.txt", "tests/fixtures/test1/file1.txt"),
+            (
+                "test1/dir1/file2.txt",
+                "tests/fixtures/test1/dir1/file2.txt",
+            ),
+        ] {
+            let (entity, content) = archive.find_first(|e| e.name == name).unwrap().unwrap();
+            assert_eq!(content, std::fs::read(path).unwrap());
+            assert_eq!(entity.size, Some(content.len() as u64));
+        }
+        let (_, readme) = archive
+            .find_first(|e| e.name == "readme.txt")
+            .unwrap()
+            .unwrap();
+        assert_eq!(readme, b"stored as is\n");
+    }
+}
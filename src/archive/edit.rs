@@ -0,0 +1,124 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, FixedOffset};
+
+use super::{entry_visitor::normalized_name, ArchiveError, DataSource};
+
+/// Change to the attributes of an entry, see [`Archived::rename`](super::Archived::rename) and
+/// [`Archived::touch`](super::Archived::touch).
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntryEdit {
+    /// Renames the entry, and the entries under it if it is a directory.
+    Rename(String),
+    /// Sets the modification time of the entry.
+    Touch(DateTime<FixedOffset>),
+}
+
+fn trimmed(name: &str) -> &str {
+    normalized_name(name).trim_end_matches('/')
+}
+
+impl EntryEdit {
+    /// Whether editing `target` changes the entry `name`.
+    pub(crate) fn applies(&self, target: &str, name: &str) -> bool {
+        let (target, name) = (trimmed(target), trimmed(name));
+        match self {
+            EntryEdit::Rename(_) => {
+                name == target
+                    || name
+                        .strip_prefix(target)
+                        .is_some_and(|rest| rest.starts_with('/'))
+            }
+            EntryEdit::Touch(_) => name == target,
+        }
+    }
+
+    /// New name of the entry `name` when editing `target`, if it changes.
+    pub(crate) fn renamed(&self, target: &str, name: &str) -> Option<String> {
+        let EntryEdit::Rename(new_name) = self else {
+            return None;
+        };
+        if !self.applies(target, name) {
+            return None;
+        }
+
+        let rest = &trimmed(name)[trimmed(target).len()..];
+        let directory = if name.ends_with('/') { "/" } else { "" };
+        Some(format!("{}{}{}", trimmed(new_name), rest, directory))
+    }
+
+    /// New modification time of the entry `name` when editing `target`, if it changes.
+    pub(crate) fn modified(&self, target: &str, name: &str) -> Option<DateTime<FixedOffset>> {
+        match self {
+            EntryEdit::Touch(modified) if self.applies(target, name) => Some(*modified),
+            _ => None,
+        }
+    }
+}
+
+/// Path of the archive read from `source`, which must be a file to be edited.
+pub(crate) fn archive_path(source: &DataSource) -> Result<PathBuf, ArchiveError> {
+    match source {
+        DataSource::File(_, path) => Ok(PathBuf::from(path)),
+        DataSource::Volumes(_) => Err(ArchiveError::InvalidDataSource(
+            "archives split into volumes cannot be edited".to_string(),
+        )),
+        DataSource::Memory(_) | DataSource::Reader(_) | DataSource::Pipe(_) => {
+            Err(ArchiveError::InvalidDataSource(
+                "only archives read from a file can be edited".to_string(),
+            ))
+        }
+    }
+}
+
+/// Replaces the archive at `path` with the one written by `write` to a temporary file next to
+/// it. `write` returns whether it edited an entry, the archive is left untouched otherwise.
+pub(crate) fn rewrite<F>(path: &Path, target: &str, write: F) -> Result<(), ArchiveError>
+where
+    F: FnOnce(File) -> Result<bool, ArchiveError>,
+{
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let temp = path.with_file_name(format!(".{}.hezi-edit", file_name));
+
+    let result = File::create(&temp)
+        .map_err(ArchiveError::from)
+        .and_then(write);
+    match result {
+        Ok(true) => Ok(std::fs::rename(&temp, path)?),
+        Ok(false) => {
+            _ = std::fs::remove_file(&temp);
+            Err(ArchiveError::EntryNotFound(
+                PathBuf::from(target),
+                Vec::new(),
+            ))
+        }
+        Err(e) => {
+            _ = std::fs::remove_file(&temp);
+            Err(e)
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renames_directories_recursively() {
+        let edit = EntryEdit::Rename("docs".to_string());
+        assert_eq!(edit.renamed("dir", "dir/"), Some("docs/".to_string()));
+        assert_eq!(
+            edit.renamed("dir/", "./dir/a.txt"),
+            Some("docs/a.txt".to_string())
+        );
+        assert_eq!(edit.renamed("dir", "directory/a.txt"), None);
+        assert_eq!(edit.modified("dir", "dir/"), None);
+    }
+}
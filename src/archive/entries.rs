@@ -0,0 +1,294 @@
+// streaming of the entries of an archive: each one comes with a reader of its content, decoded from
+// the archive as the iteration goes rather than listed or extracted up front.
+
+use std::io::{self, Read};
+
+use super::{
+    entry_visitor::{EntryLink, VisitOptions, VisitedEntry},
+    Archive, ArchiveError, ArchiveFileEntity, ListOptions,
+};
+
+/// An entry yielded by [`Archive::entries`], reading as its content. Entries other than files
+/// read as empty.
+pub struct ArchiveEntry<'a> {
+    entry: VisitedEntry,
+    content: Box<dyn Read + 'a>,
+}
+
+impl<'a> ArchiveEntry<'a> {
+    pub(crate) fn new(entry: VisitedEntry, content: Box<dyn Read + 'a>) -> Self {
+        Self { entry, content }
+    }
+
+    /// An entry without content, such as a directory or a link.
+    pub(crate) fn empty(entry: VisitedEntry) -> Self {
+        Self::new(entry, Box::new(io::empty()))
+    }
+
+    pub fn entity(&self) -> &ArchiveFileEntity {
+        &self.entry.entity
+    }
+
+    pub fn name(&self) -> &str {
+        &self.entry.entity.name
+    }
+
+    /// Unix permissions of the entry, for the formats keeping them.
+    pub fn mode(&self) -> Option<u32> {
+        self.entry.mode
+    }
+
+    /// Target of a symbolic link, or name of the entry a hard link points to.
+    pub fn link_target(&self) -> Option<&str> {
+        match &self.entry.link {
+            Some(EntryLink::Symbolic(target) | EntryLink::Hard(target)) => Some(target),
+            None => None,
+        }
+    }
+
+    pub fn into_entity(self) -> ArchiveFileEntity {
+        self.entry.entity
+    }
+}
+
+impl Read for ArchiveEntry<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.content.read(buf)
+    }
+}
+
+impl std::fmt::Debug for ArchiveEntry<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ArchiveEntry")
+            .field("entity", &self.entry.entity)
+            .field("mode", &self.entry.mode)
+            .field("link", &self.entry.link)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Iterator returned by [`Archive::entries`].
+pub struct ArchiveEntries<'a> {
+    inner: Box<dyn Iterator<Item = Result<ArchiveEntry<'a>, ArchiveError>> + 'a>,
+}
+
+impl<'a> ArchiveEntries<'a> {
+    pub(crate) fn new<I>(inner: I) -> Self
+    where
+        I: Iterator<Item = Result<ArchiveEntry<'a>, ArchiveError>> + 'a,
+    {
+        Self {
+            inner: Box::new(inner),
+        }
+    }
+
+    /// Iterator yielding `error` alone, for archives that could not be opened.
+    pub(crate) fn failed(error: ArchiveError) -> Self {
+        Self::new(std::iter::once(Err(error)))
+    }
+
+    /// Leaves out the first `offset` entries and ends after `limit` ones, without decoding the
+    /// content of the entries left out nor reading the archive past the last one.
+    fn paged(self, offset: usize, limit: Option<usize>) -> Self {
+        if offset == 0 && limit.is_none() {
+            return self;
+        }
+        Self::new(self.inner.skip(offset).take(limit.unwrap_or(usize::MAX)))
+    }
+}
+
+impl<'a> Iterator for ArchiveEntries<'a> {
+    type Item = Result<ArchiveEntry<'a>, ArchiveError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+}
+
+/// The options of a traversal reading contents, borrowed from the `options` of an iteration.
+pub(crate) fn visit_options<'v>(options: &'v ListOptions<'_>) -> VisitOptions<'v> {
+    VisitOptions {
+        password: options.password.as_deref(),
+        prefix: options.prefix.as_deref(),
+        contents: true,
+        events: Some(options),
+        ..Default::default()
+    }
+}
+
+impl Archive {
+    /// Iterates over the entries under [`ListOptions::prefix`] in archive order, from
+    /// [`ListOptions::offset`] to [`ListOptions::limit`], each with a reader of its content.
+    /// Contents are decoded as they are read, once the iterator reached them, except for 7z
+    /// archives whose solid blocks are decoded whole into memory, and for encrypted zip entries.
+    ///
+    /// Tar archives are read in a single pass: the content of an entry can no longer be read once
+    /// the iterator moved to the next one. Streaming is supported for zip, tar, 7z and iso
+    /// archives, other formats yield an error.
+    pub fn entries<'s>(&'s self, options: ListOptions<'s>) -> ArchiveEntries<'s> {
+        let (offset, limit) = (options.offset, options.limit);
+        let entries = match self {
+            #[cfg(feature = "zip_archive")]
+            Archive::Zip(a) => a.entries(options),
+            #[cfg(feature = "tar_archive")]
+            Archive::Tar(a) => a.entries(options),
+            #[cfg(feature = "sevenz_archive")]
+            Archive::SevenZ(a) => a.entries(options),
+            #[cfg(feature = "iso_archive")]
+            Archive::Iso(a) => a.entries(options),
+            _ => ArchiveEntries::failed(ArchiveError::Io(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "streaming entries is only supported for zip, tar, 7z and iso archives",
+            ))),
+        };
+        entries.paged(offset, limit)
+    }
+}
+
+#[cfg(all(
+    test,
+    feature = "zip_archive",
+    feature = "deflate_codecs",
+    feature = "tar_archive",
+    feature = "sevenz_archive"
+))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::ops::ControlFlow;
+
+    use super::*;
+    use crate::archive::{
+        entry_visitor::EntryVisitor, password::StaticPassword, ArchiveFileEntityType, Archived,
+    };
+
+    fn read_entries(path: &str, options: ListOptions) -> Vec<(String, Vec<u8>)> {
+        let archive = Archive::from_path(path).unwrap();
+        archive
+            .entries(options)
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let mut content = Vec::new();
+                entry.read_to_end(&mut content).unwrap();
+                (entry.name().trim_end_matches('/').to_string(), content)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn streams_entries_with_their_contents() {
+        for path in [
+            "tests/fixtures/test1.zip",
+            "tests/fixtures/test1.tar",
+            "tests/fixtures/test1.tar.gz",
+            "tests/fixtures/test1.7z",
+        ] {
+            // the contents the traversal behind extraction reads
+            let archive = Archive::from_path(path).unwrap();
+            let mut visited = Vec::new();
+            let options = VisitOptions {
+                contents: true,
+                ..Default::default()
+            };
+            archive
+                .for_each_entry(&options, |entry, reader| {
+                    let mut content = Vec::new();
+                    if let Some(reader) = reader {
+                        reader.read_to_end(&mut content)?;
+                    }
+                    let name = entry.entity.name.trim_end_matches('/').to_string();
+                    visited.push((name, content));
+                    Ok(ControlFlow::Continue(()))
+                })
+                .unwrap();
+            assert_eq!(
+                read_entries(path, ListOptions::default()),
+                visited,
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn streams_entries_under_prefix() {
+        let options = || ListOptions {
+            prefix: Some("test1/dir1".to_string()),
+            ..Default::default()
+        };
+        for path in ["tests/fixtures/test1.zip", "tests/fixtures/test1.tar"] {
+            let names = read_entries(path, options())
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>();
+            assert!(!names.is_empty());
+            assert!(
+                names.iter().all(|n| n.starts_with("test1/dir1/")),
+                "{:?}",
+                names
+            );
+        }
+    }
+
+    #[test]
+    fn streams_a_page_of_entries() {
+        let page = || ListOptions {
+            offset: 1,
+            limit: Some(2),
+            ..Default::default()
+        };
+        for path in ["tests/fixtures/test1.zip", "tests/fixtures/test1.tar"] {
+            let all = read_entries(path, ListOptions::default());
+            assert_eq!(read_entries(path, page()), all[1..3], "{}", path);
+        }
+    }
+
+    #[test]
+    fn tar_contents_end_with_the_next_entry() {
+        let archive = Archive::from_path("tests/fixtures/test1.tar").unwrap();
+        let mut entries = archive.entries(ListOptions::default());
+        let mut file = entries
+            .by_ref()
+            .map(Result::unwrap)
+            .find(|e| e.entity().fstype() == ArchiveFileEntityType::File)
+            .unwrap();
+        let mut start = [0; 1];
+        file.read_exact(&mut start).unwrap();
+        entries.next().unwrap().unwrap();
+        assert!(file.read(&mut [0; 16]).is_err());
+    }
+
+    #[test]
+    fn decrypts_zip_entries() {
+        let options = |password: &str| ListOptions {
+            password: Some(Box::new(StaticPassword(password.to_string()))),
+            ..Default::default()
+        };
+        let archive = Archive::from_path("tests/fixtures/encrypted.zip").unwrap();
+        let files = archive
+            .entries(options("hunter2"))
+            .map(Result::unwrap)
+            .filter(|e| e.entity().fstype() == ArchiveFileEntityType::File)
+            .map(|mut e| {
+                let mut content = Vec::new();
+                e.read_to_end(&mut content).map(|_| content)
+            })
+            .collect::<Vec<_>>();
+        assert!(!files.is_empty());
+        assert!(files
+            .iter()
+            .all(|c| c.as_ref().is_ok_and(|c| !c.is_empty())));
+
+        assert!(archive
+            .entries(options("wrong"))
+            .any(|e| matches!(e, Err(ArchiveError::WrongPassword))));
+    }
+
+    #[cfg(feature = "cpio_archive")]
+    #[test]
+    fn fails_for_other_formats() {
+        let archive = Archive::from_path("tests/fixtures/test1.cpio").unwrap();
+        let mut entries = archive.entries(ListOptions::default());
+        assert!(entries.next().unwrap().is_err());
+        assert!(entries.next().is_none());
+    }
+}
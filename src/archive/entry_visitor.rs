@@ -0,0 +1,737 @@
+use std::{
+    cell::Cell,
+    collections::HashSet,
+    io::{self, Read, Write},
+    ops::ControlFlow,
+};
+
+use super::{
+    extract_sink::{ExtractSink, ExtractSource, ReportingEvents},
+    password::PasswordProvider,
+    retry::IoRetryPolicy,
+    Archive, ArchiveError, ArchiveEvent, ArchiveFileEntity, ArchiveFileEntityType, ArchiveScan,
+    EventHandler, ExtractOptions, ExtractReport, ListOptions, OpenOptions, OpenOutput, SkipReason,
+};
+
+/// Link stored in place of an entry's content.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum EntryLink {
+    Symbolic(String),
+    /// Name of a previous entry this one is a hard link to.
+    Hard(String),
+}
+
+/// An entry yielded by [`EntryVisitor::for_each_entry`], with the details extraction needs on
+/// top of its [`ArchiveFileEntity`].
+#[derive(Debug, Clone)]
+pub(crate) struct VisitedEntry {
+    pub(crate) entity: ArchiveFileEntity,
+    pub(crate) mode: Option<u32>,
+    pub(crate) link: Option<EntryLink>,
+    /// CRC-32 of the content recorded by the archive, keying the [`ExtractOptions::cache`].
+    pub(crate) crc32: Option<u32>,
+}
+
+impl VisitedEntry {
+    pub(crate) fn new(entity: ArchiveFileEntity) -> Self {
+        Self {
+            entity,
+            mode: None,
+            link: None,
+            crc32: None,
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct VisitOptions<'v> {
+    /// Asked for the password once encrypted data is met.
+    pub(crate) password: Option<&'v dyn PasswordProvider>,
+    /// Only entries under this directory are visited.
+    pub(crate) prefix: Option<&'v str>,
+    /// Only entries passing the filter are visited, it is applied before any content is read.
+    pub(crate) filter: Option<&'v dyn Fn(&ArchiveFileEntity) -> bool>,
+    /// Whether file contents are needed. When unset, backends may skip decoding and visit
+    /// entries without a reader.
+    pub(crate) contents: bool,
+    /// Receives the entries that could not be read and were skipped, and the retried reads.
+    pub(crate) events: Option<&'v dyn EventHandler>,
+    /// Applied to the reads from the archive source.
+    pub(crate) retry: IoRetryPolicy,
+    /// Encrypted entries without a valid password are skipped and reported with
+    /// [`SkipReason::NoValidPassword`] rather than failing the traversal, for the backends
+    /// encrypting entries one by one.
+    pub(crate) skip_without_password: bool,
+    /// Whether the rest of the source is read once all the entries are, so that the backends
+    /// decoding a compressed stream, like tar ones, check its checksum.
+    pub(crate) drain: bool,
+}
+
+impl VisitOptions<'_> {
+    pub(crate) fn wants(&self, entity: &ArchiveFileEntity) -> bool {
+        self.prefix
+            .is_none_or(|prefix| is_under(&entity.name, prefix))
+            && self.filter.is_none_or(|f| f(entity))
+    }
+
+    /// Asks the password provider for the password of `entry`, or of the whole archive, failing
+    /// with [`ArchiveError::PasswordRequired`] if there is none.
+    pub(crate) fn ask_password(&self, entry: Option<&str>) -> Result<String, ArchiveError> {
+        self.password
+            .map_or(Ok(None), |p| p.password(entry))?
+            .ok_or(ArchiveError::PasswordRequired)
+    }
+}
+
+/// Strips the leading `/` and `./` backends may prefix entry names with.
+pub(crate) fn normalized_name(name: &str) -> &str {
+    let mut name = name;
+    while let Some(rest) = name.strip_prefix("./").or_else(|| name.strip_prefix('/')) {
+        name = rest;
+    }
+    name
+}
+
+/// Whether the entry `name` lies under the directory `prefix` of the archive.
+pub(crate) fn is_under(name: &str, prefix: &str) -> bool {
+    let prefix = normalized_name(prefix).trim_end_matches('/');
+    if prefix.is_empty() {
+        return true;
+    }
+    normalized_name(name)
+        .strip_prefix(prefix)
+        .is_some_and(|rest| rest.len() > 1 && rest.starts_with('/'))
+}
+
+/// Single traversal implemented by every backend, on top of which listing, extraction and
+/// opening entries are built.
+pub(crate) trait EntryVisitor {
+    /// Calls `visit` for each entry selected by `options`, in archive order, until it breaks.
+    /// The reader is only provided for file entries, and only if `options.contents` is set.
+    fn for_each_entry<F>(&self, options: &VisitOptions<'_>, visit: F) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>;
+}
+
+impl EntryVisitor for Archive {
+    fn for_each_entry<F>(&self, options: &VisitOptions<'_>, visit: F) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        match self {
+            #[cfg(feature = "zip_archive")]
+            Archive::Zip(a) => a.for_each_entry(options, visit),
+            #[cfg(feature = "tar_archive")]
+            Archive::Tar(a) => a.for_each_entry(options, visit),
+            #[cfg(feature = "sevenz_archive")]
+            Archive::SevenZ(a) => a.for_each_entry(options, visit),
+            #[cfg(feature = "iso_archive")]
+            Archive::Iso(a) => a.for_each_entry(options, visit),
+            #[cfg(feature = "rar_archive")]
+            Archive::Rar(a) => a.for_each_entry(options, visit),
+            #[cfg(feature = "cpio_archive")]
+            Archive::Cpio(a) => a.for_each_entry(options, visit),
+            #[cfg(feature = "ar_archive")]
+            Archive::Ar(a) => a.for_each_entry(options, visit),
+            #[cfg(feature = "squashfs_archive")]
+            Archive::Squashfs(a) => a.for_each_entry(options, visit),
+            #[cfg(feature = "cab_archive")]
+            Archive::Cab(a) => a.for_each_entry(options, visit),
+            #[cfg(feature = "wim_archive")]
+            Archive::Wim(a) => a.for_each_entry(options, visit),
+            #[cfg(feature = "lha_archive")]
+            Archive::Lha(a) => a.for_each_entry(options, visit),
+            #[cfg(feature = "arj_archive")]
+            Archive::Arj(a) => a.for_each_entry(options, visit),
+            #[cfg(feature = "msi_archive")]
+            Archive::Msi(a) => a.for_each_entry(options, visit),
+            #[cfg(feature = "nsis_archive")]
+            Archive::Nsis(a) => a.for_each_entry(options, visit),
+            #[cfg(feature = "zpaq_archive")]
+            Archive::Zpaq(a) => a.for_each_entry(options, visit),
+            #[cfg(feature = "dmg_archive")]
+            Archive::Dmg(a) => a.for_each_entry(options, visit),
+            #[cfg(feature = "disk_image")]
+            Archive::DiskImage(a) => a.for_each_entry(options, visit),
+            #[cfg(feature = "warc_archive")]
+            Archive::Warc(a) => a.for_each_entry(options, visit),
+            #[cfg(feature = "compressed_file")]
+            Archive::CompressedFile(a) => a.for_each_entry(options, visit),
+            Archive::_Unreachable => unreachable!(),
+        }
+    }
+}
+
+/// Lists the entries under `options.prefix`, from `options.offset` to `options.limit`, filtering
+/// the `cached` scan when there is one rather than reading the archive again. Otherwise the
+/// archive is read no further than the last entry listed.
+pub(crate) fn list_entries<V: EntryVisitor>(
+    visitor: &V,
+    cached: Option<&ArchiveScan>,
+    options: &ListOptions,
+) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
+    let prefix = options.prefix.as_deref();
+    let limit = options.limit.unwrap_or(usize::MAX);
+    if let Some(scan) = cached {
+        return Ok(scan
+            .entries
+            .iter()
+            .filter(|e| prefix.is_none_or(|p| is_under(&e.name, p)))
+            .skip(options.offset)
+            .take(limit)
+            .cloned()
+            .collect());
+    }
+    if limit == 0 {
+        return Ok(Vec::new());
+    }
+
+    let visit_options = VisitOptions {
+        password: options.password.as_deref(),
+        prefix,
+        events: Some(options),
+        ..Default::default()
+    };
+    let mut skipped = 0;
+    let mut entries = Vec::new();
+    visitor.for_each_entry(&visit_options, |entry, _| {
+        if skipped < options.offset {
+            skipped += 1;
+            return Ok(ControlFlow::Continue(()));
+        }
+        entries.push(entry.entity);
+        Ok(if entries.len() < limit {
+            ControlFlow::Continue(())
+        } else {
+            ControlFlow::Break(())
+        })
+    })?;
+    Ok(entries)
+}
+
+pub(crate) fn scan_entries<V: EntryVisitor>(
+    visitor: &V,
+    password: Option<&dyn PasswordProvider>,
+    events: Option<&dyn EventHandler>,
+) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
+    let options = VisitOptions {
+        password,
+        events,
+        ..Default::default()
+    };
+
+    let mut entries = Vec::new();
+    visitor.for_each_entry(&options, |entry, _| {
+        entries.push(entry.entity);
+        Ok(ControlFlow::Continue(()))
+    })?;
+    Ok(entries)
+}
+
+pub(crate) fn extract_entries<V: EntryVisitor, S: ExtractSource>(
+    visitor: &V,
+    options: &ExtractOptions,
+    source: S,
+) -> Result<ExtractReport, ArchiveError> {
+    let events = ReportingEvents::new(options);
+    let files = options
+        .files
+        .as_ref()
+        .map(|f| f.iter().map(String::as_str).collect::<HashSet<_>>());
+    let not_in_files = Cell::new(0usize);
+    let filter = |entity: &ArchiveFileEntity| {
+        let name = entity.name.as_str();
+        let wanted = files
+            .as_ref()
+            .is_none_or(|files| files.contains(name) || files.contains(name.trim_end_matches('/')));
+        if !wanted {
+            not_in_files.set(not_in_files.get() + 1);
+            events.handle(ArchiveEvent::Skipped(
+                entity.name.clone(),
+                SkipReason::NotInFiles,
+            ));
+        }
+        wanted
+    };
+    let visit_options = VisitOptions {
+        password: options.password.as_deref(),
+        filter: Some(&filter),
+        contents: true,
+        events: Some(&events),
+        retry: options.retry,
+        skip_without_password: true,
+        ..Default::default()
+    };
+
+    let mut sink = ExtractSink::new(options, &events)?;
+    sink.protect(source.files());
+    sink.measure(source.size());
+    visitor.for_each_entry(&visit_options, |entry, reader| {
+        let name = entry.entity.name.as_str();
+        match (entry.link, &entry.entity.fstype, reader) {
+            (Some(EntryLink::Hard(target)), _, _) => sink.hard_link(name, &target)?,
+            (Some(EntryLink::Symbolic(target)), _, _) => sink.symlink(name, target)?,
+            (None, ArchiveFileEntityType::Directory, _) => sink.directory(name, entry.mode)?,
+            (None, _, Some(reader)) => {
+                let mut reader = options.transform(&entry.entity, Box::new(reader));
+                sink.file(&entry.entity, entry.mode, entry.crc32, &mut reader)?;
+            }
+            (None, _, None) => sink.skip(name, SkipReason::UnknownType),
+        }
+        Ok(ControlFlow::Continue(()))
+    })?;
+
+    if not_in_files.get() > 0 {
+        events.handle(ArchiveEvent::Log(format!(
+            "Skipped {} entries not in files",
+            not_in_files.get()
+        )));
+    }
+    sink.finish(source)?;
+    Ok(events.finish())
+}
+
+/// Writes the entries of [`Archived::open`](super::Archived::open) in the requested output.
+enum OpenWriter {
+    Concatenated {
+        dest: Box<dyn Write>,
+        separator: Option<Vec<u8>>,
+        first: bool,
+    },
+    #[cfg(feature = "tar_archive")]
+    Tar(tar::Builder<Box<dyn Write>>),
+}
+
+impl OpenWriter {
+    fn new(output: OpenOutput, dest: Box<dyn Write>) -> Self {
+        let separator = match output {
+            OpenOutput::Concatenated => None,
+            OpenOutput::Separated(separator) => Some(separator),
+            #[cfg(feature = "tar_archive")]
+            OpenOutput::Tar => return OpenWriter::Tar(tar::Builder::new(dest)),
+        };
+        OpenWriter::Concatenated {
+            dest,
+            separator,
+            first: true,
+        }
+    }
+
+    fn write(&mut self, entry: &VisitedEntry, reader: Option<&mut dyn Read>) -> io::Result<()> {
+        match self {
+            OpenWriter::Concatenated {
+                dest,
+                separator,
+                first,
+            } => {
+                // only files have contents to write
+                let Some(reader) = reader else {
+                    return Ok(());
+                };
+                if let (false, Some(separator)) = (*first, separator) {
+                    dest.write_all(separator)?;
+                }
+                *first = false;
+                std::io::copy(reader, dest)?;
+                Ok(())
+            }
+            #[cfg(feature = "tar_archive")]
+            OpenWriter::Tar(builder) => super::tar_archive::append_entry(builder, entry, reader),
+        }
+    }
+
+    fn finish(self) -> io::Result<()> {
+        match self {
+            OpenWriter::Concatenated { mut dest, .. } => dest.flush(),
+            #[cfg(feature = "tar_archive")]
+            OpenWriter::Tar(builder) => builder.into_inner()?.flush(),
+        }
+    }
+}
+
+pub(crate) fn open_entry<V: EntryVisitor>(
+    visitor: &V,
+    options: OpenOptions,
+) -> Result<(), ArchiveError> {
+    let paths = options
+        .paths
+        .iter()
+        .map(|path| path.to_string_lossy().replace('\\', "/"))
+        .collect::<Vec<_>>();
+    let selects = |path: &str, name: &str| {
+        normalized_name(name).trim_end_matches('/') == normalized_name(path).trim_end_matches('/')
+            || is_under(name, path)
+    };
+    let filter = |entity: &ArchiveFileEntity| paths.iter().any(|path| selects(path, &entity.name));
+    let visit_options = VisitOptions {
+        password: options.password.as_deref(),
+        filter: Some(&filter),
+        contents: true,
+        ..Default::default()
+    };
+
+    let mut writer = OpenWriter::new(options.output, options.dest);
+    let mut found = vec![false; paths.len()];
+    // the paths naming a file are done with once it was written
+    let mut done = vec![false; paths.len()];
+    visitor.for_each_entry(&visit_options, |entry, reader| {
+        let is_file = reader.is_some();
+        writer.write(&entry, reader)?;
+        for (i, path) in paths.iter().enumerate() {
+            if selects(path, &entry.entity.name) {
+                found[i] = true;
+                done[i] |= is_file && !is_under(&entry.entity.name, path);
+            }
+        }
+        Ok(match done.iter().all(|done| *done) {
+            true => ControlFlow::Break(()),
+            false => ControlFlow::Continue(()),
+        })
+    })?;
+    writer.finish()?;
+
+    match found.iter().position(|found| !found) {
+        Some(missing) => Err(ArchiveError::EntryNotFound(
+            options.paths[missing].clone(),
+            Vec::new(),
+        )),
+        None => Ok(()),
+    }
+}
+
+pub(crate) fn find_first_entry<V, P>(
+    visitor: &V,
+    predicate: P,
+) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+where
+    V: EntryVisitor,
+    P: Fn(&ArchiveFileEntity) -> bool,
+{
+    let filter = |entity: &ArchiveFileEntity| {
+        entity.fstype == ArchiveFileEntityType::File && predicate(entity)
+    };
+    let visit_options = VisitOptions {
+        filter: Some(&filter),
+        contents: true,
+        ..Default::default()
+    };
+
+    let mut found = None;
+    visitor.for_each_entry(&visit_options, |entry, reader| {
+        let Some(reader) = reader else {
+            return Ok(ControlFlow::Continue(()));
+        };
+        let mut content = Vec::with_capacity(entry.entity.size.unwrap_or(0) as usize);
+        reader.read_to_end(&mut content)?;
+        found = Some((entry.entity, content));
+        Ok(ControlFlow::Break(()))
+    })?;
+    Ok(found)
+}
+
+#[cfg(all(test, feature = "tar_archive", feature = "sevenz_archive"))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        io::Write,
+        path::{Path, PathBuf},
+        rc::Rc,
+    };
+
+    use super::*;
+    use crate::archive::{sevenz_archive::SevenZArchive, tar_archive::TarArchive, Archived};
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn open_options(path: &str, buf: &SharedBuf) -> OpenOptions {
+        OpenOptions {
+            paths: vec![PathBuf::from(path)],
+            password: None,
+            dest: Box::new(buf.clone()),
+            output: OpenOutput::default(),
+        }
+    }
+
+    #[test]
+    fn open_entry_reads_single_entry() {
+        let expected = std::fs::read("tests/fixtures/test1/dir1/file2.txt").unwrap();
+
+        let tar = TarArchive::from_path("tests/fixtures/test1.tar").unwrap();
+        let buf = SharedBuf::default();
+        open_entry(&tar, open_options("test1/dir1/file2.txt", &buf)).unwrap();
+        assert_eq!(*buf.0.borrow(), expected);
+
+        // a later entry of the same folder, the ones before it have to be skipped over
+        let sevenz = SevenZArchive::from_path("tests/fixtures/test1.7z").unwrap();
+        let buf = SharedBuf::default();
+        open_entry(&sevenz, open_options("test1/dir1/file2.txt", &buf)).unwrap();
+        assert_eq!(*buf.0.borrow(), expected);
+
+        assert!(matches!(
+            open_entry(&sevenz, open_options("missing.txt", &SharedBuf::default())),
+            Err(ArchiveError::EntryNotFound(..))
+        ));
+    }
+
+    #[test]
+    fn open_entry_writes_several_entries() {
+        let file1 = std::fs::read("tests/fixtures/test1/file1.txt").unwrap();
+        let file2 = std::fs::read("tests/fixtures/test1/dir1/file2.txt").unwrap();
+        let sevenz = SevenZArchive::from_path("tests/fixtures/test1.7z").unwrap();
+        let open = |paths: &[&str], output: OpenOutput| {
+            let buf = SharedBuf::default();
+            open_entry(
+                &sevenz,
+                OpenOptions {
+                    paths: paths.iter().map(PathBuf::from).collect(),
+                    output,
+                    ..open_options("", &buf)
+                },
+            )
+            .map(|_| buf.0.take())
+        };
+
+        // in archive order, whatever the order they were given in
+        let separated = open(
+            &["test1/file1.txt", "test1/dir1/file2.txt"],
+            OpenOutput::Separated(b"\0".to_vec()),
+        )
+        .unwrap();
+        assert_eq!(separated, [file2.clone(), file1.clone()].join(&b'\0'));
+        let concatenated = open(&["test1/dir1"], OpenOutput::Concatenated).unwrap();
+        assert_eq!(concatenated, file2);
+
+        let tarred = open(&["test1/dir1/", "test1/file1.txt"], OpenOutput::Tar).unwrap();
+        let mut archive = tar::Archive::new(tarred.as_slice());
+        let entries = archive
+            .entries()
+            .unwrap()
+            .map(|e| {
+                let mut e = e.unwrap();
+                let mut content = Vec::new();
+                e.read_to_end(&mut content).unwrap();
+                (e.path().unwrap().to_string_lossy().to_string(), content)
+            })
+            .collect::<Vec<_>>();
+        // 7z stores the directories after the files
+        assert_eq!(
+            entries,
+            vec![
+                ("test1/dir1/file2.txt".to_string(), file2),
+                ("test1/file1.txt".to_string(), file1),
+                ("test1/dir1".to_string(), vec![]),
+            ]
+        );
+
+        assert!(matches!(
+            open(&["test1/file1.txt", "missing.txt"], OpenOutput::Concatenated),
+            Err(ArchiveError::EntryNotFound(path, _)) if path == Path::new("missing.txt")
+        ));
+    }
+
+    #[test]
+    fn scan_without_contents_matches_decoding_scan() {
+        let sevenz = SevenZArchive::from_path("tests/fixtures/test1.7z").unwrap();
+
+        let mut decoded = Vec::new();
+        let options = VisitOptions {
+            contents: true,
+            ..Default::default()
+        };
+        sevenz
+            .for_each_entry(&options, |entry, _| {
+                decoded.push(entry.entity.name);
+                Ok(ControlFlow::Continue(()))
+            })
+            .unwrap();
+
+        let mut scanned = scan_entries(&sevenz, None, None)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect::<Vec<_>>();
+        scanned.sort();
+        decoded.sort();
+        assert_eq!(scanned, decoded);
+    }
+
+    #[test]
+    fn find_first_stops_at_match() {
+        let expected = std::fs::read("tests/fixtures/test1/dir1/file2.txt").unwrap();
+        let sevenz = SevenZArchive::from_path("tests/fixtures/test1.7z").unwrap();
+
+        let (entity, content) = sevenz
+            .find_first(|e| e.name.ends_with("file2.txt"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(entity.name, "test1/dir1/file2.txt");
+        assert_eq!(content, expected);
+
+        let tar = TarArchive::from_path("tests/fixtures/test1.tar").unwrap();
+        assert!(tar
+            .find_first(ArchiveFileEntity::is_image)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn extract_applies_transformers() {
+        let dest = std::env::temp_dir().join(format!("hezi-transform-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dest);
+
+        let tar = TarArchive::from_path("tests/fixtures/test1.tar").unwrap();
+        let truncate = crate::archive::entry_transformer(|entity, reader| {
+            if entity.name().ends_with("file2.txt") {
+                Box::new(reader.take(4))
+            } else {
+                reader
+            }
+        });
+        tar.extract(ExtractOptions {
+            destination: dest.clone(),
+            transformers: vec![truncate],
+            inspector: None,
+            ..Default::default()
+        })
+        .unwrap();
+
+        let original = std::fs::read("tests/fixtures/test1/dir1/file2.txt").unwrap();
+        let file2 = std::fs::read(dest.join("test1/dir1/file2.txt")).unwrap();
+        assert_eq!(file2, original[..4]);
+        let file1 = std::fs::metadata(dest.join("test1/file1.txt")).unwrap();
+        assert_eq!(file1.len(), 1469);
+        _ = std::fs::remove_dir_all(&dest);
+    }
+
+    #[derive(Default)]
+    struct SkipRecorder(RefCell<Vec<String>>);
+
+    impl EventHandler for SkipRecorder {
+        fn handle(&self, event: ArchiveEvent) {
+            match event {
+                ArchiveEvent::Skipped(name, SkipReason::NotInFiles) => {
+                    self.0.borrow_mut().push(name)
+                }
+                ArchiveEvent::Log(msg) => self.0.borrow_mut().push(msg),
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn extract_reports_entries_not_in_files() {
+        let dest = std::env::temp_dir().join(format!("hezi-not-in-files-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dest);
+
+        for path in ["tests/fixtures/test1.tar", "tests/fixtures/test1.7z"] {
+            let archive = Archive::from_path(path).unwrap();
+            let recorder = Rc::new(SkipRecorder::default());
+            archive
+                .extract(ExtractOptions {
+                    destination: dest.clone(),
+                    files: Some(vec!["test1/file1.txt".to_string()]),
+                    overwrite: true,
+                    event_handler: Box::new(recorder.clone()),
+                    ..Default::default()
+                })
+                .unwrap();
+
+            let skipped = recorder.0.borrow();
+            assert!(
+                skipped.contains(&"test1/dir1/file2.txt".to_string()),
+                "{}",
+                path
+            );
+            assert!(
+                !skipped.contains(&"test1/file1.txt".to_string()),
+                "{}",
+                path
+            );
+            assert_eq!(
+                skipped.last(),
+                Some(&format!(
+                    "Skipped {} entries not in files",
+                    skipped.len() - 1
+                ))
+            );
+            assert!(dest.join("test1/file1.txt").exists());
+            assert!(!dest.join("test1/dir1/file2.txt").exists());
+        }
+        _ = std::fs::remove_dir_all(&dest);
+    }
+
+    #[test]
+    fn is_under_prefix() {
+        assert!(is_under("src/main.rs", "src"));
+        assert!(is_under("./src/main.rs", "/src/"));
+        assert!(is_under("src/a/b", "src/a"));
+        assert!(!is_under("src", "src/"));
+        assert!(!is_under("src/", "src"));
+        assert!(!is_under("srcs/main.rs", "src"));
+        assert!(is_under("anything", ""));
+    }
+
+    #[test]
+    fn list_with_prefix() {
+        let tar = TarArchive::from_path("tests/fixtures/test1.tar").unwrap();
+        let options = ListOptions {
+            prefix: Some("test1/dir1/".to_string()),
+            ..Default::default()
+        };
+
+        // once without a cached scan, once filtering it
+        let streamed = tar.list(options).unwrap();
+        tar.list(ListOptions::default()).unwrap();
+        let cached = tar
+            .list(ListOptions {
+                prefix: Some("test1/dir1".to_string()),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let names = |entries: &[ArchiveFileEntity]| {
+            entries.iter().map(|e| e.name.clone()).collect::<Vec<_>>()
+        };
+        assert!(!streamed.is_empty());
+        assert!(streamed.iter().all(|e| e.name.starts_with("test1/dir1/")));
+        assert_eq!(names(&streamed), names(&cached));
+    }
+
+    #[test]
+    fn list_a_page() {
+        let page = |offset, limit| ListOptions {
+            offset,
+            limit,
+            ..Default::default()
+        };
+        let names = |entries: Vec<ArchiveFileEntity>| {
+            entries.into_iter().map(|e| e.name).collect::<Vec<_>>()
+        };
+
+        // once reading the archive no further than the page, once from the cached scan
+        let tar = TarArchive::from_path("tests/fixtures/test1.tar").unwrap();
+        let streamed = names(tar.list(page(2, Some(3))).unwrap());
+        let all = names(tar.list(ListOptions::default()).unwrap());
+        assert_eq!(streamed, all[2..5]);
+        assert_eq!(names(tar.list(page(2, Some(3))).unwrap()), streamed);
+
+        assert!(tar.list(page(0, Some(0))).unwrap().is_empty());
+        assert!(tar.list(page(all.len(), None)).unwrap().is_empty());
+        assert_eq!(
+            names(tar.list(page(all.len() - 1, Some(10))).unwrap()),
+            all[all.len() - 1..]
+        );
+    }
+}
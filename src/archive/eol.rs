@@ -0,0 +1,169 @@
+use std::io::Read;
+
+use super::{ArchiveFileEntity, EntryTransformer, DEFAULT_BUF_SIZE};
+
+/// Line ending text entries are converted to on extraction.
+///
+/// Entries are considered text unless their first block contains a NUL byte, binary entries are
+/// extracted untouched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+    /// `crlf` on Windows, `lf` elsewhere.
+    Native,
+}
+
+impl LineEnding {
+    fn newline(self) -> &'static [u8] {
+        match self {
+            LineEnding::Lf => b"\n",
+            LineEnding::Crlf => b"\r\n",
+            LineEnding::Native if cfg!(windows) => b"\r\n",
+            LineEnding::Native => b"\n",
+        }
+    }
+}
+
+impl EntryTransformer for LineEnding {
+    fn transform<'r>(
+        &self,
+        _entity: &ArchiveFileEntity,
+        reader: Box<dyn Read + 'r>,
+    ) -> Box<dyn Read + 'r> {
+        Box::new(EolReader::new(reader, *self))
+    }
+}
+
+/// Converts the line endings of `inner` while streaming it.
+pub struct EolReader<R> {
+    inner: R,
+    newline: &'static [u8],
+    /// Unknown until the first block was read.
+    text: Option<bool>,
+    /// A `\r` ended the previous block, it may be the first half of a `\r\n`.
+    pending_cr: bool,
+    input: Vec<u8>,
+    output: Vec<u8>,
+    position: usize,
+    done: bool,
+}
+
+impl<R: Read> EolReader<R> {
+    pub fn new(inner: R, line_ending: LineEnding) -> Self {
+        Self {
+            inner,
+            newline: line_ending.newline(),
+            text: None,
+            pending_cr: false,
+            input: vec![0; DEFAULT_BUF_SIZE],
+            output: Vec::with_capacity(DEFAULT_BUF_SIZE),
+            position: 0,
+            done: false,
+        }
+    }
+
+    /// Reads the next block of `inner` and converts it into `output`.
+    fn fill(&mut self) -> std::io::Result<()> {
+        self.output.clear();
+        self.position = 0;
+
+        let read = self.inner.read(&mut self.input)?;
+        if read == 0 {
+            self.done = true;
+            if std::mem::take(&mut self.pending_cr) {
+                self.output.push(b'\r');
+            }
+            return Ok(());
+        }
+
+        let block = &self.input[..read];
+        let text = *self.text.get_or_insert_with(|| !block.contains(&0));
+        if !text {
+            self.output.extend_from_slice(block);
+            return Ok(());
+        }
+
+        for &b in block {
+            if std::mem::take(&mut self.pending_cr) {
+                if b == b'\n' {
+                    self.output.extend_from_slice(self.newline);
+                    continue;
+                }
+                // a lone carriage return is not a line ending
+                self.output.push(b'\r');
+            }
+            match b {
+                b'\r' => self.pending_cr = true,
+                b'\n' => self.output.extend_from_slice(self.newline),
+                _ => self.output.push(b),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for EolReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.position == self.output.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+
+        let available = &self.output[self.position..];
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    /// Yields `data` a single byte at a time, to split `\r\n` across blocks.
+    struct Trickle<'d>(&'d [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let Some((first, rest)) = self.0.split_first() else {
+                return Ok(0);
+            };
+            buf[0] = *first;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    fn convert<R: Read>(reader: R, line_ending: LineEnding) -> Vec<u8> {
+        let mut out = Vec::new();
+        EolReader::new(reader, line_ending)
+            .read_to_end(&mut out)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn converts_line_endings() {
+        let mixed: &[u8] = b"a\r\nb\nc\rd\r";
+        assert_eq!(convert(mixed, LineEnding::Lf), b"a\nb\nc\rd\r");
+        assert_eq!(convert(mixed, LineEnding::Crlf), b"a\r\nb\r\nc\rd\r");
+        assert_eq!(convert(Trickle(mixed), LineEnding::Lf), b"a\nb\nc\rd\r");
+        assert_eq!(
+            convert(Trickle(mixed), LineEnding::Crlf),
+            b"a\r\nb\r\nc\rd\r"
+        );
+    }
+
+    #[test]
+    fn leaves_binary_untouched() {
+        let binary: &[u8] = b"\x89PNG\r\n\x1a\n\0\0";
+        assert_eq!(convert(binary, LineEnding::Crlf), binary);
+        assert_eq!(convert(binary, LineEnding::Lf), binary);
+    }
+}
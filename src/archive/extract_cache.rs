@@ -0,0 +1,157 @@
+// content-addressed cache of extracted files, shared between archives: the entries whose archive
+// records a CRC-32, such as zip and 7z ones, are stored under it and their size once extracted,
+// and later entries with the same checksum and size are copied or linked from the cache instead
+// of being decompressed again, which pays off when extracting many similar archives such as
+// nightly builds.
+
+use std::{
+    fs::{self, File},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use super::ArchiveError;
+
+/// Directory of the extracted files, see [`ExtractOptions::cache`](super::ExtractOptions::cache).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtractCache {
+    pub dir: PathBuf,
+    /// Hard links the extracted files to the cache rather than copying them, saving the space and
+    /// the time of the copies. The linked files share their content, modification time and
+    /// permissions with the cache, which a change to one of them changes for all.
+    pub link: bool,
+}
+
+impl ExtractCache {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+            link: false,
+        }
+    }
+
+    /// `hezi` under the cache directory of the user, `$XDG_CACHE_HOME` or `~/.cache` on unix and
+    /// `%LOCALAPPDATA%` on Windows, or the temporary directory of the system without one.
+    pub fn default_dir() -> PathBuf {
+        let base = if cfg!(windows) {
+            std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+        } else {
+            std::env::var_os("XDG_CACHE_HOME")
+                .map(PathBuf::from)
+                .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        };
+        base.unwrap_or_else(std::env::temp_dir).join("hezi")
+    }
+
+    /// Where the content of the given checksum and size is stored, spread over 256 directories.
+    fn path(&self, crc32: u32, size: u64) -> PathBuf {
+        self.dir
+            .join(format!("{:02x}", crc32 >> 24))
+            .join(format!("{:08x}-{}", crc32, size))
+    }
+
+    /// Creates `dest` from the cached content of the given checksum and size, with the
+    /// `modified` time and the `mode` unless it is linked, returning whether the cache held it.
+    pub(crate) fn restore(
+        &self,
+        crc32: u32,
+        size: u64,
+        dest: &Path,
+        modified: Option<SystemTime>,
+        mode: Option<u32>,
+    ) -> Result<bool, ArchiveError> {
+        let cached = self.path(crc32, size);
+        match fs::metadata(&cached) {
+            // a truncated file is not trusted
+            Ok(metadata) if metadata.len() == size => {}
+            Ok(_) => return Ok(false),
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        }
+        // links cannot cross file systems, the content is copied then
+        if self.link && fs::hard_link(&cached, dest).is_ok() {
+            return Ok(true);
+        }
+        match fs::copy(&cached, dest) {
+            Ok(_) => {}
+            // evicted meanwhile
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e.into()),
+        }
+        if let Some(modified) = modified {
+            File::options()
+                .write(true)
+                .open(dest)?
+                .set_modified(modified)?;
+        }
+        if let Some(mode) = mode {
+            super::extract_sink::set_mode(dest, mode)?;
+        }
+        Ok(true)
+    }
+
+    /// Stores the extracted file `path` as the content of the given checksum and size, unless
+    /// the cache holds it already.
+    pub(crate) fn store(&self, crc32: u32, size: u64, path: &Path) -> std::io::Result<()> {
+        let cached = self.path(crc32, size);
+        if cached.exists() {
+            return Ok(());
+        }
+        let dir = cached.parent().unwrap_or(&self.dir);
+        fs::create_dir_all(dir)?;
+        if self.link {
+            return match fs::hard_link(path, &cached) {
+                Err(e) if e.kind() != ErrorKind::AlreadyExists => self.copy(path, dir, &cached),
+                _ => Ok(()),
+            };
+        }
+        self.copy(path, dir, &cached)
+    }
+
+    /// Copies `path` to `cached` through a temporary file of `dir`, so that the extractions
+    /// sharing the cache never see a partial copy.
+    fn copy(&self, path: &Path, dir: &Path, cached: &Path) -> std::io::Result<()> {
+        let mut temp = tempfile::NamedTempFile::new_in(dir)?;
+        std::io::copy(&mut File::open(path)?, temp.as_file_mut())?;
+        temp.persist(cached)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restores_stored_files() {
+        let dir = std::env::temp_dir().join(format!("hezi-cache-{}", std::process::id()));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let extracted = dir.join("extracted.txt");
+        fs::write(&extracted, "content").unwrap();
+
+        for link in [false, true] {
+            let cache = ExtractCache {
+                link,
+                ..ExtractCache::new(dir.join(format!("cache-{}", link)))
+            };
+            let dest = dir.join(format!("restored-{}.txt", link));
+            assert!(!cache.restore(0xcafe, 7, &dest, None, None).unwrap());
+            cache.store(0xcafe, 7, &extracted).unwrap();
+            assert!(cache.path(0xcafe, 7).is_file());
+            // the size is part of the key
+            assert!(!cache.restore(0xcafe, 8, &dest, None, None).unwrap());
+
+            let modified = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_000);
+            assert!(cache
+                .restore(0xcafe, 7, &dest, Some(modified), None)
+                .unwrap());
+            assert_eq!(fs::read_to_string(&dest).unwrap(), "content");
+            let restored = fs::metadata(&dest).unwrap().modified().unwrap();
+            assert_eq!(restored == modified, !link);
+        }
+        _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,1364 @@
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::{Instant, SystemTime},
+};
+
+use super::{
+    adaptive_writes::{AdaptiveWrites, StoreHook},
+    safe_join, symlink_target_within, ArchiveError, ArchiveEvent, ArchiveFileEntity,
+    ArchiveFileEntityType, DataSource, EntryInspector, EventHandler, ExceededLimit, ExtractLimits,
+    ExtractOptions, ExtractReport, Lengthed, SkipReason, Verdict,
+};
+
+/// Where the extracted entries come from, named in the events.
+pub(crate) trait ExtractSource: std::fmt::Display {
+    /// Files the archive is read from, which its entries are never extracted over.
+    fn files(&self) -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    /// Size of the archive, which the entries recording no compressed size of their own are
+    /// checked against for [`ExtractLimits::max_compression_ratio`].
+    fn size(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl ExtractSource for &str {}
+
+impl ExtractSource for &DataSource {
+    fn files(&self) -> Vec<PathBuf> {
+        self.paths()
+    }
+
+    fn size(&self) -> Option<u64> {
+        self.len().ok()
+    }
+}
+
+/// Forwards the events of an extraction to its [`ExtractOptions`], tallying them into an
+/// [`ExtractReport`].
+pub(crate) struct ReportingEvents<'o> {
+    events: &'o dyn EventHandler,
+    started: Instant,
+    report: RefCell<ExtractReport>,
+}
+
+impl<'o> ReportingEvents<'o> {
+    pub(crate) fn new(events: &'o dyn EventHandler) -> Self {
+        Self {
+            events,
+            started: Instant::now(),
+            report: RefCell::default(),
+        }
+    }
+
+    pub(crate) fn finish(self) -> ExtractReport {
+        let mut report = self.report.into_inner();
+        report.duration = self.started.elapsed();
+        report
+    }
+}
+
+impl EventHandler for ReportingEvents<'_> {
+    fn handle(&self, event: ArchiveEvent) {
+        match &event {
+            ArchiveEvent::Extracting(path, size) => {
+                let mut report = self.report.borrow_mut();
+                report.extracted.push(PathBuf::from(path));
+                report.bytes += size.unwrap_or(0);
+            }
+            ArchiveEvent::Created(path, _) => {
+                self.report.borrow_mut().extracted.push(PathBuf::from(path));
+            }
+            ArchiveEvent::Skipped(_, SkipReason::NotInFiles) => {}
+            ArchiveEvent::Skipped(name, reason) => {
+                self.report
+                    .borrow_mut()
+                    .skipped
+                    .push((name.clone(), reason.clone()));
+            }
+            ArchiveEvent::FailedToReadEntry(name, e) => {
+                self.report
+                    .borrow_mut()
+                    .failed
+                    .push((name.clone(), e.to_string()));
+            }
+            _ => {}
+        }
+        self.events.handle(event);
+    }
+}
+
+/// Sends an [`ArchiveEvent::EntryProgress`] every [`ExtractOptions::progress_interval`] bytes read
+/// from the content of a file, and once it is all read if any was sent. Fails once more bytes
+/// than the [`ExtractLimits`] allow are read.
+struct ProgressReader<'r, R: Read + ?Sized> {
+    reader: &'r mut R,
+    interval: Option<u64>,
+    name: String,
+    size: Option<u64>,
+    copied: u64,
+    reported: u64,
+    events: &'r dyn EventHandler,
+    /// Bytes the file can hold, and the limit setting it.
+    allowed: Option<(u64, ExceededLimit)>,
+    exceeded: Option<ExceededLimit>,
+}
+
+impl<R: Read + ?Sized> Read for ProgressReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.copied += n as u64;
+        if let Some((allowed, limit)) = self.allowed.filter(|(a, _)| self.copied > *a) {
+            self.exceeded = Some(limit);
+            return Err(std::io::Error::other(format!(
+                "{} goes past {} with {} bytes",
+                self.name, limit, allowed
+            )));
+        }
+        if let Some(interval) = self.interval {
+            let done = n == 0 && !buf.is_empty() && self.reported > 0;
+            if self.copied - self.reported >= interval || (done && self.copied > self.reported) {
+                self.reported = self.copied;
+                self.events.handle(ArchiveEvent::EntryProgress(
+                    self.name.clone(),
+                    self.copied,
+                    self.size,
+                ));
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// Writes archive entries to disk on behalf of the backends, applying the [`ExtractOptions`]
+/// policies (file selection, path containment, overwrite, permissions) and emitting the
+/// corresponding events.
+pub(crate) struct ExtractSink<'o, 'a> {
+    options: &'o ExtractOptions<'a>,
+    events: &'o ReportingEvents<'o>,
+    root: PathBuf,
+    files: Option<HashSet<String>>,
+    // directory permissions are applied last so they cannot prevent writing descendants
+    directory_modes: Vec<(PathBuf, u32)>,
+    /// Writers of the files when [`ExtractOptions::adaptive`] is set.
+    writes: Option<AdaptiveWrites>,
+    /// Files restored from the [`ExtractOptions::cache`].
+    restored: usize,
+    /// Files of the archive extracted, with their symlinks resolved.
+    archive: Vec<PathBuf>,
+    /// Size of the archive extracted, if known.
+    archive_size: Option<u64>,
+    /// Entries and bytes extracted so far, checked against [`ExtractOptions::limits`].
+    entries: usize,
+    bytes: u64,
+}
+
+impl<'o, 'a> ExtractSink<'o, 'a> {
+    pub(crate) fn new(
+        options: &'o ExtractOptions<'a>,
+        events: &'o ReportingEvents<'o>,
+    ) -> Result<Self, ArchiveError> {
+        if options.destination.symlink_metadata().is_err() {
+            fs::create_dir_all(&options.destination)?;
+        }
+
+        // Canonicalizing the destination will prepend the path with '\\?\' on windows which
+        // will allow windows APIs to treat the path as an extended-length path with a 32,767
+        // character limit. Otherwise all unpacked paths over 260 characters will fail on
+        // creation with a NotFound exception.
+        let root = options
+            .destination
+            .canonicalize()
+            .unwrap_or(options.destination.to_path_buf());
+        // before the writers start, see umask
+        umask();
+
+        Ok(Self {
+            options,
+            events,
+            root,
+            files: options.files.clone().map(|f| f.into_iter().collect()),
+            directory_modes: Vec::new(),
+            writes: options
+                .adaptive
+                .then(|| AdaptiveWrites::new(options.retry))
+                .transpose()?,
+            restored: 0,
+            archive: Vec::new(),
+            archive_size: None,
+            entries: 0,
+            bytes: 0,
+        })
+    }
+
+    /// Counts the entry `name` against [`ExtractLimits::max_entries`].
+    fn count_entry(&mut self, name: &str) -> Result<(), ArchiveError> {
+        self.entries += 1;
+        match self.options.limits.max_entries {
+            Some(max) if self.entries > max => Err(ArchiveError::LimitExceeded(
+                name.to_string(),
+                ExceededLimit::Entries(max),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Bytes the file `entity` can hold, and the limit setting them, failing if the size it
+    /// declares is already past them.
+    fn allowed_size(
+        &self,
+        entity: &ArchiveFileEntity,
+    ) -> Result<Option<(u64, ExceededLimit)>, ArchiveError> {
+        let ExtractLimits {
+            max_total_uncompressed,
+            max_entry_size,
+            max_compression_ratio,
+            ..
+        } = self.options.limits;
+        // entries without a compressed size of their own are held together to the archive's
+        let ratio = max_compression_ratio.and_then(|max| {
+            let allowed = |compressed: u64| {
+                ((compressed as f64 * max) as u64).max(ExtractLimits::RATIO_CHECKED_FROM)
+            };
+            let allowed = match (entity.compressed_size, self.archive_size) {
+                (Some(compressed), _) => allowed(compressed),
+                (None, Some(archive)) => allowed(archive).saturating_sub(self.bytes),
+                (None, None) => return None,
+            };
+            Some((allowed, ExceededLimit::CompressionRatio(max)))
+        });
+        let allowed = [
+            max_total_uncompressed.map(|max| {
+                (
+                    max.saturating_sub(self.bytes),
+                    ExceededLimit::TotalUncompressed(max),
+                )
+            }),
+            max_entry_size.map(|max| (max, ExceededLimit::EntrySize(max))),
+            ratio,
+        ]
+        .into_iter()
+        .flatten()
+        .min_by_key(|(allowed, _)| *allowed);
+        match (allowed, entity.size) {
+            (Some((allowed, limit)), Some(size)) if size > allowed => {
+                Err(ArchiveError::LimitExceeded(entity.name.clone(), limit))
+            }
+            _ => Ok(allowed),
+        }
+    }
+
+    /// Skips the entries that would be extracted over `files`, the files of the archive, such
+    /// as an entry named after the archive extracted next to it.
+    pub(crate) fn protect(&mut self, files: Vec<PathBuf>) {
+        self.archive = files.iter().filter_map(|f| f.canonicalize().ok()).collect();
+    }
+
+    /// Checks the entries recording no compressed size against `size`, the size of the archive.
+    pub(crate) fn measure(&mut self, size: Option<u64>) {
+        self.archive_size = size;
+    }
+
+    fn is_archive(&self, path: &Path) -> bool {
+        // only the paths named after a file of the archive are resolved
+        self.archive.iter().any(|file| {
+            file.file_name() == path.file_name()
+                && path.canonicalize().is_ok_and(|path| path == *file)
+        })
+    }
+
+    /// Whether the entry `name` was selected by [`ExtractOptions::files`].
+    pub(crate) fn wants(&self, name: &str) -> bool {
+        self.files
+            .as_ref()
+            .is_none_or(|files| files.contains(name) || files.contains(name.trim_end_matches('/')))
+    }
+
+    /// Path `name` is extracted to, see [`safe_join`], or `None` (after emitting a skip event) if
+    /// it would land outside of the destination or over the archive.
+    fn resolve(&self, name: &str) -> Option<PathBuf> {
+        match safe_join(&self.root, name) {
+            Ok(path) if self.is_archive(&path) => {
+                self.events.handle(ArchiveEvent::Skipped(
+                    name.to_string(),
+                    SkipReason::ArchiveItself,
+                ));
+                None
+            }
+            Ok(path) => Some(path),
+            Err(_) => {
+                self.events.handle(ArchiveEvent::Skipped(
+                    name.to_string(),
+                    SkipReason::UnsafePath,
+                ));
+                None
+            }
+        }
+    }
+
+    /// Prepares `path` for writing an entry last modified at `modified`: creates its parents and
+    /// applies the overwrite and update policies. Returns `false` if the entry must be skipped.
+    fn prepare(&mut self, path: &Path, modified: Option<SystemTime>) -> Result<bool, ArchiveError> {
+        // an entry of the same name may still be being written
+        if let Some(writes) = self.writes.as_mut().filter(|w| w.is_pending(path)) {
+            writes.wait_idle()?;
+        }
+        if let Some(p) = path.parent() {
+            self.create_dirs(p)?;
+        }
+        if let Ok(existing) = path.symlink_metadata() {
+            if self.options.update {
+                // entries of unknown age always replace the existing file
+                if let (Some(modified), Ok(existing)) = (modified, existing.modified()) {
+                    if modified <= existing {
+                        self.events.handle(ArchiveEvent::Skipped(
+                            path.to_string_lossy().to_string(),
+                            SkipReason::IfOlder,
+                        ));
+                        return Ok(false);
+                    }
+                }
+            }
+            if self.options.overwrite || self.options.update {
+                fs::remove_file(path)?;
+            } else {
+                self.events.handle(ArchiveEvent::Skipped(
+                    path.to_string_lossy().to_string(),
+                    SkipReason::AlreadyExists,
+                ));
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    /// Creates `path` and its missing parents, which get [`ExtractOptions::dir_mode`] too.
+    fn create_dirs(&mut self, path: &Path) -> Result<(), ArchiveError> {
+        if path.exists() {
+            return Ok(());
+        }
+        if let Some(mode) = self.options.dir_mode {
+            let missing = path.ancestors().take_while(|p| !p.exists());
+            let missing = missing.map(|p| (p.to_path_buf(), mode)).collect::<Vec<_>>();
+            self.directory_modes.extend(missing);
+        }
+        fs::create_dir_all(path)?;
+        Ok(())
+    }
+
+    pub(crate) fn directory(&mut self, name: &str, mode: Option<u32>) -> Result<(), ArchiveError> {
+        if !self.wants(name) {
+            return Ok(());
+        }
+        self.count_entry(name)?;
+        let Some(path) = self.resolve(name) else {
+            return Ok(());
+        };
+
+        self.create_dirs(&path)?;
+        self.events.handle(ArchiveEvent::Created(
+            path.to_string_lossy().to_string(),
+            ArchiveFileEntityType::Directory,
+        ));
+        if let Some(mode) = effective_mode(
+            mode,
+            self.options.dir_mode,
+            self.options.preserve_permissions,
+        ) {
+            self.directory_modes.push((path, mode));
+        }
+        Ok(())
+    }
+
+    /// Copies `reader` to the file entry `entity`, returning the number of bytes written or `None`
+    /// if the entry was skipped. The modification time of the entry is kept, if known. When the
+    /// archive records the `crc32` of the content, it is restored from the
+    /// [`ExtractOptions::cache`] if it holds it, without reading `reader`, and stored there once
+    /// written otherwise.
+    pub(crate) fn file<R: Read + ?Sized>(
+        &mut self,
+        entity: &ArchiveFileEntity,
+        mode: Option<u32>,
+        crc32: Option<u32>,
+        reader: &mut R,
+    ) -> Result<Option<u64>, ArchiveError> {
+        let name = entity.name.as_str();
+        if !self.wants(name) {
+            return Ok(None);
+        }
+        self.count_entry(name)?;
+        let allowed = self.allowed_size(entity)?;
+        let Some(path) = self.resolve(name) else {
+            return Ok(None);
+        };
+        let modified = entity.last_modified.map(SystemTime::from);
+        if !self.prepare(&path, modified)? {
+            return Ok(None);
+        }
+        let mode = effective_mode(
+            mode,
+            self.options.file_mode,
+            self.options.preserve_permissions,
+        );
+
+        let mut reader = ProgressReader {
+            reader,
+            interval: self.options.progress_interval.filter(|i| *i > 0),
+            name: path.to_string_lossy().to_string(),
+            size: entity.size,
+            copied: 0,
+            reported: 0,
+            events: self.events,
+            allowed,
+            exceeded: None,
+        };
+        let written = self.write_file(entity, &path, modified, mode, crc32, &mut reader);
+        if let Some(limit) = reader.exceeded {
+            // the writers may still hold the part of the file read before the limit
+            if let Some(writes) = &mut self.writes {
+                writes.wait_idle()?;
+            }
+            _ = fs::remove_file(&path);
+            return Err(ArchiveError::LimitExceeded(entity.name.clone(), limit));
+        }
+        let written = written?;
+        self.bytes += written.unwrap_or_default();
+        Ok(written)
+    }
+
+    /// Writes the content of `entity` read from `reader` to `path`, from the cache when it holds
+    /// it, returning its size or `None` if an inspector rejected it.
+    fn write_file<R: Read + ?Sized>(
+        &mut self,
+        entity: &ArchiveFileEntity,
+        path: &Path,
+        modified: Option<SystemTime>,
+        mode: Option<u32>,
+        crc32: Option<u32>,
+        reader: &mut ProgressReader<'_, R>,
+    ) -> Result<Option<u64>, ArchiveError> {
+        // the cache holds the content of the entries as they are in the archive
+        let cached = match (&self.options.cache, crc32, entity.size) {
+            (Some(cache), Some(crc32), Some(size))
+                if self.options.transformers.is_empty() && self.options.inspector.is_none() =>
+            {
+                Some((cache, crc32, size))
+            }
+            _ => None,
+        };
+        if let Some((cache, crc32, size)) = cached {
+            if cache.restore(crc32, size, path, modified, mode)? {
+                self.events.handle(ArchiveEvent::Extracting(
+                    path.to_string_lossy().to_string(),
+                    entity.size,
+                ));
+                self.restored += 1;
+                return Ok(Some(size));
+            }
+        }
+
+        let staged = match &self.options.inspector {
+            Some(inspector) => match self.inspect(inspector.as_ref(), entity, path, reader)? {
+                Some(staged) => Some(staged),
+                None => return Ok(None),
+            },
+            None => None,
+        };
+        self.events.handle(ArchiveEvent::Extracting(
+            path.to_string_lossy().to_string(),
+            entity.size,
+        ));
+        let written = match (staged, &mut self.writes) {
+            (None, Some(writes)) => {
+                let store = cached.map(|(cache, crc32, size)| {
+                    let cache = cache.clone();
+                    Box::new(move |path: &Path| cache.store(crc32, size, path)) as StoreHook
+                });
+                return writes.write(path, reader, modified, mode, store).map(Some);
+            }
+            (Some((staged, written)), _) => {
+                if let Some(modified) = modified {
+                    File::options()
+                        .write(true)
+                        .open(&staged)?
+                        .set_modified(modified)?;
+                }
+                fs::rename(&staged, path)?;
+                written
+            }
+            (None, None) => {
+                let outfile = File::create(path)?;
+                let mut outfile = self.options.retry.wrap(
+                    outfile,
+                    path.display(),
+                    Some(self.events as &dyn EventHandler),
+                );
+                let written = std::io::copy(reader, &mut outfile)?;
+                if let Some(modified) = modified {
+                    outfile.get_ref().set_modified(modified)?;
+                }
+                written
+            }
+        };
+        if let Some(mode) = mode {
+            set_mode(path, mode)?;
+        }
+        if let Some((cache, crc32, size)) = cached.filter(|(_, _, size)| *size == written) {
+            cache.store(crc32, size, path)?;
+        }
+        Ok(Some(written))
+    }
+
+    /// Stages the content of `entity` next to `path` for `inspector` to read it, returning the
+    /// staged file and its size if the entry is allowed, or `None` once it is rejected.
+    fn inspect<R: Read + ?Sized>(
+        &self,
+        inspector: &dyn EntryInspector,
+        entity: &ArchiveFileEntity,
+        path: &Path,
+        reader: &mut R,
+    ) -> Result<Option<(PathBuf, u64)>, ArchiveError> {
+        let mut file_name = std::ffi::OsString::from(".");
+        file_name.push(path.file_name().unwrap_or_default());
+        file_name.push(".hezi-inspect");
+        let staged = path.with_file_name(file_name);
+
+        let verdict = (|| {
+            let outfile = File::create(&staged)?;
+            let mut outfile = self.options.retry.wrap(
+                outfile,
+                staged.display(),
+                Some(self.events as &dyn EventHandler),
+            );
+            let written = std::io::copy(reader, &mut outfile)?;
+            drop(outfile);
+            let verdict = inspector.inspect(entity, &mut File::open(&staged)?)?;
+            Ok::<_, ArchiveError>((verdict, written))
+        })();
+        match verdict {
+            Ok((Verdict::Allow, written)) => Ok(Some((staged, written))),
+            Ok((Verdict::Reject(reason), _)) => {
+                fs::remove_file(&staged)?;
+                self.events.handle(ArchiveEvent::Skipped(
+                    entity.name.clone(),
+                    SkipReason::Rejected(reason),
+                ));
+                Ok(None)
+            }
+            Err(e) => {
+                _ = fs::remove_file(&staged);
+                Err(e)
+            }
+        }
+    }
+
+    /// Creates the symbolic link `name` pointing to `target`, which must resolve inside the
+    /// destination.
+    pub(crate) fn symlink<T: AsRef<Path>>(
+        &mut self,
+        name: &str,
+        target: T,
+    ) -> Result<(), ArchiveError> {
+        if !self.wants(name) {
+            return Ok(());
+        }
+        self.count_entry(name)?;
+        let Some(path) = self.resolve(name) else {
+            return Ok(());
+        };
+        let Ok(target) = symlink_target_within(&self.root, &path, target) else {
+            self.events.handle(ArchiveEvent::Skipped(
+                name.to_string(),
+                SkipReason::UnsafePath,
+            ));
+            return Ok(());
+        };
+        if !self.prepare(&path, None)? {
+            return Ok(());
+        }
+
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(target, &path)?;
+        #[cfg(windows)]
+        std::os::windows::fs::symlink_file(target, &path)?;
+        self.events.handle(ArchiveEvent::Created(
+            path.to_string_lossy().to_string(),
+            ArchiveFileEntityType::SymbolicLink,
+        ));
+        Ok(())
+    }
+
+    /// Creates the entry `name` as a hard link to the previously extracted entry `target`.
+    pub(crate) fn hard_link(&mut self, name: &str, target: &str) -> Result<(), ArchiveError> {
+        if !self.wants(name) {
+            return Ok(());
+        }
+        self.count_entry(name)?;
+        let (Some(path), Some(original)) = (self.resolve(name), self.resolve(target)) else {
+            return Ok(());
+        };
+        if !self.prepare(&path, None)? {
+            return Ok(());
+        }
+
+        // the original may still be being written
+        if let Some(writes) = &mut self.writes {
+            writes.wait_idle()?;
+        }
+        fs::hard_link(original, &path)?;
+        self.events.handle(ArchiveEvent::Created(
+            path.to_string_lossy().to_string(),
+            ArchiveFileEntityType::File,
+        ));
+        Ok(())
+    }
+
+    /// Reports an entry that cannot be extracted.
+    pub(crate) fn skip(&self, name: &str, reason: SkipReason) {
+        if self.wants(name) {
+            self.events
+                .handle(ArchiveEvent::Skipped(name.to_string(), reason));
+        }
+    }
+
+    /// Applies the deferred directory permissions and reports the end of the extraction.
+    pub(crate) fn finish<S: std::fmt::Display>(mut self, source: S) -> Result<(), ArchiveError> {
+        if let Some(writes) = self.writes.take() {
+            let settings = writes.finish()?;
+            self.events.handle(ArchiveEvent::Log(format!(
+                "Wrote the files on {} thread(s) in chunks of {} KiB",
+                settings.writers,
+                settings.chunk_size / 1024
+            )));
+        }
+        if self.restored > 0 {
+            self.events.handle(ArchiveEvent::Log(format!(
+                "Restored {} files from the cache",
+                self.restored
+            )));
+        }
+        // deepest directories first, a read-only parent would otherwise block its children
+        self.directory_modes
+            .sort_by_key(|(p, _)| std::cmp::Reverse(p.components().count()));
+        for (path, mode) in &self.directory_modes {
+            set_mode(path, *mode)?;
+        }
+
+        self.events.handle(ArchiveEvent::DoneExtracting(
+            source.to_string(),
+            self.root.to_string_lossy().to_string(),
+        ));
+        Ok(())
+    }
+}
+
+/// Mode to give an extracted file or directory: the override if any, else the permissions of its
+/// entry less the umask, like the files created without a mode, or as they are if `preserve`.
+/// Their type bits are dropped, and the special bits of the entry unless `preserve`.
+fn effective_mode(entry: Option<u32>, mode: Option<u32>, preserve: bool) -> Option<u32> {
+    match mode {
+        Some(mode) => Some(mode & 0o7777),
+        None if preserve => entry.map(|m| m & 0o7777),
+        None => entry.map(|m| m & 0o777 & !umask()),
+    }
+}
+
+/// File mode creation mask of the process, read once. Linux reports it in `/proc`, elsewhere
+/// reading it means replacing it for a moment, during which the files created by other threads
+/// get the wrong mask, so it is read before the extraction starts any.
+#[cfg(unix)]
+fn umask() -> u32 {
+    static UMASK: OnceLock<u32> = OnceLock::new();
+    *UMASK.get_or_init(|| {
+        let status = fs::read_to_string("/proc/self/status").unwrap_or_default();
+        let reported = status
+            .lines()
+            .find_map(|line| line.strip_prefix("Umask:"))
+            .and_then(|mask| u32::from_str_radix(mask.trim(), 8).ok());
+        reported.unwrap_or_else(|| {
+            // SAFETY: umask only swaps the mask of the process, which is restored right away
+            let mask = unsafe { libc::umask(0o022) };
+            unsafe { libc::umask(mask) };
+            mask as u32
+        })
+    })
+}
+
+#[cfg(not(unix))]
+fn umask() -> u32 {
+    0
+}
+
+#[cfg(unix)]
+pub(crate) fn set_mode(path: &Path, mode: u32) -> Result<(), ArchiveError> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub(crate) fn set_mode(_path: &Path, _mode: u32) -> Result<(), ArchiveError> {
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, FixedOffset};
+
+    use crate::archive::{extract_cache::ExtractCache, retry::IoRetryPolicy, SimpleLogger};
+
+    fn entry(
+        name: &str,
+        size: Option<u64>,
+        last_modified: Option<DateTime<FixedOffset>>,
+    ) -> ArchiveFileEntity {
+        ArchiveFileEntity {
+            name: name.to_string(),
+            size,
+            compressed_size: None,
+            last_modified,
+            compression: None,
+            fstype: ArchiveFileEntityType::File,
+            raw: None,
+            unix: None,
+        }
+    }
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("hezi-sink-{}-{}", std::process::id(), name));
+        _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn options(destination: PathBuf, overwrite: bool) -> ExtractOptions<'static> {
+        ExtractOptions {
+            destination,
+            password: None,
+            files: None,
+            overwrite,
+            update: false,
+            file_mode: None,
+            dir_mode: None,
+            preserve_permissions: false,
+            show_hidden: true,
+            retry: IoRetryPolicy::default(),
+            transformers: vec![],
+            inspector: None,
+            adaptive: false,
+            cache: None,
+            progress_interval: None,
+            limits: Default::default(),
+            event_handler: Box::new(SimpleLogger),
+        }
+    }
+
+    #[test]
+    fn writes_files_inside_destination_only() {
+        let dir = scratch_dir("contained");
+        let options = options(dir.join("out"), false);
+        let events = ReportingEvents::new(&options);
+        let mut sink = ExtractSink::new(&options, &events).unwrap();
+
+        let written = sink
+            .file(
+                &entry("a/b.txt", Some(5), None),
+                None,
+                None,
+                &mut "hello".as_bytes(),
+            )
+            .unwrap();
+        assert_eq!(written, Some(5));
+        let escaped = sink
+            .file(
+                &entry("../evil.txt", Some(4), None),
+                None,
+                None,
+                &mut "evil".as_bytes(),
+            )
+            .unwrap();
+        assert_eq!(escaped, None);
+        let absolute = sink
+            .file(
+                &entry("/etc/evil.txt", Some(4), None),
+                None,
+                None,
+                &mut "evil".as_bytes(),
+            )
+            .unwrap();
+        assert_eq!(absolute, None);
+        let climbing = sink
+            .file(
+                &entry("a/../b", Some(4), None),
+                None,
+                None,
+                &mut "evil".as_bytes(),
+            )
+            .unwrap();
+        assert_eq!(climbing, None);
+        let current = sink
+            .file(
+                &entry("./c.txt", Some(2), None),
+                None,
+                None,
+                &mut "ok".as_bytes(),
+            )
+            .unwrap();
+        assert_eq!(current, Some(2));
+        sink.finish("test").unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.join("out/a/b.txt")).unwrap(),
+            "hello"
+        );
+        assert!(!dir.join("evil.txt").exists());
+        assert!(!dir.join("out/b").exists());
+
+        let report = events.finish();
+        let root = dir.join("out").canonicalize().unwrap();
+        assert_eq!(
+            report.extracted,
+            vec![root.join("a/b.txt"), root.join("c.txt")]
+        );
+        let skipped = report
+            .skipped
+            .iter()
+            .map(|(n, r)| (n.as_str(), r.name()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            skipped,
+            [
+                ("../evil.txt", "unsafe_path"),
+                ("/etc/evil.txt", "unsafe_path"),
+                ("a/../b", "unsafe_path")
+            ]
+        );
+        assert!(!report.is_complete());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn never_writes_through_links_leading_outside() {
+        let dir = scratch_dir("links");
+        let (root, outside) = (dir.join("root"), dir.join("outside"));
+        fs::create_dir_all(&outside).unwrap();
+        fs::create_dir_all(&root).unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("escape")).unwrap();
+
+        let options = options(root.clone(), true);
+        let events = ReportingEvents::new(&options);
+        let mut sink = ExtractSink::new(&options, &events).unwrap();
+        let mut file = |name: &str| {
+            sink.file(&entry(name, None, None), None, None, &mut "evil".as_bytes())
+                .unwrap()
+        };
+        assert_eq!(file("escape/evil"), None);
+        assert_eq!(file("escape"), None);
+        // `sub/up` leads back to the root, where `../out` would climb out of it
+        sink.directory("sub", None).unwrap();
+        sink.symlink("sub/up", "..").unwrap();
+        sink.symlink("sub/up/out", "../out").unwrap();
+        sink.symlink("passwd", "/etc/passwd").unwrap();
+        sink.finish("test").unwrap();
+
+        assert_eq!(fs::read_dir(&outside).unwrap().count(), 0);
+        assert!(!root.join("out").exists());
+        assert!(root.join("passwd").symlink_metadata().is_err());
+        let skipped = events.finish().skipped;
+        let skipped = skipped
+            .iter()
+            .map(|(n, r)| (n.as_str(), r.name()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            skipped,
+            [
+                ("escape/evil", "unsafe_path"),
+                ("escape", "unsafe_path"),
+                ("sub/up/out", "unsafe_path"),
+                ("passwd", "unsafe_path")
+            ]
+        );
+    }
+
+    #[test]
+    fn never_overwrites_the_archive() {
+        let dir = scratch_dir("itself");
+        let archive = dir.join("backup.tar");
+        fs::write(&archive, "archive").unwrap();
+
+        let options = options(dir.clone(), true);
+        let events = ReportingEvents::new(&options);
+        let mut sink = ExtractSink::new(&options, &events).unwrap();
+        sink.protect(vec![archive.clone()]);
+        let itself = sink
+            .file(
+                &entry("./backup.tar", Some(4), None),
+                None,
+                None,
+                &mut "evil".as_bytes(),
+            )
+            .unwrap();
+        assert_eq!(itself, None);
+        let other = sink
+            .file(
+                &entry("sub/backup.tar", Some(2), None),
+                None,
+                None,
+                &mut "ok".as_bytes(),
+            )
+            .unwrap();
+        assert_eq!(other, Some(2));
+        sink.finish("test").unwrap();
+
+        assert_eq!(fs::read_to_string(&archive).unwrap(), "archive");
+        assert!(matches!(
+            events.finish().skipped.as_slice(),
+            [(name, SkipReason::ArchiveItself)] if name == "./backup.tar"
+        ));
+    }
+
+    #[test]
+    fn stops_past_the_limits() {
+        let dir = scratch_dir("limits");
+        let mut options = options(dir.clone(), false);
+        options.limits = ExtractLimits {
+            max_total_uncompressed: Some(10),
+            max_entry_size: Some(6),
+            max_entries: Some(4),
+            max_compression_ratio: None,
+        };
+        let events = ReportingEvents::new(&options);
+        let mut sink = ExtractSink::new(&options, &events).unwrap();
+        let file = |sink: &mut ExtractSink, name: &str, size: Option<u64>, content: &str| {
+            sink.file(
+                &entry(name, size, None),
+                None,
+                None,
+                &mut content.as_bytes(),
+            )
+        };
+
+        // entries lying about their size are stopped once they are read past the limit
+        assert!(matches!(
+            file(&mut sink, "lying.txt", Some(2), "1234567"),
+            Err(ArchiveError::LimitExceeded(name, ExceededLimit::EntrySize(6))) if name == "lying.txt"
+        ));
+        assert!(!dir.join("lying.txt").exists());
+        assert!(matches!(
+            file(&mut sink, "large.txt", Some(7), ""),
+            Err(ArchiveError::LimitExceeded(_, ExceededLimit::EntrySize(6)))
+        ));
+
+        assert_eq!(file(&mut sink, "a.txt", None, "123456").unwrap(), Some(6));
+        assert!(matches!(
+            file(&mut sink, "b.txt", None, "12345"),
+            Err(ArchiveError::LimitExceeded(
+                _,
+                ExceededLimit::TotalUncompressed(10)
+            ))
+        ));
+        assert!(!dir.join("b.txt").exists());
+        assert!(matches!(
+            sink.directory("d", None),
+            Err(ArchiveError::LimitExceeded(name, ExceededLimit::Entries(4))) if name == "d"
+        ));
+    }
+
+    #[test]
+    fn stops_at_high_compression_ratios() {
+        let dir = scratch_dir("ratio");
+        let mut options = options(dir.clone(), false);
+        options.adaptive = true;
+        let events = ReportingEvents::new(&options);
+        let mut sink = ExtractSink::new(&options, &events).unwrap();
+        let bomb = |name: &str| ArchiveFileEntity {
+            compressed_size: Some(128),
+            ..entry(name, None, None)
+        };
+        let content = vec![0; 2 * 1024 * 1024];
+
+        // small files are not held to the ratio
+        let small = sink.file(&bomb("small"), None, None, &mut &content[..1024 * 1024]);
+        assert_eq!(small.unwrap(), Some(1024 * 1024));
+        assert!(matches!(
+            sink.file(&bomb("bomb"), None, None, &mut content.as_slice()),
+            Err(ArchiveError::LimitExceeded(
+                _,
+                ExceededLimit::CompressionRatio(10000.0)
+            ))
+        ));
+        assert!(!dir.join("bomb").exists());
+
+        let options = ExtractOptions {
+            limits: ExtractLimits::NONE,
+            ..options
+        };
+        let events = ReportingEvents::new(&options);
+        let mut sink = ExtractSink::new(&options, &events).unwrap();
+        let bomb = sink.file(&bomb("bomb"), None, None, &mut content.as_slice());
+        assert_eq!(bomb.unwrap(), Some(content.len() as u64));
+        sink.finish("test").unwrap();
+    }
+
+    #[test]
+    fn respects_overwrite() {
+        let dir = scratch_dir("overwrite");
+        fs::write(dir.join("f.txt"), "old").unwrap();
+
+        let keep = options(dir.clone(), false);
+        let events = ReportingEvents::new(&keep);
+        let mut sink = ExtractSink::new(&keep, &events).unwrap();
+        assert_eq!(
+            sink.file(
+                &entry("f.txt", None, None),
+                None,
+                None,
+                &mut "new".as_bytes()
+            )
+            .unwrap(),
+            None
+        );
+        assert_eq!(fs::read_to_string(dir.join("f.txt")).unwrap(), "old");
+
+        let replace = options(dir.clone(), true);
+        let events = ReportingEvents::new(&replace);
+        let mut sink = ExtractSink::new(&replace, &events).unwrap();
+        assert_eq!(
+            sink.file(
+                &entry("f.txt", None, None),
+                None,
+                None,
+                &mut "new".as_bytes()
+            )
+            .unwrap(),
+            Some(3)
+        );
+        assert_eq!(fs::read_to_string(dir.join("f.txt")).unwrap(), "new");
+    }
+
+    #[test]
+    fn update_only_replaces_older_files() {
+        let dir = scratch_dir("update");
+        fs::write(dir.join("f.txt"), "old").unwrap();
+        let existing = fs::metadata(dir.join("f.txt")).unwrap().modified().unwrap();
+        let at = |t: SystemTime| DateTime::<chrono::Utc>::from(t).fixed_offset();
+        let minute = std::time::Duration::from_secs(60);
+
+        let update = ExtractOptions {
+            update: true,
+            ..options(dir.clone(), false)
+        };
+        let events = ReportingEvents::new(&update);
+        let mut sink = ExtractSink::new(&update, &events).unwrap();
+        let older = Some(at(existing - minute));
+        assert_eq!(
+            sink.file(
+                &entry("f.txt", None, older),
+                None,
+                None,
+                &mut "stale".as_bytes()
+            )
+            .unwrap(),
+            None
+        );
+        assert_eq!(fs::read_to_string(dir.join("f.txt")).unwrap(), "old");
+
+        let newer = existing + minute;
+        assert_eq!(
+            sink.file(
+                &entry("f.txt", None, Some(at(newer))),
+                None,
+                None,
+                &mut "new".as_bytes()
+            )
+            .unwrap(),
+            Some(3)
+        );
+        let metadata = fs::metadata(dir.join("f.txt")).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("f.txt")).unwrap(), "new");
+        assert_eq!(metadata.modified().unwrap(), newer);
+    }
+
+    #[test]
+    fn adaptive_writes_wait_for_files_of_the_same_name() {
+        let dir = scratch_dir("adaptive");
+        let adaptive = ExtractOptions {
+            adaptive: true,
+            ..options(dir.clone(), true)
+        };
+        let events = ReportingEvents::new(&adaptive);
+        let mut sink = ExtractSink::new(&adaptive, &events).unwrap();
+        let content = "x".repeat(1 << 20);
+        for i in 0..20 {
+            let name = format!("d/{}.txt", i);
+            sink.file(
+                &entry(&name, None, None),
+                None,
+                None,
+                &mut content.as_bytes(),
+            )
+            .unwrap();
+        }
+        // replaced, then linked to, once the first one is written
+        sink.file(
+            &entry("d/0.txt", None, None),
+            None,
+            None,
+            &mut "new".as_bytes(),
+        )
+        .unwrap();
+        sink.hard_link("link.txt", "d/0.txt").unwrap();
+        sink.finish("test").unwrap();
+
+        assert_eq!(fs::read_to_string(dir.join("link.txt")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(dir.join("d/19.txt")).unwrap(), content);
+        assert_eq!(events.finish().extracted.len(), 22);
+    }
+
+    #[test]
+    fn restores_cached_files_without_reading_them() {
+        struct Unreadable;
+        impl Read for Unreadable {
+            fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("decompressed again"))
+            }
+        }
+
+        let dir = scratch_dir("cache");
+        let cache = ExtractCache::new(dir.join("cache"));
+        let modified = DateTime::parse_from_rfc3339("2020-01-02T03:04:05+00:00").unwrap();
+        for (out, adaptive) in [("first", false), ("second", false), ("third", true)] {
+            let cached = ExtractOptions {
+                cache: Some(cache.clone()),
+                adaptive,
+                ..options(dir.join(out), false)
+            };
+            let events = ReportingEvents::new(&cached);
+            let mut sink = ExtractSink::new(&cached, &events).unwrap();
+            let hello = entry("a/hello.txt", Some(5), Some(modified));
+            let written = match out {
+                "first" => sink.file(&hello, None, Some(0x3610a686), &mut "hello".as_bytes()),
+                _ => sink.file(&hello, None, Some(0x3610a686), &mut Unreadable),
+            };
+            assert_eq!(written.unwrap(), Some(5));
+            // entries without a checksum are not cached
+            sink.file(
+                &entry("b.txt", Some(2), None),
+                None,
+                None,
+                &mut "ok".as_bytes(),
+            )
+            .unwrap();
+            sink.finish("test").unwrap();
+
+            let path = dir.join(out).join("a/hello.txt");
+            assert_eq!(fs::read_to_string(&path).unwrap(), "hello");
+            assert_eq!(
+                fs::metadata(&path).unwrap().modified().unwrap(),
+                SystemTime::from(modified)
+            );
+        }
+        assert_eq!(fs::read_dir(dir.join("cache/36")).unwrap().count(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn applies_modes_less_umask_or_overrides() {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |path: PathBuf| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+
+        let dir = scratch_dir("modes");
+        let kept = options(dir.clone(), true);
+        let events = ReportingEvents::new(&kept);
+        let mut sink = ExtractSink::new(&kept, &events).unwrap();
+        sink.directory("d/", Some(0o40777)).unwrap();
+        sink.file(
+            &entry("d/f", None, None),
+            Some(0o100666),
+            None,
+            &mut "".as_bytes(),
+        )
+        .unwrap();
+        sink.finish("test").unwrap();
+        assert_eq!(mode(dir.join("d")), 0o777 & !umask());
+        assert_eq!(mode(dir.join("d/f")), 0o666 & !umask());
+
+        let overridden = ExtractOptions {
+            file_mode: Some(0o600),
+            dir_mode: Some(0o700),
+            ..options(dir.clone(), true)
+        };
+        let events = ReportingEvents::new(&overridden);
+        let mut sink = ExtractSink::new(&overridden, &events).unwrap();
+        sink.directory("d/", Some(0o755)).unwrap();
+        sink.file(&entry("d/f", None, None), None, None, &mut "".as_bytes())
+            .unwrap();
+        sink.finish("test").unwrap();
+        assert_eq!(mode(dir.join("d")), 0o700);
+        assert_eq!(mode(dir.join("d/f")), 0o600);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn drops_special_bits_unless_preserved() {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |path: PathBuf| fs::metadata(path).unwrap().permissions().mode() & 0o7777;
+        let extract = |options: &ExtractOptions| {
+            let events = ReportingEvents::new(options);
+            let mut sink = ExtractSink::new(options, &events).unwrap();
+            sink.directory("d/", Some(0o41777)).unwrap();
+            sink.file(
+                &entry("d/f", None, None),
+                Some(0o104755),
+                None,
+                &mut "".as_bytes(),
+            )
+            .unwrap();
+            sink.finish("test").unwrap();
+        };
+
+        let dir = scratch_dir("special-modes");
+        extract(&options(dir.clone(), true));
+        assert_eq!(mode(dir.join("d")), 0o777 & !umask());
+        assert_eq!(mode(dir.join("d/f")), 0o755 & !umask());
+
+        extract(&ExtractOptions {
+            preserve_permissions: true,
+            ..options(dir.clone(), true)
+        });
+        assert_eq!(mode(dir.join("d")), 0o1777);
+        assert_eq!(mode(dir.join("d/f")), 0o4755);
+    }
+
+    /// Reads its content three bytes at a time.
+    struct Chunked<'d>(&'d [u8]);
+
+    impl Read for Chunked<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let n = self.0.len().min(buf.len()).min(3);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[derive(Default)]
+    struct ProgressRecorder(RefCell<Vec<(String, u64, Option<u64>)>>);
+
+    impl EventHandler for ProgressRecorder {
+        fn handle(&self, event: ArchiveEvent) {
+            if let ArchiveEvent::EntryProgress(name, copied, size) = event {
+                let name = Path::new(&name).file_name().unwrap().to_string_lossy();
+                self.0.borrow_mut().push((name.to_string(), copied, size));
+            }
+        }
+    }
+
+    #[test]
+    fn reports_progress_within_files() {
+        let dir = scratch_dir("progress");
+        let recorder = std::rc::Rc::new(ProgressRecorder::default());
+        let reported = ExtractOptions {
+            progress_interval: Some(4),
+            event_handler: Box::new(recorder.clone()),
+            ..options(dir.clone(), false)
+        };
+        let events = ReportingEvents::new(&reported);
+        let mut sink = ExtractSink::new(&reported, &events).unwrap();
+
+        let large = entry("large.bin", Some(10), None);
+        sink.file(&large, None, None, &mut Chunked(b"0123456789"))
+            .unwrap();
+        let unsized_entry = entry("unsized.bin", None, None);
+        sink.file(&unsized_entry, None, None, &mut Chunked(b"abcde"))
+            .unwrap();
+        // files smaller than the interval only have their extracting event
+        let small = entry("small.bin", Some(2), None);
+        sink.file(&small, None, None, &mut Chunked(b"ab")).unwrap();
+
+        assert_eq!(fs::read(dir.join("large.bin")).unwrap(), b"0123456789");
+        assert_eq!(
+            *recorder.0.borrow(),
+            [
+                ("large.bin".to_string(), 6, Some(10)),
+                ("large.bin".to_string(), 10, Some(10)),
+                ("unsized.bin".to_string(), 5, None),
+            ]
+        );
+        _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn inspects_files_before_writing_them() {
+        let scan = |entity: &ArchiveFileEntity, reader: &mut dyn Read| {
+            let mut content = String::new();
+            reader.read_to_string(&mut content)?;
+            if entity.name == "broken.txt" {
+                return Err(ArchiveError::Io(std::io::Error::other("scanner is down")));
+            }
+            Ok(match content.contains("EICAR") {
+                true => Verdict::Reject("EICAR test file".to_string()),
+                false => Verdict::Allow,
+            })
+        };
+        let dir = scratch_dir("inspect");
+        let inspected = ExtractOptions {
+            inspector: Some(Box::new(scan)),
+            ..options(dir.clone(), false)
+        };
+        let events = ReportingEvents::new(&inspected);
+        let mut sink = ExtractSink::new(&inspected, &events).unwrap();
+
+        let modified = DateTime::parse_from_rfc3339("2020-01-02T03:04:05+00:00").unwrap();
+        let clean = entry("clean.txt", Some(5), Some(modified));
+        assert_eq!(
+            sink.file(&clean, None, None, &mut "hello".as_bytes())
+                .unwrap(),
+            Some(5)
+        );
+        let infected = entry("infected.txt", None, None);
+        assert_eq!(
+            sink.file(&infected, None, None, &mut "X5O EICAR".as_bytes())
+                .unwrap(),
+            None
+        );
+        let broken = entry("broken.txt", None, None);
+        assert!(sink
+            .file(&broken, None, None, &mut "data".as_bytes())
+            .is_err());
+
+        assert_eq!(fs::read_to_string(dir.join("clean.txt")).unwrap(), "hello");
+        assert_eq!(
+            fs::metadata(dir.join("clean.txt"))
+                .unwrap()
+                .modified()
+                .unwrap(),
+            SystemTime::from(modified)
+        );
+        // neither the rejected files nor their staged contents are left behind
+        let mut names = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["clean.txt"]);
+
+        let report = events.finish();
+        assert_eq!(report.extracted.len(), 1);
+        assert_eq!(report.bytes, 5);
+        assert!(matches!(
+            report.skipped.as_slice(),
+            [(name, SkipReason::Rejected(reason))]
+                if name == "infected.txt" && reason == "EICAR test file"
+        ));
+    }
+}
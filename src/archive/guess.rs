@@ -0,0 +1,263 @@
+// guesses the format of an archive from its name alone, for paths as well as urls, so that shells
+// and user interfaces can tell what a file is before opening it
+
+use std::path::Path;
+
+use serde::Serialize;
+
+use super::{codecs::ArchiveCompression, identify::Confidence, ArchiveError, ArchiveType};
+
+/// Format of an archive told by its name.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GuessedFormat {
+    pub archive: ArchiveType,
+    /// Compression of the archive as a whole, `None` for formats compressing their entries.
+    pub compression: Option<ArchiveCompression>,
+    /// [`Confidence::High`] for the extensions of the format itself, [`Confidence::Medium`] for
+    /// files of other kinds using the format, such as `.jar` or `.docx`, and [`Confidence::Low`]
+    /// for a compressed file which may as well hide an archive, such as `.gz`.
+    pub confidence: Confidence,
+}
+
+/// Guesses the format of archives from their name.
+pub trait ArchiveTypeExt {
+    /// Guesses the format from the extensions of the file name, ignoring their case. Names with
+    /// other dots, such as `my.backup.2024.tar.gz`, only have their last two extensions looked
+    /// at, and the query and fragment of urls are ignored.
+    fn guess_format(&self) -> Result<GuessedFormat, ArchiveError>;
+}
+
+impl<P: AsRef<Path> + ?Sized> ArchiveTypeExt for P {
+    fn guess_format(&self) -> Result<GuessedFormat, ArchiveError> {
+        let path = self.as_ref();
+        let unknown = || ArchiveError::UnknownFileExtension(path.to_string_lossy().to_string());
+        let name = file_name(path).ok_or_else(unknown)?.to_lowercase();
+
+        // a name without a dot has no extension, even if it is one, like `tar`
+        let mut extensions = name.rsplit('.');
+        let last = extensions.next().ok_or_else(unknown)?;
+        let before_last = extensions.next().ok_or_else(unknown)?;
+        let before_last = extensions.next().map(|_| before_last);
+
+        let (archive, compression, confidence) =
+            from_extensions(before_last, last).ok_or_else(unknown)?;
+        Ok(GuessedFormat {
+            archive,
+            compression,
+            confidence,
+        })
+    }
+}
+
+/// Last segment of `path`, without the query and fragment if it is a url.
+fn file_name(path: &Path) -> Option<String> {
+    let path = path.to_string_lossy();
+    let path = match path.split_once("://") {
+        Some((_, rest)) => {
+            let rest = rest.split(['?', '#']).next().unwrap_or_default();
+            // the host alone has no file name
+            rest.split_once('/')?.1.trim_end_matches('/').to_string()
+        }
+        None => path.to_string(),
+    };
+    Path::new(&path)
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+}
+
+/// Format told by the `last` extension of a name, in lower case, and the one `before_last` it.
+#[allow(unused_variables)]
+fn from_extensions(
+    before_last: Option<&str>,
+    last: &str,
+) -> Option<(ArchiveType, Option<ArchiveCompression>, Confidence)> {
+    use Confidence::{High, Low, Medium};
+
+    match (before_last, last) {
+        #[cfg(feature = "tar_archive")]
+        (Some("tar"), "gz" | "gzip") | (_, "tgz") => {
+            Some((ArchiveType::Tar, Some(ArchiveCompression::Gzip), High))
+        }
+        #[cfg(all(feature = "tar_archive", feature = "lzma_codecs"))]
+        (Some("tar"), "xz") | (_, "txz") => {
+            Some((ArchiveType::Tar, Some(ArchiveCompression::Lzma), High))
+        }
+        #[cfg(all(feature = "tar_archive", feature = "bzip2_codecs"))]
+        (Some("tar"), "bz2") | (_, "tbz2") => {
+            Some((ArchiveType::Tar, Some(ArchiveCompression::Bzip2), High))
+        }
+        #[cfg(all(feature = "tar_archive", feature = "zstd_codecs"))]
+        (Some("tar"), "zst" | "zstd") | (_, "tzst") => {
+            Some((ArchiveType::Tar, Some(ArchiveCompression::Zstd), High))
+        }
+        #[cfg(all(feature = "tar_archive", feature = "lzw_codecs"))]
+        (Some("tar"), "z") | (_, "taz") => {
+            Some((ArchiveType::Tar, Some(ArchiveCompression::Compress), High))
+        }
+        #[cfg(feature = "tar_archive")]
+        (_, "tar") => Some((ArchiveType::Tar, Some(ArchiveCompression::None), High)),
+        #[cfg(feature = "zip_archive")]
+        (_, "zip") => Some((ArchiveType::Zip, None, High)),
+        // packages and documents that are zip archives underneath
+        #[cfg(feature = "zip_archive")]
+        (_, "jar" | "war" | "apk" | "ipa" | "docx" | "xlsx" | "epub" | "odt") => {
+            Some((ArchiveType::Zip, None, Medium))
+        }
+        #[cfg(feature = "sevenz_archive")]
+        (_, "7z" | "7zip") => Some((ArchiveType::SevenZ, None, High)),
+        #[cfg(feature = "iso_archive")]
+        (_, "iso") => Some((ArchiveType::Iso, None, High)),
+        #[cfg(feature = "rar_archive")]
+        (_, "rar") => Some((ArchiveType::Rar, None, High)),
+        #[cfg(feature = "cpio_archive")]
+        (Some("cpio"), "gz" | "gzip") => {
+            Some((ArchiveType::Cpio, Some(ArchiveCompression::Gzip), High))
+        }
+        #[cfg(all(feature = "cpio_archive", feature = "lzma_codecs"))]
+        (Some("cpio"), "xz") => Some((ArchiveType::Cpio, Some(ArchiveCompression::Lzma), High)),
+        #[cfg(all(feature = "cpio_archive", feature = "bzip2_codecs"))]
+        (Some("cpio"), "bz2") => Some((ArchiveType::Cpio, Some(ArchiveCompression::Bzip2), High)),
+        #[cfg(all(feature = "cpio_archive", feature = "zstd_codecs"))]
+        (Some("cpio"), "zst" | "zstd") => {
+            Some((ArchiveType::Cpio, Some(ArchiveCompression::Zstd), High))
+        }
+        #[cfg(feature = "cpio_archive")]
+        (_, "cpio") => Some((ArchiveType::Cpio, Some(ArchiveCompression::None), High)),
+        #[cfg(feature = "ar_archive")]
+        (_, "a") => Some((ArchiveType::Ar, Some(ArchiveCompression::None), High)),
+        #[cfg(feature = "ar_archive")]
+        (_, "deb") => Some((ArchiveType::Ar, Some(ArchiveCompression::None), Medium)),
+        #[cfg(feature = "squashfs_archive")]
+        (_, "squashfs" | "sfs" | "sqfs") => Some((ArchiveType::Squashfs, None, High)),
+        #[cfg(feature = "cab_archive")]
+        (_, "cab") => Some((ArchiveType::Cab, None, High)),
+        #[cfg(feature = "wim_archive")]
+        (_, "wim") => Some((ArchiveType::Wim, None, High)),
+        #[cfg(feature = "lha_archive")]
+        (_, "lzh" | "lha") => Some((ArchiveType::Lha, None, High)),
+        #[cfg(feature = "arj_archive")]
+        (_, "arj") => Some((ArchiveType::Arj, None, High)),
+        #[cfg(feature = "msi_archive")]
+        (_, "msi" | "msm") => Some((ArchiveType::Msi, None, High)),
+        #[cfg(feature = "zpaq_archive")]
+        (_, "zpaq") => Some((ArchiveType::Zpaq, None, High)),
+        #[cfg(feature = "dmg_archive")]
+        (_, "dmg") => Some((ArchiveType::Dmg, None, High)),
+        // raw images are not all disk images
+        #[cfg(feature = "disk_image")]
+        (_, "img") => Some((ArchiveType::DiskImage, None, Medium)),
+        #[cfg(feature = "warc_archive")]
+        (Some("warc"), "gz") => Some((ArchiveType::Warc, Some(ArchiveCompression::Gzip), High)),
+        #[cfg(feature = "warc_archive")]
+        (_, "warc") => Some((ArchiveType::Warc, Some(ArchiveCompression::None), High)),
+        // any other file compressed on its own
+        #[cfg(feature = "compressed_file")]
+        (_, "gz" | "gzip") => Some((
+            ArchiveType::CompressedFile,
+            Some(ArchiveCompression::Gzip),
+            Low,
+        )),
+        #[cfg(all(feature = "compressed_file", feature = "lzma_codecs"))]
+        (_, "xz") => Some((
+            ArchiveType::CompressedFile,
+            Some(ArchiveCompression::Lzma),
+            Low,
+        )),
+        #[cfg(all(feature = "compressed_file", feature = "bzip2_codecs"))]
+        (_, "bz2") => Some((
+            ArchiveType::CompressedFile,
+            Some(ArchiveCompression::Bzip2),
+            Low,
+        )),
+        #[cfg(all(feature = "compressed_file", feature = "zstd_codecs"))]
+        (_, "zst" | "zstd") => Some((
+            ArchiveType::CompressedFile,
+            Some(ArchiveCompression::Zstd),
+            Low,
+        )),
+        #[cfg(all(feature = "compressed_file", feature = "lzw_codecs"))]
+        (_, "z") => Some((
+            ArchiveType::CompressedFile,
+            Some(ArchiveCompression::Compress),
+            Low,
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(all(test, feature = "tar_archive", feature = "zip_archive"))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn guesses_multi_dot_names_and_urls() {
+        for (name, archive, compression, confidence) in [
+            (
+                "my.backup.2024.tar.gz",
+                ArchiveType::Tar,
+                Some(ArchiveCompression::Gzip),
+                Confidence::High,
+            ),
+            (
+                "/home/me/v1.2/Release.TAR.GZ",
+                ArchiveType::Tar,
+                Some(ArchiveCompression::Gzip),
+                Confidence::High,
+            ),
+            ("Photos.ZIP", ArchiveType::Zip, None, Confidence::High),
+            ("app-1.0.jar", ArchiveType::Zip, None, Confidence::Medium),
+            (
+                "https://example.com/dl/src.v2.tar.gz?token=a.zip#top",
+                ArchiveType::Tar,
+                Some(ArchiveCompression::Gzip),
+                Confidence::High,
+            ),
+            (
+                "https://example.com/files/archive.zip/",
+                ArchiveType::Zip,
+                None,
+                Confidence::High,
+            ),
+            (
+                ".tar",
+                ArchiveType::Tar,
+                Some(ArchiveCompression::None),
+                Confidence::High,
+            ),
+        ] {
+            assert_eq!(
+                name.guess_format().unwrap(),
+                GuessedFormat {
+                    archive,
+                    compression,
+                    confidence
+                },
+                "{}",
+                name
+            );
+        }
+
+        #[cfg(feature = "compressed_file")]
+        assert_eq!(
+            Path::new("notes.2024.txt.gz").guess_format().unwrap(),
+            GuessedFormat {
+                archive: ArchiveType::CompressedFile,
+                compression: Some(ArchiveCompression::Gzip),
+                confidence: Confidence::Low,
+            }
+        );
+
+        // neither a name without extension, a directory nor a bare host tell a format
+        for name in ["tar", "archive.tar/notes", "https://example.zip", "..", ""] {
+            assert!(
+                matches!(
+                    name.guess_format(),
+                    Err(ArchiveError::UnknownFileExtension(_))
+                ),
+                "{}",
+                name
+            );
+        }
+    }
+}
@@ -0,0 +1,157 @@
+// tells what a file is: the archive type and compression found from its content and its name, how
+// much the two can be trusted, and whether the archive is encrypted
+
+use std::path::Path;
+
+use serde::Serialize;
+
+use super::{codecs::ArchiveCompression, Archive, ArchiveError, ArchiveType, Archived, DataSource};
+
+/// Password no archive is expected to be encrypted with, to tell whether one is.
+const PROBE_PASSWORD: &str = "hezi-identify-probe-b0c8d5f2e17a";
+
+/// How much the type found for a file can be trusted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Confidence {
+    /// Only the name of the file tells the type, its content was not recognized.
+    Low,
+    /// The content has the signature of the type, but a short or common one, or the name of
+    /// the file tells another type.
+    Medium,
+    /// The content has the signature of the type and the name does not tell otherwise.
+    High,
+}
+
+impl std::fmt::Display for Confidence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Confidence::Low => write!(f, "low"),
+            Confidence::Medium => write!(f, "medium"),
+            Confidence::High => write!(f, "high"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Identification {
+    /// Type of the archive, if either the content or the name of the file tells it.
+    #[serde(rename = "type")]
+    pub archive_type: Option<ArchiveType>,
+    /// Compressions wrapping the archive, outermost first, empty if it is not compressed as a
+    /// whole.
+    pub compression: Vec<ArchiveCompression>,
+    /// Whether the archive, or some of its entries, are encrypted, if it could be opened.
+    pub encrypted: Option<bool>,
+    /// How much the type can be trusted, if there is one.
+    pub confidence: Option<Confidence>,
+}
+
+/// Identifies the file at `path` from its content, falling back to its name.
+pub fn identify<P: AsRef<Path>>(path: P) -> Result<Identification, ArchiveError> {
+    let path = path.as_ref();
+    let source = DataSource::file(path)?;
+    let named = ArchiveType::guess_from_filename(path).ok();
+
+    let Ok((archive_type, compression)) = ArchiveType::try_from_datasource(source.try_clone()?)
+    else {
+        return Ok(Identification {
+            confidence: named.as_ref().map(|_| Confidence::Low),
+            compression: named
+                .as_ref()
+                .and_then(|(_, c)| c.clone())
+                .into_iter()
+                .filter(|c| *c != ArchiveCompression::None)
+                .collect(),
+            archive_type: named.map(|(t, _)| t),
+            encrypted: None,
+        });
+    };
+
+    let confidence = match &named {
+        Some((t, _)) if *t != archive_type => Confidence::Medium,
+        _ if has_weak_signature(&archive_type) => Confidence::Medium,
+        _ => Confidence::High,
+    };
+    // an archive accepting a password it cannot be encrypted with has nothing encrypted
+    let encrypted = Archive::of(source)
+        .and_then(|archive| archive.check_password(PROBE_PASSWORD))
+        .ok()
+        .map(|accepted| !accepted);
+
+    Ok(Identification {
+        archive_type: Some(archive_type),
+        compression: Some(compression)
+            .filter(|c| *c != ArchiveCompression::None)
+            .into_iter()
+            .collect(),
+        encrypted,
+        confidence: Some(confidence),
+    })
+}
+
+/// Whether the signature of `archive_type` is short or shared with other kinds of files, so that
+/// its content may only happen to match it.
+fn has_weak_signature(archive_type: &ArchiveType) -> bool {
+    match archive_type {
+        #[cfg(feature = "lha_archive")]
+        ArchiveType::Lha => true,
+        #[cfg(feature = "arj_archive")]
+        ArchiveType::Arj => true,
+        #[cfg(feature = "disk_image")]
+        ArchiveType::DiskImage => true,
+        #[cfg(feature = "compressed_file")]
+        ArchiveType::CompressedFile => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[cfg(all(feature = "tar_archive", feature = "zip_archive"))]
+    #[test]
+    fn identifies_fixtures() {
+        let id = identify("tests/fixtures/test1.tar.gz").unwrap();
+        assert_eq!(id.archive_type, Some(ArchiveType::Tar));
+        assert_eq!(id.compression, [ArchiveCompression::Gzip]);
+        assert_eq!(id.encrypted, Some(false));
+        assert_eq!(id.confidence, Some(Confidence::High));
+
+        let id = identify("tests/fixtures/encrypted.zip").unwrap();
+        assert_eq!(id.archive_type, Some(ArchiveType::Zip));
+        assert!(id.compression.is_empty());
+        assert_eq!(id.encrypted, Some(true));
+    }
+
+    #[cfg(all(feature = "zip_archive", feature = "sevenz_archive"))]
+    #[test]
+    fn falls_back_to_the_name() {
+        let dir = std::env::temp_dir().join(format!("hezi-identify-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // a zip archive named as a tarball
+        let misnamed = dir.join("archive.tar");
+        std::fs::copy("tests/fixtures/test1.zip", &misnamed).unwrap();
+        let id = identify(&misnamed).unwrap();
+        assert_eq!(id.archive_type, Some(ArchiveType::Zip));
+        assert_eq!(id.confidence, Some(Confidence::Medium));
+
+        // content nothing recognizes
+        let unknown = dir.join("notes.7z");
+        std::fs::write(&unknown, vec![b'x'; 64 * 1024]).unwrap();
+        let id = identify(&unknown).unwrap();
+        assert_eq!(id.archive_type, Some(ArchiveType::SevenZ));
+        assert_eq!(id.confidence, Some(Confidence::Low));
+        assert_eq!(id.encrypted, None);
+
+        let unnamed = dir.join("notes");
+        std::fs::rename(&unknown, &unnamed).unwrap();
+        let id = identify(&unnamed).unwrap();
+        assert_eq!(id.archive_type, None);
+        assert_eq!(id.confidence, None);
+        _ = std::fs::remove_dir_all(&dir);
+    }
+}
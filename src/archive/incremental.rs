@@ -0,0 +1,264 @@
+// incremental snapshots in the spirit of GNU tar's `--listed-incremental`: a snapshot file records
+// the state of the archived files, the next run only archives the files that changed since and
+// stores the names of the removed ones in the archive, so that extracting the archives in sequence
+// restores the latest state
+
+use std::{
+    collections::BTreeMap,
+    io::ErrorKind,
+    path::{Component, Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{entry_name, ArchiveError, CreateOptions, SourceRoot};
+
+/// Entry listing, one per line, the files removed since the previous snapshot.
+pub const REMOVED_ENTRY: &str = ".hezi-removed";
+
+/// State of the files archived by a snapshot, keyed by the name they are stored under.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotState {
+    pub files: BTreeMap<String, FileState>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FileState {
+    pub size: u64,
+    /// Modification time, in nanoseconds since the Unix epoch.
+    pub modified: u64,
+}
+
+/// Files to archive for a snapshot, see [`SnapshotState::increment`].
+#[derive(Debug)]
+pub struct Increment {
+    /// State to record once the archive is created.
+    pub state: SnapshotState,
+    /// Files added or modified since the previous snapshot.
+    pub changed: Vec<String>,
+    /// Files removed since the previous snapshot.
+    pub removed: Vec<String>,
+    /// Temporary directory holding the [`REMOVED_ENTRY`] file.
+    marker_dir: Option<PathBuf>,
+}
+
+impl Drop for Increment {
+    fn drop(&mut self) {
+        if let Some(dir) = &self.marker_dir {
+            _ = std::fs::remove_dir_all(dir);
+        }
+    }
+}
+
+impl SnapshotState {
+    /// Reads the snapshot file at `path`, an absent file being the empty state of a first, full
+    /// snapshot.
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, ArchiveError> {
+        match std::fs::File::open(path) {
+            Ok(file) => {
+                serde_json::from_reader(std::io::BufReader::new(file)).map_err(ArchiveError::Json)
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), ArchiveError> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), self)
+            .map_err(ArchiveError::Json)
+    }
+
+    /// Restricts the files of `options` to the ones added or modified since this state was
+    /// recorded, directories being kept to restore the tree. If files were removed since, an
+    /// [`REMOVED_ENTRY`] listing them is added to `options`, it must be created before the
+    /// returned [`Increment`] is dropped.
+    pub fn increment(&self, options: &mut CreateOptions) -> Result<Increment, ArchiveError> {
+        let mut state = SnapshotState::default();
+        let mut changed = Vec::new();
+        let mut files = Vec::with_capacity(options.files.len());
+        for file in std::mem::take(&mut options.files) {
+            let metadata = std::fs::metadata(&file)?;
+            if metadata.is_dir() {
+                files.push(file);
+                continue;
+            }
+
+            let modified = metadata
+                .modified()?
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos() as u64);
+            let current = FileState {
+                size: metadata.len(),
+                modified,
+            };
+            let name = entry_name(&options.source, &options.roots, &file)
+                .to_string_lossy()
+                .replace('\\', "/");
+            if self.files.get(&name) != Some(&current) {
+                changed.push(name.clone());
+                files.push(file);
+            }
+            state.files.insert(name, current);
+        }
+        options.files = files;
+        changed.sort();
+
+        let removed = self
+            .files
+            .keys()
+            .filter(|name| !state.files.contains_key(*name))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let marker_dir = if removed.is_empty() {
+            None
+        } else {
+            let dir = std::env::temp_dir().join(format!(
+                "hezi-incremental-{}-{}",
+                std::process::id(),
+                std::time::SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |d| d.as_nanos())
+            ));
+            std::fs::create_dir_all(&dir)?;
+            let marker = dir.join(REMOVED_ENTRY);
+            std::fs::write(&marker, removed.join("\n"))?;
+
+            options.roots.push(SourceRoot {
+                root: dir.clone(),
+                prefix: PathBuf::new(),
+            });
+            options.files.push(marker);
+            Some(dir)
+        };
+
+        Ok(Increment {
+            state,
+            changed,
+            removed,
+            marker_dir,
+        })
+    }
+}
+
+/// Deletes from `destination` the files listed by the [`REMOVED_ENTRY`] an incremental archive
+/// was extracted with, then the list itself, and returns the deleted paths. Does nothing if there
+/// is no such list.
+pub fn apply_removals<P: AsRef<Path>>(destination: P) -> Result<Vec<PathBuf>, ArchiveError> {
+    let destination = destination.as_ref();
+    let marker = destination.join(REMOVED_ENTRY);
+    let list = match std::fs::read_to_string(&marker) {
+        Ok(list) => list,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut deleted = Vec::new();
+    for name in list.lines().filter(|l| !l.is_empty()) {
+        let relative = Path::new(name);
+        // never follow names out of the destination
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            log::warn!("ignoring removed file outside of the destination: {}", name);
+            continue;
+        }
+
+        let path = destination.join(relative);
+        match std::fs::remove_file(&path) {
+            Ok(()) => deleted.push(path),
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    std::fs::remove_file(marker)?;
+    Ok(deleted)
+}
+
+#[cfg(all(test, feature = "tar_archive"))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::archive::{
+        Archive, ArchiveCompression, ArchiveType, Archived, CompressionSpec, DataSource,
+        ExtractOptions, SimpleLogger,
+    };
+
+    fn create(source: &Path, destination: &Path, previous: &SnapshotState) -> Increment {
+        let mut options = CreateOptions {
+            destination: destination.to_path_buf(),
+            source: source.to_path_buf(),
+            roots: vec![],
+            files: walkdir::WalkDir::new(source)
+                .into_iter()
+                .map(|e| e.unwrap().into_path())
+                .collect(),
+            password: None,
+            archive_type: ArchiveType::Tar,
+            compression: Some(CompressionSpec::new(ArchiveCompression::None)),
+            overwrite: true,
+            include_hidden: true,
+            skip_unreadable: false,
+            verify_after: false,
+            volume_size: None,
+            update: false,
+            sevenz: Default::default(),
+            zip: Default::default(),
+            tar: Default::default(),
+            event_handler: Box::new(SimpleLogger),
+        };
+        let increment = previous.increment(&mut options).unwrap();
+        Archive::create(options).unwrap();
+        increment
+    }
+
+    fn extract(archive: &Path, destination: &Path) {
+        Archive::of(DataSource::file(archive).unwrap())
+            .unwrap()
+            .extract(ExtractOptions {
+                destination: destination.to_path_buf(),
+                overwrite: true,
+                ..Default::default()
+            })
+            .unwrap();
+        apply_removals(destination).unwrap();
+    }
+
+    #[test]
+    fn restores_in_sequence() {
+        let dir =
+            std::env::temp_dir().join(format!("hezi-incremental-test-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        let (source, restored) = (dir.join("source"), dir.join("restored"));
+        std::fs::create_dir_all(source.join("sub")).unwrap();
+        std::fs::write(source.join("kept.txt"), "kept").unwrap();
+        std::fs::write(source.join("sub/removed.txt"), "removed").unwrap();
+
+        let full = create(&source, &dir.join("0.tar"), &SnapshotState::default());
+        assert_eq!(full.changed, vec!["kept.txt", "sub/removed.txt"]);
+        let state = full.state.clone();
+        drop(full);
+
+        std::fs::remove_file(source.join("sub/removed.txt")).unwrap();
+        std::fs::write(source.join("added.txt"), "added").unwrap();
+        let increment = create(&source, &dir.join("1.tar"), &state);
+        assert_eq!(increment.changed, vec!["added.txt"]);
+        assert_eq!(increment.removed, vec!["sub/removed.txt"]);
+        drop(increment);
+
+        extract(&dir.join("0.tar"), &restored);
+        assert!(restored.join("sub/removed.txt").exists());
+        extract(&dir.join("1.tar"), &restored);
+        assert!(!restored.join("sub/removed.txt").exists());
+        assert!(!restored.join(REMOVED_ENTRY).exists());
+        assert_eq!(
+            std::fs::read_to_string(restored.join("added.txt")).unwrap(),
+            "added"
+        );
+        assert!(restored.join("kept.txt").exists());
+        _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,313 @@
+// integrity test of an archive: every file is decompressed and checked against the checksums the
+// format keeps, the way `unzip -t` does, and the stream of archives compressed as a whole is read
+// to its end for the checksum of the compression. Large zip archives can instead be tested
+// quickly, their central directory checked against the local headers and only a share of the
+// files decompressed.
+
+use std::{cell::RefCell, collections::HashSet, ops::ControlFlow};
+
+use serde::Serialize;
+
+use super::{
+    entry_visitor::{EntryVisitor, VisitOptions},
+    password::PasswordProvider,
+    Archive, ArchiveError, ArchiveEvent, ArchiveFileEntity, ArchiveFileEntityType, EventHandler,
+};
+
+#[derive(Debug)]
+pub struct VerifyOptions<'a> {
+    pub password: Option<Box<dyn PasswordProvider + 'a>>,
+    /// Only check the central directory and a share of the files, for zip archives.
+    pub quick: bool,
+    /// Percentage of the files decompressed by a quick test, at least one of them is.
+    pub percent: u8,
+    /// Seed of the random picks of a quick test, to check the same files again.
+    pub seed: Option<u64>,
+}
+
+impl Default for VerifyOptions<'_> {
+    fn default() -> Self {
+        Self {
+            password: None,
+            quick: false,
+            percent: 10,
+            seed: None,
+        }
+    }
+}
+
+/// Outcome of the check of a file of the archive.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EntryCheck {
+    pub name: String,
+    /// Why the file could not be decompressed, or its checksum did not match, `None` if it
+    /// passed.
+    pub error: Option<String>,
+}
+
+impl EntryCheck {
+    pub fn passed(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Outcome of [`Archive::verify`].
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct VerifyReport {
+    /// Number of files of the archive.
+    pub files: usize,
+    /// Number of files decompressed and checked.
+    pub checked: usize,
+    /// Inconsistencies of the structure of the archive, found by quick tests, and errors of the
+    /// stream it is read from, such as a checksum of its compression that does not match.
+    pub problems: Vec<String>,
+    /// Files checked, in the order of the archive, along with those that could not be read.
+    pub entries: Vec<EntryCheck>,
+}
+
+impl VerifyReport {
+    pub fn passed(&self) -> bool {
+        self.problems.is_empty() && self.entries.iter().all(EntryCheck::passed)
+    }
+
+    /// Files that could not be decompressed, or whose checksum did not match.
+    pub fn failed(&self) -> impl Iterator<Item = &EntryCheck> {
+        self.entries.iter().filter(|entry| !entry.passed())
+    }
+}
+
+impl std::fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for problem in &self.problems {
+            writeln!(f, "inconsistent: {}", problem)?;
+        }
+        for entry in self.failed() {
+            let error = entry.error.as_deref().unwrap_or_default();
+            writeln!(f, "failed:       {} ({})", entry.name, error)?;
+        }
+        write!(
+            f,
+            "{} of {} files checked, {} failed, {} inconsistencies",
+            self.checked,
+            self.files,
+            self.failed().count(),
+            self.problems.len()
+        )
+    }
+}
+
+impl Archive {
+    /// Decompresses the files of the archive, all of them or, for quick tests, a share of them
+    /// after checking the central directory, reporting whether each one passed. The readers of
+    /// the formats check the CRC32 of zip entries and the checksums of 7z ones, and the stream
+    /// of archives compressed as a whole, such as `.tar.gz`, is read to its end so that the
+    /// checksum of the compression is checked too.
+    pub fn verify(&self, options: VerifyOptions) -> Result<VerifyReport, ArchiveError> {
+        let password = options.password.as_deref();
+        let mut report = VerifyReport::default();
+        let picked = match options.quick {
+            true => {
+                let (problems, files, picked) = pick_quick(self, password, &options)?;
+                report.problems = problems;
+                report.files = files;
+                Some(picked)
+            }
+            false => None,
+        };
+
+        let is_picked = |entity: &ArchiveFileEntity| {
+            is_file(entity) && picked.as_ref().is_none_or(|p| p.contains(&entity.name))
+        };
+        let unreadable = UnreadableEntries::default();
+        let reading = VisitOptions {
+            password,
+            filter: Some(&is_picked),
+            contents: true,
+            events: Some(&unreadable),
+            drain: true,
+            ..Default::default()
+        };
+        let visited = self.for_each_entry(&reading, |entry, reader| {
+            let Some(reader) = reader else {
+                return Ok(ControlFlow::Continue(()));
+            };
+            report.checked += 1;
+            // the readers check the checksums once the whole content is read
+            let error = std::io::copy(reader, &mut std::io::sink()).err();
+            report.entries.push(EntryCheck {
+                name: entry.entity.name,
+                error: error.map(|e| e.to_string()),
+            });
+            Ok(ControlFlow::Continue(()))
+        });
+        // a stream failing past a file, such as one cut short, still reports the files before it
+        match visited {
+            Ok(()) => {}
+            Err(e @ (ArchiveError::PasswordRequired | ArchiveError::WrongPassword)) => {
+                return Err(e)
+            }
+            Err(e) => report.problems.push(format!("stream: {}", e)),
+        }
+
+        let unreadable = unreadable.0.into_inner();
+        if !options.quick {
+            report.files = report.checked + unreadable.len();
+        }
+        report
+            .entries
+            .extend(unreadable.into_iter().map(|(name, error)| EntryCheck {
+                name,
+                error: Some(error),
+            }));
+        Ok(report)
+    }
+}
+
+/// Checks the central directory of a zip archive, returning the inconsistencies found along with
+/// the number of files and the names of those to decompress.
+fn pick_quick(
+    archive: &Archive,
+    password: Option<&dyn PasswordProvider>,
+    options: &VerifyOptions,
+) -> Result<(Vec<String>, usize, HashSet<String>), ArchiveError> {
+    let problems = match archive {
+        #[cfg(feature = "zip_archive")]
+        Archive::Zip(zip) => zip.check_directory()?,
+        _ => {
+            return Err(ArchiveError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "quick tests are only supported for zip archives",
+            )))
+        }
+    };
+
+    let mut names = Vec::new();
+    let listing = VisitOptions {
+        password,
+        filter: Some(&is_file),
+        ..Default::default()
+    };
+    archive.for_each_entry(&listing, |entry, _| {
+        names.push(entry.entity.name);
+        Ok(ControlFlow::Continue(()))
+    })?;
+
+    let files = names.len();
+    let mut rng = options
+        .seed
+        .map_or_else(fastrand::Rng::new, fastrand::Rng::with_seed);
+    rng.shuffle(&mut names);
+    names.truncate(
+        (files * options.percent.min(100) as usize)
+            .div_ceil(100)
+            .max(1),
+    );
+    Ok((problems, files, names.into_iter().collect()))
+}
+
+fn is_file(entity: &ArchiveFileEntity) -> bool {
+    entity.fstype == ArchiveFileEntityType::File
+}
+
+/// Gathers the entries a backend could not read and skipped.
+#[derive(Default)]
+struct UnreadableEntries(RefCell<Vec<(String, String)>>);
+
+impl EventHandler for UnreadableEntries {
+    fn handle(&self, event: ArchiveEvent) {
+        if let ArchiveEvent::FailedToReadEntry(name, e) = event {
+            self.0.borrow_mut().push((name, e.to_string()));
+        }
+    }
+}
+
+#[cfg(all(test, feature = "zip_archive", feature = "deflate_codecs"))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::archive::DataSource;
+
+    fn test(data: &[u8], options: VerifyOptions) -> VerifyReport {
+        let archive = Archive::of(DataSource::memory(data)).unwrap();
+        archive.verify(options).unwrap()
+    }
+
+    #[test]
+    fn passes_sound_archives() {
+        let data = std::fs::read("tests/fixtures/test1.zip").unwrap();
+        let report = test(&data, VerifyOptions::default());
+        assert!(report.passed(), "{}", report);
+        assert_eq!((report.checked, report.files), (2, 2));
+        assert!(report.entries.iter().all(EntryCheck::passed));
+
+        let quick = VerifyOptions {
+            quick: true,
+            percent: 50,
+            seed: Some(1),
+            ..Default::default()
+        };
+        let report = test(&data, quick);
+        assert!(report.passed(), "{}", report);
+        assert_eq!((report.checked, report.files), (1, 2));
+    }
+
+    #[test]
+    fn reports_corrupted_entries() {
+        let mut data = std::fs::read("tests/fixtures/test1.zip").unwrap();
+        // flips a byte of the compressed content of a file
+        let name = b"test1/dir1/file2.txt";
+        let at = data.windows(name.len()).position(|w| w == name).unwrap();
+        let extra = u16::from_le_bytes([data[at - 2], data[at - 1]]) as usize;
+        data[at + name.len() + extra + 100] ^= 0xff;
+
+        let report = test(&data, VerifyOptions::default());
+        let failed = report.failed().map(|e| e.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(failed, ["test1/dir1/file2.txt"], "{}", report);
+        assert_eq!(report.entries.len(), 2);
+
+        // a local header naming another file than the central directory
+        let mut data = std::fs::read("tests/fixtures/test1.zip").unwrap();
+        let local = data
+            .windows(4)
+            .rposition(|w| w == [0x50, 0x4b, 0x03, 0x04])
+            .unwrap();
+        data[local + 30] ^= 0x20;
+        let quick = || VerifyOptions {
+            quick: true,
+            percent: 100,
+            ..Default::default()
+        };
+        let report = test(&data, quick());
+        assert_eq!(report.problems.len(), 1, "{}", report);
+        assert_eq!(report.checked, 2);
+        assert!(!report.passed());
+
+        // quick tests need a central directory
+        #[cfg(feature = "tar_archive")]
+        {
+            let tar = std::fs::read("tests/fixtures/test1.tar").unwrap();
+            let archive = Archive::of(DataSource::memory(tar.as_slice())).unwrap();
+            assert!(archive.verify(quick()).is_err());
+        }
+    }
+
+    #[cfg(feature = "tar_archive")]
+    #[test]
+    fn reports_corrupted_compression_streams() {
+        let sound = std::fs::read("tests/fixtures/test1.tar.gz").unwrap();
+        assert!(test(&sound, VerifyOptions::default()).passed());
+
+        // the crc32 of the gzip trailer no longer matches, though every file reads fine
+        let mut data = sound.clone();
+        let at = data.len() - 6;
+        data[at] ^= 0xff;
+        let report = test(&data, VerifyOptions::default());
+        assert!(report.entries.iter().all(EntryCheck::passed), "{}", report);
+        assert_eq!(report.problems.len(), 1, "{}", report);
+        assert!(!report.passed());
+
+        // a stream cut short reports the files read before it ends
+        let report = test(&sound[..sound.len() / 2], VerifyOptions::default());
+        assert!(!report.passed(), "{}", report);
+    }
+}
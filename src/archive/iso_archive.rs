@@ -1,174 +1,327 @@
 use std::{
-    fs::File,
-    ops::Deref,
+    cell::OnceCell,
+    io::Read,
+    ops::{ControlFlow, Deref},
     path::{Path, PathBuf},
 };
 
-use cdfs::{DirectoryEntry, ExtraAttributes, ISO9660};
+use cdfs::{DirectoryEntry, ExtraAttributes, ISO9660Reader, ISODirectory, ISO9660};
 use serde_json::json;
 
 use super::{
-    datetime_from_timestamp, ArchiveError, ArchiveFileEntity, ArchiveFileEntityType,
-    ArchiveMetadata, Archived, DataSource, ExtractOptions, ListOptions,
+    datetime_from_timestamp,
+    edit::EntryEdit,
+    entries::{visit_options, ArchiveEntries, ArchiveEntry},
+    entry_visitor::{
+        extract_entries, find_first_entry, list_entries, normalized_name, open_entry, scan_entries,
+        EntryLink, EntryVisitor, VisitOptions, VisitedEntry,
+    },
+    ArchiveError, ArchiveFileEntity, ArchiveFileEntityType, ArchiveMetadata, ArchiveScan, Archived,
+    DataSource, EventHandler, ListOptions, UnixAttributes,
 };
 
-pub struct ISOArchive<'a> {
-    pub(crate) source: DataSource<'a>,
+pub struct ISOArchive {
+    pub(crate) source: DataSource,
+    scan: OnceCell<ArchiveScan>,
 }
 
-fn join_path_with_root<P: AsRef<Path>, S: Into<String>>(source: P, fs_path: S) -> PathBuf {
-    source
-        .as_ref()
-        .components()
-        .chain(PathBuf::from(fs_path.into()).components().skip(1))
-        .collect::<PathBuf>()
-}
+impl ISOArchive {
+    fn scan(&self, options: &ListOptions) -> Result<&ArchiveScan, ArchiveError> {
+        ArchiveScan::cached(&self.scan, || scan_entries(self, None, Some(options)))
+    }
 
-impl ISOArchive<'_> {
-    fn extract_dir(
-        iso: &ISO9660<DataSource<'_>>,
-        dest: &PathBuf,
-        path: &str,
-        _options: &ExtractOptions,
-    ) -> Result<(), ArchiveError> {
-        if let Some(DirectoryEntry::Directory(dir)) = iso.open(path)? {
-            std::fs::create_dir_all(join_path_with_root(dest, path))?;
-
-            for entry in dir.contents() {
-                match entry? {
-                    DirectoryEntry::File(file) => {
-                        let path = join_path_with_root(dest, &file.identifier);
-                        let mut copy_file = File::create(path)?;
-                        let mut reader = file.read();
-                        std::io::copy(&mut reader, &mut copy_file)?;
-                    }
-                    DirectoryEntry::Directory(dir) => {
-                        let path = &dir.identifier;
-                        let dest = join_path_with_root(dest, path);
-                        Self::extract_dir(iso, &dest, path, _options)?;
-                    }
-                    DirectoryEntry::Symlink(link) => {
-                        let path = &link.identifier;
-                        let dest = join_path_with_root(dest, path);
-                        if let Some(target) = link.target() {
-                            let target = join_path_with_root(&dest, target);
-                            #[cfg(unix)]
-                            std::os::unix::fs::symlink(target, dest)?;
-                            #[cfg(windows)]
-                            std::os::windows::fs::symlink_file(target, dest)?;
-                        }
+    /// The entry `entry` of the directory `cwd`, or `None` for the entries of a directory
+    /// standing for itself and its parent, and for the directories Rock Ridge relocated, which
+    /// are visited where they belong instead.
+    fn visited_entry<R: ISO9660Reader>(
+        cwd: &Path,
+        entry: &DirectoryEntry<R>,
+    ) -> Option<VisitedEntry> {
+        let (fstype, size, link) = match entry {
+            _ if is_child_link(entry) => (ArchiveFileEntityType::Directory, None, None),
+            DirectoryEntry::File(file) => {
+                (ArchiveFileEntityType::File, Some(file.size() as u64), None)
+            }
+            DirectoryEntry::Directory(dir) if dir.identifier == "." || dir.identifier == ".." => {
+                return None
+            }
+            DirectoryEntry::Directory(dir) if dir.relocated() => return None,
+            DirectoryEntry::Directory(_) => (ArchiveFileEntityType::Directory, None, None),
+            DirectoryEntry::Symlink(link) => (
+                ArchiveFileEntityType::SymbolicLink,
+                None,
+                link.target().map(|t| EntryLink::Symbolic(t.to_string())),
+            ),
+        };
+        // the PX entry of Rock Ridge, with the permissions and owner shown once mounted
+        let unix = entry.mode().map(|mode| UnixAttributes {
+            mode: mode.bits() & 0o7777,
+            uid: entry.owner().unwrap_or(0),
+            gid: entry.group().unwrap_or(0),
+        });
+        let entity = ArchiveFileEntity {
+            name: cwd.join(entry.identifier()).to_string_lossy().to_string(),
+            size,
+            compressed_size: size,
+            last_modified: datetime_from_timestamp(entry.modify_time().unix_timestamp()).ok(),
+            compression: None,
+            fstype,
+            raw: None,
+            unix,
+        };
+        Some(VisitedEntry {
+            mode: unix.map(|u| u.mode),
+            link,
+            ..VisitedEntry::new(entity)
+        })
+    }
+
+    fn visit_dir<R, F>(
+        dir: &ISODirectory<R>,
+        cwd: &Path,
+        relocated: &[ISODirectory<R>],
+        options: &VisitOptions<'_>,
+        visit: &mut F,
+    ) -> Result<ControlFlow<()>, ArchiveError>
+    where
+        R: ISO9660Reader,
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        for entry in dir.contents() {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    report_unreadable(cwd, e, options.events);
+                    continue;
+                }
+            };
+            let Some(visited) = Self::visited_entry(cwd, &entry) else {
+                continue;
+            };
+            let wanted = options.wants(&visited.entity);
+            let child_link = is_child_link(&entry);
+            let subdir = match &entry {
+                DirectoryEntry::Directory(dir) => Some(dir),
+                _ if child_link => find_relocated(relocated, &entry),
+                _ => None,
+            };
+            let flow = match (subdir, &entry) {
+                (Some(subdir), _) => {
+                    let path = PathBuf::from(&visited.entity.name);
+                    if wanted && visit(visited, None)?.is_break() {
+                        return Ok(ControlFlow::Break(()));
                     }
+
+                    Self::visit_dir(subdir, &path, relocated, options, visit)?
                 }
+                _ if !wanted => continue,
+                (None, DirectoryEntry::File(file)) if options.contents && !child_link => {
+                    visit(visited, Some(&mut file.read()))?
+                }
+                (None, _) => visit(visited, None)?,
+            };
+
+            if flow.is_break() {
+                return Ok(flow);
             }
         }
-        Ok(())
+        Ok(ControlFlow::Continue(()))
     }
 
-    fn list_dir(
-        iso: &ISO9660<DataSource<'_>>,
-        cwd: &str,
-        files: &mut Vec<ArchiveFileEntity>,
-        options: &ListOptions,
-    ) -> Result<(), ArchiveError> {
-        let cwd_path = PathBuf::from(cwd);
-        if let Some(DirectoryEntry::Directory(dir)) = iso.open(cwd)? {
-            for entry in dir.contents() {
-                match entry {
-                    Ok(DirectoryEntry::File(file)) => {
-                        let path = &file.identifier;
-                        let size = file.size();
-                        let entity = ArchiveFileEntity {
-                            name: cwd_path.join(path).to_string_lossy().to_string(),
-                            size: Some(size as u64),
-                            compressed_size: Some(size as u64),
-                            last_modified: datetime_from_timestamp(
-                                file.modify_time().unix_timestamp(),
-                            )
-                            .ok(),
-                            compression: None,
-                            fstype: ArchiveFileEntityType::File,
-                        };
-                        files.push(entity);
-                    }
-                    Ok(DirectoryEntry::Directory(dir)) => {
-                        if dir.identifier != "." && dir.identifier != ".." {
-                            let path = cwd_path.join(&dir.identifier);
-
-                            let entity = ArchiveFileEntity {
-                                name: path.to_string_lossy().to_string(),
-                                size: None,
-                                compressed_size: None,
-                                last_modified: datetime_from_timestamp(
-                                    dir.modify_time().unix_timestamp(),
-                                )
-                                .ok(),
-                                compression: None,
-                                fstype: ArchiveFileEntityType::Directory,
-                            };
-                            files.push(entity);
-
-                            Self::list_dir(iso, path.to_string_lossy().deref(), files, options)?;
-                        }
-                    }
-                    Ok(DirectoryEntry::Symlink(link)) => {
-                        let path = &link.identifier;
-
-                        let entity = ArchiveFileEntity {
-                            name: path.to_string(),
-                            size: None,
-                            compressed_size: None,
-                            last_modified: datetime_from_timestamp(
-                                link.modify_time().unix_timestamp(),
-                            )
-                            .ok(),
-                            compression: None,
-                            fstype: ArchiveFileEntityType::SymbolicLink,
-                        };
-                        files.push(entity);
-                    }
-                    Err(e) => {
-                        options
-                            .event_handler
-                            .handle(super::ArchiveEvent::FailedToReadEntry(
-                                cwd_path
-                                    .join(PathBuf::from("???"))
-                                    .to_string_lossy()
-                                    .to_string(),
-                                ArchiveError::Iso(e),
-                            ));
-                    }
+    /// The directory the entries under `prefix` are in, looked up by path so that only it needs
+    /// to be walked.
+    fn start_dir(root: &str, prefix: Option<&str>) -> PathBuf {
+        let root = PathBuf::from(root);
+        match prefix.map(|p| normalized_name(p).trim_end_matches('/')) {
+            Some(prefix) if !prefix.is_empty() => root.join(prefix),
+            _ => root,
+        }
+    }
+
+    pub(crate) fn entries<'s>(&'s self, options: ListOptions<'s>) -> ArchiveEntries<'s> {
+        let open = || -> Result<IsoEntries<'s>, ArchiveError> {
+            let iso = ISO9660::new(self.source.clone())?;
+            let start = Self::start_dir(&iso.root().identifier, options.prefix.as_deref());
+            let directories = match iso.open(start.to_string_lossy().deref())? {
+                Some(DirectoryEntry::Directory(dir)) => {
+                    vec![(start, dir.contents().collect::<Vec<_>>().into_iter())]
+                }
+                _ => vec![],
+            };
+            Ok(IsoEntries {
+                directories,
+                relocated: relocated_dirs(&iso),
+                options,
+            })
+        };
+        match open() {
+            Ok(entries) => ArchiveEntries::new(entries),
+            Err(e) => ArchiveEntries::failed(e),
+        }
+    }
+}
+
+/// Whether `entry` stands for a directory Rock Ridge moved elsewhere, such as to `rr_moved`,
+/// because it was nested deeper than ISO 9660 allows: a file with the mode of a directory, the
+/// child link to the directory being left out by the reader.
+fn is_child_link<R: ISO9660Reader>(entry: &DirectoryEntry<R>) -> bool {
+    const S_IFMT: u32 = 0o170000;
+    const S_IFDIR: u32 = 0o040000;
+    matches!(entry, DirectoryEntry::File(_))
+        && entry
+            .mode()
+            .is_some_and(|mode| mode.bits() & S_IFMT == S_IFDIR)
+}
+
+/// Directories Rock Ridge relocated, which are kept in a directory at the root of the image.
+fn relocated_dirs<R: ISO9660Reader>(iso: &ISO9660<R>) -> Vec<ISODirectory<R>> {
+    if !iso.is_rr() {
+        return Vec::new();
+    }
+    let subdirs = |dir: &ISODirectory<R>| {
+        dir.contents()
+            .filter_map(Result::ok)
+            .filter_map(|entry| match entry {
+                DirectoryEntry::Directory(dir)
+                    if dir.identifier != "." && dir.identifier != ".." =>
+                {
+                    Some(dir)
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    subdirs(iso.root())
+        .iter()
+        .flat_map(subdirs)
+        .filter(|dir| dir.relocated())
+        .collect()
+}
+
+/// The relocated directory the child link `entry` stands for, found by its name.
+fn find_relocated<'d, R: ISO9660Reader>(
+    relocated: &'d [ISODirectory<R>],
+    entry: &DirectoryEntry<R>,
+) -> Option<&'d ISODirectory<R>> {
+    relocated
+        .iter()
+        .find(|dir| dir.identifier == entry.identifier())
+}
+
+/// Reports an entry of the directory `cwd` that could not be read.
+fn report_unreadable(cwd: &Path, e: cdfs::ISOError, events: Option<&dyn EventHandler>) {
+    if let Some(events) = events {
+        events.handle(super::ArchiveEvent::FailedToReadEntry(
+            cwd.join(PathBuf::from("???")).to_string_lossy().to_string(),
+            ArchiveError::Iso(e),
+        ));
+    }
+}
+
+type DirectoryContents = std::vec::IntoIter<Result<DirectoryEntry<DataSource>, cdfs::ISOError>>;
+
+/// Iterator of [`ISOArchive::entries`], walking the directories depth first like
+/// [`ISOArchive::visit_dir`]. The entries of a directory are read once it is reached, the
+/// contents of the files as they are read.
+struct IsoEntries<'a> {
+    /// The directories being walked, with their entries left to yield.
+    directories: Vec<(PathBuf, DirectoryContents)>,
+    relocated: Vec<ISODirectory<DataSource>>,
+    options: ListOptions<'a>,
+}
+
+impl<'a> Iterator for IsoEntries<'a> {
+    type Item = Result<ArchiveEntry<'a>, ArchiveError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let options = visit_options(&self.options);
+        while let Some((cwd, contents)) = self.directories.last_mut() {
+            let Some(entry) = contents.next() else {
+                self.directories.pop();
+                continue;
+            };
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    report_unreadable(cwd, e, options.events);
+                    continue;
+                }
+            };
+            let Some(visited) = ISOArchive::visited_entry(cwd, &entry) else {
+                continue;
+            };
+            let wanted = options.wants(&visited.entity);
+            let child_link = is_child_link(&entry);
+            let subdir = match &entry {
+                DirectoryEntry::Directory(dir) => Some(dir),
+                _ if child_link => find_relocated(&self.relocated, &entry),
+                _ => None,
+            };
+            let entry = match (subdir, &entry) {
+                (Some(dir), _) => {
+                    let path = PathBuf::from(&visited.entity.name);
+                    let contents = dir.contents().collect::<Vec<_>>().into_iter();
+                    self.directories.push((path, contents));
+                    ArchiveEntry::empty(visited)
                 }
+                (None, DirectoryEntry::File(file)) if !child_link => {
+                    ArchiveEntry::new(visited, Box::new(file.read()))
+                }
+                (None, _) => ArchiveEntry::empty(visited),
+            };
+            if wanted {
+                return Some(Ok(entry));
             }
         }
+        None
+    }
+}
 
+impl EntryVisitor for ISOArchive {
+    fn for_each_entry<F>(
+        &self,
+        options: &VisitOptions<'_>,
+        mut visit: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        let source = options
+            .retry
+            .wrap(self.source.clone(), &self.source, options.events);
+        let iso = ISO9660::new(source)?;
+        let start = Self::start_dir(&iso.root().identifier, options.prefix);
+        if let Some(DirectoryEntry::Directory(dir)) = iso.open(start.to_string_lossy().deref())? {
+            let relocated = relocated_dirs(&iso);
+            _ = Self::visit_dir(&dir, &start, &relocated, options, &mut visit)?;
+        }
         Ok(())
     }
 }
 
-impl<'a> Archived<'a> for ISOArchive<'a> {
-    fn of(source: DataSource<'a>) -> Result<Self, ArchiveError>
+impl Archived for ISOArchive {
+    fn of(source: DataSource) -> Result<Self, ArchiveError>
     where
         Self: Sized,
     {
-        Ok(Self { source })
+        Ok(Self {
+            source,
+            scan: OnceCell::new(),
+        })
     }
 
-    fn extract(&self, options: super::ExtractOptions) -> Result<(), ArchiveError> {
-        let dest = &options.destination;
-        let iso = ISO9660::new(self.source.clone())?;
-
-        Self::extract_dir(&iso, dest, "/", &options)?;
-
-        Ok(())
+    fn extract(
+        &self,
+        options: super::ExtractOptions,
+    ) -> Result<super::ExtractReport, ArchiveError> {
+        extract_entries(self, &options, self.source.as_ref())
     }
 
     fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
-        let iso = ISO9660::new(self.source.clone())?;
-
-        let mut acc = Vec::<ArchiveFileEntity>::new();
-        Self::list_dir(&iso, &iso.root().identifier, &mut acc, &options)?;
-
-        Ok(acc)
+        if options.is_whole() {
+            return Ok(self.scan(&options)?.entries.clone());
+        }
+        list_entries(self, self.scan.get(), &options)
     }
 
     fn create(_options: super::CreateOptions) -> Result<super::CreateResult, ArchiveError> {
@@ -181,24 +334,17 @@ impl<'a> Archived<'a> for ISOArchive<'a> {
     fn metadata(&self) -> Result<ArchiveMetadata, ArchiveError> {
         let iso = ISO9660::new(self.source.clone())?;
 
-        let mut acc = Vec::<ArchiveFileEntity>::new();
-
-        Self::list_dir(
-            &iso,
-            &iso.root().identifier,
-            &mut acc,
-            &ListOptions::default(),
-        )?;
-
-        let (size, compressed_size) = acc.iter().fold((0, 0), |(s, cs), f| {
-            (s + f.size.unwrap_or(0), cs + f.compressed_size.unwrap_or(0))
-        });
+        let scan = self.scan(&ListOptions::default())?;
 
         Ok(ArchiveMetadata {
-            entries: acc,
-            total_size: size,
-            compressed_size,
+            archive_type: super::ArchiveType::Iso,
+            entry_count: scan.entries.len(),
+            entries: scan.entries.clone(),
+            total_size: scan.total_size,
+            compressed_size: scan.compressed_size,
             compression: None,
+            created: self.source.created(),
+            modified: self.source.modified(),
             additional: Some(json!(
                 {
                     "is_rock_ridge": iso.is_rr(),
@@ -218,32 +364,75 @@ impl<'a> Archived<'a> for ISOArchive<'a> {
     }
 
     fn open(&self, options: super::OpenOptions) -> Result<(), ArchiveError> {
-        let iso = ISO9660::new(self.source.clone())?;
+        open_entry(self, options)
+    }
 
-        let path = options.path.to_string_lossy().to_string();
+    fn find_first<P>(
+        &self,
+        predicate: P,
+    ) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+    where
+        P: Fn(&ArchiveFileEntity) -> bool,
+    {
+        find_first_entry(self, predicate)
+    }
 
-        if let Some(DirectoryEntry::File(file)) = iso.open(&path)? {
-            let mut reader = file.read();
-            let mut writer = options.dest;
-            std::io::copy(&mut reader, &mut writer)?;
-            Ok(())
-        } else {
-            Err(ArchiveError::EntryNotFound(options.path))
-        }
+    fn check_password(&self, _password: &str) -> Result<bool, ArchiveError> {
+        // iso images are never encrypted
+        Ok(true)
+    }
+
+    fn edit(&self, _entry: &str, _edit: &EntryEdit) -> Result<(), ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "edit".to_string(),
+            super::ArchiveType::Iso,
+        ))
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::unwrap_used)]
 mod tests {
-    use std::path::PathBuf;
+    use super::*;
+    use crate::archive::Archive;
 
     #[test]
-    fn test_join_path_with_root() {
-        let source = PathBuf::from("./Desktop");
-        let fs_path = "/test";
+    fn lists_rock_ridge_names_modes_and_owners() {
+        use ArchiveFileEntityType::{Directory, File};
+
+        // a/b/c/d/e/f/g/h is deeper than ISO 9660 allows, h being kept in rr_moved
+        let archive =
+            Archive::of(DataSource::file("tests/fixtures/rock_ridge.iso").unwrap()).unwrap();
+        let entries = archive.list(ListOptions::default()).unwrap();
+        let listed = entries
+            .iter()
+            .map(|e| {
+                let unix = e.unix.unwrap();
+                (e.name.as_str(), e.fstype, unix.mode, unix.uid, unix.gid)
+            })
+            .collect::<Vec<_>>();
         assert_eq!(
-            super::join_path_with_root(source, fs_path),
-            PathBuf::from("./Desktop/test")
+            listed,
+            vec![
+                ("./a", Directory, 0o755, 1000, 100),
+                ("./a/b", Directory, 0o755, 1000, 100),
+                ("./a/b/c", Directory, 0o755, 1000, 100),
+                ("./a/b/c/d", Directory, 0o755, 1000, 100),
+                ("./a/b/c/d/e", Directory, 0o755, 1000, 100),
+                ("./a/b/c/d/e/f", Directory, 0o755, 1000, 100),
+                ("./a/b/c/d/e/f/g", Directory, 0o755, 1000, 100),
+                ("./a/b/c/d/e/f/g/h", Directory, 0o700, 1000, 100),
+                ("./a/b/c/d/e/f/g/h/deep.txt", File, 0o600, 1001, 1001),
+                ("./hello.txt", File, 0o644, 1000, 100),
+                ("./rr_moved", Directory, 0o555, 0, 0),
+                ("./run.sh", File, 0o755, 0, 0),
+            ]
         );
+
+        let (_, content) = archive
+            .find_first(|e| e.name.ends_with("h/deep.txt"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(content, b"deep\n");
     }
 }
@@ -0,0 +1,41 @@
+// stores archive passwords in the OS keyring (Keychain, Credential Manager, Secret Service)
+// entries are keyed by the canonical path of the archive so that moving the
+// archive does not leak its password to another file with the same name
+
+use std::path::{Path, PathBuf};
+
+use keyring::Entry;
+
+use super::ArchiveError;
+
+const SERVICE: &str = "hezi";
+
+fn entry_for<P: AsRef<Path>>(archive: P) -> Result<Entry, ArchiveError> {
+    let path = archive
+        .as_ref()
+        .canonicalize()
+        .unwrap_or_else(|_| PathBuf::from(archive.as_ref()));
+    Ok(Entry::new(SERVICE, &path.to_string_lossy())?)
+}
+
+/// Returns the password remembered for `archive`, if any.
+pub fn stored_password<P: AsRef<Path>>(archive: P) -> Result<Option<String>, ArchiveError> {
+    match entry_for(archive)?.get_password() {
+        Ok(password) => Ok(Some(password)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Remembers `password` for `archive`, replacing any previous value.
+pub fn store_password<P: AsRef<Path>>(archive: P, password: &str) -> Result<(), ArchiveError> {
+    Ok(entry_for(archive)?.set_password(password)?)
+}
+
+/// Forgets the password remembered for `archive`. Does nothing if there is none.
+pub fn forget_password<P: AsRef<Path>>(archive: P) -> Result<(), ArchiveError> {
+    match entry_for(archive)?.delete_password() {
+        Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
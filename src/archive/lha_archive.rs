@@ -0,0 +1,456 @@
+// read-only support for LHA archives (`.lzh`, `.lha`), still found in retro software and firmware
+// dumps. Members follow each other with a header of level 0, 1 or 2, and are stored (`-lh0-`) or
+// compressed with the static Huffman methods `-lh4-` to `-lh7-`, which only differ by the size of
+// their window. Directories are members of their own, with the `-lhd-` method.
+
+use std::{
+    cell::OnceCell,
+    io::{BufReader, ErrorKind, Read},
+    ops::ControlFlow,
+};
+
+use crate::archive::{
+    datetime_from_timestamp, dos_datetime,
+    entry_visitor::{
+        extract_entries, find_first_entry, list_entries, open_entry, scan_entries, EntryLink,
+        EntryVisitor, VisitOptions, VisitedEntry,
+    },
+    ArchiveError, ArchiveFileEntity, ArchiveFileEntityType, ArchiveMetadata, ArchiveScan,
+    ArchiveType, Archived, CreateOptions, CreateResult, DataSource, ExtractOptions, ExtractReport,
+    ListOptions, OpenOptions, DEFAULT_BUF_SIZE,
+};
+
+use super::{edit::EntryEdit, lzh::Decoder};
+
+const EXTENSION_FILENAME: u8 = 0x01;
+const EXTENSION_DIRECTORY: u8 = 0x02;
+const EXTENSION_UNIX_MODE: u8 = 0x50;
+const EXTENSION_UNIX_TIME: u8 = 0x54;
+/// Separator of the directory names in the extended headers.
+const DIRECTORY_SEPARATOR: u8 = 0xff;
+const S_IFMT: u32 = 0o170000;
+const S_IFLNK: u32 = 0o120000;
+
+fn invalid_data(message: String) -> ArchiveError {
+    ArchiveError::Io(std::io::Error::new(ErrorKind::InvalidData, message))
+}
+
+fn u16_at(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([buf[at], buf[at + 1]])
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+}
+
+#[derive(Debug)]
+struct Header {
+    /// The method, such as `-lh5-`.
+    method: String,
+    name: String,
+    /// Size of the data following the header.
+    compressed_size: u64,
+    size: u64,
+    last_modified: Option<chrono::DateTime<chrono::FixedOffset>>,
+    mode: Option<u32>,
+    crc: u16,
+}
+
+impl Header {
+    /// Reads the next header, or `None` at the end of the archive.
+    fn read<R: Read>(reader: &mut R) -> Result<Option<Self>, ArchiveError> {
+        let mut start = [0; 2];
+        match reader.read(&mut start[..1])? {
+            0 => return Ok(None),
+            _ if start[0] == 0 => return Ok(None),
+            _ => reader.read_exact(&mut start[1..])?,
+        }
+        // the method and sizes lie at the same offsets whatever the level, which is at 20
+        let mut base = [0; 22];
+        base[..2].copy_from_slice(&start);
+        reader.read_exact(&mut base[2..])?;
+        let method = String::from_utf8_lossy(&base[2..7]).to_string();
+        let mut compressed_size = u32_at(&base, 7) as u64;
+        let size = u32_at(&base, 11) as u64;
+        let time = u32_at(&base, 15);
+
+        let (mut name, crc, mut next_extension, last_modified) = match base[20] {
+            level @ (0 | 1) => {
+                // the header size does not count its first two bytes
+                let mut rest = vec![0; (base[0] as usize + 2).saturating_sub(base.len())];
+                reader.read_exact(&mut rest)?;
+                let name_len = base[21] as usize;
+                if rest.len() < name_len + 2 {
+                    return Err(invalid_data("invalid LHA header".to_string()));
+                }
+                let name = String::from_utf8_lossy(&rest[..name_len]).replace('\\', "/");
+                let crc = u16_at(&rest, name_len);
+                let next = match level {
+                    1 if rest.len() >= name_len + 5 => u16_at(&rest, rest.len() - 2),
+                    _ => 0,
+                };
+                (name, crc, next, dos_datetime(time))
+            }
+            2 => {
+                let mut rest = [0; 4];
+                reader.read_exact(&mut rest)?;
+                let crc = u16_at(&[base[21], rest[0]], 0);
+                (
+                    String::new(),
+                    crc,
+                    u16_at(&rest, 2),
+                    datetime_from_timestamp(time as i64).ok(),
+                )
+            }
+            level => {
+                return Err(ArchiveError::Io(std::io::Error::new(
+                    ErrorKind::Unsupported,
+                    format!("LHA headers of level {} are not supported", level),
+                )))
+            }
+        };
+
+        let mut header = Self {
+            method,
+            name: String::new(),
+            compressed_size,
+            size,
+            last_modified,
+            mode: None,
+            crc,
+        };
+
+        // extended headers, each followed by the size of the next one
+        let mut directory = None;
+        let mut header_len = 26;
+        while next_extension != 0 {
+            let len = next_extension as usize;
+            if len < 3 {
+                return Err(invalid_data("invalid LHA extended header".to_string()));
+            }
+            let mut extension = vec![0; len];
+            reader.read_exact(&mut extension)?;
+            header_len += len;
+            // level 1 counts the extended headers in the compressed size
+            if base[20] == 1 {
+                compressed_size = compressed_size.saturating_sub(len as u64);
+            }
+            let data = &extension[1..len - 2];
+            match extension[0] {
+                EXTENSION_FILENAME => name = String::from_utf8_lossy(data).to_string(),
+                EXTENSION_DIRECTORY => {
+                    let path = data
+                        .split(|b| *b == DIRECTORY_SEPARATOR || *b == b'\\')
+                        .filter(|part| !part.is_empty())
+                        .map(String::from_utf8_lossy)
+                        .collect::<Vec<_>>();
+                    directory = Some(path.join("/"));
+                }
+                EXTENSION_UNIX_MODE if data.len() >= 2 => {
+                    header.mode = Some(u16_at(data, 0) as u32);
+                }
+                EXTENSION_UNIX_TIME if data.len() >= 4 => {
+                    header.last_modified = datetime_from_timestamp(u32_at(data, 0) as i64).ok();
+                }
+                _ => {}
+            }
+            next_extension = u16_at(&extension, len - 2);
+        }
+
+        // level 2 headers may end with a padding byte, so that their size is never read as the
+        // end of the archive
+        if base[20] == 2 {
+            let padding = (u16_at(&base, 0) as usize).saturating_sub(header_len);
+            std::io::copy(
+                &mut reader.by_ref().take(padding as u64),
+                &mut std::io::sink(),
+            )?;
+        }
+
+        header.name = match directory {
+            Some(directory) if !directory.is_empty() => format!("{}/{}", directory, name),
+            _ => name,
+        };
+        header.compressed_size = compressed_size;
+        Ok(Some(header))
+    }
+
+    fn is_directory(&self) -> bool {
+        self.method == "-lhd-"
+    }
+
+    /// Target of a Unix symbolic link, stored after a `|` in its name.
+    fn link_target(&self) -> Option<(&str, &str)> {
+        match self.mode {
+            Some(mode) if mode & S_IFMT == S_IFLNK => self.name.split_once('|'),
+            _ => None,
+        }
+    }
+
+    fn visited_entry(&self) -> VisitedEntry {
+        let (name, fstype, link) = if let Some((name, target)) = self.link_target() {
+            (
+                name.to_string(),
+                ArchiveFileEntityType::SymbolicLink,
+                Some(EntryLink::Symbolic(target.to_string())),
+            )
+        } else if self.is_directory() {
+            (
+                format!("{}/", self.name.trim_end_matches('/')),
+                ArchiveFileEntityType::Directory,
+                None,
+            )
+        } else {
+            (self.name.clone(), ArchiveFileEntityType::File, None)
+        };
+        let is_file = fstype == ArchiveFileEntityType::File;
+
+        VisitedEntry {
+            entity: ArchiveFileEntity {
+                name,
+                size: is_file.then_some(self.size),
+                compressed_size: is_file.then_some(self.compressed_size),
+                last_modified: self.last_modified,
+                compression: is_file.then(|| self.method.trim_matches('-').to_string()),
+                fstype,
+                raw: None,
+                unix: None,
+            },
+            mode: self.mode.map(|mode| mode & 0o7777),
+            link,
+            crc32: None,
+        }
+    }
+
+    /// Reader of the content of the member from its compressed `data`.
+    fn reader<'r, R: Read + 'r>(&self, data: R) -> Result<Box<dyn Read + 'r>, ArchiveError> {
+        // window size in bits of the compressed methods
+        let window_bits = match self.method.as_str() {
+            "-lh0-" | "-lz4-" => {
+                return Ok(Box::new(CrcReader::new(data.take(self.size), self)));
+            }
+            "-lh4-" => 12,
+            "-lh5-" => 13,
+            "-lh6-" => 15,
+            "-lh7-" => 16,
+            method => {
+                return Err(ArchiveError::Io(std::io::Error::new(
+                    ErrorKind::Unsupported,
+                    format!("unsupported LHA method {}", method),
+                )))
+            }
+        };
+        let decoder = Decoder::new(data, window_bits, self.size);
+        Ok(Box::new(CrcReader::new(decoder, self)))
+    }
+}
+
+/// Checks the CRC-16 of the content once it was read entirely.
+struct CrcReader<R> {
+    inner: R,
+    name: String,
+    crc: u16,
+    expected: u16,
+}
+
+impl<R: Read> CrcReader<R> {
+    fn new(inner: R, header: &Header) -> Self {
+        Self {
+            inner,
+            name: header.name.clone(),
+            crc: 0,
+            expected: header.crc,
+        }
+    }
+}
+
+impl<R: Read> Read for CrcReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        for byte in &buf[..n] {
+            self.crc ^= *byte as u16;
+            for _ in 0..8 {
+                self.crc = match self.crc & 1 {
+                    1 => (self.crc >> 1) ^ 0xa001,
+                    _ => self.crc >> 1,
+                };
+            }
+        }
+        if n == 0 && !buf.is_empty() && self.crc != self.expected {
+            return Err(std::io::Error::new(
+                ErrorKind::InvalidData,
+                format!("CRC mismatch for {}", self.name),
+            ));
+        }
+        Ok(n)
+    }
+}
+
+pub struct LhaArchive {
+    pub(crate) source: DataSource,
+    scan: OnceCell<ArchiveScan>,
+}
+
+impl LhaArchive {
+    fn scan(&self) -> Result<&ArchiveScan, ArchiveError> {
+        ArchiveScan::cached(&self.scan, || scan_entries(self, None, None))
+    }
+}
+
+impl EntryVisitor for LhaArchive {
+    fn for_each_entry<F>(
+        &self,
+        options: &VisitOptions<'_>,
+        mut visit: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        let source = options
+            .retry
+            .wrap(self.source.clone(), &self.source, options.events);
+        let mut reader = BufReader::with_capacity(DEFAULT_BUF_SIZE, source);
+
+        while let Some(header) = Header::read(&mut reader)? {
+            let mut data = (&mut reader).take(header.compressed_size);
+            let visited = header.visited_entry();
+
+            let flow = if !options.wants(&visited.entity) {
+                ControlFlow::Continue(())
+            } else if options.contents && visited.entity.fstype == ArchiveFileEntityType::File {
+                let mut content = header.reader(&mut data)?;
+                visit(visited, Some(&mut content))?
+            } else {
+                visit(visited, None)?
+            };
+            // the visitor may leave the data unread
+            std::io::copy(&mut data, &mut std::io::sink())?;
+            if flow.is_break() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Archived for LhaArchive {
+    fn of(source: DataSource) -> Result<Self, ArchiveError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            source,
+            scan: OnceCell::new(),
+        })
+    }
+
+    fn extract(&self, options: ExtractOptions) -> Result<ExtractReport, ArchiveError> {
+        extract_entries(self, &options, self.source.as_ref())
+    }
+
+    fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
+        if options.is_whole() {
+            return Ok(self.scan()?.entries.clone());
+        }
+        list_entries(self, self.scan.get(), &options)
+    }
+
+    fn create(_options: CreateOptions) -> Result<CreateResult, ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "create".to_string(),
+            ArchiveType::Lha,
+        ))
+    }
+
+    fn metadata(&self) -> Result<ArchiveMetadata, ArchiveError> {
+        let scan = self.scan()?;
+        Ok(ArchiveMetadata {
+            archive_type: ArchiveType::Lha,
+            entry_count: scan.entries.len(),
+            entries: scan.entries.clone(),
+            total_size: scan.total_size,
+            compressed_size: scan.compressed_size,
+            compression: None,
+            created: self.source.created(),
+            modified: self.source.modified(),
+            additional: None,
+        })
+    }
+
+    fn open(&self, options: OpenOptions) -> Result<(), ArchiveError> {
+        open_entry(self, options)
+    }
+
+    fn find_first<P>(
+        &self,
+        predicate: P,
+    ) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+    where
+        P: Fn(&ArchiveFileEntity) -> bool,
+    {
+        find_first_entry(self, predicate)
+    }
+
+    fn check_password(&self, _password: &str) -> Result<bool, ArchiveError> {
+        // lha has no encryption
+        Ok(true)
+    }
+
+    fn edit(&self, _entry: &str, _edit: &EntryEdit) -> Result<(), ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "edit".to_string(),
+            ArchiveType::Lha,
+        ))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::archive::Archive;
+
+    #[test]
+    fn reads_lha_archives() {
+        let archive = Archive::of(DataSource::file("tests/fixtures/test1.lzh").unwrap()).unwrap();
+        assert!(matches!(archive, Archive::Lha(_)));
+
+        let entries = archive.list(ListOptions::default()).unwrap();
+        let names = entries
+            .iter()
+            .map(|e| (e.name.as_str(), e.fstype, e.compression.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                ("test1/", ArchiveFileEntityType::Directory, None),
+                ("test1/file1.txt", ArchiveFileEntityType::File, Some("lh5")),
+                (
+                    "test1/dir1/file2.txt",
+                    ArchiveFileEntityType::File,
+                    Some("lh6")
+                ),
+                ("readme.txt", ArchiveFileEntityType::File, Some("lh0")),
+            ]
+        );
+        assert_eq!(
+            entries[1].last_modified.unwrap().to_rfc3339(),
+            "2024-05-04T09:24:00+00:00"
+        );
+
+        for (name, expected) in [
+            (
+                "test1/file1.txt",
+                std::fs::read("tests/fixtures/test1/file1.txt").unwrap(),
+            ),
+            (
+                "test1/dir1/file2.txt",
+                std::fs::read("tests/fixtures/test1/dir1/file2.txt").unwrap(),
+            ),
+            ("readme.txt", b"stored as is\n".to_vec()),
+        ] {
+            let (_, content) = archive.find_first(|e| e.name == name).unwrap().unwrap();
+            assert_eq!(content, expected, "{}", name);
+        }
+    }
+}
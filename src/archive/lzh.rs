@@ -0,0 +1,256 @@
+// decoder of the static Huffman methods of LHA (`-lh4-` to `-lh7-`), which ARJ archives also
+// compress their entries with. Blocks start with the Huffman tables of their literals and match
+// lengths, and of their match offsets, followed by the codes of as many literals and matches as
+// the block counts.
+
+use std::io::{ErrorKind, Read};
+
+use crate::archive::ArchiveError;
+
+fn invalid_data(message: String) -> ArchiveError {
+    ArchiveError::Io(std::io::Error::new(ErrorKind::InvalidData, message))
+}
+
+/// Bits read most significant first, zeroes past the end of the data like LHa does.
+pub(crate) struct BitReader<R> {
+    inner: R,
+    bits: u32,
+    count: u32,
+}
+
+impl<R: Read> BitReader<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self {
+            inner,
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    pub(crate) fn bits(&mut self, n: u32) -> std::io::Result<u32> {
+        while self.count < n {
+            let mut byte = [0; 1];
+            let byte = match self.inner.read(&mut byte)? {
+                0 => 0,
+                _ => byte[0],
+            };
+            self.bits = (self.bits << 8) | byte as u32;
+            self.count += 8;
+        }
+        self.count -= n;
+        Ok((self.bits >> self.count) & ((1 << n) - 1))
+    }
+}
+
+/// Canonical Huffman code, the shorter codes coming first and codes of the same length in the
+/// order of their symbols.
+enum Huffman {
+    /// The only symbol of a block, taking no bits.
+    Single(u16),
+    Codes {
+        counts: [u16; 17],
+        symbols: Vec<u16>,
+    },
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, ArchiveError> {
+        let mut counts = [0u16; 17];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols = Vec::with_capacity(lengths.len());
+        for length in 1..=16 {
+            symbols.extend(
+                (0..lengths.len())
+                    .filter(|s| lengths[*s] as usize == length)
+                    .map(|s| s as u16),
+            );
+        }
+        if symbols.is_empty() {
+            return Err(invalid_data("empty LZH Huffman table".to_string()));
+        }
+        Ok(Huffman::Codes { counts, symbols })
+    }
+
+    fn decode<R: Read>(&self, reader: &mut BitReader<R>) -> std::io::Result<u16> {
+        let (counts, symbols) = match self {
+            Huffman::Single(symbol) => return Ok(*symbol),
+            Huffman::Codes { counts, symbols } => (counts, symbols),
+        };
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for count in &counts[1..] {
+            code |= reader.bits(1)? as i32;
+            let count = *count as i32;
+            if code - first < count {
+                return Ok(symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(std::io::Error::new(
+            ErrorKind::InvalidData,
+            "invalid LZH Huffman code",
+        ))
+    }
+}
+
+/// Literals and match lengths, `256 + length - 3` for matches of 3 to 256 bytes.
+const CODES: usize = 510;
+/// Codes of the lengths of the literal and length codes.
+const LENGTH_CODES: usize = 19;
+
+/// Decoder of the static Huffman methods, whose blocks start with their code tables.
+pub(crate) struct Decoder<R> {
+    bits: BitReader<R>,
+    window: Vec<u8>,
+    position: usize,
+    /// Number of offset codes, and bits their count is stored in.
+    offset_codes: usize,
+    offset_bits: u32,
+    remaining: u64,
+    block_remaining: u32,
+    codes: Huffman,
+    offsets: Huffman,
+    /// Distance and remaining length of the match being copied.
+    copying: (usize, usize),
+}
+
+impl<R: Read> Decoder<R> {
+    /// A decoder of `size` bytes with a window of `window_bits` bits, 12 to 16.
+    pub(crate) fn new(inner: R, window_bits: u32, size: u64) -> Self {
+        let (offset_codes, offset_bits) = match window_bits {
+            12 | 13 => (14, 4),
+            15 => (16, 5),
+            _ => (17, 5),
+        };
+        Self {
+            bits: BitReader::new(inner),
+            window: vec![0; 1 << window_bits],
+            position: 0,
+            offset_codes,
+            offset_bits,
+            remaining: size,
+            block_remaining: 0,
+            codes: Huffman::Single(0),
+            offsets: Huffman::Single(0),
+            copying: (0, 0),
+        }
+    }
+
+    /// Reads a code length: 0 to 6 on 3 bits, longer ones as `111` followed by one `1` for
+    /// each additional length and a `0`.
+    fn code_length(&mut self) -> std::io::Result<u8> {
+        let mut length = self.bits.bits(3)? as u8;
+        if length == 7 {
+            while self.bits.bits(1)? == 1 {
+                length += 1;
+                if length > 16 {
+                    return Err(std::io::Error::new(
+                        ErrorKind::InvalidData,
+                        "invalid LZH code length",
+                    ));
+                }
+            }
+        }
+        Ok(length)
+    }
+
+    /// Reads the table of a code whose lengths are stored as is, the count of lengths being
+    /// stored on `count_bits` bits. After the third length of the code length table comes the
+    /// number of zero lengths that follow it, on 2 bits.
+    fn read_lengths(
+        &mut self,
+        symbols: usize,
+        count_bits: u32,
+        zeroes_after_third: bool,
+    ) -> Result<Huffman, ArchiveError> {
+        let count = self.bits.bits(count_bits)? as usize;
+        if count == 0 {
+            return Ok(Huffman::Single(self.bits.bits(count_bits)? as u16));
+        }
+        let mut lengths = vec![0u8; symbols];
+        let mut i = 0;
+        while i < count.min(symbols) {
+            lengths[i] = self.code_length()?;
+            i += 1;
+            if zeroes_after_third && i == 3 {
+                i += self.bits.bits(2)? as usize;
+            }
+        }
+        Huffman::new(&lengths)
+    }
+
+    fn read_code_lengths(&mut self) -> Result<Huffman, ArchiveError> {
+        let length_codes = self.read_lengths(LENGTH_CODES, 5, true)?;
+        let count = self.bits.bits(9)? as usize;
+        if count == 0 {
+            return Ok(Huffman::Single(self.bits.bits(9)? as u16));
+        }
+        let mut lengths = vec![0u8; CODES];
+        let mut i = 0;
+        while i < count.min(CODES) {
+            // codes 0 to 2 are runs of zero lengths, the others lengths plus 2
+            let zeroes = match length_codes.decode(&mut self.bits)? {
+                0 => 1,
+                1 => self.bits.bits(4)? as usize + 3,
+                2 => self.bits.bits(9)? as usize + 20,
+                code => {
+                    lengths[i] = (code - 2) as u8;
+                    i += 1;
+                    continue;
+                }
+            };
+            i += zeroes;
+        }
+        Huffman::new(&lengths)
+    }
+
+    fn next_byte(&mut self) -> Result<u8, ArchiveError> {
+        let mask = self.window.len() - 1;
+        if self.copying.1 == 0 {
+            if self.block_remaining == 0 {
+                self.block_remaining = self.bits.bits(16)?;
+                self.codes = self.read_code_lengths()?;
+                self.offsets = self.read_lengths(self.offset_codes, self.offset_bits, false)?;
+            }
+            self.block_remaining -= 1;
+
+            let code = self.codes.decode(&mut self.bits)? as usize;
+            if code < 256 {
+                self.window[self.position & mask] = code as u8;
+                self.position += 1;
+                return Ok(code as u8);
+            }
+            let distance = match self.offsets.decode(&mut self.bits)? as u32 {
+                0 => 0,
+                bits => (1 << (bits - 1)) + self.bits.bits(bits - 1)?,
+            };
+            self.copying = (distance as usize + 1, code - 256 + 3);
+        }
+
+        let byte = self.window[self.position.wrapping_sub(self.copying.0) & mask];
+        self.window[self.position & mask] = byte;
+        self.position += 1;
+        self.copying.1 -= 1;
+        Ok(byte)
+    }
+}
+
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = buf
+            .len()
+            .min(self.remaining.min(usize::MAX as u64) as usize);
+        for byte in &mut buf[..n] {
+            *byte = self.next_byte().map_err(|e| match e {
+                ArchiveError::Io(e) => e,
+                e => std::io::Error::other(e.to_string()),
+            })?;
+        }
+        self.remaining -= n as u64;
+        Ok(n)
+    }
+}
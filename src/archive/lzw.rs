@@ -0,0 +1,224 @@
+// decoder of the LZW streams written by the old unix `compress`, still found as `.Z` and `.tar.Z`
+// files from Solaris and AIX systems. Only decompression is supported.
+//
+// Codes start at 9 bits and grow up to the maximum found in the header, packed least significant
+// bit first. `compress` writes them in groups of eight, and pads the current group when the width
+// changes or the table is cleared, so that the rest of that group has to be skipped.
+
+use std::io::{Error, ErrorKind, Read};
+
+const MAGIC: [u8; 2] = [0x1f, 0x9d];
+const INIT_BITS: u32 = 9;
+const MAX_BITS: u32 = 16;
+/// Flag of the header telling that code 256 clears the table.
+const BLOCK_MODE: u8 = 0x80;
+const CLEAR: u16 = 256;
+
+/// Reader decompressing a `compress` stream read from `inner`.
+pub struct LzwReader<R: Read> {
+    inner: R,
+    max_bits: u32,
+    block_mode: bool,
+    n_bits: u32,
+    /// Next entry of the table to be added.
+    free_ent: u32,
+    prefix: Vec<u16>,
+    suffix: Vec<u8>,
+    /// Code read before the current one, `None` at the start and after a clear.
+    previous: Option<u16>,
+    /// First byte of the string of the previous code.
+    first: u8,
+    /// Group of codes being read, and the position of the next code in it, in bits.
+    group: Vec<u8>,
+    group_bits: usize,
+    position: usize,
+    /// Decoded bytes not yet returned, stored backwards.
+    pending: Vec<u8>,
+    done: bool,
+}
+
+impl<R: Read> LzwReader<R> {
+    /// Reads the header of the stream, failing if `inner` is not a `compress` stream.
+    pub fn new(mut inner: R) -> Result<Self, Error> {
+        let mut header = [0u8; 3];
+        inner.read_exact(&mut header)?;
+        if header[..2] != MAGIC {
+            return Err(invalid("not a compress stream"));
+        }
+        let max_bits = (header[2] & 0x1f) as u32;
+        if !(INIT_BITS..=MAX_BITS).contains(&max_bits) {
+            return Err(invalid(format!(
+                "unsupported code width of {} bits",
+                max_bits
+            )));
+        }
+        let block_mode = header[2] & BLOCK_MODE != 0;
+
+        Ok(Self {
+            inner,
+            max_bits,
+            block_mode,
+            n_bits: INIT_BITS,
+            free_ent: first_entry(block_mode),
+            prefix: vec![0; 1 << max_bits],
+            suffix: (0..1u32 << max_bits).map(|c| c as u8).collect(),
+            previous: None,
+            first: 0,
+            group: Vec::with_capacity(MAX_BITS as usize),
+            group_bits: 0,
+            position: 0,
+            pending: Vec::new(),
+            done: false,
+        })
+    }
+
+    fn max_code(&self) -> u32 {
+        (1 << self.n_bits) - 1
+    }
+
+    /// Reads the next code, `None` at the end of the stream.
+    fn next_code(&mut self) -> Result<Option<u16>, Error> {
+        if self.free_ent > self.max_code() && self.n_bits < self.max_bits {
+            self.n_bits += 1;
+            self.skip_group();
+        }
+        if self.position + self.n_bits as usize > self.group_bits && !self.read_group()? {
+            return Ok(None);
+        }
+
+        let mut code = 0u32;
+        for i in 0..self.n_bits as usize {
+            let bit = self.position + i;
+            code |= ((self.group[bit / 8] >> (bit % 8)) as u32 & 1) << i;
+        }
+        self.position += self.n_bits as usize;
+        Ok(Some(code as u16))
+    }
+
+    /// Reads the next group of eight codes, which may be shorter at the end of the stream. Returns
+    /// whether it holds a whole code.
+    fn read_group(&mut self) -> Result<bool, Error> {
+        self.group.clear();
+        (&mut self.inner)
+            .take(self.n_bits as u64)
+            .read_to_end(&mut self.group)?;
+        self.group_bits = self.group.len() * 8;
+        self.position = 0;
+        Ok(self.group_bits >= self.n_bits as usize)
+    }
+
+    /// Skips the padding left after the code just read.
+    fn skip_group(&mut self) {
+        self.position = self.group_bits;
+    }
+
+    /// Decodes the next code into `pending`, returning `false` at the end of the stream.
+    fn decode_next(&mut self) -> Result<bool, Error> {
+        let Some(code) = self.next_code()? else {
+            return Ok(false);
+        };
+        if self.block_mode && code == CLEAR {
+            self.n_bits = INIT_BITS;
+            self.free_ent = first_entry(true);
+            self.previous = None;
+            self.skip_group();
+            return Ok(true);
+        }
+
+        let Some(previous) = self.previous else {
+            if code > 0xff {
+                return Err(invalid("invalid first code"));
+            }
+            self.first = code as u8;
+            self.previous = Some(code);
+            self.pending.push(code as u8);
+            return Ok(true);
+        };
+
+        let mut current = code as u32;
+        if current > self.free_ent {
+            return Err(invalid(format!("invalid code {}", code)));
+        }
+        // the code being defined by this very step stands for the previous string followed by
+        // its own first byte
+        if current == self.free_ent {
+            self.pending.push(self.first);
+            current = previous as u32;
+        }
+        while current > 0xff {
+            self.pending.push(self.suffix[current as usize]);
+            current = self.prefix[current as usize] as u32;
+        }
+        self.first = current as u8;
+        self.pending.push(self.first);
+
+        if self.free_ent < 1 << self.max_bits {
+            self.prefix[self.free_ent as usize] = previous;
+            self.suffix[self.free_ent as usize] = self.first;
+            self.free_ent += 1;
+        }
+        self.previous = Some(code);
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for LzwReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        while self.pending.is_empty() && !self.done {
+            self.done = !self.decode_next()?;
+        }
+
+        let n = buf.len().min(self.pending.len());
+        for b in buf.iter_mut().take(n) {
+            // `pending` is never empty here, it holds at least `n` bytes
+            *b = self.pending.pop().unwrap_or_default();
+        }
+        Ok(n)
+    }
+}
+
+fn first_entry(block_mode: bool) -> u32 {
+    match block_mode {
+        true => CLEAR as u32 + 1,
+        false => CLEAR as u32,
+    }
+}
+
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> Error {
+    Error::new(ErrorKind::InvalidData, error)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut out = Vec::new();
+        LzwReader::new(data)?.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn decompresses_tarballs() {
+        let data = std::fs::read("tests/fixtures/test1.tar.Z").unwrap();
+        let tar = std::fs::read("tests/fixtures/test1.tar").unwrap();
+        assert_eq!(decompress(&data).unwrap(), tar);
+    }
+
+    #[test]
+    fn decompresses_cleared_tables() {
+        // written with 10 bit codes at most, so that the table is full and cleared many times
+        let data = std::fs::read("tests/fixtures/cleared.Z").unwrap();
+        let expected = (0..5000u32)
+            .flat_map(|i| format!("{} ", i * 7919 % 1000).into_bytes())
+            .collect::<Vec<_>>();
+        assert_eq!(decompress(&data).unwrap(), expected);
+    }
+
+    #[test]
+    fn rejects_other_streams() {
+        assert!(decompress(b"\x1f\x8b\x08\x00").is_err());
+        assert!(decompress(b"\x1f\x9d\x91\xff\xff").is_err());
+    }
+}
@@ -0,0 +1,301 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::Read,
+    ops::ControlFlow,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::{
+    entry_visitor::{normalized_name, EntryVisitor, VisitOptions},
+    is_hidden,
+    password::{PasswordProvider, StaticPassword},
+    Archive, ArchiveError, ArchiveFileEntityType, CreateOptions, DataSource, DEFAULT_BUF_SIZE,
+};
+
+/// Size and SHA-256 digest of every file of an archive, keyed by entry name.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub entries: BTreeMap<String, ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub size: u64,
+    /// Lowercase hex encoded digest of the content.
+    pub sha256: String,
+}
+
+/// Differences between an archive and the manifest it was checked against.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ManifestDiff {
+    /// Entries of the manifest absent from the archive.
+    pub missing: Vec<String>,
+    /// Entries of the archive absent from the manifest.
+    pub unexpected: Vec<String>,
+    /// Entries whose size or digest differ.
+    pub changed: Vec<String>,
+}
+
+impl ManifestDiff {
+    pub fn is_clean(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl std::fmt::Display for ManifestDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_clean() {
+            return write!(f, "archive matches the manifest");
+        }
+        for name in &self.missing {
+            writeln!(f, "missing:    {}", name)?;
+        }
+        for name in &self.unexpected {
+            writeln!(f, "unexpected: {}", name)?;
+        }
+        for name in &self.changed {
+            writeln!(f, "changed:    {}", name)?;
+        }
+        write!(
+            f,
+            "{} missing, {} unexpected, {} changed",
+            self.missing.len(),
+            self.unexpected.len(),
+            self.changed.len()
+        )
+    }
+}
+
+impl Manifest {
+    /// Hashes every file entry of `archive`.
+    pub fn of(
+        archive: &Archive,
+        password: Option<&dyn PasswordProvider>,
+    ) -> Result<Self, ArchiveError> {
+        let is_file = |e: &super::ArchiveFileEntity| e.fstype == ArchiveFileEntityType::File;
+        let options = VisitOptions {
+            password,
+            filter: Some(&is_file),
+            contents: true,
+            ..Default::default()
+        };
+
+        let mut entries = BTreeMap::new();
+        archive.for_each_entry(&options, |entry, reader| {
+            if let Some(reader) = reader {
+                let name = normalized_name(&entry.entity.name).to_string();
+                entries.insert(name, ManifestEntry::digest(reader)?);
+            }
+            Ok(ControlFlow::Continue(()))
+        })?;
+        Ok(Self { entries })
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<Self, ArchiveError> {
+        let file = std::fs::File::open(path)?;
+        serde_json::from_reader(std::io::BufReader::new(file)).map_err(ArchiveError::Json)
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), ArchiveError> {
+        let file = std::fs::File::create(path)?;
+        serde_json::to_writer_pretty(std::io::BufWriter::new(file), self)
+            .map_err(ArchiveError::Json)
+    }
+
+    /// Compares `actual` against this manifest, taken as the expected state.
+    pub fn diff(&self, actual: &Manifest) -> ManifestDiff {
+        let mut diff = ManifestDiff::default();
+        for (name, expected) in &self.entries {
+            match actual.entries.get(name) {
+                None => diff.missing.push(name.clone()),
+                Some(entry) if entry != expected => diff.changed.push(name.clone()),
+                Some(_) => {}
+            }
+        }
+        diff.unexpected = actual
+            .entries
+            .keys()
+            .filter(|name| !self.entries.contains_key(*name))
+            .cloned()
+            .collect();
+        diff
+    }
+}
+
+/// Source files of an archive, hashed before it is created to check it against them once
+/// created, see [`CreateOptions::verify_after`].
+pub(crate) struct SourceFiles {
+    manifest: Manifest,
+    /// Files that may be left out of the archive, hidden ones not being added by every format.
+    optional: HashSet<String>,
+}
+
+impl SourceFiles {
+    pub(crate) fn hash(options: &CreateOptions) -> Result<Self, ArchiveError> {
+        let mut manifest = Manifest::default();
+        let mut optional = HashSet::new();
+        for path in &options.files {
+            let metadata = std::fs::metadata(path)?;
+            if !metadata.is_file() {
+                continue;
+            }
+
+            let name = options
+                .entry_name(path)
+                .to_string_lossy()
+                .replace('\\', "/");
+            let name = normalized_name(&name).to_string();
+            if !options.include_hidden && is_hidden(&name, &metadata) {
+                optional.insert(name.clone());
+            }
+            let mut file = std::fs::File::open(path)?;
+            manifest
+                .entries
+                .insert(name, ManifestEntry::digest(&mut file)?);
+        }
+        Ok(Self { manifest, optional })
+    }
+
+    /// Checks the archive at `path` against the files, failing with
+    /// [`ArchiveError::VerificationFailed`] if it does not hold all of them unchanged.
+    pub(crate) fn verify(&self, path: &Path, password: Option<&str>) -> Result<(), ArchiveError> {
+        let archive = Archive::of(DataSource::file(path)?)?;
+        let password = password.map(|p| StaticPassword(p.to_string()));
+        let password = password.as_ref().map(|p| p as &dyn PasswordProvider);
+        let mut diff = self.manifest.diff(&Manifest::of(&archive, password)?);
+        diff.missing.retain(|name| !self.optional.contains(name));
+        if diff.is_clean() {
+            Ok(())
+        } else {
+            Err(ArchiveError::VerificationFailed(diff))
+        }
+    }
+}
+
+impl ManifestEntry {
+    pub(crate) fn digest<R: Read + ?Sized>(reader: &mut R) -> Result<Self, ArchiveError> {
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0; DEFAULT_BUF_SIZE];
+        let mut size = 0;
+        loop {
+            let read = reader.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buf[..read]);
+            size += read as u64;
+        }
+
+        let sha256 = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        Ok(Self { size, sha256 })
+    }
+}
+
+#[cfg(all(test, feature = "tar_archive", feature = "zip_archive"))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::archive::{
+        ArchiveCompression, ArchiveType, Archived, CompressionSpec, SimpleLogger,
+    };
+
+    fn manifest(path: &str) -> Manifest {
+        let archive = Archive::of(DataSource::file(path).unwrap()).unwrap();
+        Manifest::of(&archive, None).unwrap()
+    }
+
+    #[test]
+    fn hashes_file_entries() {
+        let tar = manifest("tests/fixtures/test1.tar");
+        let zip = manifest("tests/fixtures/test1.zip");
+
+        let original = std::fs::read("tests/fixtures/test1/dir1/file2.txt").unwrap();
+        let expected = ManifestEntry::digest(&mut original.as_slice()).unwrap();
+        assert_eq!(tar.entries["test1/dir1/file2.txt"], expected);
+        assert_eq!(zip.entries["test1/dir1/file2.txt"], expected);
+        assert!(!zip.entries.contains_key("test1/dir1/"));
+        assert!(tar.diff(&manifest("tests/fixtures/test1.tar")).is_clean());
+    }
+
+    #[test]
+    fn reports_drift() {
+        let expected = manifest("tests/fixtures/test1.tar");
+        let mut actual = expected.clone();
+        actual.entries.remove("test1/file1.txt");
+        actual.entries.insert(
+            "test1/extra.txt".to_string(),
+            ManifestEntry {
+                size: 0,
+                sha256: String::new(),
+            },
+        );
+        if let Some(entry) = actual.entries.get_mut("test1/dir1/file2.txt") {
+            entry.size += 1;
+        }
+
+        let diff = expected.diff(&actual);
+        assert_eq!(diff.missing, vec!["test1/file1.txt"]);
+        assert_eq!(diff.unexpected, vec!["test1/extra.txt"]);
+        assert_eq!(diff.changed, vec!["test1/dir1/file2.txt"]);
+        assert!(!diff.is_clean());
+    }
+
+    fn create_options(source: &Path, destination: &Path) -> CreateOptions<'static> {
+        CreateOptions {
+            destination: destination.to_path_buf(),
+            source: source.to_path_buf(),
+            roots: vec![],
+            files: walkdir::WalkDir::new(source)
+                .into_iter()
+                .map(|e| e.unwrap().into_path())
+                .collect(),
+            password: None,
+            archive_type: ArchiveType::Tar,
+            compression: Some(CompressionSpec::new(ArchiveCompression::None)),
+            overwrite: true,
+            include_hidden: true,
+            skip_unreadable: false,
+            verify_after: true,
+            volume_size: None,
+            update: false,
+            sevenz: Default::default(),
+            zip: Default::default(),
+            tar: Default::default(),
+            event_handler: Box::new(SimpleLogger),
+        }
+    }
+
+    #[test]
+    fn verifies_created_archives() {
+        let dir = std::env::temp_dir().join(format!("hezi-verify-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        let source = dir.join("source");
+        std::fs::create_dir_all(source.join("test1/dir1")).unwrap();
+        for file in ["test1/file1.txt", "test1/dir1/file2.txt"] {
+            std::fs::copy(Path::new("tests/fixtures").join(file), source.join(file)).unwrap();
+        }
+
+        let created = dir.join("created.tar");
+        Archive::create(create_options(&source.join("test1"), &created)).unwrap();
+
+        // the fixture no longer holds what the source files do
+        std::fs::write(source.join("test1/file1.txt"), "changed").unwrap();
+        let sources = SourceFiles::hash(&create_options(&source, &created)).unwrap();
+        let fixture = Path::new("tests/fixtures/test1.tar");
+        match sources.verify(fixture, None) {
+            Err(ArchiveError::VerificationFailed(diff)) => {
+                assert_eq!(diff.changed, vec!["test1/file1.txt"]);
+                assert!(diff.missing.is_empty());
+            }
+            other => panic!("expected a verification failure, got {:?}", other.err()),
+        }
+        _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,101 @@
+use std::{
+    collections::HashSet,
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    entry_visitor::{normalized_name, EntryVisitor, VisitOptions},
+    password::PasswordProvider,
+    Archive, ArchiveError,
+};
+
+/// Files and directories of `destination` that are not entries of `archive`, so that deleting
+/// them makes `destination` match the archive. The content of an extraneous directory is not
+/// listed, the directory itself is.
+pub fn extraneous_files<P: AsRef<Path>>(
+    archive: &Archive,
+    password: Option<&dyn PasswordProvider>,
+    destination: P,
+) -> Result<Vec<PathBuf>, ArchiveError> {
+    let mut expected = HashSet::new();
+    let options = VisitOptions {
+        password,
+        ..Default::default()
+    };
+    archive.for_each_entry(&options, |entry, _| {
+        let name = normalized_name(&entry.entity.name).trim_end_matches('/');
+        // the parents of an entry are expected even if the archive has no entry for them
+        for ancestor in Path::new(name).ancestors() {
+            if !expected.insert(ancestor.to_string_lossy().replace('\\', "/")) {
+                break;
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    })?;
+
+    let destination = destination.as_ref();
+    let mut extraneous = Vec::new();
+    let mut walk = walkdir::WalkDir::new(destination)
+        .min_depth(1)
+        .sort_by_file_name()
+        .into_iter();
+    while let Some(entry) = walk.next() {
+        let entry = entry.map_err(std::io::Error::from)?;
+        let relative = entry
+            .path()
+            .strip_prefix(destination)
+            .unwrap_or(entry.path())
+            .to_string_lossy()
+            .replace('\\', "/");
+        if !expected.contains(&relative) {
+            if entry.file_type().is_dir() {
+                walk.skip_current_dir();
+            }
+            extraneous.push(entry.into_path());
+        }
+    }
+    Ok(extraneous)
+}
+
+/// Deletes `paths`, as returned by [`extraneous_files`].
+pub fn remove_extraneous(paths: &[PathBuf]) -> Result<(), ArchiveError> {
+    for path in paths {
+        if path.symlink_metadata()?.is_dir() {
+            std::fs::remove_dir_all(path)?;
+        } else {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(all(test, feature = "tar_archive"))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::archive::DataSource;
+
+    #[test]
+    fn finds_files_absent_from_archive() {
+        let dest = std::env::temp_dir().join(format!("hezi-mirror-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dest);
+        std::fs::create_dir_all(dest.join("test1/dir1")).unwrap();
+        std::fs::create_dir_all(dest.join("stale/nested")).unwrap();
+        std::fs::write(dest.join("test1/file1.txt"), "kept").unwrap();
+        std::fs::write(dest.join("test1/dir1/extra.txt"), "extra").unwrap();
+        std::fs::write(dest.join("stale/nested/old.txt"), "old").unwrap();
+
+        let archive = Archive::of(DataSource::file("tests/fixtures/test1.tar").unwrap()).unwrap();
+        let extraneous = extraneous_files(&archive, None, &dest).unwrap();
+        assert_eq!(
+            extraneous,
+            vec![dest.join("stale"), dest.join("test1/dir1/extra.txt")]
+        );
+
+        remove_extraneous(&extraneous).unwrap();
+        assert!(!dest.join("stale").exists());
+        assert!(dest.join("test1/file1.txt").exists());
+        _ = std::fs::remove_dir_all(&dest);
+    }
+}
@@ -1,15 +1,85 @@
+#[cfg(feature = "ar_archive")]
+pub mod ar_archive;
+#[cfg(feature = "arj_archive")]
+pub mod arj_archive;
+#[cfg(feature = "cab_archive")]
+pub mod cab_archive;
 pub mod codecs;
+#[cfg(feature = "compressed_file")]
+pub mod compressed_file;
+pub mod convert;
+#[cfg(feature = "cpio_archive")]
+pub mod cpio_archive;
+pub mod date_format;
+pub mod diff;
+#[cfg(feature = "disk_image")]
+pub mod disk_image;
+#[cfg(feature = "dmg_archive")]
+pub mod dmg_archive;
+pub mod edit;
+pub mod entries;
+pub mod eol;
+pub mod extract_cache;
+pub mod guess;
+pub mod identify;
+pub mod incremental;
+pub mod integrity;
 #[cfg(feature = "iso_archive")]
 pub mod iso_archive;
+#[cfg(feature = "keyring")]
+pub mod keyring_store;
+#[cfg(feature = "lha_archive")]
+pub mod lha_archive;
+#[cfg(any(feature = "lha_archive", feature = "arj_archive"))]
+mod lzh;
+#[cfg(feature = "lzw_codecs")]
+pub mod lzw;
+pub mod manifest;
+pub mod mirror;
+#[cfg(feature = "msi_archive")]
+pub mod msi_archive;
+pub mod name_template;
+#[cfg(feature = "notifications")]
+pub mod notifier;
+#[cfg(feature = "nsis_archive")]
+pub mod nsis_archive;
+pub mod owner;
+pub mod password;
+pub mod peek;
+pub mod progress;
+#[cfg(feature = "rar_archive")]
+pub mod rar_archive;
+pub mod retry;
+pub mod rollup;
+pub mod sample;
 #[cfg(feature = "sevenz_archive")]
 pub mod sevenz_archive;
+#[cfg(feature = "squashfs_archive")]
+pub mod squashfs_archive;
+pub mod suggest;
 #[cfg(feature = "tar_archive")]
 pub mod tar_archive;
+pub mod tree;
+pub mod volumes;
+#[cfg(feature = "warc_archive")]
+pub mod warc_archive;
+#[cfg(feature = "wim_archive")]
+pub mod wim_archive;
 #[cfg(feature = "zip_archive")]
 pub mod zip_archive;
+#[cfg(feature = "zip_archive")]
+pub mod zip_stream;
+#[cfg(feature = "zpaq_archive")]
+pub mod zpaq_archive;
 
+mod adaptive_writes;
 mod archive_base;
+mod entry_visitor;
+mod extract_sink;
 pub mod macros;
+#[cfg(feature = "multithreading")]
+mod parallel_gzip;
+mod tuned_encoders;
 
 #[cfg(any(feature = "nu_plugin", feature = "cli"))]
 pub mod nu_protocol_serialization;
@@ -0,0 +1,648 @@
+// read-only support for Windows Installer packages (`.msi`, `.msm`), compound files whose streams
+// hold the tables of a database and the cabinets of the files to install. The files are named in
+// the cabinets by their key in the `File` table, which tells their long name and, through the
+// `Component` and `Directory` tables, the directory they are installed in.
+
+use std::{
+    cell::OnceCell,
+    collections::HashMap,
+    io::{BufReader, Read, Seek},
+    ops::ControlFlow,
+    path::PathBuf,
+};
+
+use cab::Cabinet;
+use cfb::CompoundFile;
+
+use crate::archive::{
+    cab_archive::CabArchive,
+    entry_visitor::{
+        extract_entries, find_first_entry, list_entries, open_entry, scan_entries, EntryVisitor,
+        VisitOptions, VisitedEntry,
+    },
+    ArchiveError, ArchiveFileEntity, ArchiveMetadata, ArchiveScan, ArchiveType, Archived,
+    CreateOptions, CreateResult, DataSource, ExtractOptions, ExtractReport, ListOptions,
+    OpenOptions, DEFAULT_BUF_SIZE,
+};
+
+use super::edit::EntryEdit;
+
+/// Magic of compound files, which Office documents of old share with installers.
+pub(crate) const COMPOUND_FILE_MAGIC: [u8; 8] = [0xd0, 0xcf, 0x11, 0xe0, 0xa1, 0xb1, 0x1a, 0xe1];
+/// First character of the names of the streams holding tables.
+const TABLE_PREFIX: char = '\u{4840}';
+/// Characters encoded in stream names, two by character of the name or one for the last.
+const STREAM_NAME_CHARS: &[u8; 64] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz._";
+const TYPE_VALID: u16 = 0x0100;
+const TYPE_STRING: u16 = 0x0800;
+const TYPE_NULLABLE: u16 = 0x1000;
+
+/// Name of a stream as the database knows it, which packs the characters the names of tables and
+/// keys are made of by two in a character of the range `0x3800..0x4840`.
+fn decode_stream_name(name: &str) -> String {
+    let mut decoded = String::with_capacity(name.len() * 2);
+    for c in name.chars() {
+        match c as u32 {
+            c @ 0x3800..0x4800 => {
+                let c = c - 0x3800;
+                decoded.push(STREAM_NAME_CHARS[(c & 0x3f) as usize] as char);
+                decoded.push(STREAM_NAME_CHARS[((c >> 6) & 0x3f) as usize] as char);
+            }
+            c @ 0x4800..0x4840 => decoded.push(STREAM_NAME_CHARS[(c - 0x4800) as usize] as char),
+            _ => decoded.push(c),
+        }
+    }
+    decoded
+}
+
+/// The streams at the root of `file`, by their decoded name.
+fn streams<F: Read + Seek>(file: &CompoundFile<F>) -> HashMap<String, PathBuf> {
+    file.read_root_storage()
+        .filter(|entry| entry.is_stream())
+        .map(|entry| (decode_stream_name(entry.name()), entry.path().to_path_buf()))
+        .collect()
+}
+
+/// Whether `reader` is a compound file holding a database, rather than e.g. a Word document.
+pub(crate) fn is_installer<R: Read + Seek>(reader: R) -> bool {
+    CompoundFile::open(reader)
+        .is_ok_and(|file| streams(&file).contains_key(&format!("{}_StringPool", TABLE_PREFIX)))
+}
+
+fn read_stream<F: Read + Seek>(
+    file: &mut CompoundFile<F>,
+    streams: &HashMap<String, PathBuf>,
+    name: &str,
+) -> Result<Vec<u8>, ArchiveError> {
+    let mut data = Vec::new();
+    if let Some(path) = streams.get(name) {
+        file.open_stream(path)?.read_to_end(&mut data)?;
+    }
+    Ok(data)
+}
+
+/// Strings of the code page of the database, read as UTF-8 when they are valid, else as Latin-1.
+fn decode_string(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(s) => s.to_string(),
+        Err(_) => bytes.iter().map(|b| *b as char).collect(),
+    }
+}
+
+/// The tables of the database, as far as they tell where files are installed.
+struct Database {
+    /// The shared strings the cells refer to, the first being the null one.
+    strings: Vec<String>,
+    /// Size of the references to strings, 2 or 3 bytes.
+    string_ref: usize,
+    /// Names and types of the columns of each table, in their order.
+    columns: HashMap<String, Vec<(String, u16)>>,
+}
+
+impl Database {
+    fn read<F: Read + Seek>(
+        file: &mut CompoundFile<F>,
+        streams: &HashMap<String, PathBuf>,
+    ) -> Result<Self, ArchiveError> {
+        let table = |name: &str| format!("{}{}", TABLE_PREFIX, name);
+        let pool = read_stream(file, streams, &table("_StringPool"))?;
+        let data = read_stream(file, streams, &table("_StringData"))?;
+
+        // entries of a length and a reference count, after one holding the code page; longer
+        // strings have a null length, the next entry holding it
+        let word = |i: usize| {
+            pool.get(i * 2..i * 2 + 2)
+                .map_or(0, |w| u16::from_le_bytes([w[0], w[1]]) as usize)
+        };
+        let string_ref = if word(1) & 0x8000 != 0 { 3 } else { 2 };
+        let mut strings = vec![String::new()];
+        let (mut entry, mut offset) = (1, 0);
+        while entry < pool.len() / 4 {
+            let (len, refs) = (word(entry * 2), word(entry * 2 + 1));
+            let len = match (len, refs) {
+                (0, 0) => {
+                    strings.push(String::new());
+                    entry += 1;
+                    continue;
+                }
+                (0, _) => {
+                    entry += 2;
+                    (word(entry * 2 - 1) << 16) + word(entry * 2 - 2)
+                }
+                (len, _) => {
+                    entry += 1;
+                    len
+                }
+            };
+            strings.push(decode_string(data.get(offset..offset + len).unwrap_or(&[])));
+            offset += len;
+        }
+
+        let mut database = Database {
+            strings,
+            string_ref,
+            columns: HashMap::new(),
+        };
+        let definitions =
+            database.read_table(file, streams, "_Columns", &[TYPE_STRING, 2, TYPE_STRING, 2])?;
+        let mut columns = HashMap::<String, Vec<(i64, String, u16)>>::new();
+        for row in definitions {
+            if let [Some(table), Some(number), Some(name), Some(ty)] = row.as_slice() {
+                let number = number.parse().unwrap_or_default();
+                let ty = ty.parse::<i64>().unwrap_or_default() as u16;
+                columns
+                    .entry(table.clone())
+                    .or_default()
+                    .push((number, name.clone(), ty));
+            }
+        }
+        database.columns = columns
+            .into_iter()
+            .map(|(table, mut columns)| {
+                columns.sort_by_key(|(number, ..)| *number);
+                let columns = columns.into_iter().map(|(_, name, ty)| (name, ty));
+                (table, columns.collect())
+            })
+            .collect();
+        Ok(database)
+    }
+
+    /// Size of the cells of a column, binary columns referring to streams by a short.
+    fn width(&self, ty: u16) -> usize {
+        match ty {
+            ty if ty & !TYPE_NULLABLE == TYPE_STRING | TYPE_VALID => 2,
+            ty if ty & TYPE_STRING != 0 => self.string_ref,
+            ty if ty & 0xff <= 2 => 2,
+            _ => 4,
+        }
+    }
+
+    /// Rows of the table `name` whose columns have the types `types`, integers being formatted.
+    /// The table is stored column by column, null cells being zeroes and integers offset so that
+    /// their most significant bit is set.
+    fn read_table<F: Read + Seek>(
+        &self,
+        file: &mut CompoundFile<F>,
+        streams: &HashMap<String, PathBuf>,
+        name: &str,
+        types: &[u16],
+    ) -> Result<Vec<Vec<Option<String>>>, ArchiveError> {
+        let data = read_stream(file, streams, &format!("{}{}", TABLE_PREFIX, name))?;
+        let widths = types.iter().map(|ty| self.width(*ty)).collect::<Vec<_>>();
+        let row_size = widths.iter().sum::<usize>();
+        let rows = data.len().checked_div(row_size).unwrap_or_default();
+
+        let mut table = vec![Vec::with_capacity(types.len()); rows];
+        let mut column_start = 0;
+        for (ty, width) in types.iter().zip(widths) {
+            for (i, row) in table.iter_mut().enumerate() {
+                let at = column_start + i * width;
+                let value = data[at..at + width]
+                    .iter()
+                    .rev()
+                    .fold(0u32, |value, b| (value << 8) | *b as u32);
+                row.push(match (value, width) {
+                    (0, _) => None,
+                    _ if ty & TYPE_STRING != 0 => self.strings.get(value as usize).cloned(),
+                    (value, 2) => Some((value as i64 - 0x8000).to_string()),
+                    (value, _) => Some((value as i64 - 0x8000_0000).to_string()),
+                });
+            }
+            column_start += width * rows;
+        }
+        Ok(table)
+    }
+
+    /// Rows of the table `name` with the values of the columns `wanted` only, none if the table or
+    /// one of the columns is missing.
+    fn select<F: Read + Seek>(
+        &self,
+        file: &mut CompoundFile<F>,
+        streams: &HashMap<String, PathBuf>,
+        name: &str,
+        wanted: &[&str],
+    ) -> Result<Vec<Vec<Option<String>>>, ArchiveError> {
+        let Some(columns) = self.columns.get(name) else {
+            return Ok(Vec::new());
+        };
+        let Some(indices) = wanted
+            .iter()
+            .map(|w| columns.iter().position(|(name, _)| name == w))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Ok(Vec::new());
+        };
+        let types = columns.iter().map(|(_, ty)| *ty).collect::<Vec<_>>();
+        Ok(self
+            .read_table(file, streams, name, &types)?
+            .into_iter()
+            .map(|row| indices.iter().map(|i| row[*i].clone()).collect())
+            .collect())
+    }
+
+    /// Paths the files are installed at, by their key, relative to the root of the installation.
+    fn file_paths<F: Read + Seek>(
+        &self,
+        file: &mut CompoundFile<F>,
+        streams: &HashMap<String, PathBuf>,
+    ) -> Result<HashMap<String, String>, ArchiveError> {
+        let directories = self
+            .select(
+                file,
+                streams,
+                "Directory",
+                &["Directory", "Directory_Parent", "DefaultDir"],
+            )?
+            .into_iter()
+            .filter_map(|row| match row.as_slice() {
+                [Some(key), parent, Some(name)] => {
+                    Some((key.clone(), (parent.clone(), name.clone())))
+                }
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        let components = self
+            .select(file, streams, "Component", &["Component", "Directory_"])?
+            .into_iter()
+            .filter_map(|row| match row.as_slice() {
+                [Some(key), Some(directory)] => Some((key.clone(), directory.clone())),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+
+        // the directories above `key`, up to the root whose name is the source one
+        let directory_path = |key: &str| {
+            let mut parts = Vec::new();
+            let mut key = key.to_string();
+            while let Some((Some(parent), name)) = directories.get(&key) {
+                if *parent == key || parts.len() > directories.len() {
+                    break;
+                }
+                // names of the target, then source directory, each short then long
+                let target = name.split(':').next().unwrap_or_default();
+                let long = target.rsplit('|').next().unwrap_or_default();
+                if long != "." {
+                    parts.push(long.to_string());
+                }
+                key = parent.clone();
+            }
+            parts.reverse();
+            parts
+        };
+
+        Ok(self
+            .select(file, streams, "File", &["File", "Component_", "FileName"])?
+            .into_iter()
+            .filter_map(|row| match row.as_slice() {
+                [Some(key), Some(component), Some(name)] => {
+                    let mut path = components
+                        .get(component)
+                        .map(|directory| directory_path(directory))
+                        .unwrap_or_default();
+                    path.push(name.rsplit('|').next().unwrap_or_default().to_string());
+                    Some((key.clone(), path.join("/")))
+                }
+                _ => None,
+            })
+            .collect())
+    }
+}
+
+pub struct MsiArchive {
+    pub(crate) source: DataSource,
+    scan: OnceCell<ArchiveScan>,
+}
+
+impl MsiArchive {
+    fn scan(&self) -> Result<&ArchiveScan, ArchiveError> {
+        ArchiveScan::cached(&self.scan, || scan_entries(self, None, None))
+    }
+}
+
+impl EntryVisitor for MsiArchive {
+    fn for_each_entry<F>(
+        &self,
+        options: &VisitOptions<'_>,
+        mut visit: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        let source = options
+            .retry
+            .wrap(self.source.clone(), &self.source, options.events);
+        let mut file = CompoundFile::open(BufReader::with_capacity(DEFAULT_BUF_SIZE, source))?;
+        let streams = streams(&file);
+        // merge modules and damaged databases leave the files named by their key
+        let paths = Database::read(&mut file, &streams)
+            .and_then(|database| database.file_paths(&mut file, &streams))
+            .unwrap_or_default();
+
+        let mut names = streams
+            .iter()
+            .filter(|(name, _)| !name.starts_with(TABLE_PREFIX))
+            .collect::<Vec<_>>();
+        names.sort();
+        for (_, path) in names {
+            let mut stream = file.open_stream(path)?;
+            let mut magic = [0; 4];
+            if stream.read_exact(&mut magic).is_err() || magic != *b"MSCF" {
+                continue;
+            }
+            stream.rewind()?;
+            let mut cabinet = Cabinet::new(BufReader::with_capacity(DEFAULT_BUF_SIZE, stream))?;
+
+            let files = cabinet
+                .folder_entries()
+                .flat_map(|folder| {
+                    folder
+                        .file_entries()
+                        .map(|file| {
+                            let mut entity = CabArchive::entity(file, folder.compression_type());
+                            if let Some(path) = paths.get(file.name()) {
+                                entity.name = path.clone();
+                            }
+                            (file.name().to_string(), entity)
+                        })
+                        .collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>();
+
+            for (key, entity) in files {
+                if !options.wants(&entity) {
+                    continue;
+                }
+                let flow = if options.contents {
+                    let mut reader = cabinet.read_file(&key)?;
+                    visit(VisitedEntry::new(entity), Some(&mut reader))?
+                } else {
+                    visit(VisitedEntry::new(entity), None)?
+                };
+                if flow.is_break() {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Archived for MsiArchive {
+    fn of(source: DataSource) -> Result<Self, ArchiveError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            source,
+            scan: OnceCell::new(),
+        })
+    }
+
+    fn extract(&self, options: ExtractOptions) -> Result<ExtractReport, ArchiveError> {
+        extract_entries(self, &options, self.source.as_ref())
+    }
+
+    fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
+        if options.is_whole() {
+            return Ok(self.scan()?.entries.clone());
+        }
+        list_entries(self, self.scan.get(), &options)
+    }
+
+    fn create(_options: CreateOptions) -> Result<CreateResult, ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "create".to_string(),
+            ArchiveType::Msi,
+        ))
+    }
+
+    fn metadata(&self) -> Result<ArchiveMetadata, ArchiveError> {
+        let scan = self.scan()?;
+        Ok(ArchiveMetadata {
+            archive_type: ArchiveType::Msi,
+            entry_count: scan.entries.len(),
+            entries: scan.entries.clone(),
+            total_size: scan.total_size,
+            compressed_size: scan.compressed_size,
+            compression: None,
+            created: self.source.created(),
+            modified: self.source.modified(),
+            additional: None,
+        })
+    }
+
+    fn open(&self, options: OpenOptions) -> Result<(), ArchiveError> {
+        open_entry(self, options)
+    }
+
+    fn find_first<P>(
+        &self,
+        predicate: P,
+    ) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+    where
+        P: Fn(&ArchiveFileEntity) -> bool,
+    {
+        find_first_entry(self, predicate)
+    }
+
+    fn check_password(&self, _password: &str) -> Result<bool, ArchiveError> {
+        // installers have no encryption
+        Ok(true)
+    }
+
+    fn edit(&self, _entry: &str, _edit: &EntryEdit) -> Result<(), ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "edit".to_string(),
+            ArchiveType::Msi,
+        ))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::io::{Cursor, Write};
+
+    use super::*;
+    use crate::archive::Archive;
+
+    /// Packs `name` as the database does, the inverse of [`decode_stream_name`].
+    fn encode_stream_name(name: &str, table: bool) -> String {
+        let index = |c: u8| STREAM_NAME_CHARS.iter().position(|x| *x == c).unwrap() as u32;
+        let mut encoded = table
+            .then_some(TABLE_PREFIX)
+            .into_iter()
+            .collect::<String>();
+        for pair in name.as_bytes().chunks(2) {
+            let c = match pair {
+                [a, b] => 0x3800 + index(*a) + (index(*b) << 6),
+                [a] => 0x4800 + index(*a),
+                _ => unreachable!(),
+            };
+            encoded.push(char::from_u32(c).unwrap());
+        }
+        encoded
+    }
+
+    /// A database with the tables naming `files`, at (key, directory, long name), and `dirs`, at
+    /// (key, parent, default dir), whose content is in a cabinet.
+    fn installer(dirs: &[(&str, &str, &str)], files: &[(&str, &str, &str, &[u8])]) -> Vec<u8> {
+        let mut strings = vec![String::new()];
+        let mut id = |s: &str| -> u16 {
+            if s.is_empty() {
+                return 0;
+            }
+            match strings.iter().position(|x| x == s) {
+                Some(i) => i as u16,
+                None => {
+                    strings.push(s.to_string());
+                    (strings.len() - 1) as u16
+                }
+            }
+        };
+        // tables of string columns, stored column by column
+        let mut table = |rows: &[Vec<&str>]| -> Vec<u8> {
+            let columns = rows.first().map_or(0, Vec::len);
+            (0..columns)
+                .flat_map(|c| rows.iter().map(|r| r[c]).collect::<Vec<_>>())
+                .flat_map(|s| id(s).to_le_bytes())
+                .collect()
+        };
+        let directory = table(
+            &dirs
+                .iter()
+                .map(|(k, p, n)| vec![*k, *p, *n])
+                .collect::<Vec<_>>(),
+        );
+        let component = table(
+            &files
+                .iter()
+                .map(|(k, d, ..)| vec![*k, *d])
+                .collect::<Vec<_>>(),
+        );
+        let file = table(
+            &files
+                .iter()
+                .map(|(k, _, n, _)| vec![*k, *k, *n])
+                .collect::<Vec<_>>(),
+        );
+        let definitions = [
+            (
+                "Directory",
+                ["Directory", "Directory_Parent", "DefaultDir"].as_slice(),
+            ),
+            ("Component", &["Component", "Directory_"]),
+            ("File", &["File", "Component_", "FileName"]),
+        ]
+        .iter()
+        .flat_map(|(table, columns)| {
+            columns
+                .iter()
+                .enumerate()
+                .map(|(i, column)| (*table, i as u16 + 1, *column))
+        })
+        .collect::<Vec<_>>();
+        let mut columns = Vec::new();
+        for c in 0..4 {
+            for (table, number, name) in &definitions {
+                let value = match c {
+                    0 => id(table),
+                    1 => number + 0x8000,
+                    2 => id(name),
+                    _ => 0x8000 | TYPE_VALID | TYPE_STRING | 72,
+                };
+                columns.extend(value.to_le_bytes());
+            }
+        }
+        let mut pool = vec![0xe4, 0x04, 0, 0];
+        let mut data = Vec::new();
+        for s in &strings[1..] {
+            pool.extend((s.len() as u16).to_le_bytes());
+            pool.extend(1u16.to_le_bytes());
+            data.extend(s.as_bytes());
+        }
+
+        let mut builder = cab::CabinetBuilder::new();
+        let folder = builder.add_folder(cab::CompressionType::MsZip);
+        for (key, ..) in files {
+            folder.add_file(*key);
+        }
+        let mut writer = builder.build(Cursor::new(Vec::new())).unwrap();
+        let mut contents = files.iter().map(|(.., content)| *content);
+        while let Some(mut file) = writer.next_file().unwrap() {
+            file.write_all(contents.next().unwrap()).unwrap();
+        }
+        let cabinet = writer.finish().unwrap().into_inner();
+
+        let mut compound = CompoundFile::create(Cursor::new(Vec::new())).unwrap();
+        for (name, table, content) in [
+            ("_StringPool", true, pool),
+            ("_StringData", true, data),
+            ("_Columns", true, columns),
+            ("Directory", true, directory),
+            ("Component", true, component),
+            ("File", true, file),
+            ("disk1.cab", false, cabinet),
+        ] {
+            let path = format!("/{}", encode_stream_name(name, table));
+            compound
+                .create_stream(path)
+                .unwrap()
+                .write_all(&content)
+                .unwrap();
+        }
+        compound.flush().unwrap();
+        compound.into_inner().into_inner()
+    }
+
+    #[test]
+    fn reads_installers() {
+        let bytes = installer(
+            &[
+                ("TARGETDIR", "", "SourceDir"),
+                ("ProgramFilesFolder", "TARGETDIR", "."),
+                ("APPDIR", "ProgramFilesFolder", "HEZI|Hezi App"),
+                ("DOCS", "APPDIR", "docs"),
+            ],
+            &[
+                ("hezi.exe", "APPDIR", "HEZI.EXE|hezi.exe", b"MZ binary"),
+                ("readme", "DOCS", "README.TXT|readme.txt", b"read me\n"),
+            ],
+        );
+        assert_eq!(bytes[..8], COMPOUND_FILE_MAGIC);
+        let archive = Archive::from_bytes(bytes).unwrap();
+        assert!(matches!(archive, Archive::Msi(_)));
+
+        let names = archive
+            .list(ListOptions::default())
+            .unwrap()
+            .into_iter()
+            .map(|e| (e.name, e.size))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                ("Hezi App/hezi.exe".to_string(), Some(9)),
+                ("Hezi App/docs/readme.txt".to_string(), Some(8)),
+            ]
+        );
+        let (_, content) = archive
+            .find_first(|e| e.name.ends_with("readme.txt"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(content, b"read me\n");
+    }
+
+    #[test]
+    fn decodes_stream_names() {
+        for (name, table) in [("_StringPool", true), ("Data1.cab", false), ("A", false)] {
+            let encoded = encode_stream_name(name, table);
+            let expected = match table {
+                true => format!("{}{}", TABLE_PREFIX, name),
+                false => name.to_string(),
+            };
+            assert_eq!(decode_stream_name(&encoded), expected);
+        }
+    }
+}
@@ -0,0 +1,240 @@
+use std::path::{Path, PathBuf};
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, FixedOffset,
+};
+
+use super::ArchiveError;
+
+const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// Part of a name template such as `backup-{date:%Y%m%d}-{host}.tar.zst`.
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Literal(String),
+    /// `{date}` or `{date:FORMAT}`, formatted with [`chrono`]'s strftime syntax.
+    Date(String),
+    /// `{host}`, the name of the machine.
+    Host,
+}
+
+fn parse(template: &str) -> Result<Vec<Segment>, ArchiveError> {
+    let invalid =
+        |reason: &str| ArchiveError::InvalidNameTemplate(format!("{} in `{}`", reason, template));
+
+    let mut segments = Vec::new();
+    let mut literal = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                literal.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                literal.push('}');
+            }
+            '}' => return Err(invalid("unmatched `}`")),
+            '{' => {
+                let mut placeholder = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) => placeholder.push(c),
+                        None => return Err(invalid("unclosed `{`")),
+                    }
+                }
+
+                let segment = match placeholder.split_once(':') {
+                    None if placeholder == "date" => Segment::Date(DEFAULT_DATE_FORMAT.to_string()),
+                    None if placeholder == "host" => Segment::Host,
+                    Some(("date", format)) => {
+                        if StrftimeItems::new(format).any(|i| matches!(i, Item::Error)) {
+                            return Err(invalid(&format!("invalid date format `{}`", format)));
+                        }
+                        Segment::Date(format.to_string())
+                    }
+                    _ => return Err(invalid(&format!("unknown placeholder `{}`", placeholder))),
+                };
+                if !literal.is_empty() {
+                    segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                }
+                segments.push(segment);
+            }
+            c => literal.push(c),
+        }
+    }
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+    Ok(segments)
+}
+
+/// Expands the placeholders of `template`: `{date}` (or `{date:FORMAT}` with a strftime
+/// format) with `now`, and `{host}` with the name of the machine. `{{` and `}}` stand for
+/// literal braces.
+pub fn expand_name_template(
+    template: &str,
+    now: DateTime<FixedOffset>,
+) -> Result<String, ArchiveError> {
+    Ok(parse(template)?
+        .iter()
+        .map(|segment| match segment {
+            Segment::Literal(l) => l.clone(),
+            Segment::Date(format) => now.format(format).to_string(),
+            Segment::Host => hostname(),
+        })
+        .collect())
+}
+
+fn hostname() -> String {
+    ["HOSTNAME", "COMPUTERNAME"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok())
+        .or_else(|| std::fs::read_to_string("/etc/hostname").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| "localhost".to_string())
+}
+
+/// Whether `name` could have been produced by expanding the file name template `segments`,
+/// placeholders matching any text.
+fn matches(name: &str, segments: &[Segment]) -> bool {
+    // the literal parts, in between placeholders
+    let mut parts = vec![String::new()];
+    for segment in segments {
+        match (segment, parts.last_mut()) {
+            (Segment::Literal(l), Some(part)) => part.push_str(l),
+            _ => parts.push(String::new()),
+        }
+    }
+
+    let (first, last) = (&parts[0], &parts[parts.len() - 1]);
+    if parts.len() == 1 {
+        return name == first;
+    }
+    if name.len() < first.len() + last.len() || !name.starts_with(first) || !name.ends_with(last) {
+        return false;
+    }
+
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for middle in &parts[1..parts.len() - 1] {
+        match rest.find(middle.as_str()) {
+            Some(i) => rest = &rest[i + middle.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Deletes the oldest files matching the name `template`, keeping the `keep` most recently
+/// modified ones, and returns the deleted paths. Placeholders are only supported in the file
+/// name, not in the directories leading to it.
+pub fn rotate_snapshots(template: &str, keep: usize) -> Result<Vec<PathBuf>, ArchiveError> {
+    let template = Path::new(template);
+    let dir = match template.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if parse(&dir.to_string_lossy())?
+        .iter()
+        .any(|s| !matches!(s, Segment::Literal(_)))
+    {
+        return Err(ArchiveError::InvalidNameTemplate(format!(
+            "placeholders are only supported in the file name when rotating, in `{}`",
+            template.display()
+        )));
+    }
+    let segments = parse(
+        &template
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default(),
+    )?;
+
+    let mut snapshots = std::fs::read_dir(dir)?
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_ok_and(|t| t.is_file()))
+        .filter(|e| matches(&e.file_name().to_string_lossy(), &segments))
+        .map(|e| {
+            let modified = e.metadata().and_then(|m| m.modified()).ok();
+            (modified, e.path())
+        })
+        .collect::<Vec<_>>();
+    // oldest first, names break ties as dated names sort chronologically
+    snapshots.sort();
+
+    let excess = snapshots.len().saturating_sub(keep);
+    let mut deleted = Vec::with_capacity(excess);
+    for (_, path) in snapshots.into_iter().take(excess) {
+        std::fs::remove_file(&path)?;
+        deleted.push(path);
+    }
+    Ok(deleted)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    fn now() -> DateTime<FixedOffset> {
+        DateTime::<FixedOffset>::from_str("2024-03-09T14:05:00+01:00").unwrap()
+    }
+
+    #[test]
+    fn expands_placeholders() {
+        assert_eq!(
+            expand_name_template("backup-{date:%Y%m%d}.tar.zst", now()).unwrap(),
+            "backup-20240309.tar.zst"
+        );
+        assert_eq!(
+            expand_name_template("{date}-{{literal}}.zip", now()).unwrap(),
+            "2024-03-09-{literal}.zip"
+        );
+        assert!(!expand_name_template("{host}.zip", now())
+            .unwrap()
+            .starts_with('.'));
+
+        for invalid in ["{nope}.zip", "{date.zip", "a}.zip", "{date:%Q}.zip"] {
+            assert!(matches!(
+                expand_name_template(invalid, now()),
+                Err(ArchiveError::InvalidNameTemplate(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn matches_expanded_names() {
+        let segments = parse("backup-{date:%Y%m%d}-{host}.tar.zst").unwrap();
+        assert!(matches("backup-20240309-box.tar.zst", &segments));
+        assert!(!matches("backup-20240309-box.tar.gz", &segments));
+        assert!(!matches("other-20240309-box.tar.zst", &segments));
+        assert!(matches("plain.zip", &parse("plain.zip").unwrap()));
+    }
+
+    #[test]
+    fn rotation_keeps_newest() {
+        let dir = std::env::temp_dir().join(format!("hezi-rotate-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for day in ["20240301", "20240302", "20240303"] {
+            std::fs::write(dir.join(format!("backup-{}.zip", day)), day).unwrap();
+            // distinct modification times
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        std::fs::write(dir.join("unrelated.zip"), "").unwrap();
+
+        let template = dir.join("backup-{date:%Y%m%d}.zip");
+        let deleted = rotate_snapshots(&template.to_string_lossy(), 2).unwrap();
+
+        assert_eq!(deleted, vec![dir.join("backup-20240301.zip")]);
+        assert!(dir.join("backup-20240303.zip").exists());
+        assert!(dir.join("unrelated.zip").exists());
+        _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,128 @@
+// reports the progress of long operations outside of the terminal output: the percentage is
+// shown in the terminal title and a desktop notification is sent once the operation is over
+
+use std::{
+    cell::Cell,
+    fmt::Display,
+    io::{IsTerminal, Write},
+};
+
+use notify_rust::Notification;
+
+use super::{ArchiveEvent, EventHandler};
+
+/// Event handler updating the terminal title with the progress of an operation and sending a
+/// desktop notification when it completes, before forwarding the events to `inner`. Call
+/// [`ProgressNotifier::failed`] if the operation fails.
+pub struct ProgressNotifier<'a> {
+    inner: Box<dyn EventHandler + 'a>,
+    operation: String,
+    /// Total size of the entries processed by the operation, if known.
+    total: Option<u64>,
+    processed: Cell<u64>,
+    percent: Cell<Option<u64>>,
+    title: bool,
+}
+
+impl<'a> ProgressNotifier<'a> {
+    pub fn new<S: Into<String>>(
+        operation: S,
+        total: Option<u64>,
+        inner: Box<dyn EventHandler + 'a>,
+    ) -> Self {
+        Self {
+            inner,
+            operation: operation.into(),
+            total,
+            processed: Cell::new(0),
+            percent: Cell::new(None),
+            title: std::io::stderr().is_terminal(),
+        }
+    }
+
+    /// Progress of the operation in percent, if its total size is known.
+    pub fn percent(&self) -> Option<u64> {
+        self.percent.get()
+    }
+
+    /// Reports that the operation failed with `error`.
+    pub fn failed<E: Display>(&self, error: E) {
+        self.set_title(&format!("failed {}", self.operation));
+        self.notify(&format!("Failed {}", self.operation), &error.to_string());
+    }
+
+    fn progress(&self, size: Option<u64>) {
+        self.processed
+            .set(self.processed.get() + size.unwrap_or_default());
+        let Some(total) = self.total.filter(|t| *t > 0) else {
+            return;
+        };
+
+        let percent = (self.processed.get() * 100 / total).min(100);
+        if self.percent.replace(Some(percent)) != Some(percent) {
+            self.set_title(&format!("{}% {}", percent, self.operation));
+        }
+    }
+
+    fn done(&self, body: &str) {
+        self.set_title(&format!("done {}", self.operation));
+        self.notify(&format!("Done {}", self.operation), body);
+    }
+
+    fn set_title(&self, title: &str) {
+        if self.title {
+            let mut stderr = std::io::stderr();
+            _ = write!(stderr, "\x1b]0;hezi: {}\x07", title);
+            _ = stderr.flush();
+        }
+    }
+
+    fn notify(&self, summary: &str, body: &str) {
+        if let Err(e) = Notification::new()
+            .appname("hezi")
+            .summary(summary)
+            .body(body)
+            .show()
+        {
+            log::warn!("could not send desktop notification: {}", e);
+        }
+    }
+}
+
+impl EventHandler for ProgressNotifier<'_> {
+    fn handle(&self, event: ArchiveEvent) {
+        match &event {
+            ArchiveEvent::Extracting(_, size) | ArchiveEvent::Added(_, size) => {
+                self.progress(*size)
+            }
+            ArchiveEvent::DoneExtracting(_, destination) => {
+                self.done(&format!("Extracted to {}", destination))
+            }
+            ArchiveEvent::DoneCreating(destination) => {
+                self.done(&format!("Created {}", destination))
+            }
+            _ => {}
+        }
+        self.inner.handle(event);
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::archive::SimpleLogger;
+
+    #[test]
+    fn tracks_percent() {
+        let notifier = ProgressNotifier::new("extracting test", Some(200), Box::new(SimpleLogger));
+        assert_eq!(notifier.percent(), None);
+
+        notifier.handle(ArchiveEvent::Extracting("a".to_string(), Some(50)));
+        assert_eq!(notifier.percent(), Some(25));
+        notifier.handle(ArchiveEvent::Extracting("dir".to_string(), None));
+        assert_eq!(notifier.percent(), Some(25));
+        notifier.handle(ArchiveEvent::Added("b".to_string(), Some(500)));
+        assert_eq!(notifier.percent(), Some(100));
+    }
+}
@@ -0,0 +1,746 @@
+// read-only support for the payload of Nullsoft installers, executables followed at a boundary of
+// 512 bytes by a first header, then by a header holding the script of the installer and by the
+// blocks of the files it extracts. The blocks are either compressed one by one, each behind its
+// size, or together with the header as a single solid stream. The files are named by the
+// instructions of the script which extract them, relative to the directory last set as output.
+
+use std::{
+    cell::OnceCell,
+    collections::HashSet,
+    io::{BufReader, ErrorKind, Read, Seek, SeekFrom},
+    ops::ControlFlow,
+};
+
+use crate::archive::{
+    datetime_from_timestamp,
+    entry_visitor::{
+        extract_entries, find_first_entry, list_entries, open_entry, scan_entries, EntryVisitor,
+        VisitOptions, VisitedEntry,
+    },
+    ArchiveError, ArchiveFileEntity, ArchiveFileEntityType, ArchiveMetadata, ArchiveScan,
+    ArchiveType, Archived, CreateOptions, CreateResult, DataSource, ExtractOptions, ExtractReport,
+    ListOptions, OpenOptions, DEFAULT_BUF_SIZE,
+};
+
+use super::edit::EntryEdit;
+
+/// Magic following the flags of the first header.
+const SIGNATURE: &[u8; 16] = b"\xef\xbe\xad\xdeNullsoftInst";
+const FIRST_HEADER_SIZE: u64 = 28;
+/// The first header lies at a multiple of this, past the executable.
+const ALIGNMENT: usize = 512;
+const BLOCK_ENTRIES: usize = 2;
+const BLOCK_STRINGS: usize = 3;
+const ENTRY_SIZE: usize = 28;
+const EW_CREATEDIR: u32 = 11;
+const EW_EXTRACTFILE: u32 = 20;
+/// Blocks compressed on their own have this bit set in their size.
+const COMPRESSED_BLOCK: u32 = 0x8000_0000;
+/// Seconds between the Windows epoch, 1601-01-01, and the Unix one.
+const WINDOWS_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+/// Names of the variables of the scripts, from `$0`.
+const VARIABLES: [&str; 32] = [
+    "$0",
+    "$1",
+    "$2",
+    "$3",
+    "$4",
+    "$5",
+    "$6",
+    "$7",
+    "$8",
+    "$9",
+    "$R0",
+    "$R1",
+    "$R2",
+    "$R3",
+    "$R4",
+    "$R5",
+    "$R6",
+    "$R7",
+    "$R8",
+    "$R9",
+    "$CMDLINE",
+    "$INSTDIR",
+    "$OUTDIR",
+    "$EXEDIR",
+    "$LANGUAGE",
+    "$TEMP",
+    "$PLUGINSDIR",
+    "$EXEPATH",
+    "$EXEFILE",
+    "$HWNDPARENT",
+    "$_CLICK",
+    "$_OUTDIR",
+];
+
+fn invalid_data(message: String) -> ArchiveError {
+    ArchiveError::Io(std::io::Error::new(ErrorKind::InvalidData, message))
+}
+
+fn u32_at(data: &[u8], at: usize) -> Result<u32, ArchiveError> {
+    at.checked_add(4)
+        .and_then(|end| data.get(at..end))
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| invalid_data("truncated NSIS header".to_string()))
+}
+
+/// Offset of the first header in `reader`, if it is an installer.
+fn find_first_header<R: Read>(reader: &mut R) -> Result<Option<u64>, ArchiveError> {
+    let mut chunk = vec![0; ALIGNMENT * 128];
+    let mut offset = 0;
+    loop {
+        let mut len = 0;
+        while len < chunk.len() {
+            match reader.read(&mut chunk[len..])? {
+                0 => break,
+                n => len += n,
+            }
+        }
+        let found = chunk[..len]
+            .chunks(ALIGNMENT)
+            .position(|block| block.get(4..20) == Some(SIGNATURE));
+        if let Some(i) = found {
+            return Ok(Some(offset + (i * ALIGNMENT) as u64));
+        }
+        if len < chunk.len() {
+            return Ok(None);
+        }
+        offset += len as u64;
+    }
+}
+
+/// Whether `reader` holds the payload of a Nullsoft installer.
+pub(crate) fn is_installer<R: Read>(mut reader: R) -> bool {
+    find_first_header(&mut reader).is_ok_and(|offset| offset.is_some())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Method {
+    Stored,
+    Deflate,
+    Lzma([u8; 5]),
+    Bzip2,
+}
+
+impl Method {
+    fn name(&self) -> &'static str {
+        match self {
+            Method::Stored => "stored",
+            Method::Deflate => "deflate",
+            Method::Lzma(_) => "lzma",
+            Method::Bzip2 => "bzip2",
+        }
+    }
+
+    /// The method of the stream starting with `data`, told by its properties.
+    fn of(data: &[u8]) -> Self {
+        match data {
+            // literal context bits 3 and position bits 2, then a dictionary size below 16 MiB and
+            // the first byte of the range coder
+            [0x5d, props @ .., 0] if props[..2] == [0, 0] => {
+                Method::Lzma([0x5d, props[0], props[1], props[2], props[3]])
+            }
+            // the magic of a block, the stream header being left out
+            [0x31, 0x41, 0x59, 0x26, 0x53, 0x59] => Method::Bzip2,
+            _ => Method::Deflate,
+        }
+    }
+
+    /// Reader of the content of a stream compressed with this method, from its `data`.
+    fn reader<'r, R: Read + 'r>(&self, data: R) -> Result<Box<dyn Read + 'r>, ArchiveError> {
+        match self {
+            Method::Stored => Ok(Box::new(data)),
+            Method::Deflate => Ok(Box::new(flate2::read::DeflateDecoder::new(data))),
+            #[cfg(feature = "lzma_codecs")]
+            Method::Lzma(props) => {
+                let mut filters = liblzma::stream::Filters::new();
+                filters.lzma1_properties(props)?;
+                let stream = liblzma::stream::Stream::new_raw_decoder(&filters)?;
+                let mut data = data;
+                data.read_exact(&mut [0; 5])?;
+                Ok(Box::new(UnmarkedLzma(
+                    liblzma::read::XzDecoder::new_stream(data, stream),
+                )))
+            }
+            _ => Err(ArchiveError::Io(std::io::Error::new(
+                ErrorKind::Unsupported,
+                format!("{} compressed installers are not supported", self.name()),
+            ))),
+        }
+    }
+}
+
+/// Reader of a raw LZMA stream, which installers may end without a marker, liblzma then
+/// reporting the end of the data as premature.
+#[cfg(feature = "lzma_codecs")]
+struct UnmarkedLzma<R>(R);
+
+#[cfg(feature = "lzma_codecs")]
+impl<R: Read> Read for UnmarkedLzma<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.0.read(buf) {
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(0),
+            result => result,
+        }
+    }
+}
+
+/// The strings of the script, in which variables, shell folders and localized strings are
+/// referred to by codes.
+struct Strings<'h> {
+    data: &'h [u8],
+    unicode: bool,
+    /// Codes of NSIS 3, from 1, rather than those of NSIS 2, from 252.
+    nsis3: bool,
+}
+
+impl<'h> Strings<'h> {
+    fn new(data: &'h [u8]) -> Self {
+        // the first string is the empty one, whose terminator is two bytes long in Unicode
+        let unicode = data.starts_with(&[0, 0]);
+        let nsis3 = unicode || data.iter().any(|b| (1..=4).contains(b));
+        Self {
+            data,
+            unicode,
+            nsis3,
+        }
+    }
+
+    fn chars(&self, offset: usize) -> Vec<u16> {
+        let data = self
+            .data
+            .get(offset * (1 + self.unicode as usize)..)
+            .unwrap_or_default();
+        let chars: Box<dyn Iterator<Item = u16>> = match self.unicode {
+            true => Box::new(
+                data.chunks_exact(2)
+                    .map(|c| u16::from_le_bytes([c[0], c[1]])),
+            ),
+            false => Box::new(data.iter().map(|b| *b as u16)),
+        };
+        chars.take_while(|c| *c != 0).collect()
+    }
+
+    fn get(&self, offset: u32) -> String {
+        let (lang, shell, var, skip) = match self.nsis3 {
+            true => (1, 2, 3, 4),
+            false => (255, 254, 253, 252),
+        };
+        let chars = self.chars(offset as usize);
+        let mut string = String::new();
+        let mut chars = chars.into_iter();
+        while let Some(c) = chars.next() {
+            if ![lang, shell, var, skip].contains(&c) {
+                string.push(match self.unicode {
+                    true => char::from_u32(c as u32).unwrap_or(char::REPLACEMENT_CHARACTER),
+                    false => c as u8 as char,
+                });
+                continue;
+            }
+            if c == skip {
+                // the next character is a literal one
+                match (self.unicode, chars.next()) {
+                    (true, Some(c)) => string.push(char::from_u32(c as u32).unwrap_or('?')),
+                    (false, Some(c)) => string.push(c as u8 as char),
+                    (_, None) => {}
+                }
+                continue;
+            }
+            // the parameter of a code takes a character in Unicode, else two bytes
+            let param = match self.unicode {
+                true => chars.next().unwrap_or_default(),
+                false => chars.next().unwrap_or_default() | chars.next().unwrap_or_default() << 8,
+            };
+            let index = match self.unicode {
+                true => param & 0x7fff,
+                false => ((param >> 8) & 0x7f) << 7 | (param & 0x7f),
+            } as usize;
+            match c {
+                _ if c == var => match VARIABLES.get(index) {
+                    Some(name) => string.push_str(name),
+                    None => string.push_str(&format!("$_{}_", index)),
+                },
+                _ if c == shell => string.push_str(&self.shell_folder(param)),
+                _ => string.push_str(&format!("$(LSTR_{})", index)),
+            }
+        }
+        string
+    }
+
+    /// Name of a shell folder, given by its CSIDL or by the registry value holding it.
+    fn shell_folder(&self, param: u16) -> String {
+        let (csidl, registry) = (param & 0xff, param >> 8);
+        if registry & 0x80 != 0 {
+            let value = self.chars((registry & 0x3f) as usize);
+            return match String::from_utf16_lossy(&value).as_str() {
+                "CommonFilesDir" => "$COMMONFILES",
+                _ => "$PROGRAMFILES",
+            }
+            .to_string();
+        }
+        match csidl & 0x3f {
+            0x02 => "$SMPROGRAMS",
+            0x05 => "$DOCUMENTS",
+            0x07 => "$SMSTARTUP",
+            0x0b => "$STARTMENU",
+            0x10 => "$DESKTOP",
+            0x14 => "$FONTS",
+            0x1a => "$APPDATA",
+            0x1c => "$LOCALAPPDATA",
+            0x24 => "$WINDIR",
+            0x25 => "$SYSDIR",
+            0x26 => "$PROGRAMFILES",
+            0x2b => "$COMMONFILES",
+            csidl => return format!("$SHELL_{:#x}", csidl),
+        }
+        .to_string()
+    }
+}
+
+/// A file the script extracts, at `offset` past the header.
+#[derive(Debug)]
+struct Item {
+    name: String,
+    offset: u32,
+    modified: Option<u64>,
+}
+
+/// The files extracted by the script of `header`, in the order of their data.
+fn read_items(header: &[u8]) -> Result<Vec<Item>, ArchiveError> {
+    let block = |i: usize| -> Result<(usize, usize), ArchiveError> {
+        Ok((
+            u32_at(header, 4 + i * 8)? as usize,
+            u32_at(header, 8 + i * 8)? as usize,
+        ))
+    };
+    let (entries, count) = block(BLOCK_ENTRIES)?;
+    let (strings, _) = block(BLOCK_STRINGS)?;
+    let strings = Strings::new(
+        header
+            .get(strings..)
+            .ok_or_else(|| invalid_data("truncated NSIS header".to_string()))?,
+    );
+
+    let mut output = "$INSTDIR".to_string();
+    let mut items = Vec::new();
+    let mut offsets = HashSet::new();
+    for i in 0..count {
+        let at = entries + i * ENTRY_SIZE;
+        let param = |p: usize| u32_at(header, at + 4 + p * 4);
+        match u32_at(header, at)? {
+            // `SetOutPath` creates the directory and extracts the next files in it
+            EW_CREATEDIR if param(1)? != 0 => {
+                output = strings.get(param(0)?).replace("$OUTDIR", &output);
+            }
+            EW_EXTRACTFILE => {
+                let name = strings.get(param(1)?).replace("$OUTDIR", &output);
+                let name = match name.starts_with('$') || name.contains(':') {
+                    true => name,
+                    false => format!("{}\\{}", output, name),
+                };
+                let offset = param(2)?;
+                // files extracted twice share their data
+                if !offsets.insert(offset) {
+                    continue;
+                }
+                let filetime = (param(4)? as u64) << 32 | param(3)? as u64;
+                items.push(Item {
+                    name: name.replace('\\', "/"),
+                    offset,
+                    modified: (filetime != 0 && filetime != u64::MAX).then_some(filetime),
+                });
+            }
+            _ => {}
+        }
+    }
+    items.sort_by_key(|item| item.offset);
+    Ok(items)
+}
+
+impl Item {
+    fn entity(
+        &self,
+        size: Option<u64>,
+        compressed_size: Option<u64>,
+        method: Method,
+    ) -> ArchiveFileEntity {
+        let seconds = self
+            .modified
+            .map(|t| (t / 10_000_000) as i64 - WINDOWS_EPOCH_OFFSET);
+        ArchiveFileEntity {
+            name: self.name.clone(),
+            size,
+            compressed_size,
+            last_modified: seconds.and_then(|s| datetime_from_timestamp(s).ok()),
+            compression: Some(method.name().to_string()),
+            fstype: ArchiveFileEntityType::File,
+            raw: None,
+            unix: None,
+        }
+    }
+}
+
+pub struct NsisArchive {
+    pub(crate) source: DataSource,
+    scan: OnceCell<ArchiveScan>,
+}
+
+impl NsisArchive {
+    fn scan(&self) -> Result<&ArchiveScan, ArchiveError> {
+        ArchiveScan::cached(&self.scan, || scan_entries(self, None, None))
+    }
+}
+
+impl EntryVisitor for NsisArchive {
+    fn for_each_entry<F>(
+        &self,
+        options: &VisitOptions<'_>,
+        mut visit: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        let source = options
+            .retry
+            .wrap(self.source.clone(), &self.source, options.events);
+        let mut reader = BufReader::with_capacity(DEFAULT_BUF_SIZE, source);
+        let offset = find_first_header(&mut reader)?
+            .ok_or_else(|| invalid_data("no Nullsoft installer data found".to_string()))?;
+
+        let mut first_header = [0; FIRST_HEADER_SIZE as usize];
+        reader.seek(SeekFrom::Start(offset))?;
+        reader.read_exact(&mut first_header)?;
+        let header_size = u32_at(&first_header, 20)? as u64;
+        let data_size = (u32_at(&first_header, 24)? as u64).saturating_sub(FIRST_HEADER_SIZE);
+        let mut signature = [0; 6];
+        reader.read_exact(&mut signature)?;
+        reader.seek(SeekFrom::Start(offset + FIRST_HEADER_SIZE))?;
+
+        // the size of a header stored as is, the properties of a solid LZMA stream, or the size
+        // of a compressed header, whose most significant byte is only its flag
+        let block_size =
+            u32::from_le_bytes([signature[0], signature[1], signature[2], signature[3]]);
+        let (solid, method) = match Method::of(&signature) {
+            _ if block_size as u64 == header_size => (false, Method::Stored),
+            Method::Lzma(props) => (true, Method::Lzma(props)),
+            _ if block_size >> 24 == 0x80 => {
+                reader.read_exact(&mut [0; 4])?;
+                reader.read_exact(&mut signature)?;
+                reader.seek(SeekFrom::Start(offset + FIRST_HEADER_SIZE))?;
+                (false, Method::of(&signature))
+            }
+            method => (true, method),
+        };
+
+        if solid {
+            // the header then the blocks, each behind its size, in a single stream
+            let mut stream = method.reader((&mut reader).take(data_size))?;
+            let mut size = [0; 4];
+            stream.read_exact(&mut size)?;
+            let mut header = Vec::new();
+            (&mut stream)
+                .take(u32::from_le_bytes(size) as u64)
+                .read_to_end(&mut header)?;
+            let mut position = 0;
+            for item in read_items(&header)? {
+                std::io::copy(
+                    &mut (&mut stream).take((item.offset as u64).saturating_sub(position)),
+                    &mut std::io::sink(),
+                )?;
+                stream.read_exact(&mut size)?;
+                let size = u32::from_le_bytes(size) as u64;
+                let mut data = (&mut stream).take(size);
+                let entity = item.entity(Some(size), None, method);
+
+                let flow = if !options.wants(&entity) {
+                    ControlFlow::Continue(())
+                } else if options.contents {
+                    visit(VisitedEntry::new(entity), Some(&mut data))?
+                } else {
+                    visit(VisitedEntry::new(entity), None)?
+                };
+                std::io::copy(&mut data, &mut std::io::sink())?;
+                position = item.offset as u64 + 4 + size;
+                if flow.is_break() {
+                    break;
+                }
+            }
+            return Ok(());
+        }
+
+        // the header then the blocks, each behind its size and compressed on its own
+        let mut size = [0; 4];
+        reader.read_exact(&mut size)?;
+        let size = u32::from_le_bytes(size);
+        let blocks = offset + FIRST_HEADER_SIZE + 4 + (size & !COMPRESSED_BLOCK) as u64;
+        let mut header = Vec::new();
+        let data = (&mut reader).take((size & !COMPRESSED_BLOCK) as u64);
+        match size & COMPRESSED_BLOCK {
+            0 => Method::Stored.reader(data)?,
+            _ => method.reader(data)?,
+        }
+        .take(header_size)
+        .read_to_end(&mut header)?;
+
+        for item in read_items(&header)? {
+            reader.seek(SeekFrom::Start(blocks + item.offset as u64))?;
+            let mut size = [0; 4];
+            reader.read_exact(&mut size)?;
+            let size = u32::from_le_bytes(size);
+            let compressed_size = (size & !COMPRESSED_BLOCK) as u64;
+            let (method, entity) = match size & COMPRESSED_BLOCK {
+                0 => (
+                    Method::Stored,
+                    item.entity(Some(compressed_size), Some(compressed_size), Method::Stored),
+                ),
+                _ => (method, item.entity(None, Some(compressed_size), method)),
+            };
+
+            let flow = if !options.wants(&entity) {
+                ControlFlow::Continue(())
+            } else if options.contents {
+                let mut content = method.reader((&mut reader).take(compressed_size))?;
+                visit(VisitedEntry::new(entity), Some(&mut content))?
+            } else {
+                visit(VisitedEntry::new(entity), None)?
+            };
+            if flow.is_break() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Archived for NsisArchive {
+    fn of(source: DataSource) -> Result<Self, ArchiveError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            source,
+            scan: OnceCell::new(),
+        })
+    }
+
+    fn extract(&self, options: ExtractOptions) -> Result<ExtractReport, ArchiveError> {
+        extract_entries(self, &options, self.source.as_ref())
+    }
+
+    fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
+        if options.is_whole() {
+            return Ok(self.scan()?.entries.clone());
+        }
+        list_entries(self, self.scan.get(), &options)
+    }
+
+    fn create(_options: CreateOptions) -> Result<CreateResult, ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "create".to_string(),
+            ArchiveType::Nsis,
+        ))
+    }
+
+    fn metadata(&self) -> Result<ArchiveMetadata, ArchiveError> {
+        let scan = self.scan()?;
+        Ok(ArchiveMetadata {
+            archive_type: ArchiveType::Nsis,
+            entry_count: scan.entries.len(),
+            entries: scan.entries.clone(),
+            total_size: scan.total_size,
+            compressed_size: scan.compressed_size,
+            compression: None,
+            created: self.source.created(),
+            modified: self.source.modified(),
+            additional: None,
+        })
+    }
+
+    fn open(&self, options: OpenOptions) -> Result<(), ArchiveError> {
+        open_entry(self, options)
+    }
+
+    fn find_first<P>(
+        &self,
+        predicate: P,
+    ) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+    where
+        P: Fn(&ArchiveFileEntity) -> bool,
+    {
+        find_first_entry(self, predicate)
+    }
+
+    fn check_password(&self, _password: &str) -> Result<bool, ArchiveError> {
+        // installers have no encryption
+        Ok(true)
+    }
+
+    fn edit(&self, _entry: &str, _edit: &EntryEdit) -> Result<(), ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "edit".to_string(),
+            ArchiveType::Nsis,
+        ))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+    use crate::archive::Archive;
+
+    const FILES: [(&str, &[u8]); 2] = [("file1.txt", b"first file\n"), ("file2.txt", b"second\n")];
+
+    /// The header of a script extracting `FILES` to `$INSTDIR` and `$INSTDIR\dir1`, whose data
+    /// is at `offsets`.
+    fn header(offsets: [u32; 2]) -> Vec<u8> {
+        // a variable is a code then its index over two bytes of 7 bits
+        let instdir = [3, 21 | 0x80, 0x80];
+        let mut strings = vec![0];
+        let mut add = |parts: &[&[u8]]| {
+            let offset = strings.len() as u32;
+            strings.extend(parts.concat());
+            strings.push(0);
+            offset
+        };
+        let root = add(&[&instdir]);
+        let dir = add(&[&instdir, b"\\dir1"]);
+        let names = FILES.map(|(name, _)| add(&[name.as_bytes()]));
+        let entries = [
+            [EW_CREATEDIR, root, 1, 0, 0, 0, 0],
+            [
+                EW_EXTRACTFILE,
+                0,
+                names[0],
+                offsets[0],
+                0xd53e_8000,
+                0x019d_b1de,
+                0,
+            ],
+            [EW_CREATEDIR, dir, 1, 0, 0, 0, 0],
+            [EW_EXTRACTFILE, 0, names[1], offsets[1], 0, 0, 0],
+        ];
+
+        let entries_offset = 4 + 8 * 8;
+        let strings_offset = entries_offset + entries.len() * ENTRY_SIZE;
+        let mut blocks = [(0, 0); 8];
+        blocks[BLOCK_ENTRIES] = (entries_offset, entries.len());
+        blocks[BLOCK_STRINGS] = (strings_offset, strings.len());
+        let mut header = vec![0; 4];
+        for (offset, num) in blocks {
+            header.extend((offset as u32).to_le_bytes());
+            header.extend((num as u32).to_le_bytes());
+        }
+        header.extend(entries.iter().flatten().flat_map(|p| p.to_le_bytes()));
+        header.extend(strings);
+        header
+    }
+
+    /// An executable stub followed by the first header and by `data`.
+    fn installer(header_size: usize, data: &[u8]) -> Vec<u8> {
+        let mut bytes = b"MZ".to_vec();
+        bytes.resize(ALIGNMENT * 2, 0);
+        bytes.extend(0u32.to_le_bytes());
+        bytes.extend(SIGNATURE);
+        bytes.extend((header_size as u32).to_le_bytes());
+        bytes.extend((FIRST_HEADER_SIZE as u32 + data.len() as u32).to_le_bytes());
+        bytes.extend(data);
+        bytes
+    }
+
+    fn deflate(data: &[u8]) -> Vec<u8> {
+        let mut encoder =
+            flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let mut block = (compressed.len() as u32 | COMPRESSED_BLOCK)
+            .to_le_bytes()
+            .to_vec();
+        block.extend(compressed);
+        block
+    }
+
+    fn assert_extracts_files(bytes: Vec<u8>, sizes: [Option<u64>; 2]) {
+        let archive = Archive::from_bytes(bytes).unwrap();
+        assert!(matches!(archive, Archive::Nsis(_)));
+        let entries = archive.list(ListOptions::default()).unwrap();
+        let names = entries
+            .iter()
+            .map(|e| (e.name.as_str(), e.size))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                ("$INSTDIR/file1.txt", sizes[0]),
+                ("$INSTDIR/dir1/file2.txt", sizes[1]),
+            ]
+        );
+        assert!(entries[0].last_modified.is_some());
+        assert!(entries[1].last_modified.is_none());
+        for (name, content) in FILES {
+            let (_, data) = archive
+                .find_first(|e| e.name.ends_with(name))
+                .unwrap()
+                .unwrap();
+            assert_eq!(data, content);
+        }
+    }
+
+    #[test]
+    fn reads_installers_compressed_block_by_block() {
+        let files = FILES.map(|(_, content)| deflate(content));
+        let header = header([0, files[0].len() as u32]);
+        let mut data = deflate(&header);
+        data.extend(files.concat());
+        assert_extracts_files(installer(header.len(), &data), [None, None]);
+    }
+
+    #[test]
+    fn reads_stored_installers() {
+        let header = header([0, 4 + FILES[0].1.len() as u32]);
+        let mut data = (header.len() as u32).to_le_bytes().to_vec();
+        data.extend(&header);
+        for (_, content) in FILES {
+            data.extend((content.len() as u32).to_le_bytes());
+            data.extend(content);
+        }
+        let sizes = FILES.map(|(_, content)| Some(content.len() as u64));
+        assert_extracts_files(installer(header.len(), &data), sizes);
+    }
+
+    #[cfg(feature = "lzma_codecs")]
+    #[test]
+    fn reads_solid_installers() {
+        let header = header([0, 4 + FILES[0].1.len() as u32]);
+        let mut stream = (header.len() as u32).to_le_bytes().to_vec();
+        stream.extend(&header);
+        for (_, content) in FILES {
+            stream.extend((content.len() as u32).to_le_bytes());
+            stream.extend(content);
+        }
+
+        let options = liblzma::stream::LzmaOptions::new_preset(6).unwrap();
+        let mut filters = liblzma::stream::Filters::new();
+        filters.lzma1(&options);
+        let encoder = liblzma::stream::Stream::new_raw_encoder(&filters).unwrap();
+        let mut compressed = liblzma::write::XzEncoder::new_stream(Vec::new(), encoder);
+        compressed.write_all(&stream).unwrap();
+        // literal context bits 3, position bits 2 and a dictionary of 8 MiB
+        let mut data = vec![0x5d, 0, 0, 0x80, 0];
+        data.extend(compressed.finish().unwrap());
+
+        let sizes = FILES.map(|(_, content)| Some(content.len() as u64));
+        assert_extracts_files(installer(header.len(), &data), sizes);
+    }
+
+    #[test]
+    fn finds_no_installer_in_other_executables() {
+        let mut bytes = b"MZ".to_vec();
+        bytes.resize(ALIGNMENT * 300, 0);
+        assert!(!is_installer(bytes.as_slice()));
+    }
+}
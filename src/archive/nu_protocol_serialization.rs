@@ -2,44 +2,27 @@ use nu_protocol::{CustomValue, FromValue, Record, ShellError, Span, Value};
 use num::traits::AsPrimitive;
 use strum::IntoEnumIterator;
 
-use crate::archive::{ArchiveFileEntity, ArchiveMetadata};
+use crate::{
+    archive::{ArchiveFileEntity, ArchiveMetadata},
+    output::{self, Versioned},
+};
 
-use super::{ArchiveCompression, ArchiveError, DataSource};
+use super::{suggest::closest_matches, ArchiveCompression, ArchiveError, DataSource, GzipStrategy};
 
+#[typetag::serde]
 impl CustomValue for ArchiveMetadata {
     fn clone_value(&self, span: Span) -> Value {
         Value::custom(Box::new(self.clone()), span)
     }
 
     fn to_base_value(&self, span: Span) -> Result<Value, ShellError> {
-        let json_value =
-            serde_json::to_value(self.clone()).map_err(|e| ShellError::CantConvert {
-                from_type: "ArchiveMetadata".to_string(),
-                to_type: "JsonValue".to_string(),
-                span,
-                help: Some(e.to_string()),
-            })?;
-
-        let nu_value =
-            json_value_to_nu_value(json_value, span).map_err(|e| e.into_shell_error(span))?;
-
-        Ok(nu_value)
+        to_versioned_value(output::Metadata::from(self), span)
     }
 
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
 
-    #[doc(hidden)]
-    fn typetag_name(&self) -> &'static str {
-        "ArchiveMetadata"
-    }
-
-    #[doc(hidden)]
-    fn typetag_deserialize(&self) {
-        unimplemented!()
-    }
-
     #[doc = r" The friendly type name to show for the custom value, e.g. in `describe` and in error"]
     #[doc = r" messages. This does not have to be the same as the name of the struct or enum, but"]
     #[doc = r" conventionally often is."]
@@ -71,6 +54,19 @@ impl JsonToNushellValueError {
     }
 }
 
+/// Value of the [`output`] document `data`, with its `schema_version`, as the plugin returns it.
+pub fn to_versioned_value<T: serde::Serialize>(data: T, span: Span) -> Result<Value, ShellError> {
+    let json_value =
+        serde_json::to_value(Versioned::new(data)).map_err(|e| ShellError::CantConvert {
+            from_type: std::any::type_name::<T>().to_string(),
+            to_type: "JsonValue".to_string(),
+            span,
+            help: Some(e.to_string()),
+        })?;
+
+    json_value_to_nu_value(json_value, span).map_err(|e| e.into_shell_error(span))
+}
+
 fn json_value_to_nu_value(
     value: serde_json::Value,
     span: Span,
@@ -99,52 +95,63 @@ fn json_value_to_nu_value(
     }
 }
 
+#[typetag::serde]
 impl CustomValue for ArchiveFileEntity {
     fn clone_value(&self, span: Span) -> Value {
         Value::custom(Box::new(self.clone()), span)
     }
 
     fn to_base_value(&self, span: Span) -> Result<Value, ShellError> {
-        Ok(Value::record(
-            Record::from_raw_cols_vals(
-                vec![
-                    "name".to_string(),
-                    "size".to_string(),
-                    "compressed_size".to_string(),
-                    "type".to_string(),
-                    "last_modified".to_string(),
-                    "compression".to_string(),
-                ],
-                vec![
-                    Value::String {
-                        val: self.name.clone(),
-                        internal_span: span,
-                    },
-                    self.size.to_filesize_value(span),
-                    self.compressed_size.to_filesize_value(span),
-                    Value::String {
-                        val: self.fstype.to_string(),
-                        internal_span: span,
-                    },
-                    self.last_modified.to_date_value(span),
-                    self.compression.to_string_value(span),
-                ],
-                span,
-                span,
-            )?,
+        let mut record = Record::from_raw_cols_vals(
+            vec![
+                "name".to_string(),
+                "size".to_string(),
+                "compressed_size".to_string(),
+                "type".to_string(),
+                "last_modified".to_string(),
+                "compression".to_string(),
+                "ratio".to_string(),
+            ],
+            vec![
+                Value::String {
+                    val: self.name.clone(),
+                    internal_span: span,
+                },
+                self.size.to_filesize_value(span),
+                self.compressed_size.to_filesize_value(span),
+                Value::String {
+                    val: self.fstype.to_string(),
+                    internal_span: span,
+                },
+                self.last_modified.to_date_value(span),
+                self.compression.to_string_value(span),
+                self.ratio()
+                    .to_value_or_nothing(span, |r| Value::string(format!("{:.1}%", r), span)),
+            ],
             span,
-        ))
+            span,
+        )?;
+        // only the formats locating their entries have the column
+        if let Some(raw) = &self.raw {
+            record.push(
+                "raw",
+                Value::record(
+                    nu_protocol::record! {
+                        "header_offset" => Value::int(raw.header_offset as i64, span),
+                        "data_offset" => Value::int(raw.data_offset as i64, span),
+                        "flags" => Value::int(raw.flags as i64, span),
+                    },
+                    span,
+                ),
+            );
+        }
+        Ok(Value::record(record, span))
     }
 
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
 
-    #[doc(hidden)]
-    fn typetag_name(&self) -> &'static str {
-        "ArchiveFileEntity"
-    }
-
     fn type_name(&self) -> String {
         "ArchiveFileEntity".to_string()
     }
@@ -152,11 +159,6 @@ impl CustomValue for ArchiveFileEntity {
     fn as_mut_any(&mut self) -> &mut dyn std::any::Any {
         self
     }
-
-    #[doc(hidden)]
-    fn typetag_deserialize(&self) {
-        unimplemented!()
-    }
 }
 
 pub trait ToFilesize {
@@ -211,12 +213,12 @@ impl<T: AsPrimitive<i64>> ToFilesize for Option<T> {
     }
 }
 
-impl<'a> TryFrom<&'a Value> for DataSource<'a> {
+impl TryFrom<&Value> for DataSource {
     type Error = ArchiveError;
 
-    fn try_from(value: &'a Value) -> Result<DataSource<'a>, Self::Error> {
+    fn try_from(value: &Value) -> Result<DataSource, Self::Error> {
         match value {
-            Value::Binary { val, .. } => Ok(DataSource::stream(val)),
+            Value::Binary { val, .. } => Ok(DataSource::memory(val.as_slice())),
             v => Err(ArchiveError::InvalidDataSource(v.get_type().to_string())),
         }
     }
@@ -231,6 +233,8 @@ impl FromValue for ArchiveCompression {
                 "bzip2" => Ok(ArchiveCompression::Bzip2),
                 #[cfg(feature = "lzma_codecs")]
                 "lzma" | "xz" => Ok(ArchiveCompression::Lzma),
+                #[cfg(feature = "lzw_codecs")]
+                "compress" | "z" => Ok(ArchiveCompression::Compress),
                 #[cfg(feature = "zstd_codecs")]
                 "zstd" => Ok(ArchiveCompression::Zstd),
                 #[cfg(feature = "aes_codecs")]
@@ -239,21 +243,13 @@ impl FromValue for ArchiveCompression {
                 #[cfg(feature = "deflate_codecs")]
                 "deflate" | "deflated" => Ok(ArchiveCompression::Deflate),
                 _ => {
-                    let lower = val.to_lowercase();
-                    let closest = ArchiveCompression::iter()
-                        .map(|c| {
-                            (
-                                nu_protocol::levenshtein_distance(
-                                    lower.as_str(),
-                                    c.to_string().to_lowercase().as_str(),
-                                ),
-                                c,
-                            )
-                        })
-                        .reduce(|a, b| if a.0 < b.0 { a } else { b });
-                    if let Some((0..=3, c)) = closest {
+                    let names = ArchiveCompression::iter()
+                        .map(|c| c.to_string())
+                        .collect::<Vec<_>>();
+                    let closest = closest_matches(val, names.iter().map(String::as_str), 3);
+                    if let Some(suggestion) = closest.first() {
                         Err(nu_protocol::ShellError::DidYouMean {
-                            suggestion: c.to_string(),
+                            suggestion: suggestion.to_string(),
                             span: value.span(),
                         })
                     } else {
@@ -276,11 +272,32 @@ impl FromValue for ArchiveCompression {
     }
 }
 
+impl FromValue for GzipStrategy {
+    fn from_value(value: Value) -> Result<Self, nu_protocol::ShellError> {
+        let strategy = match &value {
+            Value::String { val, .. } => match val.to_lowercase().as_str() {
+                "filtered" => Some(GzipStrategy::Filtered),
+                "huffman-only" => Some(GzipStrategy::HuffmanOnly),
+                "rle" => Some(GzipStrategy::Rle),
+                "fixed" => Some(GzipStrategy::Fixed),
+                _ => None,
+            },
+            _ => None,
+        };
+        strategy.ok_or_else(|| nu_protocol::ShellError::CantConvert {
+            from_type: value.get_type().to_string(),
+            to_type: "GzipStrategy".to_string(),
+            span: value.span(),
+            help: Some("expected filtered, huffman-only, rle or fixed".to_string()),
+        })
+    }
+}
+
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod tests {
     use super::*;
-    use crate::archive::{ArchiveCompression, ArchiveFileEntityType, ArchiveMetadata};
+    use crate::archive::{ArchiveCompression, ArchiveFileEntityType, ArchiveMetadata, ArchiveType};
     use nu_protocol::{record, Value};
     use serde_json::json;
 
@@ -341,12 +358,17 @@ mod tests {
         );
     }
 
+    #[cfg(all(feature = "tar_archive", feature = "zstd_codecs"))]
     #[test]
     fn test_archive_metadata_to_value() {
         let metadata = ArchiveMetadata {
+            archive_type: ArchiveType::Tar,
             compressed_size: 360,
             compression: Some(ArchiveCompression::Zstd),
             total_size: 420,
+            entry_count: 1,
+            created: None,
+            modified: Some(chrono::DateTime::parse_from_rfc3339("2021-01-02T00:00:00Z").unwrap()),
             entries: vec![ArchiveFileEntity {
                 name: "test".to_string(),
                 size: Some(100),
@@ -356,6 +378,8 @@ mod tests {
                 ),
                 compression: Some(ArchiveCompression::Zstd.to_string()),
                 fstype: ArchiveFileEntityType::File,
+                raw: None,
+                unix: None,
             }],
             additional: Some(json!(
                 {
@@ -375,16 +399,26 @@ mod tests {
             Value::record(
                 Record::from_raw_cols_vals(
                     vec![
+                        "schema_version".to_string(),
+                        "archive_type".to_string(),
                         "total_size".to_string(),
                         "compressed_size".to_string(),
                         "compression".to_string(),
+                        "entry_count".to_string(),
+                        "created".to_string(),
+                        "modified".to_string(),
                         "entries".to_string(),
                         "additional".to_string(),
                     ],
                     vec![
+                        Value::int(1, Span::unknown()),
+                        Value::string("tar", Span::unknown()),
                         Value::int(420, Span::unknown()),
                         Value::int(360, Span::unknown()),
                         Value::string("zstd", Span::unknown()),
+                        Value::int(1, Span::unknown()),
+                        Value::nothing(Span::unknown()),
+                        Value::string("2021-01-02T00:00:00Z", Span::unknown()),
                         Value::list(
                             vec![Value::record(
                                 Record::from_raw_cols_vals(
@@ -434,4 +468,71 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn test_archive_file_entity_ratio() {
+        let entity = ArchiveFileEntity {
+            name: "test".to_string(),
+            size: Some(200),
+            compressed_size: Some(69),
+            last_modified: None,
+            compression: None,
+            fstype: ArchiveFileEntityType::File,
+            raw: None,
+            unix: None,
+        };
+        let value = entity.to_base_value(Span::unknown()).unwrap();
+        assert_eq!(
+            value.get_data_by_key("ratio"),
+            Some(Value::string("34.5%", Span::unknown()))
+        );
+
+        let directory = ArchiveFileEntity {
+            size: Some(0),
+            fstype: ArchiveFileEntityType::Directory,
+            ..entity
+        };
+        let value = directory.to_base_value(Span::unknown()).unwrap();
+        assert_eq!(
+            value.get_data_by_key("ratio"),
+            Some(Value::nothing(Span::unknown()))
+        );
+    }
+
+    #[cfg(feature = "zip_archive")]
+    #[test]
+    fn test_custom_values_round_trip() {
+        let entity = ArchiveFileEntity {
+            name: "dir/test".to_string(),
+            size: Some(100),
+            compressed_size: None,
+            last_modified: None,
+            compression: None,
+            fstype: ArchiveFileEntityType::File,
+            raw: None,
+            unix: None,
+        };
+        let metadata = ArchiveMetadata {
+            archive_type: ArchiveType::Zip,
+            compressed_size: 69,
+            compression: None,
+            total_size: 100,
+            entry_count: 1,
+            created: None,
+            modified: None,
+            entries: vec![entity.clone()],
+            additional: None,
+        };
+
+        let values: [Box<dyn CustomValue>; 2] = [Box::new(metadata), Box::new(entity)];
+        for value in values {
+            let json = serde_json::to_string(&value).unwrap();
+            let restored = serde_json::from_str::<Box<dyn CustomValue>>(&json).unwrap();
+            assert_eq!(restored.type_name(), value.type_name());
+            assert_eq!(
+                restored.to_base_value(Span::unknown()).unwrap(),
+                value.to_base_value(Span::unknown()).unwrap()
+            );
+        }
+    }
 }
@@ -0,0 +1,140 @@
+// owners of the entries of created archives: the user and group names of the local accounts, and
+// the fixed owner that distributed tarballs and container layers are usually built with
+
+use std::collections::HashMap;
+
+/// User or group given to every entry instead of the owner of the file, with its name and id as
+/// `NAME:ID`, a bare `ID`, or a `NAME` whose id is looked up in the local accounts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Owner {
+    pub name: Option<String>,
+    pub id: u64,
+}
+
+impl Owner {
+    /// Parses a user such as `root`, `0` or `build:1000`.
+    pub fn user(s: &str) -> Result<Self, String> {
+        Self::parse(s, false)
+    }
+
+    /// Parses a group such as `root`, `0` or `build:1000`.
+    pub fn group(s: &str) -> Result<Self, String> {
+        Self::parse(s, true)
+    }
+
+    fn parse(s: &str, group: bool) -> Result<Self, String> {
+        let kind = if group { "group" } else { "user" };
+        let invalid_id = |id: &str| format!("invalid {} id `{}`", kind, id);
+        match s.split_once(':') {
+            Some((name, id)) => Ok(Self {
+                name: (!name.is_empty()).then(|| name.to_string()),
+                id: id.parse().map_err(|_| invalid_id(id))?,
+            }),
+            None if s.is_empty() => Err(format!("missing {}", kind)),
+            None if s.bytes().all(|b| b.is_ascii_digit()) => Ok(Self {
+                name: None,
+                id: s.parse().map_err(|_| invalid_id(s))?,
+            }),
+            None => Ok(Self {
+                name: Some(s.to_string()),
+                id: id_of(s, group).ok_or_else(|| {
+                    format!("unknown {} `{}`, give its id as `{}:ID`", kind, s, s)
+                })?,
+            }),
+        }
+    }
+}
+
+/// Names of the users and groups owning the archived files, looked up once per id.
+#[derive(Debug, Default)]
+pub(crate) struct OwnerNames {
+    users: HashMap<u64, Option<String>>,
+    groups: HashMap<u64, Option<String>>,
+}
+
+impl OwnerNames {
+    pub(crate) fn user(&mut self, uid: u64) -> Option<&str> {
+        self.users
+            .entry(uid)
+            .or_insert_with(|| name_of(uid, false))
+            .as_deref()
+    }
+
+    pub(crate) fn group(&mut self, gid: u64) -> Option<&str> {
+        self.groups
+            .entry(gid)
+            .or_insert_with(|| name_of(gid, true))
+            .as_deref()
+    }
+}
+
+#[cfg(unix)]
+fn id_of(name: &str, group: bool) -> Option<u64> {
+    let name = std::ffi::CString::new(name).ok()?;
+    // SAFETY: the name is NUL terminated, and the static entry returned is read before any other
+    // lookup may overwrite it
+    unsafe {
+        if group {
+            let entry = libc::getgrnam(name.as_ptr());
+            (!entry.is_null()).then(|| (*entry).gr_gid as u64)
+        } else {
+            let entry = libc::getpwnam(name.as_ptr());
+            (!entry.is_null()).then(|| (*entry).pw_uid as u64)
+        }
+    }
+}
+
+#[cfg(unix)]
+fn name_of(id: u64, group: bool) -> Option<String> {
+    // SAFETY: as in `id_of`, the name is copied out of the static entry right away
+    let name = unsafe {
+        let name = if group {
+            let entry = libc::getgrgid(id.try_into().ok()?);
+            (!entry.is_null()).then(|| (*entry).gr_name)
+        } else {
+            let entry = libc::getpwuid(id.try_into().ok()?);
+            (!entry.is_null()).then(|| (*entry).pw_name)
+        }?;
+        std::ffi::CStr::from_ptr(name)
+    };
+    Some(name.to_string_lossy().to_string())
+}
+
+#[cfg(not(unix))]
+fn id_of(_name: &str, _group: bool) -> Option<u64> {
+    None
+}
+
+#[cfg(not(unix))]
+fn name_of(_id: u64, _group: bool) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_owners() {
+        assert_eq!(
+            Owner::user("build:1000").unwrap(),
+            Owner {
+                name: Some("build".to_string()),
+                id: 1000,
+            }
+        );
+        assert_eq!(Owner::group("42").unwrap(), Owner { name: None, id: 42 });
+        assert!(Owner::user("build:x").is_err());
+        assert!(Owner::user("").is_err());
+        assert!(Owner::user("no-such-user-hezi").is_err());
+        #[cfg(unix)]
+        assert_eq!(
+            Owner::user("root").unwrap(),
+            Owner {
+                name: Some("root".to_string()),
+                id: 0,
+            }
+        );
+    }
+}
@@ -0,0 +1,168 @@
+// gzip encoder compressing blocks of the input on several threads, the way `pigz` does.
+//
+// Each block is compressed on its own into raw deflate data ending with a sync flush, which leaves
+// it byte aligned and without the final bit set, so that the blocks can simply be appended to each
+// other. An empty final block then closes the deflate stream, and the output is an ordinary gzip
+// member that any decoder reads. Blocks do not share their history, which costs a little ratio.
+
+use std::io::{Error, ErrorKind, Write};
+
+use flate2::{Compress, Compression, Crc, FlushCompress};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+
+use super::tuned_encoders::gzip_header;
+
+/// Size of the blocks compressed on each thread.
+const BLOCK_SIZE: usize = 128 * 1024;
+/// Empty fixed huffman block with the final bit set.
+const FINAL_BLOCK: [u8; 2] = [0x03, 0x00];
+
+/// Writer compressing to gzip on the rayon thread pool, `threads` blocks at a time.
+pub(crate) struct ParallelGzEncoder<W: Write> {
+    inner: W,
+    level: Compression,
+    /// Amount of data compressed at once, a block for each thread.
+    batch: usize,
+    /// Data written but not compressed yet.
+    pending: Vec<u8>,
+    crc: Crc,
+    header_written: bool,
+    finished: bool,
+}
+
+impl<W: Write> ParallelGzEncoder<W> {
+    pub(crate) fn new(inner: W, level: Compression, threads: usize) -> Self {
+        let batch = threads.max(1) * BLOCK_SIZE;
+        Self {
+            inner,
+            level,
+            batch,
+            pending: Vec::with_capacity(batch),
+            crc: Crc::new(),
+            header_written: false,
+            finished: false,
+        }
+    }
+
+    /// Compresses and writes all the pending data.
+    fn write_blocks(&mut self) -> Result<(), Error> {
+        if !self.header_written {
+            self.inner.write_all(&gzip_header(self.level.level()))?;
+            self.header_written = true;
+        }
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        self.crc.update(&self.pending);
+        let level = self.level;
+        let blocks = self
+            .pending
+            .chunks(BLOCK_SIZE)
+            .collect::<Vec<_>>()
+            .par_iter()
+            .map(|block| compress_block(block, level))
+            .collect::<Result<Vec<_>, _>>()?;
+        for block in blocks {
+            self.inner.write_all(&block)?;
+        }
+        self.pending.clear();
+        Ok(())
+    }
+
+    /// Writes the remaining data and the end of the member.
+    pub(crate) fn try_finish(&mut self) -> Result<(), Error> {
+        if self.finished {
+            return Ok(());
+        }
+        self.write_blocks()?;
+        self.inner.write_all(&FINAL_BLOCK)?;
+        self.inner.write_all(&self.crc.sum().to_le_bytes())?;
+        self.inner.write_all(&self.crc.amount().to_le_bytes())?;
+        self.inner.flush()?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for ParallelGzEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        if self.finished {
+            return Err(Error::new(
+                ErrorKind::BrokenPipe,
+                "gzip stream already finished",
+            ));
+        }
+        let n = buf.len().min(self.batch - self.pending.len());
+        self.pending.extend_from_slice(&buf[..n]);
+        if self.pending.len() == self.batch {
+            self.write_blocks()?;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        if !self.finished {
+            self.write_blocks()?;
+        }
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for ParallelGzEncoder<W> {
+    fn drop(&mut self) {
+        let _ = self.try_finish();
+    }
+}
+
+/// Raw deflate data of `block`, byte aligned and without a final block.
+fn compress_block(block: &[u8], level: Compression) -> Result<Vec<u8>, Error> {
+    let mut compress = Compress::new(level, false);
+    let mut out = Vec::with_capacity(block.len() + block.len() / 16 + 64);
+    loop {
+        let consumed = compress.total_in() as usize;
+        compress
+            .compress_vec(&block[consumed..], &mut out, FlushCompress::Sync)
+            .map_err(Error::other)?;
+        // the flush is complete once everything is consumed and some room is left
+        if compress.total_in() as usize == block.len() && out.len() < out.capacity() {
+            return Ok(out);
+        }
+        out.reserve(out.capacity().max(64));
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    fn round_trip(data: &[u8], threads: usize) {
+        let mut out = Vec::new();
+        let mut encoder = ParallelGzEncoder::new(&mut out, Compression::default(), threads);
+        encoder.write_all(data).unwrap();
+        encoder.try_finish().unwrap();
+        drop(encoder);
+
+        let mut decoded = Vec::new();
+        let mut decoder = flate2::read::GzDecoder::new(out.as_slice());
+        decoder.read_to_end(&mut decoded).unwrap();
+        assert_eq!(decoded, data);
+        // a single member, checked against its trailer
+        assert!(decoder.into_inner().is_empty());
+    }
+
+    #[test]
+    fn writes_valid_members() {
+        let data = (0..200_000u32)
+            .flat_map(|i| (i * 7919 % 1000).to_string().into_bytes())
+            .collect::<Vec<_>>();
+        for threads in [1, 3, 8] {
+            round_trip(&data, threads);
+        }
+        round_trip(b"", 4);
+        round_trip(&data[..BLOCK_SIZE], 2);
+    }
+}
@@ -0,0 +1,344 @@
+use std::{
+    collections::HashSet,
+    fmt::Debug,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use super::{ArchiveError, Archived};
+
+/// Supplies the password of an encrypted archive. Backends only ask for it once they meet
+/// encrypted data, so a provider can prompt lazily or hand out a different password per entry.
+pub trait PasswordProvider {
+    /// Returns the password of `entry`, or of the whole archive when `entry` is `None` for
+    /// formats encrypting their headers or several entries at once. `Ok(None)` fails the read
+    /// with [`ArchiveError::PasswordRequired`].
+    fn password(&self, entry: Option<&str>) -> Result<Option<String>, ArchiveError>;
+
+    /// Called when the password returned for `entry` does not decrypt it, before asking for it
+    /// again. Backends give up once the provider returns a password it already tried.
+    fn rejected(&self, _entry: Option<&str>) {}
+}
+
+impl<F> PasswordProvider for F
+where
+    F: Fn(Option<&str>) -> Result<Option<String>, ArchiveError>,
+{
+    fn password(&self, entry: Option<&str>) -> Result<Option<String>, ArchiveError> {
+        self(entry)
+    }
+}
+
+impl<'a> Debug for dyn PasswordProvider + 'a {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "PasswordProvider#{}",
+            self as *const _ as *const u8 as usize
+        )
+    }
+}
+
+/// Boxes a closure as a [`PasswordProvider`], giving it the signature the trait expects.
+pub fn password_provider<'a, F>(f: F) -> Box<dyn PasswordProvider + 'a>
+where
+    F: Fn(Option<&str>) -> Result<Option<String>, ArchiveError> + 'a,
+{
+    Box::new(f)
+}
+
+/// The same password for every entry.
+#[derive(Clone)]
+pub struct StaticPassword(pub String);
+
+impl PasswordProvider for StaticPassword {
+    fn password(&self, _entry: Option<&str>) -> Result<Option<String>, ArchiveError> {
+        Ok(Some(self.0.clone()))
+    }
+}
+
+impl StaticPassword {
+    /// Boxes `password` as a provider, `None` if there is no password.
+    pub fn boxed<'a>(password: Option<String>) -> Option<Box<dyn PasswordProvider + 'a>> {
+        password.map(|p| Box::new(StaticPassword(p)) as Box<dyn PasswordProvider>)
+    }
+}
+
+/// Reads the password from an environment variable when it is needed.
+#[derive(Clone, Debug)]
+pub struct EnvPassword(pub String);
+
+impl PasswordProvider for EnvPassword {
+    fn password(&self, _entry: Option<&str>) -> Result<Option<String>, ArchiveError> {
+        match std::env::var(&self.0) {
+            Ok(password) => Ok(Some(password)),
+            Err(std::env::VarError::NotPresent) => Ok(None),
+            Err(e) => Err(ArchiveError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{}: {}", self.0, e),
+            ))),
+        }
+    }
+}
+
+/// Asks for the password on the terminal the first time it is needed, naming the entry it is
+/// for, and gives the same answer for the following entries until it is rejected. Never prompts
+/// when the standard input is not a terminal.
+#[cfg(feature = "password_prompt")]
+#[derive(Debug)]
+pub struct PromptPassword {
+    prompt: String,
+    answer: std::cell::RefCell<Option<String>>,
+}
+
+#[cfg(feature = "password_prompt")]
+impl PromptPassword {
+    /// `prompt` is followed by the name of the entry the password is for, if any.
+    pub fn new<S: Into<String>>(prompt: S) -> Self {
+        Self {
+            prompt: prompt.into(),
+            answer: std::cell::RefCell::new(None),
+        }
+    }
+
+    /// The last password that was typed in and not rejected.
+    pub fn answer(&self) -> Option<String> {
+        self.answer.borrow().clone()
+    }
+}
+
+#[cfg(feature = "password_prompt")]
+impl PasswordProvider for PromptPassword {
+    fn password(&self, entry: Option<&str>) -> Result<Option<String>, ArchiveError> {
+        if let Some(answer) = self.answer() {
+            return Ok(Some(answer));
+        }
+        if !std::io::IsTerminal::is_terminal(&std::io::stdin()) {
+            return Ok(None);
+        }
+        let prompt = match entry {
+            Some(entry) => format!("{} for {}: ", self.prompt, entry),
+            None => format!("{}: ", self.prompt),
+        };
+        let answer = Some(rpassword::prompt_password(prompt)?).filter(|a| !a.is_empty());
+        self.answer.replace(answer.clone());
+        Ok(answer)
+    }
+
+    fn rejected(&self, _entry: Option<&str>) {
+        self.answer.take();
+    }
+}
+
+/// Looks up the password remembered for an archive in the OS keyring, see
+/// [`keyring_store`](super::keyring_store).
+#[cfg(feature = "keyring")]
+#[derive(Clone, Debug)]
+pub struct KeyringPassword(pub std::path::PathBuf);
+
+#[cfg(feature = "keyring")]
+impl PasswordProvider for KeyringPassword {
+    fn password(&self, _entry: Option<&str>) -> Result<Option<String>, ArchiveError> {
+        super::keyring_store::stored_password(&self.0)
+    }
+}
+
+/// Caps applied while trying candidate passwords against an archive.
+#[derive(Debug, Clone, Default)]
+pub struct PasswordAttemptLimits {
+    /// Give up after this many candidates have been tried.
+    pub max_attempts: Option<usize>,
+    /// Wait this long between two consecutive attempts.
+    pub delay: Option<Duration>,
+}
+
+/// Outcome of [`find_password`].
+#[derive(Debug, Clone)]
+pub struct PasswordSearch {
+    pub password: Option<String>,
+    pub attempts: usize,
+    pub candidates: usize,
+    pub elapsed: Duration,
+}
+
+impl std::fmt::Display for PasswordSearch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let outcome = if self.password.is_some() {
+            "found a matching password"
+        } else if self.attempts < self.candidates {
+            "stopped before finding a matching password"
+        } else {
+            "no candidate matched"
+        };
+        write!(
+            f,
+            "Tried {} of {} candidate passwords in {:.2}s: {}",
+            self.attempts,
+            self.candidates,
+            self.elapsed.as_secs_f64(),
+            outcome
+        )
+    }
+}
+
+/// Reads candidate passwords from a file, one per line. Empty lines and duplicates are skipped.
+pub fn read_password_list<P: AsRef<Path>>(path: P) -> Result<Vec<String>, ArchiveError> {
+    let content = std::fs::read_to_string(path)?;
+    let mut seen = HashSet::new();
+
+    Ok(content
+        .lines()
+        .map(|l| l.trim_end_matches('\r'))
+        .filter(|l| !l.is_empty())
+        .filter(|l| seen.insert(l.to_string()))
+        .map(String::from)
+        .collect())
+}
+
+/// Tries each candidate in order until one decrypts `archive`, honoring `limits`.
+pub fn find_password<A: Archived>(
+    archive: &A,
+    candidates: &[String],
+    limits: &PasswordAttemptLimits,
+) -> Result<PasswordSearch, ArchiveError> {
+    let start = Instant::now();
+    let max_attempts = limits.max_attempts.unwrap_or(usize::MAX);
+
+    let mut attempts = 0;
+    let mut password = None;
+    for candidate in candidates.iter().take(max_attempts) {
+        if attempts > 0 {
+            if let Some(delay) = limits.delay {
+                std::thread::sleep(delay);
+            }
+        }
+        attempts += 1;
+        if archive.check_password(candidate)? {
+            password = Some(candidate.clone());
+            break;
+        }
+    }
+
+    Ok(PasswordSearch {
+        password,
+        attempts,
+        candidates: candidates.len(),
+        elapsed: start.elapsed(),
+    })
+}
+
+#[cfg(all(test, feature = "zip_archive", feature = "deflate_codecs"))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::archive::{
+        zip_archive::ZipArchive, ArchiveFileEntityType, ExtractOptions, ListOptions, OpenOptions,
+    };
+
+    fn candidates(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn finds_password_in_list() {
+        let archive = ZipArchive::from_path("tests/fixtures/encrypted.zip").unwrap();
+        let search = find_password(
+            &archive,
+            &candidates(&["letmein", "hunter2", "password"]),
+            &PasswordAttemptLimits::default(),
+        )
+        .unwrap();
+
+        assert_eq!(search.password.as_deref(), Some("hunter2"));
+        assert_eq!(search.attempts, 2);
+    }
+
+    #[test]
+    fn stops_at_max_attempts() {
+        let archive = ZipArchive::from_path("tests/fixtures/encrypted.zip").unwrap();
+        let search = find_password(
+            &archive,
+            &candidates(&["letmein", "password", "hunter2"]),
+            &PasswordAttemptLimits {
+                max_attempts: Some(2),
+                delay: None,
+            },
+        )
+        .unwrap();
+
+        assert_eq!(search.password, None);
+        assert_eq!(search.attempts, 2);
+        assert_eq!(search.candidates, 3);
+    }
+
+    #[test]
+    fn asks_for_each_encrypted_entry() {
+        let archive = ZipArchive::from_path("tests/fixtures/encrypted.zip").unwrap();
+        let asked = RefCell::new(Vec::new());
+        let provider = || {
+            password_provider(|entry| {
+                asked.borrow_mut().push(entry.map(String::from));
+                Ok(Some("hunter2".to_string()))
+            })
+        };
+
+        // listing reads the central directory only
+        let entries = archive
+            .list(ListOptions {
+                password: Some(provider()),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(entries.len(), 4);
+        assert!(asked.borrow().is_empty());
+
+        let dir = std::env::temp_dir().join(format!("hezi-password-test-{}", std::process::id()));
+        archive
+            .extract(ExtractOptions {
+                destination: dir.clone(),
+                password: Some(provider()),
+                overwrite: true,
+                ..Default::default()
+            })
+            .unwrap();
+        let files = entries
+            .iter()
+            .filter(|e| e.fstype == ArchiveFileEntityType::File)
+            .map(|e| Some(e.name.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(*asked.borrow(), files);
+        assert_eq!(
+            std::fs::read(dir.join("test1/file1.txt")).unwrap(),
+            std::fs::read("tests/fixtures/test1/file1.txt").unwrap()
+        );
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn fails_without_password() {
+        let archive = ZipArchive::from_path("tests/fixtures/encrypted.zip").unwrap();
+        let read = |password: Option<Box<dyn PasswordProvider>>| {
+            archive.open(OpenOptions {
+                paths: vec!["test1/file1.txt".into()],
+                password,
+                dest: Box::new(std::io::sink()),
+                output: Default::default(),
+            })
+        };
+
+        assert!(matches!(read(None), Err(ArchiveError::PasswordRequired)));
+        let var = format!("HEZI_TEST_PASSWORD_{}", std::process::id());
+        assert!(matches!(
+            read(Some(Box::new(EnvPassword(var.clone())))),
+            Err(ArchiveError::PasswordRequired)
+        ));
+        assert!(matches!(
+            read(Some(Box::new(StaticPassword("wrong".to_string())))),
+            Err(ArchiveError::WrongPassword)
+        ));
+        std::env::set_var(&var, "hunter2");
+        read(Some(Box::new(EnvPassword(var)))).unwrap();
+    }
+}
@@ -0,0 +1,161 @@
+// quick look inside archives: their content, or a single entry of it, extracted to a temporary
+// directory of its own under a root that hezi manages, so that the looks kept around can all be
+// cleaned at once.
+
+use std::{
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+};
+
+use tempfile::TempDir;
+
+use super::{
+    entry_visitor::{is_under, normalized_name, EntryVisitor, VisitOptions},
+    password::{password_provider, PasswordProvider},
+    suggest::entry_suggestions,
+    Archive, ArchiveError, Archived, ExtractOptions,
+};
+
+/// Directory holding the extracted archives, in the temporary directory of the system.
+pub fn peek_root() -> PathBuf {
+    std::env::temp_dir().join("hezi-peek")
+}
+
+/// Archive extracted to a temporary directory, deleted when it is dropped unless it is kept.
+#[derive(Debug)]
+pub struct Peek {
+    dir: TempDir,
+    target: PathBuf,
+}
+
+impl Peek {
+    /// The extracted entry, or the directory holding the whole archive.
+    pub fn path(&self) -> &Path {
+        &self.target
+    }
+
+    /// The directory the archive is extracted to.
+    pub fn dir(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Keeps the extracted files once the peek is gone, until [`clean_peeks`] deletes them, and
+    /// returns [`Peek::path`].
+    pub fn keep(self) -> PathBuf {
+        _ = self.dir.keep();
+        self.target
+    }
+}
+
+/// Extracts `archive`, or only its `entry` and the files under it if it is a directory, to a new
+/// directory of [`peek_root`] named after the archive `name`.
+pub fn peek(
+    archive: &Archive,
+    name: &str,
+    entry: Option<&str>,
+    password: Option<&dyn PasswordProvider>,
+) -> Result<Peek, ArchiveError> {
+    let files = match entry {
+        Some(entry) => {
+            let target = normalized_name(entry).trim_end_matches('/');
+            let mut names = Vec::new();
+            let mut files = Vec::new();
+            let options = VisitOptions {
+                password,
+                ..Default::default()
+            };
+            archive.for_each_entry(&options, |visited, _| {
+                let name = &visited.entity.name;
+                if normalized_name(name).trim_end_matches('/') == target || is_under(name, target) {
+                    files.push(name.clone());
+                }
+                names.push(name.clone());
+                Ok(ControlFlow::Continue(()))
+            })?;
+            if files.is_empty() {
+                let suggestions = entry_suggestions(target, names.iter().map(String::as_str));
+                return Err(ArchiveError::EntryNotFound(
+                    PathBuf::from(target),
+                    suggestions,
+                ));
+            }
+            Some(files)
+        }
+        None => None,
+    };
+
+    let root = peek_root();
+    std::fs::create_dir_all(&root)?;
+    let stem = Path::new(name)
+        .file_stem()
+        .map_or("stdin".into(), |stem| stem.to_string_lossy());
+    let dir = tempfile::Builder::new()
+        .prefix(&format!("{}-", stem))
+        .tempdir_in(&root)?;
+
+    archive.extract(ExtractOptions {
+        destination: dir.path().to_path_buf(),
+        password: password.map(|p| password_provider(move |entry| p.password(entry))),
+        files,
+        show_hidden: true,
+        ..Default::default()
+    })?;
+    let target = match entry {
+        Some(entry) => dir
+            .path()
+            .join(normalized_name(entry).trim_end_matches('/')),
+        None => dir.path().to_path_buf(),
+    };
+    Ok(Peek { dir, target })
+}
+
+/// Deletes the archives extracted by [`peek`] and kept, returning how many there were.
+pub fn clean_peeks() -> Result<usize, ArchiveError> {
+    let root = peek_root();
+    if !root.exists() {
+        return Ok(0);
+    }
+    let mut cleaned = 0;
+    for dir in std::fs::read_dir(&root)? {
+        let path = dir?.path();
+        if path.is_dir() {
+            std::fs::remove_dir_all(&path)?;
+        } else {
+            std::fs::remove_file(&path)?;
+        }
+        cleaned += 1;
+    }
+    Ok(cleaned)
+}
+
+#[cfg(all(test, feature = "tar_archive"))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::archive::DataSource;
+
+    fn archive() -> Archive {
+        Archive::of(DataSource::file("tests/fixtures/test1.tar").unwrap()).unwrap()
+    }
+
+    #[test]
+    fn peeks_at_archives_and_entries() {
+        let peek = super::peek(&archive(), "tests/fixtures/test1.tar", None, None).unwrap();
+        assert!(peek.dir().starts_with(peek_root()));
+        assert!(peek.path().join("test1/dir1/file2.txt").is_file());
+        let dir = peek.dir().to_path_buf();
+        drop(peek);
+        assert!(!dir.exists());
+
+        let peek = super::peek(&archive(), "test1.tar", Some("test1/dir1"), None).unwrap();
+        assert!(peek.path().ends_with("test1/dir1"));
+        assert!(peek.path().join("file2.txt").is_file());
+        assert!(!peek.dir().join("test1/file1.txt").exists());
+        let kept = peek.keep();
+        assert!(kept.exists());
+        std::fs::remove_dir_all(kept.parent().unwrap().parent().unwrap()).unwrap();
+
+        let missing = super::peek(&archive(), "test1.tar", Some("test1/fil1.txt"), None);
+        assert!(matches!(missing, Err(ArchiveError::EntryNotFound(_, s)) if !s.is_empty()));
+    }
+}
@@ -0,0 +1,203 @@
+// machine readable progress: the events of an operation are written as lines of JSON to a file
+// descriptor of their own, or a named pipe on Windows, so that the programs driving hezi can show
+// its progress while its standard output is left to the data
+
+use std::{
+    cell::{Cell, RefCell},
+    fmt::Display,
+    fs::File,
+    io::Write,
+};
+
+use serde_json::{json, Value};
+
+use super::{ArchiveEvent, EventHandler};
+
+/// Event handler writing every event as a line of JSON to `out`, along with the bytes processed
+/// so far and the total, before forwarding it to `inner`. Call [`ProgressWriter::failed`] if the
+/// operation fails.
+pub struct ProgressWriter<'a, W: Write> {
+    inner: Box<dyn EventHandler + 'a>,
+    out: RefCell<W>,
+    /// Total size of the entries processed by the operation, if known.
+    total: Option<u64>,
+    processed: Cell<u64>,
+}
+
+impl<'a, W: Write> ProgressWriter<'a, W> {
+    pub fn new(out: W, total: Option<u64>, inner: Box<dyn EventHandler + 'a>) -> Self {
+        Self {
+            inner,
+            out: RefCell::new(out),
+            total,
+            processed: Cell::new(0),
+        }
+    }
+
+    /// Reports that the operation failed with `error`.
+    pub fn failed<E: Display>(&self, error: E) {
+        self.write(json!({ "event": "failed", "error": error.to_string() }));
+    }
+
+    /// Writes `line`, ignoring the errors: a reader going away must not stop the operation.
+    fn write(&self, line: Value) {
+        self.write_processed(line, self.processed.get());
+    }
+
+    fn write_processed(&self, mut line: Value, processed: u64) {
+        line["processed"] = processed.into();
+        line["total"] = self.total.into();
+        let mut out = self.out.borrow_mut();
+        _ = writeln!(out, "{}", line);
+        _ = out.flush();
+    }
+}
+
+impl<W: Write> EventHandler for ProgressWriter<'_, W> {
+    fn handle(&self, event: ArchiveEvent) {
+        if let ArchiveEvent::Extracting(_, Some(size)) | ArchiveEvent::Added(_, Some(size)) = &event
+        {
+            self.processed.set(self.processed.get() + size);
+        }
+        match &event {
+            // the size of the file is counted as soon as it starts being extracted
+            ArchiveEvent::EntryProgress(_, copied, Some(size)) => self.write_processed(
+                event_json(&event),
+                self.processed.get().saturating_sub(*size) + copied,
+            ),
+            _ => self.write(event_json(&event)),
+        }
+        self.inner.handle(event);
+    }
+}
+
+/// JSON object of `event`, its kind in `event` and its details in the other fields.
+fn event_json(event: &ArchiveEvent) -> Value {
+    match event {
+        ArchiveEvent::Extracting(name, size) => {
+            json!({ "event": "extracting", "name": name, "size": size })
+        }
+        ArchiveEvent::EntryProgress(name, copied, size) => {
+            json!({ "event": "entry_progress", "name": name, "copied": copied, "size": size })
+        }
+        ArchiveEvent::DoneExtracting(name, destination) => {
+            json!({ "event": "done_extracting", "name": name, "destination": destination })
+        }
+        ArchiveEvent::FailedToReadEntry(name, error) => {
+            json!({ "event": "failed_to_read_entry", "name": name, "error": error.to_string() })
+        }
+        ArchiveEvent::Created(name, fstype) => {
+            json!({ "event": "created", "name": name, "type": fstype })
+        }
+        ArchiveEvent::Skipped(name, reason) => {
+            json!({ "event": "skipped", "name": name, "reason": reason.name() })
+        }
+        ArchiveEvent::Added(name, size) => json!({ "event": "added", "name": name, "size": size }),
+        ArchiveEvent::DoneCreating(path) => json!({ "event": "done_creating", "path": path }),
+        ArchiveEvent::Retrying(name, attempt, error) => json!({
+            "event": "retrying",
+            "name": name,
+            "attempt": attempt,
+            "error": error.to_string(),
+        }),
+        ArchiveEvent::Log(message) => json!({ "event": "log", "message": message }),
+    }
+}
+
+/// Opens the file descriptor `target`, such as `3`, to write progress to. The descriptor is
+/// duplicated, so that it stays open for the caller once the file is dropped.
+#[cfg(unix)]
+pub fn open_progress_fd(target: &str) -> std::io::Result<File> {
+    use std::os::fd::FromRawFd;
+
+    let fd = target.parse::<std::os::fd::RawFd>().map_err(|_| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("invalid file descriptor `{}`", target),
+        )
+    })?;
+    // SAFETY: `dup` only reads `fd`, and the descriptor it returns is owned by nothing else
+    unsafe {
+        let duplicated = libc::dup(fd);
+        if duplicated < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(File::from_raw_fd(duplicated))
+    }
+}
+
+/// Opens the named pipe `target`, such as `\\.\pipe\hezi`, to write progress to.
+#[cfg(not(unix))]
+pub fn open_progress_fd(target: &str) -> std::io::Result<File> {
+    std::fs::OpenOptions::new().write(true).open(target)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::archive::{SimpleLogger, SkipReason};
+
+    #[test]
+    fn writes_events_as_json_lines() {
+        let mut out = Vec::new();
+        {
+            let progress = ProgressWriter::new(&mut out, Some(10), Box::new(SimpleLogger));
+            progress.handle(ArchiveEvent::Added("a.txt".to_string(), Some(4)));
+            progress.handle(ArchiveEvent::Skipped(
+                ".hidden".to_string(),
+                SkipReason::Hidden,
+            ));
+            progress.failed("disk full");
+        }
+
+        let lines = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str::<Value>(l).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lines,
+            [
+                json!({ "event": "added", "name": "a.txt", "size": 4, "processed": 4, "total": 10 }),
+                json!({
+                    "event": "skipped",
+                    "name": ".hidden",
+                    "reason": "hidden",
+                    "processed": 4,
+                    "total": 10,
+                }),
+                json!({ "event": "failed", "error": "disk full", "processed": 4, "total": 10 }),
+            ]
+        );
+    }
+
+    #[test]
+    fn counts_the_bytes_copied_of_the_file_being_extracted() {
+        let mut out = Vec::new();
+        {
+            let progress = ProgressWriter::new(&mut out, Some(14), Box::new(SimpleLogger));
+            progress.handle(ArchiveEvent::Extracting("a".to_string(), Some(4)));
+            progress.handle(ArchiveEvent::Extracting("b".to_string(), Some(10)));
+            progress.handle(ArchiveEvent::EntryProgress("b".to_string(), 6, Some(10)));
+        }
+
+        let last = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .last()
+            .unwrap()
+            .to_string();
+        assert_eq!(
+            serde_json::from_str::<Value>(&last).unwrap(),
+            json!({
+                "event": "entry_progress",
+                "name": "b",
+                "copied": 6,
+                "size": 10,
+                "processed": 10,
+                "total": 14,
+            })
+        );
+    }
+}
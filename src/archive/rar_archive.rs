@@ -0,0 +1,322 @@
+// read-only support for RAR archives through the unrar library, which only opens archives from
+// a path and hands out the content of an entry once fully decompressed
+
+use std::{cell::OnceCell, io::Read, ops::ControlFlow, path::PathBuf};
+
+use serde_json::json;
+use unrar::{
+    error::{Code, UnrarError},
+    FileHeader,
+};
+
+use super::{
+    dos_datetime,
+    edit::EntryEdit,
+    entry_visitor::{
+        extract_entries, find_first_entry, list_entries, open_entry, scan_entries, EntryVisitor,
+        VisitOptions, VisitedEntry,
+    },
+    password::PasswordProvider,
+    ArchiveError, ArchiveFileEntity, ArchiveFileEntityType, ArchiveMetadata, ArchiveScan,
+    ArchiveType, Archived, CreateOptions, CreateResult, DataSource, ExtractOptions, ExtractReport,
+    ListOptions, OpenOptions,
+};
+
+pub struct RarArchive {
+    pub(crate) source: DataSource,
+    scan: OnceCell<ArchiveScan>,
+}
+
+impl RarArchive {
+    fn path(&self) -> Result<PathBuf, ArchiveError> {
+        match &self.source {
+            DataSource::File(_, path) => Ok(PathBuf::from(path)),
+            DataSource::Memory(_)
+            | DataSource::Reader(_)
+            | DataSource::Pipe(_)
+            | DataSource::Volumes(_) => Err(ArchiveError::InvalidDataSource(
+                "rar archives can only be read from a file".to_string(),
+            )),
+        }
+    }
+
+    fn scan(&self, password: Option<&dyn PasswordProvider>) -> Result<&ArchiveScan, ArchiveError> {
+        ArchiveScan::cached(&self.scan, || scan_entries(self, password, None))
+    }
+
+    fn entity(header: &FileHeader) -> ArchiveFileEntity {
+        let mut name = header.filename.to_string_lossy().replace('\\', "/");
+        let (fstype, size) = if header.is_directory() {
+            if !name.ends_with('/') {
+                name.push('/');
+            }
+            (ArchiveFileEntityType::Directory, None)
+        } else {
+            (ArchiveFileEntityType::File, Some(header.unpacked_size))
+        };
+
+        ArchiveFileEntity {
+            name,
+            size,
+            // the library does not report packed sizes
+            compressed_size: None,
+            last_modified: dos_datetime(header.file_time),
+            compression: Some(method_name(header.method)),
+            fstype,
+            raw: None,
+            unix: None,
+        }
+    }
+}
+
+/// Whether reading with a password failed with `e` because the password is wrong, which older
+/// archives only tell by data failing to decode.
+fn is_bad_password(e: &UnrarError) -> bool {
+    matches!(
+        e.code,
+        Code::BadPassword | Code::MissingPassword | Code::BadData
+    )
+}
+
+/// Converts an error of reading the archive, into [`ArchiveError::WrongPassword`] if it was read
+/// `with_password` and the password is to blame.
+fn read_error(e: UnrarError, with_password: bool) -> ArchiveError {
+    if with_password && is_bad_password(&e) {
+        ArchiveError::WrongPassword
+    } else {
+        e.into()
+    }
+}
+
+impl EntryVisitor for RarArchive {
+    fn for_each_entry<F>(
+        &self,
+        options: &VisitOptions<'_>,
+        mut visit: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        let path = self.path()?;
+        // the password is given when opening, asked for the whole archive if the headers are
+        // encrypted and otherwise once the first encrypted file has to be read
+        let mut password = None;
+        let mut cursor = match unrar::Archive::new(&path).open_for_processing() {
+            Err(e) if e.code == Code::MissingPassword => {
+                let asked = password.insert(options.ask_password(None)?);
+                unrar::Archive::with_password(&path, asked)
+                    .open_for_processing()
+                    .map_err(|e| read_error(e, true))?
+            }
+            cursor => cursor?,
+        };
+        let mut visited = 0;
+        while let Some(header) = cursor
+            .read_header()
+            .map_err(|e| read_error(e, password.is_some()))?
+        {
+            let entity = Self::entity(header.entry());
+            if !options.wants(&entity) {
+                cursor = header.skip()?;
+                visited += 1;
+                continue;
+            }
+
+            let flow;
+            if options.contents && entity.fstype == ArchiveFileEntityType::File {
+                if password.is_none() && header.entry().is_encrypted() {
+                    // start over with the password, back to this entry
+                    let asked = password.insert(options.ask_password(Some(&entity.name))?);
+                    cursor = unrar::Archive::with_password(&path, asked).open_for_processing()?;
+                    for _ in 0..visited {
+                        cursor = match cursor.read_header()? {
+                            Some(header) => header.skip()?,
+                            None => return Ok(()),
+                        };
+                    }
+                    continue;
+                }
+                let (data, next) = header
+                    .read()
+                    .map_err(|e| read_error(e, password.is_some()))?;
+                cursor = next;
+                flow = visit(VisitedEntry::new(entity), Some(&mut data.as_slice()))?;
+            } else {
+                cursor = header.skip()?;
+                flow = visit(VisitedEntry::new(entity), None)?;
+            }
+            if flow.is_break() {
+                break;
+            }
+            visited += 1;
+        }
+        Ok(())
+    }
+}
+
+impl Archived for RarArchive {
+    fn of(source: DataSource) -> Result<Self, ArchiveError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            source,
+            scan: OnceCell::new(),
+        })
+    }
+
+    fn extract(&self, options: ExtractOptions) -> Result<ExtractReport, ArchiveError> {
+        extract_entries(self, &options, self.source.as_ref())
+    }
+
+    fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
+        if options.is_whole() {
+            return Ok(self.scan(options.password.as_deref())?.entries.clone());
+        }
+        list_entries(self, self.scan.get(), &options)
+    }
+
+    fn create(_options: CreateOptions) -> Result<CreateResult, ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "create".to_string(),
+            ArchiveType::Rar,
+        ))
+    }
+
+    fn metadata(&self) -> Result<ArchiveMetadata, ArchiveError> {
+        let path = self.path()?;
+        let archive = unrar::Archive::new(&path).open_for_listing()?;
+        let additional = json!({
+            "solid": archive.is_solid(),
+            "locked": archive.is_locked(),
+            "encrypted_headers": archive.has_encrypted_headers(),
+            "recovery_record": archive.has_recovery_record(),
+        });
+
+        let scan = self.scan(None)?;
+
+        Ok(ArchiveMetadata {
+            archive_type: ArchiveType::Rar,
+            entry_count: scan.entries.len(),
+            entries: scan.entries.clone(),
+            total_size: scan.total_size,
+            compressed_size: std::fs::metadata(&path)?.len(),
+            compression: None,
+            created: self.source.created(),
+            modified: self.source.modified(),
+            additional: Some(additional),
+        })
+    }
+
+    fn open(&self, options: OpenOptions) -> Result<(), ArchiveError> {
+        open_entry(self, options)
+    }
+
+    fn find_first<P>(
+        &self,
+        predicate: P,
+    ) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+    where
+        P: Fn(&ArchiveFileEntity) -> bool,
+    {
+        find_first_entry(self, predicate)
+    }
+
+    fn check_password(&self, password: &str) -> Result<bool, ArchiveError> {
+        let path = self.path()?;
+
+        // headers may be in the clear, testing the first encrypted file tells whether the
+        // content decrypts
+        let mut cursor = match unrar::Archive::with_password(&path, password).open_for_processing()
+        {
+            Ok(cursor) => cursor,
+            Err(e) if is_bad_password(&e) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
+        loop {
+            let header = match cursor.read_header() {
+                Ok(Some(header)) => header,
+                Ok(None) => return Ok(true),
+                Err(e) if is_bad_password(&e) => return Ok(false),
+                Err(e) => return Err(e.into()),
+            };
+            if !header.entry().is_encrypted() {
+                cursor = header.skip()?;
+                continue;
+            }
+            return match header.test() {
+                Ok(_) => Ok(true),
+                Err(e) if is_bad_password(&e) => Ok(false),
+                Err(e) => Err(e.into()),
+            };
+        }
+    }
+
+    fn edit(&self, _entry: &str, _edit: &EntryEdit) -> Result<(), ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "edit".to_string(),
+            ArchiveType::Rar,
+        ))
+    }
+}
+
+fn method_name(method: u32) -> String {
+    match method {
+        0x30 => "Stored".to_string(),
+        0x31 => "Fastest".to_string(),
+        0x32 => "Fast".to_string(),
+        0x33 => "Normal".to_string(),
+        0x34 => "Good".to_string(),
+        0x35 => "Best".to_string(),
+        m => format!("Unknown({})", m),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::archive::Archive;
+
+    #[test]
+    fn reads_rar_archives() {
+        let archive = Archive::of(DataSource::file("tests/fixtures/test1.rar").unwrap()).unwrap();
+        assert!(matches!(archive, Archive::Rar(_)));
+
+        let entries = archive.list(ListOptions::default()).unwrap();
+        let names = entries
+            .iter()
+            .map(|e| (e.name.as_str(), e.size))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                ("test1/dir1/", None),
+                ("test1/dir1/file2.txt", Some(444)),
+                ("test1/file1.txt", Some(1469)),
+            ]
+        );
+        assert_eq!(entries[1].compression.as_deref(), Some("Stored"));
+
+        let (entity, content) = archive
+            .find_first(|e| e.name == "test1/file1.txt")
+            .unwrap()
+            .unwrap();
+        assert_eq!(entity.size, Some(1469));
+        assert_eq!(
+            content,
+            std::fs::read("tests/fixtures/test1/file1.txt").unwrap()
+        );
+        assert!(archive.check_password("anything").unwrap());
+    }
+
+    #[test]
+    fn rejects_streams() {
+        let bytes = std::fs::read("tests/fixtures/test1.rar").unwrap();
+        let archive = Archive::of(DataSource::memory(bytes.as_slice())).unwrap();
+        assert!(matches!(
+            archive.list(ListOptions::default()),
+            Err(ArchiveError::InvalidDataSource(_))
+        ));
+    }
+}
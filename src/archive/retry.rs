@@ -0,0 +1,230 @@
+use std::{
+    io::{Error, ErrorKind, Read, Seek, SeekFrom, Write},
+    time::Duration,
+};
+
+use super::{ArchiveEvent, EventHandler};
+
+/// How reads from the archive source and writes to the extraction destination are retried when
+/// they fail with a transient error, as happens on network filesystems.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IoRetryPolicy {
+    /// Number of retries of a failed operation, `0` disables retrying.
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled after each one.
+    pub initial_backoff: Duration,
+    /// Upper bound of the delay between two retries.
+    pub max_backoff: Duration,
+}
+
+impl Default for IoRetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl IoRetryPolicy {
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            ..Default::default()
+        }
+    }
+
+    /// Delay before the `retry`-th retry, starting at 1.
+    pub fn backoff(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+
+    /// Whether `error` may go away by trying again.
+    pub fn is_transient(error: &Error) -> bool {
+        matches!(
+            error.kind(),
+            ErrorKind::Interrupted
+                | ErrorKind::WouldBlock
+                | ErrorKind::TimedOut
+                | ErrorKind::ConnectionReset
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::NotConnected
+                | ErrorKind::BrokenPipe
+                | ErrorKind::ResourceBusy
+        )
+    }
+
+    /// Runs `op`, retrying it on transient errors and reporting each retry on `events`.
+    pub(crate) fn run<T, F>(
+        &self,
+        name: &str,
+        events: Option<&dyn EventHandler>,
+        mut op: F,
+    ) -> Result<T, Error>
+    where
+        F: FnMut() -> Result<T, Error>,
+    {
+        let mut retry = 0;
+        loop {
+            match op() {
+                Err(e) if retry < self.max_retries && Self::is_transient(&e) => {
+                    retry += 1;
+                    let delay = self.backoff(retry);
+                    log::warn!("{}: {}, retrying in {:?}", name, e, delay);
+                    if let Some(events) = events {
+                        events.handle(ArchiveEvent::Retrying(name.to_string(), retry, e));
+                    }
+                    std::thread::sleep(delay);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Wraps `inner` so that its reads, writes and seeks follow this policy.
+    pub(crate) fn wrap<'e, T>(
+        self,
+        inner: T,
+        name: impl ToString,
+        events: Option<&'e dyn EventHandler>,
+    ) -> Retrying<'e, T> {
+        Retrying {
+            inner,
+            name: name.to_string(),
+            policy: self,
+            events,
+        }
+    }
+}
+
+/// Reader or writer whose operations are retried according to an [`IoRetryPolicy`].
+pub(crate) struct Retrying<'e, T> {
+    inner: T,
+    name: String,
+    policy: IoRetryPolicy,
+    events: Option<&'e dyn EventHandler>,
+}
+
+impl<T> Retrying<'_, T> {
+    pub(crate) fn get_ref(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: Read> Read for Retrying<'_, T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let inner = &mut self.inner;
+        self.policy.run(&self.name, self.events, || inner.read(buf))
+    }
+}
+
+impl<T: Seek> Seek for Retrying<'_, T> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let inner = &mut self.inner;
+        self.policy.run(&self.name, self.events, || inner.seek(pos))
+    }
+}
+
+impl<T: Write> Write for Retrying<'_, T> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let inner = &mut self.inner;
+        self.policy
+            .run(&self.name, self.events, || inner.write(buf))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        let inner = &mut self.inner;
+        self.policy.run(&self.name, self.events, || inner.flush())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::cell::{Cell, RefCell};
+
+    use super::*;
+
+    /// Fails the first `failures` reads with `kind`.
+    struct Flaky {
+        failures: Cell<u32>,
+        kind: ErrorKind,
+    }
+
+    impl Read for Flaky {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.failures.get() > 0 {
+                self.failures.set(self.failures.get() - 1);
+                return Err(Error::from(self.kind));
+            }
+            buf[0] = 42;
+            Ok(1)
+        }
+    }
+
+    #[derive(Default)]
+    struct Retries(RefCell<Vec<u32>>);
+
+    impl EventHandler for Retries {
+        fn handle(&self, event: ArchiveEvent) {
+            if let ArchiveEvent::Retrying(_, retry, _) = event {
+                self.0.borrow_mut().push(retry);
+            }
+        }
+    }
+
+    fn policy(max_retries: u32) -> IoRetryPolicy {
+        IoRetryPolicy {
+            max_retries,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(2),
+        }
+    }
+
+    fn flaky(failures: u32, kind: ErrorKind) -> Flaky {
+        Flaky {
+            failures: Cell::new(failures),
+            kind,
+        }
+    }
+
+    #[test]
+    fn retries_transient_errors() {
+        let events = Retries::default();
+        let mut reader = policy(3).wrap(flaky(2, ErrorKind::TimedOut), "source", Some(&events));
+
+        let mut buf = [0; 1];
+        assert_eq!(reader.read(&mut buf).unwrap(), 1);
+        assert_eq!(buf[0], 42);
+        assert_eq!(*events.0.borrow(), vec![1, 2]);
+    }
+
+    #[test]
+    fn gives_up() {
+        let mut buf = [0; 1];
+
+        let mut exhausted = policy(1).wrap(flaky(2, ErrorKind::TimedOut), "source", None);
+        assert_eq!(
+            exhausted.read(&mut buf).unwrap_err().kind(),
+            ErrorKind::TimedOut
+        );
+
+        let mut permanent = policy(3).wrap(flaky(1, ErrorKind::NotFound), "source", None);
+        assert_eq!(
+            permanent.read(&mut buf).unwrap_err().kind(),
+            ErrorKind::NotFound
+        );
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let policy = IoRetryPolicy::new(10);
+        assert_eq!(policy.backoff(1), Duration::from_millis(100));
+        assert_eq!(policy.backoff(3), Duration::from_millis(400));
+        assert_eq!(policy.backoff(10), Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,92 @@
+// cumulative sizes of directories, computed from a listing so that any backend benefits
+
+use std::collections::HashMap;
+
+use super::{entry_visitor::normalized_name, ArchiveFileEntity, ArchiveFileEntityType};
+
+/// Sets the size of every directory of `entries` to the total size of the entries beneath it,
+/// at any depth. Directories missing from the list are not added.
+pub fn roll_up_sizes(entries: &mut [ArchiveFileEntity]) {
+    let directories = entries
+        .iter()
+        .enumerate()
+        .filter(|(_, e)| e.fstype == ArchiveFileEntityType::Directory)
+        .map(|(i, e)| {
+            (
+                normalized_name(&e.name).trim_end_matches('/').to_string(),
+                i,
+            )
+        })
+        .collect::<HashMap<_, _>>();
+
+    let mut totals = vec![0; entries.len()];
+    for entry in entries
+        .iter()
+        .filter(|e| e.fstype != ArchiveFileEntityType::Directory)
+    {
+        let Some(size) = entry.size else {
+            continue;
+        };
+        let mut name = normalized_name(&entry.name).trim_end_matches('/');
+        while let Some((parent, _)) = name.rsplit_once('/') {
+            if let Some(i) = directories.get(parent) {
+                totals[*i] += size;
+            }
+            name = parent;
+        }
+    }
+
+    for i in directories.into_values() {
+        entries[i].size = Some(totals[i]);
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, size: Option<u64>) -> ArchiveFileEntity {
+        ArchiveFileEntity {
+            name: name.to_string(),
+            size,
+            compressed_size: None,
+            last_modified: None,
+            compression: None,
+            fstype: match name.ends_with('/') {
+                true => ArchiveFileEntityType::Directory,
+                false => ArchiveFileEntityType::File,
+            },
+            raw: None,
+            unix: None,
+        }
+    }
+
+    #[test]
+    fn rolls_up_sizes_of_directories() {
+        let mut entries = vec![
+            entry("./a/", None),
+            entry("./a/b/", None),
+            entry("./a/b/c.txt", Some(3)),
+            entry("./a/d.txt", Some(4)),
+            entry("./a/e/f.txt", Some(5)),
+            entry("./g/", None),
+            entry("./h.txt", Some(6)),
+        ];
+        roll_up_sizes(&mut entries);
+
+        let sizes = entries.iter().map(|e| e.size).collect::<Vec<_>>();
+        assert_eq!(
+            sizes,
+            vec![
+                Some(12),
+                Some(3),
+                Some(3),
+                Some(4),
+                Some(5),
+                Some(0),
+                Some(6)
+            ]
+        );
+    }
+}
@@ -0,0 +1,265 @@
+// quick look at unfamiliar archives: a random sample of their files along with the start of their
+// contents. Archives with an index of their entries only have the sampled files read, the others
+// are read once while keeping a reservoir of the files met so far.
+
+use std::{collections::HashSet, io::Read, ops::ControlFlow};
+
+use serde::Serialize;
+
+use super::{
+    entry_visitor::{EntryVisitor, VisitOptions},
+    password::PasswordProvider,
+    Archive, ArchiveError, ArchiveFileEntity, ArchiveFileEntityType,
+};
+
+#[derive(Debug, Clone)]
+pub struct SampleOptions {
+    /// Number of files to pick, all of them if the archive has fewer.
+    pub count: usize,
+    /// Number of bytes of each file to preview.
+    pub preview_size: usize,
+    /// Seed of the random picks, to sample the same files again.
+    pub seed: Option<u64>,
+}
+
+impl Default for SampleOptions {
+    fn default() -> Self {
+        Self {
+            count: 10,
+            preview_size: 64,
+            seed: None,
+        }
+    }
+}
+
+/// File picked from an archive with the start of its content.
+#[derive(Debug, Clone, Serialize)]
+pub struct SampledEntry {
+    #[serde(flatten)]
+    pub entity: ArchiveFileEntity,
+    /// Start of the content, as text if it is some, else as hexadecimal bytes.
+    pub preview: String,
+}
+
+/// Picks `options.count` files of `archive` at random, returned in archive order.
+pub fn sample_entries(
+    archive: &Archive,
+    password: Option<&dyn PasswordProvider>,
+    options: &SampleOptions,
+) -> Result<Vec<SampledEntry>, ArchiveError> {
+    let mut rng = options
+        .seed
+        .map_or_else(fastrand::Rng::new, fastrand::Rng::with_seed);
+    if options.count == 0 {
+        return Ok(Vec::new());
+    }
+    match has_index(archive) {
+        true => sample_indexed(archive, password, options, &mut rng),
+        false => sample_sequential(archive, password, options, &mut rng),
+    }
+}
+
+/// Whether the entries of `archive` can be listed, and then read one by one, without reading the
+/// contents of the others.
+fn has_index(archive: &Archive) -> bool {
+    match archive {
+        #[cfg(feature = "zip_archive")]
+        Archive::Zip(_) => true,
+        #[cfg(feature = "sevenz_archive")]
+        Archive::SevenZ(_) => true,
+        #[cfg(feature = "iso_archive")]
+        Archive::Iso(_) => true,
+        #[cfg(feature = "squashfs_archive")]
+        Archive::Squashfs(_) => true,
+        #[cfg(feature = "cab_archive")]
+        Archive::Cab(_) => true,
+        #[cfg(feature = "wim_archive")]
+        Archive::Wim(_) => true,
+        _ => false,
+    }
+}
+
+fn is_file(entity: &ArchiveFileEntity) -> bool {
+    entity.fstype == ArchiveFileEntityType::File
+}
+
+/// Lists the files, picks some of them, then only reads those.
+fn sample_indexed(
+    archive: &Archive,
+    password: Option<&dyn PasswordProvider>,
+    options: &SampleOptions,
+    rng: &mut fastrand::Rng,
+) -> Result<Vec<SampledEntry>, ArchiveError> {
+    let mut names = Vec::new();
+    let listing = VisitOptions {
+        password,
+        filter: Some(&is_file),
+        ..Default::default()
+    };
+    archive.for_each_entry(&listing, |entry, _| {
+        names.push(entry.entity.name);
+        Ok(ControlFlow::Continue(()))
+    })?;
+
+    rng.shuffle(&mut names);
+    names.truncate(options.count);
+    let picked = names.into_iter().collect::<HashSet<_>>();
+    let is_picked = |entity: &ArchiveFileEntity| is_file(entity) && picked.contains(&entity.name);
+    let reading = VisitOptions {
+        password,
+        filter: Some(&is_picked),
+        contents: true,
+        ..Default::default()
+    };
+
+    let mut sample = Vec::with_capacity(picked.len());
+    // only the first of the files sharing a name is kept
+    let mut taken = HashSet::new();
+    archive.for_each_entry(&reading, |entry, reader| {
+        let Some(reader) = reader else {
+            return Ok(ControlFlow::Continue(()));
+        };
+        if !taken.insert(entry.entity.name.clone()) {
+            return Ok(ControlFlow::Continue(()));
+        }
+        sample.push(SampledEntry {
+            preview: preview(reader, options.preview_size)?,
+            entity: entry.entity,
+        });
+        Ok(match sample.len() < picked.len() {
+            true => ControlFlow::Continue(()),
+            false => ControlFlow::Break(()),
+        })
+    })?;
+    Ok(sample)
+}
+
+/// Reads the archive once, each file replacing a random one of the sample with a decreasing
+/// probability so that all of them are as likely to be picked.
+fn sample_sequential(
+    archive: &Archive,
+    password: Option<&dyn PasswordProvider>,
+    options: &SampleOptions,
+    rng: &mut fastrand::Rng,
+) -> Result<Vec<SampledEntry>, ArchiveError> {
+    let reading = VisitOptions {
+        password,
+        filter: Some(&is_file),
+        contents: true,
+        ..Default::default()
+    };
+
+    // the files picked so far, with their position to return them in archive order
+    let mut sample: Vec<(usize, SampledEntry)> = Vec::with_capacity(options.count);
+    let mut seen = 0;
+    archive.for_each_entry(&reading, |entry, reader| {
+        let Some(reader) = reader else {
+            return Ok(ControlFlow::Continue(()));
+        };
+        let slot = match sample.len() < options.count {
+            true => Some(sample.len()),
+            false => Some(rng.usize(..=seen)).filter(|i| *i < options.count),
+        };
+        if let Some(slot) = slot {
+            let picked = SampledEntry {
+                preview: preview(reader, options.preview_size)?,
+                entity: entry.entity,
+            };
+            match slot < sample.len() {
+                true => sample[slot] = (seen, picked),
+                false => sample.push((seen, picked)),
+            }
+        }
+        seen += 1;
+        Ok(ControlFlow::Continue(()))
+    })?;
+
+    sample.sort_by_key(|(position, _)| *position);
+    Ok(sample.into_iter().map(|(_, entry)| entry).collect())
+}
+
+/// Renders the first `size` bytes of `reader` as text, or as hexadecimal bytes if they are not.
+fn preview(reader: &mut dyn Read, size: usize) -> Result<String, ArchiveError> {
+    let mut start = Vec::with_capacity(size);
+    reader.take(size as u64).read_to_end(&mut start)?;
+
+    // the last character may be cut, the text is only checked up to it
+    let text = match std::str::from_utf8(&start) {
+        Ok(text) => Some(text),
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&start[..e.valid_up_to()]).ok(),
+        Err(_) => None,
+    };
+    Ok(match text {
+        Some(text) if !text.contains('\0') => text
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .collect(),
+        _ => start
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect::<Vec<_>>()
+            .join(" "),
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::archive::DataSource;
+
+    fn sample(path: &str, count: usize, seed: u64) -> Vec<SampledEntry> {
+        let archive = Archive::of(DataSource::file(path).unwrap()).unwrap();
+        sample_entries(
+            &archive,
+            None,
+            &SampleOptions {
+                count,
+                preview_size: 16,
+                seed: Some(seed),
+            },
+        )
+        .unwrap()
+    }
+
+    #[cfg(all(
+        feature = "zip_archive",
+        feature = "deflate_codecs",
+        feature = "cpio_archive"
+    ))]
+    #[test]
+    fn samples_files_with_previews() {
+        for (path, order) in [
+            ("tests/fixtures/test1.zip", [0, 1]),
+            ("tests/fixtures/test1.cpio", [1, 0]),
+        ] {
+            let all = sample(path, 10, 1);
+            let names = all
+                .iter()
+                .map(|e| e.entity.name.as_str())
+                .collect::<Vec<_>>();
+            let expected = ["test1/dir1/file2.txt", "test1/file1.txt"];
+            assert_eq!(names, order.map(|i| expected[i]));
+            assert_eq!(all[order[1]].preview, "all your codebas");
+
+            let one = sample(path, 1, 7);
+            assert_eq!(one.len(), 1);
+            assert!(names.contains(&one[0].entity.name.as_str()));
+            assert_eq!(
+                sample(path, 1, 7)[0].entity.name,
+                one[0].entity.name,
+                "the same seed picks the same files"
+            );
+        }
+    }
+
+    #[test]
+    fn previews_binary_as_hex() {
+        let mut data: &[u8] = &[0x89, b'P', b'N', b'G', 0, 1];
+        assert_eq!(preview(&mut data, 4).unwrap(), "89 50 4e 47");
+        let mut text = "héllo\nworld".as_bytes();
+        assert_eq!(preview(&mut text, 2).unwrap(), "h");
+        let mut text = "héllo\nworld".as_bytes();
+        assert_eq!(preview(&mut text, 64).unwrap(), "héllo world");
+    }
+}
@@ -1,250 +1,614 @@
 use std::{
-    collections::HashSet,
-    fs::File,
-    io::{BufWriter, Error, Read, Write},
+    cell::OnceCell,
+    collections::VecDeque,
+    io::{Error, Read, Seek},
+    ops::ControlFlow,
 };
 
 use super::{
-    datetime_from_timestamp, ArchiveError, ArchiveEvent, ArchiveFileEntity, ArchiveFileEntityType,
-    ArchiveMetadata, Archived, CreateOptions, CreateResult, DataSource, EventHandler,
-    ExtractOptions, Lengthed, ListOptions, SimpleLogger, SkipReason, DEFAULT_BUF_SIZE,
+    datetime_from_timestamp,
+    edit::EntryEdit,
+    entries::{visit_options, ArchiveEntries, ArchiveEntry},
+    entry_visitor::{
+        extract_entries, find_first_entry, list_entries, open_entry, scan_entries, EntryVisitor,
+        VisitOptions, VisitedEntry,
+    },
+    password::PasswordProvider,
+    AppendOptions, ArchiveError, ArchiveFileEntity, ArchiveFileEntityType, ArchiveMetadata,
+    ArchiveScan, ArchiveType, Archived, CreateOptions, CreateResult, DataSource, ExtractOptions,
+    ExtractReport, Lengthed, ListOptions,
 };
-use byte_unit::Byte;
 use sevenz_rust::{BlockDecoder, Password, SevenZArchiveEntry, SevenZMethod, SevenZReader};
 
 #[cfg(feature = "lzma_codecs")]
-use sevenz_rust::SevenZWriter;
+use super::{
+    codecs::ArchiveCompression, SevenZContentMethod, SevenZCreateOptions, DEFAULT_BUF_SIZE,
+};
+#[cfg(feature = "lzma_codecs")]
+use byte_unit::Byte;
+#[cfg(feature = "lzma_codecs")]
+use sevenz_rust::nt_time::FileTime;
+#[cfg(feature = "lzma_codecs")]
+use sevenz_rust::{
+    lzma::{LZMA2Options, DICT_SIZE_MAX, DICT_SIZE_MIN},
+    MethodOptions, SeqReader, SevenZMethodConfiguration, SevenZWriter, SourceReader,
+};
+#[cfg(feature = "lzma_codecs")]
+use std::io::Write;
+#[cfg(feature = "lzma_codecs")]
+use std::{fs::File, io::BufWriter};
+
+/// Compression of the contents of the created archives, `level` being the preset of LZMA.
+#[cfg(feature = "lzma_codecs")]
+fn content_method(
+    options: &SevenZCreateOptions,
+    level: Option<i32>,
+) -> Result<SevenZMethodConfiguration, ArchiveError> {
+    let mut lzma = match ArchiveCompression::Lzma.checked_level(level)? {
+        Some(level) => LZMA2Options::with_preset(level as u32),
+        None => LZMA2Options::default(),
+    };
+    if let Some(size) = options.dictionary_size {
+        lzma.dict_size = size.clamp(DICT_SIZE_MIN, DICT_SIZE_MAX);
+    }
+    Ok(match options.method {
+        SevenZContentMethod::Lzma2 => lzma.into(),
+        SevenZContentMethod::Lzma => SevenZMethodConfiguration::new(SevenZMethod::LZMA)
+            .with_options(MethodOptions::LZMA2(lzma)),
+    })
+}
 
-pub struct SevenZArchive<'a> {
-    pub(crate) source: DataSource<'a>,
+/// Files compressed together once the block is full.
+#[cfg(feature = "lzma_codecs")]
+#[derive(Default)]
+struct SolidBlock {
+    entries: Vec<SevenZArchiveEntry>,
+    readers: Vec<SourceReader<File>>,
+    size: u64,
 }
 
-impl<'a> SevenZArchive<'a> {
+#[cfg(feature = "lzma_codecs")]
+impl SolidBlock {
+    fn push_to<W: Write + Seek>(&mut self, sz: &mut SevenZWriter<W>) -> Result<(), ArchiveError> {
+        if !self.entries.is_empty() {
+            let block = std::mem::take(self);
+            sz.push_archive_entries(block.entries, SeqReader::new(block.readers))?;
+        }
+        Ok(())
+    }
+}
+
+/// 7z archive written by [`Archive::convert`](super::Archive::convert), each file compressed on
+/// its own.
+#[cfg(feature = "lzma_codecs")]
+pub(crate) struct SevenZEntryWriter {
+    writer: SevenZWriter<BufWriter<File>>,
+}
+
+#[cfg(feature = "lzma_codecs")]
+impl SevenZEntryWriter {
+    pub(crate) fn new(file: File, level: Option<i32>) -> Result<Self, ArchiveError> {
+        let mut writer = SevenZWriter::new(BufWriter::with_capacity(DEFAULT_BUF_SIZE, file))?;
+        writer.set_content_methods(vec![content_method(
+            &SevenZCreateOptions::default(),
+            level,
+        )?]);
+        Ok(Self { writer })
+    }
+}
+
+#[cfg(feature = "lzma_codecs")]
+impl super::convert::EntryWriter for SevenZEntryWriter {
+    fn write(
+        &mut self,
+        entry: &VisitedEntry,
+        reader: Option<&mut dyn Read>,
+    ) -> Result<bool, ArchiveError> {
+        // links are not stored
+        if entry.link.is_some() {
+            return Ok(false);
+        }
+        let entity = &entry.entity;
+        let mut sz_entry = SevenZArchiveEntry::new();
+        sz_entry.name = entity.name.trim_end_matches('/').to_string();
+        sz_entry.has_stream = reader.is_some();
+        sz_entry.is_directory = entity.fstype == ArchiveFileEntityType::Directory;
+        let modified = entity
+            .last_modified
+            .and_then(|modified| FileTime::from_unix_time(modified.timestamp()).ok());
+        if let Some(modified) = modified {
+            sz_entry.last_modified_date = modified;
+            sz_entry.has_last_modified_date = true;
+        }
+        self.writer.push_archive_entry(sz_entry, reader)?;
+        Ok(true)
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), ArchiveError> {
+        self.writer.finish()?.flush()?;
+        Ok(())
+    }
+}
+
+pub struct SevenZArchive {
+    pub(crate) source: DataSource,
+    scan: OnceCell<ArchiveScan>,
+}
+
+impl SevenZArchive {
     #[inline]
-    fn reader(&'a self) -> Result<DataSource<'a>, Error> {
+    fn reader(&self) -> Result<DataSource, Error> {
         self.source.try_clone()
     }
-}
 
-impl<'a> Archived<'a> for SevenZArchive<'a> {
-    fn of(source: DataSource<'a>) -> Result<Self, ArchiveError>
-    where
-        Self: Sized,
-    {
-        Ok(Self { source })
-    }
-
-    fn extract(&self, options: ExtractOptions) -> Result<(), ArchiveError> {
-        let reader = self.reader()?;
-        let reader_len: u64 = reader.len()?;
-        let mut sz = SevenZReader::new(
-            reader,
-            // reader_len: u64
-            reader_len,
-            // password
-            match options.password {
-                None => Password::empty(),
-                Some(ref p) => Password::from(p.as_str()),
-            },
-        )?;
-
-        let files = options
-            .files
-            .clone()
-            .map(|f| f.into_iter().collect::<HashSet<_>>());
-
-        let _total_size: u64 = sz
-            .archive()
-            .files
-            .iter()
-            .filter(|e| e.has_stream())
-            .map(|e| e.size())
-            .sum();
-
-        let mut uncompressed_size = 0;
-        sz.for_each_entries(|entry, reader| {
-            let mut buf = [0u8; 1024];
-            let path = &options.destination.join(entry.name());
-
-            if !options.overwrite && path.exists() {
-                options.handle(ArchiveEvent::Skipped(
-                    entry.name().to_string(),
-                    SkipReason::AlreadyExists,
-                ));
-                return Ok(true);
-            }
+    fn scan(&self, password: Option<&dyn PasswordProvider>) -> Result<&ArchiveScan, ArchiveError> {
+        ArchiveScan::cached(&self.scan, || scan_entries(self, password, None))
+    }
 
-            if let Some(files) = &files {
-                if !files.contains(&entry.name().to_string()) {
-                    return Ok(true);
-                }
-            }
+    /// Streams the entries of the archive to a new one ending with the files of `options`, the
+    /// headers being compressed along with the offsets of the packed streams.
+    #[cfg_attr(not(feature = "lzma_codecs"), allow(unused_variables))]
+    pub(crate) fn append(&self, options: AppendOptions) -> Result<CreateResult, ArchiveError> {
+        #[cfg(not(feature = "lzma_codecs"))]
+        {
+            Err(ArchiveError::UnsupportedActionForArchiveType(
+                "append".to_string(),
+                ArchiveType::SevenZ,
+            ))
+        }
 
-            if entry.is_directory() {
-                options.handle(ArchiveEvent::Extracting(entry.name().to_string(), None));
-                std::fs::create_dir_all(path)?;
-                Ok(true)
-            } else if entry.has_stream() {
-                options.handle(ArchiveEvent::Extracting(
-                    entry.name().to_string(),
-                    Some(entry.size()),
-                ));
-                if let Some(p) = path.parent() {
-                    if !p.exists() {
-                        std::fs::create_dir_all(p)?;
+        #[cfg(feature = "lzma_codecs")]
+        {
+            use super::{
+                edit::{archive_path, rewrite},
+                entry_visitor::normalized_name,
+                ArchiveEvent, EventHandler,
+            };
+
+            let path = archive_path(&self.source)?;
+            let mut reader = self.reader()?;
+            let len = reader.len()?;
+            let mut sz = SevenZReader::new(&mut reader, len, Password::empty())?;
+
+            let mut total_size = 0;
+            rewrite(&path, "", |out| {
+                let mut writer =
+                    SevenZWriter::new(BufWriter::with_capacity(DEFAULT_BUF_SIZE, out))?;
+                writer.set_content_methods(vec![content_method(&options.sevenz, options.level)?]);
+                let replaced = options.replaced();
+                sz.for_each_entries(|entry, reader| {
+                    if replaced.contains(normalized_name(entry.name())) {
+                        // the content still has to be read past in solid blocks
+                        std::io::copy(reader, &mut std::io::sink())?;
+                        return Ok(true);
                     }
-                }
+                    let reader = entry.has_stream.then_some(reader);
+                    writer.push_archive_entry(entry.clone(), reader)?;
+                    Ok(true)
+                })?;
 
-                let mut file = File::create(path)?;
-                loop {
-                    let read_size = reader.read(&mut buf)?;
-                    if read_size == 0 {
-                        break Ok(true);
+                for file in &options.files {
+                    let metadata = std::fs::metadata(file)?;
+                    let name = options.entry_name(file).to_string_lossy().to_string();
+                    // the source directory itself, which is the top level of the archive
+                    if name.is_empty() {
+                        continue;
                     }
-                    file.write_all(&buf[..read_size])?;
-                    uncompressed_size += read_size;
+                    eprintln!("Adding: {} -> {}", file.display(), name);
+                    let entry = SevenZArchiveEntry::from_path(file, name.clone());
+                    let reader = (!metadata.is_dir()).then(|| File::open(file)).transpose()?;
+                    let res = writer.push_archive_entry::<File>(entry, reader)?;
+                    total_size += res.size();
+                    options.handle(ArchiveEvent::Added(name, Some(res.size())));
                 }
-            } else {
-                options.handle(ArchiveEvent::Skipped(
-                    entry.name().to_string(),
-                    SkipReason::UnknownType,
-                ));
+                writer.finish()?;
                 Ok(true)
+            })?;
+            Ok(CreateResult {
+                compressed_size: std::fs::metadata(&path)?.len(),
+                path,
+                total_size,
+                unreadable: Vec::new(),
+            })
+        }
+    }
+
+    fn visited_entry(data: &SevenZForEachEntryData) -> VisitedEntry {
+        let entry = data.entry;
+        let estimated_compress_ratio = match (data.folder_pack_size, data.folder_unpack_size) {
+            (Some(pack_size), Some(unpack_size)) => {
+                if pack_size == 0 {
+                    None
+                } else {
+                    Some(unpack_size as f64 / pack_size as f64)
+                }
             }
-        })?;
+            _ => None,
+        };
 
-        options.handle(ArchiveEvent::DoneExtracting(
-            self.source.as_ref().to_string(),
-            options.destination.to_string_lossy().to_string(),
-        ));
-        Ok(())
-    }
+        let estimated_compressed_size = match estimated_compress_ratio {
+            Some(ratio) => (entry.size() as f64 / ratio) as u64,
+            None => entry.size(),
+        };
 
-    fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
-        // eprintln!("list: options: {:?}", options);
-        let mut reader = self.reader()?;
+        let last_modified = entry.last_modified_date;
+        let fstype = if entry.is_directory {
+            ArchiveFileEntityType::Directory
+        } else if entry.has_stream {
+            ArchiveFileEntityType::File
+        } else {
+            ArchiveFileEntityType::Unknown
+        };
+        let (size, compressed_size) = if entry.has_stream {
+            (Some(entry.size()), Some(estimated_compressed_size))
+        } else {
+            (None, None)
+        };
+
+        let entity = ArchiveFileEntity {
+            name: entry.name.to_string(),
+            size,
+            compressed_size,
+            fstype,
+            last_modified: if entry.has_last_modified_date {
+                datetime_from_timestamp(last_modified.to_unix_time()).ok()
+            } else {
+                None
+            },
+            compression: data.compression.map(|c| c.name().to_string()),
+            raw: None,
+            unix: None,
+        };
+        VisitedEntry {
+            crc32: (entry.has_stream && entry.has_crc).then_some(entry.crc as u32),
+            ..VisitedEntry::new(entity)
+        }
+    }
 
+    /// Reads the headers of the archive, along with the password they are encrypted with if they
+    /// are.
+    fn read_headers(
+        &self,
+        options: &VisitOptions<'_>,
+    ) -> Result<(sevenz_rust::Archive, Password), ArchiveError> {
+        let reader = self.source.try_clone()?;
         let len = reader.len()?;
-        let pw = options
-            .password
-            .clone()
-            .map_or(Password::empty(), |p| Password::from(p.as_str()));
+        let mut reader = options.retry.wrap(reader, &self.source, options.events);
 
-        let sz = SevenZReader::new(&mut reader, len, pw)?;
+        // the password is asked for the whole archive, when the headers are encrypted or when
+        // the folders holding wanted entries are
+        match sevenz_rust::Archive::read(&mut reader, len, &[]) {
+            Err(sevenz_rust::Error::PasswordRequired) => {
+                let password = Password::from(options.ask_password(None)?.as_str());
+                let mut reader =
+                    options
+                        .retry
+                        .wrap(self.source.try_clone()?, &self.source, options.events);
+                let archive = sevenz_rust::Archive::read(&mut reader, len, password.as_slice())
+                    .map_err(|e| read_error(e, true))?;
+                Ok((archive, password))
+            }
+            archive => Ok((archive?, Password::empty())),
+        }
+    }
 
-        let mut entries = Vec::<ArchiveFileEntity>::new();
+    pub(crate) fn entries<'s>(&'s self, options: ListOptions<'s>) -> ArchiveEntries<'s> {
+        let open = || -> Result<SevenZEntries<'s>, ArchiveError> {
+            let visit = visit_options(&options);
+            let (archive, mut password) = self.read_headers(&visit)?;
+            let wanted = wanted_entries(&archive, &visit);
+            if password.is_empty() && has_encrypted_folder(&archive, &wanted) {
+                password = Password::from(visit.ask_password(None)?.as_str());
+            }
+            Ok(SevenZEntries {
+                archive,
+                password,
+                source: self.source.clone(),
+                wanted,
+                folder: 0,
+                file: 0,
+                decoded: VecDeque::new(),
+            })
+        };
+        match open() {
+            Ok(entries) => ArchiveEntries::new(entries),
+            Err(e) => ArchiveEntries::failed(e),
+        }
+    }
+}
 
-        let mut reader = self.reader()?;
+/// Iterator of [`SevenZArchive::entries`]. Folders are decoded one at a time, their wanted
+/// entries being held in memory until they are yielded, then come the entries without content.
+struct SevenZEntries<'a> {
+    archive: sevenz_rust::Archive,
+    password: Password,
+    source: DataSource,
+    wanted: Vec<bool>,
+    /// Next folder to decode.
+    folder: usize,
+    /// Next file to look at once all the folders were decoded.
+    file: usize,
+    decoded: VecDeque<ArchiveEntry<'a>>,
+}
 
-        for_each_entries(
-            sz.archive(),
-            Password::from(options.password.as_deref().unwrap_or_default()),
-            &mut reader,
-            |data, _reader| {
-                let entry = data.entry;
-                let estimated_compress_ratio =
-                    match (data.folder_pack_size, data.folder_unpack_size) {
-                        (Some(pack_size), Some(unpack_size)) => {
-                            if pack_size == 0 {
-                                None
-                            } else {
-                                Some(unpack_size as f64 / pack_size as f64)
-                            }
-                        }
-                        _ => None,
+impl<'a> SevenZEntries<'a> {
+    fn next_entry(&mut self) -> Result<Option<ArchiveEntry<'a>>, ArchiveError> {
+        let archive = &self.archive;
+        while self.decoded.is_empty() && self.folder < archive.folders.len() {
+            let folder_index = self.folder;
+            self.folder += 1;
+            let start = archive.stream_map.folder_first_file_index[folder_index];
+            let count = archive.folders[folder_index].num_unpack_sub_streams;
+            if !self.wanted[start..start + count].contains(&true) {
+                continue;
+            }
+
+            let with_password = !self.password.is_empty();
+            let mut source = self.source.clone();
+            let decoder =
+                BlockDecoder::new(folder_index, archive, self.password.as_slice(), &mut source);
+            let mut file_index = start;
+            decoder
+                .for_each_entries(&mut |entry, reader| {
+                    file_index += 1;
+                    let visited = SevenZArchive::visited_entry(&SevenZForEachEntryData::of(
+                        archive,
+                        entry,
+                        Some(folder_index),
+                    ));
+                    // entries of a folder share a single decoder, the unwanted ones are skipped
+                    let mut content = Vec::new();
+                    let read = match self.wanted[file_index - 1] {
+                        true => reader.read_to_end(&mut content),
+                        false => std::io::copy(reader, &mut std::io::sink()).map(|n| n as usize),
                     };
+                    read.map_err(|e| sevenz_rust::Error::bad_password(e, with_password))?;
+                    if self.wanted[file_index - 1] {
+                        let content = std::io::Cursor::new(content);
+                        self.decoded
+                            .push_back(ArchiveEntry::new(visited, Box::new(content)));
+                    }
+                    Ok(true)
+                })
+                .map_err(|e| read_error(e, with_password))?;
+        }
+        if let Some(entry) = self.decoded.pop_front() {
+            return Ok(Some(entry));
+        }
 
-                let estimated_compressed_size = match estimated_compress_ratio {
-                    Some(ratio) => (entry.size() as f64 / ratio) as u64,
-                    None => entry.size(),
-                };
+        while self.file < archive.files.len() {
+            let file_index = self.file;
+            self.file += 1;
+            if archive.stream_map.file_folder_index[file_index].is_none() && self.wanted[file_index]
+            {
+                let data = SevenZForEachEntryData::of(archive, &archive.files[file_index], None);
+                return Ok(Some(ArchiveEntry::empty(SevenZArchive::visited_entry(
+                    &data,
+                ))));
+            }
+        }
+        Ok(None)
+    }
+}
 
-                let last_modified = entry.last_modified_date;
-                let fstype = if entry.is_directory {
-                    ArchiveFileEntityType::Directory
-                } else if entry.has_stream {
-                    ArchiveFileEntityType::File
-                } else {
-                    ArchiveFileEntityType::Unknown
+impl<'a> Iterator for SevenZEntries<'a> {
+    type Item = Result<ArchiveEntry<'a>, ArchiveError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let next = self.next_entry();
+        if next.is_err() {
+            // the folder failed to decode, the next ones are tried on the next call
+            self.decoded.clear();
+        }
+        next.transpose()
+    }
+}
+
+/// Whether each file of `archive` is selected by `options`.
+fn wanted_entries(archive: &sevenz_rust::Archive, options: &VisitOptions<'_>) -> Vec<bool> {
+    archive
+        .files
+        .iter()
+        .enumerate()
+        .map(|(file_index, entry)| {
+            let folder_index = archive.stream_map.file_folder_index[file_index];
+            let data = SevenZForEachEntryData::of(archive, entry, folder_index);
+            options.wants(&SevenZArchive::visited_entry(&data).entity)
+        })
+        .collect()
+}
+
+impl EntryVisitor for SevenZArchive {
+    fn for_each_entry<F>(
+        &self,
+        options: &VisitOptions<'_>,
+        mut visit: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        let (archive, mut password) = self.read_headers(options)?;
+        let archive = &archive;
+
+        if !options.contents {
+            // headers describe every entry, no need to decode the folders
+            for (file_index, entry) in archive.files.iter().enumerate() {
+                let folder_index = archive.stream_map.file_folder_index[file_index];
+                let visited =
+                    Self::visited_entry(&SevenZForEachEntryData::of(archive, entry, folder_index));
+                if options.wants(&visited.entity) && visit(visited, None)?.is_break() {
+                    break;
+                }
+            }
+            return Ok(());
+        }
+
+        // selecting the entries up front lets folders without any be skipped undecoded
+        let wanted = wanted_entries(archive, options);
+        if password.is_empty() && has_encrypted_folder(archive, &wanted) {
+            password = Password::from(options.ask_password(None)?.as_str());
+        }
+
+        let with_password = !password.is_empty();
+        let mut failure = None;
+        let mut reader = options
+            .retry
+            .wrap(self.reader()?, &self.source, options.events);
+        for_each_entries(
+            archive,
+            password,
+            &mut reader,
+            &wanted,
+            |file_index, data, reader| {
+                let visited = Self::visited_entry(&data);
+                let mut reader = DecodeFailures {
+                    inner: reader,
+                    failed: false,
                 };
-                let (size, compressed_size) = if entry.has_stream {
-                    (Some(entry.size()), Some(estimated_compressed_size))
+                let flow = if !wanted[file_index] {
+                    ControlFlow::Continue(())
+                } else if data.entry.is_directory {
+                    match visit(visited, None) {
+                        Ok(flow) => flow,
+                        Err(e) => {
+                            failure = Some(e);
+                            ControlFlow::Break(())
+                        }
+                    }
                 } else {
-                    (None, None)
-                };
-                let entity = ArchiveFileEntity {
-                    name: entry.name.to_string(),
-                    size,
-                    compressed_size,
-                    fstype,
-                    last_modified: if entry.has_last_modified_date {
-                        datetime_from_timestamp(last_modified.to_unix_time()).ok()
-                    } else {
-                        None
-                    },
-                    compression: data.compression.map(|c| c.name().to_string()),
+                    match visit(visited, Some(&mut reader)) {
+                        Ok(flow) => flow,
+                        Err(_) if with_password && reader.failed => {
+                            failure = Some(ArchiveError::WrongPassword);
+                            ControlFlow::Break(())
+                        }
+                        Err(e) => {
+                            failure = Some(e);
+                            ControlFlow::Break(())
+                        }
+                    }
                 };
 
-                entries.push(entity);
-
-                Ok(true)
+                // entries of a folder share a single decoder, unread data has to be skipped
+                // before moving on to the next one
+                if flow.is_continue() {
+                    std::io::copy(&mut reader, &mut std::io::sink())
+                        .map_err(|e| sevenz_rust::Error::bad_password(e, with_password))?;
+                }
+                Ok(flow.is_continue())
             },
-        )?;
+        )
+        .map_err(|e| read_error(e, with_password))?;
+
+        failure.map_or(Ok(()), Err)
+    }
+}
+
+impl Archived for SevenZArchive {
+    fn of(source: DataSource) -> Result<Self, ArchiveError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            source,
+            scan: OnceCell::new(),
+        })
+    }
 
-        Ok(entries)
+    fn extract(&self, options: ExtractOptions) -> Result<ExtractReport, ArchiveError> {
+        extract_entries(self, &options, self.source.as_ref())
+    }
+
+    fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
+        if options.is_whole() {
+            return Ok(self.scan(options.password.as_deref())?.entries.clone());
+        }
+        list_entries(self, self.scan.get(), &options)
     }
 
+    #[cfg_attr(not(feature = "lzma_codecs"), allow(unused_variables))]
     fn create(options: CreateOptions) -> Result<CreateResult, ArchiveError> {
         #[cfg(not(feature = "lzma_codecs"))]
         {
             Err(ArchiveError::UnsupportedActionForArchiveType(
                 "create".to_string(),
-                crate::archive::ArchiveType::SevenZ,
+                ArchiveType::SevenZ,
             ))
         }
 
         #[cfg(feature = "lzma_codecs")]
         {
+            use super::{ArchiveEvent, EventHandler};
+
             let writer = File::create(&options.destination)?;
             let buf_writer = BufWriter::with_capacity(DEFAULT_BUF_SIZE, writer);
 
             let mut sz = SevenZWriter::new(buf_writer)?;
+            sz.set_content_methods(vec![content_method(
+                &options.sevenz,
+                options.compression_level(),
+            )?]);
 
             let mut total_size: u64 = 0;
             let mut total_compressed_size: u64 = 0;
 
-            for file in options.files {
-                let metadata = std::fs::metadata(&file)?;
+            // files waiting to be compressed together in the current solid block
+            let mut block = SolidBlock::default();
+            for file in &options.files {
+                let metadata = std::fs::metadata(file)?;
                 eprintln!(
                     "Adding: {} ({})",
                     file.display(),
                     Byte::from(metadata.len()).get_appropriate_unit(byte_unit::UnitType::Both)
                 );
-                let res = sz.push_archive_entry::<File>(
-                    SevenZArchiveEntry::from_path(
-                        &file,
-                        file.strip_prefix(&options.source)
-                            .as_deref()
-                            .unwrap_or(&file)
-                            .to_string_lossy()
-                            .to_string(),
-                    ),
-                    Some(File::open(file)?),
-                )?;
+                let name = options.entry_name(file).to_string_lossy().to_string();
+                // the source directory itself, whose entries are at the top level
+                if metadata.is_dir() && name.is_empty() {
+                    continue;
+                }
+                let entry = SevenZArchiveEntry::from_path(file, name.clone());
+                if options.sevenz.solid && metadata.is_file() && metadata.len() > 0 {
+                    if options
+                        .sevenz
+                        .solid_block_size
+                        .is_some_and(|max| block.size > 0 && block.size + metadata.len() > max)
+                    {
+                        block.push_to(&mut sz)?;
+                    }
+                    block.size += metadata.len();
+                    block.entries.push(entry);
+                    block.readers.push(SourceReader::new(File::open(file)?));
+                    total_size += metadata.len();
+                    options.handle(ArchiveEvent::Added(name, Some(metadata.len())));
+                    continue;
+                }
+
+                let reader = (!metadata.is_dir()).then(|| File::open(file)).transpose()?;
+                let res = sz.push_archive_entry::<File>(entry, reader)?;
                 total_size += res.size();
                 total_compressed_size += res.compressed_size;
+                options.handle(ArchiveEvent::Added(name, Some(res.size())));
             }
+            block.push_to(&mut sz)?;
 
             sz.finish()?;
+            if options.sevenz.solid {
+                // solid blocks are not sized entry by entry
+                total_compressed_size = std::fs::metadata(&options.destination)?.len();
+            }
             eprintln!(
                 "Done creating 7z archive: {} ({})",
                 options.destination.display(),
                 Byte::from(total_size).get_appropriate_unit(byte_unit::UnitType::Both)
             );
+            options.handle(ArchiveEvent::DoneCreating(
+                options.destination.to_string_lossy().to_string(),
+            ));
             Ok(CreateResult {
                 path: options.destination,
                 total_size,
                 compressed_size: total_compressed_size,
+                unreadable: Vec::new(),
             })
         }
     }
@@ -255,52 +619,112 @@ impl<'a> Archived<'a> for SevenZArchive<'a> {
         let pw = Password::empty();
         let sz = SevenZReader::new(&mut reader, len, pw)?;
 
-        let entries = self.list(ListOptions {
-            password: None,
-            event_handler: Box::new(SimpleLogger),
-        })?;
-
-        let size = entries.iter().filter_map(|f| f.size).sum();
+        let scan = self.scan(None)?;
 
         Ok(ArchiveMetadata {
-            entries,
-            total_size: size,
+            archive_type: ArchiveType::SevenZ,
+            entry_count: scan.entries.len(),
+            entries: scan.entries.clone(),
+            total_size: scan.total_size,
             compression: None,
             compressed_size: sz.archive().pack_sizes.iter().sum(),
+            created: self.source.created(),
+            modified: self.source.modified(),
             additional: None,
         })
     }
 
-    fn open(&self, mut options: super::OpenOptions) -> Result<(), ArchiveError> {
-        let path = options.path.to_string_lossy().to_string();
-        let pw = match options.password {
-            None => Password::empty(),
-            Some(ref p) => Password::from(p.as_str()),
-        };
+    fn open(&self, options: super::OpenOptions) -> Result<(), ArchiveError> {
+        open_entry(self, options)
+    }
 
-        let mut reader = self.reader()?;
+    fn find_first<P>(
+        &self,
+        predicate: P,
+    ) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+    where
+        P: Fn(&ArchiveFileEntity) -> bool,
+    {
+        find_first_entry(self, predicate)
+    }
 
+    fn check_password(&self, password: &str) -> Result<bool, ArchiveError> {
+        let mut reader = self.reader()?;
         let len = reader.len()?;
 
-        let mut sz = SevenZReader::new(&mut reader, len, pw)?;
-
-        let mut found = false;
+        let mut sz = match SevenZReader::new(&mut reader, len, Password::from(password)) {
+            Ok(sz) => sz,
+            Err(e) if is_bad_password(&e) => return Ok(false),
+            Err(e) => return Err(e.into()),
+        };
 
-        sz.for_each_entries(|entry, reader| {
-            if entry.name() == path {
-                std::io::copy(reader, &mut options.dest)?;
-                found = true;
-            } else {
-                // still need to read the stream
-                std::io::copy(reader, &mut std::io::sink())?;
+        // headers may be in the clear, decoding the first stream tells whether the
+        // content decrypts
+        let result = sz.for_each_entries(|entry, reader| {
+            if !entry.has_stream() {
+                return Ok(true);
             }
-            Ok(!found)
-        })?;
+            std::io::copy(reader, &mut std::io::sink())?;
+            Ok(false)
+        });
 
-        if found {
-            Ok(())
-        } else {
-            Err(ArchiveError::EntryNotFound(options.path))
+        match result {
+            Ok(()) => Ok(true),
+            Err(e) if is_bad_password(&e) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn edit(&self, target: &str, edit: &EntryEdit) -> Result<(), ArchiveError> {
+        #[cfg(not(feature = "lzma_codecs"))]
+        {
+            _ = (target, edit);
+            Err(ArchiveError::UnsupportedActionForArchiveType(
+                "edit".to_string(),
+                ArchiveType::SevenZ,
+            ))
+        }
+
+        // the header holding names and times is compressed along with the offsets of the
+        // packed streams, the entries are recompressed into a new archive
+        #[cfg(feature = "lzma_codecs")]
+        {
+            use super::edit::{archive_path, rewrite};
+
+            let path = archive_path(&self.source)?;
+            let mut reader = self.reader()?;
+            let len = reader.len()?;
+            let mut sz = SevenZReader::new(&mut reader, len, Password::empty())?;
+            let modified = match edit {
+                EntryEdit::Touch(modified) => Some(
+                    FileTime::from_unix_time(modified.timestamp())
+                        .map_err(|e| Error::new(std::io::ErrorKind::InvalidInput, e))?,
+                ),
+                EntryEdit::Rename(_) => None,
+            };
+
+            rewrite(&path, target, |out| {
+                let mut writer =
+                    SevenZWriter::new(BufWriter::with_capacity(DEFAULT_BUF_SIZE, out))?;
+                let mut edited = false;
+                sz.for_each_entries(|entry, reader| {
+                    let mut entry = entry.clone();
+                    edited |= edit.applies(target, &entry.name);
+                    if let Some(name) = edit.renamed(target, &entry.name) {
+                        entry.name = name;
+                    }
+                    if let Some(modified) = modified.filter(|_| edit.applies(target, &entry.name)) {
+                        entry.last_modified_date = modified;
+                        entry.has_last_modified_date = true;
+                    }
+
+                    let reader = entry.has_stream.then_some(reader);
+                    writer.push_archive_entry(entry, reader)?;
+                    Ok(true)
+                })?;
+                writer.finish()?;
+                Ok(edited)
+            })
         }
     }
 }
@@ -312,45 +736,135 @@ struct SevenZForEachEntryData<'a> {
     compression: Option<SevenZMethod>,
 }
 
+impl<'a> SevenZForEachEntryData<'a> {
+    fn of(
+        archive: &sevenz_rust::Archive,
+        entry: &'a SevenZArchiveEntry,
+        folder_index: Option<usize>,
+    ) -> Self {
+        match folder_index {
+            Some(folder_index) => Self {
+                entry,
+                folder_unpack_size: archive
+                    .folders
+                    .get(folder_index)
+                    .map(|f| f.get_unpack_size()),
+                folder_pack_size: archive.pack_sizes.get(folder_index).copied(),
+                compression: folder_compression(archive, folder_index),
+            },
+            None => Self {
+                entry,
+                folder_unpack_size: None,
+                folder_pack_size: None,
+                compression: None,
+            },
+        }
+    }
+}
+
+fn folder_compression(archive: &sevenz_rust::Archive, folder_index: usize) -> Option<SevenZMethod> {
+    archive
+        .folders
+        .get(folder_index)
+        .and_then(|f| {
+            f.ordered_coder_iter()
+                .next()
+                .map(|(_, c)| c.decompression_method_id())
+        })
+        .and_then(SevenZMethod::by_id)
+}
+
+/// Whether the content of one of the `wanted` entries is encrypted.
+/// Reader of an entry noting whether its data failed to decode, which for encrypted entries means
+/// the password is wrong.
+struct DecodeFailures<'r> {
+    inner: &'r mut dyn Read,
+    failed: bool,
+}
+
+impl Read for DecodeFailures<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf);
+        self.failed |= read.is_err();
+        read
+    }
+}
+
+/// Whether reading with a password failed with `e` most likely because the password is wrong,
+/// the decrypted data then failing to decode or to match its checksum.
+fn is_bad_password(e: &sevenz_rust::Error) -> bool {
+    matches!(
+        e,
+        sevenz_rust::Error::PasswordRequired
+            | sevenz_rust::Error::MaybeBadPassword(_)
+            | sevenz_rust::Error::ChecksumVerificationFailed
+            | sevenz_rust::Error::NextHeaderCrcMismatch
+    )
+}
+
+/// Converts an error of reading the archive, into [`ArchiveError::WrongPassword`] if it was read
+/// `with_password` and the password is to blame.
+fn read_error(e: sevenz_rust::Error, with_password: bool) -> ArchiveError {
+    if with_password && is_bad_password(&e) {
+        ArchiveError::WrongPassword
+    } else {
+        e.into()
+    }
+}
+
+fn has_encrypted_folder(archive: &sevenz_rust::Archive, wanted: &[bool]) -> bool {
+    archive
+        .stream_map
+        .file_folder_index
+        .iter()
+        .zip(wanted)
+        .filter_map(|(folder, &wanted)| folder.filter(|_| wanted))
+        .any(|folder| {
+            archive.folders[folder]
+                .coders
+                .iter()
+                .any(|c| c.decompression_method_id() == SevenZMethod::ID_AES256SHA256)
+        })
+}
+
+/// Calls `each` with the index of each entry of the folders holding at least one `wanted` entry,
+/// then with the entries without content. The other folders are not decoded at all.
 fn for_each_entries<
-    F: FnMut(SevenZForEachEntryData, &mut dyn Read) -> Result<bool, sevenz_rust::Error>,
+    R: Read + Seek,
+    F: FnMut(usize, SevenZForEachEntryData, &mut dyn Read) -> Result<bool, sevenz_rust::Error>,
 >(
     archive: &sevenz_rust::Archive,
     password: Password,
-    source: &mut DataSource,
+    source: &mut R,
+    wanted: &[bool],
     mut each: F,
 ) -> Result<(), sevenz_rust::Error> {
     let folder_count = archive.folders.len();
 
     for folder_index in 0..folder_count {
-        let forder_dec = BlockDecoder::new(folder_index, archive, password.as_slice(), source);
-        let compression = archive
-            .folders
-            .get(folder_index)
-            .and_then(|f| {
-                f.ordered_coder_iter()
-                    .next()
-                    .map(|(_, c)| c.decompression_method_id())
-            })
-            .and_then(SevenZMethod::by_id);
+        let start = archive.stream_map.folder_first_file_index[folder_index];
+        let count = archive.folders[folder_index].num_unpack_sub_streams;
+        // the decoder seeks to the packed stream of the folder, skipped ones are never read
+        if !wanted
+            .get(start..start + count)
+            .is_none_or(|w| w.contains(&true))
+        {
+            continue;
+        }
 
-        forder_dec.for_each_entries(&mut |entry, reader| {
-            if !each(
-                SevenZForEachEntryData {
-                    entry,
-                    folder_unpack_size: archive
-                        .folders
-                        .get(folder_index)
-                        .map(|f| f.get_unpack_size()),
-                    folder_pack_size: archive.pack_sizes.get(folder_index).copied(),
-                    compression,
-                },
+        let forder_dec = BlockDecoder::new(folder_index, archive, password.as_slice(), source);
+        let mut file_index = start;
+        let completed = forder_dec.for_each_entries(&mut |entry, reader| {
+            file_index += 1;
+            each(
+                file_index - 1,
+                SevenZForEachEntryData::of(archive, entry, Some(folder_index)),
                 reader,
-            )? {
-                return Ok(false);
-            }
-            Ok(true)
+            )
         })?;
+        if !completed {
+            return Ok(());
+        }
     }
     // decode empty files
     for file_index in 0..archive.files.len() {
@@ -359,12 +873,8 @@ fn for_each_entries<
             let file = &archive.files[file_index];
             let empty_reader: &mut dyn Read = &mut ([0u8; 0].as_slice());
             if !each(
-                SevenZForEachEntryData {
-                    entry: file,
-                    folder_unpack_size: None,
-                    folder_pack_size: None,
-                    compression: None,
-                },
+                file_index,
+                SevenZForEachEntryData::of(archive, file, None),
                 empty_reader,
             )? {
                 return Ok(());
@@ -373,3 +883,285 @@ fn for_each_entries<
     }
     Ok(())
 }
+
+#[cfg(all(test, feature = "lzma_codecs"))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::{cell::RefCell, io::Write, rc::Rc};
+
+    use super::*;
+    use crate::archive::OpenOptions;
+
+    #[derive(Clone, Default)]
+    struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn open_skips_other_folders() {
+        let path = std::env::temp_dir().join(format!("hezi-7z-folders-{}.7z", std::process::id()));
+        let mut writer = SevenZWriter::create(&path).unwrap();
+        for (name, content) in [("a.txt", "first folder"), ("b.txt", "second folder")] {
+            let mut entry = SevenZArchiveEntry::new();
+            entry.name = name.to_string();
+            entry.has_stream = true;
+            writer
+                .push_archive_entry(entry, Some(content.as_bytes()))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        // corrupt the packed stream of the first folder, right after the signature header
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[32..40].fill(0xff);
+        std::fs::write(&path, bytes).unwrap();
+
+        let archive = SevenZArchive::of(DataSource::file(&path).unwrap()).unwrap();
+        let content = SharedBuf::default();
+        archive
+            .open(OpenOptions {
+                paths: vec!["b.txt".into()],
+                password: None,
+                dest: Box::new(content.clone()),
+                output: Default::default(),
+            })
+            .unwrap();
+        assert_eq!(*content.0.borrow(), b"second folder");
+        _ = std::fs::remove_file(&path);
+    }
+
+    #[cfg(feature = "aes_codecs")]
+    #[test]
+    fn lists_encrypted_headers_with_password() {
+        use crate::archive::password::StaticPassword;
+        use sevenz_rust::{AesEncoderOptions, SevenZMethodConfiguration};
+
+        let path = std::env::temp_dir().join(format!("hezi-7z-header-{}.7z", std::process::id()));
+        let mut writer = SevenZWriter::create(&path).unwrap();
+        writer.set_content_methods(vec![
+            AesEncoderOptions::new(Password::from("secret")).into(),
+            SevenZMethodConfiguration::new(SevenZMethod::LZMA2),
+        ]);
+        // the header is only encrypted when it compresses, hence enough similar names
+        for i in 0..64 {
+            let mut entry = SevenZArchiveEntry::new();
+            entry.name = format!("hidden/file-{:02}.txt", i);
+            entry.has_stream = true;
+            writer
+                .push_archive_entry(entry, Some(&b"content"[..]))
+                .unwrap();
+        }
+        writer.finish().unwrap();
+
+        let list = |password: Option<&str>| {
+            SevenZArchive::of(DataSource::file(&path).unwrap())
+                .unwrap()
+                .list(ListOptions {
+                    password: StaticPassword::boxed(password.map(String::from)),
+                    ..Default::default()
+                })
+        };
+        assert!(matches!(list(None), Err(ArchiveError::PasswordRequired)));
+        assert!(matches!(
+            list(Some("wrong")),
+            Err(ArchiveError::WrongPassword)
+        ));
+        let entries = list(Some("secret")).unwrap();
+        assert_eq!(entries.len(), 64);
+        assert_eq!(entries[0].name(), "hidden/file-00.txt");
+        _ = std::fs::remove_file(&path);
+    }
+
+    #[cfg(feature = "aes_codecs")]
+    #[test]
+    fn asks_password_for_encrypted_content_only() {
+        use sevenz_rust::{AesEncoderOptions, SevenZMethodConfiguration};
+
+        let path = std::env::temp_dir().join(format!("hezi-7z-content-{}.7z", std::process::id()));
+        let mut writer = SevenZWriter::create(&path).unwrap();
+        writer.set_content_methods(vec![
+            AesEncoderOptions::new(Password::from("secret")).into(),
+            SevenZMethodConfiguration::new(SevenZMethod::LZMA2),
+        ]);
+        let mut entry = SevenZArchiveEntry::new();
+        entry.name = "secret.txt".to_string();
+        entry.has_stream = true;
+        writer
+            .push_archive_entry(entry, Some(&b"content"[..]))
+            .unwrap();
+        writer.finish().unwrap();
+
+        let asked = Rc::new(RefCell::new(Vec::new()));
+        let provider = |asked: Rc<RefCell<Vec<Option<String>>>>| {
+            crate::archive::password::password_provider(move |entry| {
+                asked.borrow_mut().push(entry.map(String::from));
+                Ok(Some("secret".to_string()))
+            })
+        };
+        let archive = SevenZArchive::of(DataSource::file(&path).unwrap()).unwrap();
+        let entries = archive
+            .list(ListOptions {
+                password: Some(provider(asked.clone())),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(entries.len(), 1);
+        assert!(asked.borrow().is_empty());
+
+        let content = SharedBuf::default();
+        archive
+            .open(OpenOptions {
+                paths: vec!["secret.txt".into()],
+                password: Some(provider(asked.clone())),
+                dest: Box::new(content.clone()),
+                output: Default::default(),
+            })
+            .unwrap();
+        assert_eq!(*content.0.borrow(), b"content");
+        assert_eq!(*asked.borrow(), vec![None]);
+
+        let wrong = archive.open(OpenOptions {
+            paths: vec!["secret.txt".into()],
+            password: crate::archive::password::StaticPassword::boxed(Some("wrong".to_string())),
+            dest: Box::new(std::io::sink()),
+            output: Default::default(),
+        });
+        assert!(matches!(wrong, Err(ArchiveError::WrongPassword)));
+        _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn creates_solid_blocks_with_method() {
+        use crate::archive::{Archive, ArchiveType, CreateOptions, SimpleLogger};
+
+        let source = std::path::PathBuf::from("tests/fixtures/test1");
+        let create = |name: &str, sevenz: SevenZCreateOptions| {
+            let destination =
+                std::env::temp_dir().join(format!("hezi-7z-{}-{}.7z", name, std::process::id()));
+            Archive::create(CreateOptions {
+                destination: destination.clone(),
+                source: source.clone(),
+                roots: vec![],
+                files: walkdir::WalkDir::new(&source)
+                    .sort_by_file_name()
+                    .into_iter()
+                    .map(|e| e.unwrap().into_path())
+                    .collect(),
+                password: None,
+                archive_type: ArchiveType::SevenZ,
+                compression: None,
+                overwrite: true,
+                include_hidden: true,
+                skip_unreadable: false,
+                verify_after: false,
+                volume_size: None,
+                update: false,
+                sevenz,
+                zip: Default::default(),
+                tar: Default::default(),
+                event_handler: Box::new(SimpleLogger),
+            })
+            .unwrap();
+
+            let mut file = File::open(&destination).unwrap();
+            let len = file.metadata().unwrap().len();
+            let folders = SevenZReader::new(&mut file, len, Password::empty())
+                .unwrap()
+                .archive()
+                .folders
+                .iter()
+                .map(|f| f.coders[0].decompression_method_id().to_vec())
+                .collect::<Vec<_>>();
+            let archive = SevenZArchive::of(DataSource::file(&destination).unwrap()).unwrap();
+            let (_, content) = archive
+                .find_first(|e| e.name == "dir1/file2.txt")
+                .unwrap()
+                .unwrap();
+            assert_eq!(
+                content,
+                std::fs::read("tests/fixtures/test1/dir1/file2.txt").unwrap()
+            );
+            _ = std::fs::remove_file(&destination);
+            folders
+        };
+
+        let lzma2 = SevenZMethod::ID_LZMA2.to_vec();
+        assert_eq!(
+            create("default", SevenZCreateOptions::default()),
+            vec![lzma2.clone(), lzma2.clone()]
+        );
+        assert_eq!(
+            create(
+                "solid",
+                SevenZCreateOptions {
+                    solid: true,
+                    dictionary_size: Some(1 << 16),
+                    ..Default::default()
+                }
+            ),
+            vec![lzma2]
+        );
+        let lzma = SevenZMethod::ID_LZMA.to_vec();
+        assert_eq!(
+            create(
+                "blocks",
+                SevenZCreateOptions {
+                    method: SevenZContentMethod::Lzma,
+                    solid: true,
+                    solid_block_size: Some(1),
+                    ..Default::default()
+                }
+            ),
+            vec![lzma.clone(), lzma]
+        );
+    }
+
+    #[test]
+    fn appends_by_recompressing() {
+        use crate::archive::{AppendOptions, Archive, ListOptions};
+
+        let path = std::env::temp_dir().join(format!("hezi-7z-add-{}.7z", std::process::id()));
+        std::fs::copy("tests/fixtures/test1.7z", &path).unwrap();
+
+        let result = Archive::of(DataSource::file(&path).unwrap())
+            .unwrap()
+            .append(AppendOptions {
+                source: std::path::PathBuf::from("tests/fixtures/test1"),
+                files: vec![std::path::PathBuf::from("tests/fixtures/test1/file1.txt")],
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(
+            result.compressed_size,
+            std::fs::metadata(&path).unwrap().len()
+        );
+
+        let appended = SevenZArchive::from_path(&path).unwrap();
+        let names = appended
+            .list(ListOptions::default())
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect::<Vec<_>>();
+        assert!(names.contains(&"test1/dir1/file2.txt".to_string()));
+        assert_eq!(names.last().unwrap(), "file1.txt");
+        let (entity, content) = appended
+            .find_first(|e| e.name == "file1.txt")
+            .unwrap()
+            .unwrap();
+        assert_eq!(entity.size, Some(result.total_size));
+        assert_eq!(
+            content,
+            std::fs::read("tests/fixtures/test1/file1.txt").unwrap()
+        );
+        _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,394 @@
+// read-only support for SquashFS images, the compressed filesystems of live systems, snaps and
+// AppImages. Files are split in blocks compressed one by one, those that would not shrink being
+// stored as is, so the compression of an entry tells how many of its blocks are compressed.
+
+use std::{
+    cell::OnceCell,
+    io::{BufReader, Read, Seek, SeekFrom},
+    ops::ControlFlow,
+};
+
+use backhand::{
+    compression::{self, CompressionAction, DefaultCompressor},
+    kind::Kind,
+    traits::types::Compressor,
+    BackhandError, FilesystemCompressor, FilesystemReader, InnerNode, Node, SquashfsFileReader,
+    SuperBlock,
+};
+use serde_json::json;
+
+use crate::archive::{
+    datetime_from_timestamp,
+    entry_visitor::{
+        extract_entries, find_first_entry, list_entries, open_entry, scan_entries, EntryLink,
+        EntryVisitor, VisitOptions, VisitedEntry,
+    },
+    ArchiveError, ArchiveFileEntity, ArchiveFileEntityType, ArchiveMetadata, ArchiveScan,
+    ArchiveType, Archived, CreateOptions, CreateResult, DataSource, ExtractOptions, ExtractReport,
+    ListOptions, OpenOptions, DEFAULT_BUF_SIZE,
+};
+
+use super::edit::EntryEdit;
+
+/// Fragment index of the files whose tail is stored in their last block.
+const NO_FRAGMENT: usize = 0xffff_ffff;
+
+/// Decompresses the blocks with the codecs of the library, except for xz and lzma which go
+/// through [`ArchiveCodec`](super::ArchiveCodec) so that a single liblzma is linked.
+struct HeziCompressor;
+
+static HEZI_COMPRESSOR: HeziCompressor = HeziCompressor;
+
+impl CompressionAction for HeziCompressor {
+    type Error = BackhandError;
+    type Compressor = compression::Compressor;
+    type FilesystemCompressor = FilesystemCompressor;
+    type SuperBlock = SuperBlock;
+
+    fn decompress(
+        &self,
+        bytes: &[u8],
+        out: &mut Vec<u8>,
+        compressor: Self::Compressor,
+    ) -> Result<(), Self::Error> {
+        match compressor {
+            #[cfg(feature = "lzma_codecs")]
+            compression::Compressor::Xz | compression::Compressor::Lzma => {
+                super::ArchiveCodec::get_reader(
+                    std::io::Cursor::new(bytes),
+                    &super::ArchiveCompression::Lzma,
+                )
+                .map_err(|e| BackhandError::StdIo(std::io::Error::other(e.to_string())))?
+                .read_to_end(out)?;
+                Ok(())
+            }
+            _ => DefaultCompressor.decompress(bytes, out, compressor),
+        }
+    }
+
+    fn compress(
+        &self,
+        bytes: &[u8],
+        fc: Self::FilesystemCompressor,
+        block_size: u32,
+    ) -> Result<Vec<u8>, Self::Error> {
+        DefaultCompressor.compress(bytes, fc, block_size)
+    }
+}
+
+pub struct SquashfsArchive {
+    pub(crate) source: DataSource,
+    scan: OnceCell<ArchiveScan>,
+}
+
+impl SquashfsArchive {
+    fn filesystem(&self) -> Result<FilesystemReader<'_>, ArchiveError> {
+        let reader = BufReader::with_capacity(DEFAULT_BUF_SIZE, self.source.clone());
+        Ok(FilesystemReader::from_reader_with_offset_and_kind(
+            reader,
+            0,
+            Kind::new_v4(&HEZI_COMPRESSOR),
+        )?)
+    }
+
+    fn scan(&self) -> Result<&ArchiveScan, ArchiveError> {
+        ArchiveScan::cached(&self.scan, || scan_entries(self, None, None))
+    }
+
+    /// The entry of `node`, `None` for the root directory.
+    fn visited_entry(
+        filesystem: &FilesystemReader,
+        node: &Node<SquashfsFileReader>,
+    ) -> Option<VisitedEntry> {
+        let mut name = node
+            .fullpath
+            .to_string_lossy()
+            .trim_start_matches('/')
+            .to_string();
+        if name.is_empty() {
+            return None;
+        }
+
+        let mut link = None;
+        let (fstype, size, compressed_size, compression) = match &node.inner {
+            InnerNode::File(file) => {
+                let (compressed_size, compression) = block_compression(filesystem, file);
+                (
+                    ArchiveFileEntityType::File,
+                    Some(file.file_len() as u64),
+                    compressed_size,
+                    Some(compression),
+                )
+            }
+            InnerNode::Dir(_) => {
+                name.push('/');
+                (ArchiveFileEntityType::Directory, None, None, None)
+            }
+            InnerNode::Symlink(symlink) => {
+                link = Some(EntryLink::Symbolic(
+                    symlink.link.to_string_lossy().to_string(),
+                ));
+                (ArchiveFileEntityType::SymbolicLink, None, None, None)
+            }
+            _ => (ArchiveFileEntityType::Unknown, None, None, None),
+        };
+
+        Some(VisitedEntry {
+            entity: ArchiveFileEntity {
+                name,
+                size,
+                compressed_size,
+                last_modified: datetime_from_timestamp(node.header.mtime as i64).ok(),
+                compression,
+                fstype,
+                raw: None,
+                unix: None,
+            },
+            mode: Some(node.header.permissions as u32),
+            link,
+            crc32: None,
+        })
+    }
+}
+
+fn compressor_name(compressor: Option<Compressor>) -> &'static str {
+    match compressor {
+        None | Some(Compressor::Uncompressed) => "none",
+        Some(Compressor::Gzip) => "gzip",
+        Some(Compressor::Lzma) => "lzma",
+        Some(Compressor::Lzo) => "lzo",
+        Some(Compressor::Xz) => "xz",
+        Some(Compressor::Lz4) => "lz4",
+        Some(Compressor::Zstd) => "zstd",
+    }
+}
+
+/// Size the blocks of `file` take in the image, unknown when its tail shares a fragment with
+/// other files, and its compression: the compressor of the image when all of its blocks are
+/// compressed, `stored` when none is, or the share of compressed blocks otherwise.
+fn block_compression(
+    filesystem: &FilesystemReader,
+    file: &SquashfsFileReader,
+) -> (Option<u64>, String) {
+    let fragment = match file.frag_index() {
+        NO_FRAGMENT => None,
+        index => filesystem
+            .fragments
+            .as_ref()
+            .and_then(|fragments| fragments.get(index)),
+    };
+    let blocks = file
+        .block_sizes()
+        .iter()
+        .chain(fragment.map(|f| &f.size))
+        .collect::<Vec<_>>();
+    let compressed = blocks.iter().filter(|b| !b.uncompressed()).count();
+
+    let compressed_size = match fragment {
+        None => Some(blocks.iter().map(|b| b.size() as u64).sum()),
+        Some(_) => None,
+    };
+    let compression = match compressed {
+        0 => "stored".to_string(),
+        n if n == blocks.len() => compressor_name(filesystem.compressor).to_string(),
+        n => format!(
+            "{} ({}/{} blocks)",
+            compressor_name(filesystem.compressor),
+            n,
+            blocks.len()
+        ),
+    };
+    (compressed_size, compression)
+}
+
+impl EntryVisitor for SquashfsArchive {
+    fn for_each_entry<F>(
+        &self,
+        options: &VisitOptions<'_>,
+        mut visit: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        let filesystem = self.filesystem()?;
+        for node in filesystem.files() {
+            let Some(visited) = Self::visited_entry(&filesystem, node) else {
+                continue;
+            };
+            if !options.wants(&visited.entity) {
+                continue;
+            }
+
+            let flow = match &node.inner {
+                InnerNode::File(file) if options.contents => {
+                    let mut reader = filesystem.file(file).reader_checked()?;
+                    visit(visited, Some(&mut reader))?
+                }
+                _ => visit(visited, None)?,
+            };
+            if flow.is_break() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Archived for SquashfsArchive {
+    fn of(source: DataSource) -> Result<Self, ArchiveError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            source,
+            scan: OnceCell::new(),
+        })
+    }
+
+    fn extract(&self, options: ExtractOptions) -> Result<ExtractReport, ArchiveError> {
+        extract_entries(self, &options, self.source.as_ref())
+    }
+
+    fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
+        if options.is_whole() {
+            return Ok(self.scan()?.entries.clone());
+        }
+        list_entries(self, self.scan.get(), &options)
+    }
+
+    fn create(_options: CreateOptions) -> Result<CreateResult, ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "create".to_string(),
+            ArchiveType::Squashfs,
+        ))
+    }
+
+    fn metadata(&self) -> Result<ArchiveMetadata, ArchiveError> {
+        let filesystem = self.filesystem()?;
+        let additional = json!({
+            "compressor": compressor_name(filesystem.compressor),
+            "block_size": filesystem.block_size,
+        });
+        let created = datetime_from_timestamp(filesystem.mod_time as i64).ok();
+        drop(filesystem);
+
+        let len = self.source.clone().seek(SeekFrom::End(0))?;
+        let scan = self.scan()?;
+
+        Ok(ArchiveMetadata {
+            archive_type: ArchiveType::Squashfs,
+            entry_count: scan.entries.len(),
+            entries: scan.entries.clone(),
+            total_size: scan.total_size,
+            compressed_size: len,
+            compression: None,
+            created: created.or(self.source.created()),
+            modified: self.source.modified(),
+            additional: Some(additional),
+        })
+    }
+
+    fn open(&self, options: OpenOptions) -> Result<(), ArchiveError> {
+        open_entry(self, options)
+    }
+
+    fn find_first<P>(
+        &self,
+        predicate: P,
+    ) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+    where
+        P: Fn(&ArchiveFileEntity) -> bool,
+    {
+        find_first_entry(self, predicate)
+    }
+
+    fn check_password(&self, _password: &str) -> Result<bool, ArchiveError> {
+        // squashfs has no encryption
+        Ok(true)
+    }
+
+    fn edit(&self, _entry: &str, _edit: &EntryEdit) -> Result<(), ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "edit".to_string(),
+            ArchiveType::Squashfs,
+        ))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::archive::Archive;
+
+    fn entries(path: &str) -> Vec<ArchiveFileEntity> {
+        let archive = Archive::of(DataSource::file(path).unwrap()).unwrap();
+        assert!(matches!(archive, Archive::Squashfs(_)));
+        archive.list(ListOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn reads_squashfs_images() {
+        let entries = entries("tests/fixtures/test1.squashfs");
+        let names = entries
+            .iter()
+            .map(|e| (e.name.as_str(), e.fstype))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                ("test1/", ArchiveFileEntityType::Directory),
+                ("test1/blocks.bin", ArchiveFileEntityType::File),
+                ("test1/dir1/", ArchiveFileEntityType::Directory),
+                ("test1/dir1/file2.txt", ArchiveFileEntityType::File),
+                ("test1/file1.txt", ArchiveFileEntityType::File),
+                ("test1/link.txt", ArchiveFileEntityType::SymbolicLink),
+            ]
+        );
+
+        // a compressible block, a random one stored as is and a compressed tail
+        assert_eq!(entries[1].size, Some(8292));
+        assert_eq!(entries[1].compressed_size, Some(4254));
+        assert_eq!(entries[1].compression.as_deref(), Some("gzip (2/3 blocks)"));
+        assert_eq!(entries[4].compression.as_deref(), Some("gzip"));
+
+        let archive =
+            Archive::of(DataSource::file("tests/fixtures/test1.squashfs").unwrap()).unwrap();
+        let (_, content) = archive
+            .find_first(|e| e.name == "test1/file1.txt")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            content,
+            std::fs::read("tests/fixtures/test1/file1.txt").unwrap()
+        );
+        let metadata = archive.metadata().unwrap();
+        assert_eq!(
+            metadata.additional.unwrap()["compressor"].as_str(),
+            Some("gzip")
+        );
+    }
+
+    #[cfg(feature = "lzma_codecs")]
+    #[test]
+    fn decompresses_xz_through_the_codecs() {
+        let dest = std::env::temp_dir().join(format!("hezi-squashfs-xz-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dest);
+        let archive =
+            Archive::of(DataSource::file("tests/fixtures/test1-xz.sqfs").unwrap()).unwrap();
+        archive
+            .extract(ExtractOptions {
+                destination: dest.clone(),
+                ..Default::default()
+            })
+            .unwrap();
+
+        for file in ["file1.txt", "dir1/file2.txt"] {
+            assert_eq!(
+                std::fs::read(dest.join("test1").join(file)).unwrap(),
+                std::fs::read(format!("tests/fixtures/test1/{}", file)).unwrap()
+            );
+        }
+        _ = std::fs::remove_dir_all(&dest);
+    }
+}
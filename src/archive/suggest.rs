@@ -0,0 +1,120 @@
+// "did you mean" suggestions for names that were mistyped, entries of an archive as well as
+// compression methods of the nu plugin
+
+use nu_protocol::levenshtein_distance;
+
+use super::entry_visitor::normalized_name;
+
+/// Most entry names suggested when an entry is not found.
+pub const MAX_SUGGESTIONS: usize = 3;
+
+/// The candidates at most `max_distance` edits away from `target`, ignoring case, closest first.
+pub fn closest_matches<'c, I>(target: &str, candidates: I, max_distance: usize) -> Vec<&'c str>
+where
+    I: IntoIterator<Item = &'c str>,
+{
+    let target = target.to_lowercase();
+    let mut matches = candidates
+        .into_iter()
+        .map(|c| (levenshtein_distance(&target, &c.to_lowercase()), c))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .collect::<Vec<_>>();
+    // stable, candidates as close keep their order
+    matches.sort_by_key(|(distance, _)| *distance);
+    matches.into_iter().map(|(_, c)| c).collect()
+}
+
+/// Up to [`MAX_SUGGESTIONS`] entry names close to `target`: the entries it is the start of,
+/// then the ones a few edits away.
+pub(crate) fn entry_suggestions<'n, I>(target: &str, names: I) -> Vec<String>
+where
+    I: IntoIterator<Item = &'n str>,
+{
+    let target = normalized_name(target).trim_end_matches('/');
+    if target.is_empty() {
+        return Vec::new();
+    }
+    let names = names.into_iter().map(normalized_name).collect::<Vec<_>>();
+
+    let prefixed = names
+        .iter()
+        .copied()
+        .filter(|name| name.starts_with(target));
+    // a third of the name may be mistyped, and a short one by up to two characters
+    let max_distance = (target.chars().count() / 3).max(2);
+    let close = closest_matches(target, names.iter().copied(), max_distance);
+
+    let mut suggestions = Vec::new();
+    for name in prefixed.chain(close) {
+        if !suggestions.iter().any(|s: &String| s == name) {
+            suggestions.push(name.to_string());
+        }
+        if suggestions.len() == MAX_SUGGESTIONS {
+            break;
+        }
+    }
+    suggestions
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suggests_close_entry_names() {
+        let names = [
+            "./test1/",
+            "./test1/file1.txt",
+            "./test1/dir1/",
+            "./test1/dir1/file2.txt",
+            "./other/readme.md",
+        ];
+        assert_eq!(
+            entry_suggestions("test1/fiel1.txt", names),
+            vec!["test1/file1.txt"]
+        );
+        assert_eq!(
+            entry_suggestions("test1/dir1", names),
+            vec!["test1/dir1/", "test1/dir1/file2.txt"]
+        );
+        assert_eq!(
+            entry_suggestions("OTHER/readme.md", names),
+            vec!["other/readme.md"]
+        );
+        assert!(entry_suggestions("nothing/like/it", names).is_empty());
+    }
+
+    #[test]
+    fn orders_matches_by_distance() {
+        assert_eq!(
+            closest_matches("gzp", ["bzip2", "gzip", "zstd"], 3),
+            vec!["gzip", "bzip2"]
+        );
+        assert_eq!(
+            closest_matches("gzp", ["bzip2", "gzip", "zstd"], 1),
+            vec!["gzip"]
+        );
+    }
+
+    #[cfg(feature = "tar_archive")]
+    #[test]
+    fn suggests_entries_when_opening() {
+        use crate::archive::{Archive, Archived, DataSource, OpenOptions};
+
+        let archive = Archive::of(DataSource::file("tests/fixtures/test1.tar").unwrap()).unwrap();
+        let error = archive
+            .open(OpenOptions {
+                paths: vec!["test1/file2.txt".into()],
+                password: None,
+                dest: Box::new(std::io::sink()),
+                output: Default::default(),
+            })
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Entry not found: test1/file2.txt. Did you mean test1/file1.txt, test1/._file1.txt or \
+             test1/dir1/file2.txt?"
+        );
+    }
+}
@@ -1,7 +1,10 @@
 use std::{
-    collections::HashSet,
+    cell::{OnceCell, RefCell},
     fs::File,
-    io::{BufReader, Read, Write},
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    rc::Rc,
 };
 
 use byte_unit::{Byte, UnitType};
@@ -10,285 +13,927 @@ use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use tar;
 
 use crate::archive::{
-    codecs::{ArchiveCodec, ArchiveCompression, FinishableWrite},
-    datetime_from_timestamp, ArchiveError, ArchiveFileEntity, ArchiveFileEntityType,
-    ArchiveMetadata, ArchiveType, Archived, AsTarArchiveResult, CreateOptions, CreateResult,
-    DataSource, EventHandler, ExtractOptions, ListOptions, MagicBytesHex,
+    codecs::{ArchiveCodec, ArchiveCompression, CompressionSpec, CountingWriter, FinishableWrite},
+    convert::EntryWriter,
+    datetime_from_timestamp,
+    edit::{archive_path, rewrite, EntryEdit},
+    entries::{visit_options, ArchiveEntries, ArchiveEntry},
+    entry_name,
+    entry_visitor::{
+        extract_entries, find_first_entry, list_entries, normalized_name, open_entry, scan_entries,
+        EntryLink, EntryVisitor, VisitOptions, VisitedEntry,
+    },
+    owner::OwnerNames,
+    AppendOptions, ArchiveError, ArchiveEvent, ArchiveFileEntity, ArchiveFileEntityType,
+    ArchiveMetadata, ArchiveScan, ArchiveType, Archived, AsTarArchiveResult, CreateOptions,
+    CreateResult, DataSource, EventHandler, ExtractOptions, ExtractReport, ListOptions,
+    MagicBytesHex, RawEntryLocation, TarCreateOptions, TarFormat, DEFAULT_BUF_SIZE,
 };
 
-pub struct TarArchive<'a> {
-    pub(crate) source: DataSource<'a>,
+/// Contents of a file added to an archive, read in chunks of [`DEFAULT_BUF_SIZE`]. Exactly the
+/// `size` recorded in the header of its entry is read, should the file change meanwhile: the
+/// bytes past it are left out and a file that got shorter is an error.
+struct FileContents {
+    reader: BufReader<File>,
+    path: PathBuf,
+    remaining: u64,
 }
 
-impl<'a> TarArchive<'a> {
-    fn reader(&'a self) -> Result<Box<dyn std::io::Read + 'a>, ArchiveError> {
-        let compression = ArchiveType::try_from_datasource(self.source.clone())?.1;
-
-        ArchiveCodec::get_reader(self.source.clone(), &compression)
+impl FileContents {
+    fn open(path: &Path, size: u64) -> Result<Self, ArchiveError> {
+        Ok(Self {
+            reader: BufReader::with_capacity(DEFAULT_BUF_SIZE, File::open(path)?),
+            path: path.to_path_buf(),
+            remaining: size,
+        })
     }
+}
 
-    fn writer<'w, R: Write + 'w>(
-        tar_compression: &ArchiveCompression,
-        writer: R,
-    ) -> Result<Box<dyn FinishableWrite + 'w>, ArchiveError> {
-        ArchiveCodec::get_writer(tar_compression, writer)
+impl Read for FileContents {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        let max = self.remaining.min(buf.len() as u64) as usize;
+        let n = self.reader.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("{} got shorter while it was read", self.path.display()),
+            ));
+        }
+        self.remaining -= n as u64;
+        Ok(n)
     }
 }
 
-impl<'a> Archived<'a> for TarArchive<'a> {
-    fn of(source: DataSource<'a>) -> Result<Self, ArchiveError>
-    where
-        Self: Sized,
+/// "ustar\000" of POSIX headers and "ustar  \0" of old GNU ones, at offset 257.
+const USTAR_MAGICS: [&[u8; 8]; 2] = [b"ustar\x0000", b"ustar  \0"];
+
+/// Whether `block` is the header of a tar entry, from its magic, or else from its checksum for the
+/// V7 headers written before there was one.
+pub(crate) fn is_tar_header(block: &[u8; 512]) -> bool {
+    if USTAR_MAGICS
+        .iter()
+        .any(|magic| block[257..265] == magic[..])
     {
-        Ok(Self { source })
+        return true;
+    }
+    if block[0] == 0 {
+        return false;
+    }
+    let Ok(expected) = tar::Header::from_byte_slice(block).cksum() else {
+        return false;
+    };
+    // the checksum field counts as spaces, and some old tars summed signed bytes
+    let field = 148..156;
+    let unsigned = block
+        .iter()
+        .enumerate()
+        .map(|(i, b)| if field.contains(&i) { 0x20 } else { *b as u32 })
+        .sum::<u32>();
+    let signed = block
+        .iter()
+        .enumerate()
+        .map(|(i, b)| {
+            if field.contains(&i) {
+                0x20
+            } else {
+                *b as i8 as i32
+            }
+        })
+        .sum::<i32>();
+    expected == unsigned || expected as i32 == signed
+}
+
+/// Sets the owner overrides of `options` on `header`, and the names of its owners unless only
+/// numeric ids are wanted.
+fn set_owner(
+    header: &mut tar::Header,
+    options: &TarCreateOptions,
+    names: &mut OwnerNames,
+) -> Result<(), ArchiveError> {
+    if let Some(owner) = &options.owner {
+        header.set_uid(owner.id);
+    }
+    if let Some(group) = &options.group {
+        header.set_gid(group.id);
+    }
+    if options.numeric_owner {
+        return Ok(());
     }
 
-    fn extract(&self, options: ExtractOptions) -> Result<(), ArchiveError> {
-        use std::fs;
-        let reader = self.reader()?;
-        let mut archive = tar::Archive::new(reader);
+    let user = match &options.owner {
+        Some(owner) => owner.name.as_deref(),
+        None => names.user(header.uid()?),
+    };
+    if let Some(user) = user {
+        header.set_username(user)?;
+    }
+    let group = match &options.group {
+        Some(group) => group.name.as_deref(),
+        None => names.group(header.gid()?),
+    };
+    if let Some(group) = group {
+        header.set_groupname(group)?;
+    }
+    Ok(())
+}
 
-        let files = options
-            .files
-            .clone()
-            .map(|f| f.into_iter().collect::<HashSet<_>>());
+/// Pax record `key=value`, prefixed by its own length in bytes.
+fn pax_record(key: &str, value: &str) -> String {
+    let rest = format!(" {}={}\n", key, value);
+    // the length counts its own digits, which it may gain by counting them
+    let mut len = rest.len() + 1;
+    while len.to_string().len() + rest.len() != len {
+        len = len.to_string().len() + rest.len();
+    }
+    format!("{}{}", len, rest)
+}
 
-        if options.destination.symlink_metadata().is_err() {
-            fs::create_dir_all(&options.destination)?;
+/// Writes the pax records of the entry `name` of `header` to `builder`, and sets its path to
+/// what of `name` fits the ustar fields.
+fn append_pax_header<W: Write>(
+    builder: &mut tar::Builder<W>,
+    header: &mut tar::Header,
+    name: &Path,
+    metadata: &std::fs::Metadata,
+    options: &TarCreateOptions,
+) -> Result<(), ArchiveError> {
+    let mut records = String::new();
+    let path = name.to_string_lossy();
+    let fits = header.set_path(name).is_ok();
+    if !fits || !path.is_ascii() {
+        records.push_str(&pax_record("path", &path));
+    }
+    if !fits {
+        // readers without pax support still get the start of the name
+        if let Some(ustar) = header.as_ustar_mut() {
+            let len = path.len().min(ustar.name.len());
+            ustar.prefix.fill(0);
+            ustar.name.fill(0);
+            ustar.name[..len].copy_from_slice(&path.as_bytes()[..len]);
         }
+    }
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok());
+    if let Some(modified) = modified.filter(|_| options.precise_times) {
+        let mtime = format!("{}.{:09}", modified.as_secs(), modified.subsec_nanos());
+        records.push_str(&pax_record("mtime", &mtime));
+    }
+    if records.is_empty() {
+        return Ok(());
+    }
 
-        // Canonicalizing the dst directory will prepend the path with '\\?\'
-        // on windows which will allow windows APIs to treat the path as an
-        // extended-length path with a 32,767 character limit. Otherwise all
-        // unpacked paths over 260 characters will fail on creation with a
-        // NotFound exception.
-        let dst = &options
-            .destination
-            .canonicalize()
-            .unwrap_or(options.destination.to_path_buf());
-
-        // Delay any directory entries until the end (they will be created if needed by
-        // descendants), to ensure that directory permissions do not interfer with descendant
-        // extraction.
-        let mut directories = Vec::new();
-        for entry in archive.entries()? {
-            let mut file = entry?;
+    let mut pax = tar::Header::new_ustar();
+    let file_name = name
+        .file_name()
+        .map_or("entry".into(), |name| name.to_string_lossy());
+    let pax_name: String = format!("PaxHeaders/{}", file_name)
+        .chars()
+        .filter(char::is_ascii)
+        .take(100)
+        .collect();
+    pax.set_path(pax_name)?;
+    pax.set_entry_type(tar::EntryType::XHeader);
+    pax.set_mode(0o644);
+    pax.set_mtime(header.mtime()?);
+    pax.set_size(records.len() as u64);
+    pax.set_cksum();
+    builder.append(&pax, records.as_bytes())?;
+    Ok(())
+}
+
+/// Writes `file` to `builder` as the entry `name`.
+fn append_file<W: Write>(
+    builder: &mut tar::Builder<W>,
+    file: &Path,
+    name: &Path,
+    metadata: &std::fs::Metadata,
+    options: &TarCreateOptions,
+    names: &mut OwnerNames,
+) -> Result<(), ArchiveError> {
+    let mut header = match options.format {
+        TarFormat::Gnu => tar::Header::new_gnu(),
+        TarFormat::Pax => tar::Header::new_ustar(),
+    };
+    header.set_metadata(metadata);
+    set_owner(&mut header, options, names)?;
+    let contents: Box<dyn Read> = if metadata.is_file() {
+        Box::new(FileContents::open(file, metadata.len())?)
+    } else {
+        Box::new(std::io::empty())
+    };
+    match options.format {
+        TarFormat::Gnu => builder.append_data(&mut header, name, contents),
+        // the tar crate only writes GNU entries for the long names
+        TarFormat::Pax => {
+            append_pax_header(builder, &mut header, name, metadata, options)?;
+            header.set_cksum();
+            builder.append(&header, contents)
+        }
+    }
+    .into_tar_archive_result()
+}
 
-            let file_path: String = file.path().map(|p| p.to_string_lossy().to_string())?;
+/// Writes the files of `options` to `builder`, returning the size of their content.
+fn append_files<W: Write>(
+    builder: &mut tar::Builder<W>,
+    options: &AppendOptions,
+) -> Result<u64, ArchiveError> {
+    let mut total_size = 0;
+    let mut names = OwnerNames::default();
+    for file in &options.files {
+        let metadata = std::fs::metadata(file)?;
+        let name = options.entry_name(file);
+        // the source directory itself, which is the top level of the archive
+        if name.as_os_str().is_empty() {
+            continue;
+        }
+        eprintln!("Adding: {} -> {}", file.display(), name.display());
+        append_file(builder, file, &name, &metadata, &options.tar, &mut names)?;
+        let size = metadata.is_file().then_some(metadata.len());
+        total_size += size.unwrap_or(0);
+        options.handle(ArchiveEvent::Added(
+            name.to_string_lossy().to_string(),
+            size,
+        ));
+    }
+    Ok(total_size)
+}
 
-            if let Some(files) = &files {
-                if !files.contains(&file_path) {
-                    continue;
+/// Writes the visited `entry` to `builder`, with the content of files read from `reader`.
+pub(crate) fn append_entry<W: Write>(
+    builder: &mut tar::Builder<W>,
+    entry: &VisitedEntry,
+    reader: Option<&mut dyn Read>,
+) -> std::io::Result<()> {
+    let entity = &entry.entity;
+    let name = normalized_name(&entity.name).trim_end_matches('/');
+    let mut header = tar::Header::new_gnu();
+    header.set_mtime(
+        entity
+            .last_modified
+            .map_or(0, |t| t.timestamp().max(0) as u64),
+    );
+    header.set_size(0);
+
+    match (&entry.link, entity.fstype, reader) {
+        (Some(EntryLink::Symbolic(target)), ..) => {
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_mode(entry.mode.unwrap_or(0o777));
+            builder.append_link(&mut header, name, target)
+        }
+        (Some(EntryLink::Hard(target)), ..) => {
+            header.set_entry_type(tar::EntryType::Link);
+            header.set_mode(entry.mode.unwrap_or(0o644));
+            builder.append_link(&mut header, name, normalized_name(target))
+        }
+        (None, ArchiveFileEntityType::Directory, _) => {
+            header.set_entry_type(tar::EntryType::Directory);
+            header.set_mode(entry.mode.unwrap_or(0o755));
+            builder.append_data(&mut header, name, std::io::empty())
+        }
+        (None, _, Some(reader)) => {
+            header.set_mode(entry.mode.unwrap_or(0o644));
+            // the size goes before the data, which is buffered when it is unknown
+            match entity.size {
+                Some(size) => {
+                    header.set_size(size);
+                    builder.append_data(&mut header, name, reader.take(size))
+                }
+                None => {
+                    let mut content = Vec::new();
+                    reader.read_to_end(&mut content)?;
+                    header.set_size(content.len() as u64);
+                    builder.append_data(&mut header, name, content.as_slice())
                 }
-            }
-            if file.header().entry_type() == tar::EntryType::Directory {
-                let path = dst.join(file_path);
-                directories.push(file);
-                options.handle(crate::archive::ArchiveEvent::Created(
-                    path.to_string_lossy().to_string(),
-                    crate::archive::ArchiveFileEntityType::Directory,
-                ));
-            } else {
-                file.unpack_in(dst)?;
-                options.handle(crate::archive::ArchiveEvent::Extracting(
-                    file_path,
-                    file.size().into(),
-                ));
             }
         }
-        for mut dir in directories {
-            dir.unpack_in(dst)?;
-            let dir_path = dir.path().map(|p| p.to_string_lossy().to_string())?;
-            options.handle(crate::archive::ArchiveEvent::Extracting(dir_path, None));
-        }
+        _ => Ok(()),
+    }
+}
 
-        options.handle(crate::archive::ArchiveEvent::DoneExtracting(
-            self.source.as_ref().to_string(),
-            dst.to_string_lossy().to_string(),
-        ));
+/// Tar archive written by [`Archive::convert`](super::Archive::convert).
+pub(crate) struct TarEntryWriter<'w> {
+    builder: tar::Builder<Box<dyn FinishableWrite + 'w>>,
+}
+
+impl<'w> TarEntryWriter<'w> {
+    pub(crate) fn new<W: Write + 'w>(
+        compression: &CompressionSpec,
+        writer: W,
+    ) -> Result<Self, ArchiveError> {
+        Ok(Self {
+            builder: tar::Builder::new(TarArchive::writer(compression, writer)?),
+        })
+    }
+}
+
+impl EntryWriter for TarEntryWriter<'_> {
+    fn write(
+        &mut self,
+        entry: &VisitedEntry,
+        reader: Option<&mut dyn Read>,
+    ) -> Result<bool, ArchiveError> {
+        append_entry(&mut self.builder, entry, reader)?;
+        Ok(true)
+    }
+
+    fn finish(self: Box<Self>) -> Result<(), ArchiveError> {
+        self.builder.into_inner()?.finish_writer()?;
         Ok(())
     }
+}
 
-    fn list(&self, _options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
-        // println!("list tar archive");
-        // read the file to identify the archive type
-        let reader = self.reader()?;
+/// Writes a tar archive of the files of `options` to `writer`, front to back, so that it can be a
+/// pipe such as stdout.
+pub fn create_tar_stream<W: Write>(
+    options: CreateOptions,
+    writer: W,
+) -> Result<CreateResult, ArchiveError> {
+    let compression = options
+        .compression
+        .clone()
+        .ok_or(ArchiveError::CompressionMethodRequired)?;
 
-        let compression = ArchiveType::try_from_datasource(self.source.clone())?.1;
-        // println!("compression: {:?}", compression);
+    eprintln!(
+        "Creating tar archive at {} with compression {} and source {}",
+        options.destination.display(),
+        compression.algorithm,
+        options.source.display()
+    );
 
-        let mut archive = tar::Archive::new(reader);
+    let mut writer = CountingWriter::new(writer);
+    let enc_writer = TarArchive::writer(&compression, &mut writer)?;
 
-        let entities = archive
-            .entries()?
-            .map(|entry| {
-                let entry = entry?;
-                let fstype = entry.header().entry_type().into();
-
-                let (size, compressed_size) = if fstype == ArchiveFileEntityType::File {
-                    (Some(entry.size()), Some(entry.size()))
-                } else {
-                    (None, None)
-                };
-                Ok(ArchiveFileEntity {
-                    name: entry
-                        .path()?
-                        .to_string_lossy()
-                        .to_string()
-                        .replace('\\', "/"),
-                    size,
-                    compressed_size,
-                    fstype,
-                    last_modified: entry
-                        .header()
-                        .mtime()
-                        .map(|t| t as i64)
-                        .and_then(datetime_from_timestamp)
-                        .ok(),
-                    compression: Some(compression.to_string()),
-                })
-            })
-            .collect::<Result<Vec<_>, ArchiveError>>();
+    let mut archive = tar::Builder::new(enc_writer);
+    let mut total_size = 0;
+
+    let (source, roots) = (&options.source, &options.roots);
+    let files = options
+        .files
+        .par_iter()
+        .map(|f| {
+            let metadata = std::fs::metadata(f).map_err(|e| {
+                ArchiveError::Io(std::io::Error::new(
+                    e.kind(),
+                    format!("could not read file metadata for '{}': {}", f.display(), e),
+                ))
+            })?;
+
+            let mut name = entry_name(source, roots, f);
+            if metadata.is_dir() && name.as_os_str().is_empty() {
+                name.push(".");
+            }
+            Ok((f, name, metadata))
+        })
+        .collect::<Result<Vec<_>, ArchiveError>>()
+        .map_err(|e| {
+            ArchiveError::Io(std::io::Error::other(format!(
+                "Failed to read file metadatas: {}",
+                e
+            )))
+        })?;
 
-        entities
+    let mut names = OwnerNames::default();
+    for (file, name, metadata) in files {
+        total_size += metadata.len();
+
+        if metadata.is_file() {
+            eprintln!(
+                "Adding: {} -> {} ({})",
+                file.display(),
+                name.display(),
+                Byte::from(metadata.len()).get_appropriate_unit(UnitType::Both)
+            );
+        } else {
+            eprintln!("Adding: {} -> {}", file.display(), name.display());
+        }
+        append_file(
+            &mut archive,
+            file,
+            &name,
+            &metadata,
+            &options.tar,
+            &mut names,
+        )?;
+        options.handle(ArchiveEvent::Added(
+            name.to_string_lossy().to_string(),
+            metadata.is_file().then_some(metadata.len()),
+        ));
     }
 
-    fn create(options: CreateOptions) -> Result<CreateResult, ArchiveError> {
-        let compression = options
-            .archive_compression
-            .ok_or_else(|| ArchiveError::CompressionMethodRequired)?;
-
-        eprintln!(
-            "Creating tar archive at {} with compression {} and source {}",
-            options.destination.display(),
-            compression,
-            options.source.display()
-        );
+    let mut moved = archive.into_inner()?;
+    moved.finish_writer()?;
+    drop(moved);
+    writer.flush()?;
+    let size = writer.written;
 
-        let writer = File::create(&options.destination).map_err(|e| {
-            ArchiveError::Io(std::io::Error::new(
-                e.kind(),
-                format!("could not create destination file: {}", e),
-            ))
-        })?;
+    eprintln!(
+        "Done creating tar archive: {} ({})",
+        options.destination.display(),
+        Byte::from(size).get_appropriate_unit(UnitType::Both)
+    );
+    options.handle(ArchiveEvent::DoneCreating(
+        options.destination.to_string_lossy().to_string(),
+    ));
+
+    Ok(CreateResult {
+        path: options.destination,
+        total_size,
+        compressed_size: size,
+        unreadable: Vec::new(),
+    })
+}
 
-        let enc_writer = Self::writer(&compression, &writer)?;
+pub struct TarArchive {
+    pub(crate) source: DataSource,
+    scan: OnceCell<ArchiveScan>,
+}
 
-        let mut archive = tar::Builder::new(enc_writer);
-        let mut total_size = 0;
+impl TarArchive {
+    fn writer<'w, R: Write + 'w>(
+        compression: &CompressionSpec,
+        writer: R,
+    ) -> Result<Box<dyn FinishableWrite + 'w>, ArchiveError> {
+        ArchiveCodec::get_writer(compression, writer)
+    }
+
+    fn scan(&self) -> Result<&ArchiveScan, ArchiveError> {
+        ArchiveScan::cached(&self.scan, || scan_entries(self, None, None))
+    }
 
-        let files = options
-            .files
-            .par_iter()
-            .map(|f| {
-                let metadata = std::fs::metadata(f).map_err(|e| {
-                    ArchiveError::Io(std::io::Error::new(
-                        e.kind(),
-                        format!("could not read file metadata for '{}': {}", f.display(), e),
-                    ))
-                })?;
-
-                let mut name = f
-                    .strip_prefix(&options.source)
-                    .as_deref()
-                    .map_or_else(|_| f.to_path_buf(), |p| p.to_path_buf());
-                if metadata.is_dir() && name.as_os_str().is_empty() {
-                    name.push(".");
+    /// Writes the files of `options` past the last entry of an uncompressed archive, or streams
+    /// a compressed one, or one with entries replaced, to a new archive ending with them.
+    pub(crate) fn append(&self, options: AppendOptions) -> Result<CreateResult, ArchiveError> {
+        let path = archive_path(&self.source)?;
+        let compression = ArchiveType::compression_of(self.source.clone())?;
+        let replaced = options.replaced();
+
+        if compression == ArchiveCompression::None && replaced.is_empty() {
+            let mut file = File::options().read(true).write(true).open(&path)?;
+            // the end-of-archive blocks, and the padding of the last record, are written over
+            let mut end = 0;
+            for entry in tar::Archive::new(&mut file).entries_with_seek()? {
+                let entry = entry?;
+                end =
+                    entry.raw_file_position() + entry.header().entry_size()?.next_multiple_of(512);
+            }
+            file.seek(SeekFrom::Start(end))?;
+            let mut builder = tar::Builder::new(&mut file);
+            let total_size = append_files(&mut builder, &options)?;
+            builder.into_inner()?;
+            let len = file.stream_position()?;
+            file.set_len(len)?;
+            return Ok(CreateResult {
+                path,
+                total_size,
+                compressed_size: len,
+                unreadable: Vec::new(),
+            });
+        }
+
+        let mut archive =
+            tar::Archive::new(ArchiveCodec::get_reader(self.source.clone(), &compression)?);
+        let mut total_size = 0;
+        rewrite(&path, "", |out| {
+            let spec = CompressionSpec {
+                level: options.level,
+                ..CompressionSpec::new(compression.clone())
+            };
+            let mut builder = tar::Builder::new(Self::writer(&spec, out)?);
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let name = entry.path()?.to_path_buf();
+                if replaced.contains(normalized_name(&name.to_string_lossy())) {
+                    continue;
+                }
+                let mut header = entry.header().clone();
+                match header.entry_type() {
+                    tar::EntryType::Symlink | tar::EntryType::Link => {
+                        let link = entry.link_name()?.unwrap_or_default().to_path_buf();
+                        builder.append_link(&mut header, name, link)?;
+                    }
+                    _ => builder.append_data(&mut header, name, &mut entry)?,
                 }
-                Ok((f, name, metadata))
+            }
+            total_size = append_files(&mut builder, &options)?;
+            builder.into_inner()?.finish_writer()?;
+            Ok(true)
+        })?;
+        Ok(CreateResult {
+            compressed_size: std::fs::metadata(&path)?.len(),
+            path,
+            total_size,
+            unreadable: Vec::new(),
+        })
+    }
+
+    fn visited_entry<R: Read>(
+        entry: &tar::Entry<'_, R>,
+        compression: &ArchiveCompression,
+    ) -> Result<VisitedEntry, ArchiveError> {
+        let header = entry.header();
+        let fstype = header.entry_type().into();
+
+        let (size, compressed_size) = if fstype == ArchiveFileEntityType::File {
+            // the compressed size of an entry is unknown once the whole archive is compressed
+            let compressed = (*compression == ArchiveCompression::None).then(|| entry.size());
+            (Some(entry.size()), compressed)
+        } else {
+            (None, None)
+        };
+        let entity = ArchiveFileEntity {
+            name: entry
+                .path()?
+                .to_string_lossy()
+                .to_string()
+                .replace('\\', "/"),
+            size,
+            compressed_size,
+            fstype,
+            last_modified: header
+                .mtime()
+                .map(|t| t as i64)
+                .and_then(datetime_from_timestamp)
+                .ok(),
+            compression: Some(compression.to_string()),
+            raw: Some(RawEntryLocation {
+                header_offset: entry.raw_header_position(),
+                data_offset: entry.raw_file_position(),
+                flags: header.entry_type().as_byte() as u16,
+            }),
+            unix: None,
+        };
+
+        let link_name = || -> Result<String, ArchiveError> {
+            Ok(entry
+                .link_name()?
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_default())
+        };
+        let link = match header.entry_type() {
+            tar::EntryType::Symlink => Some(EntryLink::Symbolic(link_name()?)),
+            tar::EntryType::Link => Some(EntryLink::Hard(link_name()?)),
+            _ => None,
+        };
+
+        Ok(VisitedEntry {
+            mode: header.mode().ok(),
+            link,
+            ..VisitedEntry::new(entity)
+        })
+    }
+
+    pub(crate) fn entries<'s>(&'s self, options: ListOptions<'s>) -> ArchiveEntries<'s> {
+        let open = || -> Result<TarEntries<'s>, ArchiveError> {
+            let compression = ArchiveType::compression_of(self.source.clone())?;
+            let reader = ArchiveCodec::get_reader(self.source.clone(), &compression)?;
+            Ok(TarEntries {
+                stream: Rc::new(RefCell::new(TarStream {
+                    reader,
+                    entry: 0,
+                    remaining: 0,
+                    padding: 0,
+                    offset: 0,
+                })),
+                compression,
+                options,
+                done: false,
             })
-            .collect::<Result<Vec<_>, ArchiveError>>()
-            .map_err(|e| {
-                ArchiveError::Io(std::io::Error::new(
-                    std::io::ErrorKind::Other,
-                    format!("Failed to read file metadatas: {}", e),
-                ))
-            })?;
+        };
+        match open() {
+            Ok(entries) => ArchiveEntries::new(entries),
+            Err(e) => ArchiveEntries::failed(e),
+        }
+    }
+}
 
-        for (file, name, metadata) in files {
-            total_size += metadata.len();
+/// Iterator of [`TarArchive::entries`], reading the headers and the contents of the entries in
+/// turn from a single pass over the archive.
+struct TarEntries<'a> {
+    stream: Rc<RefCell<TarStream<'a>>>,
+    compression: ArchiveCompression,
+    options: ListOptions<'a>,
+    done: bool,
+}
 
-            if metadata.is_file() {
-                eprintln!(
-                    "Adding: {} -> {} ({})",
-                    file.display(),
-                    name.display(),
-                    Byte::from(metadata.len()).get_appropriate_unit(UnitType::Both)
-                );
-            } else {
-                eprintln!("Adding: {} -> {}", file.display(), name.display());
+/// The decompressed archive, shared by the iterator and the content of the current entry.
+struct TarStream<'a> {
+    reader: Box<dyn Read + 'a>,
+    /// Number of the current entry, the only one whose content can be read.
+    entry: usize,
+    /// Bytes of its content left to read, and of the padding following them.
+    remaining: u64,
+    padding: u64,
+    /// Offset of the headers of the next entry, its positions being read relative to them.
+    offset: u64,
+}
+
+/// Content of the entry `entry` of a [`TarStream`].
+struct TarContent<'a> {
+    stream: Rc<RefCell<TarStream<'a>>>,
+    entry: usize,
+}
+
+impl Read for TarContent<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut stream = self.stream.borrow_mut();
+        if stream.entry != self.entry {
+            return Err(std::io::Error::other(
+                "the content of a tar entry can only be read until the next entry",
+            ));
+        }
+        if stream.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+        let max = stream.remaining.min(buf.len() as u64) as usize;
+        let n = stream.reader.read(&mut buf[..max])?;
+        if n == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "tar archive truncated",
+            ));
+        }
+        stream.remaining -= n as u64;
+        Ok(n)
+    }
+}
+
+impl<'a> TarEntries<'a> {
+    fn next_entry(&mut self) -> Result<Option<ArchiveEntry<'a>>, ArchiveError> {
+        loop {
+            let mut guard = self.stream.borrow_mut();
+            let stream = &mut *guard;
+            // what was left unread of the previous entry
+            let left = stream.remaining + stream.padding;
+            if std::io::copy(&mut (&mut stream.reader).take(left), &mut std::io::sink())? < left {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "tar archive truncated",
+                )
+                .into());
+            }
+            stream.entry += 1;
+            (stream.remaining, stream.padding) = (0, 0);
+
+            // a new archive reading the headers of a single entry, long names and extensions
+            // included, and leaving the stream at its content
+            let mut archive = tar::Archive::new(&mut stream.reader);
+            let Some(entry) = archive.entries()?.next() else {
+                return Ok(None);
+            };
+            let mut entry = entry?;
+            let mut visited = Self::visited(&entry, &self.compression, &self.options);
+            if let Some(raw) = visited.as_mut().and_then(|v| v.entity.raw.as_mut()) {
+                raw.header_offset += stream.offset;
+                raw.data_offset += stream.offset;
             }
-            archive
-                .append_path_with_name(file, name)
-                .into_tar_archive_result()?;
+            let entry_type = entry.header().entry_type();
+            let is_file = matches!(
+                entry_type,
+                tar::EntryType::Regular | tar::EntryType::Continuous
+            );
+            let stored = match entry_type.is_gnu_sparse() {
+                true => entry.header().entry_size()?,
+                false => entry.size(),
+            };
+            // sparse files are read through the archive, their holes filled
+            let sparse = match entry_type.is_gnu_sparse() && visited.is_some() {
+                true => {
+                    let mut content = Vec::new();
+                    entry.read_to_end(&mut content)?;
+                    Some(content)
+                }
+                false => None,
+            };
+            stream.remaining = if sparse.is_some() { 0 } else { stored };
+            stream.padding = stored.next_multiple_of(512) - stored;
+            stream.offset += entry.raw_file_position() + stored + stream.padding;
+
+            let Some(visited) = visited else {
+                continue;
+            };
+            let content: Box<dyn Read + 'a> = match sparse {
+                _ if !is_file => Box::new(std::io::empty()),
+                Some(content) => Box::new(std::io::Cursor::new(content)),
+                None => Box::new(TarContent {
+                    stream: self.stream.clone(),
+                    entry: stream.entry,
+                }),
+            };
+            return Ok(Some(ArchiveEntry::new(visited, content)));
         }
+    }
 
-        let mut moved = archive.into_inner()?;
-        moved.finish_writer()?;
+    /// The entry, if it is readable and wanted.
+    fn visited<R: Read>(
+        entry: &tar::Entry<'_, R>,
+        compression: &ArchiveCompression,
+        options: &ListOptions,
+    ) -> Option<VisitedEntry> {
+        match TarArchive::visited_entry(entry, compression) {
+            Ok(visited) => visit_options(options)
+                .wants(&visited.entity)
+                .then_some(visited),
+            Err(e) => {
+                options.handle(ArchiveEvent::FailedToReadEntry(
+                    String::from_utf8_lossy(&entry.path_bytes()).to_string(),
+                    e,
+                ));
+                None
+            }
+        }
+    }
+}
 
-        let size = writer.metadata()?.len();
+impl<'a> Iterator for TarEntries<'a> {
+    type Item = Result<ArchiveEntry<'a>, ArchiveError>;
 
-        eprintln!(
-            "Done creating tar archive: {} ({})",
-            options.destination.display(),
-            Byte::from(size).get_appropriate_unit(UnitType::Both)
-        );
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let next = self.next_entry();
+        // the position in the stream is lost after an error
+        self.done = !matches!(next, Ok(Some(_)));
+        next.transpose()
+    }
+}
 
-        Ok(CreateResult {
-            path: options.destination,
-            total_size,
-            compressed_size: size,
+impl EntryVisitor for TarArchive {
+    fn for_each_entry<F>(
+        &self,
+        options: &VisitOptions<'_>,
+        mut visit: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        let compression = ArchiveType::compression_of(self.source.clone())?;
+        let source = options
+            .retry
+            .wrap(self.source.clone(), &self.source, options.events);
+        let mut archive = tar::Archive::new(ArchiveCodec::get_reader(source, &compression)?);
+
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let visited = match Self::visited_entry(&entry, &compression) {
+                Ok(visited) => visited,
+                Err(e) => {
+                    if let Some(events) = options.events {
+                        events.handle(ArchiveEvent::FailedToReadEntry(
+                            String::from_utf8_lossy(&entry.path_bytes()).to_string(),
+                            e,
+                        ));
+                    }
+                    continue;
+                }
+            };
+            if !options.wants(&visited.entity) {
+                continue;
+            }
+
+            let is_file = matches!(
+                entry.header().entry_type(),
+                tar::EntryType::Regular | tar::EntryType::Continuous
+            );
+            let flow = if options.contents && is_file {
+                visit(visited, Some(&mut entry))?
+            } else {
+                visit(visited, None)?
+            };
+
+            if flow.is_break() {
+                return Ok(());
+            }
+        }
+        if options.drain {
+            std::io::copy(&mut archive.into_inner(), &mut std::io::sink())?;
+        }
+        Ok(())
+    }
+}
+
+impl Archived for TarArchive {
+    fn of(source: DataSource) -> Result<Self, ArchiveError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            source,
+            scan: OnceCell::new(),
         })
     }
 
-    fn metadata(&self) -> Result<ArchiveMetadata, ArchiveError> {
-        let entries = self.list(ListOptions::default())?;
+    fn extract(&self, options: ExtractOptions) -> Result<ExtractReport, ArchiveError> {
+        extract_entries(self, &options, self.source.as_ref())
+    }
 
-        let (size, compressed_size) = entries.iter().fold((0, 0), |(s, cs), e| {
-            (s + e.size.unwrap_or(0), cs + e.compressed_size.unwrap_or(0))
-        });
+    fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
+        if options.is_whole() {
+            return Ok(self.scan()?.entries.clone());
+        }
+        list_entries(self, self.scan.get(), &options)
+    }
+
+    fn create(options: CreateOptions) -> Result<CreateResult, ArchiveError> {
+        let writer = File::create(&options.destination).map_err(|e| {
+            ArchiveError::Io(std::io::Error::new(
+                e.kind(),
+                format!("could not create destination file: {}", e),
+            ))
+        })?;
+        create_tar_stream(options, &writer)
+    }
+
+    fn metadata(&self) -> Result<ArchiveMetadata, ArchiveError> {
+        let scan = self.scan()?;
 
         Ok(ArchiveMetadata {
-            entries,
-            total_size: size,
-            compressed_size,
-            compression: ArchiveType::try_from_datasource(self.source.clone())
-                .ok()
-                .map(|t| t.1),
+            archive_type: ArchiveType::Tar,
+            entry_count: scan.entries.len(),
+            entries: scan.entries.clone(),
+            total_size: scan.total_size,
+            compressed_size: scan.compressed_size,
+            compression: ArchiveType::compression_of(self.source.clone()).ok(),
+            created: self.source.created(),
+            modified: self.source.modified(),
             additional: None,
         })
     }
 
-    fn open(&'a self, options: crate::archive::OpenOptions) -> Result<(), ArchiveError> {
-        let path = options.path;
+    fn open(&self, options: crate::archive::OpenOptions) -> Result<(), ArchiveError> {
+        open_entry(self, options)
+    }
 
-        let reader = self.reader()?;
+    fn find_first<P>(
+        &self,
+        predicate: P,
+    ) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+    where
+        P: Fn(&ArchiveFileEntity) -> bool,
+    {
+        find_first_entry(self, predicate)
+    }
 
-        let mut archive = tar::Archive::new(reader);
+    fn check_password(&self, _password: &str) -> Result<bool, ArchiveError> {
+        // tar has no encryption of its own
+        Ok(true)
+    }
 
-        let mut file = archive
-            .entries()?
-            .find_map(|entry| {
-                let entry = entry.ok()?;
-                let entry_path = entry.path().ok()?;
-                if entry_path == path {
-                    Some(entry)
-                } else {
-                    None
-                }
-            })
-            .ok_or_else(|| ArchiveError::EntryNotFound(path))?;
+    fn edit(&self, target: &str, edit: &EntryEdit) -> Result<(), ArchiveError> {
+        // names and times live in the header of each entry, the archive is streamed to a new
+        // one with the edited headers
+        let path = archive_path(&self.source)?;
+        let compression = ArchiveType::compression_of(self.source.clone())?;
+        let mut archive =
+            tar::Archive::new(ArchiveCodec::get_reader(self.source.clone(), &compression)?);
 
-        let mut writer = options.dest;
+        rewrite(&path, target, |out| {
+            let mut builder = tar::Builder::new(Self::writer(
+                &CompressionSpec::new(compression.clone()),
+                out,
+            )?);
+            let mut edited = false;
+            for entry in archive.entries()? {
+                let mut entry = entry?;
+                let name = entry.path()?.to_string_lossy().replace('\\', "/");
+                edited |= edit.applies(target, &name);
 
-        std::io::copy(&mut file, &mut writer)?;
+                let mut header = entry.header().clone();
+                if let Some(modified) = edit.modified(target, &name) {
+                    header.set_mtime(modified.timestamp().max(0) as u64);
+                }
+                let new_name = edit.renamed(target, &name).unwrap_or(name);
 
-        Ok(())
+                match header.entry_type() {
+                    tar::EntryType::Symlink | tar::EntryType::Link => {
+                        let link = entry
+                            .link_name()?
+                            .map(|l| l.to_string_lossy().to_string())
+                            .unwrap_or_default();
+                        // hard links point to entries of the archive, which may be renamed too
+                        let link = match header.entry_type() {
+                            tar::EntryType::Link => edit.renamed(target, &link).unwrap_or(link),
+                            _ => link,
+                        };
+                        builder.append_link(&mut header, new_name, link)?;
+                    }
+                    _ => builder.append_data(&mut header, new_name, &mut entry)?,
+                }
+            }
+            builder.into_inner()?.finish_writer()?;
+            Ok(edited)
+        })
     }
 }
 
-impl<'a> TryFrom<DataSource<'a>> for ArchiveCompression {
-    fn try_from(source: DataSource<'a>) -> Result<Self, Self::Error> {
+impl TryFrom<DataSource> for ArchiveCompression {
+    fn try_from(source: DataSource) -> Result<Self, Self::Error> {
         let mut reader = BufReader::new(source);
 
         // read magic bytes to identify the compression
@@ -307,7 +952,10 @@ mod tests {
 
     use chrono::{DateTime, FixedOffset};
 
-    use crate::{archive::ArchiveFileEntityType, assert_eq_some};
+    use crate::{
+        archive::{owner::Owner, Archive, ArchiveFileEntityType, ExceededLimit, ExtractLimits},
+        assert_eq_some,
+    };
 
     use super::*;
 
@@ -355,4 +1003,397 @@ mod tests {
             Some(DateTime::<FixedOffset>::from_str("2023-10-01T16:46:52+00:00").unwrap())
         );
     }
+
+    #[test]
+    fn tar_metadata_reuses_listing() {
+        let archive = TarArchive::from_path("tests/fixtures/test1.tar.gz").unwrap();
+        let entities = archive.list(ListOptions::default()).unwrap();
+        let metadata = archive.metadata().unwrap();
+
+        assert_eq!(metadata.entry_count, entities.len());
+        assert_eq!(
+            metadata.total_size,
+            entities.iter().filter_map(|e| e.size).sum::<u64>()
+        );
+        assert_eq!(metadata.compression, Some(ArchiveCompression::Gzip));
+    }
+
+    #[test]
+    fn edit_rewrites_archive() {
+        let path =
+            std::env::temp_dir().join(format!("hezi-tar-edit-{}.tar.gz", std::process::id()));
+        std::fs::copy("tests/fixtures/test1.tar.gz", &path).unwrap();
+
+        let archive = TarArchive::from_path(&path).unwrap();
+        let modified = DateTime::<FixedOffset>::from_str("2020-05-06T07:08:10+00:00").unwrap();
+        archive.rename("test1/file1.txt", "renamed.txt").unwrap();
+        archive.touch("renamed.txt", modified).unwrap();
+
+        let edited = TarArchive::from_path(&path).unwrap();
+        let (entity, content) = edited
+            .find_first(|e| e.name == "renamed.txt")
+            .unwrap()
+            .unwrap();
+        assert_eq!(entity.last_modified, Some(modified));
+        assert_eq!(content.len(), 1469);
+        assert_eq!(
+            ArchiveType::try_from_datasource(edited.source.clone())
+                .unwrap()
+                .1,
+            ArchiveCompression::Gzip
+        );
+        _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn appends_to_plain_and_compressed_archives() {
+        use crate::archive::{AppendOptions, Archive};
+
+        for fixture in ["test1.tar", "test1.tar.gz"] {
+            let path =
+                std::env::temp_dir().join(format!("hezi-add-{}-{}", std::process::id(), fixture));
+            std::fs::copy(format!("tests/fixtures/{}", fixture), &path).unwrap();
+            let size = std::fs::metadata(&path).unwrap().len();
+
+            let result = Archive::of(DataSource::file(&path).unwrap())
+                .unwrap()
+                .append(AppendOptions {
+                    source: PathBuf::from("tests/fixtures/test1"),
+                    files: vec![
+                        PathBuf::from("tests/fixtures/test1/dir1"),
+                        PathBuf::from("tests/fixtures/test1/dir1/file2.txt"),
+                    ],
+                    ..Default::default()
+                })
+                .unwrap();
+            assert_eq!(result.total_size, 444);
+
+            let appended = TarArchive::from_path(&path).unwrap();
+            let names = appended
+                .list(ListOptions::default())
+                .unwrap()
+                .into_iter()
+                .map(|e| e.name)
+                .collect::<Vec<_>>();
+            assert_eq!(names[names.len() - 2..], ["dir1", "dir1/file2.txt"]);
+            assert!(names.contains(&"test1/file1.txt".to_string()));
+            let (_, content) = appended
+                .find_first(|e| e.name == "dir1/file2.txt")
+                .unwrap()
+                .unwrap();
+            assert_eq!(content.len(), 444);
+            assert_eq!(
+                ArchiveType::compression_of(appended.source.clone()).unwrap(),
+                ArchiveType::guess_from_filename(fixture)
+                    .unwrap()
+                    .1
+                    .unwrap_or_default()
+            );
+            if fixture == "test1.tar" {
+                // the new entries replace the end-of-archive blocks
+                assert!(std::fs::metadata(&path).unwrap().len() < size + 4 * 512);
+            }
+            _ = std::fs::remove_file(&path);
+        }
+    }
+
+    #[test]
+    fn file_contents_keep_header_size() {
+        let path = std::env::temp_dir().join(format!("hezi-tar-contents-{}", std::process::id()));
+        std::fs::write(&path, vec![7; 3 * DEFAULT_BUF_SIZE]).unwrap();
+
+        let mut contents = Vec::new();
+        FileContents::open(&path, 2 * DEFAULT_BUF_SIZE as u64 + 1)
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        assert_eq!(contents.len(), 2 * DEFAULT_BUF_SIZE + 1);
+
+        let shorter = FileContents::open(&path, 4 * DEFAULT_BUF_SIZE as u64)
+            .unwrap()
+            .read_to_end(&mut Vec::new());
+        assert_eq!(
+            shorter.unwrap_err().kind(),
+            std::io::ErrorKind::UnexpectedEof
+        );
+        _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn creates_tar_streams() {
+        let source = std::path::PathBuf::from("tests/fixtures/test1");
+        let mut written = Vec::new();
+        let result = create_tar_stream(
+            CreateOptions {
+                destination: "-".into(),
+                source: source.clone(),
+                roots: vec![],
+                files: walkdir::WalkDir::new(&source)
+                    .sort_by_file_name()
+                    .into_iter()
+                    .map(|e| e.unwrap().into_path())
+                    .collect(),
+                password: None,
+                archive_type: ArchiveType::Tar,
+                compression: Some(CompressionSpec::new(ArchiveCompression::Gzip)),
+                overwrite: false,
+                include_hidden: true,
+                skip_unreadable: false,
+                verify_after: false,
+                volume_size: None,
+                update: false,
+                sevenz: Default::default(),
+                zip: Default::default(),
+                tar: Default::default(),
+                event_handler: Box::new(crate::archive::SimpleLogger),
+            },
+            &mut written,
+        )
+        .unwrap();
+        assert_eq!(result.compressed_size, written.len() as u64);
+
+        let archive = TarArchive::of(DataSource::memory(written.as_slice())).unwrap();
+        let (_, content) = archive
+            .find_first(|e| e.name == "dir1/file2.txt")
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            content,
+            std::fs::read(source.join("dir1/file2.txt")).unwrap()
+        );
+    }
+
+    #[test]
+    fn writes_pax_records_for_names_and_times() {
+        assert_eq!(pax_record("path", "a"), "9 path=a\n");
+        let record = pax_record("path", &"a".repeat(91));
+        assert_eq!(record.len(), 101);
+        assert!(record.starts_with("101 "));
+
+        let source = std::env::temp_dir().join(format!("hezi-tar-pax-{}", std::process::id()));
+        let name = format!("{}/été-{}.txt", "nested".repeat(10), "long".repeat(30));
+        std::fs::create_dir_all(source.join("nested".repeat(10))).unwrap();
+        std::fs::write(source.join(&name), "content").unwrap();
+
+        let mut written = Vec::new();
+        create_tar_stream(
+            CreateOptions {
+                destination: "-".into(),
+                source: source.clone(),
+                roots: vec![],
+                files: vec![source.join(&name)],
+                password: None,
+                archive_type: ArchiveType::Tar,
+                compression: Some(CompressionSpec::new(ArchiveCompression::None)),
+                overwrite: false,
+                include_hidden: true,
+                skip_unreadable: false,
+                verify_after: false,
+                volume_size: None,
+                update: false,
+                sevenz: Default::default(),
+                zip: Default::default(),
+                tar: TarCreateOptions {
+                    format: TarFormat::Pax,
+                    precise_times: true,
+                    ..Default::default()
+                },
+                event_handler: Box::new(crate::archive::SimpleLogger),
+            },
+            &mut written,
+        )
+        .unwrap();
+
+        let mut archive = tar::Archive::new(written.as_slice());
+        let mut entry = archive.entries().unwrap().next().unwrap().unwrap();
+        assert!(entry.header().as_ustar().is_some());
+        assert_eq!(entry.path().unwrap().to_string_lossy(), name);
+        let mtime = entry
+            .pax_extensions()
+            .unwrap()
+            .unwrap()
+            .map(|e| e.unwrap())
+            .find(|e| e.key() == Ok("mtime"))
+            .map(|e| e.value().unwrap().to_string())
+            .unwrap();
+        assert!(mtime.starts_with(&entry.header().mtime().unwrap().to_string()));
+        let mut content = String::new();
+        entry.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "content");
+
+        let listed = TarArchive::of(DataSource::memory(written.as_slice()))
+            .unwrap()
+            .list(ListOptions::default())
+            .unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].name, name);
+        _ = std::fs::remove_dir_all(&source);
+    }
+
+    #[test]
+    fn reads_headers_without_ustar_magic() {
+        for path in [
+            "tests/fixtures/test1-v7.tar",
+            "tests/fixtures/test1-oldgnu.tar",
+        ] {
+            let mut header = [0; 512];
+            std::fs::File::open(path)
+                .unwrap()
+                .read_exact(&mut header)
+                .unwrap();
+            assert!(is_tar_header(&header), "{}", path);
+
+            let archive = TarArchive::from_path(path).unwrap();
+            let entities = archive.list(ListOptions::default()).unwrap();
+            let files = entities
+                .iter()
+                .filter(|e| e.fstype == ArchiveFileEntityType::File)
+                .map(|e| (e.name.as_str(), e.size))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                files,
+                [
+                    ("test1/dir1/file2.txt", Some(444)),
+                    ("test1/file1.txt", Some(1469))
+                ],
+                "{}",
+                path
+            );
+        }
+
+        let mut corrupted = [0; 512];
+        corrupted[..5].copy_from_slice(b"notes");
+        corrupted[148..155].copy_from_slice(b"0001234");
+        assert!(!is_tar_header(&corrupted));
+    }
+
+    #[test]
+    fn overrides_owners() {
+        let header = || {
+            let mut header = tar::Header::new_gnu();
+            header.set_uid(1234);
+            header.set_gid(5678);
+            header
+        };
+        let root = TarCreateOptions {
+            owner: Some(Owner::user("root:0").unwrap()),
+            group: Some(Owner::group("wheel:0").unwrap()),
+            numeric_owner: false,
+            ..Default::default()
+        };
+        let mut forced = header();
+        set_owner(&mut forced, &root, &mut OwnerNames::default()).unwrap();
+        assert_eq!((forced.uid().unwrap(), forced.gid().unwrap()), (0, 0));
+        assert_eq_some!(forced.username().unwrap(), "root");
+        assert_eq_some!(forced.groupname().unwrap(), "wheel");
+
+        let numeric = TarCreateOptions {
+            owner: Some(Owner::user("42").unwrap()),
+            group: None,
+            numeric_owner: true,
+            ..Default::default()
+        };
+        let mut forced = header();
+        set_owner(&mut forced, &numeric, &mut OwnerNames::default()).unwrap();
+        assert_eq!((forced.uid().unwrap(), forced.gid().unwrap()), (42, 5678));
+        assert_eq!(forced.username().unwrap(), Some(""));
+        assert_eq!(forced.groupname().unwrap(), Some(""));
+    }
+
+    #[test]
+    fn locates_entries() {
+        let data = std::fs::read("tests/fixtures/test1.tar").unwrap();
+        let archive = TarArchive::from_bytes(data.as_slice()).unwrap();
+        let entries = archive.list(ListOptions::default()).unwrap();
+        assert!(!entries.is_empty());
+        for entry in &entries {
+            let raw = entry.raw().unwrap();
+            let header = tar::Header::from_byte_slice(&data[raw.header_offset as usize..][..512]);
+            assert_eq!(header.entry_type().as_byte() as u16, raw.flags);
+            assert_eq!(raw.data_offset, raw.header_offset + 512);
+            if let Some(size) = entry.size() {
+                assert_eq!(header.entry_size().unwrap(), size);
+            }
+        }
+
+        // a single pass reading one entry at a time finds the same locations
+        let streamed = Archive::Tar(archive)
+            .entries(ListOptions::default())
+            .map(|e| e.unwrap().entity().raw().copied())
+            .collect::<Vec<_>>();
+        let listed = entries.iter().map(|e| e.raw().copied()).collect::<Vec<_>>();
+        assert_eq!(streamed, listed);
+    }
+
+    /// A tar archive of `files` files of `size` zeros.
+    fn zeros_tar(files: usize, size: usize) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for i in 0..files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(size as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(
+                    &mut header,
+                    format!("zeros{i}"),
+                    std::io::repeat(0).take(size as u64),
+                )
+                .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    #[test]
+    fn stops_at_tar_gz_bombs() {
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::best());
+        gz.write_all(&zeros_tar(2, 8 * 1024 * 1024)).unwrap();
+        let bomb = gz.finish().unwrap();
+        let dest = std::env::temp_dir().join(format!("hezi-tar-gz-bomb-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dest);
+
+        // each file is within the ratio on its own, not both together
+        let max = 12.0 * 1024.0 * 1024.0 / bomb.len() as f64;
+        let extracted = TarArchive::of(DataSource::memory(bomb))
+            .unwrap()
+            .extract(ExtractOptions {
+                destination: dest.clone(),
+                limits: ExtractLimits {
+                    max_compression_ratio: Some(max),
+                    ..ExtractLimits::NONE
+                },
+                ..Default::default()
+            });
+        assert!(matches!(
+            extracted,
+            Err(ArchiveError::LimitExceeded(name, ExceededLimit::CompressionRatio(_))) if name == "zeros1"
+        ));
+        assert!(dest.join("zeros0").exists());
+        assert!(!dest.join("zeros1").exists());
+        _ = std::fs::remove_dir_all(&dest);
+    }
+
+    #[cfg(feature = "zstd_codecs")]
+    #[test]
+    fn stops_at_tar_zst_bombs_by_default() {
+        let bomb = zstd::encode_all(zeros_tar(1, 32 * 1024 * 1024).as_slice(), 0).unwrap();
+        let dest = std::env::temp_dir().join(format!("hezi-tar-zst-bomb-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dest);
+
+        let extracted = TarArchive::of(DataSource::memory(bomb))
+            .unwrap()
+            .extract(ExtractOptions {
+                destination: dest.clone(),
+                ..Default::default()
+            });
+        assert!(matches!(
+            extracted,
+            Err(ArchiveError::LimitExceeded(
+                _,
+                ExceededLimit::CompressionRatio(10000.0)
+            ))
+        ));
+        assert!(!dest.join("zeros0").exists());
+        _ = std::fs::remove_dir_all(&dest);
+    }
 }
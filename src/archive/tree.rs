@@ -0,0 +1,153 @@
+// the entries of an archive as a directory tree rather than a flat list, for the views browsing it
+// folder by folder, directories holding the total size of the files under them
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use super::{entry_visitor::normalized_name, ArchiveFileEntity, ArchiveFileEntityType};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EntryTree {
+    /// Last component of the path of the entry.
+    pub name: String,
+    pub path: String,
+    #[serde(rename = "type")]
+    pub fstype: ArchiveFileEntityType,
+    /// Size of the file, or total size of the files under the directory.
+    pub size: u64,
+    /// Compressed size of the file, or total of the files under the directory, if all of them
+    /// have one.
+    pub compressed_size: Option<u64>,
+    pub last_modified: Option<chrono::DateTime<chrono::FixedOffset>>,
+    /// Entries of the directory, sorted by name.
+    pub children: Vec<EntryTree>,
+}
+
+/// Entry of a path and those under it, while the tree is built.
+#[derive(Default)]
+struct Node<'e> {
+    entity: Option<&'e ArchiveFileEntity>,
+    children: BTreeMap<&'e str, Node<'e>>,
+}
+
+impl EntryTree {
+    /// Trees of `entries`, one for each entry at the root of the archive. The directories holding
+    /// entries are added even if the archive has no entry of their own.
+    pub fn build(entries: &[ArchiveFileEntity]) -> Vec<EntryTree> {
+        let mut root = Node::default();
+        for entity in entries {
+            let name = normalized_name(&entity.name);
+            let mut components = name.split('/').filter(|c| !c.is_empty()).peekable();
+            // the root of the archive itself, such as `./`, has no node of its own
+            if components.peek().is_none() {
+                continue;
+            }
+            let mut node = &mut root;
+            for component in components {
+                node = node.children.entry(component).or_default();
+            }
+            node.entity = Some(entity);
+        }
+        root.children
+            .into_iter()
+            .map(|(name, node)| node.into_tree(name, String::new()))
+            .collect()
+    }
+
+    /// Number of files in the tree, the entry itself included.
+    pub fn files(&self) -> usize {
+        match self.fstype {
+            ArchiveFileEntityType::Directory => self.children.iter().map(EntryTree::files).sum(),
+            _ => 1,
+        }
+    }
+}
+
+impl Node<'_> {
+    fn into_tree(self, name: &str, parent: String) -> EntryTree {
+        let path = match parent.is_empty() {
+            true => name.to_string(),
+            false => format!("{}/{}", parent, name),
+        };
+        let fstype = match (self.entity, self.children.is_empty()) {
+            (Some(entity), true) => entity.fstype,
+            _ => ArchiveFileEntityType::Directory,
+        };
+        let children = self
+            .children
+            .into_iter()
+            .map(|(name, node)| node.into_tree(name, path.clone()))
+            .collect::<Vec<_>>();
+        let (size, compressed_size) = match fstype {
+            ArchiveFileEntityType::Directory => (
+                children.iter().map(|c| c.size).sum(),
+                children.iter().map(|c| c.compressed_size).sum(),
+            ),
+            _ => (
+                self.entity.and_then(|e| e.size).unwrap_or(0),
+                self.entity.and_then(|e| e.compressed_size),
+            ),
+        };
+        EntryTree {
+            name: name.to_string(),
+            path,
+            fstype,
+            size,
+            compressed_size,
+            last_modified: self.entity.and_then(|e| e.last_modified),
+            children,
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn entity(name: &str, size: Option<u64>, compressed_size: Option<u64>) -> ArchiveFileEntity {
+        ArchiveFileEntity {
+            name: name.to_string(),
+            size,
+            compressed_size,
+            last_modified: None,
+            compression: None,
+            fstype: match name.ends_with('/') {
+                true => ArchiveFileEntityType::Directory,
+                false => ArchiveFileEntityType::File,
+            },
+            raw: None,
+            unix: None,
+        }
+    }
+
+    #[test]
+    fn builds_nested_directories_with_total_sizes() {
+        let entries = [
+            entity("./", None, None),
+            entity("./docs/", None, None),
+            entity("./docs/a.txt", Some(10), Some(4)),
+            entity("./src/lib/b.rs", Some(20), Some(8)),
+            entity("./src/main.rs", Some(5), None),
+            entity("README", Some(1), Some(1)),
+        ];
+        let tree = EntryTree::build(&entries);
+
+        let names = tree.iter().map(|t| t.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["README", "docs", "src"]);
+        let docs = &tree[1];
+        assert_eq!(docs.fstype, ArchiveFileEntityType::Directory);
+        assert_eq!((docs.size, docs.compressed_size), (10, Some(4)));
+        assert_eq!(docs.children[0].path, "docs/a.txt");
+
+        // `src` and `src/lib` are not in the archive, and `main.rs` has no compressed size
+        let src = &tree[2];
+        assert_eq!((src.size, src.compressed_size), (25, None));
+        assert_eq!(src.files(), 2);
+        let lib = &src.children[0];
+        assert_eq!((lib.path.as_str(), lib.size), ("src/lib", 20));
+        assert_eq!(lib.children[0].path, "src/lib/b.rs");
+        assert_eq!(tree.iter().map(EntryTree::files).sum::<usize>(), 4);
+    }
+}
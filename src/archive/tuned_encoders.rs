@@ -0,0 +1,260 @@
+// encoders for the settings of the codecs that the writers of flate2 and bzip2 do not expose: the
+// strategy of gzip, which miniz_oxide takes, and the work factor of bzip2, which only its raw
+// stream takes.
+
+use std::io::{Error, ErrorKind, Write};
+
+use flate2::Crc;
+use miniz_oxide::{
+    deflate::{
+        core::{create_comp_flags_from_zip_params, CompressorOxide},
+        stream::deflate,
+    },
+    MZFlush, MZStatus,
+};
+
+use super::GzipStrategy;
+
+/// Size of the buffer the compressed data goes through.
+const OUT_SIZE: usize = 64 * 1024;
+
+/// Header of a gzip member without name nor modification time, written by an unknown OS, with the
+/// extra flags telling the level, as gzip does.
+pub(crate) fn gzip_header(level: u32) -> [u8; 10] {
+    let flags = match level {
+        9 => 2,
+        1 => 4,
+        _ => 0,
+    };
+    [0x1f, 0x8b, 0x08, 0, 0, 0, 0, 0, flags, 0xff]
+}
+
+/// Writer compressing to gzip, looking for matches with `strategy`.
+pub(crate) struct StrategyGzEncoder<W: Write> {
+    inner: W,
+    level: u32,
+    compressor: Box<CompressorOxide>,
+    crc: Crc,
+    out: Vec<u8>,
+    header_written: bool,
+    finished: bool,
+}
+
+impl<W: Write> StrategyGzEncoder<W> {
+    pub(crate) fn new(inner: W, level: u32, strategy: GzipStrategy) -> Self {
+        let strategy = match strategy {
+            GzipStrategy::Filtered => 1,
+            GzipStrategy::HuffmanOnly => 2,
+            GzipStrategy::Rle => 3,
+            GzipStrategy::Fixed => 4,
+        };
+        // negative window bits for raw deflate data, the gzip framing being written here
+        let flags = create_comp_flags_from_zip_params(level as i32, -15, strategy);
+        Self {
+            inner,
+            level,
+            compressor: Box::new(CompressorOxide::new(flags)),
+            crc: Crc::new(),
+            out: vec![0; OUT_SIZE],
+            header_written: false,
+            finished: false,
+        }
+    }
+
+    /// Compresses `input` whole, writing the output as it comes, and returns whether the stream
+    /// ended.
+    fn compress(&mut self, mut input: &[u8], flush: MZFlush) -> Result<bool, Error> {
+        if !self.header_written {
+            self.inner.write_all(&gzip_header(self.level))?;
+            self.header_written = true;
+        }
+        loop {
+            let res = deflate(&mut self.compressor, input, &mut self.out, flush);
+            let status = res
+                .status
+                .map_err(|e| Error::other(format!("deflate failed: {:?}", e)))?;
+            self.inner.write_all(&self.out[..res.bytes_written])?;
+            input = &input[res.bytes_consumed..];
+            if status == MZStatus::StreamEnd {
+                return Ok(true);
+            }
+            // the output filling the buffer up may leave more to write
+            if input.is_empty() && res.bytes_written < self.out.len() {
+                return Ok(false);
+            }
+        }
+    }
+
+    /// Writes the remaining data and the end of the member.
+    pub(crate) fn try_finish(&mut self) -> Result<(), Error> {
+        if self.finished {
+            return Ok(());
+        }
+        while !self.compress(&[], MZFlush::Finish)? {}
+        self.inner.write_all(&self.crc.sum().to_le_bytes())?;
+        self.inner.write_all(&self.crc.amount().to_le_bytes())?;
+        self.inner.flush()?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for StrategyGzEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        if self.finished {
+            return Err(Error::new(
+                ErrorKind::BrokenPipe,
+                "gzip stream already finished",
+            ));
+        }
+        self.crc.update(buf);
+        self.compress(buf, MZFlush::None)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        if !self.finished {
+            self.compress(&[], MZFlush::Sync)?;
+        }
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for StrategyGzEncoder<W> {
+    fn drop(&mut self) {
+        let _ = self.try_finish();
+    }
+}
+
+/// Writer compressing to bzip2 with a `work_factor`, the effort spent sorting repetitive blocks
+/// before falling back to a slower but steady sort.
+#[cfg(feature = "bzip2_codecs")]
+pub(crate) struct BzWorkFactorEncoder<W: Write> {
+    inner: W,
+    compress: bzip2::Compress,
+    out: Vec<u8>,
+    finished: bool,
+}
+
+#[cfg(feature = "bzip2_codecs")]
+impl<W: Write> BzWorkFactorEncoder<W> {
+    pub(crate) fn new(inner: W, level: bzip2::Compression, work_factor: u32) -> Self {
+        Self {
+            inner,
+            compress: bzip2::Compress::new(level, work_factor),
+            out: Vec::with_capacity(OUT_SIZE),
+            finished: false,
+        }
+    }
+
+    /// Compresses `input` whole with `action`, writing the output as it comes, and returns whether
+    /// the stream ended.
+    fn compress(&mut self, mut input: &[u8], action: bzip2::Action) -> Result<bool, Error> {
+        loop {
+            self.out.clear();
+            let consumed = self.compress.total_in();
+            let status = self
+                .compress
+                .compress_vec(input, &mut self.out, action)
+                .map_err(Error::other)?;
+            input = &input[(self.compress.total_in() - consumed) as usize..];
+            self.inner.write_all(&self.out)?;
+            if status == bzip2::Status::StreamEnd {
+                return Ok(true);
+            }
+            if input.is_empty() && action == bzip2::Action::Run {
+                return Ok(false);
+            }
+        }
+    }
+
+    /// Writes the remaining data and the end of the stream.
+    pub(crate) fn try_finish(&mut self) -> Result<(), Error> {
+        if self.finished {
+            return Ok(());
+        }
+        self.compress(&[], bzip2::Action::Finish)?;
+        self.inner.flush()?;
+        self.finished = true;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "bzip2_codecs")]
+impl<W: Write> Write for BzWorkFactorEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        if self.finished {
+            return Err(Error::new(
+                ErrorKind::BrokenPipe,
+                "bzip2 stream already finished",
+            ));
+        }
+        self.compress(buf, bzip2::Action::Run)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(feature = "bzip2_codecs")]
+impl<W: Write> Drop for BzWorkFactorEncoder<W> {
+    fn drop(&mut self) {
+        let _ = self.try_finish();
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    fn data() -> Vec<u8> {
+        (0..100_000u64)
+            .flat_map(|i| (i * 7919 % 1000).to_string().into_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn writes_gzip_with_strategies() {
+        let data = data();
+        for strategy in [
+            GzipStrategy::Filtered,
+            GzipStrategy::HuffmanOnly,
+            GzipStrategy::Rle,
+            GzipStrategy::Fixed,
+        ] {
+            let mut out = Vec::new();
+            let mut encoder = StrategyGzEncoder::new(&mut out, 6, strategy);
+            encoder.write_all(&data).unwrap();
+            encoder.try_finish().unwrap();
+            drop(encoder);
+
+            let mut decoded = Vec::new();
+            flate2::read::GzDecoder::new(out.as_slice())
+                .read_to_end(&mut decoded)
+                .unwrap();
+            assert_eq!(decoded, data, "{:?}", strategy);
+        }
+    }
+
+    #[cfg(feature = "bzip2_codecs")]
+    #[test]
+    fn writes_bzip2_with_work_factor() {
+        let data = data();
+        let mut out = Vec::new();
+        let mut encoder = BzWorkFactorEncoder::new(&mut out, bzip2::Compression::best(), 250);
+        encoder.write_all(&data).unwrap();
+        encoder.try_finish().unwrap();
+        drop(encoder);
+
+        let mut decoded = Vec::new();
+        bzip2::read::BzDecoder::new(out.as_slice())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+}
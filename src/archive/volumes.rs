@@ -0,0 +1,482 @@
+// archives split into volumes: the parts of an archive cut at a fixed size, named after it with
+// `.001`, `.002`, ..., and zip archives spanned over `.z01`, `.z02`, ..., `.zip` files, read as a
+// single file
+
+use std::{
+    fs::File,
+    io::{self, ErrorKind, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+/// Signature of the end of central directory record of zip archives.
+const EOCD_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x05, 0x06];
+const EOCD_SIZE: usize = 22;
+/// Signature of the file headers of the central directory of zip archives.
+const CENTRAL_HEADER_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x01, 0x02];
+const CENTRAL_HEADER_SIZE: usize = 46;
+
+/// The volumes of an archive, read one after the other as the archive they were split from.
+pub struct Volumes {
+    parts: Vec<Part>,
+    position: u64,
+    /// Central directory of a spanned zip archive and the bytes after it, rewritten with the
+    /// offsets from the start of the first volume rather than of their own, with its position.
+    central_directory: Option<(u64, Arc<[u8]>)>,
+}
+
+struct Part {
+    path: PathBuf,
+    file: File,
+    /// Offset of the volume from the start of the first one.
+    start: u64,
+    len: u64,
+}
+
+impl Volumes {
+    /// Opens the volumes `path` is the first of, or for spanned zip archives any of, `None` if it
+    /// is not named as a volume or has no other volume next to it.
+    pub(crate) fn open(path: &Path) -> io::Result<Option<Self>> {
+        let Some((paths, spanned)) = volume_paths(path) else {
+            return Ok(None);
+        };
+        let mut volumes = Self::of(&paths)?;
+        if spanned {
+            volumes.central_directory = Some(volumes.joined_central_directory()?);
+        }
+        Ok(Some(volumes))
+    }
+
+    fn of(paths: &[PathBuf]) -> io::Result<Self> {
+        let mut start = 0;
+        let mut parts = Vec::with_capacity(paths.len());
+        for path in paths {
+            let file = File::open(path)?;
+            let len = file.metadata()?.len();
+            parts.push(Part {
+                path: path.clone(),
+                file,
+                start,
+                len,
+            });
+            start += len;
+        }
+        Ok(Self {
+            parts,
+            position: 0,
+            central_directory: None,
+        })
+    }
+
+    /// Path of the first volume.
+    pub fn path(&self) -> &Path {
+        &self.parts[0].path
+    }
+
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.parts.iter().map(|p| p.path.as_path())
+    }
+
+    pub fn len(&self) -> u64 {
+        self.parts.last().map_or(0, |p| p.start + p.len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// File of the first volume, e.g. for its times.
+    pub(crate) fn first(&self) -> &File {
+        &self.parts[0].file
+    }
+
+    /// The volumes opened again, reading from the start.
+    pub fn try_clone(&self) -> io::Result<Self> {
+        let paths = self
+            .parts
+            .iter()
+            .map(|p| p.path.clone())
+            .collect::<Vec<_>>();
+        Ok(Self {
+            central_directory: self.central_directory.clone(),
+            ..Self::of(&paths)?
+        })
+    }
+
+    /// The central directory of a spanned zip archive and its end record, with the volume of
+    /// each file and of the directory set to the first one, and their offsets made relative to
+    /// its start, so that the volumes read as a single zip archive.
+    fn joined_central_directory(&mut self) -> io::Result<(u64, Arc<[u8]>)> {
+        let invalid = |message: &str| io::Error::new(ErrorKind::InvalidData, message.to_string());
+        let unsupported = || {
+            io::Error::new(
+                ErrorKind::Unsupported,
+                "spanned zip archives with Zip64 records are not supported",
+            )
+        };
+
+        let len = self.len();
+        let tail_len = len.min((EOCD_SIZE + u16::MAX as usize) as u64);
+        let mut tail = vec![0; tail_len as usize];
+        self.seek(SeekFrom::Start(len - tail_len))?;
+        self.read_exact(&mut tail)?;
+        let eocd = tail
+            .windows(4)
+            .rposition(|w| w == EOCD_SIGNATURE)
+            .filter(|i| i + EOCD_SIZE <= tail.len())
+            .ok_or_else(|| invalid("end of central directory not found in the last volume"))?;
+        let eocd_position = len - tail_len + eocd as u64;
+        let record = &tail[eocd..];
+        let directory_volume = u16_at(record, 6);
+        let directory_size = u32_at(record, 12);
+        let directory_offset = u32_at(record, 16);
+        if directory_volume == u16::MAX
+            || directory_size == u32::MAX
+            || directory_offset == u32::MAX
+        {
+            return Err(unsupported());
+        }
+
+        let start = self.volume_start(directory_volume)? + directory_offset as u64;
+        if start > eocd_position {
+            return Err(invalid("central directory past its end record"));
+        }
+        let mut directory = vec![0; (len - start) as usize];
+        self.seek(SeekFrom::Start(start))?;
+        self.read_exact(&mut directory)?;
+
+        let mut header = 0;
+        let end = (eocd_position - start) as usize;
+        while header < end {
+            let fields = directory
+                .get(header..header + CENTRAL_HEADER_SIZE)
+                .filter(|h| h[..4] == CENTRAL_HEADER_SIGNATURE)
+                .ok_or_else(|| invalid("invalid central directory"))?;
+            let volume = u16_at(fields, 34);
+            let offset = u32_at(fields, 42);
+            if volume == u16::MAX || offset == u32::MAX {
+                return Err(unsupported());
+            }
+            let joined = u32::try_from(self.volume_start(volume)? + offset as u64)
+                .map_err(|_| unsupported())?;
+            let size = CENTRAL_HEADER_SIZE
+                + u16_at(fields, 28) as usize
+                + u16_at(fields, 30) as usize
+                + u16_at(fields, 32) as usize;
+            directory[header + 34..header + 36].copy_from_slice(&0u16.to_le_bytes());
+            directory[header + 42..header + 46].copy_from_slice(&joined.to_le_bytes());
+            header += size;
+        }
+
+        let joined = u32::try_from(start).map_err(|_| unsupported())?;
+        let record = &mut directory[end..];
+        let entries = u16_at(record, 10);
+        record[4..8].copy_from_slice(&[0; 4]);
+        record[8..10].copy_from_slice(&entries.to_le_bytes());
+        record[16..20].copy_from_slice(&joined.to_le_bytes());
+        self.position = 0;
+        Ok((start, directory.into()))
+    }
+
+    fn volume_start(&self, volume: u16) -> io::Result<u64> {
+        self.parts
+            .get(volume as usize)
+            .map(|p| p.start)
+            .ok_or_else(|| {
+                io::Error::new(
+                    ErrorKind::NotFound,
+                    format!("volume {} of the archive is missing", volume as usize + 1),
+                )
+            })
+    }
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+impl std::fmt::Debug for Volumes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Volumes")
+            .field("paths", &self.paths().collect::<Vec<_>>())
+            .field("position", &self.position)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Read for Volumes {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut end = self.len();
+        if let Some((start, directory)) = &self.central_directory {
+            if self.position >= *start {
+                let rest = directory
+                    .get((self.position - start) as usize..)
+                    .unwrap_or_default();
+                let n = rest.len().min(buf.len());
+                buf[..n].copy_from_slice(&rest[..n]);
+                self.position += n as u64;
+                return Ok(n);
+            }
+            end = *start;
+        }
+        let position = self.position;
+        let Some(part) = self.parts.iter_mut().find(|p| position < p.start + p.len) else {
+            return Ok(0);
+        };
+        let available = (part.start + part.len).min(end) - position;
+        let n = buf
+            .len()
+            .min(usize::try_from(available).unwrap_or(usize::MAX));
+        part.file.seek(SeekFrom::Start(position - part.start))?;
+        let n = part.file.read(&mut buf[..n])?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for Volumes {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(p) => Some(p),
+            SeekFrom::Current(d) => self.position.checked_add_signed(d),
+            SeekFrom::End(d) => self.len().checked_add_signed(d),
+        };
+        self.position = position.ok_or_else(|| {
+            io::Error::new(
+                ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )
+        })?;
+        Ok(self.position)
+    }
+}
+
+/// Path of the `index`th volume of the archive at `path`, counting from 1, e.g. `a.zip.003`.
+pub fn volume_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{:03}", index));
+    PathBuf::from(name)
+}
+
+/// The volumes of the archive `path` belongs to, with whether they are the volumes of a spanned
+/// zip archive rather than pieces of a file. `None` unless there is more than one.
+fn volume_paths(path: &Path) -> Option<(Vec<PathBuf>, bool)> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    let numbered = |first: PathBuf, nth: &dyn Fn(usize) -> PathBuf| {
+        std::iter::once(first)
+            .chain((2..).map(nth))
+            .take_while(|p| p.is_file())
+            .collect::<Vec<_>>()
+    };
+    let volumes = match extension.as_str() {
+        "001" => {
+            let archive = path.with_extension("");
+            (
+                numbered(path.to_path_buf(), &|i| volume_path(&archive, i)),
+                false,
+            )
+        }
+        "z01" | "zip" => {
+            let spanned = |i: usize| path.with_extension(format!("z{:02}", i));
+            let mut volumes = numbered(spanned(1), &spanned);
+            volumes.push(path.with_extension("zip"));
+            (volumes, true)
+        }
+        _ => return None,
+    };
+    volumes
+        .0
+        .iter()
+        .all(|p| p.is_file())
+        .then_some(volumes)
+        .filter(|(paths, _)| paths.len() > 1)
+}
+
+/// Splits the file at `path` into volumes of `volume_size` bytes, the last one holding the rest,
+/// named after it with `.001`, `.002`, .... The file is removed, as are the volumes of a longer
+/// file split before with the same name. Returns the paths of the volumes.
+pub fn split(path: &Path, volume_size: u64) -> io::Result<Vec<PathBuf>> {
+    if volume_size == 0 {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            "volumes must hold at least one byte",
+        ));
+    }
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let count = len.div_ceil(volume_size).max(1);
+    let mut volumes = Vec::new();
+    for index in 1..=count as usize {
+        let volume = volume_path(path, index);
+        let mut writer = io::BufWriter::new(File::create(&volume)?);
+        io::copy(&mut (&mut file).take(volume_size), &mut writer)?;
+        writer.flush()?;
+        volumes.push(volume);
+    }
+    for stale in (count as usize + 1..)
+        .map(|i| volume_path(path, i))
+        .take_while(|p| p.is_file())
+    {
+        std::fs::remove_file(stale)?;
+    }
+    drop(file);
+    std::fs::remove_file(path)?;
+    Ok(volumes)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("hezi-volumes-{}-{}", name, std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn splits_and_joins_volumes() {
+        let dir = temp_dir("split");
+        let path = dir.join("data.bin");
+        let data = (0..2500u32).map(|i| i as u8).collect::<Vec<_>>();
+        std::fs::write(&path, &data).unwrap();
+        // volumes of a longer file split before are removed
+        std::fs::write(volume_path(&path, 4), b"stale").unwrap();
+
+        let volumes = split(&path, 1000).unwrap();
+        assert_eq!(
+            volumes,
+            [1, 2, 3].map(|i| dir.join(format!("data.bin.00{}", i)))
+        );
+        assert!(!path.exists());
+        assert!(!volume_path(&path, 4).exists());
+        assert_eq!(std::fs::metadata(&volumes[2]).unwrap().len(), 500);
+
+        // opened from the first volume only
+        assert!(Volumes::open(&volumes[1]).unwrap().is_none());
+        let mut joined = Volumes::open(&volumes[0]).unwrap().unwrap();
+        let mut read = Vec::new();
+        joined.read_to_end(&mut read).unwrap();
+        assert_eq!(read, data);
+
+        let mut end = [0; 20];
+        joined.seek(SeekFrom::End(-10)).unwrap();
+        assert_eq!(joined.read(&mut end).unwrap(), 10);
+        assert_eq!(end[..10], data[2490..]);
+        joined.seek(SeekFrom::Start(995)).unwrap();
+        joined.read_exact(&mut end).unwrap();
+        assert_eq!(end, data[995..1015]);
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn a_single_volume_is_a_file() {
+        let dir = temp_dir("single");
+        let path = dir.join("small.tar");
+        std::fs::write(&path, b"small").unwrap();
+        let volumes = split(&path, 1000).unwrap();
+        assert_eq!(volumes, [dir.join("small.tar.001")]);
+        assert!(Volumes::open(&volumes[0]).unwrap().is_none());
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(feature = "zip_archive")]
+    #[test]
+    fn reads_spanned_zip_archives() {
+        use crate::archive::{Archive, DataSource, ListOptions};
+
+        let numbers = (1..=14000).map(|i| format!("{}\n", i)).collect::<String>();
+        for path in ["tests/fixtures/spanned.zip", "tests/fixtures/spanned.z01"] {
+            let archive = Archive::of(DataSource::file(path).unwrap()).unwrap();
+            let files = archive
+                .entries(ListOptions::default())
+                .map(|entry| {
+                    let mut entry = entry.unwrap();
+                    let mut content = String::new();
+                    entry.read_to_string(&mut content).unwrap();
+                    (entry.name().to_string(), content)
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                files,
+                [
+                    ("numbers.txt".to_string(), numbers.clone()),
+                    // stored in the second volume
+                    ("hello.txt".to_string(), "hello\n".to_string()),
+                ],
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[cfg(feature = "tar_archive")]
+    #[test]
+    fn creates_archives_in_volumes() {
+        use crate::archive::{
+            Archive, ArchiveCompression, ArchiveType, Archived, CompressionSpec, CreateOptions,
+            DataSource, ListOptions, SimpleLogger,
+        };
+
+        let dir = temp_dir("create");
+        let destination = dir.join("test1.tar");
+        let files = walkdir::WalkDir::new("tests/fixtures/test1")
+            .into_iter()
+            .map(|e| e.unwrap().into_path())
+            .collect::<Vec<_>>();
+        let result = Archive::create(CreateOptions {
+            destination: destination.clone(),
+            source: PathBuf::from("tests/fixtures"),
+            roots: vec![],
+            files,
+            password: None,
+            archive_type: ArchiveType::Tar,
+            compression: Some(CompressionSpec::new(ArchiveCompression::None)),
+            overwrite: true,
+            include_hidden: true,
+            skip_unreadable: false,
+            verify_after: true,
+            volume_size: Some(4096),
+            update: false,
+            sevenz: Default::default(),
+            zip: Default::default(),
+            tar: Default::default(),
+            event_handler: Box::new(SimpleLogger),
+        })
+        .unwrap();
+        assert_eq!(result.path, dir.join("test1.tar.001"));
+        assert!(!destination.exists());
+        assert!(volume_path(&destination, 2).exists());
+
+        // verified against the source before being split, then read back from the volumes
+        let names = Archive::of(DataSource::file(&result.path).unwrap())
+            .unwrap()
+            .list(ListOptions::default())
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            [
+                "test1",
+                "test1/dir1",
+                "test1/dir1/file2.txt",
+                "test1/file1.txt"
+            ]
+        );
+        _ = std::fs::remove_dir_all(&dir);
+    }
+}
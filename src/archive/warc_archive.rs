@@ -0,0 +1,486 @@
+// read-only support for the web archives of ISO 28500 (WARC), as written by crawlers such as
+// wget or Heritrix. A WARC file is a sequence of records, each a block of named header fields
+// followed by a block of the length they tell, and is usually compressed with a gzip member per
+// record, which reads as a single gzip stream. The responses and resources captured are listed
+// as files named after their URL; the other records, such as requests, are only counted.
+
+use std::{
+    cell::OnceCell,
+    collections::{BTreeMap, HashMap},
+    io::{BufRead, BufReader, ErrorKind, Read, Seek, SeekFrom},
+    ops::ControlFlow,
+};
+
+use chrono::{DateTime, FixedOffset};
+use serde_json::json;
+
+use crate::archive::{
+    codecs::{ArchiveCodec, ArchiveCompression},
+    entry_visitor::{
+        extract_entries, find_first_entry, list_entries, open_entry, scan_entries, EntryVisitor,
+        VisitOptions, VisitedEntry,
+    },
+    ArchiveError, ArchiveFileEntity, ArchiveFileEntityType, ArchiveMetadata, ArchiveScan,
+    ArchiveType, Archived, CreateOptions, CreateResult, DataSource, ExtractOptions, ExtractReport,
+    ListOptions, OpenOptions, DEFAULT_BUF_SIZE,
+};
+
+use super::edit::EntryEdit;
+
+/// Start of the first line of every record, followed by the version.
+pub(crate) const WARC_MAGIC: &[u8] = b"WARC/";
+
+fn invalid_data(message: String) -> ArchiveError {
+    ArchiveError::Io(std::io::Error::new(ErrorKind::InvalidData, message))
+}
+
+/// Header fields of a record, or of the HTTP message it holds.
+#[derive(Debug, Default)]
+struct Fields(Vec<(String, String)>);
+
+impl Fields {
+    /// Parses lines of `Name: value` fields, values being continued on the lines starting with
+    /// white space.
+    fn parse(text: &str) -> Result<Self, ArchiveError> {
+        let mut fields: Vec<(String, String)> = Vec::new();
+        for line in text.lines().map(|l| l.trim_end_matches('\r')) {
+            match (line.starts_with([' ', '\t']), fields.last_mut()) {
+                (_, _) if line.trim().is_empty() => {}
+                (true, Some((_, value))) => {
+                    value.push(' ');
+                    value.push_str(line.trim());
+                }
+                _ => {
+                    let (name, value) = line.split_once(':').ok_or_else(|| {
+                        invalid_data(format!("invalid WARC header line: {}", line))
+                    })?;
+                    fields.push((name.trim().to_string(), value.trim().to_string()));
+                }
+            }
+        }
+        Ok(Self(fields))
+    }
+
+    /// Reads fields up to an empty line, returning them with the number of bytes read.
+    fn read<R: BufRead + ?Sized>(reader: &mut R) -> Result<(Self, u64), ArchiveError> {
+        let (mut text, mut line) = (String::new(), String::new());
+        let mut read = 0;
+        loop {
+            line.clear();
+            let n = reader.read_line(&mut line)?;
+            if n == 0 {
+                return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into());
+            }
+            read += n as u64;
+            if line.trim_end_matches(['\r', '\n']).is_empty() {
+                return Ok((Self::parse(&text)?, read));
+            }
+            text.push_str(&line);
+        }
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+#[derive(Debug)]
+struct Record {
+    version: String,
+    fields: Fields,
+    length: u64,
+}
+
+impl Record {
+    /// Reads the header of the next record, or `None` at the end of the file.
+    fn read<R: BufRead + ?Sized>(reader: &mut R) -> Result<Option<Self>, ArchiveError> {
+        // records are separated by two line breaks, tolerated in any number
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            if !line.trim().is_empty() {
+                break;
+            }
+        }
+        let version = line
+            .trim_end()
+            .strip_prefix("WARC/")
+            .ok_or_else(|| invalid_data(format!("invalid WARC record start: {}", line.trim())))?
+            .to_string();
+
+        let (fields, _) = Fields::read(reader)?;
+        let length = fields
+            .get("Content-Length")
+            .and_then(|l| l.parse().ok())
+            .ok_or_else(|| invalid_data("WARC record without a Content-Length".to_string()))?;
+        Ok(Some(Self {
+            version,
+            fields,
+            length,
+        }))
+    }
+
+    fn kind(&self) -> &str {
+        self.fields.get("WARC-Type").unwrap_or_default()
+    }
+
+    fn date(&self) -> Option<DateTime<FixedOffset>> {
+        DateTime::parse_from_rfc3339(self.fields.get("WARC-Date")?).ok()
+    }
+
+    /// Whether the block is an HTTP response, whose body is the payload.
+    fn holds_http_response(&self) -> bool {
+        self.kind() == "response"
+            && self
+                .fields
+                .get("Content-Type")
+                .is_some_and(|t| t.starts_with("application/http"))
+    }
+}
+
+/// Path a payload fetched from `uri` is stored at: the host followed by the path, where a
+/// directory stands for its `index.html`, and an escaped query if any.
+fn uri_path(uri: &str) -> String {
+    let uri = uri.trim_start_matches('<').trim_end_matches('>');
+    let Some((_, rest)) = uri.split_once("://") else {
+        // such as urn:uuid:..., without a hierarchy
+        return uri.replace([':', '/'], "_");
+    };
+    let rest = rest.split('#').next().unwrap_or_default();
+    let (location, query) = match rest.split_once('?') {
+        Some((location, query)) => (location, Some(query)),
+        None => (rest, None),
+    };
+    // file URLs have no host
+    let (host, location) = location.split_once('/').unwrap_or((location, ""));
+    let mut path = match host {
+        "" => location.to_string(),
+        host => format!("{}/{}", host, location),
+    };
+    if location.is_empty() || location.ends_with('/') {
+        path.push_str("index.html");
+    }
+    if let Some(query) = query {
+        path.push_str("%3F");
+        path.push_str(&query.replace('/', "%2F"));
+    }
+    path
+}
+
+/// Decodes an HTTP body sent with the chunked transfer coding.
+fn read_chunked<R: BufRead + ?Sized>(reader: &mut R) -> Result<Vec<u8>, ArchiveError> {
+    let mut body = Vec::new();
+    let mut line = String::new();
+    loop {
+        line.clear();
+        reader.read_line(&mut line)?;
+        let size = line.split(';').next().unwrap_or_default().trim();
+        let size = u64::from_str_radix(size, 16)
+            .map_err(|_| invalid_data(format!("invalid HTTP chunk size: {}", size)))?;
+        if size == 0 {
+            // the trailer fields are left unread
+            return Ok(body);
+        }
+        let read = reader.take(size).read_to_end(&mut body)?;
+        if (read as u64) < size {
+            return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into());
+        }
+        line.clear();
+        reader.read_line(&mut line)?;
+    }
+}
+
+pub struct WarcArchive {
+    pub(crate) source: DataSource,
+    scan: OnceCell<ArchiveScan>,
+}
+
+impl WarcArchive {
+    fn scan(&self) -> Result<&ArchiveScan, ArchiveError> {
+        ArchiveScan::cached(&self.scan, || scan_entries(self, None, None))
+    }
+
+    fn compression(&self) -> Result<ArchiveCompression, ArchiveError> {
+        ArchiveType::compression_of(self.source.clone())
+    }
+
+    /// Calls `record` with the header and the block of every record, in order.
+    fn for_each_record<F>(
+        &self,
+        compression: &ArchiveCompression,
+        options: &VisitOptions<'_>,
+        mut record: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(&Record, &mut dyn BufRead) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        let source = options
+            .retry
+            .wrap(self.source.clone(), &self.source, options.events);
+        let mut reader = BufReader::with_capacity(
+            DEFAULT_BUF_SIZE,
+            ArchiveCodec::get_reader(source, compression)?,
+        );
+
+        while let Some(header) = Record::read(&mut reader)? {
+            let mut block = (&mut reader).take(header.length);
+            let flow = record(&header, &mut block)?;
+            // the block may be left partly unread
+            std::io::copy(&mut block, &mut std::io::sink())?;
+            if block.limit() > 0 {
+                return Err(std::io::Error::from(ErrorKind::UnexpectedEof).into());
+            }
+            if flow.is_break() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl EntryVisitor for WarcArchive {
+    fn for_each_entry<F>(
+        &self,
+        options: &VisitOptions<'_>,
+        mut visit: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        // the same URL may have been captured several times
+        let mut captures = HashMap::<String, usize>::new();
+        let compression = self.compression()?;
+        self.for_each_record(&compression, options, |record, block| {
+            if !matches!(record.kind(), "response" | "resource") {
+                return Ok(ControlFlow::Continue(()));
+            }
+            let Some(uri) = record.fields.get("WARC-Target-URI") else {
+                return Ok(ControlFlow::Continue(()));
+            };
+            let path = uri_path(uri);
+            let count = captures.entry(path.clone()).or_default();
+            *count += 1;
+            let name = match *count {
+                1 => path,
+                n => format!("{}~{}", path, n),
+            };
+
+            let mut chunked = None;
+            let mut size = record.length;
+            if record.holds_http_response() {
+                let mut status = String::new();
+                let status_len = block.read_line(&mut status)? as u64;
+                let (http, header_len) = Fields::read(block)?;
+                size = size.saturating_sub(status_len + header_len);
+                let is_chunked = http
+                    .get("Transfer-Encoding")
+                    .is_some_and(|e| e.eq_ignore_ascii_case("chunked"));
+                if is_chunked {
+                    let body = read_chunked(block)?;
+                    size = body.len() as u64;
+                    chunked = Some(body);
+                }
+            }
+
+            let visited = VisitedEntry::new(ArchiveFileEntity {
+                name,
+                size: Some(size),
+                compressed_size: None,
+                last_modified: record.date(),
+                compression: Some(compression.to_string()),
+                fstype: ArchiveFileEntityType::File,
+                raw: None,
+                unix: None,
+            });
+            if !options.wants(&visited.entity) {
+                return Ok(ControlFlow::Continue(()));
+            }
+            match (options.contents, chunked) {
+                (true, Some(body)) => visit(visited, Some(&mut body.as_slice())),
+                (true, None) => visit(visited, Some(block)),
+                (false, _) => visit(visited, None),
+            }
+        })
+    }
+}
+
+impl Archived for WarcArchive {
+    fn of(source: DataSource) -> Result<Self, ArchiveError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            source,
+            scan: OnceCell::new(),
+        })
+    }
+
+    fn extract(&self, options: ExtractOptions) -> Result<ExtractReport, ArchiveError> {
+        extract_entries(self, &options, self.source.as_ref())
+    }
+
+    fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
+        if options.is_whole() {
+            return Ok(self.scan()?.entries.clone());
+        }
+        list_entries(self, self.scan.get(), &options)
+    }
+
+    fn create(_options: CreateOptions) -> Result<CreateResult, ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "create".to_string(),
+            ArchiveType::Warc,
+        ))
+    }
+
+    fn metadata(&self) -> Result<ArchiveMetadata, ArchiveError> {
+        let compression = self.compression()?;
+        let mut records = BTreeMap::<String, usize>::new();
+        let (mut version, mut software) = (None, None);
+        self.for_each_record(&compression, &VisitOptions::default(), |record, block| {
+            *records.entry(record.kind().to_string()).or_default() += 1;
+            version.get_or_insert_with(|| record.version.clone());
+            if record.kind() == "warcinfo" && software.is_none() {
+                // the fields describing the crawl are in the block
+                let mut text = String::new();
+                block.read_to_string(&mut text)?;
+                software = Fields::parse(&text)?.get("software").map(str::to_string);
+            }
+            Ok(ControlFlow::Continue(()))
+        })?;
+
+        let scan = self.scan()?;
+
+        Ok(ArchiveMetadata {
+            archive_type: ArchiveType::Warc,
+            entry_count: scan.entries.len(),
+            entries: scan.entries.clone(),
+            total_size: scan.total_size,
+            compressed_size: self.source.clone().seek(SeekFrom::End(0))?,
+            compression: Some(compression),
+            created: self.source.created(),
+            modified: self.source.modified(),
+            additional: Some(json!({
+                "version": version,
+                "software": software,
+                "records": records,
+            })),
+        })
+    }
+
+    fn open(&self, options: OpenOptions) -> Result<(), ArchiveError> {
+        open_entry(self, options)
+    }
+
+    fn find_first<P>(
+        &self,
+        predicate: P,
+    ) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+    where
+        P: Fn(&ArchiveFileEntity) -> bool,
+    {
+        find_first_entry(self, predicate)
+    }
+
+    fn check_password(&self, _password: &str) -> Result<bool, ArchiveError> {
+        Ok(true)
+    }
+
+    fn edit(&self, _entry: &str, _edit: &EntryEdit) -> Result<(), ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "edit".to_string(),
+            ArchiveType::Warc,
+        ))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::archive::Archive;
+
+    #[test]
+    fn names_payloads_after_their_url() {
+        assert_eq!(uri_path("http://example.com"), "example.com/index.html");
+        assert_eq!(
+            uri_path("<https://example.com/a/>"),
+            "example.com/a/index.html"
+        );
+        assert_eq!(uri_path("http://example.com/a#top"), "example.com/a");
+        assert_eq!(
+            uri_path("http://example.com/?q=a/b"),
+            "example.com/index.html%3Fq=a%2Fb"
+        );
+        assert_eq!(uri_path("file:///notes.txt"), "notes.txt");
+        assert_eq!(uri_path("urn:uuid:1234"), "urn_uuid_1234");
+    }
+
+    #[test]
+    fn reads_records() {
+        let file1 = std::fs::read("tests/fixtures/test1/file1.txt").unwrap();
+        let file2 = std::fs::read("tests/fixtures/test1/dir1/file2.txt").unwrap();
+        for (path, compression) in [
+            ("tests/fixtures/test1.warc.gz", ArchiveCompression::Gzip),
+            ("tests/fixtures/test1.warc", ArchiveCompression::None),
+        ] {
+            let archive = Archive::of(DataSource::file(path).unwrap()).unwrap();
+            assert!(matches!(archive, Archive::Warc(_)));
+            let entries = archive
+                .list(ListOptions::default())
+                .unwrap()
+                .into_iter()
+                .map(|e| (e.name, e.size))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                entries,
+                vec![
+                    (
+                        "example.com/index.html".to_string(),
+                        Some(file1.len() as u64)
+                    ),
+                    (
+                        "example.com/dir1/file2.txt".to_string(),
+                        Some(file2.len() as u64)
+                    ),
+                    ("example.com/search%3Fq=a%2Fb".to_string(), Some(8)),
+                    ("notes.txt".to_string(), Some(6)),
+                    (
+                        "example.com/index.html~2".to_string(),
+                        Some(file2.len() as u64)
+                    ),
+                ]
+            );
+
+            let metadata = archive.metadata().unwrap();
+            assert_eq!(metadata.compression, Some(compression));
+            assert_eq!(
+                metadata.additional,
+                Some(json!({
+                    "version": "1.0",
+                    "software": "Wget/1.21.4",
+                    "records": { "warcinfo": 1, "request": 1, "response": 4, "resource": 1 },
+                }))
+            );
+
+            // chunked responses are decoded
+            let (_, content) = archive
+                .find_first(|e| e.name == "example.com/dir1/file2.txt")
+                .unwrap()
+                .unwrap();
+            assert_eq!(content, file2);
+
+            let (_, content) = archive
+                .find_first(|e| e.name == "example.com/index.html")
+                .unwrap()
+                .unwrap();
+            assert_eq!(content, file1);
+        }
+    }
+}
@@ -0,0 +1,997 @@
+// read-only support for Windows Imaging Format files, the `install.wim` and `boot.wim` of Windows
+// media. A WIM holds one or more images, each a directory tree whose files point by SHA-1 to
+// deduplicated resources, compressed in independent chunks. Images are listed at the root when
+// there is a single one, and under a directory named after their index otherwise, the way 7-Zip
+// does. Only uncompressed and XPRESS resources can be read, not LZX, LZMS nor solid ones.
+
+use std::{
+    cell::OnceCell,
+    collections::{HashMap, HashSet},
+    io::{BufReader, ErrorKind, Read, Seek, SeekFrom},
+    ops::ControlFlow,
+};
+
+use serde_json::json;
+
+use crate::archive::{
+    datetime_from_timestamp,
+    entry_visitor::{
+        extract_entries, find_first_entry, list_entries, open_entry, scan_entries, EntryLink,
+        EntryVisitor, VisitOptions, VisitedEntry,
+    },
+    ArchiveError, ArchiveFileEntity, ArchiveFileEntityType, ArchiveMetadata, ArchiveScan,
+    ArchiveType, Archived, CreateOptions, CreateResult, DataSource, ExtractOptions, ExtractReport,
+    ListOptions, OpenOptions, DEFAULT_BUF_SIZE,
+};
+
+use super::edit::EntryEdit;
+
+pub(crate) const MAGIC: &[u8; 8] = b"MSWIM\0\0\0";
+const HEADER_LEN: usize = 208;
+const LOOKUP_ENTRY_LEN: usize = 50;
+/// Size of a directory entry without its names.
+const DENTRY_LEN: usize = 102;
+
+const HEADER_FLAG_COMPRESSION: u32 = 0x2;
+const HEADER_FLAG_XPRESS: u32 = 0x20000;
+const HEADER_FLAG_LZX: u32 = 0x40000;
+const HEADER_FLAG_LZMS: u32 = 0x80000;
+
+const RESOURCE_FLAG_METADATA: u8 = 0x2;
+const RESOURCE_FLAG_COMPRESSED: u8 = 0x4;
+const RESOURCE_FLAG_SOLID: u8 = 0x10;
+
+const ATTRIBUTE_DIRECTORY: u32 = 0x10;
+const ATTRIBUTE_REPARSE_POINT: u32 = 0x400;
+const REPARSE_TAG_MOUNT_POINT: u32 = 0xa000_0003;
+const REPARSE_TAG_SYMLINK: u32 = 0xa000_000c;
+
+/// Seconds between the Windows epoch, 1601-01-01, and the Unix one.
+const WINDOWS_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+fn invalid_data(message: String) -> ArchiveError {
+    ArchiveError::Io(std::io::Error::new(ErrorKind::InvalidData, message))
+}
+
+fn slice(data: &[u8], offset: usize, len: usize) -> Result<&[u8], ArchiveError> {
+    offset
+        .checked_add(len)
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(|| invalid_data("truncated WIM structure".to_string()))
+}
+
+fn u16_at(data: &[u8], offset: usize) -> Result<u16, ArchiveError> {
+    Ok(u16::from_le_bytes(
+        slice(data, offset, 2)?.try_into().unwrap_or_default(),
+    ))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32, ArchiveError> {
+    Ok(u32::from_le_bytes(
+        slice(data, offset, 4)?.try_into().unwrap_or_default(),
+    ))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Result<u64, ArchiveError> {
+    Ok(u64::from_le_bytes(
+        slice(data, offset, 8)?.try_into().unwrap_or_default(),
+    ))
+}
+
+fn utf16_at(data: &[u8], offset: usize, len: usize) -> Result<String, ArchiveError> {
+    let units = slice(data, offset, len)?
+        .chunks_exact(2)
+        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+        .collect::<Vec<_>>();
+    Ok(String::from_utf16_lossy(&units))
+}
+
+fn align8(n: u64) -> u64 {
+    n.div_ceil(8) * 8
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Compression {
+    None,
+    Xpress,
+    Lzx,
+    Lzms,
+}
+
+impl Compression {
+    fn name(self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Xpress => "xpress",
+            Compression::Lzx => "lzx",
+            Compression::Lzms => "lzms",
+        }
+    }
+}
+
+/// Location of a resource in the file.
+#[derive(Debug, Clone, Copy)]
+struct Resource {
+    /// Size the resource takes in the file.
+    size: u64,
+    flags: u8,
+    offset: u64,
+    original_size: u64,
+}
+
+impl Resource {
+    fn parse(data: &[u8], offset: usize) -> Result<Self, ArchiveError> {
+        let packed = u64_at(data, offset)?;
+        Ok(Self {
+            size: packed & 0x00ff_ffff_ffff_ffff,
+            flags: (packed >> 56) as u8,
+            offset: u64_at(data, offset + 8)?,
+            original_size: u64_at(data, offset + 16)?,
+        })
+    }
+
+    fn compressed(&self) -> bool {
+        self.flags & RESOURCE_FLAG_COMPRESSED != 0
+    }
+}
+
+#[derive(Debug)]
+struct Header {
+    compression: Compression,
+    chunk_size: u32,
+    image_count: u32,
+    lookup_table: Resource,
+    xml_data: Resource,
+    boot_index: u32,
+}
+
+impl Header {
+    fn read<R: Read>(reader: &mut R) -> Result<Self, ArchiveError> {
+        let mut header = [0; HEADER_LEN];
+        reader.read_exact(&mut header)?;
+        if &header[..8] != MAGIC {
+            return Err(invalid_data("not a WIM image".to_string()));
+        }
+
+        let flags = u32_at(&header, 16)?;
+        let compression = match flags {
+            f if f & HEADER_FLAG_COMPRESSION == 0 => Compression::None,
+            f if f & HEADER_FLAG_LZX != 0 => Compression::Lzx,
+            f if f & HEADER_FLAG_LZMS != 0 => Compression::Lzms,
+            f if f & HEADER_FLAG_XPRESS != 0 => Compression::Xpress,
+            f => {
+                return Err(invalid_data(format!(
+                    "unknown WIM compression flags: {:#x}",
+                    f
+                )))
+            }
+        };
+        let total_parts = u16_at(&header, 42)?;
+        if total_parts > 1 {
+            return Err(ArchiveError::Io(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "spanned WIM images are not supported",
+            )));
+        }
+        let chunk_size = match u32_at(&header, 20)? {
+            // older images leave it unset
+            0 => 32768,
+            size => size,
+        };
+
+        Ok(Self {
+            compression,
+            chunk_size,
+            image_count: u32_at(&header, 44)?,
+            lookup_table: Resource::parse(&header, 48)?,
+            xml_data: Resource::parse(&header, 72)?,
+            boot_index: u32_at(&header, 120)?,
+        })
+    }
+}
+
+/// Reads a resource chunk by chunk, decompressing the chunks that were.
+struct ResourceReader<R> {
+    source: R,
+    resource: Resource,
+    compression: Compression,
+    chunk_size: u64,
+    /// Offsets of the chunks from the start of the resource, followed by its size.
+    chunks: Vec<u64>,
+    next_chunk: usize,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read + Seek> ResourceReader<R> {
+    fn new(
+        mut source: R,
+        resource: Resource,
+        compression: Compression,
+        chunk_size: u32,
+    ) -> Result<Self, ArchiveError> {
+        if resource.flags & RESOURCE_FLAG_SOLID != 0 {
+            return Err(ArchiveError::Io(std::io::Error::new(
+                ErrorKind::Unsupported,
+                "solid WIM resources are not supported",
+            )));
+        }
+        let chunk_size = chunk_size as u64;
+        let count = resource.original_size.div_ceil(chunk_size);
+
+        let chunks = if !resource.compressed() {
+            (0..count)
+                .map(|i| i * chunk_size)
+                .chain([resource.original_size])
+                .collect()
+        } else {
+            // the table holds the offsets of all chunks but the first, from the end of the table
+            let entry_len = match resource.original_size > u32::MAX as u64 {
+                true => 8,
+                false => 4,
+            };
+            let table_len = count.saturating_sub(1).saturating_mul(entry_len);
+            // the table lies within the resource, itself within the file
+            let file_len = source.seek(SeekFrom::End(0))?;
+            if table_len > resource.size || resource.offset.saturating_add(table_len) > file_len {
+                return Err(invalid_data("invalid WIM chunk table".to_string()));
+            }
+            let mut table = vec![0; table_len as usize];
+            source.seek(SeekFrom::Start(resource.offset))?;
+            source.read_exact(&mut table)?;
+            let offsets = table
+                .chunks_exact(entry_len as usize)
+                .map(|entry| match entry_len {
+                    8 => u64_at(entry, 0),
+                    _ => u32_at(entry, 0).map(u64::from),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            [0].into_iter()
+                .chain(offsets)
+                .map(|offset| table_len + offset)
+                .chain([resource.size])
+                .collect()
+        };
+
+        Ok(Self {
+            source,
+            resource,
+            compression,
+            chunk_size,
+            chunks,
+            next_chunk: 0,
+            chunk: Vec::new(),
+            position: 0,
+        })
+    }
+
+    fn read_chunk(&mut self) -> Result<(), ArchiveError> {
+        let index = self.next_chunk;
+        let (start, end) = (self.chunks[index], self.chunks[index + 1]);
+        let len = self
+            .chunk_size
+            .min(self.resource.original_size - index as u64 * self.chunk_size);
+        if end < start || end > self.resource.size {
+            return Err(invalid_data("invalid WIM chunk table".to_string()));
+        }
+
+        let mut data = vec![0; (end - start) as usize];
+        self.source
+            .seek(SeekFrom::Start(self.resource.offset + start))?;
+        self.source.read_exact(&mut data)?;
+        // chunks that would not shrink are stored as is
+        self.chunk = match (data.len() as u64 == len, self.compression) {
+            (true, _) => data,
+            (false, Compression::Xpress) => xpress_decompress(&data, len as usize)?,
+            (false, compression) => {
+                return Err(ArchiveError::Io(std::io::Error::new(
+                    ErrorKind::Unsupported,
+                    format!(
+                        "{} compressed WIM images are not supported",
+                        compression.name()
+                    ),
+                )))
+            }
+        };
+        self.next_chunk += 1;
+        self.position = 0;
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for ResourceReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.position == self.chunk.len() {
+            if self.next_chunk + 1 >= self.chunks.len() {
+                return Ok(0);
+            }
+            self.read_chunk().map_err(|e| match e {
+                ArchiveError::Io(e) => e,
+                e => std::io::Error::other(e.to_string()),
+            })?;
+        }
+        let n = buf.len().min(self.chunk.len() - self.position);
+        buf[..n].copy_from_slice(&self.chunk[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+/// Decodes a chunk compressed with the Huffman variant of XPRESS into `len` bytes: a table of
+/// the 4-bit code lengths of 256 literals and 256 match headers, followed by the codes read most
+/// significant bit first from 16-bit little endian words, with the extended match lengths stored
+/// as bytes in between.
+fn xpress_decompress(input: &[u8], len: usize) -> Result<Vec<u8>, ArchiveError> {
+    const TABLE_BITS: u32 = 15;
+    let corrupted = || invalid_data("corrupted XPRESS chunk".to_string());
+
+    let lengths = slice(input, 0, 256)?
+        .iter()
+        .flat_map(|b| [b & 0xf, b >> 4])
+        .collect::<Vec<_>>();
+    // canonical codes, indexed by their bits padded to the longest code length
+    let mut table = vec![0u16; 1 << TABLE_BITS];
+    let mut code = 0usize;
+    for length in 1..=TABLE_BITS {
+        for (symbol, _) in lengths
+            .iter()
+            .enumerate()
+            .filter(|(_, l)| **l as u32 == length)
+        {
+            let span = 1 << (TABLE_BITS - length);
+            let start = code << (TABLE_BITS - length);
+            table
+                .get_mut(start..start + span)
+                .ok_or_else(corrupted)?
+                .fill(((symbol as u16) << 4) | length as u16);
+            code += 1;
+        }
+        code <<= 1;
+    }
+
+    let mut position = 256;
+    let word = |position: &mut usize| {
+        let word = input
+            .get(*position..*position + 2)
+            .map_or(0, |w| u16::from_le_bytes([w[0], w[1]]));
+        *position += 2;
+        word as u32
+    };
+    let mut bits = (word(&mut position) << 16) | word(&mut position);
+    let mut extra_bits = 16i32;
+    let mut consume = |bits: &mut u32, n: u32, position: &mut usize| {
+        if n == 0 {
+            return;
+        }
+        *bits <<= n;
+        extra_bits -= n as i32;
+        if extra_bits < 0 {
+            *bits |= word(position) << -extra_bits;
+            extra_bits += 16;
+        }
+    };
+
+    let mut output = Vec::with_capacity(len);
+    while output.len() < len {
+        let entry = table[(bits >> (32 - TABLE_BITS)) as usize];
+        if entry == 0 {
+            return Err(corrupted());
+        }
+        consume(&mut bits, (entry & 0xf) as u32, &mut position);
+        let symbol = entry >> 4;
+        if symbol < 256 {
+            output.push(symbol as u8);
+            continue;
+        }
+
+        let header = symbol - 256;
+        let mut match_len = (header & 0xf) as usize;
+        let offset_bits = (header >> 4) as u32;
+        if match_len == 15 {
+            match_len = *input.get(position).ok_or_else(corrupted)? as usize;
+            position += 1;
+            if match_len == 255 {
+                match_len = u16_at(input, position)? as usize;
+                position += 2;
+                match_len = match_len.checked_sub(15).ok_or_else(corrupted)?;
+            }
+            match_len += 15;
+        }
+        match_len += 3;
+        let offset = match offset_bits {
+            0 => 1,
+            n => ((bits >> (32 - n)) as usize) | (1 << n),
+        };
+        consume(&mut bits, offset_bits, &mut position);
+
+        let start = output.len().checked_sub(offset).ok_or_else(corrupted)?;
+        for i in 0..match_len.min(len - output.len()) {
+            output.push(output[start + i]);
+        }
+    }
+    Ok(output)
+}
+
+/// A file or directory of an image.
+#[derive(Debug)]
+struct Dentry {
+    name: String,
+    attributes: u32,
+    /// Offset of the first child in the metadata resource, 0 if there is none.
+    subdir_offset: u64,
+    last_write: u64,
+    /// Hash of the content, all zeroes for empty files.
+    hash: [u8; 20],
+    reparse_tag: u32,
+}
+
+impl Dentry {
+    /// Parses the entry at `offset`, returning it along with the offset of its next sibling, or
+    /// `None` at the end of a directory.
+    fn parse(metadata: &[u8], offset: usize) -> Result<Option<(Self, usize)>, ArchiveError> {
+        let len = u64_at(metadata, offset)?;
+        if len == 0 {
+            return Ok(None);
+        }
+        if len < DENTRY_LEN as u64 {
+            return Err(invalid_data("invalid WIM directory entry".to_string()));
+        }
+
+        let mut hash: [u8; 20] = slice(metadata, offset + 64, 20)?
+            .try_into()
+            .unwrap_or_default();
+        let name_len = u16_at(metadata, offset + 100)? as usize;
+        let name = utf16_at(metadata, offset + DENTRY_LEN, name_len)?;
+
+        // the unnamed data stream may be stored among the alternate ones
+        let mut next = offset as u64 + align8(len);
+        for _ in 0..u16_at(metadata, offset + 96)? {
+            let stream = next as usize;
+            let stream_len = u64_at(metadata, stream)?;
+            if stream_len == 0 {
+                return Err(invalid_data("invalid WIM stream entry".to_string()));
+            }
+            if u16_at(metadata, stream + 36)? == 0 && hash == [0; 20] {
+                hash = slice(metadata, stream + 16, 20)?
+                    .try_into()
+                    .unwrap_or_default();
+            }
+            next += align8(stream_len);
+        }
+
+        let dentry = Self {
+            name,
+            attributes: u32_at(metadata, offset + 8)?,
+            subdir_offset: u64_at(metadata, offset + 16)?,
+            last_write: u64_at(metadata, offset + 56)?,
+            hash,
+            reparse_tag: u32_at(metadata, offset + 88)?,
+        };
+        Ok(Some((dentry, next as usize)))
+    }
+
+    fn is_directory(&self) -> bool {
+        self.attributes & ATTRIBUTE_DIRECTORY != 0
+    }
+
+    fn is_symlink(&self) -> bool {
+        self.attributes & ATTRIBUTE_REPARSE_POINT != 0
+            && matches!(
+                self.reparse_tag,
+                REPARSE_TAG_SYMLINK | REPARSE_TAG_MOUNT_POINT
+            )
+    }
+}
+
+/// Appends the entries under the directory whose children start at `offset`, depth first.
+fn walk(
+    metadata: &[u8],
+    offset: u64,
+    parent: &str,
+    seen: &mut HashSet<u64>,
+    entries: &mut Vec<(String, Dentry)>,
+) -> Result<(), ArchiveError> {
+    if !seen.insert(offset) {
+        return Err(invalid_data("cyclic WIM directory tree".to_string()));
+    }
+    let mut next = offset as usize;
+    while let Some((dentry, sibling)) = Dentry::parse(metadata, next)? {
+        next = sibling;
+        let mut name = format!("{}{}", parent, dentry.name);
+        let subdir_offset = dentry.subdir_offset;
+        let is_directory = dentry.is_directory() && !dentry.is_symlink();
+        if is_directory {
+            name.push('/');
+        }
+        entries.push((name.clone(), dentry));
+        if is_directory && subdir_offset != 0 {
+            walk(metadata, subdir_offset, &name, seen, entries)?;
+        }
+    }
+    Ok(())
+}
+
+/// The entries of the image described by `metadata`, their names prefixed by `root`.
+fn image_entries(metadata: &[u8], root: &str) -> Result<Vec<(String, Dentry)>, ArchiveError> {
+    // the root directory follows the security descriptors
+    let security_len = match u32_at(metadata, 0)? {
+        0 => 8,
+        len => align8(len as u64),
+    };
+    let (root_dentry, _) = Dentry::parse(metadata, security_len as usize)?
+        .ok_or_else(|| invalid_data("WIM image without root directory".to_string()))?;
+
+    let mut entries = Vec::new();
+    if root_dentry.subdir_offset != 0 {
+        walk(
+            metadata,
+            root_dentry.subdir_offset,
+            root,
+            &mut HashSet::new(),
+            &mut entries,
+        )?;
+    }
+    Ok(entries)
+}
+
+/// Target of a symbolic link or junction, from its reparse data without the reparse header.
+fn reparse_target(data: &[u8], tag: u32) -> Result<String, ArchiveError> {
+    let offset = u16_at(data, 4)? as usize;
+    let len = u16_at(data, 6)? as usize;
+    let buffer = match tag {
+        REPARSE_TAG_SYMLINK => 12,
+        _ => 8,
+    };
+    Ok(utf16_at(data, buffer + offset, len)?.replace('\\', "/"))
+}
+
+/// An image as described by the XML data of the file.
+#[derive(Debug, Default)]
+struct ImageInfo {
+    index: u32,
+    name: Option<String>,
+    description: Option<String>,
+    dir_count: Option<u64>,
+    file_count: Option<u64>,
+    total_bytes: Option<u64>,
+}
+
+/// Text of the first `tag` element of `xml`, which the image descriptions only hold once.
+fn xml_text(xml: &str, tag: &str) -> Option<String> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(
+        xml[start..end]
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&"),
+    )
+}
+
+fn parse_images(xml: &str) -> Vec<ImageInfo> {
+    xml.split("<IMAGE ")
+        .skip(1)
+        .filter_map(|image| {
+            let image = &image[..image.find("</IMAGE>")?];
+            let index = image
+                .split_once("INDEX=\"")?
+                .1
+                .split_once('"')?
+                .0
+                .parse()
+                .ok()?;
+            // the totals of the image are the first ones, before those of its windows details
+            let number = |tag| xml_text(image, tag).and_then(|n| n.trim().parse().ok());
+            Some(ImageInfo {
+                index,
+                name: xml_text(image, "NAME"),
+                description: xml_text(image, "DESCRIPTION"),
+                dir_count: number("DIRCOUNT"),
+                file_count: number("FILECOUNT"),
+                total_bytes: number("TOTALBYTES"),
+            })
+        })
+        .collect()
+}
+
+/// The structures of a WIM file needed to walk its images.
+struct Layout {
+    header: Header,
+    /// Resources by the SHA-1 of their content.
+    resources: HashMap<[u8; 20], Resource>,
+    /// The directory tree of each image, in order.
+    metadata: Vec<Resource>,
+}
+
+impl Layout {
+    fn read<R: Read + Seek>(source: &mut R) -> Result<Self, ArchiveError> {
+        source.rewind()?;
+        let header = Header::read(source)?;
+
+        let mut table = Vec::new();
+        ResourceReader::new(
+            &mut *source,
+            header.lookup_table,
+            header.compression,
+            header.chunk_size,
+        )?
+        .read_to_end(&mut table)?;
+
+        let mut resources = HashMap::new();
+        let mut metadata = Vec::new();
+        for entry in table.chunks_exact(LOOKUP_ENTRY_LEN) {
+            let resource = Resource::parse(entry, 0)?;
+            match resource.flags & RESOURCE_FLAG_METADATA != 0 {
+                true => metadata.push(resource),
+                false => {
+                    let hash = slice(entry, 30, 20)?.try_into().unwrap_or_default();
+                    resources.insert(hash, resource);
+                }
+            }
+        }
+
+        Ok(Self {
+            header,
+            resources,
+            metadata,
+        })
+    }
+
+    fn reader<'r, R: Read + Seek>(
+        &self,
+        source: &'r mut R,
+        resource: Resource,
+    ) -> Result<ResourceReader<&'r mut R>, ArchiveError> {
+        ResourceReader::new(
+            source,
+            resource,
+            self.header.compression,
+            self.header.chunk_size,
+        )
+    }
+
+    fn images<R: Read + Seek>(&self, source: &mut R) -> Result<Vec<ImageInfo>, ArchiveError> {
+        let mut xml = Vec::new();
+        self.reader(source, self.header.xml_data)?
+            .read_to_end(&mut xml)?;
+        let xml = utf16_at(&xml, 0, xml.len() & !1)?;
+        Ok(parse_images(xml.trim_start_matches('\u{feff}')))
+    }
+
+    fn entity(&self, name: String, dentry: &Dentry) -> ArchiveFileEntity {
+        let resource = self.resources.get(&dentry.hash);
+        let (fstype, size, compressed_size, compression) = if dentry.is_symlink() {
+            (ArchiveFileEntityType::SymbolicLink, None, None, None)
+        } else if dentry.is_directory() {
+            (ArchiveFileEntityType::Directory, None, None, None)
+        } else if dentry.hash == [0; 20] {
+            (ArchiveFileEntityType::File, Some(0), Some(0), None)
+        } else {
+            (
+                ArchiveFileEntityType::File,
+                resource.map(|r| r.original_size),
+                resource.map(|r| r.size),
+                resource.map(|r| match r.compressed() {
+                    true => self.header.compression.name().to_string(),
+                    false => "stored".to_string(),
+                }),
+            )
+        };
+
+        let seconds = (dentry.last_write / 10_000_000) as i64 - WINDOWS_EPOCH_OFFSET;
+        ArchiveFileEntity {
+            name,
+            size,
+            compressed_size,
+            last_modified: match dentry.last_write {
+                0 => None,
+                _ => datetime_from_timestamp(seconds).ok(),
+            },
+            compression,
+            fstype,
+            raw: None,
+            unix: None,
+        }
+    }
+
+    fn content_resource(&self, dentry: &Dentry) -> Result<Option<Resource>, ArchiveError> {
+        if dentry.hash == [0; 20] {
+            return Ok(None);
+        }
+        self.resources
+            .get(&dentry.hash)
+            .copied()
+            .map(Some)
+            .ok_or_else(|| invalid_data(format!("missing WIM resource of {}", dentry.name)))
+    }
+}
+
+pub struct WimArchive {
+    pub(crate) source: DataSource,
+    scan: OnceCell<ArchiveScan>,
+}
+
+impl WimArchive {
+    fn scan(&self) -> Result<&ArchiveScan, ArchiveError> {
+        ArchiveScan::cached(&self.scan, || scan_entries(self, None, None))
+    }
+}
+
+impl EntryVisitor for WimArchive {
+    fn for_each_entry<F>(
+        &self,
+        options: &VisitOptions<'_>,
+        mut visit: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        let source = options
+            .retry
+            .wrap(self.source.clone(), &self.source, options.events);
+        let mut reader = BufReader::with_capacity(DEFAULT_BUF_SIZE, source);
+        let layout = Layout::read(&mut reader)?;
+        let single = layout.metadata.len() == 1;
+
+        for (index, metadata) in layout.metadata.iter().enumerate() {
+            let root = match single {
+                true => String::new(),
+                false => format!("{}/", index + 1),
+            };
+            if !single {
+                let visited = VisitedEntry::new(ArchiveFileEntity {
+                    name: root.clone(),
+                    size: None,
+                    compressed_size: None,
+                    last_modified: None,
+                    compression: None,
+                    fstype: ArchiveFileEntityType::Directory,
+                    raw: None,
+                    unix: None,
+                });
+                if options.wants(&visited.entity) && visit(visited, None)?.is_break() {
+                    return Ok(());
+                }
+            }
+
+            let mut data = Vec::new();
+            layout
+                .reader(&mut reader, *metadata)?
+                .read_to_end(&mut data)?;
+
+            for (name, dentry) in image_entries(&data, &root)? {
+                let mut visited = VisitedEntry::new(layout.entity(name, &dentry));
+                if !options.wants(&visited.entity) {
+                    continue;
+                }
+
+                let resource = layout.content_resource(&dentry)?;
+                let flow = match visited.entity.fstype {
+                    ArchiveFileEntityType::File if options.contents => match resource {
+                        Some(resource) => {
+                            let mut content = layout.reader(&mut reader, resource)?;
+                            visit(visited, Some(&mut content))?
+                        }
+                        None => visit(visited, Some(&mut std::io::empty()))?,
+                    },
+                    ArchiveFileEntityType::SymbolicLink if options.contents => {
+                        let mut target = Vec::new();
+                        if let Some(resource) = resource {
+                            layout
+                                .reader(&mut reader, resource)?
+                                .read_to_end(&mut target)?;
+                        }
+                        visited.link = Some(EntryLink::Symbolic(reparse_target(
+                            &target,
+                            dentry.reparse_tag,
+                        )?));
+                        visit(visited, None)?
+                    }
+                    _ => visit(visited, None)?,
+                };
+                if flow.is_break() {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Archived for WimArchive {
+    fn of(source: DataSource) -> Result<Self, ArchiveError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            source,
+            scan: OnceCell::new(),
+        })
+    }
+
+    fn extract(&self, options: ExtractOptions) -> Result<ExtractReport, ArchiveError> {
+        extract_entries(self, &options, self.source.as_ref())
+    }
+
+    fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
+        if options.is_whole() {
+            return Ok(self.scan()?.entries.clone());
+        }
+        list_entries(self, self.scan.get(), &options)
+    }
+
+    fn create(_options: CreateOptions) -> Result<CreateResult, ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "create".to_string(),
+            ArchiveType::Wim,
+        ))
+    }
+
+    fn metadata(&self) -> Result<ArchiveMetadata, ArchiveError> {
+        let mut source = BufReader::with_capacity(DEFAULT_BUF_SIZE, self.source.clone());
+        let layout = Layout::read(&mut source)?;
+        let images = layout
+            .images(&mut source)?
+            .into_iter()
+            .map(|image| {
+                json!({
+                    "index": image.index,
+                    "name": image.name,
+                    "description": image.description,
+                    "dir_count": image.dir_count,
+                    "file_count": image.file_count,
+                    "total_bytes": image.total_bytes,
+                })
+            })
+            .collect::<Vec<_>>();
+        let additional = json!({
+            "compression": layout.header.compression.name(),
+            "chunk_size": layout.header.chunk_size,
+            "image_count": layout.header.image_count,
+            "boot_index": layout.header.boot_index,
+            "images": images,
+        });
+        let size = source.seek(SeekFrom::End(0))?;
+
+        let scan = self.scan()?;
+
+        Ok(ArchiveMetadata {
+            archive_type: ArchiveType::Wim,
+            entry_count: scan.entries.len(),
+            entries: scan.entries.clone(),
+            total_size: scan.total_size,
+            compressed_size: size,
+            compression: None,
+            created: self.source.created(),
+            modified: self.source.modified(),
+            additional: Some(additional),
+        })
+    }
+
+    fn open(&self, options: OpenOptions) -> Result<(), ArchiveError> {
+        open_entry(self, options)
+    }
+
+    fn find_first<P>(
+        &self,
+        predicate: P,
+    ) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+    where
+        P: Fn(&ArchiveFileEntity) -> bool,
+    {
+        find_first_entry(self, predicate)
+    }
+
+    fn check_password(&self, _password: &str) -> Result<bool, ArchiveError> {
+        // WIM files have no encryption
+        Ok(true)
+    }
+
+    fn edit(&self, _entry: &str, _edit: &EntryEdit) -> Result<(), ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "edit".to_string(),
+            ArchiveType::Wim,
+        ))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::archive::Archive;
+
+    /// The generated file of the fixture, spanning two chunks.
+    fn lines() -> Vec<u8> {
+        (0..4000)
+            .flat_map(|i| format!("line {:05}\n", i).into_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn reads_wim_images() {
+        let archive = Archive::of(DataSource::file("tests/fixtures/test1.wim").unwrap()).unwrap();
+        assert!(matches!(archive, Archive::Wim(_)));
+
+        let entries = archive.list(ListOptions::default()).unwrap();
+        let names = entries
+            .iter()
+            .map(|e| (e.name.as_str(), e.fstype, e.compression.as_deref()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                ("1/", ArchiveFileEntityType::Directory, None),
+                ("1/test1/", ArchiveFileEntityType::Directory, None),
+                ("1/test1/dir1/", ArchiveFileEntityType::Directory, None),
+                (
+                    "1/test1/dir1/file2.txt",
+                    ArchiveFileEntityType::File,
+                    Some("stored")
+                ),
+                (
+                    "1/test1/file1.txt",
+                    ArchiveFileEntityType::File,
+                    Some("xpress")
+                ),
+                (
+                    "1/test1/lines.txt",
+                    ArchiveFileEntityType::File,
+                    Some("xpress")
+                ),
+                ("2/", ArchiveFileEntityType::Directory, None),
+                ("2/empty.txt", ArchiveFileEntityType::File, None),
+                ("2/file1.txt", ArchiveFileEntityType::File, Some("xpress")),
+            ]
+        );
+        assert_eq!(entries[5].size, Some(44000));
+        assert_eq!(entries[4].last_modified.unwrap().timestamp(), 1714814640);
+
+        for (name, expected) in [
+            (
+                "1/test1/file1.txt",
+                std::fs::read("tests/fixtures/test1/file1.txt").unwrap(),
+            ),
+            (
+                "1/test1/dir1/file2.txt",
+                std::fs::read("tests/fixtures/test1/dir1/file2.txt").unwrap(),
+            ),
+            ("1/test1/lines.txt", lines()),
+            (
+                "2/file1.txt",
+                std::fs::read("tests/fixtures/test1/file1.txt").unwrap(),
+            ),
+        ] {
+            let (_, content) = archive.find_first(|e| e.name == name).unwrap().unwrap();
+            assert_eq!(content, expected, "{}", name);
+        }
+
+        let additional = archive.metadata().unwrap().additional.unwrap();
+        assert_eq!(additional["compression"], "xpress");
+        assert_eq!(additional["image_count"], 2);
+        assert_eq!(additional["images"][0]["name"], "Base & tools");
+        assert_eq!(additional["images"][0]["file_count"], 3);
+        assert_eq!(additional["images"][1]["name"], "Extra");
+        assert_eq!(additional["images"][1]["dir_count"], 0);
+    }
+
+    #[test]
+    fn rejects_chunk_tables_past_the_resource() {
+        // a chunk count claiming a table of terabytes, in a resource of 16 bytes
+        let resource = Resource {
+            size: 16,
+            flags: RESOURCE_FLAG_COMPRESSED,
+            offset: 0,
+            original_size: 1 << 50,
+        };
+        let reader = ResourceReader::new(
+            std::io::Cursor::new([0; 16]),
+            resource,
+            Compression::Xpress,
+            4096,
+        );
+        assert!(matches!(reader, Err(ArchiveError::Io(e)) if e.kind() == ErrorKind::InvalidData));
+    }
+}
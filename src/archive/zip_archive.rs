@@ -1,179 +1,737 @@
 use std::{
-    collections::HashSet,
+    cell::OnceCell,
     fs::File,
-    io::{BufWriter, Error, ErrorKind, Read},
-    path::PathBuf,
+    io::{BufWriter, Error, ErrorKind, Read, Seek, SeekFrom, Write},
+    ops::{ControlFlow, Range},
+    path::{Path, PathBuf},
 };
 
 use byte_unit::{Byte, UnitType};
+use chrono::{DateTime, Datelike, FixedOffset, Timelike};
 use serde_json::json;
 use zip::{result::ZipError, write::FileOptions, ZipWriter};
 
 use crate::archive::{
-    codecs::ArchiveCompression, datetime_from_timestamp, ArchiveError, ArchiveEvent,
-    ArchiveFileEntity, ArchiveFileEntityType, Archived, CreateOptions, CreateResult, DataSource,
-    EventHandler, ExtractOptions, ListOptions, ReadSeek, SkipReason, DEFAULT_BUF_SIZE,
+    codecs::{ArchiveCompression, CompressionSpec},
+    convert::EntryWriter,
+    datetime_from_timestamp, AppendOptions, ArchiveError, ArchiveEvent, ArchiveFileEntity,
+    ArchiveFileEntityType, ArchiveType, Archived, CreateOptions, CreateResult, DataSource,
+    EventHandler, ExtractOptions, ExtractReport, ListOptions, ReadSeek, SkipReason,
+    ZipCreateOptions, DEFAULT_BUF_SIZE,
 };
 
-use super::ArchiveMetadata;
+use super::{
+    edit::{archive_path, rewrite, EntryEdit},
+    entries::{visit_options, ArchiveEntries, ArchiveEntry},
+    entry_visitor::{
+        extract_entries, find_first_entry, list_entries, normalized_name, open_entry, scan_entries,
+        EntryLink, EntryVisitor, VisitOptions, VisitedEntry,
+    },
+    is_hidden, ArchiveMetadata, ArchiveScan, RawEntryLocation, ZipUnicodeNames,
+};
 
-pub struct ZipArchive<'a> {
-    pub(crate) source: DataSource<'a>,
+pub struct ZipArchive {
+    pub(crate) source: DataSource,
+    scan: OnceCell<ArchiveScan>,
 }
 
-impl<'a> ZipArchive<'a> {
-    fn reader(&'a self) -> Result<Box<dyn ReadSeek + 'a>, Error> {
+impl ZipArchive {
+    fn reader(&self) -> Result<Box<dyn ReadSeek + '_>, Error> {
         match &self.source {
             DataSource::File(file, _) => Ok(Box::new(file.try_clone()?)),
-            DataSource::Stream(val) => Ok(Box::new(val.clone())),
+            DataSource::Memory(val) => Ok(Box::new(val.clone())),
+            DataSource::Reader(_) | DataSource::Pipe(_) | DataSource::Volumes(_) => {
+                Ok(Box::new(self.source.try_clone()?))
+            }
         }
     }
-}
 
-impl<'a> Archived<'a> for ZipArchive<'a> {
-    fn of(source: DataSource<'a>) -> Result<Self, ArchiveError>
-    where
-        Self: Sized,
-    {
-        Ok(Self { source })
+    fn scan(&self) -> Result<&ArchiveScan, ArchiveError> {
+        ArchiveScan::cached(&self.scan, || scan_entries(self, None, None))
     }
 
-    fn extract(&self, options: ExtractOptions) -> Result<(), ArchiveError> {
-        use std::fs;
+    /// Writes the files of `options` over the central directory of the archive, which is written
+    /// again after them. The archive is copied to a new one without the entries replaced, if
+    /// any, their data being left in it otherwise.
+    pub(crate) fn append(&self, options: AppendOptions) -> Result<CreateResult, ArchiveError> {
+        #[cfg(feature = "deflate_codecs")]
+        const DEFAULT_COMPRESSION: ArchiveCompression = ArchiveCompression::Deflate;
+        #[cfg(not(feature = "deflate_codecs"))]
+        const DEFAULT_COMPRESSION: ArchiveCompression = ArchiveCompression::None;
 
-        let reader = self.reader()?;
-        let mut zip = zip::ZipArchive::new(reader)?;
+        let path = archive_path(&self.source)?;
+        let compression = zip::CompressionMethod::try_from(
+            options.compression.clone().unwrap_or(DEFAULT_COMPRESSION),
+        )?;
 
-        let files = options
-            .files
-            .clone()
-            .map(|f| f.into_iter().collect::<HashSet<_>>());
+        let add = |zip: &mut ZipWriter<File>| {
+            add_files(
+                zip,
+                &options.files,
+                |path| options.entry_name(path),
+                file_options(compression, options.level),
+                &options.zip,
+                true,
+                &options,
+            )
+        };
+
+        let replaced = options.replaced();
+        let mut archive = zip::ZipArchive::new(self.reader()?)?;
+        let replacing = archive
+            .file_names()
+            .any(|name| replaced.contains(normalized_name(name)));
+        if !replacing {
+            let file = File::options().read(true).write(true).open(&path)?;
+            let mut zip = ZipWriter::new_append(file)?;
+            let total_size = add(&mut zip)?;
+            let mut file = zip.finish()?;
+            return Ok(CreateResult {
+                total_size,
+                compressed_size: file.stream_position()?,
+                path,
+                unreadable: Vec::new(),
+            });
+        }
+
+        let mut total_size = 0;
+        rewrite(&path, "", |out| {
+            let mut zip = ZipWriter::new(out);
+            zip.set_raw_comment(archive.comment().to_vec());
+            for i in 0..archive.len() {
+                let file = archive.by_index_raw(i)?;
+                if !replaced.contains(normalized_name(file.name())) {
+                    zip.raw_copy_file(file)?;
+                }
+            }
+            total_size = add(&mut zip)?;
+            zip.finish()?;
+            Ok(true)
+        })?;
+        Ok(CreateResult {
+            total_size,
+            compressed_size: std::fs::metadata(&path)?.len(),
+            path,
+            unreadable: Vec::new(),
+        })
+    }
 
+    /// Checks that the entries of the central directory match their local headers and that their
+    /// data lies before the central directory without overlapping, returning the inconsistencies
+    /// found. No content is decompressed.
+    pub(crate) fn check_directory(&self) -> Result<Vec<String>, ArchiveError> {
+        let mut zip = zip::ZipArchive::new(self.reader()?)?;
+        let mut entries = Vec::with_capacity(zip.len());
+        let mut problems = Vec::new();
         for i in 0..zip.len() {
-            let mut file = match &options.password {
-                None => zip.by_index(i).map_err(ArchiveError::Zip),
-                Some(p) => match zip.by_index_decrypt(i, p.as_bytes()) {
-                    Ok(Ok(f)) => Ok(f),
-                    Ok(Err(e)) => Err(ArchiveError::Password(e)),
-                    Err(e) => Err(ArchiveError::Zip(e)),
-                },
-            }?;
-            if let Some(files) = &files {
-                if !files.contains(file.name()) {
-                    continue;
+            // fails when the local header is missing
+            match zip.by_index_raw(i) {
+                Ok(file) => entries.push(DirectoryEntry {
+                    name: file.name().to_string(),
+                    header_start: file.header_start(),
+                    central_header_start: file.central_header_start(),
+                    data: file.data_start()..file.data_start() + file.compressed_size(),
+                }),
+                Err(e) => problems.push(format!("entry {}: {}", i, e)),
+            }
+        }
+        drop(zip);
+
+        let mut reader = self.reader()?;
+        let end = reader.seek(SeekFrom::End(0))?;
+        let directory_start = entries
+            .iter()
+            .map(|e| e.central_header_start)
+            .min()
+            .unwrap_or(end);
+        for entry in &entries {
+            if let Err(problem) = entry.check_headers(&mut reader) {
+                problems.push(format!("{}: {}", entry.name, problem));
+            }
+        }
+
+        entries.sort_by_key(|e| e.header_start);
+        for (i, entry) in entries.iter().enumerate() {
+            let limit = entries
+                .get(i + 1)
+                .map_or(directory_start, |next| next.header_start);
+            if entry.data.end > limit {
+                problems.push(match entries.get(i + 1) {
+                    Some(next) => format!("{}: data overlaps the entry {}", entry.name, next.name),
+                    None => format!("{}: data overlaps the central directory", entry.name),
+                });
+            }
+        }
+        Ok(problems)
+    }
+
+    /// The entry `file`, whose local header is read from `headers` for its flags.
+    fn visited_entry<R: Read + Seek>(
+        file: &zip::read::ZipFile<'_>,
+        headers: &mut R,
+    ) -> Result<VisitedEntry, ArchiveError> {
+        let last_modified = file
+            .last_modified()
+            .to_time()
+            .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
+        // the extended timestamp is to the second, and in UTC for sure
+        let last_modified =
+            extra_modified(file.extra_data()).unwrap_or(last_modified.unix_timestamp());
+
+        let tpe = if file.is_dir() {
+            ArchiveFileEntityType::Directory
+        } else if file.is_file() {
+            ArchiveFileEntityType::File
+        } else {
+            ArchiveFileEntityType::Unknown
+        };
+
+        let (size, compressed_size) = if tpe == ArchiveFileEntityType::File {
+            (Some(file.size()), (Some(file.compressed_size())))
+        } else {
+            (None, None)
+        };
+
+        let entity = ArchiveFileEntity {
+            name: file.name().to_string(),
+            size,
+            compressed_size,
+            fstype: tpe,
+            last_modified: datetime_from_timestamp(last_modified).ok(),
+            compression: Some(file.compression().to_string()),
+            raw: Some(RawEntryLocation {
+                header_offset: file.header_start(),
+                data_offset: file.data_start(),
+                flags: local_header_flags(headers, file.header_start())?,
+            }),
+            unix: None,
+        };
+
+        Ok(VisitedEntry {
+            mode: file.unix_mode(),
+            crc32: file.is_file().then(|| file.crc32()),
+            ..VisitedEntry::new(entity)
+        })
+    }
+
+    pub(crate) fn entries<'s>(&'s self, options: ListOptions<'s>) -> ArchiveEntries<'s> {
+        match zip::ZipArchive::new(self.source.clone()) {
+            Ok(zip) => ArchiveEntries::new(ZipEntries {
+                zip,
+                source: self.source.clone(),
+                index: 0,
+                options,
+            }),
+            Err(e) => ArchiveEntries::failed(e.into()),
+        }
+    }
+}
+
+/// Where an entry of the central directory says it lies, see [`ZipArchive::check_directory`].
+struct DirectoryEntry {
+    name: String,
+    header_start: u64,
+    central_header_start: u64,
+    data: Range<u64>,
+}
+
+/// Size of the fixed part of a local header.
+const LOCAL_HEADER_SIZE: usize = 30;
+/// Size of the fixed part of a central directory header.
+const CENTRAL_HEADER_SIZE: usize = 46;
+/// Flag of the entries whose crc and sizes follow their data instead of their local header.
+const DATA_DESCRIPTOR_FLAG: u16 = 1 << 3;
+
+impl DirectoryEntry {
+    /// Compares the local header of the entry with its central directory header.
+    fn check_headers<R: Read + Seek>(&self, reader: &mut R) -> Result<(), String> {
+        let (central, central_name) =
+            read_header(reader, self.central_header_start, CENTRAL_HEADER_SIZE, 28)
+                .map_err(|e| format!("unreadable central directory header: {}", e))?;
+        let (local, local_name) = read_header(reader, self.header_start, LOCAL_HEADER_SIZE, 26)
+            .map_err(|e| format!("unreadable local header: {}", e))?;
+        if u32_at(&central, 0) != CENTRAL_HEADER_SIGNATURE {
+            return Err("invalid central directory header signature".to_string());
+        }
+        if u32_at(&local, 0) != LOCAL_HEADER_SIGNATURE {
+            return Err("invalid local header signature".to_string());
+        }
+        if u16_at(&local, 8) != u16_at(&central, 10) {
+            return Err("compression method differs from the central directory".to_string());
+        }
+        if local_name != central_name {
+            return Err(format!(
+                "local header names it {}",
+                String::from_utf8_lossy(&local_name)
+            ));
+        }
+
+        if u16_at(&local, 6) & DATA_DESCRIPTOR_FLAG == 0 {
+            if u32_at(&local, 14) != u32_at(&central, 16) {
+                return Err("crc differs from the central directory".to_string());
+            }
+            // zip64 entries keep their sizes in an extra field
+            for (local_at, central_at) in [(18, 20), (22, 24)] {
+                let (l, c) = (u32_at(&local, local_at), u32_at(&central, central_at));
+                if l != c && l != u32::MAX && c != u32::MAX {
+                    return Err("size differs from the central directory".to_string());
                 }
             }
-            let filepath = file
-                .enclosed_name()
-                .ok_or(ArchiveError::Zip(ZipError::FileNotFound))?;
+        }
+        Ok(())
+    }
+}
 
-            let outpath = options.destination.join(filepath);
+/// General purpose flags of the local header at `offset`.
+fn local_header_flags<R: Read + Seek>(reader: &mut R, offset: u64) -> Result<u16, Error> {
+    let mut flags = [0; 2];
+    reader.seek(SeekFrom::Start(offset + 6))?;
+    reader.read_exact(&mut flags)?;
+    Ok(u16::from_le_bytes(flags))
+}
 
-            if file.name().ends_with('/') {
-                fs::create_dir_all(&outpath)?;
-                options.handle(ArchiveEvent::Created(
-                    outpath.to_string_lossy().to_string(),
-                    ArchiveFileEntityType::Directory,
-                ));
-            } else {
-                options.handle(ArchiveEvent::Extracting(
-                    outpath.to_string_lossy().to_string(),
-                    Some(file.size()),
-                ));
+/// The fixed part of `size` bytes of the header at `offset`, and the name following it, whose
+/// length is at `name_length_at`.
+fn read_header<R: Read + Seek>(
+    reader: &mut R,
+    offset: u64,
+    size: usize,
+    name_length_at: usize,
+) -> Result<(Vec<u8>, Vec<u8>), Error> {
+    let mut header = vec![0; size];
+    reader.seek(SeekFrom::Start(offset))?;
+    reader.read_exact(&mut header)?;
+    let mut name = vec![0; u16_at(&header, name_length_at) as usize];
+    reader.read_exact(&mut name)?;
+    Ok((header, name))
+}
+
+/// Tries of a password provider coming up with new passwords for an entry before giving up on it.
+const MAX_PASSWORD_ATTEMPTS: usize = 3;
+
+/// Password decrypting the encrypted entry `index`, asking the provider again while it comes up
+/// with new ones. Fails with [`ArchiveError::PasswordRequired`] if it has none, or with
+/// [`ArchiveError::WrongPassword`] if none of them decrypts the entry.
+fn entry_password<R: Read + Seek>(
+    zip: &mut zip::ZipArchive<R>,
+    index: usize,
+    name: &str,
+    options: &VisitOptions<'_>,
+) -> Result<String, ArchiveError> {
+    let mut tried = Vec::new();
+    while tried.len() < MAX_PASSWORD_ATTEMPTS {
+        let password = options.ask_password(Some(name))?;
+        if tried.contains(&password) {
+            break;
+        }
+        if zip.by_index_decrypt(index, password.as_bytes())?.is_ok() {
+            return Ok(password);
+        }
+        if let Some(provider) = options.password {
+            provider.rejected(Some(name));
+        }
+        tried.push(password);
+    }
+    Err(ArchiveError::WrongPassword)
+}
 
-                if let Some(p) = outpath.parent() {
-                    if !p.exists() {
-                        fs::create_dir_all(p)?;
+impl EntryVisitor for ZipArchive {
+    fn for_each_entry<F>(
+        &self,
+        options: &VisitOptions<'_>,
+        mut visit: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        let reader = options
+            .retry
+            .wrap(self.reader()?, &self.source, options.events);
+        let mut zip = zip::ZipArchive::new(reader)?;
+        let mut headers = self.reader()?;
+
+        for i in 0..zip.len() {
+            // entry metadata lives in the central directory, no need to decrypt
+            let raw = zip.by_index_raw(i)?;
+            let entry = match Self::visited_entry(&raw, &mut headers) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    if let Some(events) = options.events {
+                        events.handle(ArchiveEvent::FailedToReadEntry(raw.name().to_string(), e));
                     }
+                    continue;
                 }
-                if outpath.exists() {
-                    if options.overwrite {
-                        fs::remove_file(&outpath)?;
-                    } else {
-                        // yellow in ansi
-                        options.handle(ArchiveEvent::Skipped(
-                            outpath.to_string_lossy().to_string(),
-                            SkipReason::AlreadyExists,
-                        ));
-                        continue;
-                    }
+            };
+            drop(raw);
+            if !options.wants(&entry.entity) {
+                continue;
+            }
+
+            let flow = if options.contents && entry.entity.fstype == ArchiveFileEntityType::File {
+                // encryption is per entry, the password is only asked for the encrypted ones
+                let encrypted = matches!(
+                    zip.by_index(i),
+                    Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED))
+                );
+                let mut file = if encrypted {
+                    let password = match entry_password(&mut zip, i, &entry.entity.name, options) {
+                        Ok(password) => password,
+                        Err(ArchiveError::PasswordRequired | ArchiveError::WrongPassword)
+                            if options.skip_without_password =>
+                        {
+                            if let Some(events) = options.events {
+                                events.handle(ArchiveEvent::Skipped(
+                                    entry.entity.name,
+                                    SkipReason::NoValidPassword,
+                                ));
+                            }
+                            continue;
+                        }
+                        Err(e) => return Err(e),
+                    };
+                    zip.by_index_decrypt(i, password.as_bytes())??
+                } else {
+                    zip.by_index(i)?
+                };
+                visit(entry, Some(&mut file))?
+            } else {
+                visit(entry, None)?
+            };
+
+            if flow.is_break() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Iterator of [`ZipArchive::entries`]. The contents are read from their own clone of the
+/// source, through the decoders of their compression method, or else decoded into memory by the
+/// zip crate, as are the encrypted ones.
+struct ZipEntries<'a> {
+    zip: zip::ZipArchive<DataSource>,
+    source: DataSource,
+    index: usize,
+    options: ListOptions<'a>,
+}
+
+impl<'a> ZipEntries<'a> {
+    fn next_entry(&mut self) -> Result<Option<ArchiveEntry<'a>>, ArchiveError> {
+        let options = visit_options(&self.options);
+        while self.index < self.zip.len() {
+            let i = self.index;
+            self.index += 1;
+            let raw = self.zip.by_index_raw(i)?;
+            let entry = match ZipArchive::visited_entry(&raw, &mut self.source) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    self.options
+                        .handle(ArchiveEvent::FailedToReadEntry(raw.name().to_string(), e));
+                    continue;
                 }
-                let mut outfile = fs::File::create(&outpath)?;
-                std::io::copy(&mut file, &mut outfile)?;
+            };
+            let data = raw.data_start()..raw.data_start() + raw.compressed_size();
+            let (method, crc) = (raw.compression(), raw.crc32());
+            drop(raw);
+            if !options.wants(&entry.entity) {
+                continue;
+            }
+            if entry.entity.fstype != ArchiveFileEntityType::File {
+                return Ok(Some(ArchiveEntry::empty(entry)));
             }
-            // Get and Set permissions
-            #[cfg(unix)]
-            {
-                use std::os::unix::fs::PermissionsExt;
-                if let Some(mode) = file.unix_mode() {
-                    fs::set_permissions(&outpath, fs::Permissions::from_mode(mode))?;
+
+            let encrypted = matches!(
+                self.zip.by_index(i),
+                Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED))
+            );
+            let section = Section {
+                source: self.source.clone(),
+                position: data.start,
+                end: data.end,
+            };
+            let decoder: Box<dyn Read + 'a> = match method {
+                _ if encrypted => {
+                    let password = entry_password(&mut self.zip, i, &entry.entity.name, &options)?;
+                    let mut file = self.zip.by_index_decrypt(i, password.as_bytes())??;
+                    return Ok(Some(ArchiveEntry::new(
+                        entry,
+                        Box::new(read_all(&mut file)?),
+                    )));
+                }
+                zip::CompressionMethod::Stored => Box::new(section),
+                #[cfg(feature = "deflate_codecs")]
+                zip::CompressionMethod::Deflated => {
+                    Box::new(flate2::read::DeflateDecoder::new(section))
+                }
+                #[cfg(feature = "bzip2_codecs")]
+                zip::CompressionMethod::Bzip2 => Box::new(bzip2::read::BzDecoder::new(section)),
+                #[cfg(feature = "zstd_codecs")]
+                zip::CompressionMethod::Zstd => Box::new(zstd::Decoder::new(section)?),
+                _ => {
+                    let mut file = self.zip.by_index(i)?;
+                    return Ok(Some(ArchiveEntry::new(
+                        entry,
+                        Box::new(read_all(&mut file)?),
+                    )));
                 }
+            };
+            let size = entry.entity.size.unwrap_or_default();
+            let content = Crc32Reader {
+                inner: decoder.take(size),
+                name: entry.entity.name.clone(),
+                hasher: flate2::Crc::new(),
+                expected: crc,
+            };
+            return Ok(Some(ArchiveEntry::new(entry, Box::new(content))));
+        }
+        Ok(None)
+    }
+}
+
+impl<'a> Iterator for ZipEntries<'a> {
+    type Item = Result<ArchiveEntry<'a>, ArchiveError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_entry().transpose()
+    }
+}
+
+/// The whole content of `file`, decoded by the zip crate.
+fn read_all(file: &mut zip::read::ZipFile<'_>) -> Result<std::io::Cursor<Vec<u8>>, Error> {
+    let mut content = Vec::with_capacity(file.size() as usize);
+    file.read_to_end(&mut content)?;
+    Ok(std::io::Cursor::new(content))
+}
+
+/// The bytes from `position` to `end` of the source, sought before every read so that sources
+/// sharing their cursor can be read alternately.
+struct Section {
+    source: DataSource,
+    position: u64,
+    end: u64,
+}
+
+impl Read for Section {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let max = (self.end - self.position).min(buf.len() as u64) as usize;
+        if max == 0 {
+            return Ok(0);
+        }
+        self.source.seek(SeekFrom::Start(self.position))?;
+        let n = self.source.read(&mut buf[..max])?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+/// Checks the CRC-32 of the content once it was read entirely.
+struct Crc32Reader<R> {
+    inner: R,
+    name: String,
+    hasher: flate2::Crc,
+    expected: u32,
+}
+
+impl<R: Read> Read for Crc32Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        if n == 0 && !buf.is_empty() && self.hasher.sum() != self.expected {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("CRC mismatch for {}", self.name),
+            ));
+        }
+        Ok(n)
+    }
+}
+
+/// Zip archive written by [`Archive::convert`](super::Archive::convert).
+pub(crate) struct ZipEntryWriter {
+    zip: ZipWriter<BufWriter<File>>,
+    options: FileOptions,
+}
+
+impl ZipEntryWriter {
+    /// Writer of entries compressed with `compression`, deflated if `None` and available.
+    pub(crate) fn new(
+        file: File,
+        compression: Option<&CompressionSpec>,
+    ) -> Result<Self, ArchiveError> {
+        #[cfg(feature = "deflate_codecs")]
+        const DEFAULT_COMPRESSION: ArchiveCompression = ArchiveCompression::Deflate;
+        #[cfg(not(feature = "deflate_codecs"))]
+        const DEFAULT_COMPRESSION: ArchiveCompression = ArchiveCompression::None;
+
+        let method = zip::CompressionMethod::try_from(
+            compression.map_or(DEFAULT_COMPRESSION, |c| c.algorithm.clone()),
+        )?;
+        Ok(Self {
+            zip: ZipWriter::new(BufWriter::with_capacity(DEFAULT_BUF_SIZE, file)),
+            options: file_options(method, compression.and_then(|c| c.level)),
+        })
+    }
+}
+
+impl EntryWriter for ZipEntryWriter {
+    fn write(
+        &mut self,
+        entry: &VisitedEntry,
+        reader: Option<&mut dyn Read>,
+    ) -> Result<bool, ArchiveError> {
+        let entity = &entry.entity;
+        let name = normalized_name(&entity.name).trim_end_matches('/');
+        let mut options = self.options;
+        if let Some(modified) = entity.last_modified {
+            let (time, date) = dos_datetime(modified);
+            options = options.last_modified_time(zip::DateTime::from_msdos(date, time));
+        }
+        if let Some(mode) = entry.mode {
+            options = options.unix_permissions(mode & 0o7777);
+        }
+
+        match (&entry.link, entity.fstype, reader) {
+            (Some(EntryLink::Symbolic(target)), ..) => {
+                self.zip.add_symlink(name, target, options)?
+            }
+            (None, ArchiveFileEntityType::Directory, _) => self.zip.add_directory(name, options)?,
+            (None, _, Some(reader)) => {
+                // the Zip64 fields go before the data, whose size may be unknown
+                let large = entity.size.is_none_or(|size| size > u32::MAX as u64);
+                self.zip.start_file(name, options.large_file(large))?;
+                std::io::copy(reader, &mut self.zip)?;
             }
+            // zip archives have no hard links
+            _ => return Ok(false),
         }
-        options.handle(ArchiveEvent::DoneExtracting(
-            self.source.as_ref().to_string(),
-            options.destination.to_string_lossy().to_string(),
-        ));
+        Ok(true)
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<(), ArchiveError> {
+        self.zip.finish()?.flush()?;
         Ok(())
     }
+}
 
-    fn list(&self, _options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
-        let reader = self.reader()?;
+/// Options of the entries compressed with `compression` at `level`.
+fn file_options(compression: zip::CompressionMethod, level: Option<i32>) -> FileOptions {
+    // stored entries have no level
+    FileOptions::default()
+        .compression_method(compression)
+        .compression_level(level.filter(|_| compression != zip::CompressionMethod::Stored))
+}
 
-        let mut zip = zip::ZipArchive::new(reader)?;
+/// Writes `files` to `zip` under the names given by `entry_name`, returning the size of their
+/// content.
+fn add_files<W: Write + Seek>(
+    zip: &mut ZipWriter<W>,
+    files: &[PathBuf],
+    entry_name: impl Fn(&Path) -> PathBuf,
+    file_options: FileOptions,
+    zip_options: &ZipCreateOptions,
+    allow_hidden: bool,
+    handler: &dyn EventHandler,
+) -> Result<u64, ArchiveError> {
+    let mut total_size = 0;
 
-        let entities = (0..zip.len())
-            .map(|i| {
-                let file = zip.by_index(i)?;
+    for path in files {
+        let metadata = std::fs::metadata(path)?;
 
-                let name = file
-                    .enclosed_name()
-                    .map(|n| n.to_string_lossy().to_string())
-                    .unwrap_or_default();
+        let name = entry_name(path).to_string_lossy().to_string();
 
-                let last_modified = file
-                    .last_modified()
-                    .to_time()
-                    .map_err(|e| std::io::Error::new(ErrorKind::InvalidData, e))?;
+        // the source directory itself, whose entries are at the top level
+        if metadata.is_dir() && name.is_empty() {
+            continue;
+        }
+        if metadata.is_dir() {
+            eprintln!("Adding directory: {}", name);
+            zip.add_directory(&name, file_options)?;
+            handler.handle(ArchiveEvent::Added(name, None));
+        } else {
+            eprintln!(
+                "Adding file: {} ({})",
+                name,
+                Byte::from(metadata.len()).get_appropriate_unit(UnitType::Both)
+            );
+            // check first if the file is hidden
+            if !allow_hidden && is_hidden(&name, &metadata) {
+                continue;
+            }
 
-                let tpe = if file.is_dir() {
-                    ArchiveFileEntityType::Directory
-                } else if file.is_file() {
-                    ArchiveFileEntityType::File
-                } else {
-                    ArchiveFileEntityType::Unknown
-                };
+            // max size is 4GB without the Zip64 fields
+            let mut file_options =
+                file_options.large_file(zip_options.zip64.large_file(metadata.len()));
+            // the time of the file rather than the time it is added, which updates compare
+            let modified = metadata
+                .modified()
+                .ok()
+                .map(|modified| DateTime::<chrono::Utc>::from(modified).fixed_offset());
+            if let Some(modified) = modified {
+                let (time, date) = dos_datetime(modified);
+                file_options =
+                    file_options.last_modified_time(zip::DateTime::from_msdos(date, time));
+            }
+            let mut extra = Vec::new();
+            if let Some(modified) = modified.filter(|_| zip_options.extended_timestamps) {
+                extra.extend(extended_timestamp(modified.timestamp()));
+            }
+            if zip_options.unicode_names == ZipUnicodeNames::PathField && !name.is_ascii() {
+                extra.extend(unicode_path(&name));
+            }
+            if extra.is_empty() {
+                zip.start_file(&name, file_options)?;
+            } else {
+                zip.start_file_with_extra_data(&name, file_options)?;
+                zip.write_all(&extra)?;
+                zip.end_extra_data()?;
+            }
 
-                let (size, compressed_size) = if tpe == ArchiveFileEntityType::File {
-                    (Some(file.size()), (Some(file.compressed_size())))
-                } else {
-                    (None, None)
-                };
+            let mut file = File::open(path)?;
 
-                let entity: ArchiveFileEntity = ArchiveFileEntity {
-                    name,
-                    size,
-                    compressed_size,
-                    fstype: tpe,
-                    last_modified: datetime_from_timestamp(last_modified.unix_timestamp()).ok(),
-                    compression: Some(file.compression().to_string()),
-                };
+            let size = std::io::copy(&mut file, zip)?;
+            total_size += size;
+            handler.handle(ArchiveEvent::Added(name, Some(size)));
+        }
+    }
+    Ok(total_size)
+}
 
-                Ok(entity)
-            })
-            .collect::<Result<Vec<_>, ArchiveError>>();
+impl Archived for ZipArchive {
+    fn of(source: DataSource) -> Result<Self, ArchiveError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            source,
+            scan: OnceCell::new(),
+        })
+    }
 
-        entities
+    fn extract(&self, options: ExtractOptions) -> Result<ExtractReport, ArchiveError> {
+        extract_entries(self, &options, self.source.as_ref())
+    }
+
+    fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
+        if options.is_whole() {
+            return Ok(self.scan()?.entries.clone());
+        }
+        list_entries(self, self.scan.get(), &options)
     }
 
     fn create(options: CreateOptions) -> Result<CreateResult, ArchiveError> {
-        const DEFAULT_COMPRESSION: ArchiveCompression = ArchiveCompression::Gzip;
+        #[cfg(feature = "deflate_codecs")]
+        const DEFAULT_COMPRESSION: ArchiveCompression = ArchiveCompression::Deflate;
+        #[cfg(not(feature = "deflate_codecs"))]
+        const DEFAULT_COMPRESSION: ArchiveCompression = ArchiveCompression::None;
 
-        let dest = options.destination;
-        let files = options.files;
+        let dest = &options.destination;
         let allow_hidden = options.include_hidden;
         let compression = zip::CompressionMethod::try_from(
-            options.archive_compression.unwrap_or(DEFAULT_COMPRESSION),
+            options
+                .compression
+                .as_ref()
+                .map_or(DEFAULT_COMPRESSION, |c| c.algorithm.clone()),
         )?;
 
         eprintln!(
@@ -182,62 +740,20 @@ impl<'a> Archived<'a> for ZipArchive<'a> {
             compression
         );
 
-        let file = File::create(&dest)?;
+        let file = File::create(dest)?;
         let buf_writer = BufWriter::with_capacity(DEFAULT_BUF_SIZE, file);
 
         let mut zip = ZipWriter::new(buf_writer);
 
-        let mut total_size = 0;
-
-        for path in files {
-            let metadata = std::fs::metadata(&path)?;
-
-            let name = path
-                .strip_prefix(&options.source)
-                .as_deref()
-                .unwrap_or(path.as_path())
-                .to_string_lossy()
-                .to_string();
-
-            let options = FileOptions::default()
-                .compression_method(compression)
-                .compression_level(None);
-
-            if metadata.is_dir() {
-                eprintln!("Adding directory: {}", name);
-                zip.add_directory(&name, options)?;
-            } else {
-                eprintln!(
-                    "Adding file: {} ({})",
-                    name,
-                    Byte::from(metadata.len()).get_appropriate_unit(UnitType::Both)
-                );
-                // check first if the file is hidden
-                let is_hidden = {
-                    #[cfg(windows)]
-                    {
-                        use std::os::windows::fs::MetadataExt;
-                        const FILE_ATTRIBUTE_HIDDEN: u32 = 0x0000_0002;
-                        metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0
-                    }
-                    #[cfg(not(windows))]
-                    {
-                        name.starts_with('.')
-                    }
-                };
-                if !allow_hidden && is_hidden {
-                    continue;
-                }
-
-                // max size is 4GB
-                zip.start_file(&name, options.large_file(metadata.len() > u32::MAX as u64))?;
-
-                let mut file = File::open(&path)?;
-
-                let size = std::io::copy(&mut file, &mut zip)?;
-                total_size += size;
-            }
-        }
+        let total_size = add_files(
+            &mut zip,
+            &options.files,
+            |path| options.entry_name(path),
+            file_options(compression, options.compression_level()),
+            &options.zip,
+            allow_hidden,
+            &options,
+        )?;
         zip.finish()?;
 
         eprintln!(
@@ -245,11 +761,15 @@ impl<'a> Archived<'a> for ZipArchive<'a> {
             dest.display(),
             Byte::from(total_size).get_appropriate_unit(UnitType::Both)
         );
+        options.handle(ArchiveEvent::DoneCreating(
+            dest.to_string_lossy().to_string(),
+        ));
 
         Ok(CreateResult {
-            path: PathBuf::from(&dest),
+            path: PathBuf::from(dest),
             total_size,
             compressed_size: std::fs::metadata(dest)?.len(),
+            unreadable: Vec::new(),
         })
     }
 
@@ -260,13 +780,17 @@ impl<'a> Archived<'a> for ZipArchive<'a> {
         let mut str = String::new();
         let comment = zip.comment().read_to_string(&mut str).map(|_| str);
 
-        let entries = self.list(ListOptions::default())?;
+        let scan = self.scan()?;
 
         Ok(ArchiveMetadata {
-            total_size: entries.iter().filter_map(|e| e.size).sum(),
+            archive_type: ArchiveType::Zip,
+            total_size: scan.total_size,
             compressed_size: len,
             compression: None,
-            entries,
+            entry_count: scan.entries.len(),
+            created: self.source.created(),
+            modified: self.source.modified(),
+            entries: scan.entries.clone(),
             additional: Some(json!(
                 {
                     "comment": comment.ok(),
@@ -275,29 +799,303 @@ impl<'a> Archived<'a> for ZipArchive<'a> {
         })
     }
 
-    fn open(&'a self, options: super::OpenOptions) -> Result<(), ArchiveError> {
+    fn open(&self, options: super::OpenOptions) -> Result<(), ArchiveError> {
+        open_entry(self, options)
+    }
+
+    fn find_first<P>(
+        &self,
+        predicate: P,
+    ) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+    where
+        P: Fn(&ArchiveFileEntity) -> bool,
+    {
+        find_first_entry(self, predicate)
+    }
+
+    fn check_password(&self, password: &str) -> Result<bool, ArchiveError> {
         let reader = self.reader()?;
         let mut zip = zip::ZipArchive::new(reader)?;
 
-        let path_str = options.path.to_string_lossy().to_string();
+        // the first encrypted entry is the one that refuses to open without a password
+        let encrypted = (0..zip.len()).find(|&i| {
+            matches!(
+                zip.by_index(i),
+                Err(ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED))
+            )
+        });
+        let Some(index) = encrypted else {
+            return Ok(true);
+        };
 
-        let mut file = match &options.password {
-            None => zip.by_name(path_str.as_str()).map_err(ArchiveError::Zip),
-            Some(p) => match zip.by_name_decrypt(path_str.as_str(), p.as_bytes()) {
-                Ok(Ok(f)) => Ok(f),
-                Ok(Err(e)) => Err(ArchiveError::Password(e)),
-                Err(e) => Err(ArchiveError::Zip(e)),
-            },
-        }?;
+        let result = match zip.by_index_decrypt(index, password.as_bytes())? {
+            // the header check only rules out 255/256 of wrong passwords, the CRC
+            // verified at the end of the stream catches the rest
+            Ok(mut file) => Ok(std::io::copy(&mut file, &mut std::io::sink()).is_ok()),
+            Err(_) => Ok(false),
+        };
+        result
+    }
 
-        let mut writer = options.dest;
+    fn edit(&self, target: &str, edit: &EntryEdit) -> Result<(), ArchiveError> {
+        let path = archive_path(&self.source)?;
+        if edit_central_directory(&path, target, edit)? {
+            Ok(())
+        } else {
+            Err(ArchiveError::EntryNotFound(
+                PathBuf::from(target),
+                Vec::new(),
+            ))
+        }
+    }
+}
 
-        std::io::copy(&mut file, &mut writer)?;
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+const EXTENDED_TIMESTAMP_FIELD: u16 = 0x5455;
+const UNICODE_PATH_FIELD: u16 = 0x7075;
 
-        Ok(())
+fn u16_at(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([buf[at], buf[at + 1]])
+}
+
+fn u32_at(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+}
+
+fn invalid_zip(reason: &'static str) -> ArchiveError {
+    ArchiveError::Zip(ZipError::InvalidArchive(reason))
+}
+
+/// MS-DOS time and date of `modified`, which are read back as UTC.
+pub(crate) fn dos_datetime(modified: DateTime<FixedOffset>) -> (u16, u16) {
+    let utc = modified.naive_utc();
+    // the format covers 1980 to 2107
+    let year = utc.year().clamp(1980, 2107);
+    if year != utc.year() {
+        let (month, day) = if year == 1980 { (1, 1) } else { (12, 31) };
+        return (0, (((year - 1980) as u16) << 9) | (month << 5) | day);
+    }
+
+    let time =
+        ((utc.hour() as u16) << 11) | ((utc.minute() as u16) << 5) | (utc.second() as u16 / 2);
+    let date = (((year - 1980) as u16) << 9) | ((utc.month() as u16) << 5) | utc.day() as u16;
+    (time, date)
+}
+
+/// Ids and data ranges of the fields of an extra field block.
+fn extra_fields(extra: &[u8]) -> Vec<(u16, Range<usize>)> {
+    let mut fields = Vec::new();
+    let mut position = 0;
+    while position + 4 <= extra.len() {
+        let start = position + 4;
+        let end = (start + u16_at(extra, position + 2) as usize).min(extra.len());
+        fields.push((u16_at(extra, position), start..end));
+        position = end;
+    }
+    fields
+}
+
+/// Extended timestamp field holding only the modification time `modified`.
+fn extended_timestamp(modified: i64) -> [u8; 9] {
+    let modified = modified.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+    let mut field = [0; 9];
+    field[..2].copy_from_slice(&EXTENDED_TIMESTAMP_FIELD.to_le_bytes());
+    field[2..4].copy_from_slice(&5u16.to_le_bytes());
+    field[4] = 1;
+    field[5..].copy_from_slice(&modified.to_le_bytes());
+    field
+}
+
+/// Info-ZIP unicode path field holding `name`, which the name of the entry holds as well, both
+/// as UTF-8.
+fn unicode_path(name: &str) -> Vec<u8> {
+    let mut crc = flate2::Crc::new();
+    crc.update(name.as_bytes());
+    let mut field = Vec::with_capacity(9 + name.len());
+    field.extend(UNICODE_PATH_FIELD.to_le_bytes());
+    field.extend((5 + name.len() as u16).to_le_bytes());
+    field.push(1);
+    field.extend(crc.sum().to_le_bytes());
+    field.extend(name.as_bytes());
+    field
+}
+
+/// Modification time of the extended timestamp field of `extra`, if it has one.
+fn extra_modified(extra: &[u8]) -> Option<i64> {
+    extra_fields(extra).into_iter().find_map(|(id, range)| {
+        (id == EXTENDED_TIMESTAMP_FIELD && range.len() >= 5 && extra[range.start] & 1 == 1)
+            .then(|| u32_at(extra, range.start + 1) as i32 as i64)
+    })
+}
+
+/// Sets the modification time of the extended timestamp field of `extra`, if it has one.
+fn touch_extra(extra: &mut [u8], modified: i64) {
+    let modified = modified.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+    for (id, range) in extra_fields(extra) {
+        // flags, then the modification time if the first one is set
+        if id == EXTENDED_TIMESTAMP_FIELD && range.len() >= 5 && extra[range.start] & 1 == 1 {
+            extra[range.start + 1..range.start + 5].copy_from_slice(&modified.to_le_bytes());
+        }
     }
 }
 
+/// Applies `edit` in place, returning whether it matched an entry. The central directory is
+/// rewritten with the new names and times, times are also patched in the local headers, and the
+/// data of the entries is left untouched.
+fn edit_central_directory(
+    path: &Path,
+    target: &str,
+    edit: &EntryEdit,
+) -> Result<bool, ArchiveError> {
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)?;
+
+    // the end of central directory record is followed by a comment of up to 64 KiB
+    let len = file.metadata()?.len();
+    let tail_len = len.min(22 + u16::MAX as u64);
+    let mut tail = vec![0; tail_len as usize];
+    file.seek(SeekFrom::Start(len - tail_len))?;
+    file.read_exact(&mut tail)?;
+    let eocd_at = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&i| u32_at(&tail, i) == END_OF_CENTRAL_DIRECTORY_SIGNATURE)
+        .ok_or(invalid_zip("end of central directory not found"))?;
+    let mut eocd = tail[eocd_at..].to_vec();
+
+    let (entries, cd_size, cd_offset) = (u16_at(&eocd, 10), u32_at(&eocd, 12), u32_at(&eocd, 16));
+    if entries == u16::MAX || cd_size == u32::MAX || cd_offset == u32::MAX {
+        return Err(ArchiveError::UnsupportedActionForArchiveType(
+            "edit zip64".to_string(),
+            ArchiveType::Zip,
+        ));
+    }
+    if cd_offset as u64 + cd_size as u64 != len - tail_len + eocd_at as u64 {
+        return Err(invalid_zip("unexpected data after the central directory"));
+    }
+
+    let mut central = vec![0; cd_size as usize];
+    file.seek(SeekFrom::Start(cd_offset as u64))?;
+    file.read_exact(&mut central)?;
+
+    let mut rewritten = Vec::with_capacity(central.len());
+    let mut renamed_local_headers = Vec::new();
+    let mut touched_local_headers = Vec::new();
+    let mut edited = false;
+    let mut position = 0;
+    for _ in 0..entries {
+        if position + 46 > central.len() || u32_at(&central, position) != CENTRAL_HEADER_SIGNATURE {
+            return Err(invalid_zip("invalid central directory header"));
+        }
+        let name_len = u16_at(&central, position + 28) as usize;
+        let extra_len = u16_at(&central, position + 30) as usize;
+        let comment_len = u16_at(&central, position + 32) as usize;
+        let (name_start, extra_start) = (position + 46, position + 46 + name_len);
+        let end = extra_start + extra_len + comment_len;
+        if end > central.len() {
+            return Err(invalid_zip("invalid central directory header"));
+        }
+
+        let mut header = central[position..name_start].to_vec();
+        let mut name = central[name_start..extra_start].to_vec();
+        let mut extra = central[extra_start..extra_start + extra_len].to_vec();
+        let entry = String::from_utf8_lossy(&name).to_string();
+        edited |= edit.applies(target, &entry);
+
+        if let Some(new_name) = edit.renamed(target, &entry) {
+            if !new_name.is_ascii() {
+                // language encoding flag, the name is UTF-8
+                let flags = u16_at(&header, 8) | (1 << 11);
+                header[8..10].copy_from_slice(&flags.to_le_bytes());
+            }
+            // the local header is only renamed if the name fits, readers go by the central
+            // directory anyway
+            if new_name.len() == name.len() {
+                renamed_local_headers.push((u32_at(&header, 42) as u64, new_name.clone()));
+            }
+            name = new_name.into_bytes();
+            // a unicode path field would take precedence over the new name
+            extra = extra_fields(&extra)
+                .into_iter()
+                .filter(|(id, _)| *id != UNICODE_PATH_FIELD)
+                .flat_map(|(_, range)| extra[range.start - 4..range.end].to_vec())
+                .collect();
+        }
+        if let Some(modified) = edit.modified(target, &entry) {
+            // with a data descriptor, traditional encryption checks passwords against the time
+            let flags = u16_at(&header, 8);
+            if flags & 1 == 1 && flags & (1 << 3) != 0 {
+                return Err(ArchiveError::UnsupportedActionForArchiveType(
+                    "change the time of an encrypted entry".to_string(),
+                    ArchiveType::Zip,
+                ));
+            }
+            let (time, date) = dos_datetime(modified);
+            header[12..14].copy_from_slice(&time.to_le_bytes());
+            header[14..16].copy_from_slice(&date.to_le_bytes());
+            touch_extra(&mut extra, modified.timestamp());
+            touched_local_headers.push((u32_at(&header, 42) as u64, time, date, modified));
+        }
+
+        let name_len = u16::try_from(name.len())
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "entry name too long"))?;
+        header[28..30].copy_from_slice(&name_len.to_le_bytes());
+        header[30..32].copy_from_slice(&(extra.len() as u16).to_le_bytes());
+        rewritten.extend_from_slice(&header);
+        rewritten.extend_from_slice(&name);
+        rewritten.extend_from_slice(&extra);
+        rewritten.extend_from_slice(&central[extra_start + extra_len..end]);
+        position = end;
+    }
+    if !edited {
+        return Ok(false);
+    }
+
+    for (offset, name) in renamed_local_headers {
+        let mut header = [0; 30];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut header)?;
+        if u32_at(&header, 0) == LOCAL_HEADER_SIGNATURE
+            && u16_at(&header, 26) as usize == name.len()
+        {
+            file.seek(SeekFrom::Start(offset + 30))?;
+            file.write_all(name.as_bytes())?;
+        }
+    }
+    for (offset, time, date, modified) in touched_local_headers {
+        let mut header = [0; 30];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut header)?;
+        if u32_at(&header, 0) != LOCAL_HEADER_SIGNATURE {
+            return Err(invalid_zip("invalid local file header"));
+        }
+        header[10..12].copy_from_slice(&time.to_le_bytes());
+        header[12..14].copy_from_slice(&date.to_le_bytes());
+        let extra_offset = offset + 30 + u16_at(&header, 26) as u64;
+        let mut extra = vec![0; u16_at(&header, 28) as usize];
+        file.seek(SeekFrom::Start(extra_offset))?;
+        file.read_exact(&mut extra)?;
+        touch_extra(&mut extra, modified.timestamp());
+
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(&header)?;
+        file.seek(SeekFrom::Start(extra_offset))?;
+        file.write_all(&extra)?;
+    }
+
+    let cd_size = u32::try_from(rewritten.len())
+        .map_err(|_| Error::new(ErrorKind::InvalidInput, "central directory too large"))?;
+    eocd[12..16].copy_from_slice(&cd_size.to_le_bytes());
+    file.seek(SeekFrom::Start(cd_offset as u64))?;
+    file.write_all(&rewritten)?;
+    file.write_all(&eocd)?;
+    file.set_len(cd_offset as u64 + rewritten.len() as u64 + eocd.len() as u64)?;
+    Ok(true)
+}
+
 #[cfg(test)]
 #[allow(clippy::unwrap_used)]
 mod tests {
@@ -356,4 +1154,330 @@ mod tests {
             Some(DateTime::<FixedOffset>::from_str("2023-10-01T16:46:52+00:00").unwrap())
         );
     }
+
+    #[test]
+    fn test_zip_metadata() {
+        let archive = ZipArchive::from_path("tests/fixtures/test1.zip").unwrap();
+        let metadata = archive.metadata().unwrap();
+
+        assert_eq!(metadata.archive_type, ArchiveType::Zip);
+        assert_eq!(metadata.entry_count, 3);
+        assert_eq!(metadata.entry_count, metadata.entries.len());
+        assert!(metadata.modified.is_some());
+    }
+
+    #[test]
+    fn edits_central_directory() {
+        let path = std::env::temp_dir().join(format!("hezi-zip-edit-{}.zip", std::process::id()));
+        std::fs::copy("tests/fixtures/test1.zip", &path).unwrap();
+        let size = std::fs::metadata(&path).unwrap().len();
+
+        let archive = ZipArchive::from_path(&path).unwrap();
+        let modified = DateTime::<FixedOffset>::from_str("2020-05-06T07:08:10+00:00").unwrap();
+        archive.rename("test1/dir1", "test1/documents").unwrap();
+        archive.touch("test1/file1.txt", modified).unwrap();
+        assert!(matches!(
+            archive.touch("missing.txt", modified),
+            Err(ArchiveError::EntryNotFound(..))
+        ));
+
+        let edited = ZipArchive::from_path(&path).unwrap();
+        let entities = edited.list(ListOptions::default()).unwrap();
+        let names = entities.iter().map(|e| e.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "test1/documents/",
+                "test1/documents/file2.txt",
+                "test1/file1.txt"
+            ]
+        );
+        assert_eq!(entities[2].last_modified, Some(modified));
+        // only the central directory grew, by the longer names
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), size + 2 * 5);
+        assert!(edited.check_password("").unwrap());
+        _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn creates_extended_timestamps_and_zip64_fields() {
+        use crate::archive::{CompressionSpec, Zip64Mode};
+
+        let dir = std::env::temp_dir().join(format!("hezi-zip-fields-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("file.txt");
+        std::fs::write(&file, "content").unwrap();
+        // an odd second, which the MS-DOS fields cannot hold
+        let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000_001);
+        File::options()
+            .write(true)
+            .open(&file)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        let create = |zip64, extended_timestamps| {
+            let destination = dir.join(format!("{:?}-{}.zip", zip64, extended_timestamps));
+            ZipArchive::create(CreateOptions {
+                destination: destination.clone(),
+                source: dir.clone(),
+                roots: vec![],
+                files: vec![file.clone()],
+                password: None,
+                archive_type: ArchiveType::Zip,
+                compression: Some(CompressionSpec::new(ArchiveCompression::None)),
+                overwrite: true,
+                include_hidden: true,
+                skip_unreadable: false,
+                verify_after: false,
+                volume_size: None,
+                update: false,
+                sevenz: Default::default(),
+                zip: ZipCreateOptions {
+                    zip64,
+                    extended_timestamps,
+                    ..Default::default()
+                },
+                tar: Default::default(),
+                event_handler: Box::new(crate::archive::SimpleLogger),
+            })
+            .unwrap();
+            destination
+        };
+
+        let extended = ZipArchive::from_path(create(Zip64Mode::Never, true)).unwrap();
+        let entries = extended.list(ListOptions::default()).unwrap();
+        assert_eq!(entries[0].last_modified.unwrap().timestamp(), 1_000_000_001);
+        let (_, content) = extended.find_first(|_| true).unwrap().unwrap();
+        assert_eq!(content, b"content");
+
+        let plain = create(Zip64Mode::Never, false);
+        let zip64 = create(Zip64Mode::Always, false);
+        let len = |path| std::fs::metadata(path).unwrap().len();
+        assert!(len(&zip64) > len(&plain));
+        let zip64 = ZipArchive::from_path(&zip64).unwrap();
+        let (_, content) = zip64.find_first(|_| true).unwrap().unwrap();
+        assert_eq!(content, b"content");
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn creates_unicode_path_fields() {
+        use crate::archive::{CompressionSpec, ZipUnicodeNames};
+
+        let dir = std::env::temp_dir().join(format!("hezi-zip-unicode-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("été.txt");
+        std::fs::write(&file, "content").unwrap();
+
+        let create = |unicode_names| {
+            let destination = dir.join(format!("{:?}.zip", unicode_names));
+            ZipArchive::create(CreateOptions {
+                destination: destination.clone(),
+                source: dir.clone(),
+                roots: vec![],
+                files: vec![file.clone()],
+                password: None,
+                archive_type: ArchiveType::Zip,
+                compression: Some(CompressionSpec::new(ArchiveCompression::None)),
+                overwrite: true,
+                include_hidden: true,
+                skip_unreadable: false,
+                verify_after: false,
+                volume_size: None,
+                update: false,
+                sevenz: Default::default(),
+                zip: ZipCreateOptions {
+                    unicode_names,
+                    ..Default::default()
+                },
+                tar: Default::default(),
+                event_handler: Box::new(crate::archive::SimpleLogger),
+            })
+            .unwrap();
+            std::fs::read(destination).unwrap()
+        };
+
+        for unicode_names in [ZipUnicodeNames::Flag, ZipUnicodeNames::PathField] {
+            let bytes = create(unicode_names);
+            // the language encoding flag of the local header
+            assert_ne!(u16_at(&bytes, 6) & 1 << 11, 0);
+            let mut zip = zip::ZipArchive::new(std::io::Cursor::new(&bytes)).unwrap();
+            let entry = zip.by_index(0).unwrap();
+            assert_eq!(entry.name(), "été.txt");
+            let extra = entry.extra_data();
+            let fields = extra_fields(extra);
+            let path = fields.iter().find(|(id, _)| *id == UNICODE_PATH_FIELD);
+            match unicode_names {
+                ZipUnicodeNames::Flag => assert!(path.is_none()),
+                ZipUnicodeNames::PathField => {
+                    let range = path.unwrap().1.clone();
+                    assert_eq!(&extra[range.start + 5..range.end], "été.txt".as_bytes());
+                }
+            }
+        }
+        _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn appends_over_central_directory() {
+        use crate::archive::{AppendOptions, Archive};
+
+        let path = std::env::temp_dir().join(format!("hezi-zip-add-{}.zip", std::process::id()));
+        std::fs::copy("tests/fixtures/test1.zip", &path).unwrap();
+
+        let archive = Archive::of(DataSource::file(&path).unwrap()).unwrap();
+        let result = archive
+            .append(AppendOptions {
+                source: PathBuf::from("tests/fixtures/test1"),
+                files: vec![PathBuf::from("tests/fixtures/test1/dir1/file2.txt")],
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(result.total_size, 444);
+        assert_eq!(
+            result.compressed_size,
+            std::fs::metadata(&path).unwrap().len()
+        );
+
+        let appended = ZipArchive::from_path(&path).unwrap();
+        let names = appended
+            .list(ListOptions::default())
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "test1/dir1/",
+                "test1/dir1/file2.txt",
+                "test1/file1.txt",
+                "dir1/file2.txt"
+            ]
+        );
+        assert!(appended.check_directory().unwrap().is_empty());
+
+        // the directory is already there, the file is not added twice
+        let again = Archive::of(DataSource::file(&path).unwrap())
+            .unwrap()
+            .append(AppendOptions {
+                source: PathBuf::from("tests/fixtures"),
+                files: vec![
+                    PathBuf::from("tests/fixtures/test1"),
+                    PathBuf::from("tests/fixtures/test1/file1.txt"),
+                ],
+                ..Default::default()
+            });
+        assert!(matches!(again, Err(ArchiveError::Io(e)) if e.kind() == ErrorKind::AlreadyExists));
+        _ = std::fs::remove_file(&path);
+    }
+
+    /// Hands out the answers in order, moving to the next one when rejected.
+    struct Answers(std::cell::RefCell<Vec<&'static str>>);
+
+    impl crate::archive::password::PasswordProvider for Answers {
+        fn password(&self, _entry: Option<&str>) -> Result<Option<String>, ArchiveError> {
+            Ok(self.0.borrow().first().map(|a| a.to_string()))
+        }
+
+        fn rejected(&self, _entry: Option<&str>) {
+            self.0.borrow_mut().remove(0);
+        }
+    }
+
+    #[derive(Default)]
+    struct Skipped(std::cell::RefCell<Vec<String>>);
+
+    impl EventHandler for std::rc::Rc<Skipped> {
+        fn handle(&self, event: ArchiveEvent) {
+            if let ArchiveEvent::Skipped(name, SkipReason::NoValidPassword) = event {
+                self.0.borrow_mut().push(name);
+            }
+        }
+    }
+
+    fn extract_mixed(
+        password: Box<dyn crate::archive::password::PasswordProvider>,
+    ) -> (Vec<String>, Vec<String>) {
+        let dest = std::env::temp_dir().join(format!("hezi-mixed-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dest);
+        let skipped = std::rc::Rc::new(Skipped::default());
+        let report = ZipArchive::from_path("tests/fixtures/mixed_passwords.zip")
+            .unwrap()
+            .extract(ExtractOptions {
+                destination: dest.clone(),
+                password: Some(password),
+                event_handler: Box::new(skipped.clone()),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let mut extracted = std::fs::read_dir(&dest)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect::<Vec<_>>();
+        extracted.sort();
+        _ = std::fs::remove_dir_all(&dest);
+        let skipped = skipped.0.take();
+        assert_eq!(report.extracted.len(), extracted.len());
+        let reported = report
+            .skipped
+            .iter()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(reported, skipped.iter().collect::<Vec<_>>());
+        assert_eq!(report.is_complete(), skipped.is_empty());
+        (extracted, skipped)
+    }
+
+    #[test]
+    fn asks_again_for_rejected_passwords() {
+        let (extracted, skipped) = extract_mixed(Box::new(Answers(vec!["alpha", "beta"].into())));
+        assert_eq!(extracted, vec!["alpha.txt", "beta.txt", "plain.txt"]);
+        assert!(skipped.is_empty());
+    }
+
+    #[test]
+    fn skips_entries_without_valid_password() {
+        let asked = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let provider = {
+            let asked = asked.clone();
+            crate::archive::password::password_provider(move |entry| {
+                let entry = entry.unwrap().to_string();
+                let password = (entry == "alpha.txt").then(|| "alpha".to_string());
+                asked.borrow_mut().push(entry);
+                Ok(password)
+            })
+        };
+
+        let (extracted, skipped) = extract_mixed(provider);
+        assert_eq!(extracted, vec!["alpha.txt", "plain.txt"]);
+        assert_eq!(skipped, vec!["beta.txt"]);
+        assert_eq!(*asked.borrow(), vec!["alpha.txt", "beta.txt"]);
+    }
+
+    #[test]
+    fn locates_entries() {
+        let data = std::fs::read("tests/fixtures/test1.zip").unwrap();
+        let archive = ZipArchive::from_bytes(data.as_slice()).unwrap();
+        let entries = archive.list(ListOptions::default()).unwrap();
+        assert!(!entries.is_empty());
+        for entry in entries {
+            let raw = entry.raw().unwrap();
+            let header = raw.header_offset as usize;
+            assert_eq!(u32_at(&data, header), LOCAL_HEADER_SIGNATURE);
+            assert_eq!(u16_at(&data, header + 6), raw.flags);
+            let name_length = u16_at(&data, header + 26) as usize;
+            let extra_length = u16_at(&data, header + 28) as usize;
+            assert_eq!(
+                &data[header + LOCAL_HEADER_SIZE..][..name_length],
+                entry.name().as_bytes()
+            );
+            assert_eq!(
+                raw.data_offset as usize,
+                header + LOCAL_HEADER_SIZE + name_length + extra_length
+            );
+        }
+    }
 }
@@ -0,0 +1,828 @@
+// forward-only reading of zip archives from sources that cannot seek, such as pipes: entries are
+// read from their local headers in order instead of from the central directory. Entries written
+// by streaming writers have their sizes and checksum in a data descriptor after their data, the
+// end of their data is then found by decompressing it. Archives are written the same way to
+// outputs that cannot seek either.
+
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::{BufReader, Error, ErrorKind, Read, Write},
+    ops::ControlFlow,
+};
+
+use byte_unit::{Byte, UnitType};
+use chrono::{DateTime, Utc};
+use flate2::{bufread::DeflateDecoder, write::DeflateEncoder, Compression, CrcReader};
+use zip::result::ZipError;
+
+use super::{
+    codecs::{ArchiveCompression, CountingWriter},
+    datetime_from_timestamp,
+    entry_visitor::{extract_entries, list_entries, EntryVisitor, VisitOptions, VisitedEntry},
+    is_hidden,
+    zip_archive::dos_datetime,
+    ArchiveError, ArchiveEvent, ArchiveFileEntity, ArchiveFileEntityType, CreateOptions,
+    CreateResult, EventHandler, ExtractOptions, ExtractReport, ListOptions,
+};
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+const DATA_DESCRIPTOR_SIGNATURE: u32 = 0x08074b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06064b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE: u32 = 0x07064b50;
+const ZIP64_EXTRA_FIELD: u16 = 0x0001;
+
+const FLAG_ENCRYPTED: u16 = 1;
+const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+const FLAG_UTF8: u16 = 1 << 11;
+
+/// Version 2.0, or 4.5 for zip64, made on Unix.
+const VERSION: u16 = 20;
+const VERSION_ZIP64: u16 = 45;
+const MADE_BY_UNIX: u16 = 3 << 8;
+
+const STORED: u16 = 0;
+const DEFLATED: u16 = 8;
+
+/// Zip archive read once, front to back, from a `reader` that cannot seek. Entries written by
+/// streaming writers, with a data descriptor instead of sizes in their local header, are
+/// supported when deflated. Encrypted entries are not supported.
+pub struct ZipStream<R: Read> {
+    reader: RefCell<Option<BufReader<R>>>,
+}
+
+impl<R: Read> ZipStream<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader: RefCell::new(Some(BufReader::new(reader))),
+        }
+    }
+
+    /// Lists the entries of the archive, consuming the stream.
+    pub fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
+        list_entries(self, None, &options)
+    }
+
+    /// Extracts the archive, consuming the stream.
+    pub fn extract(&self, options: ExtractOptions) -> Result<ExtractReport, ArchiveError> {
+        extract_entries(self, &options, "zip stream")
+    }
+}
+
+impl<R: Read> EntryVisitor for ZipStream<R> {
+    fn for_each_entry<F>(
+        &self,
+        options: &VisitOptions<'_>,
+        mut visit: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        let Some(mut reader) = self.reader.borrow_mut().take() else {
+            return Err(ArchiveError::InvalidDataSource(
+                "a zip stream can only be read once".to_string(),
+            ));
+        };
+
+        while let Some(header) = LocalHeader::read(&mut reader)? {
+            let mut entity = header.entity.clone();
+            let wanted = options.wants(&entity);
+            let contents = options.contents && entity.fstype == ArchiveFileEntityType::File;
+            // checksum of the content, when it was decompressed
+            let mut crc = None;
+            // whether the descriptor has 8 byte sizes
+            let mut zip64 = header.zip64;
+
+            match header.compressed_size {
+                Some(compressed_size) => {
+                    let mut raw = (&mut reader).take(compressed_size);
+                    let flow = if wanted && contents {
+                        let mut data = CrcReader::new(decoder(header.method, &mut raw)?);
+                        let flow = visit(VisitedEntry::new(entity), Some(&mut data))?;
+                        if flow.is_continue() {
+                            std::io::copy(&mut data, &mut std::io::sink())?;
+                            crc = Some(data.crc().sum());
+                        }
+                        flow
+                    } else if wanted {
+                        visit(VisitedEntry::new(entity), None)?
+                    } else {
+                        ControlFlow::Continue(())
+                    };
+                    if flow.is_break() {
+                        return Ok(());
+                    }
+                    std::io::copy(&mut raw, &mut std::io::sink())?;
+                }
+                // deflate marks the end of its data, the sizes are known once it is read
+                None => {
+                    let mut data = CrcReader::new(DeflateDecoder::new(&mut reader));
+                    if wanted
+                        && contents
+                        && visit(VisitedEntry::new(entity.clone()), Some(&mut data))?.is_break()
+                    {
+                        return Ok(());
+                    }
+                    std::io::copy(&mut data, &mut std::io::sink())?;
+                    crc = Some(data.crc().sum());
+
+                    let (compressed_size, size) =
+                        (data.get_ref().total_in(), data.get_ref().total_out());
+                    zip64 |= compressed_size >= u32::MAX as u64 || size >= u32::MAX as u64;
+                    entity.compressed_size = Some(compressed_size);
+                    entity.size = Some(size);
+                    if wanted && !contents && visit(VisitedEntry::new(entity), None)?.is_break() {
+                        return Ok(());
+                    }
+                }
+            }
+
+            let expected = if header.flags & FLAG_DATA_DESCRIPTOR != 0 {
+                read_descriptor(&mut reader, zip64)?
+            } else {
+                header.crc32
+            };
+            if crc.is_some_and(|crc| crc != expected) {
+                return Err(ArchiveError::Io(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("invalid checksum for {}", header.entity.name),
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes a zip archive of the files of `options` to `writer`, which does not need to seek: the
+/// sizes and checksum of each entry follow its data in a data descriptor. Entries are stored or
+/// deflated, deflate being the default, and cannot be encrypted. The returned path is the
+/// destination of `options`, nothing is written to it.
+pub fn create_zip_stream<W: Write>(
+    mut options: CreateOptions,
+    writer: W,
+) -> Result<CreateResult, ArchiveError> {
+    let method = match options.compression.as_ref().map(|c| &c.algorithm) {
+        None => DEFLATED,
+        #[cfg(feature = "deflate_codecs")]
+        Some(ArchiveCompression::Deflate) => DEFLATED,
+        Some(ArchiveCompression::None) => STORED,
+        Some(c) => return Err(ArchiveError::UnsupportedCompression(c.clone())),
+    };
+    let level = match options.compression_level() {
+        None => Compression::default(),
+        Some(level @ 0..=9) => Compression::new(level as u32),
+        Some(level) => {
+            return Err(ArchiveError::Io(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "deflate compression level must be between 0 and 9 but was {}",
+                    level
+                ),
+            )))
+        }
+    };
+    if options.password.is_some() {
+        return Err(ArchiveError::Zip(ZipError::UnsupportedArchive(
+            "Encrypted entries cannot be written to a stream",
+        )));
+    }
+
+    let unreadable = options.remove_unreadable();
+
+    eprintln!(
+        "Creating zip stream using compression method {}.",
+        compression_name(method)
+    );
+
+    let mut writer = CountingWriter::new(writer);
+    let mut entries = Vec::with_capacity(options.files.len());
+    let mut total_size = 0;
+
+    for path in &options.files {
+        let metadata = std::fs::metadata(path)?;
+        let mut name = options
+            .entry_name(path)
+            .to_string_lossy()
+            .replace('\\', "/");
+        let (time, date) = dos_datetime(DateTime::<Utc>::from(metadata.modified()?).fixed_offset());
+
+        let mut entry = CentralEntry {
+            flags: if name.is_ascii() { 0 } else { FLAG_UTF8 },
+            method,
+            time,
+            date,
+            crc32: 0,
+            compressed_size: 0,
+            size: 0,
+            offset: writer.written,
+            attributes: external_attributes(&metadata),
+            name: Vec::new(),
+        };
+
+        if !options.include_hidden && is_hidden(&name, &metadata) {
+            continue;
+        }
+        if metadata.is_dir() {
+            // the source directory itself has no entry
+            if name.is_empty() {
+                continue;
+            }
+            eprintln!("Adding directory: {}", name);
+            if !name.ends_with('/') {
+                name.push('/');
+            }
+            entry.method = STORED;
+            entry.name = name.clone().into_bytes();
+            entry.write_local_header(&mut writer)?;
+            options.handle(ArchiveEvent::Added(name, None));
+        } else {
+            eprintln!(
+                "Adding file: {} ({})",
+                name,
+                Byte::from(metadata.len()).get_appropriate_unit(UnitType::Both)
+            );
+
+            entry.flags |= FLAG_DATA_DESCRIPTOR;
+            entry.name = name.clone().into_bytes();
+            entry.write_local_header(&mut writer)?;
+
+            let start = writer.written;
+            let mut file = CrcReader::new(File::open(path)?);
+            entry.size = if method == DEFLATED {
+                let mut encoder = DeflateEncoder::new(&mut writer, level);
+                let size = std::io::copy(&mut file, &mut encoder)?;
+                encoder.finish()?;
+                size
+            } else {
+                std::io::copy(&mut file, &mut writer)?
+            };
+            entry.compressed_size = writer.written - start;
+            entry.crc32 = file.crc().sum();
+            entry.write_descriptor(&mut writer)?;
+
+            total_size += entry.size;
+            options.handle(ArchiveEvent::Added(name, Some(entry.size)));
+        }
+        entries.push(entry);
+    }
+
+    let directory_offset = writer.written;
+    for entry in &entries {
+        entry.write_central_header(&mut writer)?;
+    }
+    let directory_size = writer.written - directory_offset;
+    write_end_of_central_directory(
+        &mut writer,
+        entries.len() as u64,
+        directory_offset,
+        directory_size,
+    )?;
+    writer.flush()?;
+
+    eprintln!(
+        "Done creating zip stream ({})",
+        Byte::from(total_size).get_appropriate_unit(UnitType::Both)
+    );
+    options.handle(ArchiveEvent::DoneCreating(
+        options.destination.to_string_lossy().to_string(),
+    ));
+
+    Ok(CreateResult {
+        path: options.destination.clone(),
+        total_size,
+        compressed_size: writer.written,
+        unreadable,
+    })
+}
+
+/// Entry of the central directory, known once its data is written.
+struct CentralEntry {
+    flags: u16,
+    method: u16,
+    time: u16,
+    date: u16,
+    crc32: u32,
+    compressed_size: u64,
+    size: u64,
+    offset: u64,
+    attributes: u32,
+    name: Vec<u8>,
+}
+
+impl CentralEntry {
+    /// Local header of the entry, its sizes and checksum being left for the descriptor. As the
+    /// size of a file is unknown until it is written, whether it needs zip64 is too: its header
+    /// always has a zip64 field, with sizes of 0, and its descriptor 8 byte sizes.
+    fn write_local_header<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        let streamed = self.flags & FLAG_DATA_DESCRIPTOR != 0;
+        let version = if streamed { VERSION_ZIP64 } else { VERSION };
+        writer.write_all(&LOCAL_HEADER_SIGNATURE.to_le_bytes())?;
+        for field in [version, self.flags, self.method, self.time, self.date] {
+            writer.write_all(&field.to_le_bytes())?;
+        }
+        writer.write_all(&0u32.to_le_bytes())?;
+        let mut extra = Vec::new();
+        if streamed {
+            writer.write_all(&[0xff; 8])?;
+            extra.extend(ZIP64_EXTRA_FIELD.to_le_bytes());
+            extra.extend(16u16.to_le_bytes());
+            extra.extend([0; 16]);
+        } else {
+            writer.write_all(&[0; 8])?;
+        }
+        writer.write_all(&(self.name.len() as u16).to_le_bytes())?;
+        writer.write_all(&(extra.len() as u16).to_le_bytes())?;
+        writer.write_all(&self.name)?;
+        writer.write_all(&extra)
+    }
+
+    fn write_descriptor<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        writer.write_all(&DATA_DESCRIPTOR_SIGNATURE.to_le_bytes())?;
+        writer.write_all(&self.crc32.to_le_bytes())?;
+        writer.write_all(&self.compressed_size.to_le_bytes())?;
+        writer.write_all(&self.size.to_le_bytes())
+    }
+
+    fn write_central_header<W: Write>(&self, writer: &mut W) -> Result<(), Error> {
+        // the zip64 field holds the values that do not fit in the header, in this order
+        let mut zip64 = Vec::new();
+        let mut fit = |value: u64| {
+            if value >= u32::MAX as u64 {
+                zip64.extend(value.to_le_bytes());
+                u32::MAX
+            } else {
+                value as u32
+            }
+        };
+        let size = fit(self.size);
+        let compressed_size = fit(self.compressed_size);
+        let offset = fit(self.offset);
+        let mut extra = Vec::new();
+        if !zip64.is_empty() {
+            extra.extend(ZIP64_EXTRA_FIELD.to_le_bytes());
+            extra.extend((zip64.len() as u16).to_le_bytes());
+            extra.extend(zip64);
+        }
+        let version = if extra.is_empty() {
+            VERSION
+        } else {
+            VERSION_ZIP64
+        };
+
+        writer.write_all(&CENTRAL_HEADER_SIGNATURE.to_le_bytes())?;
+        for field in [
+            MADE_BY_UNIX | version,
+            version,
+            self.flags,
+            self.method,
+            self.time,
+            self.date,
+        ] {
+            writer.write_all(&field.to_le_bytes())?;
+        }
+        writer.write_all(&self.crc32.to_le_bytes())?;
+        writer.write_all(&compressed_size.to_le_bytes())?;
+        writer.write_all(&size.to_le_bytes())?;
+        writer.write_all(&(self.name.len() as u16).to_le_bytes())?;
+        writer.write_all(&(extra.len() as u16).to_le_bytes())?;
+        // comment length, disk number and internal attributes
+        writer.write_all(&[0; 6])?;
+        writer.write_all(&self.attributes.to_le_bytes())?;
+        writer.write_all(&offset.to_le_bytes())?;
+        writer.write_all(&self.name)?;
+        writer.write_all(&extra)
+    }
+}
+
+/// External attributes of an entry: the Unix mode in the high bits, and the MS-DOS directory
+/// attribute.
+fn external_attributes(metadata: &std::fs::Metadata) -> u32 {
+    const DOS_DIRECTORY: u32 = 0x10;
+    let directory = if metadata.is_dir() { DOS_DIRECTORY } else { 0 };
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        (metadata.permissions().mode() << 16) | directory
+    }
+    #[cfg(not(unix))]
+    {
+        const S_IFDIR: u32 = 0o040000;
+        const S_IFREG: u32 = 0o100000;
+        let mode = if metadata.is_dir() {
+            S_IFDIR | 0o755
+        } else {
+            S_IFREG | 0o644
+        };
+        (mode << 16) | directory
+    }
+}
+
+/// Writes the end of the central directory, preceded by its zip64 version when the counts or
+/// offsets do not fit.
+fn write_end_of_central_directory<W: Write>(
+    writer: &mut CountingWriter<W>,
+    entries: u64,
+    offset: u64,
+    size: u64,
+) -> Result<(), Error> {
+    let zip64 = entries >= u16::MAX as u64 || offset >= u32::MAX as u64 || size >= u32::MAX as u64;
+    if zip64 {
+        let record_offset = writer.written;
+        writer.write_all(&ZIP64_END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes())?;
+        // size of the rest of the record
+        writer.write_all(&44u64.to_le_bytes())?;
+        writer.write_all(&(MADE_BY_UNIX | VERSION_ZIP64).to_le_bytes())?;
+        writer.write_all(&VERSION_ZIP64.to_le_bytes())?;
+        // disk numbers
+        writer.write_all(&[0; 8])?;
+        for field in [entries, entries, size, offset] {
+            writer.write_all(&field.to_le_bytes())?;
+        }
+
+        writer.write_all(&ZIP64_END_OF_CENTRAL_DIRECTORY_LOCATOR_SIGNATURE.to_le_bytes())?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        writer.write_all(&record_offset.to_le_bytes())?;
+        writer.write_all(&1u32.to_le_bytes())?;
+    }
+
+    let entries = entries.min(u16::MAX as u64) as u16;
+    writer.write_all(&END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes())?;
+    // disk numbers
+    writer.write_all(&[0; 4])?;
+    writer.write_all(&entries.to_le_bytes())?;
+    writer.write_all(&entries.to_le_bytes())?;
+    writer.write_all(&(size.min(u32::MAX as u64) as u32).to_le_bytes())?;
+    writer.write_all(&(offset.min(u32::MAX as u64) as u32).to_le_bytes())?;
+    // comment length
+    writer.write_all(&0u16.to_le_bytes())
+}
+
+/// Local header of an entry, its sizes are `None` when they follow its data in a descriptor.
+struct LocalHeader {
+    flags: u16,
+    method: u16,
+    crc32: u32,
+    compressed_size: Option<u64>,
+    zip64: bool,
+    entity: ArchiveFileEntity,
+}
+
+impl LocalHeader {
+    /// Reads the next local header, or `None` once the central directory is reached.
+    fn read<R: Read>(reader: &mut R) -> Result<Option<LocalHeader>, ArchiveError> {
+        match read_u32(reader)? {
+            LOCAL_HEADER_SIGNATURE => {}
+            CENTRAL_HEADER_SIGNATURE | END_OF_CENTRAL_DIRECTORY_SIGNATURE => return Ok(None),
+            _ => {
+                return Err(ArchiveError::Zip(ZipError::InvalidArchive(
+                    "Invalid local file header",
+                )))
+            }
+        }
+
+        let _version = read_u16(reader)?;
+        let flags = read_u16(reader)?;
+        let method = read_u16(reader)?;
+        let time = read_u16(reader)?;
+        let date = read_u16(reader)?;
+        let crc32 = read_u32(reader)?;
+        let mut compressed_size = read_u32(reader)? as u64;
+        let mut size = read_u32(reader)? as u64;
+        let name_len = read_u16(reader)? as usize;
+        let extra_len = read_u16(reader)? as usize;
+
+        let mut name = vec![0; name_len];
+        reader.read_exact(&mut name)?;
+        let mut extra = vec![0; extra_len];
+        reader.read_exact(&mut extra)?;
+
+        // the zip64 field holds the sizes that do not fit in the header, in this order
+        let mut zip64 = false;
+        let mut fields = extra.as_slice();
+        while fields.len() >= 4 {
+            let id = u16::from_le_bytes([fields[0], fields[1]]);
+            let end = (4 + u16::from_le_bytes([fields[2], fields[3]]) as usize).min(fields.len());
+            let mut field = &fields[4..end];
+            if id == ZIP64_EXTRA_FIELD {
+                zip64 = true;
+                if size == u32::MAX as u64 && field.len() >= 8 {
+                    size = read_u64(&mut field)?;
+                }
+                if compressed_size == u32::MAX as u64 && field.len() >= 8 {
+                    compressed_size = read_u64(&mut field)?;
+                }
+            }
+            fields = &fields[end..];
+        }
+
+        if flags & FLAG_ENCRYPTED != 0 {
+            return Err(ArchiveError::Zip(ZipError::UnsupportedArchive(
+                "Encrypted entries cannot be read from a stream",
+            )));
+        }
+
+        let name = String::from_utf8_lossy(&name).to_string();
+        let fstype = if name.ends_with('/') {
+            ArchiveFileEntityType::Directory
+        } else {
+            ArchiveFileEntityType::File
+        };
+        let sizes_known = flags & FLAG_DATA_DESCRIPTOR == 0
+            || compressed_size != 0
+            || fstype == ArchiveFileEntityType::Directory;
+        if !sizes_known && method != DEFLATED {
+            return Err(ArchiveError::Zip(ZipError::UnsupportedArchive(
+                "Only deflated entries can have a data descriptor in a stream",
+            )));
+        }
+        let is_file = fstype == ArchiveFileEntityType::File;
+
+        Ok(Some(LocalHeader {
+            flags,
+            method,
+            crc32,
+            compressed_size: sizes_known.then_some(compressed_size),
+            zip64,
+            entity: ArchiveFileEntity {
+                name,
+                size: (sizes_known && is_file).then_some(size),
+                compressed_size: (sizes_known && is_file).then_some(compressed_size),
+                last_modified: zip::DateTime::from_msdos(date, time)
+                    .to_time()
+                    .ok()
+                    .and_then(|t| datetime_from_timestamp(t.unix_timestamp()).ok()),
+                compression: Some(compression_name(method)),
+                fstype,
+                raw: None,
+                unix: None,
+            },
+        }))
+    }
+}
+
+/// Decompresses the `raw` data of an entry of known size.
+fn decoder<'r, R: Read + 'r>(method: u16, raw: R) -> Result<Box<dyn Read + 'r>, ArchiveError> {
+    match method {
+        STORED => Ok(Box::new(raw)),
+        DEFLATED => Ok(Box::new(DeflateDecoder::new(BufReader::new(raw)))),
+        #[cfg(feature = "bzip2_codecs")]
+        12 => Ok(Box::new(bzip2::read::BzDecoder::new(raw))),
+        #[cfg(feature = "zstd_codecs")]
+        93 => Ok(Box::new(zstd::Decoder::new(raw)?)),
+        _ => Err(ArchiveError::Zip(ZipError::UnsupportedArchive(
+            "Compression method not supported",
+        ))),
+    }
+}
+
+fn compression_name(method: u16) -> String {
+    match method {
+        STORED => "Stored".to_string(),
+        DEFLATED => "Deflated".to_string(),
+        12 => "Bzip2".to_string(),
+        93 => "Zstd".to_string(),
+        m => format!("Unknown({})", m),
+    }
+}
+
+/// Reads the data descriptor following the data of an entry and returns its checksum.
+fn read_descriptor<R: Read>(reader: &mut R, zip64: bool) -> Result<u32, ArchiveError> {
+    // the signature is optional
+    let mut crc = read_u32(reader)?;
+    if crc == DATA_DESCRIPTOR_SIGNATURE {
+        crc = read_u32(reader)?;
+    }
+    if zip64 {
+        read_u64(reader)?;
+        read_u64(reader)?;
+    } else {
+        read_u32(reader)?;
+        read_u32(reader)?;
+    }
+    Ok(crc)
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> Result<u16, Error> {
+    let mut buf = [0; 2];
+    reader.read_exact(&mut buf)?;
+    Ok(u16::from_le_bytes(buf))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, Error> {
+    let mut buf = [0; 4];
+    reader.read_exact(&mut buf)?;
+    Ok(u32::from_le_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, Error> {
+    let mut buf = [0; 8];
+    reader.read_exact(&mut buf)?;
+    Ok(u64::from_le_bytes(buf))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::DeflateEncoder, Compression};
+
+    use super::*;
+    use crate::archive::{Archive, ArchiveType, Archived, DataSource, SimpleLogger};
+
+    /// Local header of an entry, the sizes being left out if `descriptor` is set.
+    fn local_header(
+        name: &str,
+        method: u16,
+        descriptor: bool,
+        crc: u32,
+        sizes: (u32, u32),
+    ) -> Vec<u8> {
+        let mut header = LOCAL_HEADER_SIGNATURE.to_le_bytes().to_vec();
+        header.extend(20u16.to_le_bytes());
+        header.extend((if descriptor { FLAG_DATA_DESCRIPTOR } else { 0 }).to_le_bytes());
+        header.extend(method.to_le_bytes());
+        header.extend([0, 0, 0x21, 0]); // 1980-01-01 00:00
+        let (crc, sizes) = if descriptor {
+            (0, (0, 0))
+        } else {
+            (crc, sizes)
+        };
+        header.extend(crc.to_le_bytes());
+        header.extend(sizes.0.to_le_bytes());
+        header.extend(sizes.1.to_le_bytes());
+        header.extend((name.len() as u16).to_le_bytes());
+        header.extend(0u16.to_le_bytes());
+        header.extend(name.as_bytes());
+        header
+    }
+
+    /// Archive as written by a streaming writer: a deflated entry followed by a data
+    /// descriptor, between a directory and a stored entry.
+    fn streamed_zip() -> Vec<u8> {
+        let content = b"streamed content, streamed content, streamed content";
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(content).unwrap();
+        let deflated = encoder.finish().unwrap();
+        let crc = {
+            let mut hasher = flate2::Crc::new();
+            hasher.update(content);
+            hasher.sum()
+        };
+
+        let mut zip = local_header("dir/", STORED, false, 0, (0, 0));
+        zip.extend(local_header("dir/a.txt", DEFLATED, true, 0, (0, 0)));
+        zip.extend(&deflated);
+        zip.extend(DATA_DESCRIPTOR_SIGNATURE.to_le_bytes());
+        zip.extend(crc.to_le_bytes());
+        zip.extend((deflated.len() as u32).to_le_bytes());
+        zip.extend((content.len() as u32).to_le_bytes());
+
+        let mut hasher = flate2::Crc::new();
+        hasher.update(b"stored");
+        zip.extend(local_header("b.txt", STORED, false, hasher.sum(), (6, 6)));
+        zip.extend(b"stored");
+        zip.extend(END_OF_CENTRAL_DIRECTORY_SIGNATURE.to_le_bytes());
+        zip.extend([0; 18]);
+        zip
+    }
+
+    #[test]
+    fn reads_data_descriptors() {
+        let zip = streamed_zip();
+        let entries = ZipStream::new(zip.as_slice())
+            .list(ListOptions::default())
+            .unwrap();
+        let names = entries.iter().map(|e| e.name()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["dir/", "dir/a.txt", "b.txt"]);
+        assert_eq!(entries[1].size(), Some(52));
+
+        let dest = std::env::temp_dir().join(format!("hezi-zip-stream-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dest);
+        let stream = ZipStream::new(zip.as_slice());
+        stream
+            .extract(ExtractOptions {
+                destination: dest.clone(),
+                ..Default::default()
+            })
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dest.join("dir/a.txt")).unwrap(),
+            "streamed content, streamed content, streamed content"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dest.join("b.txt")).unwrap(),
+            "stored"
+        );
+        assert!(matches!(
+            stream.list(ListOptions::default()),
+            Err(ArchiveError::InvalidDataSource(_))
+        ));
+        _ = std::fs::remove_dir_all(&dest);
+    }
+
+    #[test]
+    fn matches_central_directory() {
+        let bytes = std::fs::read("tests/fixtures/test1.zip").unwrap();
+        let streamed = ZipStream::new(bytes.as_slice())
+            .list(ListOptions::default())
+            .unwrap();
+        let listed = Archive::of(DataSource::memory(bytes.as_slice()))
+            .unwrap()
+            .list(ListOptions::default())
+            .unwrap();
+        let names = |entries: &[ArchiveFileEntity]| {
+            entries
+                .iter()
+                .map(|e| (e.name().to_string(), e.size()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&streamed), names(&listed));
+    }
+
+    #[cfg(feature = "deflate_codecs")]
+    #[test]
+    fn writes_readable_streams() {
+        let dir = std::env::temp_dir().join(format!("hezi-zip-write-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        std::fs::write(dir.join("sub/a.txt"), "streamed ".repeat(100)).unwrap();
+        std::fs::write(dir.join("b.txt"), "b").unwrap();
+        std::fs::write(dir.join(".hidden"), "hidden").unwrap();
+        std::fs::create_dir_all(dir.join(".cache")).unwrap();
+
+        let options = CreateOptions {
+            destination: "-".into(),
+            source: dir.clone(),
+            roots: vec![],
+            files: vec![
+                dir.clone(),
+                dir.join("sub"),
+                dir.join("sub/a.txt"),
+                dir.join("b.txt"),
+                dir.join(".hidden"),
+                dir.join(".cache"),
+            ],
+            password: None,
+            archive_type: ArchiveType::Zip,
+            compression: None,
+            overwrite: false,
+            include_hidden: false,
+            skip_unreadable: false,
+            verify_after: false,
+            volume_size: None,
+            update: false,
+            sevenz: Default::default(),
+            zip: Default::default(),
+            tar: Default::default(),
+            event_handler: Box::new(SimpleLogger),
+        };
+        let mut zip = Vec::new();
+        let result = create_zip_stream(options, &mut zip).unwrap();
+        assert_eq!(result.total_size, 901);
+        assert_eq!(result.compressed_size, zip.len() as u64);
+
+        let entries = |entries: Vec<ArchiveFileEntity>| {
+            entries
+                .iter()
+                .map(|e| (e.name().to_string(), e.size()))
+                .collect::<Vec<_>>()
+        };
+        let streamed = entries(
+            ZipStream::new(zip.as_slice())
+                .list(ListOptions::default())
+                .unwrap(),
+        );
+        assert_eq!(
+            streamed,
+            vec![
+                ("sub/".to_string(), None),
+                ("sub/a.txt".to_string(), Some(900)),
+                ("b.txt".to_string(), Some(1)),
+            ]
+        );
+        let listed = Archive::of(DataSource::memory(zip.as_slice()))
+            .unwrap()
+            .list(ListOptions::default())
+            .unwrap();
+        assert_eq!(entries(listed), streamed);
+
+        // files have a zip64 field with sizes of 0 in their local header
+        let name = zip.windows(9).position(|w| w == b"sub/a.txt").unwrap();
+        assert_eq!(zip[name - 12..name - 4], [0xff; 8]);
+        assert_eq!(zip[name + 9..name + 13], [1, 0, 16, 0]);
+        assert_eq!(zip[name + 13..name + 29], [0; 16]);
+
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(&zip)).unwrap();
+        let mut content = String::new();
+        archive
+            .by_name("sub/a.txt")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "streamed ".repeat(100));
+        _ = std::fs::remove_dir_all(&dir);
+    }
+}
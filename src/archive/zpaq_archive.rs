@@ -0,0 +1,1573 @@
+// read-only support for ZPAQ archives, mostly written by the zpaq incremental backup tool in its
+// journaling format. Each update appends a version: a `c` block opening the transaction, `d`
+// blocks holding deduplicated fragments of file contents, `h` blocks with the size and SHA-1 of
+// these fragments, then `i` blocks listing the files added, changed or deleted. Blocks are
+// compressed with context mixing models they describe themselves, along with ZPAQL programs
+// computing the contexts of the models and undoing the preprocessing of the data, which are run
+// by the interpreter below. Streaming archives, whose segments are whole files, are read as a
+// single version.
+
+use std::{
+    cell::OnceCell,
+    collections::BTreeMap,
+    io::{BufReader, ErrorKind, Read, Seek, SeekFrom},
+    ops::ControlFlow,
+    rc::Rc,
+    sync::OnceLock,
+};
+
+use chrono::NaiveDate;
+use serde_json::json;
+use sha1::{Digest, Sha1};
+
+use crate::archive::{
+    entry_visitor::{
+        extract_entries, find_first_entry, list_entries, open_entry, scan_entries, EntryVisitor,
+        VisitOptions, VisitedEntry,
+    },
+    ArchiveError, ArchiveFileEntity, ArchiveFileEntityType, ArchiveMetadata, ArchiveScan,
+    ArchiveType, Archived, CreateOptions, CreateResult, DataSource, ExtractOptions, ExtractReport,
+    ListOptions, OpenOptions, DEFAULT_BUF_SIZE,
+};
+
+use super::edit::EntryEdit;
+
+/// Optionally written before a block so that archives can be found within other files.
+const LOCATOR_TAG: [u8; 13] = [
+    0x37, 0x6b, 0x53, 0x74, 0xa0, 0x31, 0x83, 0xd3, 0x8c, 0xb2, 0x28, 0xb0, 0xd3,
+];
+
+const CONS: u8 = 1;
+const CM: u8 = 2;
+const ICM: u8 = 3;
+const MATCH: u8 = 4;
+const AVG: u8 = 5;
+const MIX2: u8 = 6;
+const MIX: u8 = 7;
+const ISSE: u8 = 8;
+const SSE: u8 = 9;
+/// Size of the description of each type of component, its type included.
+const COMPONENT_SIZES: [usize; 10] = [0, 2, 3, 2, 3, 4, 6, 6, 3, 5];
+
+fn invalid_data(message: String) -> ArchiveError {
+    ArchiveError::Io(std::io::Error::new(ErrorKind::InvalidData, message))
+}
+
+fn io_error(error: ArchiveError) -> std::io::Error {
+    match error {
+        ArchiveError::Io(e) => e,
+        e => std::io::Error::other(e.to_string()),
+    }
+}
+
+fn u32_at(buf: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(buf.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn read_byte<R: Read>(reader: &mut R) -> Result<u8, ArchiveError> {
+    let mut byte = [0; 1];
+    reader.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+/// Reads a NUL terminated string.
+fn read_string<R: Read>(reader: &mut R) -> Result<String, ArchiveError> {
+    let mut bytes = Vec::new();
+    loop {
+        match read_byte(reader)? {
+            0 => return Ok(String::from_utf8_lossy(&bytes).to_string()),
+            b => bytes.push(b),
+        }
+    }
+}
+
+/// A date stored as the decimal number `YYYYMMDDHHMMSS`, in UTC.
+fn decimal_date(date: u64) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+    let part = |unit: u64, max: u64| (date / unit % max) as u32;
+    NaiveDate::from_ymd_opt(
+        (date / 10_000_000_000) as i32,
+        part(100_000_000, 100),
+        part(1_000_000, 100),
+    )?
+    .and_hms_opt(part(10_000, 100), part(100, 100), part(1, 100))
+    .map(|t| t.and_utc().fixed_offset())
+}
+
+/// Lookup tables shared by all the models.
+struct Tables {
+    /// `ln(p / (1 - p))` of a 15 bit probability, scaled by 64.
+    stretch: Vec<i32>,
+    /// Inverse of `stretch`, from -2048 to 2047.
+    squash: Vec<i32>,
+    /// Learning rate of a context model by number of updates.
+    dt: Vec<i32>,
+    /// Confidence in a match by length.
+    dt2k: Vec<i32>,
+    /// Next bit history state by state and bit.
+    next: Vec<[u8; 2]>,
+    /// Initial 23 bit probability of a one by bit history state.
+    cminit: Vec<u32>,
+}
+
+fn tables() -> &'static Tables {
+    static TABLES: OnceLock<Tables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let (next, cminit) = bit_histories();
+        Tables {
+            stretch: (0..32768)
+                .map(|i| {
+                    let i = i as f64;
+                    ((i + 0.5) / (32767.5 - i))
+                        .ln()
+                        .mul_add(64.0, 0.5 + 100000.0) as i32
+                        - 100000
+                })
+                .collect(),
+            squash: (0..4096)
+                .map(|i| (32768.0 / (1.0 + ((i - 2048) as f64 * (-1.0 / 64.0)).exp())) as i32)
+                .collect(),
+            dt: (0..1024).map(|i| (1 << 17) / (i * 2 + 3) * 2).collect(),
+            dt2k: (0..256)
+                .map(|i| if i == 0 { 0 } else { 2048 / i })
+                .collect(),
+            next,
+            cminit,
+        }
+    })
+}
+
+fn stretch(p: u32) -> i32 {
+    tables().stretch[p as usize & 32767]
+}
+
+fn squash(x: i32) -> i32 {
+    tables().squash[(x.clamp(-2048, 2047) + 2048) as usize]
+}
+
+fn clamp2k(x: i32) -> i32 {
+    x.clamp(-2048, 2047)
+}
+
+fn clamp512k(x: i32) -> i32 {
+    x.clamp(-(1 << 19), (1 << 19) - 1)
+}
+
+/// Number of bit history states representing `n0` zeros and `n1` ones, the counts being
+/// bounded so that there are 256 states.
+fn state_count(n0: i32, n1: i32) -> i32 {
+    const BOUND: [i32; 6] = [20, 48, 15, 8, 6, 5];
+    if n0 < n1 {
+        return state_count(n1, n0);
+    }
+    if !(0..6).contains(&n1) || n0 > BOUND[n1 as usize] {
+        return 0;
+    }
+    1 + (n1 > 0 && n0 + n1 <= 17) as i32
+}
+
+/// Count of the opposite bit once a bit is observed.
+fn discount(n: &mut i32) {
+    *n = [1, 2, 3, 4, 5, 7, 8].iter().filter(|t| *n >= **t).count() as i32;
+}
+
+fn next_counts(n0: &mut i32, n1: &mut i32, y: i32) {
+    if *n0 < *n1 {
+        return next_counts(n1, n0, 1 - y);
+    }
+    if y == 1 {
+        *n1 += 1;
+        discount(n0);
+    } else {
+        *n0 += 1;
+        discount(n1);
+    }
+    while state_count(*n0, *n1) == 0 {
+        if *n1 < 2 {
+            *n0 -= 1;
+        } else {
+            *n0 = (*n0 * (*n1 - 1) + *n1 / 2) / *n1;
+            *n1 -= 1;
+        }
+    }
+}
+
+/// The bit history state machine of indirect models, numbering states by total count.
+fn bit_histories() -> (Vec<[u8; 2]>, Vec<u32>) {
+    const N: usize = 50;
+    let mut states = vec![[[0u8; 2]; N]; N];
+    let mut state = 0;
+    for total in 0..N {
+        // by increasing count of ones
+        for (n0, row) in states.iter_mut().enumerate().take(total + 1).rev() {
+            let n1 = total - n0;
+            let count = state_count(n0 as i32, n1 as i32) as usize;
+            if count > 0 {
+                row[n1] = [state as u8, (state + count - 1) as u8];
+                state += count;
+            }
+        }
+    }
+
+    let mut next = vec![[0u8; 2]; 256];
+    let mut cminit = vec![0u32; 256];
+    for n0 in 0..N {
+        for n1 in 0..N {
+            for y in 0..state_count(n0 as i32, n1 as i32) as usize {
+                let s = states[n0][n1][y] as usize;
+                for bit in 0..2 {
+                    let (mut s0, mut s1) = (n0 as i32, n1 as i32);
+                    next_counts(&mut s0, &mut s1, bit);
+                    next[s][bit as usize] = states[s0 as usize][s1 as usize][bit as usize];
+                }
+                cminit[s] = (((n1 * 2 + 1) << 22) / (n0 + n1 + 1)) as u32;
+            }
+        }
+    }
+    (next, cminit)
+}
+
+/// Interpreter of ZPAQL, the language of the programs computing the contexts of the models
+/// (HCOMP) and post-processing the decoded data (PCOMP).
+struct Zpaql {
+    code: Vec<u8>,
+    h: Vec<u32>,
+    m: Vec<u8>,
+    r: [u32; 256],
+    a: u32,
+    b: u32,
+    c: u32,
+    d: u32,
+    f: bool,
+    /// Bytes written by `OUT`.
+    output: Vec<u8>,
+}
+
+impl Zpaql {
+    fn new(mut code: Vec<u8>, h_bits: u8, m_bits: u8) -> Result<Self, ArchiveError> {
+        if h_bits > 32 || m_bits > 32 {
+            return Err(invalid_data("invalid ZPAQL memory size".to_string()));
+        }
+        // running past the end reads an ERROR instruction
+        code.push(0);
+        Ok(Self {
+            code,
+            h: vec![0; 1 << h_bits],
+            m: vec![0; 1 << m_bits],
+            r: [0; 256],
+            a: 0,
+            b: 0,
+            c: 0,
+            d: 0,
+            f: false,
+            output: Vec::new(),
+        })
+    }
+
+    /// Register or memory cell of an instruction: A, B, C, D, *B, *C and *D.
+    fn get(&self, operand: u8) -> u32 {
+        match operand {
+            0 => self.a,
+            1 => self.b,
+            2 => self.c,
+            3 => self.d,
+            4 => self.m[self.b as usize & (self.m.len() - 1)] as u32,
+            5 => self.m[self.c as usize & (self.m.len() - 1)] as u32,
+            _ => self.h[self.d as usize & (self.h.len() - 1)],
+        }
+    }
+
+    fn set(&mut self, operand: u8, value: u32) {
+        let (m, h) = (self.m.len() - 1, self.h.len() - 1);
+        match operand {
+            0 => self.a = value,
+            1 => self.b = value,
+            2 => self.c = value,
+            3 => self.d = value,
+            4 => self.m[self.b as usize & m] = value as u8,
+            5 => self.m[self.c as usize & m] = value as u8,
+            _ => self.h[self.d as usize & h] = value,
+        }
+    }
+
+    fn operand(&self, pc: &mut usize) -> Result<u8, ArchiveError> {
+        let n = *self
+            .code
+            .get(*pc)
+            .ok_or_else(|| invalid_data("ZPAQL program out of bounds".to_string()))?;
+        *pc += 1;
+        Ok(n)
+    }
+
+    fn jump(&self, pc: &mut usize) -> Result<(), ArchiveError> {
+        let offset = self.operand(pc)? as i8;
+        *pc = pc
+            .checked_add_signed(offset as isize)
+            .ok_or_else(|| invalid_data("ZPAQL jump out of bounds".to_string()))?;
+        Ok(())
+    }
+
+    /// Runs the program with `input` in A, until it halts.
+    fn run(&mut self, input: u32) -> Result<(), ArchiveError> {
+        self.a = input;
+        let mut pc = 0;
+        loop {
+            let op = self.operand(&mut pc)?;
+            match (op >> 3, op & 7) {
+                // HALT, OUT, HASH, HASHD and JMP
+                (7, 0) => return Ok(()),
+                (7, 1) => self.output.push(self.a as u8),
+                (7, 3) => {
+                    let byte = self.get(4);
+                    self.a = self
+                        .a
+                        .wrapping_add(byte)
+                        .wrapping_add(512)
+                        .wrapping_mul(773);
+                }
+                (7, 4) => {
+                    let hash = self.get(6).wrapping_add(self.a).wrapping_add(512);
+                    self.set(6, hash.wrapping_mul(773));
+                }
+                (7, 7) => self.jump(&mut pc)?,
+                // JT, JF and R=A
+                (4, 7) if self.f => self.jump(&mut pc)?,
+                (5, 7) if !self.f => self.jump(&mut pc)?,
+                (4 | 5, 7) => pc += 1,
+                (6, 7) => self.r[self.operand(&mut pc)? as usize] = self.a,
+                // swaps with A, ++, --, ! and =0
+                (1..=6, 0) => {
+                    let (a, x) = (self.a, self.get(op >> 3));
+                    self.a = match op >> 3 {
+                        4 | 5 => (a & !255) | x,
+                        _ => x,
+                    };
+                    self.set(op >> 3, a);
+                }
+                (0..=6, 1..=4) => {
+                    let x = self.get(op >> 3);
+                    let x = match op & 7 {
+                        1 => x.wrapping_add(1),
+                        2 => x.wrapping_sub(1),
+                        3 => !x,
+                        _ => 0,
+                    };
+                    self.set(op >> 3, x);
+                }
+                // A=R N to D=R N
+                (0..=3, 7) => {
+                    let r = self.r[self.operand(&mut pc)? as usize];
+                    self.set(op >> 3, r);
+                }
+                // assignments, then arithmetic and comparisons on A
+                (8..=14, source) => {
+                    let x = self.source(source, &mut pc)?;
+                    self.set((op >> 3) - 8, x);
+                }
+                (16..=29, source) => {
+                    let (a, x) = (self.a, self.source(source, &mut pc)?);
+                    match (op >> 3) - 16 {
+                        0 => self.a = a.wrapping_add(x),
+                        1 => self.a = a.wrapping_sub(x),
+                        2 => self.a = a.wrapping_mul(x),
+                        3 => self.a = a.checked_div(x).unwrap_or(0),
+                        4 => self.a = a.checked_rem(x).unwrap_or(0),
+                        5 => self.a = a & x,
+                        6 => self.a = a & !x,
+                        7 => self.a = a | x,
+                        8 => self.a = a ^ x,
+                        9 => self.a = a << (x & 31),
+                        10 => self.a = a >> (x & 31),
+                        11 => self.f = a == x,
+                        12 => self.f = a < x,
+                        _ => self.f = a > x,
+                    }
+                }
+                // LJ, a jump to an absolute address
+                (31, 7) => {
+                    let low = self.operand(&mut pc)? as usize;
+                    pc = low + 256 * self.operand(&mut pc)? as usize;
+                }
+                _ => {
+                    return Err(invalid_data(format!(
+                        "invalid ZPAQL instruction {} at {}",
+                        op,
+                        pc - 1
+                    )))
+                }
+            }
+        }
+    }
+
+    /// Value of the source of an assignment: a register, a memory cell or the next byte.
+    fn source(&self, source: u8, pc: &mut usize) -> Result<u32, ArchiveError> {
+        match source {
+            7 => Ok(self.operand(pc)? as u32),
+            source => Ok(self.get(source)),
+        }
+    }
+}
+
+/// State of a component, whose fields are used differently by each type like in libzpaq.
+#[derive(Default)]
+struct Component {
+    limit: u32,
+    cxt: u32,
+    a: u32,
+    b: u32,
+    c: u32,
+    cm: Vec<u32>,
+    ht: Vec<u8>,
+    a16: Vec<u16>,
+}
+
+impl Component {
+    fn cm(&mut self, i: u32) -> &mut u32 {
+        let mask = self.cm.len() - 1;
+        &mut self.cm[i as usize & mask]
+    }
+
+    fn ht(&mut self, i: u32) -> &mut u8 {
+        let mask = self.ht.len() - 1;
+        &mut self.ht[i as usize & mask]
+    }
+
+    /// Moves the probability of the current context towards `y`, more slowly as its count of
+    /// updates grows.
+    fn train(&mut self, y: u32) {
+        let limit = self.limit;
+        let cxt = self.cxt;
+        let p = self.cm(cxt);
+        let count = *p & 0x3ff;
+        let error = (y * 32767).wrapping_sub(*p >> 17) as i32;
+        let delta = error.wrapping_mul(tables().dt[count as usize]) & -1024;
+        *p = p
+            .wrapping_add(delta as u32)
+            .wrapping_add((count < limit) as u32);
+    }
+}
+
+/// Slot of 16 bit histories for the context `cxt` in a hash table, replacing the least used
+/// of 3 candidates when none matches.
+fn find(ht: &mut [u8], size_bits: u32, cxt: u32) -> usize {
+    let check = (cxt.checked_shr(size_bits).unwrap_or(0) & 255) as u8;
+    let h0 = cxt.wrapping_mul(16) as usize & (ht.len() - 16);
+    let (h1, h2) = (h0 ^ 16, h0 ^ 32);
+    for h in [h0, h1, h2] {
+        if ht[h] == check {
+            return h;
+        }
+    }
+    let h = if ht[h0 + 1] <= ht[h1 + 1] && ht[h0 + 1] <= ht[h2 + 1] {
+        h0
+    } else if ht[h1 + 1] < ht[h2 + 1] {
+        h1
+    } else {
+        h2
+    };
+    ht[h..h + 16].fill(0);
+    ht[h] = check;
+    h
+}
+
+/// Predicts the bits of a block by mixing the predictions of its components.
+struct Predictor {
+    /// Type and parameters of each component.
+    specs: Vec<[u8; 6]>,
+    components: Vec<Component>,
+    hcomp: Zpaql,
+    /// Prediction of each component, stretched.
+    p: Vec<i32>,
+    /// Context of each component, computed by HCOMP after each byte.
+    h: Vec<u32>,
+    /// Bits of the current byte, after a leading 1.
+    c8: u32,
+    /// Bits of the current nibble, used to index bit histories.
+    hmap4: u32,
+}
+
+impl Predictor {
+    fn new(specs: Vec<[u8; 6]>, hcomp: Zpaql) -> Result<Self, ArchiveError> {
+        let tables = tables();
+        let invalid = |i: usize| invalid_data(format!("invalid ZPAQ component {}", i));
+        let mut components = Vec::with_capacity(specs.len());
+        let mut p = vec![0; specs.len()];
+        for (i, cp) in specs.iter().enumerate() {
+            let mut cr = Component::default();
+            let inputs_ok = |j: u8| (j as usize) < i;
+            match cp[0] {
+                CONS => p[i] = (cp[1] as i32 - 128) * 4,
+                CM if cp[1] <= 32 => {
+                    cr.cm = vec![0x80000000; 1 << cp[1]];
+                    cr.limit = cp[2] as u32 * 4;
+                }
+                ICM if cp[1] <= 26 => {
+                    cr.limit = 1023;
+                    cr.cm = tables.cminit.clone();
+                    cr.ht = vec![0; 64 << cp[1]];
+                }
+                MATCH if cp[1] <= 32 && cp[2] <= 32 => {
+                    cr.cm = vec![0; 1 << cp[1]];
+                    cr.ht = vec![0; 1 << cp[2]];
+                    cr.ht[0] = 1;
+                }
+                AVG if inputs_ok(cp[1]) && inputs_ok(cp[2]) => {}
+                MIX2 if cp[1] <= 32 && inputs_ok(cp[2]) && inputs_ok(cp[3]) => {
+                    cr.c = 1 << cp[1];
+                    cr.a16 = vec![32768; 1 << cp[1]];
+                }
+                MIX if cp[1] <= 32
+                    && inputs_ok(cp[2])
+                    && (1..=i - cp[2] as usize).contains(&(cp[3] as usize)) =>
+                {
+                    let m = cp[3] as usize;
+                    cr.c = 1 << cp[1];
+                    cr.cm = vec![65536 / m as u32; m << cp[1]];
+                }
+                ISSE if cp[1] <= 32 && inputs_ok(cp[2]) => {
+                    cr.ht = vec![0; 64 << cp[1]];
+                    cr.cm = (0..512)
+                        .map(|j| match j % 2 {
+                            0 => 1 << 15,
+                            _ => clamp512k(stretch(tables.cminit[j / 2] >> 8) << 10) as u32,
+                        })
+                        .collect();
+                }
+                SSE if cp[1] <= 32 && inputs_ok(cp[2]) && cp[3] as u32 <= cp[4] as u32 * 4 => {
+                    cr.cm = (0..32u32 << cp[1])
+                        .map(|j| (squash((j & 31) as i32 * 64 - 992) as u32) << 17 | cp[3] as u32)
+                        .collect();
+                    cr.limit = cp[4] as u32 * 4;
+                }
+                _ => return Err(invalid(i)),
+            }
+            components.push(cr);
+        }
+        Ok(Self {
+            h: vec![0; specs.len()],
+            specs,
+            components,
+            hcomp,
+            p,
+            c8: 1,
+            hmap4: 1,
+        })
+    }
+
+    /// Probability that the next bit is a one, on 15 bits.
+    fn predict(&mut self) -> u32 {
+        let tables = tables();
+        let Self {
+            specs,
+            components,
+            p,
+            h,
+            c8,
+            hmap4,
+            ..
+        } = self;
+        let (c8, hmap4) = (*c8, *hmap4);
+        for (i, cp) in specs.iter().enumerate() {
+            let cr = &mut components[i];
+            match cp[0] {
+                CM => {
+                    cr.cxt = h[i] ^ hmap4;
+                    let cxt = cr.cxt;
+                    p[i] = stretch(*cr.cm(cxt) >> 17);
+                }
+                ICM => {
+                    if c8 == 1 || (c8 & 0xf0) == 16 {
+                        cr.c =
+                            find(&mut cr.ht, cp[1] as u32 + 2, h[i].wrapping_add(16 * c8)) as u32;
+                    }
+                    cr.cxt = cr.ht[(cr.c + (hmap4 & 15)) as usize] as u32;
+                    p[i] = stretch(cr.cm[cr.cxt as usize] >> 8);
+                }
+                MATCH => {
+                    if cr.a == 0 {
+                        p[i] = 0;
+                    } else {
+                        let position = cr.limit.wrapping_sub(cr.b);
+                        cr.c = (*cr.ht(position) as u32 >> (7 - cr.cxt)) & 1;
+                        let sign = match cr.c {
+                            1 => u32::MAX,
+                            _ => 1,
+                        };
+                        p[i] =
+                            stretch((tables.dt2k[cr.a as usize] as u32).wrapping_mul(sign) & 32767);
+                    }
+                }
+                AVG => {
+                    let wt = cp[3] as i32;
+                    p[i] = (p[cp[1] as usize] * wt + p[cp[2] as usize] * (256 - wt)) >> 8;
+                }
+                MIX2 => {
+                    cr.cxt = h[i].wrapping_add(c8 & cp[5] as u32) & (cr.c - 1);
+                    let w = cr.a16[cr.cxt as usize] as i32;
+                    p[i] = (w * p[cp[2] as usize] + (65536 - w) * p[cp[3] as usize]) >> 16;
+                }
+                MIX => {
+                    let m = cp[3] as usize;
+                    cr.cxt = h[i].wrapping_add(c8 & cp[5] as u32) & (cr.c - 1);
+                    cr.cxt *= m as u32;
+                    let weights = &cr.cm[cr.cxt as usize..cr.cxt as usize + m];
+                    let inputs = &p[cp[2] as usize..cp[2] as usize + m];
+                    let dot = weights
+                        .iter()
+                        .zip(inputs)
+                        .map(|(w, x)| (*w as i32 >> 8) * x)
+                        .fold(0i32, i32::wrapping_add);
+                    p[i] = clamp2k(dot >> 8);
+                }
+                ISSE => {
+                    if c8 == 1 || (c8 & 0xf0) == 16 {
+                        cr.c =
+                            find(&mut cr.ht, cp[1] as u32 + 2, h[i].wrapping_add(16 * c8)) as u32;
+                    }
+                    cr.cxt = cr.ht[(cr.c + (hmap4 & 15)) as usize] as u32;
+                    let w = &cr.cm[cr.cxt as usize * 2..];
+                    p[i] = clamp2k(
+                        (w[0] as i32)
+                            .wrapping_mul(p[cp[2] as usize])
+                            .wrapping_add((w[1] as i32).wrapping_mul(64))
+                            >> 16,
+                    );
+                }
+                SSE => {
+                    cr.cxt = h[i].wrapping_add(c8).wrapping_mul(32);
+                    let pq = (p[cp[2] as usize] + 992).clamp(0, 1983);
+                    let wt = pq as u32 & 63;
+                    cr.cxt = cr.cxt.wrapping_add(pq as u32 >> 6);
+                    let cxt = cr.cxt;
+                    let low = *cr.cm(cxt) >> 10;
+                    let high = *cr.cm(cxt.wrapping_add(1)) >> 10;
+                    p[i] = stretch((low * (64 - wt) + high * wt) >> 13);
+                    cr.cxt = cr.cxt.wrapping_add(wt >> 5);
+                }
+                _ => {}
+            }
+        }
+        squash(p[p.len() - 1]) as u32
+    }
+
+    fn update(&mut self, y: u32) -> Result<(), ArchiveError> {
+        let tables = tables();
+        let Self {
+            specs,
+            components,
+            p,
+            h,
+            c8,
+            hmap4,
+            hcomp,
+        } = self;
+        let error = |p: i32| (y * 32767) as i32 - squash(p);
+        for (i, cp) in specs.iter().enumerate() {
+            let cr = &mut components[i];
+            match cp[0] {
+                CM | SSE => cr.train(y),
+                ICM => {
+                    let slot = (cr.c + (*hmap4 & 15)) as usize;
+                    cr.ht[slot] = tables.next[cr.ht[slot] as usize][y as usize];
+                    let pn = &mut cr.cm[cr.cxt as usize];
+                    let delta = ((y * 32767).wrapping_sub(*pn >> 8) as i32) >> 2;
+                    *pn = pn.wrapping_add(delta as u32);
+                }
+                MATCH => {
+                    if cr.c != y {
+                        cr.a = 0;
+                    }
+                    let limit = cr.limit;
+                    let byte = cr.ht(limit);
+                    *byte = byte.wrapping_add(*byte).wrapping_add(y as u8);
+                    cr.cxt += 1;
+                    if cr.cxt == 8 {
+                        cr.cxt = 0;
+                        cr.limit = cr.limit.wrapping_add(1) & (cr.ht.len() - 1) as u32;
+                        let limit = cr.limit;
+                        if cr.a == 0 {
+                            cr.b = limit.wrapping_sub(*cr.cm(h[i]));
+                            if cr.b as usize & (cr.ht.len() - 1) != 0 {
+                                while cr.a < 255
+                                    && *cr.ht(limit.wrapping_sub(cr.a + 1))
+                                        == *cr.ht(limit.wrapping_sub(cr.a + cr.b + 1))
+                                {
+                                    cr.a += 1;
+                                }
+                            }
+                        } else if cr.a < 255 {
+                            cr.a += 1;
+                        }
+                        *cr.cm(h[i]) = limit;
+                    }
+                }
+                MIX2 => {
+                    let err = (error(p[i]) * cp[4] as i32) >> 5;
+                    let w = cr.a16[cr.cxt as usize] as i32
+                        + ((err * (p[cp[2] as usize] - p[cp[3] as usize]) + (1 << 12)) >> 13);
+                    cr.a16[cr.cxt as usize] = w.clamp(0, 65535) as u16;
+                }
+                MIX => {
+                    let m = cp[3] as usize;
+                    let err = (error(p[i]) * cp[4] as i32) >> 4;
+                    let weights = &mut cr.cm[cr.cxt as usize..cr.cxt as usize + m];
+                    for (w, x) in weights.iter_mut().zip(&p[cp[2] as usize..]) {
+                        *w =
+                            clamp512k((*w as i32).wrapping_add((err * x + (1 << 12)) >> 13)) as u32;
+                    }
+                }
+                ISSE => {
+                    let err = error(p[i]);
+                    let w = &mut cr.cm[cr.cxt as usize * 2..];
+                    w[0] = clamp512k(
+                        (w[0] as i32).wrapping_add((err * p[cp[2] as usize] + (1 << 12)) >> 13),
+                    ) as u32;
+                    w[1] = clamp512k((w[1] as i32).wrapping_add((err + 16) >> 5)) as u32;
+                    let slot = (cr.c + (*hmap4 & 15)) as usize;
+                    cr.ht[slot] = tables.next[cr.cxt as usize][y as usize];
+                }
+                _ => {}
+            }
+        }
+
+        *c8 = *c8 * 2 + y;
+        if *c8 >= 256 {
+            hcomp.run(*c8 - 256)?;
+            *hmap4 = 1;
+            *c8 = 1;
+            let mask = hcomp.h.len() - 1;
+            for (i, h) in h.iter_mut().enumerate() {
+                *h = hcomp.h[i & mask];
+            }
+        } else if (16..32).contains(c8) {
+            *hmap4 = (*hmap4 & 0xf) << 5 | y << 4 | 1;
+        } else {
+            *hmap4 = (*hmap4 & 0x1f0) | (((*hmap4 & 0xf) * 2 + y) & 0xf);
+        }
+        Ok(())
+    }
+}
+
+/// Arithmetic decoder of the bits of modeled segments.
+struct BitDecoder {
+    low: u32,
+    high: u32,
+    current: u32,
+}
+
+impl BitDecoder {
+    fn new<R: Read>(reader: &mut R) -> Result<Self, ArchiveError> {
+        let mut current = [0; 4];
+        reader.read_exact(&mut current)?;
+        Ok(Self {
+            low: 1,
+            high: u32::MAX,
+            current: u32::from_be_bytes(current),
+        })
+    }
+
+    /// Decodes a bit that is a one with the probability `p` on 16 bits.
+    fn decode<R: Read>(&mut self, reader: &mut R, p: u32) -> Result<u32, ArchiveError> {
+        if self.current < self.low || self.current > self.high {
+            return Err(invalid_data("corrupted ZPAQ data".to_string()));
+        }
+        let mid = self.low + ((((self.high - self.low) as u64) * p as u64) >> 16) as u32;
+        let y = match self.current <= mid {
+            true => {
+                self.high = mid;
+                1
+            }
+            false => {
+                self.low = mid + 1;
+                0
+            }
+        };
+        while (self.high ^ self.low) < 0x1000000 {
+            self.high = self.high << 8 | 255;
+            self.low <<= 8;
+            self.low += (self.low == 0) as u32;
+            self.current = self.current << 8 | read_byte(reader)? as u32;
+        }
+        Ok(y)
+    }
+}
+
+enum PostState {
+    /// Waiting for the first byte of the block, 0 to pass the data through or 1 for a program.
+    Start,
+    Pass,
+    /// Reading the size of the program, then the program.
+    Size(Option<u8>),
+    Program(usize),
+    Run(Box<Zpaql>),
+}
+
+/// Undoes the preprocessing of the data of a block, described by its first decoded bytes.
+struct PostProcessor {
+    h_bits: u8,
+    m_bits: u8,
+    state: PostState,
+    program: Vec<u8>,
+    output: Vec<u8>,
+}
+
+impl PostProcessor {
+    fn new(h_bits: u8, m_bits: u8) -> Self {
+        Self {
+            h_bits,
+            m_bits,
+            state: PostState::Start,
+            program: Vec::new(),
+            output: Vec::new(),
+        }
+    }
+
+    /// Takes a decoded byte, or `None` at the end of a segment.
+    fn write(&mut self, byte: Option<u8>) -> Result<(), ArchiveError> {
+        let unexpected_end = || invalid_data("unexpected end of ZPAQ segment".to_string());
+        match (&mut self.state, byte) {
+            (PostState::Start, Some(0)) => self.state = PostState::Pass,
+            (PostState::Start, Some(1)) => self.state = PostState::Size(None),
+            (PostState::Start, Some(_)) => {
+                return Err(invalid_data("unknown ZPAQ post-processing".to_string()))
+            }
+            (PostState::Pass, Some(b)) => self.output.push(b),
+            (PostState::Pass, None) => {}
+            (PostState::Size(None), Some(b)) => self.state = PostState::Size(Some(b)),
+            (PostState::Size(Some(low)), Some(b)) => {
+                let size = *low as usize + 256 * b as usize;
+                if size == 0 {
+                    return Err(invalid_data("empty ZPAQ post-processor".to_string()));
+                }
+                self.state = PostState::Program(size);
+            }
+            (PostState::Program(size), Some(b)) => {
+                self.program.push(b);
+                if self.program.len() == *size {
+                    let program = std::mem::take(&mut self.program);
+                    self.state =
+                        PostState::Run(Box::new(Zpaql::new(program, self.h_bits, self.m_bits)?));
+                }
+            }
+            (PostState::Run(vm), byte) => vm.run(byte.map_or(u32::MAX, u32::from))?,
+            (_, None) => return Err(unexpected_end()),
+        }
+        Ok(())
+    }
+
+    fn take_output(&mut self) -> Vec<u8> {
+        match &mut self.state {
+            PostState::Run(vm) => std::mem::take(&mut vm.output),
+            _ => std::mem::take(&mut self.output),
+        }
+    }
+}
+
+/// A block, made of segments compressed with the same model.
+struct Block {
+    predictor: Option<Predictor>,
+    post: PostProcessor,
+}
+
+impl Block {
+    /// Reads the header of the next block, or `None` at the end of the archive.
+    fn read<R: Read>(reader: &mut R) -> Result<Option<Self>, ArchiveError> {
+        let mut magic = [0; 4];
+        if reader.read(&mut magic[..1])? == 0 {
+            return Ok(None);
+        }
+        reader.read_exact(&mut magic[1..])?;
+        if magic == LOCATOR_TAG[..4] {
+            let mut tag = [0; 9];
+            reader.read_exact(&mut tag)?;
+            if tag != LOCATOR_TAG[4..] {
+                return Err(invalid_data("invalid ZPAQ locator tag".to_string()));
+            }
+            reader.read_exact(&mut magic)?;
+        }
+        if &magic[..3] != b"zPQ" || !(1..=2).contains(&magic[3]) || read_byte(reader)? != 1 {
+            return Err(invalid_data("invalid ZPAQ block header".to_string()));
+        }
+
+        let mut header = [0; 7];
+        reader.read_exact(&mut header)?;
+        let size = u16::from_le_bytes([header[0], header[1]]) as usize;
+        let [h_bits, m_bits, ph_bits, pm_bits, count] = [2, 3, 4, 5, 6].map(|i| header[i]);
+
+        let mut specs = Vec::with_capacity(count as usize);
+        let mut specs_len = 0;
+        for _ in 0..count {
+            let mut spec = [0; 6];
+            spec[0] = read_byte(reader)?;
+            let len = COMPONENT_SIZES.get(spec[0] as usize).copied().unwrap_or(0);
+            if len == 0 {
+                return Err(invalid_data(format!("unknown ZPAQ component {}", spec[0])));
+            }
+            reader.read_exact(&mut spec[1..len])?;
+            specs_len += len;
+            specs.push(spec);
+        }
+        let code_len = size
+            .checked_sub(7 + specs_len)
+            .ok_or_else(|| invalid_data("invalid ZPAQ header size".to_string()))?;
+        let mut code = vec![0; code_len + 2];
+        reader.read_exact(&mut code)?;
+        // the components and HCOMP both end with a 0
+        if code[0] != 0 || code[code_len + 1] != 0 {
+            return Err(invalid_data("invalid ZPAQ block header".to_string()));
+        }
+        code.truncate(code_len + 1);
+        code.remove(0);
+
+        let predictor = match count {
+            0 => None,
+            _ => Some(Predictor::new(specs, Zpaql::new(code, h_bits, m_bits)?)?),
+        };
+        Ok(Some(Self {
+            predictor,
+            post: PostProcessor::new(ph_bits, pm_bits),
+        }))
+    }
+
+    /// Reads the name of the next segment, or `None` at the end of the block.
+    fn next_segment<R: Read>(&mut self, reader: &mut R) -> Result<Option<String>, ArchiveError> {
+        match read_byte(reader)? {
+            1 => {
+                let name = read_string(reader)?;
+                // the comment, holding the size of the segment, and a reserved byte
+                read_string(reader)?;
+                read_byte(reader)?;
+                Ok(Some(name))
+            }
+            255 => Ok(None),
+            b => Err(invalid_data(format!("invalid ZPAQ segment marker {}", b))),
+        }
+    }
+
+    /// Decodes the data of the current segment, checking its SHA-1 when stored.
+    fn segment_data<R: Read>(&mut self, reader: &mut R) -> Result<Vec<u8>, ArchiveError> {
+        match &mut self.predictor {
+            Some(predictor) => {
+                let mut decoder = BitDecoder::new(reader)?;
+                // each byte is preceded by a bit that is a one at the end of the segment
+                while decoder.decode(reader, 0)? == 0 {
+                    let mut c = 1;
+                    while c < 256 {
+                        let p = predictor.predict() * 2 + 1;
+                        let y = decoder.decode(reader, p)?;
+                        c = c * 2 + y;
+                        predictor.update(y)?;
+                    }
+                    self.post.write(Some((c - 256) as u8))?;
+                }
+                if decoder.current != 0 {
+                    return Err(invalid_data("corrupted end of ZPAQ segment".to_string()));
+                }
+            }
+            // stored in chunks preceded by their size, up to an empty one
+            None => loop {
+                let mut size = [0; 4];
+                reader.read_exact(&mut size)?;
+                let mut chunk = vec![0; u32::from_be_bytes(size) as usize];
+                if chunk.is_empty() {
+                    break;
+                }
+                reader.read_exact(&mut chunk)?;
+                for b in chunk {
+                    self.post.write(Some(b))?;
+                }
+            },
+        }
+        self.post.write(None)?;
+        let data = self.post.take_output();
+
+        match read_byte(reader)? {
+            253 => {
+                let mut sha1 = [0; 20];
+                reader.read_exact(&mut sha1)?;
+                if Sha1::digest(&data)[..] != sha1 {
+                    return Err(invalid_data("SHA-1 mismatch of a ZPAQ segment".to_string()));
+                }
+            }
+            254 => {}
+            b => return Err(invalid_data(format!("invalid ZPAQ segment end {}", b))),
+        }
+        Ok(data)
+    }
+}
+
+/// A file of a version, made of fragments.
+#[derive(Debug)]
+struct JournalFile {
+    date: u64,
+    mode: Option<u32>,
+    fragments: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Fragment {
+    /// Index of the block holding it, then of the segment within that block.
+    block: usize,
+    segment: usize,
+    start: usize,
+    size: usize,
+    sha1: Option<[u8; 20]>,
+}
+
+struct Version {
+    date: Option<chrono::DateTime<chrono::FixedOffset>>,
+    /// The files as they were once the version was added.
+    files: BTreeMap<String, Rc<JournalFile>>,
+}
+
+/// The versions of an archive and where the fragments of their files lie.
+#[derive(Default)]
+struct Journal {
+    versions: Vec<Version>,
+    /// Offsets of the blocks holding fragments.
+    blocks: Vec<u64>,
+    /// Indexed by fragment id, starting at 1.
+    fragments: Vec<Option<Fragment>>,
+}
+
+impl Journal {
+    fn read<R: Read + Seek>(reader: &mut R) -> Result<Self, ArchiveError> {
+        reader.seek(SeekFrom::Start(0))?;
+        let mut journal = Journal::default();
+        let mut streamed = Version {
+            date: None,
+            files: BTreeMap::new(),
+        };
+        let mut last_streamed = None;
+        let mut version: Option<Version> = None;
+        // offset of the next d block of the version
+        let mut data_offset = 0;
+
+        'blocks: loop {
+            let offset = reader.stream_position()?;
+            let Some(mut block) = Block::read(reader)? else {
+                break;
+            };
+            let mut segment = 0;
+            while let Some(name) = block.next_segment(reader)? {
+                let data = block.segment_data(reader)?;
+                match journal_name(&name) {
+                    Some((date, 'c', _)) => {
+                        let size = data
+                            .get(..8)
+                            .map(|s| i64::from_le_bytes(s.try_into().unwrap_or_default()))
+                            .ok_or_else(|| invalid_data("invalid ZPAQ version".to_string()))?;
+                        // an update that was interrupted
+                        if size < 0 {
+                            break 'blocks;
+                        }
+                        let files = match version.take() {
+                            Some(previous) => {
+                                let files = previous.files.clone();
+                                journal.versions.push(previous);
+                                files
+                            }
+                            None => BTreeMap::new(),
+                        };
+                        version = Some(Version { date, files });
+                        // skip to the h and i blocks, past the d blocks
+                        while block.next_segment(reader)?.is_some() {
+                            block.segment_data(reader)?;
+                        }
+                        data_offset = reader.stream_position()?;
+                        reader.seek(SeekFrom::Start(data_offset + size as u64))?;
+                        continue 'blocks;
+                    }
+                    Some((_, 'h', first)) => {
+                        let size = u32_at(&data, 0)
+                            .ok_or_else(|| invalid_data("invalid ZPAQ h block".to_string()))?;
+                        let block = journal.blocks.len();
+                        journal.blocks.push(data_offset);
+                        data_offset += size as u64;
+                        let mut start = 0;
+                        for (i, entry) in data[4..].chunks_exact(24).enumerate() {
+                            let size = u32_at(entry, 20).unwrap_or_default() as usize;
+                            journal.insert_fragment(
+                                first as usize + i,
+                                Fragment {
+                                    block,
+                                    segment: 0,
+                                    start,
+                                    size,
+                                    sha1: entry[..20].try_into().ok(),
+                                },
+                            );
+                            start += size;
+                        }
+                    }
+                    Some((_, 'i', _)) => {
+                        let version = version.get_or_insert_with(|| Version {
+                            date: None,
+                            files: BTreeMap::new(),
+                        });
+                        read_index(&data, &mut version.files)?;
+                    }
+                    Some(_) => {}
+                    // a file of a streaming archive, continued by segments without a name
+                    None => {
+                        if segment == 0 {
+                            journal.blocks.push(offset);
+                        }
+                        let id = journal.fragments.len().max(1);
+                        journal.insert_fragment(
+                            id,
+                            Fragment {
+                                block: journal.blocks.len() - 1,
+                                segment,
+                                start: 0,
+                                size: data.len(),
+                                sha1: None,
+                            },
+                        );
+                        let name = match name.is_empty() {
+                            true => last_streamed.clone().unwrap_or_default(),
+                            false => name.trim_start_matches('/').to_string(),
+                        };
+                        let file = streamed.files.entry(name.clone()).or_insert_with(|| {
+                            Rc::new(JournalFile {
+                                date: 0,
+                                mode: None,
+                                fragments: Vec::new(),
+                            })
+                        });
+                        if let Some(file) = Rc::get_mut(file) {
+                            file.fragments.push(id as u32);
+                        }
+                        last_streamed = Some(name);
+                    }
+                }
+                segment += 1;
+            }
+        }
+
+        if !streamed.files.is_empty() {
+            journal.versions.insert(0, streamed);
+        }
+        journal.versions.extend(version);
+        Ok(journal)
+    }
+
+    fn insert_fragment(&mut self, id: usize, fragment: Fragment) {
+        if self.fragments.len() <= id {
+            self.fragments.resize(id + 1, None);
+        }
+        self.fragments[id] = Some(fragment);
+    }
+
+    fn fragment(&self, id: u32) -> Result<Fragment, ArchiveError> {
+        self.fragments
+            .get(id as usize)
+            .copied()
+            .flatten()
+            .ok_or_else(|| invalid_data(format!("missing ZPAQ fragment {}", id)))
+    }
+
+    fn size(&self, file: &JournalFile) -> Result<u64, ArchiveError> {
+        file.fragments
+            .iter()
+            .map(|id| Ok(self.fragment(*id)?.size as u64))
+            .sum()
+    }
+}
+
+/// Date, type and number of the name of a block of a journaling archive, such as
+/// `jDC20240504092400c0000000001`.
+fn journal_name(name: &str) -> Option<(Option<chrono::DateTime<chrono::FixedOffset>>, char, u32)> {
+    let rest = name.strip_prefix("jDC")?;
+    if rest.len() != 25 || !rest.is_ascii() {
+        return None;
+    }
+    let date = rest[..14].parse::<u64>().ok()?;
+    let kind = rest[14..15].chars().next()?;
+    Some((decimal_date(date), kind, rest[15..].parse().ok()?))
+}
+
+/// Applies the updates of an i block: files with their date, attributes and fragments, or
+/// deleted when their date is 0.
+fn read_index(
+    data: &[u8],
+    files: &mut BTreeMap<String, Rc<JournalFile>>,
+) -> Result<(), ArchiveError> {
+    let invalid = || invalid_data("invalid ZPAQ index".to_string());
+    let mut at = 0;
+    while at < data.len() {
+        let date = u64::from_le_bytes(
+            data.get(at..at + 8)
+                .ok_or_else(invalid)?
+                .try_into()
+                .map_err(|_| invalid())?,
+        );
+        at += 8;
+        let len = data[at..]
+            .iter()
+            .position(|b| *b == 0)
+            .ok_or_else(invalid)?;
+        let name = String::from_utf8_lossy(&data[at..at + len])
+            .trim_start_matches('/')
+            .to_string();
+        at += len + 1;
+        if date == 0 {
+            files.remove(&name);
+            continue;
+        }
+
+        let attr_len = u32_at(data, at).ok_or_else(invalid)? as usize;
+        let attr = data.get(at + 4..at + 4 + attr_len).ok_or_else(invalid)?;
+        at += 4 + attr_len;
+        // 'u' and the low bits of the Unix mode, or 'w' and the Windows attributes
+        let mode = match attr {
+            [b'u', low, high, ..] => Some(u16::from_le_bytes([*low, *high]) as u32),
+            _ => None,
+        };
+        let count = u32_at(data, at).ok_or_else(invalid)? as usize;
+        at += 4;
+        let fragments = (0..count)
+            .map(|i| u32_at(data, at + 4 * i).ok_or_else(invalid))
+            .collect::<Result<Vec<_>, _>>()?;
+        at += 4 * count;
+        files.insert(
+            name,
+            Rc::new(JournalFile {
+                date,
+                mode,
+                fragments,
+            }),
+        );
+    }
+    Ok(())
+}
+
+/// The segments of the last block fragments were read from.
+#[derive(Default)]
+struct BlockCache {
+    block: Option<usize>,
+    segments: Vec<Vec<u8>>,
+}
+
+impl BlockCache {
+    fn fragment<R: Read + Seek>(
+        &mut self,
+        journal: &Journal,
+        reader: &mut R,
+        id: u32,
+    ) -> Result<Vec<u8>, ArchiveError> {
+        let fragment = journal.fragment(id)?;
+        if self.block != Some(fragment.block) {
+            self.block = None;
+            self.segments.clear();
+            reader.seek(SeekFrom::Start(journal.blocks[fragment.block]))?;
+            let mut block = Block::read(reader)?
+                .ok_or_else(|| invalid_data("missing ZPAQ block".to_string()))?;
+            while block.next_segment(reader)?.is_some() {
+                self.segments.push(block.segment_data(reader)?);
+            }
+            self.block = Some(fragment.block);
+        }
+
+        let data = self
+            .segments
+            .get(fragment.segment)
+            .and_then(|s| s.get(fragment.start..fragment.start + fragment.size))
+            .ok_or_else(|| invalid_data(format!("invalid ZPAQ fragment {}", id)))?;
+        if fragment
+            .sha1
+            .is_some_and(|sha1| Sha1::digest(data)[..] != sha1)
+        {
+            return Err(invalid_data(format!(
+                "SHA-1 mismatch of ZPAQ fragment {}",
+                id
+            )));
+        }
+        Ok(data.to_vec())
+    }
+}
+
+/// Reads the content of a file, fragment by fragment.
+struct ContentReader<'c, R> {
+    journal: &'c Journal,
+    cache: &'c mut BlockCache,
+    reader: &'c mut R,
+    fragments: std::slice::Iter<'c, u32>,
+    current: Vec<u8>,
+    position: usize,
+}
+
+impl<R: Read + Seek> Read for ContentReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.position == self.current.len() {
+            let Some(id) = self.fragments.next() else {
+                return Ok(0);
+            };
+            self.current = self
+                .cache
+                .fragment(self.journal, self.reader, *id)
+                .map_err(io_error)?;
+            self.position = 0;
+        }
+        let n = buf.len().min(self.current.len() - self.position);
+        buf[..n].copy_from_slice(&self.current[self.position..self.position + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+pub struct ZpaqArchive {
+    pub(crate) source: DataSource,
+    scan: OnceCell<ArchiveScan>,
+}
+
+impl ZpaqArchive {
+    fn scan(&self) -> Result<&ArchiveScan, ArchiveError> {
+        ArchiveScan::cached(&self.scan, || scan_entries(self, None, None))
+    }
+}
+
+impl EntryVisitor for ZpaqArchive {
+    fn for_each_entry<F>(
+        &self,
+        options: &VisitOptions<'_>,
+        mut visit: F,
+    ) -> Result<(), ArchiveError>
+    where
+        F: FnMut(VisitedEntry, Option<&mut dyn Read>) -> Result<ControlFlow<()>, ArchiveError>,
+    {
+        let source = options
+            .retry
+            .wrap(self.source.clone(), &self.source, options.events);
+        let mut reader = BufReader::with_capacity(DEFAULT_BUF_SIZE, source);
+        let journal = Journal::read(&mut reader)?;
+        let single = journal.versions.len() == 1;
+        let mut cache = BlockCache::default();
+
+        for (index, version) in journal.versions.iter().enumerate() {
+            let root = match single {
+                true => String::new(),
+                false => format!("{}/", index + 1),
+            };
+            if !single {
+                let visited = VisitedEntry::new(ArchiveFileEntity {
+                    name: root.clone(),
+                    size: None,
+                    compressed_size: None,
+                    last_modified: version.date,
+                    compression: None,
+                    fstype: ArchiveFileEntityType::Directory,
+                    raw: None,
+                    unix: None,
+                });
+                if options.wants(&visited.entity) && visit(visited, None)?.is_break() {
+                    return Ok(());
+                }
+            }
+
+            for (name, file) in &version.files {
+                let directory = name.ends_with('/');
+                let visited = VisitedEntry {
+                    entity: ArchiveFileEntity {
+                        name: format!("{}{}", root, name),
+                        size: (!directory).then(|| journal.size(file)).transpose()?,
+                        compressed_size: None,
+                        last_modified: decimal_date(file.date),
+                        compression: None,
+                        fstype: match directory {
+                            true => ArchiveFileEntityType::Directory,
+                            false => ArchiveFileEntityType::File,
+                        },
+                        raw: None,
+                        unix: None,
+                    },
+                    mode: file.mode.map(|mode| mode & 0o7777),
+                    link: None,
+                    crc32: None,
+                };
+                if !options.wants(&visited.entity) {
+                    continue;
+                }
+
+                let flow = if options.contents && !directory {
+                    let mut content = ContentReader {
+                        journal: &journal,
+                        cache: &mut cache,
+                        reader: &mut reader,
+                        fragments: file.fragments.iter(),
+                        current: Vec::new(),
+                        position: 0,
+                    };
+                    visit(visited, Some(&mut content))?
+                } else {
+                    visit(visited, None)?
+                };
+                if flow.is_break() {
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Archived for ZpaqArchive {
+    fn of(source: DataSource) -> Result<Self, ArchiveError>
+    where
+        Self: Sized,
+    {
+        Ok(Self {
+            source,
+            scan: OnceCell::new(),
+        })
+    }
+
+    fn extract(&self, options: ExtractOptions) -> Result<ExtractReport, ArchiveError> {
+        extract_entries(self, &options, self.source.as_ref())
+    }
+
+    fn list(&self, options: ListOptions) -> Result<Vec<ArchiveFileEntity>, ArchiveError> {
+        if options.is_whole() {
+            return Ok(self.scan()?.entries.clone());
+        }
+        list_entries(self, self.scan.get(), &options)
+    }
+
+    fn create(_options: CreateOptions) -> Result<CreateResult, ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "create".to_string(),
+            ArchiveType::Zpaq,
+        ))
+    }
+
+    fn metadata(&self) -> Result<ArchiveMetadata, ArchiveError> {
+        let mut source = BufReader::with_capacity(DEFAULT_BUF_SIZE, self.source.clone());
+        let journal = Journal::read(&mut source)?;
+        let versions = journal
+            .versions
+            .iter()
+            .enumerate()
+            .map(|(index, version)| {
+                let total_bytes = version
+                    .files
+                    .values()
+                    .map(|file| journal.size(file))
+                    .sum::<Result<u64, _>>()?;
+                Ok(json!({
+                    "index": index + 1,
+                    "date": version.date,
+                    "file_count": version.files.keys().filter(|n| !n.ends_with('/')).count(),
+                    "total_bytes": total_bytes,
+                }))
+            })
+            .collect::<Result<Vec<_>, ArchiveError>>()?;
+        let additional = json!({
+            "version_count": journal.versions.len(),
+            "fragment_count": journal.fragments.iter().flatten().count(),
+            "versions": versions,
+        });
+        let size = source.seek(SeekFrom::End(0))?;
+
+        let scan = self.scan()?;
+
+        Ok(ArchiveMetadata {
+            archive_type: ArchiveType::Zpaq,
+            entry_count: scan.entries.len(),
+            entries: scan.entries.clone(),
+            total_size: scan.total_size,
+            compressed_size: size,
+            compression: None,
+            created: self.source.created(),
+            modified: self.source.modified(),
+            additional: Some(additional),
+        })
+    }
+
+    fn open(&self, options: OpenOptions) -> Result<(), ArchiveError> {
+        open_entry(self, options)
+    }
+
+    fn find_first<P>(
+        &self,
+        predicate: P,
+    ) -> Result<Option<(ArchiveFileEntity, Vec<u8>)>, ArchiveError>
+    where
+        P: Fn(&ArchiveFileEntity) -> bool,
+    {
+        find_first_entry(self, predicate)
+    }
+
+    fn check_password(&self, _password: &str) -> Result<bool, ArchiveError> {
+        // encrypted archives are not supported, their blocks cannot be read at all
+        Ok(true)
+    }
+
+    fn edit(&self, _entry: &str, _edit: &EntryEdit) -> Result<(), ArchiveError> {
+        Err(ArchiveError::UnsupportedActionForArchiveType(
+            "edit".to_string(),
+            ArchiveType::Zpaq,
+        ))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::archive::Archive;
+
+    #[test]
+    fn builds_the_tables_of_libzpaq() {
+        // checksums libzpaq verifies its tables against
+        let stretch = (0..32768).rev().fold(0u32, |sum, i| {
+            sum.wrapping_mul(3).wrapping_add(stretch(i) as u32)
+        });
+        let squash = (0..4096).rev().fold(0u32, |sum, i| {
+            sum.wrapping_mul(3).wrapping_add(squash(i - 2048) as u32)
+        });
+        assert_eq!(stretch, 3887533746);
+        assert_eq!(squash, 2278286169);
+        assert_eq!(tables().next[0], [1, 2]);
+    }
+
+    #[test]
+    fn reads_zpaq_versions() {
+        let archive = Archive::of(DataSource::file("tests/fixtures/test1.zpaq").unwrap()).unwrap();
+        assert!(matches!(archive, Archive::Zpaq(_)));
+
+        let entries = archive.list(ListOptions::default()).unwrap();
+        let names = entries.iter().map(|e| e.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "1/",
+                "1/test1/",
+                "1/test1/dir1/",
+                "1/test1/dir1/file2.txt",
+                "1/test1/file1.txt",
+                "2/",
+                "2/readme.txt",
+                "2/test1/",
+                "2/test1/dir1/",
+                "2/test1/file1.txt",
+            ]
+        );
+        assert_eq!(
+            entries[5].last_modified.unwrap().to_rfc3339(),
+            "2024-06-01T12:00:00+00:00"
+        );
+
+        let file1 = std::fs::read("tests/fixtures/test1/file1.txt").unwrap();
+        for (name, expected) in [
+            ("1/test1/file1.txt", file1.clone()),
+            (
+                "1/test1/dir1/file2.txt",
+                std::fs::read("tests/fixtures/test1/dir1/file2.txt").unwrap(),
+            ),
+            ("2/test1/file1.txt", file1),
+            ("2/readme.txt", b"stored as is\n".to_vec()),
+        ] {
+            let (_, content) = archive.find_first(|e| e.name == name).unwrap().unwrap();
+            assert_eq!(content, expected, "{}", name);
+        }
+    }
+}
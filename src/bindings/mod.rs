@@ -0,0 +1,397 @@
+//! Foreign-language interface of hezi's archive engine, generated with [uniffi](https://mozilla.github.io/uniffi-rs/).
+//!
+//! Build the library as a `cdylib` (or `staticlib` for iOS) with the `bindings` feature, then
+//! generate the Kotlin or Swift sources from it with the bundled `uniffi-bindgen` binary.
+
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use crate::archive::{
+    guess::ArchiveTypeExt, password::StaticPassword, retry::IoRetryPolicy, Archive,
+    ArchiveCompression, ArchiveError, ArchiveEvent, ArchiveFileEntity, ArchiveType, Archived,
+    CompressionSpec, CreateOptions, DataSource, EventHandler, ExtractLimits, ExtractOptions,
+    ExtractReport, ListOptions, OpenOptions, OpenOutput,
+};
+use strum::IntoEnumIterator;
+
+#[derive(Debug, uniffi::Error)]
+#[uniffi(flat_error)]
+pub enum HeziError {
+    Archive(String),
+    InvalidArgument(String),
+    /// The archive is encrypted and no password was given, ask for one and try again.
+    PasswordRequired,
+    /// The password given does not decrypt the archive, ask for another one and try again.
+    WrongPassword,
+}
+
+impl std::fmt::Display for HeziError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeziError::Archive(e) => write!(f, "{}", e),
+            HeziError::InvalidArgument(e) => write!(f, "Invalid argument: {}", e),
+            HeziError::PasswordRequired => write!(f, "{}", ArchiveError::PasswordRequired),
+            HeziError::WrongPassword => write!(f, "{}", ArchiveError::WrongPassword),
+        }
+    }
+}
+
+impl std::error::Error for HeziError {}
+
+impl From<ArchiveError> for HeziError {
+    fn from(e: ArchiveError) -> Self {
+        match e {
+            ArchiveError::PasswordRequired => HeziError::PasswordRequired,
+            ArchiveError::WrongPassword => HeziError::WrongPassword,
+            e => HeziError::Archive(e.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for HeziError {
+    fn from(e: std::io::Error) -> Self {
+        HeziError::Archive(e.to_string())
+    }
+}
+
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct Entry {
+    pub name: String,
+    pub size: Option<u64>,
+    pub compressed_size: Option<u64>,
+    /// One of `file`, `dir`, `symlink` or `unknown`.
+    pub kind: String,
+    /// Seconds since the unix epoch.
+    pub last_modified: Option<i64>,
+    pub compression: Option<String>,
+}
+
+/// Outcome of [`extract`].
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct ExtractSummary {
+    /// Paths written to the destination.
+    pub extracted: Vec<String>,
+    /// Entries not written, with the reason, such as `already_exists`.
+    pub skipped: Vec<EntryOutcome>,
+    /// Entries that could not be read, with the error.
+    pub failed: Vec<EntryOutcome>,
+    pub duration_ms: u64,
+}
+
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct EntryOutcome {
+    pub name: String,
+    pub detail: String,
+}
+
+impl From<ExtractReport> for ExtractSummary {
+    fn from(report: ExtractReport) -> Self {
+        let outcome = |(name, detail): (String, String)| EntryOutcome { name, detail };
+        ExtractSummary {
+            extracted: report
+                .extracted
+                .iter()
+                .map(|p| p.to_string_lossy().to_string())
+                .collect(),
+            skipped: report
+                .skipped
+                .into_iter()
+                .map(|(name, reason)| outcome((name, reason.name().to_string())))
+                .collect(),
+            failed: report.failed.into_iter().map(outcome).collect(),
+            duration_ms: report.duration.as_millis() as u64,
+        }
+    }
+}
+
+impl From<&ArchiveFileEntity> for Entry {
+    fn from(e: &ArchiveFileEntity) -> Self {
+        Entry {
+            name: e.name.clone(),
+            size: e.size,
+            compressed_size: e.compressed_size,
+            kind: e.fstype.to_string(),
+            last_modified: e.last_modified.map(|t| t.timestamp()),
+            compression: e.compression.clone(),
+        }
+    }
+}
+
+/// Format of an archive told by its name, see [`guess_format`].
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct Format {
+    pub archive_type: String,
+    pub compression: Option<String>,
+    /// One of `low`, `medium` or `high`.
+    pub confidence: String,
+}
+
+#[derive(Debug, Clone, uniffi::Enum)]
+pub enum Event {
+    Extracting {
+        name: String,
+        size: Option<u64>,
+    },
+    EntryProgress {
+        name: String,
+        copied: u64,
+        size: Option<u64>,
+    },
+    DoneExtracting {
+        source: String,
+        destination: String,
+    },
+    FailedToReadEntry {
+        name: String,
+        error: String,
+    },
+    Created {
+        name: String,
+        kind: String,
+    },
+    Skipped {
+        name: String,
+        reason: String,
+    },
+    Added {
+        name: String,
+        size: Option<u64>,
+    },
+    DoneCreating {
+        destination: String,
+    },
+    Retrying {
+        name: String,
+        attempt: u32,
+        error: String,
+    },
+    Log {
+        message: String,
+    },
+}
+
+impl From<ArchiveEvent> for Event {
+    fn from(event: ArchiveEvent) -> Self {
+        match event {
+            ArchiveEvent::Extracting(name, size) => Event::Extracting { name, size },
+            ArchiveEvent::EntryProgress(name, copied, size) => {
+                Event::EntryProgress { name, copied, size }
+            }
+            ArchiveEvent::DoneExtracting(source, destination) => Event::DoneExtracting {
+                source,
+                destination,
+            },
+            ArchiveEvent::FailedToReadEntry(name, error) => Event::FailedToReadEntry {
+                name,
+                error: error.to_string(),
+            },
+            ArchiveEvent::Created(name, kind) => Event::Created {
+                name,
+                kind: kind.to_string(),
+            },
+            ArchiveEvent::Skipped(name, reason) => Event::Skipped {
+                name,
+                reason: reason.name().to_string(),
+            },
+            ArchiveEvent::Added(name, size) => Event::Added { name, size },
+            ArchiveEvent::DoneCreating(destination) => Event::DoneCreating { destination },
+            ArchiveEvent::Retrying(name, attempt, error) => Event::Retrying {
+                name,
+                attempt,
+                error: error.to_string(),
+            },
+            ArchiveEvent::Log(message) => Event::Log { message },
+        }
+    }
+}
+
+/// Receives the progress of long running operations, implemented by the host application.
+#[uniffi::export(callback_interface)]
+pub trait EventListener: Send + Sync {
+    fn on_event(&self, event: Event);
+}
+
+struct Listener(Option<Box<dyn EventListener>>);
+
+impl EventHandler for Listener {
+    fn handle(&self, event: ArchiveEvent) {
+        if let Some(listener) = &self.0 {
+            listener.on_event(event.into());
+        }
+    }
+}
+
+fn listener(listener: Option<Box<dyn EventListener>>) -> Box<Listener> {
+    Box::new(Listener(listener))
+}
+
+fn open_archive(path: &str) -> Result<Archive, HeziError> {
+    Ok(Archive::of(DataSource::file(path)?)?)
+}
+
+/// Guesses the format of the archive named `name`, a path or a url, to pick an icon or the
+/// action offered before opening it.
+#[uniffi::export]
+pub fn guess_format(name: String) -> Result<Format, HeziError> {
+    let guessed = name.guess_format()?;
+    Ok(Format {
+        archive_type: guessed.archive.to_string(),
+        compression: guessed
+            .compression
+            .filter(|c| *c != ArchiveCompression::None)
+            .map(|c| c.to_string()),
+        confidence: guessed.confidence.to_string(),
+    })
+}
+
+#[uniffi::export]
+pub fn list(path: String, password: Option<String>) -> Result<Vec<Entry>, HeziError> {
+    let entries = open_archive(&path)?.list(ListOptions {
+        password: StaticPassword::boxed(password),
+        ..Default::default()
+    })?;
+    Ok(entries.iter().map(Entry::from).collect())
+}
+
+#[uniffi::export]
+pub fn extract(
+    path: String,
+    destination: String,
+    password: Option<String>,
+    files: Option<Vec<String>>,
+    overwrite: bool,
+    listener: Option<Box<dyn EventListener>>,
+) -> Result<ExtractSummary, HeziError> {
+    let report = open_archive(&path)?.extract(ExtractOptions {
+        destination: PathBuf::from(destination),
+        password: StaticPassword::boxed(password),
+        files,
+        overwrite,
+        update: false,
+        file_mode: None,
+        dir_mode: None,
+        preserve_permissions: false,
+        show_hidden: true,
+        retry: IoRetryPolicy::default(),
+        transformers: vec![],
+        inspector: None,
+        adaptive: false,
+        cache: None,
+        progress_interval: None,
+        limits: ExtractLimits::default(),
+        event_handler: self::listener(listener),
+    })?;
+    Ok(ExtractSummary::from(report))
+}
+
+/// Reads the content of a single entry.
+#[uniffi::export]
+pub fn read_entry(
+    path: String,
+    entry: String,
+    password: Option<String>,
+) -> Result<Vec<u8>, HeziError> {
+    let buffer = SharedBuffer::default();
+    open_archive(&path)?.open(OpenOptions {
+        paths: vec![PathBuf::from(entry)],
+        password: StaticPassword::boxed(password),
+        dest: Box::new(buffer.clone()),
+        output: OpenOutput::default(),
+    })?;
+
+    let content = buffer
+        .0
+        .lock()
+        .map_err(|e| HeziError::Archive(e.to_string()))?
+        .clone();
+    Ok(content)
+}
+
+/// Creates the archive `destination` from `files`, stored relative to `source`. The archive
+/// type, and compression unless given, are inferred from the extension of `destination`.
+#[uniffi::export]
+pub fn create(
+    destination: String,
+    source: String,
+    files: Vec<String>,
+    compression: Option<String>,
+    password: Option<String>,
+    overwrite: bool,
+    listener: Option<Box<dyn EventListener>>,
+) -> Result<(), HeziError> {
+    let destination = PathBuf::from(destination);
+    let (archive_type, guessed_compression) = ArchiveType::guess_from_filename(&destination)?;
+    let archive_compression = match compression {
+        Some(c) => Some(
+            ArchiveCompression::iter()
+                .find(|known| known.to_string().eq_ignore_ascii_case(&c))
+                .ok_or_else(|| HeziError::InvalidArgument(format!("unknown compression {}", c)))?,
+        ),
+        None => guessed_compression,
+    };
+
+    Archive::create(CreateOptions {
+        destination,
+        password,
+        files: files.into_iter().map(PathBuf::from).collect(),
+        overwrite,
+        source: PathBuf::from(source),
+        roots: vec![],
+        archive_type,
+        compression: archive_compression.map(CompressionSpec::new),
+        include_hidden: true,
+        skip_unreadable: false,
+        verify_after: false,
+        volume_size: None,
+        update: false,
+        sevenz: Default::default(),
+        zip: Default::default(),
+        tar: Default::default(),
+        event_handler: self::listener(listener),
+    })?;
+    Ok(())
+}
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .lock()
+            .map_err(|e| std::io::Error::other(e.to_string()))?
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "tar_archive"))]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_and_read_entry() {
+        let entries = list("tests/fixtures/test1.tar".to_string(), None).unwrap();
+        assert!(entries
+            .iter()
+            .any(|e| e.name == "test1/dir1/file2.txt" && e.kind == "file"));
+
+        let content = read_entry(
+            "tests/fixtures/test1.tar".to_string(),
+            "test1/dir1/file2.txt".to_string(),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            content,
+            std::fs::read("tests/fixtures/test1/dir1/file2.txt").unwrap()
+        );
+    }
+}
@@ -1,20 +1,60 @@
 #![deny(clippy::unwrap_used)]
 mod nu;
 mod styling;
+#[cfg(feature = "watch")]
+mod watch;
 
 use std::env;
-use std::{io::Error, path::PathBuf};
+use std::num::NonZeroUsize;
+use std::time::Duration;
+use std::{
+    io::{Error, ErrorKind, IsTerminal, Write},
+    path::{Path, PathBuf},
+};
 
+use chrono::{DateTime, FixedOffset, Local};
 use clap::Parser;
 
 /// Search for a pattern in a file and display the lines that contain it.
+use byte_unit::{Byte, UnitType};
 use clap::{Args, Subcommand, ValueEnum};
+#[cfg(feature = "compressed_file")]
+use hezi::archive::compressed_file::create_compressed_stream;
+#[cfg(feature = "tar_archive")]
+use hezi::archive::tar_archive::create_tar_stream;
+#[cfg(feature = "zip_archive")]
+use hezi::archive::zip_stream::create_zip_stream;
 use hezi::archive::{
-    Archive, ArchiveCompression, ArchiveError, ArchiveType, Archived, CreateOptions, DataSource,
-    ExtractOptions, ListOptions, SimpleLogger,
+    convert::ConvertOptions,
+    date_format::DateFormat,
+    diff::{DiffKind, DiffOptions},
+    eol::LineEnding,
+    extract_cache::ExtractCache,
+    identify::{identify, Confidence, Identification},
+    incremental::{apply_removals, SnapshotState},
+    integrity::VerifyOptions,
+    manifest::Manifest,
+    mirror::{extraneous_files, remove_extraneous},
+    name_template::{expand_name_template, rotate_snapshots},
+    owner::Owner,
+    password::{
+        find_password, password_provider, read_password_list, PasswordAttemptLimits,
+        PasswordProvider, PromptPassword, StaticPassword,
+    },
+    peek::{clean_peeks, peek},
+    progress::{open_progress_fd, ProgressWriter},
+    retry::IoRetryPolicy,
+    rollup::roll_up_sizes,
+    sample::{sample_entries, SampleOptions},
+    walk_paths, AppendOptions, Archive, ArchiveCompression, ArchiveError, ArchiveEvent,
+    ArchiveFileEntity, ArchiveType, Archived, CodecParams, CompressionSpec, CreateOptions,
+    CreateProfile, CreateResult, DataSource, EntryTransformer, EventHandler, ExtractLimits,
+    ExtractOptions, ExtractReport, GzipStrategy, Lengthed, ListOptions, OpenOptions, OpenOutput,
+    SevenZContentMethod, SevenZCreateOptions, SimpleLogger, SourceRoot, TarCreateOptions,
+    TarFormat, Zip64Mode, ZipUnicodeNames,
 };
+use hezi::output::{self, Versioned};
 use nu::NuSetup;
-use rayon::iter::{ParallelBridge, ParallelIterator};
 
 use nu_protocol::Span;
 use styling::{get_default_color, get_styles};
@@ -34,24 +74,55 @@ enum Command {
     /// List the contents of an archive
     #[clap(alias = "l")]
     List {
-        /// Path to the archive to list
+        /// Path to the archive to list, `-` to read it from stdin
         path: String,
 
+        /// Only list the entries under this directory of the archive
+        prefix: Option<String>,
+
         /// Detailed output
         #[clap(short, long)]
         long: bool,
 
+        /// Only print the entry names, each followed by a NUL byte, e.g. for `xargs -0`
+        #[clap(short = '0', long, conflicts_with = "long")]
+        null: bool,
+
+        /// How to render modification dates: `iso`, `relative` or `custom:FORMAT` with a strftime
+        /// format such as `custom:%Y-%m-%d`
+        #[clap(long, conflicts_with = "null")]
+        date_format: Option<DateFormat>,
+
+        /// Show the total size of the entries beneath each directory as its size
+        #[clap(long, conflicts_with = "null")]
+        rollup: bool,
+
+        /// Only list this many entries, reading the archive no further than the last one
+        #[clap(long)]
+        limit: Option<usize>,
+
+        /// Number of entries to leave out before the first one listed
+        #[clap(long, default_value_t = 0)]
+        offset: usize,
+
         /// Password of the archive
         #[clap(short, long)]
         password: Option<String>,
+
+        #[clap(flatten)]
+        password_list: PasswordListArgs,
+
+        #[cfg(feature = "keyring")]
+        #[clap(flatten)]
+        keyring: KeyringArgs,
     },
     /// Create an archive
     #[clap(alias = "c")]
-    Create(CreateArgs),
+    Create(Box<CreateArgs>),
     /// Extract an archive
     #[clap(alias = "x")]
     Extract {
-        /// The path of the archive to extract
+        /// The path of the archive to extract, `-` to read it from stdin
         path: String,
 
         /// The path to write to
@@ -65,19 +136,393 @@ enum Command {
         /// A password to use
         #[clap(short, long)]
         password: Option<String>,
+
+        /// Retry reads and writes failing with a transient error up to this many times
+        #[clap(long, default_value_t = 0)]
+        retries: u32,
+
+        /// Convert the line endings of text files
+        #[clap(long, value_enum)]
+        eol: Option<LineEnding>,
+
+        /// Only replace existing files with entries modified more recently
+        #[clap(short, long)]
+        update: bool,
+
+        /// Permissions of the extracted files, in octal, instead of those of their entries
+        #[clap(long, value_name = "MODE", value_parser = parse_mode)]
+        mode: Option<u32>,
+
+        /// Permissions of the extracted directories, in octal, instead of those of their entries
+        #[clap(long, value_name = "MODE", value_parser = parse_mode)]
+        dir_mode: Option<u32>,
+
+        /// Keep the permissions of the entries as they are, with their setuid, setgid and sticky
+        /// bits and without the umask
+        #[clap(long)]
+        preserve_permissions: bool,
+
+        /// Delete the files an incremental archive records as removed
+        #[clap(long)]
+        incremental: bool,
+
+        /// Once extracted, delete the files of the destination that are not in the archive
+        #[clap(long)]
+        delete_extraneous: bool,
+
+        /// Only list the files --delete-extraneous would delete, without extracting anything
+        #[clap(long, requires = "delete_extraneous")]
+        dry_run: bool,
+
+        /// Write the files on several threads, as many as the measured disk throughput calls
+        /// for, rather than one at a time
+        #[clap(long)]
+        adaptive: bool,
+
+        /// Restore the files of zip and 7z archives from a cache of the files extracted before
+        /// when it holds their checksum, storing them there otherwise, in DIR or the cache
+        /// directory of the user
+        #[clap(long, value_name = "DIR", num_args = 0..=1)]
+        cache: Option<Option<PathBuf>>,
+
+        /// Hard link the files restored from the cache instead of copying them, which then share
+        /// their content, modification time and permissions with it
+        #[clap(long, requires = "cache")]
+        cache_link: bool,
+
+        /// Report the progress of a file every time this many more bytes of it are written, such
+        /// as `64MiB`, for archives holding a few large files
+        #[clap(long, value_name = "SIZE", value_parser = parse_size)]
+        progress_interval: Option<u64>,
+
+        #[clap(flatten)]
+        limits: LimitsArgs,
+
+        #[clap(flatten)]
+        password_list: PasswordListArgs,
+
+        #[cfg(feature = "keyring")]
+        #[clap(flatten)]
+        keyring: KeyringArgs,
+
+        #[cfg(feature = "notifications")]
+        #[clap(flatten)]
+        notify: NotifyArgs,
+    },
+    /// Write the content of entries of an archive to stdout
+    Cat {
+        /// Path to the archive, `-` to read it from stdin
+        path: String,
+
+        /// The entries to write, in archive order, a directory standing for the files under it
+        #[clap(required = true)]
+        entries: Vec<PathBuf>,
+
+        /// Written between the contents of two entries, where `\n`, `\t` and `\0` are escapes
+        #[clap(short, long, value_name = "SEP")]
+        separator: Option<String>,
+
+        /// Write the entries as a tar stream, keeping their names
+        #[cfg(feature = "tar_archive")]
+        #[clap(long, conflicts_with = "separator")]
+        tar: bool,
+
+        /// Password of the archive
+        #[clap(short, long)]
+        password: Option<String>,
+    },
+    /// Extract an archive to a temporary directory and print its path, to quickly look inside it
+    Peek {
+        /// Path to the archive, `-` to read it from stdin
+        #[clap(required_unless_present = "clean")]
+        path: Option<String>,
+
+        /// Only extract this entry, or the files under it if it is a directory
+        entry: Option<String>,
+
+        /// Open the extracted entry or archive with the default application of the system
+        #[clap(long)]
+        open: bool,
+
+        /// Keep the extracted files until `hezi peek --clean`, rather than deleting them once
+        /// Enter is pressed. Always the case when stdin is not a terminal
+        #[clap(short, long)]
+        keep: bool,
+
+        /// Delete the files kept by earlier peeks
+        #[clap(long, conflicts_with_all = ["path", "open", "keep"])]
+        clean: bool,
+
+        /// Password of the archive
+        #[clap(short, long)]
+        password: Option<String>,
+    },
+    /// Identify the archive type, compression and encryption of files
+    Id {
+        /// The files to identify
+        #[clap(required = true)]
+        paths: Vec<PathBuf>,
+    },
+    /// Print a random sample of the files of an archive with the start of their contents
+    Sample {
+        /// Path to the archive, `-` to read it from stdin
+        path: String,
+
+        /// Number of files to pick
+        #[clap(short = 'n', long, default_value_t = 10)]
+        count: usize,
+
+        /// Number of bytes of each file to preview
+        #[clap(long, value_name = "BYTES", default_value_t = 64)]
+        preview: usize,
+
+        /// Seed of the random picks, to sample the same files again
+        #[clap(long)]
+        seed: Option<u64>,
+
+        /// Password of the archive
+        #[clap(short, long)]
+        password: Option<String>,
+    },
+    /// Re-create an archive whenever the files it is made of change
+    #[cfg(feature = "watch")]
+    Watch {
+        #[clap(flatten)]
+        create: CreateArgs,
+
+        /// Milliseconds without changes to wait for before updating the archive
+        #[clap(long, value_name = "MS", default_value_t = 500)]
+        debounce: u64,
+
+        /// Files not to archive nor react to, as globs relative to the sources (repeatable)
+        #[clap(long, value_name = "GLOB")]
+        exclude: Vec<glob::Pattern>,
+    },
+    /// Write the size and SHA-256 of every file of an archive to a manifest
+    Manifest {
+        /// Path to the archive, `-` to read it from stdin
+        path: String,
+
+        /// The manifest to write, printed to stdout if not given
+        #[clap(short)]
+        out: Option<PathBuf>,
+
+        /// Password of the archive
+        #[clap(short, long)]
+        password: Option<String>,
+    },
+    /// Check an archive against a manifest, exiting with a non-zero status on any difference
+    Verify {
+        /// Path to the archive to verify, `-` to read it from stdin
+        path: String,
+
+        /// The manifest the archive must match
+        #[clap(long, value_name = "MANIFEST")]
+        against: PathBuf,
+
+        /// Print a JSON report on stdout instead of a human readable one
+        #[clap(long)]
+        ci: bool,
+
+        /// Password of the archive
+        #[clap(short, long)]
+        password: Option<String>,
+    },
+    /// Test the integrity of an archive by decompressing its files, exiting with a non-zero status
+    /// on any error
+    #[clap(alias = "t")]
+    Test {
+        /// Path to the archive to test, `-` to read it from stdin
+        path: String,
+
+        /// Check the central directory of a zip archive and only decompress a share of its files
+        #[clap(long)]
+        quick: bool,
+
+        /// Percentage of the files decompressed by --quick
+        #[clap(long, default_value_t = 10, value_parser = clap::value_parser!(u8).range(1..=100))]
+        percent: u8,
+
+        /// Seed of the files picked by --quick, to check the same files again
+        #[clap(long)]
+        seed: Option<u64>,
+
+        /// Password of the archive
+        #[clap(short, long)]
+        password: Option<String>,
+    },
+    /// Compare two archives, printing the entries added, removed or changed and exiting with a
+    /// non-zero status if there is any
+    Diff {
+        /// Path to the old archive
+        old: String,
+
+        /// Path to the new archive
+        new: String,
+
+        /// Compare the SHA-256 of the files too, reading both archives whole
+        #[clap(long)]
+        content: bool,
+
+        /// Password of the old archive
+        #[clap(short, long)]
+        password: Option<String>,
+
+        /// Password of the new archive, the one of the old archive if not given
+        #[clap(long)]
+        new_password: Option<String>,
+    },
+    /// Rename an entry of an archive, along with its content if it is a directory
+    Rename {
+        /// Path to the archive
+        path: String,
+
+        /// The entry to rename
+        entry: String,
+
+        /// The new name of the entry
+        new_name: String,
+    },
+    /// Set the modification time of an entry of an archive
+    Touch {
+        /// Path to the archive
+        path: String,
+
+        /// The entry to update
+        entry: String,
+
+        /// The new modification time, in RFC 3339 format, defaults to now
+        #[clap(long, short)]
+        date: Option<DateTime<FixedOffset>>,
+    },
+    /// Add files to an existing zip, tar or 7z archive
+    Add {
+        /// Path to the archive
+        path: String,
+
+        /// Files to add to the archive, directories with everything beneath them
+        #[clap(name = "FILE", required = true)]
+        files: Vec<PathBuf>,
+
+        /// Directory the names of the added entries are relative to, the current one by default
+        #[clap(long, short)]
+        directory: Option<PathBuf>,
+
+        /// Compression algorithm of the added entries of zip archives
+        #[clap(long, short)]
+        compression: Option<ArchiveCompression>,
+
+        /// Compression level
+        #[clap(long, short)]
+        level: Option<i32>,
+    },
+    /// Convert an archive to another format, such as a zip archive to a `.tar.zst` one, without
+    /// extracting it to the disk
+    Convert {
+        /// Path to the archive to convert, `-` to read it from stdin
+        path: String,
+
+        /// Path of the new zip, tar or 7z archive, whose format is guessed from its extension
+        out: PathBuf,
+
+        /// Compression of the new archive, or of its entries for zip archives, instead of the
+        /// one of its extension
+        #[clap(long, short)]
+        compression: Option<ArchiveCompression>,
+
+        /// Compression level
+        #[clap(long, short)]
+        level: Option<i32>,
+
+        /// Overwrite the new archive if it exists
+        #[clap(long, short)]
+        overwrite: bool,
+
+        /// Password of the archive to convert
+        #[clap(long, short)]
+        password: Option<String>,
     },
 }
 
+#[derive(Debug, Args, Clone)]
+struct PasswordListArgs {
+    /// File of candidate passwords to try, one per line
+    #[clap(long, value_name = "FILE", conflicts_with = "password")]
+    password_list: Option<PathBuf>,
+
+    /// Maximum number of candidate passwords to try
+    #[clap(long, requires = "password_list")]
+    max_attempts: Option<usize>,
+
+    /// Milliseconds to wait between two attempts
+    #[clap(long, value_name = "MS", requires = "password_list")]
+    attempt_delay: Option<u64>,
+}
+
+#[derive(Debug, Args, Clone)]
+struct LimitsArgs {
+    /// Stop once the extracted files add up to more than this size, such as `10GiB`
+    #[clap(long, value_name = "SIZE", value_parser = parse_size)]
+    max_total: Option<u64>,
+
+    /// Stop at a file larger than this size
+    #[clap(long, value_name = "SIZE", value_parser = parse_size)]
+    max_entry_size: Option<u64>,
+
+    /// Stop past this many entries
+    #[clap(long, value_name = "N")]
+    max_entries: Option<usize>,
+
+    /// Stop at a file of more than 1MiB larger than this many times its compressed size, or at
+    /// files adding up to this many times the archive when it compresses them all together
+    #[clap(long, value_name = "RATIO", default_value_t = 10000.0)]
+    max_ratio: f64,
+
+    /// Extract whatever the sizes, entries and compression ratios
+    #[clap(long, conflicts_with_all = ["max_total", "max_entry_size", "max_entries", "max_ratio"])]
+    no_limits: bool,
+}
+
+#[cfg(feature = "keyring")]
+#[derive(Debug, Args, Clone)]
+struct KeyringArgs {
+    /// Remember the password in the OS keyring once the archive was read successfully
+    #[clap(long)]
+    save_password: bool,
+
+    /// Do not look up or store the password in the OS keyring
+    #[clap(long, conflicts_with = "save_password")]
+    no_keyring: bool,
+}
+
+#[cfg(feature = "notifications")]
+#[derive(Debug, Args, Clone)]
+struct NotifyArgs {
+    /// Show the progress in the terminal title and send a desktop notification when done
+    #[clap(long)]
+    notify: bool,
+}
+
 #[derive(Debug, Args, Clone)]
 struct CreateArgs {
-    /// The path of the archive to create
+    /// The path of the archive to create, where `{date}`, `{date:FORMAT}` and `{host}` are
+    /// expanded, or `-` to write a zip, tar or compressed file to stdout
     archive_path: String,
 
+    /// Format of the archive, as a file extension such as `zip` or `tar.gz`, instead of guessing
+    /// it from the path (required when writing to stdout)
+    #[clap(long, value_name = "EXT")]
+    format: Option<String>,
+
     /// Directory to use as the root of the archive
     #[clap(long, short)]
     directory: Option<PathBuf>,
 
-    /// Files to add to the archive
+    /// Additional directory to add, stored under PREFIX (repeatable)
+    #[clap(long = "map", value_name = "ROOT=:PREFIX")]
+    map: Vec<SourceRoot>,
+
+    /// Files to add to the archive, directories with everything beneath them
     #[clap(name = "FILE", trailing_var_arg = true)]
     files: Option<Vec<PathBuf>>,
 
@@ -93,9 +538,115 @@ struct CreateArgs {
     #[clap(long, short)]
     compression: Option<ArchiveCompression>,
 
+    /// Long-distance matching of zstd, finding repetitions far apart in large inputs, within
+    /// 2^WINDOW bytes, 2^27 by default
+    #[clap(
+        long,
+        value_name = "WINDOW",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "27",
+        value_parser = clap::value_parser!(u32).range(10..=31)
+    )]
+    zstd_long: Option<u32>,
+
+    /// How gzip looks for repetitions, which then compresses on a single thread
+    #[clap(long, value_enum, value_name = "STRATEGY")]
+    gzip_strategy: Option<GzipStrategy>,
+
+    /// Effort of bzip2 on highly repetitive data before falling back to a slower sort, 30 by
+    /// default
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..=250))]
+    bzip2_work_factor: Option<u32>,
+
     /// Password
     #[clap(long, short)]
     password: Option<String>,
+
+    /// Once created, delete the oldest archives matching the name template, keeping the N most
+    /// recent ones
+    #[clap(long, value_name = "N")]
+    rotate: Option<usize>,
+
+    /// Only archive the files changed since the state recorded in this snapshot file, then record
+    /// the new state in it
+    #[clap(long, value_name = "SNAPSHOT")]
+    listed_incremental: Option<PathBuf>,
+
+    /// Leave out the files that cannot be read, such as broken symlinks, instead of failing
+    #[clap(long)]
+    skip_unreadable: bool,
+
+    /// Once created, check the size and SHA-256 of the archived files against the source files
+    #[clap(long)]
+    verify: bool,
+
+    /// Split the archive once created into volumes of this size, named `.001`, `.002`, ... after
+    /// it, e.g. `--volume-size 100MiB`
+    #[clap(long, value_name = "SIZE", value_parser = parse_size, conflicts_with = "update")]
+    volume_size: Option<u64>,
+
+    /// Only add the files missing from the existing archive or whose size or modification time
+    /// changed, replacing their entries
+    #[clap(long, short, conflicts_with = "listed_incremental")]
+    update: bool,
+
+    /// Method compressing the contents of 7z archives
+    #[clap(long = "7z-method", value_enum, value_name = "METHOD")]
+    sevenz_method: Option<SevenZContentMethod>,
+
+    /// Dictionary size of 7z archives, such as `64MiB`
+    #[clap(long, value_name = "SIZE", value_parser = parse_dictionary_size)]
+    dictionary_size: Option<u32>,
+
+    /// Compress the files of 7z archives together in solid blocks
+    #[clap(long)]
+    solid: bool,
+
+    /// Start a new solid block once it holds this size of files, such as `256MiB`
+    #[clap(long, value_name = "SIZE", value_parser = parse_size, requires = "solid")]
+    solid_block_size: Option<u64>,
+
+    /// User owning every entry of tar archives, as NAME, ID or NAME:ID (e.g. `root`)
+    #[clap(long, value_name = "USER", value_parser = Owner::user)]
+    owner: Option<Owner>,
+
+    /// Group owning every entry of tar archives, as NAME, ID or NAME:ID (e.g. `root`)
+    #[clap(long, value_name = "GROUP", value_parser = Owner::group)]
+    group: Option<Owner>,
+
+    /// Only store the numeric user and group ids in tar archives, without their names
+    #[clap(long)]
+    numeric_owner: bool,
+
+    /// Preset of the format options: `strict` keeps names and times exactly, `compat` is read by
+    /// older tools, `fast` compresses at the fastest level unless `--level` is given
+    #[clap(long, value_enum)]
+    profile: Option<CreateProfile>,
+
+    /// Headers of tar archives, overriding the profile
+    #[clap(long, value_enum, value_name = "FORMAT")]
+    tar_format: Option<TarFormat>,
+
+    /// When zip entries get the Zip64 fields, overriding the profile
+    #[clap(long, value_enum, value_name = "WHEN")]
+    zip64: Option<Zip64Mode>,
+
+    /// How zip entries mark names that are not ASCII as UTF-8, overriding the profile
+    #[clap(long, value_enum, value_name = "MARK")]
+    zip_unicode_names: Option<ZipUnicodeNames>,
+
+    #[cfg(feature = "notifications")]
+    #[clap(flatten)]
+    notify: NotifyArgs,
+
+    /// Number of threads compression may use, from the global `--threads`
+    #[clap(skip)]
+    threads: Option<usize>,
+
+    /// Where to write the progress to, from the global `--progress-fd`
+    #[clap(skip)]
+    progress_fd: Option<String>,
 }
 
 #[derive(Debug, Args, Clone)]
@@ -112,6 +663,15 @@ struct GlobalOpts {
     // #[clap(long, global = true)]
     #[clap(long, global = true)]
     json: bool,
+
+    /// Number of threads to use, all the available cores by default
+    #[clap(long, global = true, value_name = "N")]
+    threads: Option<NonZeroUsize>,
+
+    /// File descriptor to write the progress of create and extract to as lines of JSON, such as
+    /// `3`, or the name of a pipe such as `\\.\pipe\hezi` on Windows
+    #[clap(long, global = true, value_name = "FD")]
+    progress_fd: Option<String>,
 }
 
 #[derive(Clone, Debug, ValueEnum)]
@@ -122,7 +682,8 @@ pub enum Color {
 }
 
 fn main() {
-    env_logger::init();
+    // warnings of hezi itself are shown, such as an archive opened after its extension
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("hezi=warn")).init();
     let res = App::parse();
 
     // if res.global_opts.help {
@@ -139,7 +700,7 @@ fn main() {
             const RED: &str = "\x1b[31m";
             const RESET: &str = "\x1b[0m";
             const BOLD: &str = "\x1b[1m";
-            eprintln!("{}An error occurred: \n\n{}{:?}{}", RED, BOLD, e, RESET);
+            eprintln!("{}An error occurred: \n\n{}{}{}", RED, BOLD, e, RESET);
             std::process::exit(1);
         }
     }
@@ -150,126 +711,1183 @@ fn run(app: App, nu: NuSetup) -> Result<(), ShellError> {
         println!("command: {:#?}", app.command);
     }
 
+    let threads = app.global_opts.threads.map(NonZeroUsize::get);
+    let progress_fd = app.global_opts.progress_fd;
+    let json = app.global_opts.json;
+    if let Some(threads) = threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .map_err(|e| ShellError::InvalidArgument(e.to_string()))?;
+    }
+
     match app.command {
-        Command::List { path, password, .. } => {
-            let source = DataSource::file(path)?;
+        Command::List {
+            path,
+            prefix,
+            null,
+            date_format,
+            rollup,
+            limit,
+            offset,
+            password,
+            password_list,
+            #[cfg(feature = "keyring")]
+            keyring,
+            ..
+        } => {
+            let archive = Archive::of(open_source(&path)?)?;
 
-            let archive = Archive::of(source)?;
+            let password = password_list.resolve(&archive, password)?;
+            #[cfg(feature = "keyring")]
+            let password = keyring.resolve(&path, password)?;
+            let password = CliPassword::new(password);
 
-            let entries = archive.list(ListOptions {
-                password,
-                event_handler: nu.event_handler(),
+            let mut entries = password.retrying(|| {
+                archive.list(ListOptions {
+                    password: Some(password.provider()),
+                    prefix: prefix.clone(),
+                    offset,
+                    limit,
+                    event_handler: nu.event_handler(),
+                })
             })?;
+            if rollup {
+                roll_up_sizes(&mut entries);
+            }
+
+            #[cfg(feature = "keyring")]
+            keyring.save(&path, password.resolved().as_deref())?;
 
-            nu.display_list(entries)?;
+            display_entries(&nu, entries, null, date_format.as_ref())?;
 
             Ok(())
         }
-        Command::Create(create) => {
-            let (archive_type, guessed_compression) =
-                ArchiveType::guess_from_filename(&create.archive_path)?;
-            let archive_compression =
-                create
-                    .compression
-                    .or(guessed_compression)
-                    .ok_or(ShellError::InvalidOption(
-                        "could not determine compression algorithm".to_string(),
-                    ))?;
-
-            if let (Some(level), Some(range)) =
-                (create.level, archive_compression.valid_level_range())
-            {
-                if !range.contains(&level) {
-                    return Err(ShellError::InvalidArgument(format!(
-                        "compression level must be between {} and {} but was {}",
-                        range.start(),
-                        range.end(),
-                        level
-                    )));
+        Command::Create(mut create) => {
+            create.threads = threads;
+            create.progress_fd = progress_fd;
+            create.run(&[])
+        }
+        #[cfg(feature = "watch")]
+        Command::Watch {
+            mut create,
+            debounce,
+            exclude,
+        } => {
+            create.threads = threads;
+            create.progress_fd = progress_fd;
+            watch::watch(create, Duration::from_millis(debounce), exclude)
+        }
+        Command::Extract {
+            path,
+            out,
+            force,
+            password,
+            retries,
+            eol,
+            update,
+            mode,
+            dir_mode,
+            preserve_permissions,
+            incremental,
+            delete_extraneous,
+            dry_run,
+            adaptive,
+            cache,
+            cache_link,
+            progress_interval,
+            limits,
+            password_list,
+            #[cfg(feature = "keyring")]
+            keyring,
+            #[cfg(feature = "notifications")]
+            notify,
+        } => {
+            let archive = Archive::of(open_source(&path)?)?;
+            let path = match path.as_str() {
+                "-" => PathBuf::from(path),
+                _ => PathBuf::from(path).canonicalize()?,
+            };
+
+            // a single compressed file, or an archive piped on stdin, is extracted in the current
+            // directory, as gunzip would, other archives in a directory named after them
+            let dest: PathBuf = out
+                .map(PathBuf::from)
+                .or(env::current_dir().ok().and_then(|cwd| match archive {
+                    _ if path == Path::new("-") => Some(cwd),
+                    #[cfg(feature = "compressed_file")]
+                    Archive::CompressedFile(_) => Some(cwd),
+                    _ => path.file_stem().map(|p| cwd.join(p)),
+                }))
+                .ok_or(Error::other("could not determine output path"))?;
+
+            println!("Extracting {} to {}", path.display(), dest.display());
+
+            let password = password_list.resolve(&archive, password)?;
+            #[cfg(feature = "keyring")]
+            let password = keyring.resolve(&path, password)?;
+            let password = CliPassword::new(password);
+
+            if dry_run {
+                for path in extraneous_files(&archive, Some(&password), &dest)? {
+                    println!("Would delete {}", path.display());
                 }
+                return Ok(());
             }
 
-            if create.files.is_none() && create.directory.is_none() {
-                return Err(ShellError::InvalidArgument(
-                    "no files or directory specified".to_string(),
-                ));
+            let total = || archive.metadata().map(|m| m.total_size).unwrap_or_default();
+            let (handler, progress_writer) =
+                progress_events(progress_fd.as_deref(), nu.event_handler(), total)?;
+            #[cfg(feature = "notifications")]
+            let (handler, notifier) =
+                notify.wrap(handler, format!("extracting {}", path.display()), total);
+            let result = password.retrying(|| {
+                archive.extract(ExtractOptions {
+                    destination: dest.clone(),
+                    password: Some(password.provider()),
+                    files: None,
+                    overwrite: force,
+                    update,
+                    file_mode: mode,
+                    dir_mode,
+                    preserve_permissions,
+                    show_hidden: true,
+                    retry: IoRetryPolicy::new(retries),
+                    transformers: eol
+                        .map(|eol| Box::new(eol) as Box<dyn EntryTransformer>)
+                        .into_iter()
+                        .collect(),
+                    inspector: None,
+                    adaptive,
+                    cache: cache.clone().map(|dir| ExtractCache {
+                        dir: dir.unwrap_or_else(ExtractCache::default_dir),
+                        link: cache_link,
+                    }),
+                    progress_interval,
+                    limits: limits.limits(),
+                    event_handler: Box::new(Lent(handler.as_ref())),
+                })
+            });
+            report_failure(progress_writer, &result);
+            #[cfg(feature = "notifications")]
+            NotifyArgs::report(notifier, &result);
+            print_summary(&result?);
+
+            if incremental {
+                for deleted in apply_removals(&dest)? {
+                    println!("Deleted {}", deleted.display());
+                }
+            }
+            if delete_extraneous {
+                let extraneous = extraneous_files(&archive, Some(&password), &dest)?;
+                for path in &extraneous {
+                    println!("Deleting {}", path.display());
+                }
+                remove_extraneous(&extraneous)?;
             }
 
-            // let cwd = env::current_dir().expect("could not get current working directory");
-            let source = create
-                .directory
-                .map_or_else(env::current_dir, |p| p.canonicalize())?;
+            #[cfg(feature = "keyring")]
+            keyring.save(&path, password.resolved().as_deref())?;
 
-            println!("Creating archive from {}", source.display());
+            Ok(())
+        }
+        Command::Cat {
+            path,
+            entries,
+            separator,
+            #[cfg(feature = "tar_archive")]
+            tar,
+            password,
+        } => {
+            let output = match separator {
+                Some(separator) => OpenOutput::Separated(unescape(&separator).into_bytes()),
+                None => OpenOutput::Concatenated,
+            };
+            #[cfg(feature = "tar_archive")]
+            let output = match tar {
+                true => OpenOutput::Tar,
+                false => output,
+            };
+            let password: Box<dyn PasswordProvider> = match password {
+                Some(password) => Box::new(StaticPassword(password)),
+                None => Box::new(PromptPassword::new("Password")),
+            };
 
-            let files = if let Some(files) = create.files {
-                files
-                    .iter()
-                    .map(|p| p.canonicalize())
-                    .collect::<Result<_, _>>()?
-            } else {
-                walkdir::WalkDir::new(&source)
-                    .into_iter()
-                    .par_bridge()
-                    .filter_map(|e| e.ok())
-                    .map(|e| e.into_path())
-                    .collect::<Vec<_>>()
+            Archive::of(open_source(&path)?)?.open(OpenOptions {
+                paths: entries,
+                password: Some(password),
+                dest: Box::new(std::io::BufWriter::new(std::io::stdout())),
+                output,
+            })?;
+            Ok(())
+        }
+        Command::Peek {
+            path,
+            entry,
+            open,
+            keep,
+            clean,
+            password,
+        } => {
+            let Some(path) = path.filter(|_| !clean) else {
+                let cleaned = clean_peeks()?;
+                println!("Deleted {} peeked archives", cleaned);
+                return Ok(());
             };
+            let archive = Archive::of(open_source(&path)?)?;
+            let peek = peek(
+                &archive,
+                &path,
+                entry.as_deref(),
+                Some(&CliPassword::new(password)),
+            )?;
+            println!("{}", peek.path().display());
+            if open {
+                open_with_default_app(peek.path())?;
+            }
 
-            let destination = std::path::PathBuf::from(create.archive_path);
+            // an archive piped on stdin leaves nothing to wait on
+            if keep || path == "-" || !std::io::stdin().is_terminal() {
+                peek.keep();
+                return Ok(());
+            }
+            eprint!("Press Enter to delete the extracted files");
+            std::io::stdin().read_line(&mut String::new())?;
+            Ok(())
+        }
+        Command::Id { paths } => {
+            let mut identified = Vec::with_capacity(paths.len());
+            let mut failed = false;
+            for path in paths {
+                match identify(&path) {
+                    Ok(id) => identified.push((path, id)),
+                    Err(e) => {
+                        eprintln!("{}: {}", path.display(), e);
+                        failed = true;
+                    }
+                }
+            }
 
-            let options = CreateOptions {
-                destination,
-                password: create.password,
-                files,
-                overwrite: create.overwrite,
-                source,
-                archive_type,
-                archive_compression: Some(archive_compression),
-                include_hidden: true,
-                event_handler: Box::new(SimpleLogger),
-            };
+            if json {
+                let identified = output::Identified {
+                    files: identified
+                        .iter()
+                        .map(|(path, id)| {
+                            output::IdentifiedFile::new(path.to_string_lossy().to_string(), id)
+                        })
+                        .collect(),
+                };
+                println!(
+                    "{}",
+                    Versioned::new(identified)
+                        .to_json()
+                        .map_err(ArchiveError::Json)?
+                );
+            } else {
+                for (path, id) in &identified {
+                    println!("{}: {}", path.display(), describe(id));
+                }
+            }
 
-            Archive::create(options)?;
+            if failed {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Sample {
+            path,
+            count,
+            preview,
+            seed,
+            password,
+        } => {
+            let archive = Archive::of(open_source(&path)?)?;
+            let sample = sample_entries(
+                &archive,
+                Some(&CliPassword::new(password)),
+                &SampleOptions {
+                    count,
+                    preview_size: preview,
+                    seed,
+                },
+            )?;
 
+            if json {
+                let sample = output::Sample::from(sample.as_slice());
+                println!(
+                    "{}",
+                    Versioned::new(sample)
+                        .to_json()
+                        .map_err(ArchiveError::Json)?
+                );
+                return Ok(());
+            }
+            for entry in sample {
+                match entry.entity.size() {
+                    Some(size) => println!(
+                        "{} ({:.2})",
+                        entry.entity.name(),
+                        Byte::from(size).get_appropriate_unit(UnitType::Both)
+                    ),
+                    None => println!("{}", entry.entity.name()),
+                }
+                println!("    {}", entry.preview);
+            }
             Ok(())
         }
-        Command::Extract {
+        Command::Manifest {
             path,
             out,
-            force,
             password,
         } => {
-            let path = PathBuf::from(path).canonicalize()?;
-            let dest: PathBuf = out
-                .map(PathBuf::from)
-                .or(env::current_dir()
-                    .ok()
-                    .and_then(|cwd| path.file_stem().map(|p| cwd.join(p))))
-                .ok_or(Error::new(
-                    std::io::ErrorKind::Other,
-                    "could not determine output path",
-                ))?;
+            let archive = Archive::of(open_source(&path)?)?;
+            let manifest = Manifest::of(&archive, Some(&CliPassword::new(password)))?;
 
-            println!("Extracting {} to {}", path.display(), dest.display());
-
-            let datasource = DataSource::file(&path)?;
+            let manifest = Versioned::new(output::Manifest::from(&manifest));
+            let json = serde_json::to_string_pretty(&manifest).map_err(ArchiveError::Json)?;
+            match out {
+                Some(out) => std::fs::write(out, json)?,
+                None => println!("{}", json),
+            }
+            Ok(())
+        }
+        Command::Verify {
+            path,
+            against,
+            ci,
+            password,
+        } => {
+            let expected = Manifest::read(&against)?;
+            let archive = Archive::of(open_source(&path)?)?;
+            let diff = expected.diff(&Manifest::of(&archive, Some(&CliPassword::new(password)))?);
+            let passed = diff.is_clean();
 
-            let archive = Archive::of(datasource)?;
+            if ci {
+                let check =
+                    output::ManifestCheck::new(path, against.to_string_lossy().to_string(), &diff);
+                println!(
+                    "{}",
+                    Versioned::new(check)
+                        .to_json()
+                        .map_err(ArchiveError::Json)?
+                );
+            } else {
+                println!("{}", diff);
+            }
 
-            let handler = nu.event_handler();
-            archive.extract(ExtractOptions {
-                destination: dest,
-                password,
-                files: None,
-                overwrite: force,
-                show_hidden: true,
-                event_handler: handler,
+            if !passed {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Test {
+            path,
+            quick,
+            percent,
+            seed,
+            password,
+        } => {
+            let archive = Archive::of(open_source(&path)?)?;
+            let report = archive.verify(VerifyOptions {
+                password: Some(Box::new(CliPassword::new(password))),
+                quick,
+                percent,
+                seed,
             })?;
 
+            if json {
+                let test = output::Test::from(&report);
+                println!(
+                    "{}",
+                    Versioned::new(test).to_json().map_err(ArchiveError::Json)?
+                );
+            } else {
+                println!("{}", report);
+            }
+            if !report.passed() {
+                std::process::exit(1);
+            }
             Ok(())
         }
+        Command::Diff {
+            old,
+            new,
+            content,
+            password,
+            new_password,
+        } => {
+            let old = Archive::of(open_source(&old)?)?;
+            let new = Archive::of(open_source(&new)?)?;
+            let new_password = new_password.or_else(|| password.clone());
+            let diff = old.diff(
+                &new,
+                DiffOptions {
+                    password: Some(Box::new(CliPassword::new(password))),
+                    other_password: Some(Box::new(CliPassword::new(new_password))),
+                    content,
+                },
+            )?;
+
+            if json {
+                let diff = output::Diff::from(diff.as_slice());
+                println!(
+                    "{}",
+                    Versioned::new(diff).to_json().map_err(ArchiveError::Json)?
+                );
+            } else {
+                for entry in &diff {
+                    let kind = format!("{}:", entry.kind);
+                    match entry.kind {
+                        DiffKind::Changed => {
+                            let fields = entry.fields.iter().map(|f| f.to_string());
+                            let fields = fields.collect::<Vec<_>>().join(", ");
+                            println!("{:<8} {} ({})", kind, entry.name, fields);
+                        }
+                        _ => println!("{:<8} {}", kind, entry.name),
+                    }
+                }
+            }
+            if !diff.is_empty() {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Rename {
+            path,
+            entry,
+            new_name,
+        } => {
+            Archive::of(open_source(&path)?)?.rename(&entry, &new_name)?;
+            println!("Renamed {} to {} in {}", entry, new_name, path);
+            Ok(())
+        }
+        Command::Touch { path, entry, date } => {
+            let date = date.unwrap_or_else(|| Local::now().fixed_offset());
+            Archive::of(open_source(&path)?)?.touch(&entry, date)?;
+            println!(
+                "Set the modification time of {} in {} to {}",
+                entry, path, date
+            );
+            Ok(())
+        }
+        Command::Add {
+            path,
+            files,
+            directory,
+            compression,
+            level,
+        } => {
+            let source = directory.map_or_else(env::current_dir, |p| p.canonicalize())?;
+            let files = files
+                .iter()
+                .map(|p| p.canonicalize())
+                .collect::<Result<Vec<_>, _>>()?;
+            let result = Archive::of(DataSource::file(&path)?)?.append(AppendOptions {
+                source,
+                files: walk_paths(&files),
+                compression,
+                level,
+                ..Default::default()
+            })?;
+            println!(
+                "Added {} to {} ({})",
+                Byte::from(result.total_size).get_appropriate_unit(UnitType::Both),
+                path,
+                Byte::from(result.compressed_size).get_appropriate_unit(UnitType::Both)
+            );
+            Ok(())
+        }
+        Command::Convert {
+            path,
+            out,
+            compression,
+            level,
+            overwrite,
+            password,
+        } => {
+            let (target, guessed) = ArchiveType::guess_from_filename(&out)?;
+            let same_file = out.canonicalize().ok();
+            if same_file.is_some() && same_file == Path::new(&path).canonicalize().ok() {
+                return Err(ShellError::InvalidArgument(
+                    "cannot convert an archive to itself".to_string(),
+                ));
+            }
+            let compression = compression.or(guessed);
+            if compression.is_none() && level.is_some() {
+                return Err(ShellError::InvalidArgument(
+                    "--level needs a --compression algorithm".to_string(),
+                ));
+            }
+            let compression = compression.map(|algorithm| CompressionSpec {
+                level,
+                ..CompressionSpec::new(algorithm)
+            });
+            let password: Box<dyn PasswordProvider> = match password {
+                Some(password) => Box::new(StaticPassword(password)),
+                None => Box::new(PromptPassword::new("Password")),
+            };
+            let result = Archive::of(open_source(&path)?)?.convert(
+                target,
+                ConvertOptions {
+                    destination: out,
+                    compression,
+                    password: Some(password),
+                    overwrite,
+                    ..Default::default()
+                },
+            )?;
+            println!(
+                "Converted {} to {} ({})",
+                path,
+                result.path.display(),
+                Byte::from(result.compressed_size).get_appropriate_unit(UnitType::Both)
+            );
+            Ok(())
+        }
+    }
+}
+
+impl CreateArgs {
+    /// Creates the archive, leaving out the files matching one of the `exclude` patterns.
+    fn run(self, exclude: &[glob::Pattern]) -> Result<(), ShellError> {
+        let archive_path = expand_name_template(&self.archive_path, Local::now().fixed_offset())?;
+        // archives written to stdout have their progress printed to stderr
+        let to_stdout = archive_path == "-";
+        let progress = |message: String| {
+            if to_stdout {
+                eprintln!("{}", message);
+            } else {
+                println!("{}", message);
+            }
+        };
+
+        let (archive_type, guessed_compression) = match &self.format {
+            Some(format) => ArchiveType::guess_from_filename(format!("archive.{}", format))?,
+            None if to_stdout => {
+                return Err(ShellError::InvalidArgument(
+                    "--format is required when writing to stdout".to_string(),
+                ))
+            }
+            None => ArchiveType::guess_from_filename(&archive_path)?,
+        };
+        if to_stdout && self.rotate.is_some() {
+            return Err(ShellError::InvalidArgument(
+                "--rotate cannot be used when writing to stdout".to_string(),
+            ));
+        }
+        if to_stdout && self.verify {
+            return Err(ShellError::InvalidArgument(
+                "--verify cannot be used when writing to stdout".to_string(),
+            ));
+        }
+        if to_stdout && self.volume_size.is_some() {
+            return Err(ShellError::InvalidArgument(
+                "--volume-size cannot be used when writing to stdout".to_string(),
+            ));
+        }
+
+        // zip archives written to stdout are deflated by default
+        let archive_compression = self.compression.or(guessed_compression);
+        if archive_compression.is_none() && !to_stdout {
+            return Err(ShellError::InvalidOption(
+                "could not determine compression algorithm".to_string(),
+            ));
+        }
+        if archive_compression.is_none() && self.level.is_some() {
+            return Err(ShellError::InvalidArgument(
+                "--level needs a --compression algorithm".to_string(),
+            ));
+        }
+
+        if let (Some(level), Some(range)) = (
+            self.level,
+            archive_compression
+                .as_ref()
+                .and_then(|c| c.valid_level_range()),
+        ) {
+            if !range.contains(&level) {
+                return Err(ShellError::InvalidArgument(format!(
+                    "compression level must be between {} and {} but was {}",
+                    range.start(),
+                    range.end(),
+                    level
+                )));
+            }
+        }
+
+        if self.files.is_none() && self.directory.is_none() && self.map.is_empty() {
+            return Err(ShellError::InvalidArgument(
+                "no files, directory or mapped roots specified".to_string(),
+            ));
+        }
+
+        // let cwd = env::current_dir().expect("could not get current working directory");
+        let walk_source = self.directory.is_some() || self.map.is_empty();
+        let source = self
+            .directory
+            .map_or_else(env::current_dir, |p| p.canonicalize())?;
+        let roots = self
+            .map
+            .into_iter()
+            .map(|r| {
+                Ok(SourceRoot {
+                    root: r.root.canonicalize()?,
+                    prefix: r.prefix,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        if walk_source {
+            progress(format!("Creating archive from {}", source.display()));
+        }
+        for root in &roots {
+            progress(format!(
+                "Adding {} as {}",
+                root.root.display(),
+                root.prefix.display()
+            ));
+        }
+
+        // directories are walked, sorted so that the entries are in the same order on every run
+        let files = if let Some(files) = self.files {
+            let files = files
+                .iter()
+                .map(|p| p.canonicalize())
+                .collect::<Result<Vec<_>, _>>()?;
+            walk_paths(&files)
+        } else {
+            let walked = walk_source.then_some(&source);
+            let dirs = walked
+                .into_iter()
+                .chain(roots.iter().map(|r| &r.root))
+                .collect::<Vec<_>>();
+            walk_paths(&dirs)
+        };
+        let files = if exclude.is_empty() {
+            files
+        } else {
+            let bases = std::iter::once(&source)
+                .chain(roots.iter().map(|r| &r.root))
+                .collect::<Vec<_>>();
+            files
+                .into_iter()
+                .filter(|f| !is_excluded(f, &bases, exclude))
+                .collect()
+        };
+
+        let destination = std::path::PathBuf::from(archive_path);
+
+        let handler: Box<dyn EventHandler> = if to_stdout {
+            Box::new(Silent)
+        } else {
+            Box::new(SimpleLogger)
+        };
+        let total = || {
+            files
+                .iter()
+                .filter_map(|f| std::fs::metadata(f).ok())
+                .filter(|m| m.is_file())
+                .map(|m| m.len())
+                .sum()
+        };
+        let (handler, progress_writer) =
+            progress_events(self.progress_fd.as_deref(), handler, total)?;
+        #[cfg(feature = "notifications")]
+        let (handler, notifier) = self.notify.wrap(
+            handler,
+            format!("creating {}", destination.display()),
+            total,
+        );
+
+        let mut options = CreateOptions {
+            destination,
+            password: self.password,
+            files,
+            overwrite: self.overwrite,
+            source,
+            roots,
+            archive_type,
+            compression: archive_compression.map(|algorithm| CompressionSpec {
+                algorithm,
+                level: self.level,
+                threads: self.threads.map(|t| t as u32),
+                extra: CodecParams {
+                    zstd_long: self.zstd_long,
+                    gzip_strategy: self.gzip_strategy,
+                    bzip2_work_factor: self.bzip2_work_factor,
+                },
+            }),
+            include_hidden: true,
+            skip_unreadable: self.skip_unreadable,
+            verify_after: self.verify,
+            volume_size: self.volume_size,
+            update: self.update,
+            sevenz: SevenZCreateOptions {
+                method: self.sevenz_method.unwrap_or_default(),
+                dictionary_size: self.dictionary_size,
+                solid: self.solid,
+                solid_block_size: self.solid_block_size,
+            },
+            zip: Default::default(),
+            tar: TarCreateOptions {
+                owner: self.owner,
+                group: self.group,
+                numeric_owner: self.numeric_owner,
+                ..Default::default()
+            },
+            event_handler: handler,
+        };
+        if let Some(profile) = self.profile {
+            profile.apply(&mut options);
+        }
+        if let Some(format) = self.tar_format {
+            options.tar.format = format;
+        }
+        if let Some(zip64) = self.zip64 {
+            options.zip.zip64 = zip64;
+        }
+        if let Some(unicode_names) = self.zip_unicode_names {
+            options.zip.unicode_names = unicode_names;
+        }
+
+        let increment = match &self.listed_incremental {
+            Some(snapshot) => {
+                let increment = SnapshotState::read(snapshot)?.increment(&mut options)?;
+                progress(format!(
+                    "{} files changed and {} removed since the previous snapshot",
+                    increment.changed.len(),
+                    increment.removed.len()
+                ));
+                Some(increment)
+            }
+            None => None,
+        };
+
+        let result = if to_stdout {
+            create_to_stdout(options)
+        } else {
+            Archive::create(options)
+        };
+        report_failure(progress_writer, &result);
+        #[cfg(feature = "notifications")]
+        NotifyArgs::report(notifier, &result);
+        let result = result?;
+        for (path, error) in &result.unreadable {
+            eprintln!("Left out {}: {}", path.display(), error);
+        }
+        if self.volume_size.is_some() {
+            println!("Split into volumes from {}", result.path.display());
+        }
+
+        if let (Some(snapshot), Some(increment)) = (&self.listed_incremental, increment) {
+            increment.state.write(snapshot)?;
+        }
+
+        if let Some(keep) = self.rotate {
+            for deleted in rotate_snapshots(&self.archive_path, keep)? {
+                println!("Deleted old archive {}", deleted.display());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the archive described by `options` to stdout. Only the formats written front to back
+/// can be streamed, the others seek back to fill in their headers.
+fn create_to_stdout(options: CreateOptions) -> Result<CreateResult, ArchiveError> {
+    #[cfg_attr(
+        not(any(
+            feature = "zip_archive",
+            feature = "tar_archive",
+            feature = "compressed_file"
+        )),
+        allow(unused_variables)
+    )]
+    let stdout = std::io::BufWriter::new(std::io::stdout().lock());
+    match options.archive_type {
+        #[cfg(feature = "zip_archive")]
+        ArchiveType::Zip => create_zip_stream(options, stdout),
+        #[cfg(feature = "tar_archive")]
+        ArchiveType::Tar => create_tar_stream(options, stdout),
+        #[cfg(feature = "compressed_file")]
+        ArchiveType::CompressedFile => create_compressed_stream(options, stdout),
+        #[allow(unreachable_patterns)]
+        archive_type => Err(ArchiveError::UnsupportedActionForArchiveType(
+            "write to stdout, which cannot seek,".to_string(),
+            archive_type,
+        )),
+    }
+}
+
+/// Opens the archive at `path`, or the one piped on stdin if it is `-`. Tar and cpio archives piped
+/// on stdin are read as they come, other formats being spooled to a temporary file to seek in.
+fn open_source(path: &str) -> Result<DataSource, Error> {
+    if path != "-" {
+        return DataSource::file(path);
+    }
+    let no_input = || Error::new(ErrorKind::InvalidInput, "no archive piped on stdin");
+    if std::io::stdin().is_terminal() {
+        return Err(no_input());
+    }
+    let source = DataSource::reader(std::io::stdin())?;
+    match source.is_empty() {
+        Ok(true) => Err(no_input()),
+        _ => Ok(source),
+    }
+}
+
+/// Opens `path` with the application the system associates with it, without waiting for it.
+fn open_with_default_app(path: &Path) -> Result<(), Error> {
+    #[cfg(target_os = "macos")]
+    let mut command = std::process::Command::new("open");
+    #[cfg(windows)]
+    let mut command = {
+        let mut command = std::process::Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    };
+    #[cfg(not(any(target_os = "macos", windows)))]
+    let mut command = std::process::Command::new("xdg-open");
+    command.arg(path).spawn()?;
+    Ok(())
+}
+
+/// Progress written to the `--progress-fd` descriptor.
+type ProgressEvents<'a> = std::rc::Rc<ProgressWriter<'a, std::fs::File>>;
+
+/// Layers a [`ProgressWriter`] over `handler` if `--progress-fd` was given, `total` computes the
+/// size the progress is relative to.
+fn progress_events<'a, F: FnOnce() -> u64>(
+    target: Option<&str>,
+    handler: Box<dyn EventHandler + 'a>,
+    total: F,
+) -> Result<(Box<dyn EventHandler + 'a>, Option<ProgressEvents<'a>>), ShellError> {
+    let Some(target) = target else {
+        return Ok((handler, None));
+    };
+    let out = open_progress_fd(target).map_err(|e| {
+        ShellError::InvalidArgument(format!("could not open progress fd {}: {}", target, e))
+    })?;
+    let writer = std::rc::Rc::new(ProgressWriter::new(out, Some(total()), handler));
+    Ok((Box::new(writer.clone()), Some(writer)))
+}
+
+/// Reports the failure of the operation on the `--progress-fd` descriptor, its success is
+/// reported by the events.
+fn report_failure<T>(progress: Option<ProgressEvents>, result: &Result<T, ArchiveError>) {
+    if let (Some(progress), Err(e)) = (progress, result) {
+        progress.failed(e);
+    }
+}
+
+/// Describes what `id` tells of a file, such as `tar, gzip compressed, not encrypted (high
+/// confidence)`.
+fn describe(id: &Identification) -> String {
+    let (Some(archive_type), Some(confidence)) = (&id.archive_type, id.confidence) else {
+        return "unknown".to_string();
+    };
+    let mut description = archive_type.to_string();
+    for compression in &id.compression {
+        description.push_str(&format!(", {} compressed", compression));
+    }
+    match id.encrypted {
+        Some(true) => description.push_str(", encrypted"),
+        Some(false) => description.push_str(", not encrypted"),
+        None => {}
+    }
+    match confidence {
+        Confidence::Low => description.push_str(" (low confidence, from the file name only)"),
+        confidence => description.push_str(&format!(" ({} confidence)", confidence)),
+    }
+    description
+}
+
+/// Prints the outcome of an extraction, the entries themselves having been reported as they went.
+fn print_summary(report: &ExtractReport) {
+    let mut summary = format!(
+        "Extracted {} entries in {:.2?}",
+        report.extracted.len(),
+        report.duration
+    );
+    for (count, outcome) in [
+        (report.skipped.len(), "skipped"),
+        (report.failed.len(), "failed"),
+    ] {
+        if count > 0 {
+            summary.push_str(&format!(", {} {}", count, outcome));
+        }
+    }
+    println!("{}", summary);
+}
+
+/// Lends an event handler to each attempt of an operation.
+struct Lent<'h>(&'h dyn EventHandler);
+
+impl EventHandler for Lent<'_> {
+    fn handle(&self, event: ArchiveEvent) {
+        self.0.handle(event);
+    }
+}
+
+/// Drops the events of archives written to stdout, which must only receive the archive.
+struct Silent;
+
+impl EventHandler for Silent {
+    fn handle(&self, _event: ArchiveEvent) {}
+}
+
+/// Whether `path`, or one of its parents, matches one of `patterns`, either as is or relative to
+/// one of `bases`.
+fn is_excluded(path: &Path, bases: &[&PathBuf], patterns: &[glob::Pattern]) -> bool {
+    let relative = bases.iter().filter_map(|base| path.strip_prefix(base).ok());
+    std::iter::once(path).chain(relative).any(|p| {
+        p.ancestors()
+            .filter(|a| !a.as_os_str().is_empty())
+            .any(|a| patterns.iter().any(|pattern| pattern.matches_path(a)))
+    })
+}
+
+/// Password given on the command line or found for the archive, asked for on the terminal when
+/// there is none and an encrypted entry needs one.
+struct CliPassword {
+    given: Option<String>,
+    prompt: PromptPassword,
+}
+
+impl CliPassword {
+    fn new(given: Option<String>) -> Self {
+        Self {
+            given,
+            prompt: PromptPassword::new("Password"),
+        }
+    }
+
+    fn provider(&self) -> Box<dyn PasswordProvider + '_> {
+        password_provider(|entry| self.password(entry))
+    }
+
+    /// Runs `read` again while the password typed in is wrong, asking for it anew. Passwords
+    /// given on the command line are not asked again.
+    fn retrying<T>(
+        &self,
+        mut read: impl FnMut() -> Result<T, ArchiveError>,
+    ) -> Result<T, ArchiveError> {
+        const MAX_PROMPTS: usize = 3;
+        for _ in 1..MAX_PROMPTS {
+            match read() {
+                Err(ArchiveError::WrongPassword)
+                    if self.given.is_none() && self.prompt.answer().is_some() =>
+                {
+                    eprintln!("Wrong password");
+                    self.prompt.rejected(None);
+                }
+                result => return result,
+            }
+        }
+        read()
+    }
+
+    /// The password the archive was read with, if one was needed or given.
+    #[cfg_attr(not(feature = "keyring"), allow(dead_code))]
+    fn resolved(&self) -> Option<String> {
+        self.given.clone().or_else(|| self.prompt.answer())
+    }
+}
+
+impl PasswordProvider for CliPassword {
+    fn password(&self, entry: Option<&str>) -> Result<Option<String>, ArchiveError> {
+        match &self.given {
+            Some(password) => Ok(Some(password.clone())),
+            None => self.prompt.password(entry),
+        }
+    }
+
+    fn rejected(&self, entry: Option<&str>) {
+        self.prompt.rejected(entry);
+    }
+}
+
+impl LimitsArgs {
+    fn limits(&self) -> ExtractLimits {
+        if self.no_limits {
+            return ExtractLimits::NONE;
+        }
+        ExtractLimits {
+            max_total_uncompressed: self.max_total,
+            max_entry_size: self.max_entry_size,
+            max_entries: self.max_entries,
+            max_compression_ratio: Some(self.max_ratio),
+        }
+    }
+}
+
+impl PasswordListArgs {
+    /// Searches the password list for a password that decrypts `archive`, if one was given.
+    fn resolve(
+        &self,
+        archive: &Archive,
+        password: Option<String>,
+    ) -> Result<Option<String>, ShellError> {
+        let Some(list) = &self.password_list else {
+            return Ok(password);
+        };
+
+        let candidates = read_password_list(list)?;
+        let search = find_password(
+            archive,
+            &candidates,
+            &PasswordAttemptLimits {
+                max_attempts: self.max_attempts,
+                delay: self.attempt_delay.map(Duration::from_millis),
+            },
+        )?;
+        eprintln!("{}", search);
+
+        match search.password {
+            Some(password) => Ok(Some(password)),
+            None => Err(ShellError::InvalidArgument(format!(
+                "no password from {} could decrypt the archive",
+                list.display()
+            ))),
+        }
+    }
+}
+
+#[cfg(feature = "keyring")]
+impl KeyringArgs {
+    /// Falls back to the password remembered for `archive` when none was given. Archives piped
+    /// on stdin have none.
+    fn resolve<P: AsRef<std::path::Path>>(
+        &self,
+        archive: P,
+        password: Option<String>,
+    ) -> Result<Option<String>, ShellError> {
+        if password.is_some() || self.no_keyring || archive.as_ref() == Path::new("-") {
+            return Ok(password);
+        }
+        Ok(hezi::archive::keyring_store::stored_password(archive)?)
+    }
+
+    /// Remembers `password` for `archive` if `--save-password` was given.
+    fn save<P: AsRef<std::path::Path>>(
+        &self,
+        archive: P,
+        password: Option<&str>,
+    ) -> Result<(), ShellError> {
+        match password {
+            Some(password)
+                if self.save_password && !self.no_keyring && archive.as_ref() != Path::new("-") =>
+            {
+                Ok(hezi::archive::keyring_store::store_password(
+                    archive, password,
+                )?)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "notifications")]
+impl NotifyArgs {
+    /// Layers a [`ProgressNotifier`] over `handler` if `--notify` was given, `total` computes the
+    /// size the progress is relative to.
+    fn wrap<'a, F: FnOnce() -> u64>(
+        &self,
+        handler: Box<dyn EventHandler + 'a>,
+        operation: String,
+        total: F,
+    ) -> (
+        Box<dyn EventHandler + 'a>,
+        Option<std::rc::Rc<hezi::archive::notifier::ProgressNotifier<'a>>>,
+    ) {
+        if !self.notify {
+            return (handler, None);
+        }
+        let notifier = std::rc::Rc::new(hezi::archive::notifier::ProgressNotifier::new(
+            operation,
+            Some(total()),
+            handler,
+        ));
+        (Box::new(notifier.clone()), Some(notifier))
+    }
+
+    /// Notifies the failure of the operation, its success is reported by the events.
+    fn report<'a, T>(
+        notifier: Option<std::rc::Rc<hezi::archive::notifier::ProgressNotifier<'a>>>,
+        result: &Result<T, ArchiveError>,
+    ) {
+        if let (Some(notifier), Err(e)) = (notifier, result) {
+            notifier.failed(e);
+        }
+    }
+}
+
+/// Replaces the `\n`, `\t`, `\0` and `\\` escapes of `s`, leaving other backslashes as is.
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('0') => unescaped.push('\0'),
+            Some('\\') => unescaped.push('\\'),
+            Some(c) => {
+                unescaped.push('\\');
+                unescaped.push(c);
+            }
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Parses a size in bytes such as `4096`, `64MiB` or `1 GB`.
+fn parse_size(s: &str) -> Result<u64, String> {
+    Byte::parse_str(s, true)
+        .map(|size| size.as_u64())
+        .map_err(|e| format!("invalid size `{}`: {}", s, e))
+}
+
+fn parse_dictionary_size(s: &str) -> Result<u32, String> {
+    parse_size(s)?
+        .try_into()
+        .map_err(|_| format!("dictionary size `{}` is larger than 4 GiB", s))
+}
+
+/// Parses octal permissions such as `644` or `0o755`.
+fn parse_mode(s: &str) -> Result<u32, String> {
+    u32::from_str_radix(s.trim_start_matches("0o"), 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .ok_or(format!(
+            "invalid mode `{}`, expected octal permissions such as 644",
+            s
+        ))
+}
+
+/// Prints the entries of a listing as a table, or their names separated by NUL bytes if `null`.
+fn display_entries(
+    nu: &NuSetup,
+    entries: Vec<ArchiveFileEntity>,
+    null: bool,
+    dates: Option<&DateFormat>,
+) -> Result<(), ShellError> {
+    if !null {
+        return Ok(nu.display_entries(entries, dates)?);
+    }
+    let mut stdout = std::io::stdout().lock();
+    for entry in entries {
+        stdout.write_all(entry.name().as_bytes())?;
+        stdout.write_all(b"\0")?;
     }
+    stdout.flush()?;
+    Ok(())
 }
 
 #[inline]
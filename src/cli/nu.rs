@@ -1,7 +1,11 @@
 use std::io::Write;
 
 use byte_unit::{Byte, UnitType};
-use hezi::archive::{ArchiveError, ArchiveEvent, EventHandler, SkipReason};
+use hezi::archive::{
+    date_format::DateFormat, ArchiveError, ArchiveEvent, ArchiveFileEntity, EventHandler,
+    SkipReason,
+};
+use hezi::output::{self, Versioned};
 /// Search for a pattern in a file and display the lines that contain it.
 use nu_color_config::StyleComputer;
 
@@ -49,7 +53,7 @@ impl NuSetup {
     }
 
     #[inline]
-    pub fn style_computer(&self) -> StyleComputer {
+    pub fn style_computer(&self) -> StyleComputer<'_> {
         StyleComputer::from_config(&self.engine_state, &self.stack)
     }
 
@@ -103,20 +107,59 @@ impl NuSetup {
         }
     }
 
-    pub fn display_list<V: CustomValue + serde::Serialize>(
+    pub fn display_list<V: CustomValue>(&self, list: Vec<V>) -> Result<(), ArchiveError> {
+        let list = list
+            .into_iter()
+            .map(|v| v.to_base_value(Span::unknown()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ArchiveError::Io(std::io::Error::other(e)))?;
+        self.draw_list_table(list);
+
+        Ok(())
+    }
+
+    /// Displays entries with their modification dates rendered as `dates`, as strings in the
+    /// table and in JSON alike.
+    pub fn display_entries(
         &self,
-        list: Vec<V>,
+        entries: Vec<ArchiveFileEntity>,
+        dates: Option<&DateFormat>,
     ) -> Result<(), ArchiveError> {
+        let last_modified = |e: &ArchiveFileEntity| {
+            e.last_modified()
+                .map(|d| dates.map_or_else(|| output::rfc3339(&d), |dates| dates.format(&d)))
+        };
+
         if self.app.global_opts.json {
-            println!("{}", serde_json::to_string(&list)?);
+            let list = output::List {
+                entries: entries
+                    .iter()
+                    .map(|e| output::Entry {
+                        last_modified: last_modified(e),
+                        ..output::Entry::from(e)
+                    })
+                    .collect(),
+            };
+            println!("{}", Versioned::new(list).to_json()?);
             return Ok(());
         }
-
-        let list = list
-            .into_iter()
-            .map(|v| v.to_base_value(Span::unknown()))
-            .collect::<Result<Vec<_>, _>>()
-            .map_err(|e| ArchiveError::Io(std::io::Error::new(std::io::ErrorKind::Other, e)))?;
+        if dates.is_none() {
+            return self.display_list(entries);
+        }
+        let span = Span::unknown();
+
+        let list = entries
+            .iter()
+            .map(|e| {
+                let mut record = e.to_base_value(span)?.into_record()?;
+                record.insert(
+                    "last_modified",
+                    last_modified(e).map_or(Value::nothing(span), |d| Value::string(d, span)),
+                );
+                Ok(Value::record(record, span))
+            })
+            .collect::<Result<Vec<_>, nu_protocol::ShellError>>()
+            .map_err(|e| ArchiveError::Io(std::io::Error::other(e)))?;
         self.draw_list_table(list);
 
         Ok(())
@@ -133,7 +176,7 @@ impl AsRef<NuSetup> for NuSetup {
     }
 }
 
-impl<'a> EventHandler for &'a NuSetup {
+impl EventHandler for &NuSetup {
     fn handle(&self, event: ArchiveEvent) {
         match event {
             ArchiveEvent::Extracting(name, size) => {
@@ -147,6 +190,18 @@ impl<'a> EventHandler for &'a NuSetup {
                     println!("Extracting {}", name);
                 }
             }
+            ArchiveEvent::EntryProgress(name, copied, size) => {
+                let copied = Byte::from(copied).get_appropriate_unit(UnitType::Both);
+                match size {
+                    Some(size) => println!(
+                        "Extracting {}: {} of {}",
+                        name,
+                        copied,
+                        Byte::from(size).get_appropriate_unit(UnitType::Both)
+                    ),
+                    None => println!("Extracting {}: {}", name, copied),
+                }
+            }
             ArchiveEvent::DoneExtracting(name, path) => {
                 println!("Done extracting {} to {}", name, path);
             }
@@ -161,7 +216,28 @@ impl<'a> EventHandler for &'a NuSetup {
                 SkipReason::NotInFiles => println!("Skipped file {} not in files", name),
                 SkipReason::AlreadyExists => println!("Skipped file {} already exists", name),
                 SkipReason::UnknownType => println!("Skipped file {} with unknown type", name),
+                SkipReason::UnsafePath => {
+                    println!("Skipped file {} outside the destination", name)
+                }
+                SkipReason::IfOlder => {
+                    println!("Skipped file {} at least as recent as the entry", name)
+                }
+                SkipReason::Unreadable => println!("Skipped unreadable file {}", name),
+                SkipReason::ArchiveItself => {
+                    println!("Skipped file {}, the archive itself", name)
+                }
+                SkipReason::NoValidPassword => {
+                    println!("Skipped encrypted file {} without a valid password", name)
+                }
+                SkipReason::Rejected(reason) => {
+                    println!("Skipped rejected file {}: {}", name, reason)
+                }
             },
+            ArchiveEvent::Added(name, _) => println!("Added {}", name),
+            ArchiveEvent::DoneCreating(path) => println!("Done creating {}", path),
+            ArchiveEvent::Retrying(name, retry, e) => {
+                eprintln!("Retrying {} (attempt {}) after error: {}", name, retry, e)
+            }
             ArchiveEvent::Log(msg) => println!("{}", msg),
         }
     }
@@ -0,0 +1,119 @@
+use std::{
+    env,
+    path::PathBuf,
+    sync::mpsc::{self, RecvTimeoutError},
+    time::Duration,
+};
+
+use chrono::Local;
+use hezi::archive::name_template::expand_name_template;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+
+use crate::{is_excluded, CreateArgs, ShellError};
+
+/// Creates the archive described by `create`, then re-creates it whenever the files it is made
+/// of change, once no change happened for `debounce`. Runs until interrupted.
+pub(crate) fn watch(
+    mut create: CreateArgs,
+    debounce: Duration,
+    mut exclude: Vec<glob::Pattern>,
+) -> Result<(), ShellError> {
+    create.overwrite = true;
+
+    let cwd = env::current_dir()?;
+    let sources = match &create.files {
+        Some(files) => files
+            .iter()
+            .map(|f| f.canonicalize())
+            .collect::<Result<Vec<_>, _>>()?,
+        None => {
+            let walk_source = create.directory.is_some() || create.map.is_empty();
+            let source = create.directory.clone().unwrap_or_else(|| cwd.clone());
+            walk_source
+                .then_some(source)
+                .into_iter()
+                .chain(create.map.iter().map(|r| r.root.clone()))
+                .map(|dir| dir.canonicalize())
+                .collect::<Result<Vec<_>, _>>()?
+        }
+    };
+
+    // writing the archive must not trigger another update, nor end up in the archive itself
+    let destination = absolute(
+        &cwd,
+        &expand_name_template(&create.archive_path, Local::now().fixed_offset())?,
+    );
+    exclude.push(
+        glob::Pattern::new(&glob::Pattern::escape(&destination.to_string_lossy()))
+            .map_err(|e| ShellError::InvalidArgument(e.to_string()))?,
+    );
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).map_err(watch_error)?;
+    for source in &sources {
+        watcher
+            .watch(source, RecursiveMode::Recursive)
+            .map_err(watch_error)?;
+    }
+
+    let bases = sources.iter().collect::<Vec<_>>();
+    let relevant = |event: &notify::Result<Event>| match event {
+        Ok(event) => {
+            !matches!(event.kind, EventKind::Access(_))
+                && event
+                    .paths
+                    .iter()
+                    .any(|p| !is_excluded(p, &bases, &exclude))
+        }
+        Err(e) => {
+            eprintln!("Watch error: {}", e);
+            false
+        }
+    };
+
+    create.clone().run(&exclude)?;
+    loop {
+        println!(
+            "Watching {} for changes",
+            sources
+                .iter()
+                .map(|s| s.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+
+        // wait for a change, then for things to settle
+        loop {
+            match rx.recv() {
+                Ok(event) if relevant(&event) => break,
+                Ok(_) => {}
+                Err(_) => return Ok(()),
+            }
+        }
+        loop {
+            match rx.recv_timeout(debounce) {
+                Ok(_) => {}
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+        }
+
+        if let Err(e) = create.clone().run(&exclude) {
+            eprintln!("Failed to update {}: {}", destination.display(), e);
+        }
+    }
+}
+
+/// Absolute form of `path`, canonicalizing its parent so that it compares equal to the paths
+/// reported by the watcher.
+fn absolute(cwd: &std::path::Path, path: &str) -> PathBuf {
+    let path = cwd.join(path);
+    match (path.parent().map(|p| p.canonicalize()), path.file_name()) {
+        (Some(Ok(parent)), Some(name)) => parent.join(name),
+        _ => path,
+    }
+}
+
+fn watch_error(e: notify::Error) -> ShellError {
+    ShellError::Io(std::io::Error::other(e))
+}
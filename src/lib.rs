@@ -1,3 +1,81 @@
 #![deny(clippy::unwrap_used)]
+// builds leaving backends out, to keep the binary small, leave the helpers only they use unused
+#![cfg_attr(
+    not(feature = "all_archive_formats"),
+    allow(dead_code, unused_imports, unused_variables, unreachable_code)
+)]
 
 pub mod archive;
+pub mod output;
+pub mod prelude;
+
+#[cfg(feature = "bindings")]
+pub mod bindings;
+
+#[cfg(feature = "bindings")]
+uniffi::setup_scaffolding!();
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use std::process::Command;
+
+    /// Features built on their own by `builds_with_single_features`, the frontends with a format
+    /// to work with, `iso_archive` left out as it needs libfuse.
+    const FEATURES: &[&str] = &[
+        "",
+        "multithreading",
+        "zip_archive",
+        "tar_archive",
+        "sevenz_archive",
+        "rar_archive",
+        "cpio_archive",
+        "ar_archive",
+        "squashfs_archive",
+        "cab_archive",
+        "wim_archive",
+        "lha_archive",
+        "arj_archive",
+        "msi_archive",
+        "nsis_archive",
+        "zpaq_archive",
+        "dmg_archive",
+        "disk_image",
+        "warc_archive",
+        "compressed_file",
+        "zstd_codecs",
+        "bzip2_codecs",
+        "aes_codecs",
+        "deflate_codecs",
+        "lzma_codecs",
+        "lzw_codecs",
+        "keyring",
+        "watch",
+        "notifications",
+        "bindings",
+        "cli,zip_archive",
+        "nu_plugin,tar_archive",
+    ];
+
+    /// Builds and tests the library with each feature alone, as embedded users minimizing its size
+    /// would, denying warnings. Slow, run by the `features` workflow, or locally with
+    /// `cargo test -- --ignored`.
+    #[ignore]
+    #[test]
+    fn builds_with_single_features() {
+        let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+        let manifest_dir = env!("CARGO_MANIFEST_DIR");
+        for features in FEATURES {
+            let status = Command::new(&cargo)
+                .current_dir(manifest_dir)
+                .args(["test", "--lib", "--no-default-features", "--features"])
+                .arg(features)
+                // a target directory of its own, not to wait on the lock of the running tests
+                .args(["--target-dir", "target/minimal"])
+                .env("RUSTFLAGS", "-D warnings")
+                .status()
+                .unwrap();
+            assert!(status.success(), "build with `{}` failed", features);
+        }
+    }
+}
@@ -3,16 +3,16 @@ use nu_protocol::{CustomValue, LabeledError, Value};
 
 use hezi::archive::{Archive, Archived, DataSource, ListOptions};
 
-pub fn from_xx_archive<'a>(
+pub fn from_xx_archive(
     _name: &str,
     _call: &EvaluatedCall,
-    input: &'a Value,
+    input: &Value,
 ) -> Result<Value, LabeledError> {
     let span = input.span();
 
     // eprintln!("input type: {:?}", input.get_type());
 
-    let datasource: DataSource<'a> = DataSource::try_from(input)
+    let datasource: DataSource = DataSource::try_from(input)
         .map_err(|_e| LabeledError::new("could not convert value to datasource"))?;
 
     // eprintln!("datasource: {}", datasource);
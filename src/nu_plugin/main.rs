@@ -1,4 +1,6 @@
 #![deny(clippy::unwrap_used)]
+// `LabeledError` is dictated by the nushell plugin API.
+#![allow(clippy::result_large_err)]
 mod from;
 mod plugin;
 
@@ -1,15 +1,23 @@
-use std::{path::PathBuf, vec};
+use std::{path::PathBuf, time::Instant, vec};
 
 use nu_plugin::{EvaluatedCall, Plugin};
 use nu_protocol::{
-    CustomValue, IntoPipelineData, LabeledError, Record, Signature, SyntaxShape, Type, Value,
+    CustomValue, IntoPipelineData, LabeledError, Record, Signature, Span, SyntaxShape, Type, Value,
 };
 
 use hezi::archive::{
-    Archive, ArchiveCompression, ArchiveType, Archived, CreateOptions, DataSource, ExtractOptions,
-    ListOptions, OpenOptions, SimpleLogger,
+    diff::{DiffEntry, DiffOptions},
+    integrity::VerifyOptions,
+    nu_protocol_serialization::{to_versioned_value, ToDateOrNothingValue, ToFilesize},
+    password::StaticPassword,
+    retry::IoRetryPolicy,
+    tree::EntryTree,
+    walk_paths, Archive, ArchiveCompression, ArchiveError, ArchiveFileEntity, ArchiveType,
+    Archived, CodecParams, CompressionSpec, CreateOptions, DataSource, ExtractOptions,
+    GzipStrategy, ListOptions, OpenOptions, OpenOutput, SimpleLogger,
 };
 
+use hezi::output;
 
 use crate::from::from_xx_archive;
 
@@ -23,6 +31,153 @@ fn archive_list_record_type() -> Type {
         ("type".into(), Type::String),
         ("last_modified".into(), Type::Date),
         ("compression".into(), Type::String),
+        ("ratio".into(), Type::String),
+    ])
+}
+
+fn stats_type() -> Type {
+    Type::Record(vec![
+        ("duration".into(), Type::Int),
+        ("bytes".into(), Type::Int),
+        ("entries".into(), Type::Int),
+    ])
+}
+
+fn extract_report_type() -> Type {
+    let outcomes = |detail: &str| {
+        Type::Table(vec![
+            ("name".into(), Type::String),
+            (detail.into(), Type::String),
+        ])
+    };
+    Type::Record(vec![
+        ("schema_version".into(), Type::Int),
+        ("extracted".into(), Type::List(Box::new(Type::String))),
+        ("skipped".into(), outcomes("reason")),
+        ("failed".into(), outcomes("error")),
+        ("stats".into(), stats_type()),
+    ])
+}
+
+fn entry_tree_type() -> Type {
+    Type::Table(vec![
+        ("name".into(), Type::String),
+        ("path".into(), Type::String),
+        ("type".into(), Type::String),
+        ("size".into(), Type::Filesize),
+        ("compressed_size".into(), Type::Filesize),
+        ("last_modified".into(), Type::Date),
+        ("files".into(), Type::Int),
+        // the entries of a directory, a table of the same columns
+        ("children".into(), Type::List(Box::new(Type::Any))),
+    ])
+}
+
+fn entry_tree_value(tree: &EntryTree, span: Span) -> Value {
+    Value::record(
+        Record::from_iter(vec![
+            ("name".to_string(), Value::string(&tree.name, span)),
+            ("path".to_string(), Value::string(&tree.path, span)),
+            (
+                "type".to_string(),
+                Value::string(tree.fstype.to_string(), span),
+            ),
+            ("size".to_string(), Value::filesize(tree.size as i64, span)),
+            (
+                "compressed_size".to_string(),
+                tree.compressed_size.to_filesize_value(span),
+            ),
+            (
+                "last_modified".to_string(),
+                tree.last_modified.to_date_value(span),
+            ),
+            ("files".to_string(), Value::int(tree.files() as i64, span)),
+            (
+                "children".to_string(),
+                Value::list(
+                    tree.children
+                        .iter()
+                        .map(|child| entry_tree_value(child, span))
+                        .collect(),
+                    span,
+                ),
+            ),
+        ]),
+        span,
+    )
+}
+
+fn diff_type() -> Type {
+    Type::Table(vec![
+        ("name".into(), Type::String),
+        ("kind".into(), Type::String),
+        ("fields".into(), Type::List(Box::new(Type::String))),
+        ("old_size".into(), Type::Filesize),
+        ("new_size".into(), Type::Filesize),
+        ("old_modified".into(), Type::Date),
+        ("new_modified".into(), Type::Date),
+    ])
+}
+
+fn diff_value(diff: &[DiffEntry], span: Span) -> Value {
+    let size = |entity: Option<&ArchiveFileEntity>| entity.and_then(|e| e.size());
+    let modified = |entity: Option<&ArchiveFileEntity>| entity.and_then(|e| e.last_modified());
+    Value::list(
+        diff.iter()
+            .map(|entry| {
+                let (old, new) = (entry.old.as_ref(), entry.new.as_ref());
+                Value::record(
+                    Record::from_iter(vec![
+                        ("name".to_string(), Value::string(&entry.name, span)),
+                        (
+                            "kind".to_string(),
+                            Value::string(entry.kind.to_string(), span),
+                        ),
+                        (
+                            "fields".to_string(),
+                            Value::list(
+                                entry
+                                    .fields
+                                    .iter()
+                                    .map(|f| Value::string(f.to_string(), span))
+                                    .collect(),
+                                span,
+                            ),
+                        ),
+                        ("old_size".to_string(), size(old).to_filesize_value(span)),
+                        ("new_size".to_string(), size(new).to_filesize_value(span)),
+                        (
+                            "old_modified".to_string(),
+                            modified(old).to_date_value(span),
+                        ),
+                        (
+                            "new_modified".to_string(),
+                            modified(new).to_date_value(span),
+                        ),
+                    ]),
+                    span,
+                )
+            })
+            .collect(),
+        span,
+    )
+}
+
+fn verify_report_type() -> Type {
+    Type::Record(vec![
+        ("schema_version".into(), Type::Int),
+        ("passed".into(), Type::Bool),
+        ("files".into(), Type::Int),
+        ("checked".into(), Type::Int),
+        ("problems".into(), Type::List(Box::new(Type::String))),
+        (
+            "entries".into(),
+            Type::Table(vec![
+                ("name".into(), Type::String),
+                ("passed".into(), Type::Bool),
+                ("error".into(), Type::String),
+            ]),
+        ),
     ])
 }
 
@@ -46,14 +201,18 @@ const ARCHIVE_EXTENSIONS: &[&str] = &[
     "tar.bz2", "tbz2", "tbz", // Tar (bzip2)
     "tar.zst", "tzst", "tzs", "tar.zstd", // Tar (zstd)
     "tar.lzma", "tlzma", "tlz", // Tar (lzma)
+    "tar.Z", "taz", // Tar (compress)
     "7z", "7zip", // 7z
+    "jar", "war", "apk", "ipa", "docx", "xlsx", "epub", "odt", // Zip (packages and documents)
 ];
 
 fn archive_create_record_type() -> Type {
-    Type::Table(vec![
+    Type::Record(vec![
+        ("schema_version".into(), Type::Int),
         ("path".into(), Type::String),
-        ("total_size".into(), Type::Filesize),
-        ("compressed_size".into(), Type::Filesize),
+        ("total_size".into(), Type::Int),
+        ("compressed_size".into(), Type::Int),
+        ("stats".into(), stats_type()),
     ])
 }
 
@@ -61,9 +220,12 @@ impl Plugin for ArchivePlugin {
     fn commands(&self) -> Vec<Box<dyn nu_plugin::PluginCommand<Plugin = Self>>> {
         let mut commands: Vec<Box<dyn nu_plugin::PluginCommand<Plugin = Self>>> = vec![
             Box::new(ArchiveList),
+            Box::new(ArchiveTree),
             Box::new(ArchiveMetadata),
             Box::new(ArchiveCreate),
             Box::new(ArchiveExtract),
+            Box::new(ArchiveTest),
+            Box::new(ArchiveDiff),
             Box::new(ArchiveOpen),
         ];
         commands.extend(ARCHIVE_EXTENSIONS.iter().map(|ext| {
@@ -165,7 +327,7 @@ impl nu_plugin::PluginCommand for ArchiveOpen {
             .strip_prefix(&current_dir)
             .map_err(|_e| LabeledError::new("invalid path"))?;
 
-        let password = call.get_flag::<String>("password")?;
+        let password = StaticPassword::boxed(call.get_flag::<String>("password")?);
 
         let datasource = DataSource::file(&archive_path)
             .map_err(|_e| LabeledError::new("could not open file"))?;
@@ -181,11 +343,17 @@ impl nu_plugin::PluginCommand for ArchiveOpen {
 
         archive
             .open(OpenOptions {
-                path: path.into(),
+                paths: vec![path.into()],
                 dest: Box::new(std::io::stderr()),
                 password,
+                output: OpenOutput::default(),
             })
-            .map_err(|_e| LabeledError::new("could not open archive"))?;
+            .map_err(|e| match e {
+                ArchiveError::EntryNotFound(..) => {
+                    LabeledError::new("entry not found").with_help(e.to_string())
+                }
+                _ => LabeledError::new("could not open archive"),
+            })?;
 
         Ok(Value::nothing(call.head).into_pipeline_data())
     }
@@ -223,26 +391,37 @@ impl nu_plugin::PluginCommand for ArchiveExtract {
         let archive =
             Archive::of(datasource).map_err(|_e| LabeledError::new("could not open archive"))?;
 
-        archive
+        let report = archive
             .extract(ExtractOptions {
                 destination: dest.into(),
-                password: call.get_flag::<String>("password")?,
+                password: StaticPassword::boxed(call.get_flag::<String>("password")?),
                 files: call.get_flag::<Vec<String>>("files")?,
                 overwrite: call.has_flag("overwrite")?,
+                update: call.has_flag("update")?,
+                file_mode: None,
+                dir_mode: None,
+                preserve_permissions: false,
                 show_hidden: true,
+                retry: IoRetryPolicy::default(),
+                transformers: vec![],
+                inspector: None,
+                adaptive: false,
+                cache: None,
+                progress_interval: None,
+                limits: Default::default(),
                 event_handler: Box::new(SimpleLogger),
             })
             .map_err(|_e| LabeledError::new("could not extract archive"))?;
 
-        Ok(Value::nothing(call.head).into_pipeline_data())
+        Ok(to_versioned_value(output::Extract::from(&report), call.head)?.into_pipeline_data())
     }
 
     fn signature(&self) -> Signature {
         Signature::build("archive extract")
             .usage("Extract an archive")
             .input_output_types(vec![
-                (Type::String, Type::Nothing),
-                (Type::Nothing, Type::Nothing),
+                (Type::String, extract_report_type()),
+                (Type::Nothing, extract_report_type()),
             ])
             .optional("archive", SyntaxShape::String, "archive to extract")
             .required(
@@ -264,6 +443,173 @@ impl nu_plugin::PluginCommand for ArchiveExtract {
             )
             .switch("silent", "do not print anything", Some('s'))
             .switch("overwrite", "overwrite existing files", Some('f'))
+            .switch(
+                "update",
+                "only replace existing files with entries modified more recently",
+                Some('u'),
+            )
+    }
+}
+
+struct ArchiveTest;
+
+impl nu_plugin::PluginCommand for ArchiveTest {
+    fn name(&self) -> &str {
+        "archive test"
+    }
+
+    fn usage(&self) -> &str {
+        "Test the integrity of an archive by decompressing its files"
+    }
+
+    type Plugin = ArchivePlugin;
+
+    fn run(
+        &self,
+        _plugin: &Self::Plugin,
+        _engine: &nu_plugin::EngineInterface,
+        call: &EvaluatedCall,
+        input: nu_protocol::PipelineData,
+    ) -> Result<nu_protocol::PipelineData, nu_protocol::LabeledError> {
+        let path = if let Some(path) = call.positional.first() {
+            path.coerce_string()?
+        } else {
+            input.into_value(call.head).coerce_into_string()?
+        };
+        let datasource =
+            DataSource::file(&path).map_err(|_e| LabeledError::new("could not open file"))?;
+
+        let archive =
+            Archive::of(datasource).map_err(|_e| LabeledError::new("could not open archive"))?;
+
+        let percent = match call.get_flag::<i64>("percent")? {
+            Some(percent) => u8::try_from(percent)
+                .ok()
+                .filter(|p| (1..=100).contains(p))
+                .ok_or_else(|| LabeledError::new("invalid percent"))?,
+            None => VerifyOptions::default().percent,
+        };
+        let seed = call
+            .get_flag::<i64>("seed")?
+            .map(|seed| u64::try_from(seed).map_err(|_e| LabeledError::new("invalid seed")))
+            .transpose()?;
+        let report = archive
+            .verify(VerifyOptions {
+                password: StaticPassword::boxed(call.get_flag::<String>("password")?),
+                quick: call.has_flag("quick")?,
+                percent,
+                seed,
+            })
+            .map_err(|e| LabeledError::new("could not test archive").with_help(e.to_string()))?;
+
+        Ok(to_versioned_value(output::Test::from(&report), call.head)?.into_pipeline_data())
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build("archive test")
+            .usage("Test the integrity of an archive by decompressing its files")
+            .input_output_types(vec![
+                (Type::String, verify_report_type()),
+                (Type::Nothing, verify_report_type()),
+            ])
+            .optional("archive", SyntaxShape::String, "archive to test")
+            .named(
+                "password",
+                SyntaxShape::String,
+                "password of the archive",
+                Some('p'),
+            )
+            .switch(
+                "quick",
+                "check the central directory of a zip archive and only decompress a share of its files",
+                Some('q'),
+            )
+            .named(
+                "percent",
+                SyntaxShape::Int,
+                "percentage of the files decompressed by --quick",
+                None,
+            )
+            .named(
+                "seed",
+                SyntaxShape::Int,
+                "seed of the files picked by --quick, to check the same files again",
+                None,
+            )
+    }
+}
+
+struct ArchiveDiff;
+
+impl nu_plugin::PluginCommand for ArchiveDiff {
+    fn name(&self) -> &str {
+        "archive diff"
+    }
+
+    fn usage(&self) -> &str {
+        "Compare two archives, returning the entries added, removed or changed"
+    }
+
+    type Plugin = ArchivePlugin;
+
+    fn run(
+        &self,
+        _plugin: &Self::Plugin,
+        _engine: &nu_plugin::EngineInterface,
+        call: &EvaluatedCall,
+        _input: nu_protocol::PipelineData,
+    ) -> Result<nu_protocol::PipelineData, nu_protocol::LabeledError> {
+        let open = |path: String| {
+            let datasource =
+                DataSource::file(&path).map_err(|_e| LabeledError::new("could not open file"))?;
+            Archive::of(datasource).map_err(|_e| LabeledError::new("could not open archive"))
+        };
+        let old = open(call.req::<String>(0)?)?;
+        let new = open(call.req::<String>(1)?)?;
+
+        let password = call.get_flag::<String>("password")?;
+        let new_password = call
+            .get_flag::<String>("new-password")?
+            .or(password.clone());
+        let diff = old
+            .diff(
+                &new,
+                DiffOptions {
+                    password: StaticPassword::boxed(password),
+                    other_password: StaticPassword::boxed(new_password),
+                    content: call.has_flag("content")?,
+                },
+            )
+            .map_err(|e| {
+                LabeledError::new("could not compare archives").with_help(e.to_string())
+            })?;
+
+        Ok(diff_value(&diff, call.head).into_pipeline_data())
+    }
+
+    fn signature(&self) -> Signature {
+        Signature::build("archive diff")
+            .usage("Compare two archives, returning the entries added, removed or changed")
+            .input_output_types(vec![(Type::Nothing, diff_type())])
+            .required("old", SyntaxShape::String, "the old archive")
+            .required("new", SyntaxShape::String, "the new archive")
+            .switch(
+                "content",
+                "compare the SHA-256 of the files too, reading both archives whole",
+                Some('c'),
+            )
+            .named(
+                "password",
+                SyntaxShape::String,
+                "password of the old archive",
+                Some('p'),
+            )
+            .named(
+                "new-password",
+                SyntaxShape::String,
+                "password of the new archive, the one of the old archive if not given",
+                None,
+            )
     }
 }
 
@@ -321,6 +667,31 @@ impl nu_plugin::PluginCommand for ArchiveCreate {
                 "compression method to use",
                 Some('c'),
             )
+            .named("level", SyntaxShape::Int, "compression level", Some('l'))
+            .named(
+                "threads",
+                SyntaxShape::Int,
+                "number of threads compression may use",
+                None,
+            )
+            .named(
+                "zstd-long",
+                SyntaxShape::Int,
+                "window of the long-distance matching of zstd, as a power of two",
+                None,
+            )
+            .named(
+                "gzip-strategy",
+                SyntaxShape::String,
+                "how gzip looks for repetitions: filtered, huffman-only, rle or fixed",
+                None,
+            )
+            .named(
+                "bzip2-work-factor",
+                SyntaxShape::Int,
+                "effort of bzip2 on highly repetitive data, between 1 and 250",
+                None,
+            )
             .switch("overwrite", "overwrite existing files", Some('f'))
     }
 
@@ -355,6 +726,7 @@ impl nu_plugin::PluginCommand for ArchiveCreate {
             .flatten()
             .flat_map(|f| f.canonicalize())
             .collect::<Vec<_>>();
+        let resolved_files = walk_paths(&resolved_files);
 
         let dest = if let Some(p) = call.positional.first() {
             p.coerce_string()?
@@ -391,40 +763,57 @@ impl nu_plugin::PluginCommand for ArchiveCreate {
         let (archive_type, guessed_compression) = ArchiveType::guess_from_filename(&dest)
             .map_err(|_e| LabeledError::new("could not guess archive type"))?;
 
+        let int_flag = |name: &str| -> Result<Option<u32>, LabeledError> {
+            call.get_flag::<i64>(name)?
+                .map(|v| {
+                    u32::try_from(v).map_err(|_e| LabeledError::new(format!("invalid {}", name)))
+                })
+                .transpose()
+        };
+        let compression = compression_arg
+            .or(guessed_compression)
+            .map(|algorithm| -> Result<_, LabeledError> {
+                Ok(CompressionSpec {
+                    algorithm,
+                    level: int_flag("level")?.map(|l| l as i32),
+                    threads: int_flag("threads")?,
+                    extra: CodecParams {
+                        zstd_long: int_flag("zstd-long")?,
+                        gzip_strategy: call.get_flag::<GzipStrategy>("gzip-strategy")?,
+                        bzip2_work_factor: int_flag("bzip2-work-factor")?,
+                    },
+                })
+            })
+            .transpose()?;
+
+        let file_count = resolved_files.len();
         let options = CreateOptions {
             destination: PathBuf::from(dest),
             password,
             files: resolved_files,
             overwrite,
             source: PathBuf::from(source_path),
+            roots: vec![],
             archive_type,
-            archive_compression: compression_arg.or(guessed_compression),
+            compression,
             include_hidden: true,
+            skip_unreadable: false,
+            verify_after: false,
+            volume_size: None,
+            update: false,
+            sevenz: Default::default(),
+            zip: Default::default(),
+            tar: Default::default(),
             event_handler: Box::new(SimpleLogger),
         };
 
+        let started = Instant::now();
         let res =
             Archive::create(options).map_err(|_e| LabeledError::new("could not create archive"))?;
+        let entries = file_count.saturating_sub(res.unreadable.len());
 
-        Ok(Value::Record {
-            val: Record::from_iter(vec![
-                (
-                    "path".to_string(),
-                    Value::string(res.path.to_string_lossy().to_string(), call.head),
-                ),
-                (
-                    "total_size".to_string(),
-                    Value::filesize(res.total_size as i64, call.head),
-                ),
-                (
-                    "compressed_size".to_string(),
-                    Value::filesize(res.compressed_size as i64, call.head),
-                ),
-            ])
-            .into(),
-            internal_span: call.head,
-        }
-        .into_pipeline_data())
+        let result = output::Create::new(&res, started.elapsed(), entries);
+        Ok(to_versioned_value(result, call.head)?.into_pipeline_data())
     }
 }
 
@@ -502,6 +891,18 @@ impl nu_plugin::PluginCommand for ArchiveList {
                 (Type::Nothing, archive_list_record_type()),
             ])
             .optional("archive", SyntaxShape::String, "archive to list")
+            .named(
+                "prefix",
+                SyntaxShape::String,
+                "only list the entries under this directory of the archive",
+                None,
+            )
+            .named(
+                "password",
+                SyntaxShape::String,
+                "password of the archive",
+                Some('p'),
+            )
     }
 
     fn run(
@@ -522,11 +923,15 @@ impl nu_plugin::PluginCommand for ArchiveList {
         let archive =
             Archive::of(datasource).map_err(|_e| LabeledError::new("could not open archive"))?;
 
-        let list = archive.list(ListOptions::default());
+        let list = archive.list(ListOptions {
+            prefix: call.get_flag::<String>("prefix")?,
+            password: StaticPassword::boxed(call.get_flag::<String>("password")?),
+            ..Default::default()
+        });
 
         Ok(Value::List {
             vals: list
-                .map_err(|_e| LabeledError::new("could not list archive"))
+                .map_err(|e| LabeledError::new("could not list archive").with_help(e.to_string()))
                 .and_then(|f| {
                     f.iter()
                         .map(|f| f.to_base_value(call.head))
@@ -539,6 +944,78 @@ impl nu_plugin::PluginCommand for ArchiveList {
     }
 }
 
+struct ArchiveTree;
+
+impl nu_plugin::PluginCommand for ArchiveTree {
+    fn name(&self) -> &str {
+        "archive tree"
+    }
+
+    fn usage(&self) -> &str {
+        "List the contents of an archive as a tree of directories"
+    }
+
+    type Plugin = ArchivePlugin;
+
+    fn signature(&self) -> nu_protocol::Signature {
+        Signature::build("archive tree")
+            .usage("List the contents of an archive as a tree of directories")
+            .input_output_types(vec![
+                (Type::String, entry_tree_type()),
+                (Type::Nothing, entry_tree_type()),
+            ])
+            .optional("archive", SyntaxShape::String, "archive to list")
+            .named(
+                "prefix",
+                SyntaxShape::String,
+                "only list the entries under this directory of the archive",
+                None,
+            )
+            .named(
+                "password",
+                SyntaxShape::String,
+                "password of the archive",
+                Some('p'),
+            )
+    }
+
+    fn run(
+        &self,
+        _plugin: &Self::Plugin,
+        _engine: &nu_plugin::EngineInterface,
+        call: &EvaluatedCall,
+        input: nu_protocol::PipelineData,
+    ) -> Result<nu_protocol::PipelineData, nu_protocol::LabeledError> {
+        let path = if let Some(path) = call.positional.first() {
+            path.coerce_string()?
+        } else {
+            input.into_value(call.head).coerce_into_string()?
+        };
+        let datasource =
+            DataSource::file(&path).map_err(|_e| LabeledError::new("could not open file"))?;
+
+        let archive =
+            Archive::of(datasource).map_err(|_e| LabeledError::new("could not open archive"))?;
+
+        let entries = archive
+            .list(ListOptions {
+                prefix: call.get_flag::<String>("prefix")?,
+                password: StaticPassword::boxed(call.get_flag::<String>("password")?),
+                ..Default::default()
+            })
+            .map_err(|e| LabeledError::new("could not list archive").with_help(e.to_string()))?;
+
+        Ok(Value::list(
+            EntryTree::build(&entries)
+                .iter()
+                .map(|tree| entry_tree_value(tree, call.head))
+                .collect(),
+            call.head,
+        )
+        .into_pipeline_data())
+    }
+}
+
 fn compute_deepest_common_directory(paths: &[PathBuf]) -> Option<Vec<std::path::Component<'_>>> {
     paths
         .iter()
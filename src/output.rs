@@ -0,0 +1,683 @@
+//! Documents printed by `hezi --json` and returned by the nu plugin, in a schema versioned apart
+//! from the library types they are built from, so that scripts reading them keep working as those
+//! types evolve. Every document carries its `schema_version`; fields may be added within a version,
+//! but are only renamed, removed or changed in meaning along with a new one.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::archive::{
+    diff::DiffEntry,
+    identify::Identification,
+    integrity::VerifyReport,
+    manifest::{self, ManifestDiff},
+    sample::SampledEntry,
+    ArchiveFileEntity, ArchiveMetadata, CreateResult, ExtractReport, RawEntryLocation,
+    UnixAttributes,
+};
+
+/// Version of the schema of the documents written by this build.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// A document, `data` with the version of its schema next to its fields.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Versioned<T> {
+    pub schema_version: u32,
+    #[serde(flatten)]
+    pub data: T,
+}
+
+impl<T> Versioned<T> {
+    pub fn new(data: T) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            data,
+        }
+    }
+}
+
+impl<T: Serialize> Versioned<T> {
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+}
+
+/// Dates of the documents, in RFC 3339 with `Z` for UTC.
+pub fn rfc3339(date: &chrono::DateTime<chrono::FixedOffset>) -> String {
+    date.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true)
+}
+
+/// An entry of an archive.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    pub size: Option<u64>,
+    pub compressed_size: Option<u64>,
+    /// RFC 3339 date, or as formatted by `hezi list --date-format`.
+    pub last_modified: Option<String>,
+    pub compression: Option<String>,
+    /// One of `file`, `dir`, `symlink` or `unknown`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Where the entry lies in the archive, for the zip and tar archives.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<RawEntryLocation>,
+    /// Permissions and owner of the entry, for the ISO images with Rock Ridge extensions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unix: Option<UnixAttributes>,
+}
+
+impl From<&ArchiveFileEntity> for Entry {
+    fn from(entity: &ArchiveFileEntity) -> Self {
+        Entry {
+            name: entity.name().to_string(),
+            size: entity.size(),
+            compressed_size: entity.compressed_size(),
+            last_modified: entity.last_modified().as_ref().map(rfc3339),
+            compression: entity.compression().map(str::to_string),
+            kind: entity.fstype().to_string(),
+            raw: entity.raw().copied(),
+            unix: entity.unix().copied(),
+        }
+    }
+}
+
+/// Output of `hezi list`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct List {
+    pub entries: Vec<Entry>,
+}
+
+/// Output of the plugin's `archive metadata`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Metadata {
+    pub archive_type: String,
+    pub total_size: u64,
+    pub compressed_size: u64,
+    pub compression: Option<String>,
+    pub entry_count: usize,
+    pub created: Option<String>,
+    pub modified: Option<String>,
+    pub entries: Vec<Entry>,
+    /// Details of the format, such as the comment of zip archives, in a shape of its own.
+    pub additional: Option<serde_json::Value>,
+}
+
+impl From<&ArchiveMetadata> for Metadata {
+    fn from(metadata: &ArchiveMetadata) -> Self {
+        Metadata {
+            archive_type: metadata.archive_type.to_string(),
+            total_size: metadata.total_size,
+            compressed_size: metadata.compressed_size,
+            compression: metadata.compression.as_ref().map(|c| c.to_string()),
+            entry_count: metadata.entry_count,
+            created: metadata.created.as_ref().map(rfc3339),
+            modified: metadata.modified.as_ref().map(rfc3339),
+            entries: metadata.entries.iter().map(Entry::from).collect(),
+            additional: metadata.additional.clone(),
+        }
+    }
+}
+
+/// A file told apart by `hezi id`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IdentifiedFile {
+    pub path: String,
+    #[serde(rename = "type")]
+    pub archive_type: Option<String>,
+    /// Compressions wrapping the archive, outermost first.
+    pub compression: Vec<String>,
+    pub encrypted: Option<bool>,
+    /// One of `low`, `medium` or `high`.
+    pub confidence: Option<String>,
+}
+
+impl IdentifiedFile {
+    pub fn new(path: String, id: &Identification) -> Self {
+        IdentifiedFile {
+            path,
+            archive_type: id.archive_type.map(|t| t.to_string()),
+            compression: id.compression.iter().map(|c| c.to_string()).collect(),
+            encrypted: id.encrypted,
+            confidence: id.confidence.map(|c| c.to_string()),
+        }
+    }
+}
+
+/// Output of `hezi id`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Identified {
+    pub files: Vec<IdentifiedFile>,
+}
+
+/// A file picked by `hezi sample`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sampled {
+    #[serde(flatten)]
+    pub entry: Entry,
+    /// Start of the content, as text if it is some, else as hexadecimal bytes.
+    pub preview: String,
+}
+
+/// Output of `hezi sample`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub entries: Vec<Sampled>,
+}
+
+impl From<&[SampledEntry]> for Sample {
+    fn from(sample: &[SampledEntry]) -> Self {
+        Sample {
+            entries: sample
+                .iter()
+                .map(|s| Sampled {
+                    entry: Entry::from(&s.entity),
+                    preview: s.preview.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A file checked by `hezi test`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CheckedEntry {
+    pub name: String,
+    pub passed: bool,
+    pub error: Option<String>,
+}
+
+/// Output of `hezi test` and of the plugin's `archive test`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Test {
+    pub passed: bool,
+    pub files: usize,
+    pub checked: usize,
+    pub problems: Vec<String>,
+    pub entries: Vec<CheckedEntry>,
+}
+
+impl From<&VerifyReport> for Test {
+    fn from(report: &VerifyReport) -> Self {
+        Test {
+            passed: report.passed(),
+            files: report.files,
+            checked: report.checked,
+            problems: report.problems.clone(),
+            entries: report
+                .entries
+                .iter()
+                .map(|e| CheckedEntry {
+                    name: e.name.clone(),
+                    passed: e.passed(),
+                    error: e.error.clone(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Output of `hezi verify --ci`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestCheck {
+    pub archive: String,
+    pub manifest: String,
+    pub passed: bool,
+    pub missing: Vec<String>,
+    pub unexpected: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl ManifestCheck {
+    pub fn new(archive: String, manifest: String, diff: &ManifestDiff) -> Self {
+        ManifestCheck {
+            archive,
+            manifest,
+            passed: diff.is_clean(),
+            missing: diff.missing.clone(),
+            unexpected: diff.unexpected.clone(),
+            changed: diff.changed.clone(),
+        }
+    }
+}
+
+/// An entry added, removed or changed, found by `hezi diff`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiffedEntry {
+    pub name: String,
+    /// One of `added`, `removed` or `changed`.
+    pub kind: String,
+    /// What changed, among `type`, `size`, `modified` and `content`.
+    pub fields: Vec<String>,
+    pub old: Option<Entry>,
+    pub new: Option<Entry>,
+}
+
+/// Output of `hezi diff` and of the plugin's `archive diff`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diff {
+    pub entries: Vec<DiffedEntry>,
+}
+
+impl From<&[DiffEntry]> for Diff {
+    fn from(diff: &[DiffEntry]) -> Self {
+        Diff {
+            entries: diff
+                .iter()
+                .map(|d| DiffedEntry {
+                    name: d.name.clone(),
+                    kind: d.kind.to_string(),
+                    fields: d.fields.iter().map(|f| f.to_string()).collect(),
+                    old: d.old.as_ref().map(Entry::from),
+                    new: d.new.as_ref().map(Entry::from),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A file of `hezi manifest`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestFile {
+    pub size: u64,
+    /// Lowercase hexadecimal SHA-256 of the content.
+    pub sha256: String,
+}
+
+/// Output of `hezi manifest`, the files of the archive by name.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub entries: BTreeMap<String, ManifestFile>,
+}
+
+impl From<&manifest::Manifest> for Manifest {
+    fn from(manifest: &manifest::Manifest) -> Self {
+        Manifest {
+            entries: manifest
+                .entries
+                .iter()
+                .map(|(name, e)| {
+                    let file = ManifestFile {
+                        size: e.size,
+                        sha256: e.sha256.clone(),
+                    };
+                    (name.clone(), file)
+                })
+                .collect(),
+        }
+    }
+}
+
+/// How long an operation took and how much it processed, so that scripts can look into its
+/// performance.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    /// In nanoseconds.
+    pub duration: u64,
+    pub bytes: u64,
+    pub entries: usize,
+}
+
+impl Stats {
+    pub fn new(duration: Duration, bytes: u64, entries: usize) -> Self {
+        Stats {
+            duration: duration.as_nanos() as u64,
+            bytes,
+            entries,
+        }
+    }
+}
+
+/// An entry the plugin's `archive extract` did not write.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SkippedEntry {
+    pub name: String,
+    /// Name of the reason, such as `already_exists` or `unsafe_path`.
+    pub reason: String,
+}
+
+/// An entry the plugin's `archive extract` could not read.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FailedEntry {
+    pub name: String,
+    pub error: String,
+}
+
+/// Output of the plugin's `archive extract`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Extract {
+    /// Paths of the files, directories and links written.
+    pub extracted: Vec<String>,
+    pub skipped: Vec<SkippedEntry>,
+    pub failed: Vec<FailedEntry>,
+    pub stats: Stats,
+}
+
+impl From<&ExtractReport> for Extract {
+    fn from(report: &ExtractReport) -> Self {
+        Extract {
+            extracted: report
+                .extracted
+                .iter()
+                .map(|p| p.to_string_lossy().to_string())
+                .collect(),
+            skipped: report
+                .skipped
+                .iter()
+                .map(|(name, reason)| SkippedEntry {
+                    name: name.clone(),
+                    reason: reason.name().to_string(),
+                })
+                .collect(),
+            failed: report
+                .failed
+                .iter()
+                .map(|(name, error)| FailedEntry {
+                    name: name.clone(),
+                    error: error.clone(),
+                })
+                .collect(),
+            stats: Stats::new(report.duration, report.bytes, report.extracted.len()),
+        }
+    }
+}
+
+/// Output of the plugin's `archive create`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Create {
+    pub path: String,
+    pub total_size: u64,
+    pub compressed_size: u64,
+    pub stats: Stats,
+}
+
+impl Create {
+    /// `result` of creating an archive of `entries` files in `duration`.
+    pub fn new(result: &CreateResult, duration: Duration, entries: usize) -> Self {
+        Create {
+            path: result.path.to_string_lossy().to_string(),
+            total_size: result.total_size,
+            compressed_size: result.compressed_size,
+            stats: Stats::new(duration, result.total_size, entries),
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    fn entry() -> Entry {
+        Entry {
+            name: "docs/a.txt".to_string(),
+            size: Some(10),
+            compressed_size: Some(4),
+            kind: "file".to_string(),
+            last_modified: Some("2020-01-02T03:04:05Z".to_string()),
+            compression: Some("deflate".to_string()),
+            raw: None,
+            unix: None,
+        }
+    }
+
+    #[test]
+    fn converts_entities() {
+        let entity = ArchiveFileEntity {
+            name: "docs/a.txt".to_string(),
+            size: Some(10),
+            compressed_size: Some(4),
+            last_modified: Some(
+                chrono::DateTime::parse_from_rfc3339("2020-01-02T03:04:05Z").unwrap(),
+            ),
+            compression: Some("deflate".to_string()),
+            fstype: crate::archive::ArchiveFileEntityType::File,
+            raw: None,
+            unix: None,
+        };
+        assert_eq!(Entry::from(&entity), entry());
+    }
+
+    fn json_of<T: Serialize>(data: T) -> Value {
+        serde_json::from_str(&Versioned::new(data).to_json().unwrap()).unwrap()
+    }
+
+    /// Documents of version 1 as scripts read them: changing one of these is a breaking change,
+    /// which needs a new [`SCHEMA_VERSION`].
+    #[test]
+    fn keeps_the_documents_of_version_1() {
+        assert_eq!(SCHEMA_VERSION, 1);
+        let entry_v1 = json!({
+            "name": "docs/a.txt",
+            "size": 10,
+            "compressed_size": 4,
+            "type": "file",
+            "last_modified": "2020-01-02T03:04:05Z",
+            "compression": "deflate",
+        });
+
+        let mut sampled_v1 = entry_v1.clone();
+        sampled_v1["preview"] = json!("hello");
+
+        assert_eq!(
+            json_of(List {
+                entries: vec![entry()]
+            }),
+            json!({ "schema_version": 1, "entries": [entry_v1] })
+        );
+        assert_eq!(
+            json_of(Sample {
+                entries: vec![Sampled {
+                    entry: entry(),
+                    preview: "hello".to_string(),
+                }]
+            }),
+            json!({ "schema_version": 1, "entries": [sampled_v1] })
+        );
+        assert_eq!(
+            json_of(Identified {
+                files: vec![IdentifiedFile {
+                    path: "a.tar.gz".to_string(),
+                    archive_type: Some("tar".to_string()),
+                    compression: vec!["gzip".to_string()],
+                    encrypted: Some(false),
+                    confidence: Some("high".to_string()),
+                }]
+            }),
+            json!({
+                "schema_version": 1,
+                "files": [{
+                    "path": "a.tar.gz",
+                    "type": "tar",
+                    "compression": ["gzip"],
+                    "encrypted": false,
+                    "confidence": "high",
+                }],
+            })
+        );
+        assert_eq!(
+            json_of(Test {
+                passed: false,
+                files: 2,
+                checked: 2,
+                problems: vec![],
+                entries: vec![CheckedEntry {
+                    name: "a.txt".to_string(),
+                    passed: false,
+                    error: Some("checksum mismatch".to_string()),
+                }],
+            }),
+            json!({
+                "schema_version": 1,
+                "passed": false,
+                "files": 2,
+                "checked": 2,
+                "problems": [],
+                "entries": [{ "name": "a.txt", "passed": false, "error": "checksum mismatch" }],
+            })
+        );
+        assert_eq!(
+            json_of(ManifestCheck::new(
+                "a.zip".to_string(),
+                "a.json".to_string(),
+                &ManifestDiff {
+                    missing: vec!["b.txt".to_string()],
+                    ..Default::default()
+                },
+            )),
+            json!({
+                "schema_version": 1,
+                "archive": "a.zip",
+                "manifest": "a.json",
+                "passed": false,
+                "missing": ["b.txt"],
+                "unexpected": [],
+                "changed": [],
+            })
+        );
+        assert_eq!(
+            json_of(Manifest {
+                entries: BTreeMap::from([(
+                    "a.txt".to_string(),
+                    ManifestFile {
+                        size: 5,
+                        sha256: "2cf24dba".to_string(),
+                    },
+                )]),
+            }),
+            json!({
+                "schema_version": 1,
+                "entries": { "a.txt": { "size": 5, "sha256": "2cf24dba" } },
+            })
+        );
+        let stats = Stats {
+            duration: 1500,
+            bytes: 10,
+            entries: 1,
+        };
+        assert_eq!(
+            json_of(Extract {
+                extracted: vec!["out/a.txt".to_string()],
+                skipped: vec![SkippedEntry {
+                    name: "../b.txt".to_string(),
+                    reason: "unsafe_path".to_string(),
+                }],
+                failed: vec![FailedEntry {
+                    name: "c.txt".to_string(),
+                    error: "wrong password".to_string(),
+                }],
+                stats: stats.clone(),
+            }),
+            json!({
+                "schema_version": 1,
+                "extracted": ["out/a.txt"],
+                "skipped": [{ "name": "../b.txt", "reason": "unsafe_path" }],
+                "failed": [{ "name": "c.txt", "error": "wrong password" }],
+                "stats": { "duration": 1500, "bytes": 10, "entries": 1 },
+            })
+        );
+        assert_eq!(
+            json_of(Create {
+                path: "a.zip".to_string(),
+                total_size: 10,
+                compressed_size: 4,
+                stats,
+            }),
+            json!({
+                "schema_version": 1,
+                "path": "a.zip",
+                "total_size": 10,
+                "compressed_size": 4,
+                "stats": { "duration": 1500, "bytes": 10, "entries": 1 },
+            })
+        );
+    }
+
+    #[test]
+    fn keeps_the_diff_documents_of_version_1() {
+        let mut grown = entry();
+        grown.size = Some(11);
+        assert_eq!(
+            json_of(Diff {
+                entries: vec![DiffedEntry {
+                    name: "docs/a.txt".to_string(),
+                    kind: "changed".to_string(),
+                    fields: vec!["size".to_string()],
+                    old: Some(entry()),
+                    new: Some(grown),
+                }]
+            }),
+            json!({
+                "schema_version": 1,
+                "entries": [{
+                    "name": "docs/a.txt",
+                    "kind": "changed",
+                    "fields": ["size"],
+                    "old": {
+                        "name": "docs/a.txt",
+                        "size": 10,
+                        "compressed_size": 4,
+                        "last_modified": "2020-01-02T03:04:05Z",
+                        "compression": "deflate",
+                        "type": "file",
+                    },
+                    "new": {
+                        "name": "docs/a.txt",
+                        "size": 11,
+                        "compressed_size": 4,
+                        "last_modified": "2020-01-02T03:04:05Z",
+                        "compression": "deflate",
+                        "type": "file",
+                    },
+                }],
+            })
+        );
+    }
+
+    /// Fields added to version 1 later must not fail the scripts parsing its documents.
+    #[test]
+    fn reads_documents_with_unknown_fields() {
+        let document = r#"{
+            "schema_version": 1,
+            "entries": [{
+                "name": "docs/a.txt",
+                "size": 10,
+                "compressed_size": 4,
+                "type": "file",
+                "last_modified": "2020-01-02T03:04:05Z",
+                "compression": "deflate",
+                "added_later": true
+            }],
+            "added_later": {}
+        }"#;
+        let list = serde_json::from_str::<Versioned<List>>(document).unwrap();
+        assert_eq!(list.schema_version, 1);
+        assert_eq!(list.data.entries, [entry()]);
+    }
+
+    #[cfg(feature = "zip_archive")]
+    #[test]
+    fn builds_documents_from_archives() {
+        use crate::archive::{Archive, Archived, DataSource, ListOptions};
+
+        let archive = Archive::of(DataSource::file("tests/fixtures/test1.zip").unwrap()).unwrap();
+        let entries = archive.list(ListOptions::default()).unwrap();
+        let list = List {
+            entries: entries.iter().map(Entry::from).collect(),
+        };
+        let file = list
+            .entries
+            .iter()
+            .find(|e| e.name == "test1/dir1/file2.txt")
+            .unwrap();
+        assert_eq!((file.size, file.kind.as_str()), (Some(444), "file"));
+
+        let metadata = Metadata::from(&archive.metadata().unwrap());
+        assert_eq!(metadata.archive_type, "zip");
+        assert_eq!(metadata.entries, list.entries);
+        assert_eq!(json_of(metadata)["schema_version"], 1);
+    }
+}
@@ -0,0 +1,11 @@
+// the types most uses of the library need, to be glob imported with `use hezi::prelude::*;`
+
+pub use crate::archive::{
+    entries::{ArchiveEntries, ArchiveEntry},
+    guess::{ArchiveTypeExt, GuessedFormat},
+    identify::Confidence,
+    password::{PasswordProvider, StaticPassword},
+    Archive, ArchiveCompression, ArchiveError, ArchiveEvent, ArchiveFileEntity,
+    ArchiveFileEntityType, ArchiveType, Archived, CreateOptions, CreateResult, DataSource,
+    EventHandler, ExtractOptions, ExtractReport, ListOptions, OpenOptions,
+};